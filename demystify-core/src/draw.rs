@@ -0,0 +1,85 @@
+//! How a [`Problem`](crate::json::Problem) is drawn, as set in
+//! [`PlannerConfig::html_options`](crate::problem::planner::PlannerConfig::html_options).
+//! The drawing itself is done by demystify-render, which depends on this
+//! crate, so the solver is not rebuilt when it changes.
+
+use serde::{Deserialize, Serialize};
+
+/// How the puzzle and the list of statements are placed next to each other.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Orientation {
+    /// Puzzle on the left, statements on the right (wrapping below on narrow screens)
+    SideBySide,
+    /// Puzzle above the statements
+    Stacked,
+}
+
+/// Layout and display options for `create_html_with_options` in demystify-render.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct HtmlOptions {
+    /// Preferred width of the puzzle pane, in pixels. It shrinks to fit narrow screens.
+    pub puzzle_width: u32,
+    /// Minimum width of the statements pane before it wraps below the puzzle, in pixels
+    pub statements_width: u32,
+    /// Height of both panes, in pixels
+    pub height: u32,
+    pub orientation: Orientation,
+    /// Show the Essence' expression of each constraint in an expandable
+    /// block beneath its description
+    pub show_expressions: bool,
+    /// Draw cells as filled (1) or empty (0) circles rather than as numbers.
+    /// If `None`, this is done for puzzles whose variables are all 0/1.
+    pub binary_cells: Option<bool>,
+    /// Which candidates are drawn in each cell, and how many fit across it
+    pub candidates: CandidateLayout,
+    /// Write how far each row and column is solved in the margins, see
+    /// [`GridStats`](crate::problem::groups::GridStats)
+    pub margin_stats: bool,
+    /// Draw the puzzle as a small preview this many pixels across, see
+    /// `thumbnail_svg` in demystify-render
+    pub thumbnail: Option<u32>,
+}
+
+impl Default for HtmlOptions {
+    fn default() -> Self {
+        Self {
+            puzzle_width: 550,
+            statements_width: 300,
+            height: 550,
+            orientation: Orientation::SideBySide,
+            show_expressions: false,
+            binary_cells: None,
+            candidates: CandidateLayout::default(),
+            margin_stats: false,
+            thumbnail: None,
+        }
+    }
+}
+
+/// How the candidates left in a cell are drawn, so cells with large domains
+/// stay legible. By default every candidate is drawn, in a grid which grows
+/// to fit them.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct CandidateLayout {
+    /// The most candidates drawn across (and down) a cell. Cells with more
+    /// candidates than fit are treated as in `hide_above`.
+    pub max_grid: Option<usize>,
+    /// In cells with more than this many candidates, draw only those which
+    /// are highlighted (as in `only_highlighted`), with a count of them all.
+    pub hide_above: Option<usize>,
+    /// In cells with more than one candidate, draw only those the step
+    /// deduces, rules out or uses, or which are pinned, with a count of them
+    /// all.
+    pub only_highlighted: bool,
+}
+
+impl CandidateLayout {
+    /// Whether to draw only the highlighted ones of `count` candidates.
+    #[must_use]
+    pub fn hides(&self, count: usize) -> bool {
+        count > 1
+            && (self.only_highlighted
+                || self.hide_above.is_some_and(|n| count > n)
+                || self.max_grid.is_some_and(|n| count > n * n))
+    }
+}
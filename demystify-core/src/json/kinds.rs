@@ -0,0 +1,199 @@
+//! The puzzle kinds (given by `$#KIND` in the model) which the drawing code
+//! knows about, and which optional parts of a [`Puzzle`] each one uses.
+
+use crate::json::Puzzle;
+
+/// What a puzzle kind changes about how a puzzle is drawn.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KindFeatures {
+    /// The name used in `$#KIND`, compared ignoring case
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Cages are drawn as coloured regions with thick borders
+    pub cages: bool,
+    /// Regions are drawn with thick coloured borders
+    pub regions: bool,
+    /// Labels outside the grid, such as row and column sums
+    pub labels: bool,
+    /// Lines through cells, such as thermometers
+    pub lines: bool,
+    /// Thick borders around each 3x3 box
+    pub sudoku_borders: bool,
+    /// The value which marks an empty cell in the starting grid
+    pub blank_input_val: Option<i64>,
+}
+
+/// Used for kinds which are not in [`SupportedKinds`].
+pub const UNKNOWN_KIND: KindFeatures = KindFeatures {
+    name: "unknown",
+    description: "A plain grid, showing any starting values and labels",
+    cages: true,
+    regions: true,
+    labels: true,
+    lines: true,
+    sudoku_borders: false,
+    blank_input_val: None,
+};
+
+const KINDS: &[KindFeatures] = &[
+    KindFeatures {
+        name: "sudoku",
+        description: "Sudoku and variants, with 3x3 boxes and optional cages (as in killer sudoku)",
+        cages: true,
+        regions: false,
+        labels: true,
+        lines: true,
+        sudoku_borders: true,
+        blank_input_val: Some(0),
+    },
+    KindFeatures {
+        name: "binairo",
+        description: "Binairo, where 2 marks an empty cell",
+        cages: false,
+        regions: false,
+        labels: false,
+        lines: false,
+        sudoku_borders: false,
+        blank_input_val: Some(2),
+    },
+    KindFeatures {
+        name: "star-battle",
+        description: "Star battle, with regions given as either cages or regions",
+        cages: true,
+        regions: true,
+        labels: false,
+        lines: false,
+        sudoku_borders: false,
+        blank_input_val: None,
+    },
+];
+
+/// The registry of puzzle kinds with special support.
+pub struct SupportedKinds;
+
+impl SupportedKinds {
+    #[must_use]
+    pub fn all() -> &'static [KindFeatures] {
+        KINDS
+    }
+
+    #[must_use]
+    pub fn get(kind: &str) -> Option<&'static KindFeatures> {
+        KINDS.iter().find(|k| k.name.eq_ignore_ascii_case(kind))
+    }
+
+    /// The features of `kind`, falling back to [`UNKNOWN_KIND`].
+    #[must_use]
+    pub fn features(kind: &str) -> &'static KindFeatures {
+        Self::get(kind).unwrap_or(&UNKNOWN_KIND)
+    }
+
+    /// Warnings about parts of `puzzle` which may not be drawn as intended.
+    #[must_use]
+    pub fn warnings(puzzle: &Puzzle) -> Vec<String> {
+        let mut warnings = vec![];
+
+        let Some(features) = Self::get(&puzzle.kind) else {
+            warnings.push(format!(
+                "Unknown puzzle kind '{}', drawing as a plain grid. Supported kinds are: {}",
+                puzzle.kind,
+                KINDS.iter().map(|k| k.name).collect::<Vec<_>>().join(", ")
+            ));
+            return warnings;
+        };
+
+        if puzzle.cages.is_some() && !features.cages {
+            warnings.push(format!(
+                "Puzzle kind '{}' does not use cages, but the parameters contain some",
+                puzzle.kind
+            ));
+        }
+
+        if puzzle.regions.is_some() && !features.regions {
+            warnings.push(format!(
+                "Puzzle kind '{}' does not use regions, but the parameters contain some",
+                puzzle.kind
+            ));
+        }
+
+        let has_labels = [
+            &puzzle.top_labels,
+            &puzzle.bottom_labels,
+            &puzzle.left_labels,
+            &puzzle.right_labels,
+        ]
+        .iter()
+        .any(|l| l.is_some());
+
+        if has_labels && !features.labels {
+            warnings.push(format!(
+                "Puzzle kind '{}' does not use labels, but the parameters contain some",
+                puzzle.kind
+            ));
+        }
+
+        if !puzzle.lines.is_empty() && !features.lines {
+            warnings.push(format!(
+                "Puzzle kind '{}' does not use lines, but the parameters contain some",
+                puzzle.kind
+            ));
+        }
+
+        warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use super::*;
+    use crate::json::{LineKind, PathSpec};
+
+    fn puzzle(kind: &str) -> Puzzle {
+        Puzzle {
+            kind: kind.to_string(),
+            width: 4,
+            height: 4,
+            start_grid: None,
+            solution_grid: None,
+            cages: None,
+            regions: None,
+            top_labels: None,
+            bottom_labels: None,
+            left_labels: None,
+            right_labels: None,
+            binary_domain: false,
+            lines: vec![],
+            mask: None,
+            value_glyphs: BTreeMap::new(),
+            grid_map: None,
+        }
+    }
+
+    #[test]
+    fn test_kind_warnings() {
+        assert!(SupportedKinds::warnings(&puzzle("Sudoku")).is_empty());
+        assert_eq!(SupportedKinds::features("SUDOKU").name, "sudoku");
+        assert_eq!(SupportedKinds::features("Tiny"), &UNKNOWN_KIND);
+
+        let unknown = SupportedKinds::warnings(&puzzle("Tiny"));
+        assert_eq!(unknown.len(), 1);
+        assert!(unknown[0].contains("'Tiny'"));
+
+        let mut binairo = puzzle("Binairo");
+        binairo.cages = Some(vec![vec![Some(1); 4]; 4]);
+        binairo.regions = Some(vec![vec![Some(1); 4]; 4]);
+        binairo.top_labels = Some(vec!["1".to_string(); 4]);
+        binairo.lines = vec![PathSpec {
+            kind: LineKind::Whisper,
+            cells: vec![vec![1, 1], vec![1, 2]],
+            classes: BTreeSet::new(),
+        }];
+        assert_eq!(SupportedKinds::warnings(&binairo).len(), 4);
+
+        let mut star_battle = puzzle("Star-Battle");
+        star_battle.regions = Some(vec![vec![Some(1); 4]; 4]);
+        assert!(SupportedKinds::warnings(&star_battle).is_empty());
+    }
+}
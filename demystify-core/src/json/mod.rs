@@ -0,0 +1,1287 @@
+/// This module contains the definitions and implementations related to JSON serialization and deserialization for the demystify library.
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use anyhow::Context;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use self::kinds::SupportedKinds;
+use crate::problem::{
+    PuzLit, PuzVar, VarValPair,
+    explain::Explanation,
+    groups::GridStats,
+    parse::{GridMap, PuzzleParse, in_mask},
+    rules::RuleCoverage,
+    solver::PuzzleSolver,
+};
+
+pub mod kinds;
+
+#[derive(Clone, PartialOrd, Ord, Hash, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Puzzle {
+    pub kind: String,
+    pub width: i64,
+    pub height: i64,
+    pub start_grid: Option<Vec<Vec<Option<i64>>>>,
+    pub solution_grid: Option<Vec<Vec<Option<i64>>>>,
+    pub cages: Option<Vec<Vec<Option<i64>>>>,
+    /// The region id of each cell, for puzzles (such as star battle) whose
+    /// constraints are about regions. These are drawn with coloured borders,
+    /// rather than filled in like cages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regions: Option<Vec<Vec<Option<i64>>>>,
+    pub top_labels: Option<Vec<String>>,
+    pub bottom_labels: Option<Vec<String>>,
+    pub left_labels: Option<Vec<String>>,
+    pub right_labels: Option<Vec<String>>,
+    /// Every `$#VAR` has domain {0, 1}, so cells are drawn as filled or empty
+    /// circles rather than by listing their candidates
+    #[serde(default)]
+    pub binary_domain: bool,
+    /// Lines drawn through cells, for sudoku variants such as thermo sudoku
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lines: Vec<PathSpec>,
+    /// `true` for each position of the grid which is not a cell, for boards
+    /// which are not rectangles, from the param `mask`. Nothing is drawn
+    /// there, see [`Puzzle::is_cell`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mask: Option<Vec<Vec<bool>>>,
+    /// How values are drawn in cells, when not as their number, from
+    /// [`Vocabulary::glyphs`](crate::problem::parse::Vocabulary::glyphs).
+    /// See [`Puzzle::value_text`]
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub value_glyphs: BTreeMap<i64, String>,
+    /// How the grid variable is laid out, if the model flattens it to a 1-D
+    /// array. See [`Puzzle::cell_of`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grid_map: Option<GridMap>,
+}
+
+/// What a [`PathSpec`] is drawn as.
+#[derive(Clone, Copy, PartialOrd, Ord, Hash, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineKind {
+    /// A thermometer, with its bulb in the first cell (param `thermos`)
+    Thermo,
+    /// An arrow, with its circle in the first cell (param `arrows`)
+    Arrow,
+    /// A German whispers line (param `whispers`)
+    Whisper,
+}
+
+/// A line through the centres of a path of cells.
+#[derive(Clone, PartialOrd, Ord, Hash, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PathSpec {
+    pub kind: LineKind,
+    /// The (1-indexed) row and column of each cell, in order
+    pub cells: Vec<Vec<i64>>,
+    /// When showing a step, the `highlight_con<n>` classes of the constraints
+    /// of the step which only involve cells on this line
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub classes: BTreeSet<Class>,
+}
+
+impl PathSpec {
+    /// The paths in the param `label`, which is a 3d array of the (row,
+    /// column) of each cell of each path. Shorter paths are padded with cells
+    /// containing a 0, which are skipped.
+    fn from_param(problem: &PuzzleParse, label: &str, kind: LineKind) -> anyhow::Result<Vec<Self>> {
+        Ok(problem
+            .eprime
+            .param_vec_vec_vec_i64(label)?
+            .into_iter()
+            .map(|path| PathSpec {
+                kind,
+                cells: path
+                    .into_iter()
+                    .filter(|cell| cell.iter().all(|&x| x > 0))
+                    .collect(),
+                classes: BTreeSet::new(),
+            })
+            .filter(|p| !p.cells.is_empty())
+            .collect())
+    }
+}
+
+impl Puzzle {
+    pub fn new_from_puzzle(problem: &PuzzleParse) -> anyhow::Result<Puzzle> {
+        let kind = problem.eprime.kind.clone().unwrap_or("Unknown".to_string());
+
+        let mut width = None;
+        let mut height = None;
+
+        for label in ["width", "x", "x_dim"] {
+            if problem.eprime.has_param(label) {
+                width = Some(problem.eprime.param_i64(label)?);
+            }
+        }
+
+        for label in ["height", "y", "y_dim"] {
+            if problem.eprime.has_param(label) {
+                height = Some(problem.eprime.param_i64(label)?);
+            }
+        }
+
+        if problem.eprime.has_param("grid_size") {
+            height = Some(problem.eprime.param_i64("grid_size")?);
+            width = Some(problem.eprime.param_i64("grid_size")?);
+        }
+
+        if problem.eprime.has_param("size") {
+            height = Some(problem.eprime.param_i64("size")?);
+            width = Some(problem.eprime.param_i64("size")?);
+        }
+
+        if let Some(map) = &problem.eprime.grid_map {
+            width = Some(map.width);
+            height = Some(map.height);
+        }
+
+        // If there is only one 'VAR', then it might tell us what to draw
+        if height.is_none() && width.is_none() && problem.eprime.vars.len() == 1 {
+            let var = problem.eprime.vars.iter().next().unwrap();
+
+            let indices = problem.get_matrix_indices(var);
+            if let Some(v) = indices {
+                if v.len() == 2 {
+                    width = Some(v[1]);
+                    height = Some(v[0]);
+                }
+            }
+        }
+
+        let mut start_grid = None;
+        let mut cages = None;
+        let mut regions = None;
+        let mut lines = vec![];
+
+        let mut top_labels = None;
+        let mut bottom_labels = None;
+        let mut left_labels = None;
+        let mut right_labels = None;
+
+        for label in ["row_labels", "top_labels", "row_sums"] {
+            if problem.eprime.has_param(label) {
+                top_labels = Some(problem.eprime.param_vec_string(label)?);
+            }
+        }
+
+        for label in ["col_labels", "left_labels", "col_sums"] {
+            if problem.eprime.has_param(label) {
+                left_labels = Some(problem.eprime.param_vec_string(label)?);
+            }
+        }
+
+        for label in ["bottom_labels"] {
+            if problem.eprime.has_param(label) {
+                bottom_labels = Some(problem.eprime.param_vec_string(label)?);
+            }
+        }
+
+        for label in ["right_labels"] {
+            if problem.eprime.has_param(label) {
+                right_labels = Some(problem.eprime.param_vec_string(label)?);
+            }
+        }
+
+        if problem.eprime.has_param("side_labels") {
+            let side_labels = problem.eprime.param_vec_vec_string("side_labels")?;
+            left_labels = Some(side_labels[0].clone());
+            top_labels = Some(side_labels[1].clone());
+            right_labels = Some(side_labels[2].clone());
+            bottom_labels = Some(side_labels[3].clone());
+        }
+
+        if problem.eprime.has_param("start_grid") {
+            start_grid = Some(problem.eprime.param_grid("start_grid")?);
+        }
+
+        if problem.eprime.has_param("fixed") {
+            start_grid = Some(problem.eprime.param_grid("fixed")?);
+        }
+
+        if problem.eprime.has_param("cages") {
+            cages = Some(problem.eprime.param_grid("cages")?);
+        }
+
+        for label in ["regions", "galaxy"] {
+            if problem.eprime.has_param(label) {
+                regions = Some(problem.eprime.param_grid(label)?);
+            }
+        }
+
+        for (label, kind) in [
+            ("thermos", LineKind::Thermo),
+            ("arrows", LineKind::Arrow),
+            ("whispers", LineKind::Whisper),
+        ] {
+            if problem.eprime.has_param(label) {
+                lines.extend(PathSpec::from_param(problem, label, kind)?);
+            }
+        }
+
+        let mask = problem.eprime.mask()?;
+
+        if width.is_none() || height.is_none() {
+            if start_grid.is_some() {
+                width = Some(start_grid.as_ref().unwrap()[0].len() as i64);
+                height = Some(start_grid.as_ref().unwrap().len() as i64);
+            } else if let Some(grid) = cages.as_ref().or(regions.as_ref()) {
+                width = Some(grid[0].len() as i64);
+                height = Some(grid.len() as i64);
+            } else if let Some(mask) = &mask {
+                width = Some(mask[0].len() as i64);
+                height = Some(mask.len() as i64);
+            }
+        }
+
+        let values: BTreeSet<i64> = problem
+            .all_var_varvals()
+            .iter()
+            .map(VarValPair::val)
+            .collect();
+        let binary_domain = !values.is_empty() && values.iter().all(|&v| v == 0 || v == 1);
+        let value_glyphs = problem
+            .eprime
+            .vocabulary
+            .glyphs
+            .get(problem.eprime.grid_var())
+            .cloned()
+            .unwrap_or_default();
+
+        Ok(Puzzle {
+            kind,
+            width: width.context("'width' not given as a param, and unable to deduce")?,
+            height: height.context("'height' not given as a param, and unable to deduce")?,
+            start_grid,
+            solution_grid: None,
+            cages,
+            regions,
+            top_labels,
+            bottom_labels,
+            left_labels,
+            right_labels,
+            binary_domain,
+            lines,
+            mask,
+            value_glyphs,
+            grid_map: problem.eprime.grid_map.clone(),
+        })
+    }
+
+    /// Whether the (1-indexed) `indices` are those of a cell, rather than a
+    /// position left out of the board by [`Puzzle::mask`].
+    #[must_use]
+    pub fn is_cell(&self, indices: &[i64]) -> bool {
+        !self
+            .mask
+            .as_ref()
+            .is_some_and(|mask| in_mask(mask, indices))
+    }
+
+    /// The (1-indexed) row and column of the cell of `var`, converting the
+    /// number of a cell of a flattened grid with [`Puzzle::grid_map`].
+    #[must_use]
+    pub fn cell_of(&self, var: &PuzVar) -> Vec<i64> {
+        self.grid_map
+            .as_ref()
+            .and_then(|map| map.cell_of(var))
+            .unwrap_or_else(|| var.indices().clone())
+    }
+
+    /// How `val` is drawn in a cell, as its glyph from
+    /// [`Puzzle::value_glyphs`] if it has one.
+    #[must_use]
+    pub fn value_text(&self, val: i64) -> String {
+        self.value_glyphs
+            .get(&val)
+            .cloned()
+            .unwrap_or_else(|| val.to_string())
+    }
+
+    /// The region of the cell with (1-indexed) `indices`, as in the
+    /// knowledge grid.
+    #[must_use]
+    pub fn region_of(&self, indices: &[i64]) -> Option<i64> {
+        let [i, j] = indices else {
+            return None;
+        };
+        let i = usize::try_from(*i).ok()?.checked_sub(1)?;
+        let j = usize::try_from(*j).ok()?.checked_sub(1)?;
+        *self.regions.as_ref()?.get(i)?.get(j)?
+    }
+
+    /// The value the cell with (1-indexed) `indices` is given in
+    /// `start_grid`, if any. Values which mark an empty cell for this kind of
+    /// puzzle are not givens.
+    #[must_use]
+    pub fn given_value(&self, indices: &[i64]) -> Option<i64> {
+        let [i, j] = indices else {
+            return None;
+        };
+        let i = usize::try_from(*i).ok()?.checked_sub(1)?;
+        let j = usize::try_from(*j).ok()?.checked_sub(1)?;
+        let val = (*self.start_grid.as_ref()?.get(i)?.get(j)?)?;
+        let blank = SupportedKinds::features(&self.kind).blank_input_val;
+        (Some(val) != blank).then_some(val)
+    }
+
+    /// Marks a value known to be in a cell as either given by the puzzle
+    /// (class `litgiven`) or deduced (class `litknown`).
+    fn tag_known(&self, l: &VarValPair, tags: &mut BTreeSet<Class>, meta: &mut LitMeta) {
+        if self.given_value(&self.cell_of(l.var())) == Some(l.val()) {
+            tags.insert(Class::LitGiven);
+            meta.status = LitStatus::Given;
+        } else {
+            tags.insert(Class::LitKnown);
+            meta.status = LitStatus::Known;
+        }
+    }
+}
+
+/// A CSS class given to a [`StateLit`] or a [`Statement`]. These are
+/// written to JSON as the class names, so a typo is a compile error rather
+/// than a value which is silently never styled.
+#[derive(Clone, PartialOrd, Ord, Hash, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(from = "String", into = "String")]
+pub enum Class {
+    /// `js_group_header`: the first statement of a deduction
+    GroupHeader,
+    /// `highlight_con<n>`: highlighted along with the `n`th constraint of a step
+    Highlight(usize),
+    /// `highlight_<lit>`: highlighted along with a value, given as in
+    /// [`VarValPair::to_css_string`]
+    HighlightLit(String),
+    /// `js_highlighter`: highlights its matching `highlight_*` classes on hover
+    JsHighlighter,
+    /// `litinmus`: the value is in the scope of a constraint used in the step
+    LitInMus,
+    /// `litpos`: the step deduces this value
+    LitPos,
+    /// `litneg`: the step rules this value out
+    LitNeg,
+    /// `litknown`: the value was deduced in an earlier step
+    LitKnown,
+    /// `litgiven`: the value is given in the starting grid
+    LitGiven,
+    /// `litpinned`: the value is pinned, so is never deduced
+    LitPinned,
+    /// Any other class, so JSON from other versions can still be read
+    Other(String),
+}
+
+impl std::fmt::Display for Class {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Class::GroupHeader => write!(f, "js_group_header"),
+            Class::Highlight(n) => write!(f, "highlight_con{n}"),
+            Class::HighlightLit(lit) => write!(f, "highlight_{lit}"),
+            Class::JsHighlighter => write!(f, "js_highlighter"),
+            Class::LitInMus => write!(f, "litinmus"),
+            Class::LitPos => write!(f, "litpos"),
+            Class::LitNeg => write!(f, "litneg"),
+            Class::LitKnown => write!(f, "litknown"),
+            Class::LitGiven => write!(f, "litgiven"),
+            Class::LitPinned => write!(f, "litpinned"),
+            Class::Other(class) => write!(f, "{class}"),
+        }
+    }
+}
+
+impl std::str::FromStr for Class {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "js_group_header" => Class::GroupHeader,
+            "js_highlighter" => Class::JsHighlighter,
+            "litinmus" => Class::LitInMus,
+            "litpos" => Class::LitPos,
+            "litneg" => Class::LitNeg,
+            "litknown" => Class::LitKnown,
+            "litgiven" => Class::LitGiven,
+            "litpinned" => Class::LitPinned,
+            _ => {
+                if let Some(n) = s.strip_prefix("highlight_con").and_then(|n| n.parse().ok()) {
+                    Class::Highlight(n)
+                } else if let Some(lit) = s.strip_prefix("highlight_") {
+                    Class::HighlightLit(lit.to_owned())
+                } else {
+                    Class::Other(s.to_owned())
+                }
+            }
+        })
+    }
+}
+
+impl From<String> for Class {
+    fn from(s: String) -> Self {
+        let Ok(class) = s.parse();
+        class
+    }
+}
+
+impl From<Class> for String {
+    fn from(class: Class) -> Self {
+        class.to_string()
+    }
+}
+
+/// Reads classes written as `null` by older versions as no classes.
+fn classes_or_null<'de, D>(deserializer: D) -> Result<BTreeSet<Class>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(Option::deserialize(deserializer)?.unwrap_or_default())
+}
+
+#[derive(Clone, PartialOrd, Ord, Hash, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct StateLit {
+    pub val: i64,
+    #[serde(default, deserialize_with = "classes_or_null")]
+    pub classes: BTreeSet<Class>,
+    /// What `classes` says about this value, for frontends other than ours
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<LitMeta>,
+}
+
+/// Whether a value in the knowledge grid is known to be the cell's value.
+#[derive(
+    Clone, Copy, Default, PartialOrd, Ord, Hash, Debug, PartialEq, Eq, Deserialize, Serialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum LitStatus {
+    /// The cell is given this value in the puzzle's starting grid (class
+    /// `litgiven`)
+    Given,
+    /// The cell has been deduced to take this value (class `litknown`)
+    Known,
+    /// The cell may still take this value
+    #[default]
+    Candidate,
+}
+
+/// Structured information about a [`StateLit`], so frontends do not have to
+/// parse its classes.
+#[derive(Clone, Default, PartialOrd, Ord, Hash, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LitMeta {
+    pub status: LitStatus,
+    /// Set if the step shown deduces something about this value: `true` if
+    /// the cell takes it (class `litpos`), `false` if it cannot (`litneg`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deduced: Option<bool>,
+    /// The [`Statement::group`] of the deduction about this value
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<usize>,
+    /// The number of the step which deduces this value, counting from 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub step: Option<usize>,
+    /// The constraints of the step shown with this value in their scope, as
+    /// in [`Statement::content`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub constraints: Vec<String>,
+}
+
+#[derive(Clone, PartialOrd, Ord, Hash, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct State {
+    pub knowledge_grid: Option<Vec<Vec<Option<Vec<StateLit>>>>>,
+    pub statements: Option<Vec<Statement>>,
+    pub description: Option<String>,
+    /// How many values each cell had left over the course of the solve
+    #[serde(default)]
+    pub candidate_counts: Option<Vec<Vec<Option<CandidateCount>>>>,
+    /// The step shown, which `statements` and `description` are made from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub explanation: Option<Explanation>,
+    /// How far each row, column and region is solved, drawn in the margins
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grid_stats: Option<GridStats>,
+    /// The cells covered by each `$#CON`, drawn over the grid when showing
+    /// the rules of the puzzle
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rule_coverage: Option<Vec<RuleCoverage>>,
+}
+
+/// The number of values a cell could still take, summarised over every step of a solve.
+#[derive(Clone, Copy, PartialOrd, Ord, Hash, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CandidateCount {
+    pub min: usize,
+    pub max: usize,
+    /// The sum of the counts at each step, divide by `steps` for the mean
+    pub total: usize,
+    pub steps: usize,
+}
+
+impl CandidateCount {
+    #[must_use]
+    pub fn new(count: usize) -> Self {
+        Self {
+            min: count,
+            max: count,
+            total: count,
+            steps: 1,
+        }
+    }
+
+    /// Include the count from one more step.
+    pub fn add(&mut self, count: usize) {
+        self.min = self.min.min(count);
+        self.max = self.max.max(count);
+        self.total += count;
+        self.steps += 1;
+    }
+
+    #[must_use]
+    pub fn mean(&self) -> f64 {
+        self.total as f64 / self.steps as f64
+    }
+}
+
+#[derive(Clone, PartialOrd, Ord, Hash, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Statement {
+    pub content: String,
+    #[serde(default, deserialize_with = "classes_or_null")]
+    pub classes: BTreeSet<Class>,
+    /// The name of the `$#CON` this statement describes, if it is a constraint
+    #[serde(default)]
+    pub constraint: Option<String>,
+    /// Which deduction this statement is part of, used to collapse and filter them
+    #[serde(default)]
+    pub group: Option<usize>,
+    /// The cells in the scope of the constraint, as css strings
+    #[serde(default)]
+    pub scope: Vec<String>,
+    /// The indices of the cells in `scope`, without the variable names
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scope_cells: Vec<Vec<i64>>,
+    /// The ids of the [`Puzzle::regions`] containing cells in `scope_cells`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub regions: Vec<i64>,
+    /// The Essence' expression of the constraint, if it could be found in the model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expression: Option<String>,
+}
+
+impl Statement {
+    /// Does this statement contain `text` (ignoring case) in its content,
+    /// constraint name or scope, and belong to `group` (if given)?
+    #[must_use]
+    pub fn matches(&self, text: &str, group: Option<usize>) -> bool {
+        if group.is_some() && self.group != group {
+            return false;
+        }
+
+        let text = text.to_lowercase();
+        self.content.to_lowercase().contains(&text)
+            || self
+                .constraint
+                .as_ref()
+                .is_some_and(|c| c.to_lowercase().contains(&text))
+            || self.scope.iter().any(|c| c.to_lowercase().contains(&text))
+    }
+}
+
+/// The statements which match `text` and `group`, see [`Statement::matches`].
+#[must_use]
+pub fn filter_statements<'a>(
+    statements: &'a [Statement],
+    text: &str,
+    group: Option<usize>,
+) -> Vec<&'a Statement> {
+    statements
+        .iter()
+        .filter(|s| s.matches(text, group))
+        .collect()
+}
+
+#[derive(Clone, PartialOrd, Ord, Hash, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Problem {
+    pub puzzle: Puzzle,
+    pub state: Option<State>,
+    /// Problems with drawing this puzzle, see [`SupportedKinds::warnings`]
+    #[serde(default)]
+    pub warnings: Vec<String>,
+}
+
+pub struct DescriptionStatement {
+    pub result: String,
+    pub constraints: Vec<String>,
+    /// The literals deduced, if known, for [`LitMeta::group`]
+    pub deduced: BTreeSet<PuzLit>,
+}
+
+impl DescriptionStatement {
+    pub fn new(result: String, constraints: Vec<String>) -> Self {
+        Self {
+            result,
+            constraints,
+            deduced: BTreeSet::new(),
+        }
+    }
+}
+
+impl Problem {
+    pub fn new_from_puzzle(problem: &PuzzleParse) -> anyhow::Result<Problem> {
+        let puzzle = Puzzle::new_from_puzzle(problem)?;
+        Ok(Problem {
+            warnings: SupportedKinds::warnings(&puzzle),
+            puzzle,
+            state: None,
+        })
+    }
+
+    pub fn new_from_puzzle_and_state(
+        solver: &PuzzleSolver,
+        tosolve: &BTreeSet<VarValPair>,
+        known: &BTreeSet<PuzLit>,
+        deduced_lits: &BTreeSet<PuzLit>,
+        comments: &str,
+    ) -> anyhow::Result<Problem> {
+        Self::new_from_puzzle_and_mus(solver, tosolve, known, deduced_lits, &[], comments)
+    }
+
+    pub fn new_from_puzzle_and_mus(
+        solver: &PuzzleSolver,
+        tosolve: &BTreeSet<VarValPair>,
+        known: &BTreeSet<PuzLit>,
+        deduced_lits: &BTreeSet<PuzLit>,
+        deduction_list: &[DescriptionStatement],
+        comments: &str,
+    ) -> anyhow::Result<Problem> {
+        let mut puzzle = Puzzle::new_from_puzzle(solver.puzzleparse())?;
+
+        let varnames = tosolve
+            .iter()
+            .map(|x| x.var().name().clone())
+            .chain(known.iter().map(|x| x.var().name().clone()))
+            .collect::<HashSet<String>>();
+
+        let allowed_names: HashSet<String> = if varnames.len() == 1 {
+            varnames
+        } else if varnames.contains("grid") {
+            {
+                let mut set = HashSet::new();
+                set.insert("grid".to_string());
+                set
+            }
+        } else {
+            return Err(anyhow::anyhow!(
+                "More than one variable matrix, and none called 'grid', so not sure what to print: {:?}",
+                varnames
+            ));
+        };
+
+        let mut knowledgegrid: Vec<Vec<Option<Vec<StateLit>>>> =
+            vec![
+                vec![None; usize::try_from(puzzle.width).context("width is negative")?];
+                usize::try_from(puzzle.height).context("height is negative")?
+            ];
+
+        // Start by getting a list of all constraints, and assigning a number to each of them.
+        let mut constraint_num: HashMap<String, usize> = HashMap::new();
+        // Make a list of the tags we need to attach to each varvalpair in the scope of each constraint
+        let mut constraint_tags: HashMap<VarValPair, BTreeSet<Class>> = HashMap::new();
+        // ... and of the constraints themselves, for the metadata
+        let mut lit_constraints: HashMap<VarValPair, Vec<String>> = HashMap::new();
+        // Which deduction each literal is part of
+        let mut deduced_group: HashMap<VarValPair, usize> = HashMap::new();
+
+        for (group, deduction) in deduction_list.iter().enumerate() {
+            for lit in &deduction.deduced {
+                deduced_group.entry(lit.varval()).or_insert(group);
+            }
+            for constraint in &deduction.constraints {
+                // constraint_num makes sure we only tag each constraint once
+                if !constraint_num.contains_key(constraint) {
+                    let len = constraint_num.len();
+                    constraint_num.insert(constraint.clone(), len);
+                    let scope = solver.puzzleparse().constraint_scope(constraint);
+                    let scope_cells: BTreeSet<Vec<i64>> = scope
+                        .iter()
+                        .filter(|p| allowed_names.contains(p.var().name()))
+                        .map(|p| puzzle.cell_of(p.var()))
+                        .collect();
+                    for line in &mut puzzle.lines {
+                        if !scope_cells.is_empty()
+                            && scope_cells.iter().all(|c| line.cells.contains(c))
+                        {
+                            line.classes.insert(Class::Highlight(len));
+                            line.classes.insert(Class::JsHighlighter);
+                            line.classes.insert(Class::LitInMus);
+                        }
+                    }
+                    for p in scope.iter() {
+                        lit_constraints
+                            .entry(p.clone())
+                            .or_default()
+                            .push(constraint.clone());
+                        let tags = constraint_tags.entry(p.clone()).or_default();
+                        tags.insert(Class::Highlight(len));
+                        tags.insert(Class::JsHighlighter);
+                    }
+                }
+            }
+        }
+
+        let all_lits = solver.puzzleparse().all_var_varvals();
+
+        for l in all_lits {
+            if !(tosolve.contains(&l) || known.contains(&PuzLit::new_eq(l.clone()))) {
+                continue;
+            }
+
+            if !allowed_names.contains(l.var().name()) {
+                continue;
+            }
+
+            // TODO: Handle more than one variable matrix?
+            let index = puzzle.cell_of(l.var());
+            assert_eq!(index.len(), 2);
+            let i = usize::try_from(index[0]).context("negative index 0?")?;
+            let j = usize::try_from(index[1]).context("negative index 1?")?;
+
+            assert!(i > 0, "Variables should be 1-indexed");
+            assert!(j > 0, "Variables should be 1-indexed");
+
+            if !puzzle.is_cell(&index) {
+                continue;
+            }
+
+            let i = i - 1;
+            let j = j - 1;
+
+            let mut tags = BTreeSet::new();
+            let mut meta = LitMeta::default();
+
+            if let Some(val) = constraint_tags.get(&l) {
+                tags.extend(val.clone());
+                tags.insert(Class::LitInMus);
+            }
+            if let Some(constraints) = lit_constraints.get(&l) {
+                meta.constraints.clone_from(constraints);
+            }
+
+            if deduced_lits.contains(&PuzLit::new_eq(l.clone())) {
+                tags.insert(Class::LitPos);
+                tags.insert(Class::HighlightLit(l.to_css_string()));
+                tags.insert(Class::JsHighlighter);
+                meta.deduced = Some(true);
+            }
+
+            if deduced_lits.contains(&PuzLit::new_neq(l.clone())) {
+                tags.insert(Class::LitNeg);
+                tags.insert(Class::HighlightLit(l.to_css_string()));
+                tags.insert(Class::JsHighlighter);
+                meta.deduced = Some(false);
+            }
+
+            if meta.deduced.is_some() {
+                meta.group = deduced_group.get(&l).copied();
+            }
+
+            if known.contains(&PuzLit::new_eq(l.clone())) {
+                puzzle.tag_known(&l, &mut tags, &mut meta);
+            }
+
+            if knowledgegrid[i][j].is_none() {
+                knowledgegrid[i][j] = Some(vec![]);
+            }
+
+            knowledgegrid[i][j].as_mut().unwrap().push(StateLit {
+                val: l.val(),
+                classes: tags,
+                meta: Some(meta),
+            });
+        }
+
+        let mut statements = Vec::new();
+
+        for (group, deduction) in deduction_list.iter().enumerate() {
+            statements.push(Statement {
+                content: deduction.result.clone(),
+                classes: [Class::GroupHeader].into(),
+                constraint: None,
+                group: Some(group),
+                scope: vec![],
+                scope_cells: vec![],
+                regions: vec![],
+                expression: None,
+            });
+            for constraint in &deduction.constraints {
+                let num = constraint_num.get(constraint).unwrap();
+                let scope_vars: BTreeSet<PuzVar> = solver
+                    .puzzleparse()
+                    .constraint_scope(constraint)
+                    .iter()
+                    .map(|p| p.var().clone())
+                    .collect();
+                let scope = scope_vars.iter().map(PuzVar::to_css_string).collect();
+                let scope_cells: BTreeSet<Vec<i64>> =
+                    scope_vars.iter().map(|v| puzzle.cell_of(v)).collect();
+                let regions: BTreeSet<i64> = scope_cells
+                    .iter()
+                    .filter_map(|cell| puzzle.region_of(cell))
+                    .collect();
+                statements.push(Statement {
+                    content: constraint.clone(),
+                    classes: [Class::Highlight(*num), Class::JsHighlighter].into(),
+                    constraint: solver.puzzleparse().constraint_name(constraint),
+                    group: Some(group),
+                    scope,
+                    scope_cells: scope_cells.into_iter().collect(),
+                    regions: regions.into_iter().collect(),
+                    expression: solver
+                        .puzzleparse()
+                        .constraint_expression(constraint)
+                        .cloned(),
+                });
+            }
+        }
+
+        let state = State {
+            knowledge_grid: Some(knowledgegrid),
+            statements: Some(statements),
+            description: Some(comments.to_owned()),
+            candidate_counts: None,
+            explanation: None,
+            grid_stats: None,
+            rule_coverage: None,
+        };
+
+        Ok(Problem {
+            warnings: SupportedKinds::warnings(&puzzle),
+            puzzle,
+            state: Some(state),
+        })
+    }
+
+    pub fn new_from_puzzle_and_difficulty(
+        solver: &PuzzleSolver,
+        tosolve: &BTreeSet<VarValPair>,
+        known: &BTreeSet<PuzLit>,
+        complexity: &BTreeMap<VarValPair, usize>,
+        unknown: &BTreeSet<VarValPair>,
+        description: &str,
+    ) -> anyhow::Result<Problem> {
+        let puzzle = Puzzle::new_from_puzzle(solver.puzzleparse())?;
+
+        let varnames = tosolve
+            .iter()
+            .map(|x| x.var().name().clone())
+            .chain(known.iter().map(|x| x.var().name().clone()))
+            .collect::<HashSet<String>>();
+
+        let allowed_names: HashSet<String> = if varnames.len() == 1 {
+            varnames
+        } else if varnames.contains("grid") {
+            {
+                let mut set = HashSet::new();
+                set.insert("grid".to_string());
+                set
+            }
+        } else {
+            return Err(anyhow::anyhow!(
+                "More than one variable matrix, and none called 'grid', so not sure what to print: {:?}",
+                varnames
+            ));
+        };
+
+        let mut knowledgegrid: Vec<Vec<Option<Vec<StateLit>>>> =
+            vec![
+                vec![None; usize::try_from(puzzle.width).context("width is negative")?];
+                usize::try_from(puzzle.height).context("height is negative")?
+            ];
+
+        let all_lits = solver.puzzleparse().all_var_varvals();
+
+        let complexity_vals: BTreeSet<_> = complexity.values().collect();
+
+        for l in all_lits {
+            if !(tosolve.contains(&l) || known.contains(&PuzLit::new_eq(l.clone()))) {
+                continue;
+            }
+
+            if !allowed_names.contains(l.var().name()) {
+                continue;
+            }
+
+            // TODO: Handle more than one variable matrix?
+            let index = puzzle.cell_of(l.var());
+            assert_eq!(index.len(), 2);
+            let i = usize::try_from(index[0]).context("negative index 0?")?;
+            let j = usize::try_from(index[1]).context("negative index 1?")?;
+
+            assert!(i > 0, "Variables should be 1-indexed");
+            assert!(j > 0, "Variables should be 1-indexed");
+
+            if !puzzle.is_cell(&index) {
+                continue;
+            }
+
+            let i = i - 1;
+            let j = j - 1;
+
+            let mut tags = BTreeSet::new();
+
+            let mut meta = LitMeta::default();
+
+            if let Some(val) = complexity.get(&l) {
+                let i = complexity_vals.iter().position(|&v| v == val).unwrap_or(0);
+                tags.insert(Class::Highlight(i));
+                tags.insert(Class::JsHighlighter);
+            } else if unknown.contains(&l) {
+                tags.insert(Class::Highlight(complexity_vals.len()));
+                tags.insert(Class::JsHighlighter);
+            }
+
+            if known.contains(&PuzLit::new_eq(l.clone())) {
+                puzzle.tag_known(&l, &mut tags, &mut meta);
+            }
+
+            if knowledgegrid[i][j].is_none() {
+                knowledgegrid[i][j] = Some(vec![]);
+            }
+
+            knowledgegrid[i][j].as_mut().unwrap().push(StateLit {
+                val: l.val(),
+                classes: tags,
+                meta: Some(meta),
+            });
+        }
+
+        let statements = complexity_vals
+            .iter()
+            .map(|consize| format!("MUS size {consize}"))
+            .chain((!unknown.is_empty()).then(|| "Unknown difficulty (out of time)".to_owned()))
+            .enumerate()
+            .map(|(i, content)| Statement {
+                content,
+                classes: [Class::Highlight(i), Class::JsHighlighter].into(),
+                constraint: None,
+                group: None,
+                scope: vec![],
+                scope_cells: vec![],
+                regions: vec![],
+                expression: None,
+            })
+            .collect_vec();
+
+        let state = State {
+            knowledge_grid: Some(knowledgegrid),
+            statements: Some(statements),
+            description: Some(description.to_owned()),
+            candidate_counts: None,
+            explanation: None,
+            grid_stats: None,
+            rule_coverage: None,
+        };
+
+        Ok(Problem {
+            warnings: SupportedKinds::warnings(&puzzle),
+            puzzle,
+            state: Some(state),
+        })
+    }
+
+    /// Adds the candidate counts of the cells of the grid to the state, as an
+    /// extra layer drawn over the knowledge grid. As with the knowledge grid,
+    /// only variables called 'grid' are drawn if there is more than one matrix.
+    pub fn add_candidate_counts(
+        &mut self,
+        counts: &BTreeMap<PuzVar, CandidateCount>,
+    ) -> anyhow::Result<()> {
+        let width = usize::try_from(self.puzzle.width).context("width is negative")?;
+        let height = usize::try_from(self.puzzle.height).context("height is negative")?;
+
+        let varnames: HashSet<&String> = counts.keys().map(PuzVar::name).collect();
+        let only_grid = varnames.len() > 1;
+
+        let mut grid: Vec<Vec<Option<CandidateCount>>> = vec![vec![None; width]; height];
+
+        for (var, count) in counts {
+            if only_grid && var.name() != "grid" {
+                continue;
+            }
+
+            let index = self.puzzle.cell_of(var);
+            if index.len() != 2 {
+                continue;
+            }
+
+            let i = usize::try_from(index[0]).context("negative index 0?")?;
+            let j = usize::try_from(index[1]).context("negative index 1?")?;
+
+            if i == 0 || j == 0 || i > height || j > width || !self.puzzle.is_cell(&index) {
+                continue;
+            }
+
+            grid[i - 1][j - 1] = Some(*count);
+        }
+
+        self.state
+            .get_or_insert(State {
+                knowledge_grid: None,
+                statements: None,
+                description: None,
+                candidate_counts: None,
+                explanation: None,
+                grid_stats: None,
+                rule_coverage: None,
+            })
+            .candidate_counts = Some(grid);
+
+        Ok(())
+    }
+
+    /// Records that the deductions shown are made by step number `step`, in
+    /// [`LitMeta::step`].
+    pub fn mark_step(&mut self, step: usize) {
+        let Some(grid) = self.state.as_mut().and_then(|s| s.knowledge_grid.as_mut()) else {
+            return;
+        };
+
+        let lits = grid.iter_mut().flatten().flatten().flatten();
+        for meta in lits.filter_map(|lit| lit.meta.as_mut()) {
+            if meta.deduced.is_some() {
+                meta.step = Some(step);
+            }
+        }
+    }
+
+    /// Adds the class `litpinned` to the values in the knowledge grid which
+    /// the planner has been told not to deduce.
+    pub fn mark_pinned(&mut self, pinned: &BTreeSet<VarValPair>) {
+        let Some(grid) = self.state.as_mut().and_then(|s| s.knowledge_grid.as_mut()) else {
+            return;
+        };
+
+        for vv in pinned {
+            let index = self.puzzle.cell_of(vv.var());
+            if index.len() != 2 || index[0] < 1 || index[1] < 1 {
+                continue;
+            }
+            let (i, j) = ((index[0] - 1) as usize, (index[1] - 1) as usize);
+
+            let Some(Some(cell)) = grid.get_mut(i).and_then(|row| row.get_mut(j)) else {
+                continue;
+            };
+            for lit in cell.iter_mut().filter(|lit| lit.val == vv.val()) {
+                lit.classes.insert(Class::LitPinned);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use test_log::test;
+
+    use crate::json::{Class, Problem, Puzzle, StateLit, Statement, filter_statements};
+    use crate::problem::util::test_utils::{sudoku_4x4, sudoku_4x4_with};
+
+    #[test]
+    fn test_parse_essence_binairo() -> anyhow::Result<()> {
+        let eprime_path = "./tst/binairo.eprime";
+        let eprimeparam_path = "./tst/binairo-1.param";
+
+        let puz =
+            crate::problem::util::test_utils::build_puzzleparse(eprime_path, eprimeparam_path);
+
+        let p = Puzzle::new_from_puzzle(&puz)?;
+
+        assert_eq!(p.kind, "Binairo");
+        assert_eq!(p.width, 6);
+        assert_eq!(p.height, 6);
+        assert!(p.binary_domain);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binary_domain() -> anyhow::Result<()> {
+        let puz = sudoku_4x4();
+
+        assert!(!Puzzle::new_from_puzzle(&puz)?.binary_domain);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_regions() -> anyhow::Result<()> {
+        let puz = sudoku_4x4_with("sudoku-4x4-regions.json", "sudoku-4x4.dimacs");
+
+        let puzzle = Puzzle::new_from_puzzle(&puz)?;
+        assert_eq!(puzzle.region_of(&[1, 1]), Some(1));
+        assert_eq!(puzzle.region_of(&[3, 2]), Some(3));
+        assert_eq!(puzzle.region_of(&[5, 1]), None);
+        assert_eq!(puzzle.region_of(&[1]), None);
+
+        let solver = crate::problem::solver::PuzzleSolver::new(std::sync::Arc::new(puz))?;
+        let mut plan = crate::problem::planner::PuzzlePlanner::new(solver);
+        let mut boxes_seen = false;
+        while !plan.get_provable_varlits().is_empty() {
+            let step = plan.next_step();
+            let problem = plan.step_problem(&step);
+            for statement in problem.state.unwrap().statements.unwrap() {
+                let regions: BTreeSet<i64> = statement
+                    .scope_cells
+                    .iter()
+                    .filter_map(|cell| puzzle.region_of(cell))
+                    .collect();
+                assert_eq!(statement.regions, regions.into_iter().collect::<Vec<_>>());
+                if statement.constraint.as_deref() == Some("box_alldiff") {
+                    boxes_seen = true;
+                    assert_eq!(statement.regions.len(), 1);
+                }
+            }
+            plan.apply_step(&step);
+        }
+        assert!(boxes_seen);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mask() -> anyhow::Result<()> {
+        use crate::problem::planner::{LiteralStatus, PuzzlePlanner};
+        use crate::problem::solver::PuzzleSolver;
+
+        let puz = sudoku_4x4_with("sudoku-4x4-mask.json", "sudoku-4x4.dimacs");
+
+        // Cells (1,2) and (4,4) are holes
+        let puzzle = Puzzle::new_from_puzzle(&puz)?;
+        assert!(!puzzle.is_cell(&[1, 2]));
+        assert!(!puzzle.is_cell(&[4, 4]));
+        assert!(puzzle.is_cell(&[1, 1]));
+        assert!(puzzle.is_cell(&[5, 5]));
+
+        let holes = |grid: &Vec<Vec<Option<Vec<StateLit>>>>| {
+            (1..=4)
+                .flat_map(|i| (1..=4).map(move |j| (i, j)))
+                .filter(|&(i, j)| grid[i - 1][j - 1].is_none())
+                .collect::<Vec<_>>()
+        };
+
+        let solver = PuzzleSolver::new(std::sync::Arc::new(puz))?;
+        let tosolve = solver.puzzleparse().all_var_varvals();
+        let problem = Problem::new_from_puzzle_and_difficulty(
+            &solver,
+            &tosolve,
+            &BTreeSet::new(),
+            &std::collections::BTreeMap::new(),
+            &BTreeSet::new(),
+            "",
+        )?;
+        let grid = problem.state.unwrap().knowledge_grid.unwrap();
+        assert_eq!(holes(&grid), vec![(1, 2), (4, 4)]);
+
+        let mut plan = PuzzlePlanner::new(solver);
+        let step = plan.next_step();
+        let grid = plan
+            .step_problem(&step)
+            .state
+            .unwrap()
+            .knowledge_grid
+            .unwrap();
+        assert_eq!(holes(&grid), vec![(1, 2), (4, 4)]);
+        assert_eq!(plan.literal_status(&[1, 2, 3]), LiteralStatus::NotACell);
+        assert_ne!(plan.literal_status(&[1, 3, 3]), LiteralStatus::NotACell);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_given_value() -> anyhow::Result<()> {
+        let puz = sudoku_4x4();
+
+        let puzzle = Puzzle::new_from_puzzle(&puz)?;
+        assert_eq!(puzzle.given_value(&[1, 1]), Some(1));
+        assert_eq!(puzzle.given_value(&[2, 2]), Some(4));
+        // 0 marks an empty cell in a sudoku
+        assert_eq!(puzzle.given_value(&[1, 2]), None);
+        assert_eq!(puzzle.given_value(&[5, 1]), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_class_strings() -> anyhow::Result<()> {
+        let classes = [
+            (Class::GroupHeader, "js_group_header"),
+            (Class::Highlight(12), "highlight_con12"),
+            (
+                Class::HighlightLit("lit_x_i1_i2_v3".to_owned()),
+                "highlight_lit_x_i1_i2_v3",
+            ),
+            (Class::JsHighlighter, "js_highlighter"),
+            (Class::LitKnown, "litknown"),
+            (Class::LitPinned, "litpinned"),
+            (Class::Other("custom".to_owned()), "custom"),
+        ];
+        for (class, name) in classes {
+            assert_eq!(serde_json::to_string(&class)?, format!("\"{name}\""));
+            assert_eq!(
+                serde_json::from_str::<Class>(&format!("\"{name}\""))?,
+                class
+            );
+        }
+
+        // Older versions wrote a cell with no classes as null
+        let lit: StateLit = serde_json::from_str(r#"{"val": 2, "classes": null}"#)?;
+        assert!(lit.classes.is_empty());
+        let lit: StateLit = serde_json::from_str(r#"{"val": 2, "classes": ["litpos"]}"#)?;
+        assert_eq!(lit.classes, BTreeSet::from([Class::LitPos]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_statements() {
+        let statement =
+            |content: &str, constraint: Option<&str>, group, scope: &[&str]| Statement {
+                content: content.to_string(),
+                classes: BTreeSet::new(),
+                constraint: constraint.map(str::to_string),
+                group,
+                scope: scope.iter().map(|s| (*s).to_string()).collect(),
+                scope_cells: vec![],
+                regions: vec![],
+                expression: None,
+            };
+
+        let statements = vec![
+            statement("Made the following deductions", None, Some(0), &[]),
+            statement(
+                "Row 1 contains 1 to 4",
+                Some("rowalldiff"),
+                Some(0),
+                &["grid_1_1"],
+            ),
+            statement("Made the following deductions", None, Some(1), &[]),
+            statement(
+                "Box 2 contains 1 to 4",
+                Some("boxalldiff"),
+                Some(1),
+                &["grid_3_2"],
+            ),
+        ];
+
+        assert_eq!(filter_statements(&statements, "", None).len(), 4);
+        assert_eq!(filter_statements(&statements, "", Some(1)).len(), 2);
+        assert_eq!(
+            filter_statements(&statements, "ROW 1", None),
+            vec![&statements[1]]
+        );
+        assert_eq!(
+            filter_statements(&statements, "boxalldiff", None),
+            vec![&statements[3]]
+        );
+        assert_eq!(
+            filter_statements(&statements, "grid_1_1", None),
+            vec![&statements[1]]
+        );
+        assert!(filter_statements(&statements, "row", Some(1)).is_empty());
+    }
+}
@@ -0,0 +1,12 @@
+//! The solver behind demystify: parsing puzzles, finding the deductions
+//! which explain them, and the JSON they are drawn from. Most programs should
+//! use the `demystify` crate, which re-exports this one along with
+//! demystify-render.
+
+#![allow(dead_code)]
+
+pub mod draw;
+pub mod json;
+pub mod problem;
+#[doc(hidden)]
+pub mod satcore;
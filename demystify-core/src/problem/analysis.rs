@@ -0,0 +1,614 @@
+//! Analyses which run over complete solve traces, rather than single steps.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use rustsat::types::Lit;
+use serde::{Deserialize, Serialize};
+
+use super::{PuzLit, PuzVar, musdict::MusContext, parse::PuzzleParse, planner::PuzzlePlanner};
+
+/// How often a single `$#CON` annotation was used across a corpus.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ConUsage {
+    /// Number of instances in which the constraint appeared in at least one MUS
+    pub instances: usize,
+    /// Total number of MUSes the constraint appeared in, across all instances
+    pub muses: usize,
+}
+
+/// Aggregates, over a set of instances of a single model, how often each
+/// `$#CON` annotation appears in the MUSes used to explain a solve.
+///
+/// This is used to find annotations which never fire (and so could be
+/// removed from a model), or which are needed in every instance.
+#[derive(Clone, Debug, Default)]
+pub struct ConstraintUsage {
+    instances: usize,
+    usage: BTreeMap<String, ConUsage>,
+}
+
+impl ConstraintUsage {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds one solved instance, given as the steps produced by
+    /// [`PuzzlePlanner::quick_solve_muses`](super::planner::PuzzlePlanner::quick_solve_muses).
+    pub fn add_instance(&mut self, puzzle: &PuzzleParse, steps: &[Vec<MusContext>]) {
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+
+        for mc in steps.iter().flatten() {
+            let names: BTreeSet<String> = mc
+                .mus
+                .iter()
+                .flat_map(|c| puzzle.lit_to_vars(c))
+                .map(|p| p.var().name().clone())
+                .collect();
+            for name in names {
+                *counts.entry(name).or_default() += 1;
+            }
+        }
+
+        self.add_instance_counts(puzzle.eprime.cons.keys().cloned(), &counts);
+    }
+
+    /// Adds one instance, given the `$#CON` annotations declared in the model
+    /// and the number of MUSes each of them appeared in.
+    pub fn add_instance_counts(
+        &mut self,
+        declared: impl IntoIterator<Item = String>,
+        counts: &BTreeMap<String, usize>,
+    ) {
+        self.instances += 1;
+
+        for name in declared {
+            self.usage.entry(name).or_default();
+        }
+
+        for (name, &count) in counts {
+            let entry = self.usage.entry(name.clone()).or_default();
+            if count > 0 {
+                entry.instances += 1;
+                entry.muses += count;
+            }
+        }
+    }
+
+    /// The number of instances added so far.
+    #[must_use]
+    pub fn instances(&self) -> usize {
+        self.instances
+    }
+
+    /// The usage of every constraint annotation seen so far.
+    #[must_use]
+    pub fn usage(&self) -> &BTreeMap<String, ConUsage> {
+        &self.usage
+    }
+
+    /// Constraint annotations which never appeared in any MUS.
+    #[must_use]
+    pub fn never_used(&self) -> Vec<String> {
+        self.usage
+            .iter()
+            .filter(|(_, u)| u.instances == 0)
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// Constraint annotations which appeared in at least one MUS in every instance.
+    #[must_use]
+    pub fn always_used(&self) -> Vec<String> {
+        if self.instances == 0 {
+            return vec![];
+        }
+        self.usage
+            .iter()
+            .filter(|(_, u)| u.instances == self.instances)
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+}
+
+/// Which of a puzzle's starting clues are in the scope of the constraints
+/// used by a single solve.
+///
+/// A clue is an assignment which is known before any `$#CON` constraint is
+/// used, which is usually a value given in the puzzle. Clues which are never
+/// in scope cannot have contributed to any deduction, so are redundant
+/// givens. Clues in scope may still be redundant, as another route through
+/// the puzzle might not need them, so this is not a minimal set of clues:
+/// the clues are fixed in the SAT instance, so cannot be taken away one at a
+/// time to check.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClueScope {
+    pub in_scope: BTreeSet<PuzLit>,
+    pub out_of_scope: BTreeSet<PuzLit>,
+}
+
+impl ClueScope {
+    /// Builds the clue scope of one solve, given the clues (the literals in
+    /// [`PuzzlePlanner::get_all_known_lits`](super::planner::PuzzlePlanner::get_all_known_lits)
+    /// before solving starts) and the steps produced by
+    /// [`PuzzlePlanner::quick_solve_muses`](super::planner::PuzzlePlanner::quick_solve_muses).
+    #[must_use]
+    pub fn new(puzzle: &PuzzleParse, clues: &BTreeSet<Lit>, steps: &[Vec<MusContext>]) -> Self {
+        let mut vars = BTreeSet::new();
+
+        for mc in steps.iter().flatten() {
+            for con in &mc.mus {
+                for l in puzzle.varlits_in_con.get(con).into_iter().flatten() {
+                    for vvp in puzzle.direct_or_ordered_lit_to_varvalpair(l) {
+                        vars.insert(vvp.var().clone());
+                    }
+                }
+            }
+        }
+
+        let clues = clues
+            .iter()
+            .flat_map(|l| puzzle.lit_to_vars(l))
+            .filter(|p| p.sign())
+            .cloned();
+
+        Self::from_scope(clues, &vars)
+    }
+
+    /// Splits `clues` into those whose variable is in `vars` (the variables
+    /// in scope of the constraints used in the solve), and those which are not.
+    #[must_use]
+    pub fn from_scope(clues: impl IntoIterator<Item = PuzLit>, vars: &BTreeSet<PuzVar>) -> Self {
+        let (in_scope, out_of_scope) = clues.into_iter().partition(|c| vars.contains(&c.var()));
+        Self {
+            in_scope,
+            out_of_scope,
+        }
+    }
+}
+
+/// The percentage of non-trivial steps a single `$#CON` must be used by to
+/// dominate a solve.
+pub const DOMINANT_PERCENT: usize = 75;
+
+/// Solves with fewer non-trivial steps than this are never dominated, as a
+/// few steps using the same constraint say little about the puzzle.
+pub const DOMINANT_MIN_STEPS: usize = 4;
+
+/// A `$#CON` used by most of the non-trivial steps of a solve, which
+/// suggests the puzzle only needs one kind of reasoning. A step is
+/// non-trivial if it needs more than one constraint.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DominantConstraint {
+    pub name: String,
+    /// The non-trivial steps using the constraint
+    pub steps: usize,
+    /// The non-trivial steps in the solve
+    pub of: usize,
+}
+
+impl fmt::Display for DominantConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} is used by {} of the {} steps needing more than one constraint",
+            self.name, self.steps, self.of
+        )
+    }
+}
+
+/// Counts, for each `$#CON`, the non-trivial steps of a solve which use it,
+/// to find a [`DominantConstraint`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NontrivialUsage {
+    steps: usize,
+    uses: BTreeMap<String, usize>,
+}
+
+impl NontrivialUsage {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds one step, given the MUSes it applied. Only MUSes of more than one
+    /// constraint are counted, and steps without any are skipped.
+    pub fn add_step(&mut self, puzzle: &PuzzleParse, muses: &[MusContext]) {
+        let names: BTreeSet<String> = muses
+            .iter()
+            .filter(|mc| mc.mus_len() > 1)
+            .flat_map(|mc| mus_constraint_names(puzzle, mc))
+            .collect();
+        self.add_step_names(names);
+    }
+
+    /// Adds one non-trivial step, given the `$#CON` names it used. A step
+    /// with no names is skipped.
+    pub fn add_step_names(&mut self, names: BTreeSet<String>) {
+        if names.is_empty() {
+            return;
+        }
+        self.steps += 1;
+        for name in names {
+            *self.uses.entry(name).or_default() += 1;
+        }
+    }
+
+    /// The number of non-trivial steps added so far.
+    #[must_use]
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// The most used `$#CON`, if it is used by at least [`DOMINANT_PERCENT`]
+    /// of at least [`DOMINANT_MIN_STEPS`] non-trivial steps.
+    #[must_use]
+    pub fn dominant(&self) -> Option<DominantConstraint> {
+        if self.steps < DOMINANT_MIN_STEPS {
+            return None;
+        }
+        // The first of the most used, so ties are broken by name
+        let (name, &steps) = self.uses.iter().rev().max_by_key(|(_, n)| **n)?;
+        (steps * 100 >= self.steps * DOMINANT_PERCENT).then(|| DominantConstraint {
+            name: name.clone(),
+            steps,
+            of: self.steps,
+        })
+    }
+}
+
+/// How many standard deviations from the steps before it a step's MUS size
+/// must be to be a [`DifficultySpike`].
+pub const SPIKE_Z: f64 = 2.0;
+
+/// The number of steps before the first one which can be a spike, so there
+/// is a distribution to compare it to.
+pub const SPIKE_MIN_STEPS: usize = 4;
+
+/// The smallest standard deviation used when comparing a step to those
+/// before it, so after a run of steps of the same size, a step one
+/// constraint larger or smaller is not a spike.
+pub const SPIKE_MIN_SPREAD: f64 = 1.0;
+
+/// A step whose MUS size is far from those of the steps before it, which is
+/// where a solve is likely to feel unfair (if harder) or anticlimactic (if
+/// easier).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DifficultySpike {
+    /// The step, counting from 1
+    pub step: usize,
+    /// The most constraints a MUS of the step used
+    pub size: usize,
+    /// The mean size of the steps before it
+    pub mean: f64,
+    /// How many standard deviations `size` is from `mean`, negative if the
+    /// step is easier
+    pub z: f64,
+}
+
+// `mean` and `z` are never NaN
+impl Eq for DifficultySpike {}
+
+impl DifficultySpike {
+    /// Whether the step is harder than those before it.
+    #[must_use]
+    pub fn harder(&self) -> bool {
+        self.z > 0.0
+    }
+}
+
+impl fmt::Display for DifficultySpike {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "step {} is unexpectedly {}, using {} constraints against a mean of {:.1} before it (z = {:.1})",
+            self.step,
+            if self.harder() { "hard" } else { "easy" },
+            self.size,
+            self.mean,
+            self.z
+        )
+    }
+}
+
+/// The steps of a solve whose sizes, given in order, are at least
+/// [`SPIKE_Z`] standard deviations from the mean of the steps before them.
+#[must_use]
+pub fn difficulty_spikes(sizes: &[usize]) -> Vec<DifficultySpike> {
+    let mut spikes = vec![];
+    for (i, &size) in sizes.iter().enumerate().skip(SPIKE_MIN_STEPS) {
+        let before = &sizes[..i];
+        let n = before.len() as f64;
+        let mean = before.iter().sum::<usize>() as f64 / n;
+        let variance = before
+            .iter()
+            .map(|&s| (s as f64 - mean).powi(2))
+            .sum::<f64>()
+            / n;
+        let z = (size as f64 - mean) / variance.sqrt().max(SPIKE_MIN_SPREAD);
+        if z.abs() >= SPIKE_Z {
+            spikes.push(DifficultySpike {
+                step: i + 1,
+                size,
+                mean,
+                z,
+            });
+        }
+    }
+    spikes
+}
+
+/// The names of the `$#CON`s in a MUS. A constraint without a `$#CON` name
+/// is given by its description.
+#[must_use]
+pub fn mus_constraint_names(puzzle: &PuzzleParse, mc: &MusContext) -> BTreeSet<String> {
+    mc.mus
+        .iter()
+        .map(|c| {
+            let con = puzzle.lit_to_con(c);
+            puzzle.constraint_name(con).unwrap_or_else(|| con.clone())
+        })
+        .collect()
+}
+
+/// What went wrong in a puzzle with `$#REVEAL` statements.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RevealProblem {
+    /// The puzzle has no solution. `constraints` and `known` together have
+    /// no solution, and removing any one of them gives a solution.
+    Unsatisfiable {
+        constraints: Vec<String>,
+        known: BTreeSet<PuzLit>,
+    },
+    /// Nothing more can be deduced, but these variables are still undecided.
+    NotUnique { undecided: BTreeSet<PuzVar> },
+}
+
+/// The first point at which a multi-step puzzle stopped being uniquely solvable.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RevealFailure {
+    /// The number of rounds of deductions made before the problem was
+    /// found. In each round everything provable is deduced.
+    pub round: usize,
+    /// The facts revealed by the last round, which are the most likely
+    /// cause. In round 0 these are the facts revealed by the starting clues.
+    pub reveals: BTreeSet<PuzLit>,
+    pub problem: RevealProblem,
+}
+
+/// Solves a puzzle with `$#REVEAL` statements, revealing facts as it goes,
+/// and returns where it first becomes unsolvable or stops having a unique
+/// solution. Returns `None` if the puzzle is solved.
+///
+/// A mistake in the parameters of a multi-step puzzle (such as a wrong
+/// minesweeper count) may only show up once the cell it is in is revealed,
+/// so this helps puzzle authors find which reveal, and which clues, are to
+/// blame.
+pub fn localise_reveal_failure(planner: &mut PuzzlePlanner) -> Option<RevealFailure> {
+    let reveal_names = planner.puzzle().eprime.reveal_values.clone();
+    let reveals_in = |planner: &PuzzlePlanner, lits: &[Lit]| -> BTreeSet<PuzLit> {
+        lits.iter()
+            .flat_map(|l| planner.puzzle().lit_to_vars(l))
+            .filter(|p| p.sign() && reveal_names.contains(p.var().name()))
+            .cloned()
+            .collect()
+    };
+
+    let mut round = 0;
+    let mut reveals = reveals_in(planner, &planner.get_all_known_lits());
+
+    loop {
+        if !planner.solver().is_currently_solvable() {
+            let core = planner
+                .solver()
+                .explain_unsolvable()
+                .expect("IE: unsolvable puzzle has no core");
+            let puzzle = planner.puzzle();
+            let (cons, known): (Vec<Lit>, Vec<Lit>) =
+                core.into_iter().partition(|l| puzzle.lit_is_con(l));
+            return Some(RevealFailure {
+                round,
+                reveals,
+                problem: RevealProblem::Unsatisfiable {
+                    constraints: cons.iter().map(|l| puzzle.lit_to_con(l).clone()).collect(),
+                    known: known
+                        .iter()
+                        .flat_map(|l| puzzle.lit_to_vars(l))
+                        .cloned()
+                        .collect(),
+                },
+            });
+        }
+
+        let provable = planner.get_provable_varlits();
+        if provable.is_empty() {
+            let undecided: BTreeSet<PuzVar> = planner
+                .solver()
+                .get_literals_to_try_solving()
+                .iter()
+                .flat_map(|l| planner.puzzle().lit_to_vars(l))
+                .map(PuzLit::var)
+                .collect();
+            if undecided.is_empty() {
+                return None;
+            }
+            return Some(RevealFailure {
+                round,
+                reveals,
+                problem: RevealProblem::NotUnique { undecided },
+            });
+        }
+
+        let before = planner.get_all_known_lits().len();
+        for l in &provable {
+            planner.mark_lit_as_deduced(l);
+        }
+        let after = planner.get_all_known_lits()[before..].to_vec();
+        reveals = reveals_in(planner, &after);
+        round += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::problem::VarValPair;
+    use crate::problem::util::test_utils::{sudoku_4x4, sudoku_4x4_planner};
+
+    fn declared() -> Vec<String> {
+        vec!["row".to_owned(), "col".to_owned(), "box".to_owned()]
+    }
+
+    #[test]
+    fn test_constraint_usage_empty() {
+        let usage = ConstraintUsage::new();
+        assert_eq!(usage.instances(), 0);
+        assert!(usage.never_used().is_empty());
+        assert!(usage.always_used().is_empty());
+    }
+
+    #[test]
+    fn test_constraint_usage_aggregate() {
+        let mut usage = ConstraintUsage::new();
+
+        usage.add_instance_counts(
+            declared(),
+            &BTreeMap::from([("row".to_owned(), 3), ("col".to_owned(), 1)]),
+        );
+        usage.add_instance_counts(declared(), &BTreeMap::from([("row".to_owned(), 2)]));
+
+        assert_eq!(usage.instances(), 2);
+        assert_eq!(usage.never_used(), vec!["box".to_owned()]);
+        assert_eq!(usage.always_used(), vec!["row".to_owned()]);
+        assert_eq!(
+            usage.usage().get("row"),
+            Some(&ConUsage {
+                instances: 2,
+                muses: 5
+            })
+        );
+        assert_eq!(
+            usage.usage().get("col"),
+            Some(&ConUsage {
+                instances: 1,
+                muses: 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_difficulty_spikes() {
+        // Too few steps before any of them to say
+        assert!(difficulty_spikes(&[1, 9, 1, 9]).is_empty());
+        // A run of equal steps, then one a constraint larger
+        assert!(difficulty_spikes(&[2, 2, 2, 2, 3]).is_empty());
+
+        let spikes = difficulty_spikes(&[1, 2, 1, 2, 6, 1]);
+        assert_eq!(spikes.len(), 1);
+        let spike = &spikes[0];
+        assert_eq!((spike.step, spike.size), (5, 6));
+        assert!((spike.mean - 1.5).abs() < 1e-9);
+        assert!((spike.z - 4.5).abs() < 1e-9);
+        assert!(spike.harder());
+        assert_eq!(
+            spike.to_string(),
+            "step 5 is unexpectedly hard, using 6 constraints against a mean of 1.5 before it (z = 4.5)"
+        );
+
+        let spikes = difficulty_spikes(&[5, 6, 5, 6, 5, 1]);
+        assert_eq!(spikes.len(), 1);
+        assert!(!spikes[0].harder());
+        assert!(
+            spikes[0]
+                .to_string()
+                .starts_with("step 6 is unexpectedly easy")
+        );
+    }
+
+    #[test]
+    fn test_dominant_constraint() {
+        let names = |names: &[&str]| names.iter().map(|&n| n.to_owned()).collect();
+        let mut usage = NontrivialUsage::new();
+        usage.add_step_names(BTreeSet::new());
+        for _ in 0..3 {
+            usage.add_step_names(names(&["row", "col"]));
+        }
+        assert_eq!(usage.steps(), 3);
+        // Too few steps to say
+        assert_eq!(usage.dominant(), None);
+
+        usage.add_step_names(names(&["box"]));
+        let dominant = usage.dominant().unwrap();
+        assert_eq!(
+            dominant,
+            DominantConstraint {
+                name: "col".to_owned(),
+                steps: 3,
+                of: 4
+            }
+        );
+        assert_eq!(
+            dominant.to_string(),
+            "col is used by 3 of the 4 steps needing more than one constraint"
+        );
+
+        usage.add_step_names(names(&["box"]));
+        assert_eq!(usage.dominant(), None);
+    }
+
+    #[test]
+    fn test_clue_scope_from_scope() {
+        let a = PuzVar::new("grid", vec![1, 1]);
+        let b = PuzVar::new("grid", vec![1, 2]);
+        let clue_a = PuzLit::new_eq(VarValPair::new(&a, 3));
+        let clue_b = PuzLit::new_eq(VarValPair::new(&b, 4));
+
+        let scope = ClueScope::from_scope([clue_a.clone(), clue_b.clone()], &BTreeSet::from([a]));
+
+        assert_eq!(scope.in_scope, BTreeSet::from([clue_a]));
+        assert_eq!(scope.out_of_scope, BTreeSet::from([clue_b]));
+    }
+
+    #[test]
+    fn test_clue_scope_sudoku() {
+        use std::sync::Arc;
+
+        use crate::problem::{planner::PuzzlePlanner, solver::PuzzleSolver};
+
+        let puz = Arc::new(sudoku_4x4());
+
+        let mut planner = PuzzlePlanner::new(PuzzleSolver::new(puz.clone()).unwrap());
+        let clues: BTreeSet<_> = planner.get_all_known_lits().iter().copied().collect();
+        let steps = planner.quick_solve_muses();
+
+        let scope = ClueScope::new(&puz, &clues, &steps);
+
+        // The puzzle has 8 givens
+        assert_eq!(scope.in_scope.len() + scope.out_of_scope.len(), 8);
+        assert!(!scope.in_scope.is_empty());
+    }
+
+    #[test]
+    fn test_localise_failure_sudoku() {
+        let mut planner = sudoku_4x4_planner();
+        assert_eq!(localise_reveal_failure(&mut planner), None);
+
+        // (1,1) is given as 1, so (1,2) can't also be 1
+        let mut planner = sudoku_4x4_planner();
+        let bad = PuzLit::new_eq(VarValPair::new(&PuzVar::new("grid", vec![1, 2]), 1));
+        let bad_lit = planner.solver().puzlit_to_lit(&bad);
+        planner.solver().add_not_provable_known_lit(bad_lit);
+
+        let failure = localise_reveal_failure(&mut planner).unwrap();
+        assert_eq!(failure.round, 0);
+        let RevealProblem::Unsatisfiable { constraints, known } = failure.problem else {
+            panic!("Expected the puzzle to be unsatisfiable");
+        };
+        assert_eq!(constraints.len(), 1);
+        assert!(constraints[0].contains("same row"));
+        assert!(known.contains(&bad));
+    }
+}
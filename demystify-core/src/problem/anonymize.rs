@@ -0,0 +1,249 @@
+//! Anonymised copies of puzzles, which can be attached to bug reports about
+//! puzzles which cannot be shared.
+//!
+//! An [`Anonymized`] puzzle keeps exactly what the solver sees: the same CNF
+//! (with the same SAT variables, so searches behave the same), which SAT
+//! variables belong to which `$#VAR`, `$#AUX`, `$#CON` and `$#REVEAL`, and the
+//! indices and values of every variable. Everything else is dropped or
+//! renamed: names become `var01`, `con01` and so on, constraint descriptions
+//! become the constraint name and its indices, the `$#KIND` and the Essence'
+//! constraints are left out, and only parameters without any strings in them
+//! (which would be labels) are kept.
+//!
+//! The result is read back with
+//! [`parse_eprime_with_dimacs`](super::parse::parse_eprime_with_dimacs), with
+//! no need for conjure or savilerow.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write as _,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use rustsat::types::Lit;
+
+use super::{PuzVar, parse::PuzzleParse};
+
+/// The files describing an anonymised puzzle.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Anonymized {
+    /// An Essence' file with only the `$#` annotations
+    pub eprime: String,
+    /// The parameters, as JSON
+    pub params: BTreeMap<String, serde_json::Value>,
+    /// The CNF, with a comment naming each SAT variable as savilerow would
+    pub dimacs: String,
+}
+
+impl Anonymized {
+    #[must_use]
+    pub fn new(puzzle: &PuzzleParse) -> Self {
+        let annotations = &puzzle.eprime;
+
+        // Every name gets the same number of digits, so they sort in the same
+        // order as the originals. Hidden variables cannot be called 'aux01',
+        // as that is how savilerow names its own variables.
+        let count = annotations.vars.len()
+            + annotations.auxvars.len()
+            + annotations.cons.len()
+            + annotations.reveal_values.len();
+        let width = count.to_string().len().max(2);
+        let categories: [(&str, Vec<&String>); 4] = [
+            ("var", annotations.vars.iter().collect()),
+            ("hidden", annotations.auxvars.iter().collect()),
+            ("con", annotations.cons.keys().collect()),
+            ("reveal", annotations.reveal_values.iter().collect()),
+        ];
+        let mut names: BTreeMap<&String, String> = BTreeMap::new();
+        for (prefix, originals) in categories {
+            for (i, original) in originals.into_iter().enumerate() {
+                names.insert(original, format!("{prefix}{:0width$}", i + 1));
+            }
+        }
+
+        // The number of indices of each constraint, for its description
+        let mut con_arity: BTreeMap<&String, usize> = BTreeMap::new();
+        for puzlit in puzzle.litmap.keys() {
+            let var = puzlit.var();
+            if let Some((name, _)) = annotations.cons.get_key_value(var.name()) {
+                con_arity.insert(name, var.indices().len());
+            }
+        }
+
+        let mut eprime = "language ESSENCE' 1.0\n$ Anonymised by demystify\n\n".to_owned();
+        for var in &annotations.vars {
+            let _ = writeln!(eprime, "$#VAR {}", names[var]);
+        }
+        for var in &annotations.auxvars {
+            let _ = writeln!(eprime, "$#AUX {}", names[var]);
+        }
+        for con in annotations.cons.keys() {
+            let name = &names[con];
+            let indices = (0..con_arity.get(con).copied().unwrap_or(0))
+                .map(|i| format!("{{{{index[{i}]}}}}"))
+                .collect::<Vec<_>>();
+            if indices.is_empty() {
+                let _ = writeln!(eprime, "$#CON {name} \"{name}\"");
+            } else {
+                let _ = writeln!(eprime, "$#CON {name} \"{name} {}\"", indices.join(","));
+            }
+        }
+        for (var, reveal) in &annotations.reveal {
+            let _ = writeln!(eprime, "$#REVEAL {} {}", names[var], names[reveal]);
+        }
+
+        let params = annotations
+            .params()
+            .iter()
+            .filter(|(_, value)| !contains_string(value))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+
+        let savile_row_name = |var: &PuzVar| {
+            let mut name = names
+                .get(var.name())
+                .cloned()
+                .unwrap_or_else(|| var.name().clone());
+            for &i in var.indices() {
+                if i < 0 {
+                    let _ = write!(name, "_n{:05}", -i);
+                } else {
+                    let _ = write!(name, "_{i:05}");
+                }
+            }
+            name
+        };
+
+        let clauses = puzzle
+            .cnf
+            .as_deref()
+            .map(|cnf| cnf.iter().collect::<Vec<_>>());
+        let clauses = clauses.unwrap_or_default();
+        let max_var = clauses
+            .iter()
+            .flat_map(|clause| clause.iter())
+            .chain(puzzle.litmap.values())
+            .chain(puzzle.order_encoding_all_lits.iter())
+            .map(|lit| lit.var().to_ipasir())
+            .max()
+            .unwrap_or(0);
+
+        let mut dimacs = format!("p cnf {max_var} {}\n", clauses.len());
+        for (puzlit, lit) in &puzzle.litmap {
+            if puzlit.sign() {
+                let _ = writeln!(
+                    dimacs,
+                    "c Var '{}' direct represents '{}' with '{}'",
+                    savile_row_name(&puzlit.var()),
+                    puzlit.val(),
+                    lit.to_ipasir()
+                );
+            }
+        }
+        // The values of the order encoding are never read back, so are left out
+        for (var, lits) in &puzzle.order_encoding_map {
+            let lits: BTreeSet<&Lit> = lits.iter().filter(|l| l.is_pos()).collect();
+            for lit in lits {
+                let _ = writeln!(
+                    dimacs,
+                    "c Var '{}' order represents '' with '{}'",
+                    savile_row_name(var),
+                    lit.to_ipasir()
+                );
+            }
+        }
+        for clause in clauses {
+            for lit in clause.iter() {
+                let _ = write!(dimacs, "{} ", lit.to_ipasir());
+            }
+            dimacs.push_str("0\n");
+        }
+
+        Self {
+            eprime,
+            params,
+            dimacs,
+        }
+    }
+
+    /// Writes `<stem>.eprime`, `<stem>.json` and `<stem>.dimacs` into `dir`,
+    /// returning their paths in that order.
+    pub fn write(&self, dir: &Path, stem: &str) -> anyhow::Result<[PathBuf; 3]> {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {dir:?}"))?;
+        let paths = ["eprime", "json", "dimacs"].map(|ext| dir.join(format!("{stem}.{ext}")));
+        let contents = [
+            self.eprime.clone(),
+            serde_json::to_string_pretty(&self.params)?,
+            self.dimacs.clone(),
+        ];
+        for (path, content) in paths.iter().zip(contents) {
+            std::fs::write(path, content).with_context(|| format!("Failed to write {path:?}"))?;
+        }
+        Ok(paths)
+    }
+}
+
+fn contains_string(value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::String(_) => true,
+        serde_json::Value::Array(values) => values.iter().any(contains_string),
+        serde_json::Value::Object(map) => map.values().any(contains_string),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::problem::{
+        parse::parse_eprime_with_dimacs, planner::PuzzlePlanner, solver::PuzzleSolver,
+        util::test_utils::sudoku_4x4,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_anonymize_sudoku() -> anyhow::Result<()> {
+        let puzzle = sudoku_4x4();
+
+        let anonymized = Anonymized::new(&puzzle);
+        for name in ["grid", "row_alldiff", "box", "Sudoku", "same row"] {
+            assert!(!anonymized.eprime.contains(name), "{name}");
+            assert!(!anonymized.dimacs.contains(name), "{name}");
+        }
+        assert!(anonymized.eprime.contains("$#VAR var01"));
+        assert!(
+            anonymized
+                .dimacs
+                .contains("c Var 'var01_00001_00002' direct represents '2'")
+        );
+
+        let dir = tempfile::tempdir()?;
+        let [eprime, json, dimacs] = anonymized.write(dir.path(), "puzzle")?;
+        let reread = parse_eprime_with_dimacs(&eprime, &json, &dimacs)?;
+
+        assert_eq!(reread.cnf, puzzle.cnf);
+        assert_eq!(reread.varset_lits, puzzle.varset_lits);
+        assert_eq!(reread.conset_lits, puzzle.conset_lits);
+        assert_eq!(reread.eprime.params(), puzzle.eprime.params());
+
+        // The solve is the same, apart from the names
+        let solve = |p: PuzzleParse| {
+            let mut planner = PuzzlePlanner::new(PuzzleSolver::new(Arc::new(p)).unwrap());
+            planner
+                .quick_solve()
+                .iter()
+                .map(|step| {
+                    step.iter()
+                        .map(|(lits, cons)| (lits.len(), cons.len()))
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(solve(reread), solve(puzzle));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,259 @@
+//! Checks of what can and cannot be deduced in a puzzle, so puzzle authors
+//! can catch mistakes in their models in CI, run with
+//! `demystify assert --spec asserts.yaml`.
+//!
+//! A spec names the model and parameters, relative to the spec, and a list
+//! of checks. Each check adds some clues to those in the parameters, then
+//! lists literals which must, and must not, be deducible from them:
+//!
+//! ```yaml
+//! model: sudoku.eprime
+//! param: sudoku.param
+//! checks:
+//!   - name: the first row is not given away
+//!     not_provable: ["grid[1, 2]=2", "grid[1, 2]=3"]
+//!   - name: one more clue settles it
+//!     known: ["grid[1, 2]=2"]
+//!     provable: ["grid[1, 3]=3"]
+//! ```
+//!
+//! Literals are written as they are printed, see [`PuzLit`]'s `FromStr`.
+//! A spec can also give `dimacs`, to read the SAT instance instead of
+//! running conjure and savilerow. Specs ending `.yaml` or `.yml` are read
+//! as YAML, and any others as JSON.
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    PuzLit,
+    parse::{PuzzleParse, parse_eprime_with_dimacs, parse_essence},
+    solver::PuzzleSolver,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct AssertSpec {
+    pub model: PathBuf,
+    pub param: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dimacs: Option<PathBuf>,
+    pub checks: Vec<AssertCheck>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AssertCheck {
+    /// Shown in the results, instead of the number of the check
+    pub name: Option<String>,
+    /// Clues added to those in the parameters, for this check only
+    pub known: Vec<String>,
+    /// Literals which must be deducible
+    pub provable: Vec<String>,
+    /// Literals which must not be deducible
+    pub not_provable: Vec<String>,
+}
+
+/// The result of one [`AssertCheck`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct CheckOutcome {
+    pub name: String,
+    /// Why the check failed, or empty if it passed
+    pub failures: Vec<String>,
+}
+
+impl CheckOutcome {
+    #[must_use]
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+impl fmt::Display for CheckOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.passed() {
+            return write!(f, "ok: {}", self.name);
+        }
+        write!(f, "FAILED: {}", self.name)?;
+        for failure in &self.failures {
+            write!(f, "\n  {failure}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads `lits`, checking they are all literals of the puzzle.
+fn parse_lits(solver: &PuzzleSolver, lits: &[String]) -> anyhow::Result<Vec<PuzLit>> {
+    lits.iter()
+        .map(|l| {
+            let lit: PuzLit = l.parse()?;
+            if !solver.puzzleparse().litmap.contains_key(&lit) {
+                anyhow::bail!("{lit} is not a literal of the puzzle");
+            }
+            Ok(lit)
+        })
+        .collect()
+}
+
+impl AssertCheck {
+    /// Runs the check on a copy of `solver`, returning why it failed.
+    fn failures(&self, solver: &PuzzleSolver) -> anyhow::Result<Vec<String>> {
+        let provable = parse_lits(solver, &self.provable)?;
+        let not_provable = parse_lits(solver, &self.not_provable)?;
+
+        let mut solver = solver.fork();
+        for lit in parse_lits(&solver, &self.known)? {
+            let lit = solver.puzlit_to_lit(&lit);
+            solver.add_not_provable_known_lit(lit);
+        }
+        if !solver.is_currently_solvable() {
+            return Ok(vec![
+                "The puzzle has no solution with the known literals".to_owned(),
+            ]);
+        }
+
+        let mut failures = vec![];
+        if let Err(e) = solver.assert_provable(&provable) {
+            failures.push(e.to_string());
+        }
+        if let Err(e) = solver.assert_not_provable(&not_provable) {
+            failures.push(e.to_string());
+        }
+        Ok(failures)
+    }
+}
+
+impl AssertSpec {
+    /// Reads a spec, making the paths in it relative to the spec's directory.
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read assertions {}", path.display()))?;
+        let yaml = path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("yaml") || e.eq_ignore_ascii_case("yml"));
+        let mut spec = if yaml {
+            Self::from_yaml(&text)
+        } else {
+            Self::from_json(&text)
+        }
+        .with_context(|| format!("Failed to parse assertions {}", path.display()))?;
+
+        let dir = path.parent().unwrap_or(Path::new("."));
+        spec.model = dir.join(&spec.model);
+        spec.param = dir.join(&spec.param);
+        spec.dimacs = spec.dimacs.map(|d| dir.join(d));
+        Ok(spec)
+    }
+
+    pub fn from_yaml(text: &str) -> anyhow::Result<Self> {
+        Ok(serde_yaml::from_str(text)?)
+    }
+
+    pub fn from_json(text: &str) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(text)?)
+    }
+
+    /// Reads the puzzle the spec is about.
+    pub fn puzzle(&self) -> anyhow::Result<PuzzleParse> {
+        match &self.dimacs {
+            Some(dimacs) => parse_eprime_with_dimacs(&self.model, &self.param, dimacs),
+            None => parse_essence(&self.model, &self.param),
+        }
+    }
+
+    /// Runs every check on the puzzle, returning an error only if a check
+    /// could not be run at all, such as when it names a literal which is
+    /// not in the puzzle.
+    pub fn run(&self, puzzle: Arc<PuzzleParse>) -> anyhow::Result<Vec<CheckOutcome>> {
+        let solver = PuzzleSolver::new(puzzle)?;
+        self.checks
+            .iter()
+            .enumerate()
+            .map(|(i, check)| {
+                let name = check
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| format!("check {}", i + 1));
+                let failures = check
+                    .failures(&solver)
+                    .with_context(|| format!("Cannot run {name}"))?;
+                Ok(CheckOutcome { name, failures })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assert_spec() {
+        let dir = tempfile::tempdir().unwrap();
+        let tst = std::fs::canonicalize("./tst").unwrap();
+        let spec_path = dir.path().join("asserts.yaml");
+        std::fs::write(
+            &spec_path,
+            format!(
+                r#"
+model: {tst}/sudoku-4x4.eprime
+param: {tst}/sudoku-4x4-open.json
+dimacs: {tst}/sudoku-4x4-open.dimacs
+checks:
+  - name: the first row is not given away
+    not_provable: ["grid[1, 2]=2", "grid[1, 2]=3"]
+  - known: ["grid[1, 2]=2"]
+    provable: ["grid[1, 3]=3"]
+    not_provable: ["grid[1, 3]!=2"]
+  - known: ["grid[1, 2]=1"]
+"#,
+                tst = tst.display()
+            ),
+        )
+        .unwrap();
+
+        let spec = AssertSpec::from_file(&spec_path).unwrap();
+        let outcomes = spec.run(Arc::new(spec.puzzle().unwrap())).unwrap();
+        assert_eq!(
+            outcomes.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec![
+                "ok: the first row is not given away",
+                "FAILED: check 2\n  grid[1, 3]!=2 can be deduced, but should not be",
+                "FAILED: check 3\n  The puzzle has no solution with the known literals",
+            ]
+        );
+
+        // The same spec as JSON, with relative paths
+        let json = AssertSpec {
+            model: "sudoku-4x4.eprime".into(),
+            param: "sudoku-4x4-open.json".into(),
+            dimacs: Some("sudoku-4x4-open.dimacs".into()),
+            checks: vec![AssertCheck {
+                provable: vec!["grid[9, 9]=1".to_owned()],
+                ..AssertCheck::default()
+            }],
+        };
+        let text = serde_json::to_string(&json).unwrap();
+        assert_eq!(AssertSpec::from_json(&text).unwrap(), json);
+        std::fs::write(dir.path().join("asserts.json"), text).unwrap();
+        let spec = AssertSpec::from_file(&dir.path().join("asserts.json")).unwrap();
+        assert_eq!(spec.model, dir.path().join("sudoku-4x4.eprime"));
+
+        let err = AssertSpec {
+            model: tst.join("sudoku-4x4.eprime"),
+            param: tst.join("sudoku-4x4-open.json"),
+            dimacs: Some(tst.join("sudoku-4x4-open.dimacs")),
+            ..json
+        };
+        let err = err.run(Arc::new(err.puzzle().unwrap())).unwrap_err();
+        assert!(format!("{err:#}").contains("grid[9, 9]=1 is not a literal"));
+
+        assert!(AssertSpec::from_yaml("model: a\nparam: b\nchecks: []\nextra: 1").is_err());
+    }
+}
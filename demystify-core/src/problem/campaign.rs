@@ -0,0 +1,352 @@
+//! Campaigns, which chain puzzles into a sequence of levels, such as the
+//! tutorial levels teaching a kind of puzzle one technique at a time.
+//!
+//! A manifest lists the levels in order, each with its model and parameters
+//! (relative to the manifest) and when it is unlocked:
+//!
+//! ```yaml
+//! name: Learning sudoku
+//! levels:
+//!   - name: Singles
+//!     model: sudoku.eprime
+//!     param: singles.param
+//!   - name: Pairs
+//!     model: sudoku.eprime
+//!     param: pairs.param
+//!   - name: Triples
+//!     model: sudoku.eprime
+//!     param: triples.param
+//!     unlock: { difficulty: 3 }
+//! ```
+//!
+//! A level is unlocked once the level before it is finished (`previous`, the
+//! default), from the start (`always`), or once a finished level needed a
+//! step of at least that many constraints (`difficulty`). The first level is
+//! always unlocked. As with [`AssertSpec`](super::assertions::AssertSpec), a
+//! level can give `dimacs` to avoid running conjure and savilerow, and
+//! manifests ending `.yaml` or `.yml` are read as YAML, and any others as
+//! JSON.
+//!
+//! `demystify campaign --manifest campaign.yaml` solves every level, checking
+//! each can be solved without guessing and can be unlocked.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    assertions::CheckOutcome,
+    parse::{PuzzleParse, parse_eprime_with_dimacs, parse_essence},
+    planner::PuzzlePlanner,
+    solver::PuzzleSolver,
+    trace::SolveSummary,
+};
+
+/// When a level of a [`Campaign`] can be played.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Unlock {
+    /// From the start
+    Always,
+    /// Once the level before it is finished
+    #[default]
+    Previous,
+    /// Once a finished level needed a step using at least this many
+    /// constraints, as in [`SolveSummary::difficulty`]
+    Difficulty(usize),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Level {
+    pub name: String,
+    pub model: PathBuf,
+    pub param: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dimacs: Option<PathBuf>,
+    #[serde(default)]
+    pub unlock: Unlock,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Campaign {
+    pub name: String,
+    pub levels: Vec<Level>,
+}
+
+/// The levels of a [`Campaign`] a player has finished.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CampaignProgress {
+    /// The difficulty reached in each finished level, by index
+    pub finished: BTreeMap<usize, usize>,
+}
+
+impl CampaignProgress {
+    /// Records that level `index` was finished, reaching `difficulty`.
+    /// Finishing a level again keeps the highest difficulty reached.
+    pub fn finish(&mut self, index: usize, difficulty: usize) {
+        let reached = self.finished.entry(index).or_default();
+        *reached = (*reached).max(difficulty);
+    }
+
+    #[must_use]
+    pub fn is_finished(&self, index: usize) -> bool {
+        self.finished.contains_key(&index)
+    }
+
+    /// The highest difficulty reached in any finished level.
+    #[must_use]
+    pub fn difficulty(&self) -> usize {
+        self.finished.values().copied().max().unwrap_or(0)
+    }
+}
+
+impl Level {
+    /// Reads the level's puzzle.
+    pub fn puzzle(&self) -> anyhow::Result<PuzzleParse> {
+        match &self.dimacs {
+            Some(dimacs) => parse_eprime_with_dimacs(&self.model, &self.param, dimacs),
+            None => parse_essence(&self.model, &self.param),
+        }
+    }
+
+    /// Solves the level, taking the easiest step each time, as a player
+    /// pressing "Best Next Step" would.
+    fn solve(&self) -> anyhow::Result<SolveSummary> {
+        let puzzle = Arc::new(self.puzzle()?);
+        let mut planner = PuzzlePlanner::new(PuzzleSolver::new(puzzle)?);
+        while !planner.solver().get_provable_varlits().is_empty() {
+            let step = planner.next_step();
+            planner.apply_step(&step);
+        }
+        Ok(planner.solve_summary())
+    }
+}
+
+/// The result of [`Campaign::validate`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct CampaignReport {
+    /// The difficulty of each level, or `None` if it could not be solved
+    pub difficulties: Vec<Option<usize>>,
+    pub outcomes: Vec<CheckOutcome>,
+}
+
+impl Campaign {
+    /// Reads a manifest, making the paths in it relative to the manifest's
+    /// directory.
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read campaign {}", path.display()))?;
+        let yaml = path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("yaml") || e.eq_ignore_ascii_case("yml"));
+        let mut campaign = if yaml {
+            Self::from_yaml(&text)
+        } else {
+            Self::from_json(&text)
+        }
+        .with_context(|| format!("Failed to parse campaign {}", path.display()))?;
+
+        let dir = path.parent().unwrap_or(Path::new("."));
+        for level in &mut campaign.levels {
+            level.model = dir.join(&level.model);
+            level.param = dir.join(&level.param);
+            level.dimacs = level.dimacs.as_ref().map(|d| dir.join(d));
+        }
+        Ok(campaign)
+    }
+
+    pub fn from_yaml(text: &str) -> anyhow::Result<Self> {
+        Ok(serde_yaml::from_str(text)?)
+    }
+
+    pub fn from_json(text: &str) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(text)?)
+    }
+
+    /// Whether level `index` can be played, given `progress`.
+    #[must_use]
+    pub fn is_unlocked(&self, index: usize, progress: &CampaignProgress) -> bool {
+        if index == 0 {
+            return true;
+        }
+        match self.levels[index].unlock {
+            Unlock::Always => true,
+            Unlock::Previous => progress.is_finished(index - 1),
+            Unlock::Difficulty(difficulty) => progress.difficulty() >= difficulty,
+        }
+    }
+
+    /// Whether each level can be played, given `progress`.
+    #[must_use]
+    pub fn unlocked(&self, progress: &CampaignProgress) -> Vec<bool> {
+        (0..self.levels.len())
+            .map(|i| self.is_unlocked(i, progress))
+            .collect()
+    }
+
+    /// Solves every level in order, checking it is solved without guessing,
+    /// and that it is unlocked by finishing the levels before it which can
+    /// be played. Levels which cannot be read or solved fail their checks,
+    /// rather than stopping the rest being checked.
+    #[must_use]
+    pub fn validate(&self) -> CampaignReport {
+        let mut progress = CampaignProgress::default();
+        let mut difficulties = vec![];
+        let mut outcomes = vec![];
+        for (i, level) in self.levels.iter().enumerate() {
+            let unlocked = self.is_unlocked(i, &progress);
+            let failures = if unlocked {
+                vec![]
+            } else {
+                vec![match level.unlock {
+                    Unlock::Difficulty(difficulty) => format!(
+                        "Needs difficulty {difficulty}, but the levels before it reach at most {}",
+                        progress.difficulty()
+                    ),
+                    _ => "The level before it cannot be finished".to_owned(),
+                }]
+            };
+            outcomes.push(CheckOutcome {
+                name: format!("{}: can be unlocked", level.name),
+                failures,
+            });
+
+            let solved = level
+                .solve()
+                .with_context(|| format!("Cannot solve {}", level.name))
+                .and_then(|summary| {
+                    if summary.solved {
+                        Ok(summary.difficulty)
+                    } else {
+                        anyhow::bail!(
+                            "Cannot be solved without guessing, with {} cells still open",
+                            summary.open.len()
+                        )
+                    }
+                });
+            outcomes.push(CheckOutcome {
+                name: format!("{}: solvable", level.name),
+                failures: solved
+                    .as_ref()
+                    .err()
+                    .map(|e| format!("{e:#}"))
+                    .into_iter()
+                    .collect(),
+            });
+
+            let difficulty = solved.ok();
+            if let Some(difficulty) = difficulty
+                && unlocked
+            {
+                progress.finish(i, difficulty);
+            }
+            difficulties.push(difficulty);
+        }
+        CampaignReport {
+            difficulties,
+            outcomes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(name: &str, param: &str, unlock: Unlock) -> Level {
+        Level {
+            name: name.to_owned(),
+            model: "sudoku-4x4.eprime".into(),
+            param: format!("{param}.json").into(),
+            dimacs: Some(format!("{param}.dimacs").into()),
+            unlock,
+        }
+    }
+
+    #[test]
+    fn test_campaign() {
+        let campaign = Campaign {
+            name: "Tutorial".to_owned(),
+            levels: vec![
+                level("First", "sudoku-4x4", Unlock::Previous),
+                level("Open", "sudoku-4x4-open", Unlock::Previous),
+                level("Hard", "sudoku-4x4", Unlock::Difficulty(100)),
+                level("Bonus", "sudoku-4x4", Unlock::Always),
+            ],
+        };
+
+        let mut progress = CampaignProgress::default();
+        assert_eq!(campaign.unlocked(&progress), [true, false, false, true]);
+        progress.finish(0, 2);
+        progress.finish(0, 1);
+        assert_eq!(progress.difficulty(), 2);
+        assert_eq!(campaign.unlocked(&progress), [true, true, false, true]);
+        progress.finish(3, 100);
+        assert_eq!(campaign.unlocked(&progress), [true, true, true, true]);
+
+        // Written relative to the manifest
+        let dir = tempfile::tempdir().unwrap();
+        let tst = std::fs::canonicalize("./tst").unwrap();
+        for file in [
+            "sudoku-4x4.eprime",
+            "sudoku-4x4.json",
+            "sudoku-4x4.dimacs",
+            "sudoku-4x4-open.json",
+            "sudoku-4x4-open.dimacs",
+        ] {
+            std::fs::copy(tst.join(file), dir.path().join(file)).unwrap();
+        }
+        let manifest = dir.path().join("campaign.yaml");
+        std::fs::write(&manifest, serde_yaml::to_string(&campaign).unwrap()).unwrap();
+        let read = Campaign::from_file(&manifest).unwrap();
+        assert_eq!(read.levels[2].unlock, Unlock::Difficulty(100));
+        assert_eq!(
+            read.levels[1].param,
+            dir.path().join("sudoku-4x4-open.json")
+        );
+
+        let report = read.validate();
+        let failed: Vec<_> = report
+            .outcomes
+            .iter()
+            .filter(|o| !o.passed())
+            .map(ToString::to_string)
+            .collect();
+        let first = report.difficulties[0].unwrap();
+        assert!(first > 0);
+        assert_eq!(
+            report.difficulties,
+            [Some(first), None, Some(first), Some(first)]
+        );
+        assert_eq!(report.outcomes.len(), 8);
+        assert_eq!(
+            failed,
+            [
+                "FAILED: Open: solvable\n  Cannot be solved without guessing, with 12 cells still open"
+                    .to_owned(),
+                format!(
+                    "FAILED: Hard: can be unlocked\n  Needs difficulty 100, but the levels before it reach at most {first}"
+                ),
+            ]
+        );
+
+        assert!(
+            Campaign::from_yaml(
+                "name: a\nlevels:\n  - name: b\n    model: c\n    param: d\n    unlock: sometimes\n"
+            )
+            .is_err()
+        );
+        let campaign = Campaign::from_yaml(
+            "name: a\nlevels:\n  - name: b\n    model: c\n    param: d\n    unlock: always\n",
+        )
+        .unwrap();
+        assert_eq!(campaign.levels[0].unlock, Unlock::Always);
+    }
+}
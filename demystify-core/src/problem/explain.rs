@@ -0,0 +1,367 @@
+//! What a step of a solve explains, independent of how it is shown.
+//!
+//! The planner builds one [`Explanation`] for each step, and the plain text,
+//! HTML and JSON outputs are all made from it, so they always agree on what
+//! was deduced and why.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write as _,
+};
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::json::DescriptionStatement;
+
+use super::{PuzLit, PuzVar, VarValPair, parse::EPrimeAnnotations, planner::StepKind};
+
+/// What was deduced about one variable. Assignments are one fact each, while
+/// values ruled out for the same variable are grouped together.
+#[derive(Clone, PartialOrd, Ord, Hash, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Fact {
+    pub var: PuzVar,
+    /// `true` if `var` takes the (single) value in `values`, `false` if it
+    /// takes none of them
+    pub equal: bool,
+    pub values: Vec<i64>,
+    /// The fact in the model's own words, from
+    /// [`EPrimeAnnotations::describe_fact`], shown in place of `var` and
+    /// `values` when set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+impl Fact {
+    /// Groups `lits` into facts, ordered by variable. Values ruled out for a
+    /// variable which is also assigned add nothing, so are left out.
+    pub fn from_lits<'a, I>(lits: I) -> Vec<Fact>
+    where
+        I: IntoIterator<Item = &'a PuzLit>,
+    {
+        let mut var_literals: BTreeMap<PuzVar, BTreeMap<i64, bool>> = BTreeMap::new();
+        for lit in lits {
+            var_literals
+                .entry(lit.var())
+                .or_default()
+                .insert(lit.val(), lit.sign());
+        }
+
+        let mut facts = vec![];
+        for (var, val_map) in var_literals {
+            let positives: Vec<i64> = val_map
+                .iter()
+                .filter_map(|(&val, &equal)| equal.then_some(val))
+                .collect();
+            if positives.is_empty() {
+                facts.push(Fact {
+                    var,
+                    equal: false,
+                    values: val_map.into_keys().collect(),
+                    text: None,
+                });
+            } else {
+                facts.extend(positives.into_iter().map(|val| Fact {
+                    var: var.clone(),
+                    equal: true,
+                    values: vec![val],
+                    text: None,
+                }));
+            }
+        }
+        facts
+    }
+
+    /// This fact, worded with the `$#NAME` and `$#VALUE` annotations of
+    /// `eprime`.
+    #[must_use]
+    pub fn worded(mut self, eprime: &EPrimeAnnotations) -> Self {
+        self.text = eprime.describe_fact(&self);
+        self
+    }
+
+    /// The values of the grid to highlight along with this fact.
+    #[must_use]
+    pub fn highlights(&self) -> Vec<VarValPair> {
+        self.values
+            .iter()
+            .map(|&val| VarValPair::new(&self.var, val))
+            .collect()
+    }
+
+    #[must_use]
+    pub fn html(&self) -> String {
+        let classes = self
+            .highlights()
+            .iter()
+            .map(|vvp| format!("highlight_{}", vvp.to_css_string()))
+            .join(" ");
+        format!(r#"<div style="display:inline" class="{classes} js_highlighter">{self}</div>"#)
+    }
+}
+
+impl std::fmt::Display for Fact {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(text) = &self.text {
+            return write!(f, "{text}");
+        }
+        let op = if self.equal { "=" } else { "!=" };
+        write!(f, "{} {op} {}", self.var, self.values.iter().join(" or "))
+    }
+}
+
+/// One deduction of a step, and the constraints which justify it.
+#[derive(Clone, PartialOrd, Ord, Hash, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Deduction {
+    pub facts: Vec<Fact>,
+    /// The descriptions of the constraints used
+    pub constraints: Vec<String>,
+    /// The literals deduced, from which `facts` is made
+    pub deduced: BTreeSet<PuzLit>,
+}
+
+impl Deduction {
+    #[must_use]
+    pub fn new(deduced: BTreeSet<PuzLit>, constraints: Vec<String>) -> Self {
+        Self {
+            facts: Fact::from_lits(&deduced),
+            constraints,
+            deduced,
+        }
+    }
+
+    /// Words every fact with the annotations of `eprime`, see
+    /// [`Fact::worded`].
+    #[must_use]
+    pub fn worded(mut self, eprime: &EPrimeAnnotations) -> Self {
+        self.facts = self.facts.into_iter().map(|f| f.worded(eprime)).collect();
+        self
+    }
+
+    /// The facts, separated by commas.
+    #[must_use]
+    pub fn facts_text(&self) -> String {
+        self.facts.iter().join(", ")
+    }
+
+    #[must_use]
+    pub fn facts_html(&self) -> String {
+        self.facts.iter().map(Fact::html).join(", ")
+    }
+}
+
+/// Two facts of which at least one must hold, when neither can be deduced on
+/// its own, such as a value which must go in one of two cells.
+#[derive(Clone, PartialOrd, Ord, Hash, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct EitherOr {
+    pub either: Fact,
+    pub or: Fact,
+    /// The descriptions of the constraints used
+    pub constraints: Vec<String>,
+}
+
+impl EitherOr {
+    /// One line for the two facts, then one for each constraint used.
+    #[must_use]
+    pub fn text(&self) -> String {
+        let mut text = format!("Either {} or {}\n", self.either, self.or);
+        for constraint in &self.constraints {
+            let _ = writeln!(text, "  because {constraint}");
+        }
+        text
+    }
+
+    #[must_use]
+    pub fn html(&self) -> String {
+        format!(
+            "Either {} or {}, because:<br/>{}<br/>",
+            self.either.html(),
+            self.or.html(),
+            self.constraints
+                .iter()
+                .map(|s| tera::escape_html(s))
+                .join("<br/>")
+        )
+    }
+}
+
+/// Everything shown for one step of a solve.
+#[derive(Clone, Default, PartialOrd, Ord, Hash, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Explanation {
+    pub deductions: Vec<Deduction>,
+    /// How many equally simple deductions this step was chosen from, as in
+    /// [`Step::alternatives`](super::planner::Step::alternatives)
+    pub alternatives: usize,
+    /// How many of the alternatives are the same reasoning in different
+    /// places, as in [`Step::symmetric`](super::planner::Step::symmetric)
+    #[serde(default)]
+    pub symmetric: usize,
+    #[serde(default)]
+    pub kind: StepKind,
+}
+
+impl Explanation {
+    /// The literals deduced by every deduction.
+    #[must_use]
+    pub fn deduced(&self) -> BTreeSet<PuzLit> {
+        self.deductions
+            .iter()
+            .flat_map(|d| d.deduced.iter().cloned())
+            .collect()
+    }
+
+    /// Whether the step shows only one of several equally simple deductions.
+    #[must_use]
+    pub fn is_choice(&self) -> bool {
+        self.alternatives > self.deductions.len()
+    }
+
+    /// Such as "This reasoning applies in 4 symmetric places", if some of
+    /// the alternatives are the same reasoning in other places.
+    #[must_use]
+    pub fn symmetric_text(&self) -> Option<String> {
+        (self.symmetric > 1).then(|| {
+            format!(
+                "This reasoning applies in {} symmetric places",
+                self.symmetric
+            )
+        })
+    }
+
+    /// The sentences shown before the deductions, as HTML.
+    #[must_use]
+    pub fn intro_html(&self) -> String {
+        if self.kind == StepKind::Setup {
+            return "These follow from how the puzzle is set up, before using any constraint:<br/>"
+                .to_owned();
+        }
+        let choice = if self.is_choice() {
+            let symmetric = self
+                .symmetric_text()
+                .map(|text| format!("{text}. "))
+                .unwrap_or_default();
+            format!(
+                "This is one of {} equally simple deductions which could have been made here. {symmetric}<br/>",
+                self.alternatives
+            )
+        } else {
+            String::new()
+        };
+        if self.deductions.len() > 1 {
+            format!(
+                "{} simple deductions are being shown here in a single step. <br/>{choice}",
+                self.deductions.len()
+            )
+        } else {
+            format!("{choice}Made the following deductions:<br/>")
+        }
+    }
+
+    /// One statement for each deduction, with its facts as HTML and its
+    /// constraints escaped.
+    #[must_use]
+    pub fn description_statements(&self) -> Vec<DescriptionStatement> {
+        self.deductions
+            .iter()
+            .map(|d| DescriptionStatement {
+                result: d.facts_html(),
+                constraints: d.constraints.iter().map(|s| tera::escape_html(s)).collect(),
+                deduced: d.deduced.clone(),
+            })
+            .collect()
+    }
+
+    /// The step as plain text, with one line for each fact and each
+    /// constraint used.
+    #[must_use]
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        if self.is_choice() {
+            let _ = writeln!(
+                text,
+                "One of {} equally simple deductions:",
+                self.alternatives
+            );
+            if let Some(symmetric) = self.symmetric_text() {
+                let _ = writeln!(text, "{symmetric}:");
+            }
+        }
+        for deduction in &self.deductions {
+            let _ = writeln!(text, "{}", deduction.facts_text());
+            for constraint in &deduction.constraints {
+                let _ = writeln!(text, "  because {constraint}");
+            }
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_explanation_text_and_html() {
+        let var = PuzVar::new("x", vec![1, 2]);
+        let lit = |val, equal| {
+            let vvp = VarValPair::new(&var, val);
+            if equal {
+                PuzLit::new_eq(vvp)
+            } else {
+                PuzLit::new_neq(vvp)
+            }
+        };
+
+        let explanation = Explanation {
+            deductions: vec![
+                Deduction::new(
+                    [lit(2, false), lit(3, false)].into(),
+                    vec!["a < b".to_owned()],
+                ),
+                Deduction::new([lit(5, true), lit(4, false)].into(), vec![]),
+            ],
+            alternatives: 3,
+            symmetric: 1,
+            kind: StepKind::Deduction,
+        };
+
+        assert_eq!(
+            explanation.deductions[0].facts,
+            vec![Fact {
+                var: var.clone(),
+                equal: false,
+                values: vec![2, 3],
+                text: None,
+            }]
+        );
+        assert_eq!(explanation.deduced().len(), 4);
+        assert_eq!(
+            explanation.text(),
+            "One of 3 equally simple deductions:\nx[1, 2] != 2 or 3\n  because a < b\nx[1, 2] = 5\n"
+        );
+        assert!(explanation.intro_html().contains("2 simple deductions"));
+        assert_eq!(explanation.symmetric_text(), None);
+        assert!(!explanation.intro_html().contains("symmetric"));
+
+        let symmetric = Explanation {
+            deductions: vec![explanation.deductions[0].clone()],
+            symmetric: 2,
+            ..explanation.clone()
+        };
+        assert_eq!(
+            symmetric.text(),
+            "One of 3 equally simple deductions:\nThis reasoning applies in 2 symmetric places:\nx[1, 2] != 2 or 3\n  because a < b\n"
+        );
+        assert!(
+            symmetric
+                .intro_html()
+                .contains("This reasoning applies in 2 symmetric places. <br/>")
+        );
+
+        let statements = explanation.description_statements();
+        assert_eq!(statements[0].constraints, vec!["a &lt; b"]);
+        assert!(statements[1].result.contains("x[1, 2] = 5"));
+        assert!(statements[1].result.contains("highlight_lit_x_i1_i2_v5"));
+        assert!(!statements[1].result.contains("highlight_lit_x_i1_i2_v4"));
+    }
+}
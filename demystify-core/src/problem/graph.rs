@@ -0,0 +1,175 @@
+//! The bipartite graph between the constraints of a puzzle and the variables
+//! they mention, for studying how the structure of a puzzle relates to how
+//! hard it is to explain.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    parse::PuzzleParse,
+    util::format::{Format, unchanged},
+};
+
+/// How [`ConstraintGraph`]s written with [`ConstraintGraph::to_json`] are
+/// read back. Graphs written before the format had a version are the same as
+/// version 1.
+pub const GRAPH_FORMAT: Format = Format {
+    name: "Constraint graph",
+    version: 1,
+    oldest: 0,
+    upgrades: &[unchanged],
+};
+
+/// Whether the variable side of a [`ConstraintGraph`] has a node for each
+/// `VarValPair`, or for each variable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GraphNodes {
+    VarVals,
+    Vars,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConstraintNode {
+    /// The English description of the constraint
+    pub description: String,
+    /// The `$#CON` the constraint came from
+    pub group: Option<String>,
+    /// The number of variable nodes the constraint is connected to
+    pub scope_size: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VariableNode {
+    pub name: String,
+    /// The number of constraints the variable is connected to
+    pub degree: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConstraintGraph {
+    pub format_version: u32,
+    pub nodes: GraphNodes,
+    pub constraints: Vec<ConstraintNode>,
+    pub variables: Vec<VariableNode>,
+    /// Pairs of (index into `constraints`, index into `variables`)
+    pub edges: Vec<(usize, usize)>,
+}
+
+impl ConstraintGraph {
+    #[must_use]
+    pub fn new(puzzle: &PuzzleParse, nodes: GraphNodes) -> Self {
+        let mut constraints = vec![];
+        let mut variable_ids: BTreeMap<String, usize> = BTreeMap::new();
+        let mut variables: Vec<VariableNode> = vec![];
+        let mut edges = vec![];
+
+        for con in puzzle.constraints() {
+            let mut scope: Vec<String> = puzzle
+                .constraint_scope_uncached(&con)
+                .iter()
+                .map(|vv| match nodes {
+                    GraphNodes::VarVals => vv.to_string(),
+                    GraphNodes::Vars => vv.var().to_string(),
+                })
+                .collect();
+            scope.dedup();
+
+            let con_id = constraints.len();
+            for name in &scope {
+                let var_id = *variable_ids.entry(name.clone()).or_insert_with(|| {
+                    variables.push(VariableNode {
+                        name: name.clone(),
+                        degree: 0,
+                    });
+                    variables.len() - 1
+                });
+                variables[var_id].degree += 1;
+                edges.push((con_id, var_id));
+            }
+
+            constraints.push(ConstraintNode {
+                group: puzzle.constraint_name(&con),
+                description: con,
+                scope_size: scope.len(),
+            });
+        }
+
+        Self {
+            format_version: GRAPH_FORMAT.version,
+            nodes,
+            constraints,
+            variables,
+            edges,
+        }
+    }
+
+    /// The graph in Graphviz DOT format. Constraints are boxes, variables are ellipses.
+    #[must_use]
+    pub fn to_dot(&self) -> String {
+        let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+
+        let mut out = String::from("graph constraints {\n");
+        for (i, c) in self.constraints.iter().enumerate() {
+            out += &format!(
+                "  c{i} [shape=box, label={}, group={}, scope_size={}];\n",
+                quote(&c.description),
+                quote(c.group.as_deref().unwrap_or("")),
+                c.scope_size
+            );
+        }
+        for (i, v) in self.variables.iter().enumerate() {
+            out += &format!("  v{i} [label={}, degree={}];\n", quote(&v.name), v.degree);
+        }
+        for (c, v) in &self.edges {
+            out += &format!("  c{c} -- v{v};\n");
+        }
+        out += "}\n";
+        out
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Reads the output of [`ConstraintGraph::to_json`], from this or an
+    /// older version of demystify.
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        GRAPH_FORMAT.from_str(json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::problem::util::test_utils::sudoku_4x4;
+
+    #[test]
+    fn test_constraint_graph_sudoku() {
+        let puz = sudoku_4x4();
+
+        let graph = puz.constraint_graph(GraphNodes::Vars);
+
+        assert_eq!(graph.constraints.len(), 12 * 6 * 4);
+        assert_eq!(graph.variables.len(), 16);
+        // Each pair constraint mentions two cells
+        assert!(graph.constraints.iter().all(|c| c.scope_size == 2));
+        // Each cell is paired with 3 others in its row, column and box, for each of 4 values
+        assert!(graph.variables.iter().all(|v| v.degree == 3 * 3 * 4));
+        assert_eq!(graph.edges.len(), 12 * 6 * 4 * 2);
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("graph constraints {"));
+        assert!(dot.contains("group=\"row_alldiff\""));
+
+        let json = graph.to_json().unwrap();
+        assert_eq!(ConstraintGraph::from_json(&json).unwrap(), graph);
+        let unversioned = json.replace("\"format_version\": 1,", "");
+        assert_ne!(unversioned, json);
+        assert_eq!(ConstraintGraph::from_json(&unversioned).unwrap(), graph);
+
+        let varvals = puz.constraint_graph(GraphNodes::VarVals);
+        assert_eq!(varvals.constraints.len(), graph.constraints.len());
+        assert!(varvals.variables.len() > graph.variables.len());
+    }
+}
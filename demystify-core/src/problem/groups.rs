@@ -0,0 +1,212 @@
+//! How far each row, column and region of the grid has been solved, for
+//! the margins of the puzzle and the `/gridStats` route of demystify-web.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use super::{PuzVar, parse::PuzzleParse};
+use crate::json::Puzzle;
+
+/// What a [`GroupStats`] is about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupKind {
+    Row,
+    Column,
+    /// A region of [`Puzzle::regions`]
+    Region,
+}
+
+/// How far one row, column or region has been solved.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct GroupStats {
+    pub kind: GroupKind,
+    /// The (1-indexed) row or column, or the region id
+    pub index: i64,
+    pub cells: usize,
+    /// The cells whose value is known
+    pub solved: usize,
+    /// The values the other cells can still take, added up
+    pub candidates: usize,
+    /// The constraints with every cell of their scope in the group
+    pub constraints: usize,
+    /// Those of `constraints` whose cells are all solved, so they hold
+    pub satisfied: usize,
+}
+
+impl GroupStats {
+    fn new(kind: GroupKind, index: i64) -> Self {
+        Self {
+            kind,
+            index,
+            cells: 0,
+            solved: 0,
+            candidates: 0,
+            constraints: 0,
+            satisfied: 0,
+        }
+    }
+
+    /// A short label for the margin, such as `3/9`.
+    #[must_use]
+    pub fn label(&self) -> String {
+        format!("{}/{}", self.solved, self.cells)
+    }
+
+    /// The whole of the stats, for a tooltip.
+    #[must_use]
+    pub fn describe(&self) -> String {
+        let kind = match self.kind {
+            GroupKind::Row => "Row",
+            GroupKind::Column => "Column",
+            GroupKind::Region => "Region",
+        };
+        format!(
+            "{kind} {}: {} of {} cells solved, {} candidates left, {} of {} constraints satisfied",
+            self.index, self.solved, self.cells, self.candidates, self.satisfied, self.constraints
+        )
+    }
+}
+
+/// The [`GroupStats`] of every row, column and region of a puzzle.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct GridStats {
+    pub rows: Vec<GroupStats>,
+    pub columns: Vec<GroupStats>,
+    /// Empty unless the puzzle has [`Puzzle::regions`]
+    pub regions: Vec<GroupStats>,
+}
+
+impl GridStats {
+    /// The stats of the cells of the grid of `puzzle`, which can take
+    /// `candidates` as in
+    /// [`PuzzlePlanner::candidates`](super::planner::PuzzlePlanner::candidates).
+    /// The grid is the only `$#VAR`, or the one called `grid`, as when the
+    /// puzzle is drawn.
+    #[must_use]
+    pub fn new(
+        parse: &PuzzleParse,
+        puzzle: &Puzzle,
+        candidates: &BTreeMap<PuzVar, Vec<i64>>,
+    ) -> Self {
+        let grid = parse.eprime.grid_var();
+        let cells: BTreeMap<Vec<i64>, usize> = candidates
+            .iter()
+            .filter(|(var, _)| var.name() == grid)
+            .map(|(var, values)| (puzzle.cell_of(var), values.len()))
+            .filter(|(cell, _)| cell.len() == 2 && puzzle.is_cell(cell))
+            .collect();
+
+        let mut groups: BTreeMap<(GroupKind, i64), GroupStats> = BTreeMap::new();
+        let groups_of = |cell: &[i64]| {
+            let mut of = vec![(GroupKind::Row, cell[0]), (GroupKind::Column, cell[1])];
+            if let Some(region) = puzzle.region_of(cell) {
+                of.push((GroupKind::Region, region));
+            }
+            of
+        };
+
+        for (cell, &values) in &cells {
+            for (kind, index) in groups_of(cell) {
+                let stats = groups
+                    .entry((kind, index))
+                    .or_insert_with(|| GroupStats::new(kind, index));
+                stats.cells += 1;
+                if values == 1 {
+                    stats.solved += 1;
+                } else {
+                    stats.candidates += values;
+                }
+            }
+        }
+
+        for con in &parse.conset_lits {
+            let scope: BTreeSet<Vec<i64>> = parse
+                .constraint_scope(parse.lit_to_con(con))
+                .iter()
+                .filter(|vvp| vvp.var().name() == grid)
+                .map(|vvp| puzzle.cell_of(vvp.var()))
+                .collect();
+            let Some(first) = scope.first() else {
+                continue;
+            };
+            if !scope.iter().all(|cell| cells.contains_key(cell)) {
+                continue;
+            }
+            let satisfied = scope.iter().all(|cell| cells[cell] == 1);
+            for group in groups_of(first) {
+                if scope.iter().all(|cell| groups_of(cell).contains(&group))
+                    && let Some(stats) = groups.get_mut(&group)
+                {
+                    stats.constraints += 1;
+                    if satisfied {
+                        stats.satisfied += 1;
+                    }
+                }
+            }
+        }
+
+        let mut stats = GridStats::default();
+        for ((kind, _), group) in groups {
+            match kind {
+                GroupKind::Row => stats.rows.push(group),
+                GroupKind::Column => stats.columns.push(group),
+                GroupKind::Region => stats.regions.push(group),
+            }
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::problem::{
+        planner::PuzzlePlanner, solver::PuzzleSolver, util::test_utils::sudoku_4x4_with,
+    };
+
+    #[test]
+    fn test_grid_stats() {
+        let puzzle = sudoku_4x4_with("sudoku-4x4-regions.json", "sudoku-4x4.dimacs");
+        let mut plan = PuzzlePlanner::new(PuzzleSolver::new(Arc::new(puzzle)).unwrap());
+
+        let stats = plan.grid_stats().unwrap();
+        assert_eq!(stats.rows.len(), 4);
+        assert_eq!(stats.columns.len(), 4);
+        assert_eq!(stats.regions.len(), 4);
+        // The first row starts with 1 and 4
+        let first = &stats.rows[0];
+        assert_eq!((first.kind, first.index), (GroupKind::Row, 1));
+        assert_eq!((first.cells, first.solved), (4, 2));
+        // Nothing has been deduced, so the other two cells can be anything
+        assert_eq!(first.candidates, 8);
+        assert_eq!(first.label(), "2/4");
+        assert!(first.satisfied < first.constraints);
+        assert!(
+            first
+                .describe()
+                .starts_with("Row 1: 2 of 4 cells solved, 8 candidates left, ")
+        );
+
+        plan.config_mut().html_options.margin_stats = true;
+        let (problem, _) = plan.fork().quick_solve_step();
+        assert!(problem.state.unwrap().grid_stats.is_some());
+
+        plan.quick_solve();
+        let stats = plan.grid_stats().unwrap();
+        for group in stats
+            .rows
+            .iter()
+            .chain(&stats.columns)
+            .chain(&stats.regions)
+        {
+            assert_eq!(group.solved, group.cells);
+            assert_eq!(group.candidates, 0);
+            assert!(group.constraints > 0);
+            assert_eq!(group.satisfied, group.constraints);
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! The steps shown so far in a solve, as kept by a
+//! [`PuzzlePlanner`](super::planner::PuzzlePlanner) so they can be shown
+//! again.
+//!
+//! Every step draws the whole puzzle, so a long solve would keep many copies
+//! of it. Only the latest [`HistoryConfig::keep_full`] steps are kept as they
+//! were drawn. Older steps keep just their [`State`], which is all that
+//! changes from one step to the next, and are written to
+//! [`HistoryConfig::store`] if there is one, rather than kept in memory.
+//! [`StepHistory::get`] puts them back together, so callers cannot tell how
+//! a step was kept.
+
+use std::{io::Write, sync::Arc};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::{
+    trace::{SolveTrace, TraceStep},
+    util::{compress::decompress, kvstore::KvStore},
+};
+use crate::json::{Problem, Puzzle, State};
+
+/// The largest step read back from a [`KvStore`], once decompressed.
+const MAX_STORED_STEP: usize = 64 << 20;
+
+/// How much of a [`StepHistory`] is kept in memory.
+#[derive(Clone, Default)]
+pub struct HistoryConfig {
+    /// Keep this many of the latest steps as they were drawn. If `None`,
+    /// every step is.
+    pub keep_full: Option<usize>,
+    /// Where to write the steps older than `keep_full`. If `None`, they are
+    /// kept in memory.
+    pub store: Option<Arc<dyn KvStore>>,
+}
+
+/// What changes from one step to the next: a [`TraceStep`] without its
+/// [`Puzzle`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct StepDiff {
+    title: String,
+    state: Option<State>,
+    warnings: Vec<String>,
+}
+
+/// A step written to a [`KvStore`], which is removed from it once no
+/// history refers to it.
+struct Stored {
+    store: Arc<dyn KvStore>,
+    key: String,
+}
+
+impl Drop for Stored {
+    fn drop(&mut self) {
+        if let Err(e) = self.store.remove(&self.key) {
+            warn!("Failed to remove step {} of a history: {e:#}", self.key);
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Kept {
+    Full(Box<TraceStep>),
+    Diff(Box<StepDiff>),
+    Stored(Arc<Stored>),
+}
+
+/// The steps of a solve, see the [module docs](self).
+#[derive(Clone)]
+pub struct StepHistory {
+    /// Names this history's steps in a [`KvStore`]
+    id: u64,
+    /// The puzzle drawn in every step, which is left out of older steps
+    puzzle: Option<Puzzle>,
+    steps: Vec<Kept>,
+}
+
+impl Default for StepHistory {
+    fn default() -> Self {
+        Self {
+            id: rand::random(),
+            puzzle: None,
+            steps: vec![],
+        }
+    }
+}
+
+impl StepHistory {
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// A copy which keeps its own steps from now on, sharing those already
+    /// kept.
+    #[must_use]
+    pub fn fork(&self) -> Self {
+        Self {
+            id: rand::random(),
+            ..self.clone()
+        }
+    }
+
+    /// Adds `step`, then shrinks the step which has become too old to be
+    /// kept in full, as set by `config`.
+    pub fn push(&mut self, step: TraceStep, config: &HistoryConfig) {
+        if self.puzzle.is_none() {
+            self.puzzle = Some(step.problem.puzzle.clone());
+        }
+        self.steps.push(Kept::Full(Box::new(step)));
+
+        let Some(keep) = config.keep_full else {
+            return;
+        };
+        let Some(old) = self.steps.len().checked_sub(keep + 1) else {
+            return;
+        };
+        let Kept::Full(step) = &self.steps[old] else {
+            return;
+        };
+        // Steps drawing some other puzzle cannot be put back together
+        if Some(&step.problem.puzzle) != self.puzzle.as_ref() {
+            return;
+        }
+
+        let diff = StepDiff {
+            title: step.title.clone(),
+            state: step.problem.state.clone(),
+            warnings: step.problem.warnings.clone(),
+        };
+        self.steps[old] = match &config.store {
+            Some(store) => match self.write(store, old, &diff) {
+                Ok(stored) => Kept::Stored(Arc::new(stored)),
+                Err(e) => {
+                    warn!("Keeping {} in memory: {e:#}", diff.title);
+                    Kept::Diff(Box::new(diff))
+                }
+            },
+            None => Kept::Diff(Box::new(diff)),
+        };
+    }
+
+    fn write(
+        &self,
+        store: &Arc<dyn KvStore>,
+        index: usize,
+        diff: &StepDiff,
+    ) -> anyhow::Result<Stored> {
+        let key = format!("{:016x}-{index}", self.id);
+        let mut gz = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        serde_json::to_writer(&mut gz, diff)?;
+        gz.flush()?;
+        store.put(&key, &gz.finish()?)?;
+        Ok(Stored {
+            store: store.clone(),
+            key,
+        })
+    }
+
+    /// Step `index` (counting from 0), as it was drawn.
+    pub fn get(&self, index: usize) -> anyhow::Result<TraceStep> {
+        let kept = self.steps.get(index).with_context(|| {
+            format!(
+                "There is no step {}, only {} have been kept",
+                index + 1,
+                self.steps.len()
+            )
+        })?;
+        let diff = match kept {
+            Kept::Full(step) => return Ok((**step).clone()),
+            Kept::Diff(diff) => (**diff).clone(),
+            Kept::Stored(stored) => {
+                let bytes = stored
+                    .store
+                    .get(&stored.key)?
+                    .with_context(|| format!("Step {} is missing from its store", index + 1))?;
+                serde_json::from_slice(&decompress(&bytes, MAX_STORED_STEP)?)
+                    .with_context(|| format!("Step {} is damaged in its store", index + 1))?
+            }
+        };
+        Ok(TraceStep {
+            title: diff.title,
+            problem: Problem {
+                puzzle: self.puzzle.clone().context("A history has no puzzle")?,
+                state: diff.state,
+                warnings: diff.warnings,
+            },
+        })
+    }
+
+    /// Every step, as a trace without a summary.
+    pub fn trace(&self) -> anyhow::Result<SolveTrace> {
+        let mut trace = SolveTrace::new();
+        for i in 0..self.len() {
+            trace.steps.push(self.get(i)?);
+        }
+        Ok(trace)
+    }
+
+    /// How many steps are kept in full, which is what uses most memory.
+    #[must_use]
+    pub fn full_steps(&self) -> usize {
+        self.steps
+            .iter()
+            .filter(|kept| matches!(kept, Kept::Full(_)))
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::problem::{
+        planner::PlannerConfig,
+        util::{kvstore::MemoryStore, test_utils::sudoku_4x4_planner_with},
+    };
+
+    /// Solves the 4x4 sudoku a step at a time, keeping its history.
+    fn solve(history: HistoryConfig) -> crate::problem::planner::PuzzlePlanner {
+        let mut plan = sudoku_4x4_planner_with(PlannerConfig {
+            history,
+            ..PlannerConfig::default()
+        });
+        loop {
+            let step = plan.next_step();
+            if step.muses.is_empty() {
+                break;
+            }
+            plan.record_and_apply_step(&step);
+        }
+        plan
+    }
+
+    #[test]
+    fn test_history_retention() -> anyhow::Result<()> {
+        let full = solve(HistoryConfig::default());
+        let steps = full.history().len();
+        assert!(steps >= 2);
+        assert_eq!(full.history().full_steps(), steps);
+        assert_eq!(full.history().get(0)?.title, "Step 1");
+        assert!(full.history().get(steps).is_err());
+
+        let diffs = solve(HistoryConfig {
+            keep_full: Some(1),
+            store: None,
+        });
+        assert_eq!(diffs.history().full_steps(), 1);
+        assert_eq!(diffs.history().trace()?, full.history().trace()?);
+
+        let store = Arc::new(MemoryStore::default());
+        let stored = solve(HistoryConfig {
+            keep_full: Some(1),
+            store: Some(store.clone()),
+        });
+        assert_eq!(stored.history().full_steps(), 1);
+        assert_eq!(store.len(), steps - 1);
+        assert_eq!(stored.history().trace()?, full.history().trace()?);
+
+        // Forks share what was stored, which is removed with the last of them
+        let fork = stored.fork();
+        drop(stored);
+        assert_eq!(store.len(), steps - 1);
+        assert_eq!(fork.history().trace()?, full.history().trace()?);
+        drop(fork);
+        assert!(store.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,559 @@
+pub mod analysis;
+pub mod anonymize;
+pub mod assertions;
+pub mod campaign;
+pub mod explain;
+pub mod graph;
+pub mod groups;
+pub mod history;
+pub mod musdict;
+pub mod notes;
+/// Module containing problem-related functionality.
+pub mod parse;
+pub mod planner;
+#[doc(hidden)]
+pub mod propagate;
+pub mod query;
+pub mod repro;
+pub mod rules;
+#[doc(hidden)]
+pub mod selftest;
+pub mod solver;
+pub(crate) mod symmetry;
+#[doc(hidden)]
+pub mod telemetry;
+pub mod trace;
+#[doc(hidden)]
+pub mod util;
+
+use std::fmt;
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+/// Represents a puzzle variable.
+#[derive(Clone, PartialOrd, Ord, Hash, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PuzVar {
+    name: String,
+    indices: Vec<i64>,
+}
+
+impl PuzVar {
+    /// Creates a new `PuzVar` instance.
+    #[must_use]
+    pub fn new(name: &str, indices: Vec<i64>) -> PuzVar {
+        PuzVar {
+            name: name.to_string(),
+            indices,
+        }
+    }
+
+    /// Returns the name of the variable.
+    #[must_use]
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    /// Returns the indices of the variable.
+    #[must_use]
+    pub fn indices(&self) -> &Vec<i64> {
+        &self.indices
+    }
+
+    /// Converts the variable into a CSS-friendly string, used in class names
+    /// such as those which highlight a value.
+    ///
+    /// Different variables always give different strings. Letters and digits
+    /// of the name are kept, while `_` is the escape character: `_` itself
+    /// is written `_u`, `.` is `_d`, `-` is `_h` and any other character `c`
+    /// is `_x<hex>_`, where `<hex>` is its code point. Each index is then
+    /// written as `_i<index>`, with a `-` for negative indices. So `x_1[2]`
+    /// is `x_u1_i2`, while `x[1,2]` is `x_i1_i2`.
+    #[must_use]
+    pub fn to_css_string(&self) -> String {
+        let mut css = String::new();
+        for c in self.name.chars() {
+            match c {
+                c if c.is_ascii_alphanumeric() => css.push(c),
+                '_' => css.push_str("_u"),
+                '.' => css.push_str("_d"),
+                '-' => css.push_str("_h"),
+                c => css.push_str(&format!("_x{:x}_", u32::from(c))),
+            }
+        }
+        for index in &self.indices {
+            css.push_str(&format!("_i{index}"));
+        }
+        css
+    }
+}
+
+impl fmt::Display for PuzVar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{:?}", self.name, self.indices)
+    }
+}
+
+/// Represents a puzzle literal.
+#[derive(Clone, PartialOrd, Ord, Hash, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct VarValPair {
+    var: PuzVar,
+    val: i64,
+}
+
+impl fmt::Display for VarValPair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({},{})", self.var, self.val)
+    }
+}
+
+impl VarValPair {
+    /// Creates a new `VarValPair` instance.
+    #[must_use]
+    pub fn new(var: &PuzVar, val: i64) -> VarValPair {
+        VarValPair {
+            var: var.clone(),
+            val,
+        }
+    }
+
+    /// Returns the variable associated with the `VarValPair`.
+    #[must_use]
+    pub fn var(&self) -> &PuzVar {
+        &self.var
+    }
+
+    /// Returns the value associated with the `VarValPair`.
+    #[must_use]
+    pub fn val(&self) -> i64 {
+        self.val
+    }
+
+    /// Checks if the `VarValPair` is equal to a given `PuzLit`.
+    #[must_use]
+    pub fn is_lit(&self, puzlit: &PuzLit) -> bool {
+        *self == puzlit.varval()
+    }
+
+    /// Converts the `VarValPair` into a CSS-friendly string, `lit_` then the
+    /// variable as in [`PuzVar::to_css_string`] then `_v<value>`. Different
+    /// pairs always give different strings.
+    #[must_use]
+    pub fn to_css_string(&self) -> String {
+        format!("lit_{}_v{}", self.var.to_css_string(), self.val)
+    }
+}
+
+/// Represents a puzzle literal, which is the positive or negative form of a `VarValPair`.
+#[derive(Clone, PartialOrd, Ord, Hash, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PuzLit {
+    varval: VarValPair,
+    equal: bool,
+}
+
+impl fmt::Display for PuzLit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.equal {
+            write!(f, "{}={}", self.varval.var(), self.varval.val())
+        } else {
+            write!(f, "{}!={}", self.varval.var(), self.varval.val())
+        }
+    }
+}
+
+impl PuzLit {
+    /// Creates a new `PuzLit` instance representing an equality constraint.
+    #[must_use]
+    pub fn new_eq(varval: VarValPair) -> PuzLit {
+        PuzLit {
+            varval,
+            equal: true,
+        }
+    }
+
+    /// Creates a new `PuzLit` instance representing an inequality constraint.
+    #[must_use]
+    pub fn new_neq(varval: VarValPair) -> PuzLit {
+        PuzLit {
+            varval,
+            equal: false,
+        }
+    }
+
+    /// Returns the `VarValPair` associated with the `PuzLit`.
+    #[must_use]
+    pub fn varval(&self) -> VarValPair {
+        self.varval.clone()
+    }
+
+    /// Checks if the `PuzLit` is equal to a given `VarValPair`.
+    #[must_use]
+    pub fn is_varval(&self, varval: &VarValPair) -> bool {
+        self.varval == *varval
+    }
+
+    /// Returns the variable associated with the `PuzLit`.
+    #[must_use]
+    pub fn var(&self) -> PuzVar {
+        self.varval.var().clone()
+    }
+
+    /// Returns the value associated with the `PuzLit`.
+    #[must_use]
+    pub fn val(&self) -> i64 {
+        self.varval.val()
+    }
+
+    /// Returns the sign of the `PuzLit`.
+    #[must_use]
+    pub fn sign(&self) -> bool {
+        self.equal
+    }
+
+    /// Returns the negation of the `PuzLit`.
+    #[must_use]
+    pub fn neg(&self) -> PuzLit {
+        PuzLit {
+            varval: self.varval.clone(),
+            equal: !self.equal,
+        }
+    }
+
+    /// The literals as HTML, grouped by variable as in [`explain::Fact`].
+    pub fn nice_puzlit_list_html<'a, I>(puz_container: I) -> String
+    where
+        I: IntoIterator<Item = &'a PuzLit>,
+    {
+        explain::Fact::from_lits(puz_container)
+            .iter()
+            .map(explain::Fact::html)
+            .join(", ")
+    }
+}
+
+/// Reads a literal as it is printed, such as `grid[1, 2]=3` or
+/// `grid[1, 2]!=3`. Spaces are ignored, and a variable without indices
+/// can be written `x=3` as well as `x[]=3`.
+impl std::str::FromStr for PuzLit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (var, val, equal) = if let Some((var, val)) = s.split_once("!=") {
+            (var, val, false)
+        } else if let Some((var, val)) = s.split_once('=') {
+            (var, val, true)
+        } else {
+            anyhow::bail!(
+                "Expected a literal such as 'grid[1, 2]=3' or 'grid[1, 2]!=3', got '{s}'"
+            );
+        };
+
+        let var = var.trim();
+        let (name, indices) = match var.split_once('[') {
+            Some((name, rest)) => {
+                let Some(indices) = rest.trim_end().strip_suffix(']') else {
+                    anyhow::bail!("Missing ']' after the indices in '{s}'");
+                };
+                let indices = indices
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|i| !i.is_empty())
+                    .map(|i| {
+                        i.parse::<i64>()
+                            .map_err(|_| anyhow::anyhow!("Index '{i}' in '{s}' is not a number"))
+                    })
+                    .collect::<anyhow::Result<Vec<i64>>>()?;
+                (name.trim(), indices)
+            }
+            None => (var, vec![]),
+        };
+        if name.is_empty() {
+            anyhow::bail!("Missing the variable name in '{s}'");
+        }
+        let val: i64 = val
+            .trim()
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Value '{}' in '{s}' is not a number", val.trim()))?;
+
+        let varval = VarValPair::new(&PuzVar::new(name, indices), val);
+        Ok(if equal {
+            PuzLit::new_eq(varval)
+        } else {
+            PuzLit::new_neq(varval)
+        })
+    }
+}
+
+/// Represents a constraint identifier.
+#[derive(Clone, PartialOrd, Ord, Hash, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ConID {
+    pub lit: PuzLit,
+    pub name: String,
+}
+
+impl ConID {
+    /// Creates a new `ConID` instance.
+    fn new(lit: PuzLit, name: String) -> ConID {
+        ConID { lit, name }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::problem::VarValPair;
+
+    use super::{PuzLit, PuzVar, parse};
+    use std::collections::BTreeSet;
+    use std::sync::Arc;
+
+    #[test]
+    fn var() {
+        let v = PuzVar::new("v", vec![]);
+        let v2 = PuzVar::new("v", vec![2]);
+        let w = PuzVar::new("w", vec![]);
+        assert_eq!(v, v);
+        assert!(v != w);
+        assert!(v2 != w);
+        assert!(v != v2);
+    }
+
+    #[test]
+    fn varval() {
+        let v = Arc::new(PuzVar::new("v", vec![]));
+        let w = Arc::new(PuzVar::new("w", vec![]));
+        let l = VarValPair::new(&v, 2);
+        let nl = VarValPair::new(&v, 3);
+        let lw = VarValPair::new(&w, 2);
+        assert!(l != nl);
+        assert!(l != lw);
+        assert!(nl != lw);
+        assert_eq!(l, l);
+    }
+
+    #[test]
+    fn lit() {
+        let v = Arc::new(PuzVar::new("v", vec![]));
+        let w = Arc::new(PuzVar::new("w", vec![]));
+        let l = PuzLit::new_eq(VarValPair::new(&v, 2));
+        let nl = PuzLit::new_neq(VarValPair::new(&v, 2));
+        let lw = PuzLit::new_eq(VarValPair::new(&w, 2));
+        assert_eq!(l, l);
+        assert_eq!(l, l.neg().neg());
+        assert_eq!(l, nl.neg());
+        assert_eq!(l.neg(), nl);
+        assert!(l != lw);
+    }
+
+    #[test]
+    fn lit_from_str() {
+        let var = PuzVar::new("grid", vec![1, 2]);
+        let lit = PuzLit::new_eq(VarValPair::new(&var, 3));
+        assert_eq!(lit.to_string().parse::<PuzLit>().unwrap(), lit);
+        assert_eq!(lit.neg().to_string().parse::<PuzLit>().unwrap(), lit.neg());
+        assert_eq!(" grid[1,2] = 3 ".parse::<PuzLit>().unwrap(), lit);
+        assert_eq!("grid[1, 2] != 3".parse::<PuzLit>().unwrap(), lit.neg());
+
+        let scalar = PuzLit::new_eq(VarValPair::new(&PuzVar::new("x", vec![]), -1));
+        assert_eq!("x=-1".parse::<PuzLit>().unwrap(), scalar);
+        assert_eq!(scalar.to_string().parse::<PuzLit>().unwrap(), scalar);
+
+        for bad in [
+            "grid[1, 2]",
+            "grid[1, 2=3",
+            "grid[a]=3",
+            "grid[1]=b",
+            "[1]=3",
+        ] {
+            assert!(bad.parse::<PuzLit>().is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn varval_lit() {
+        let v = Arc::new(PuzVar::new("v", vec![]));
+        let w = Arc::new(PuzVar::new("w", vec![]));
+        let l = PuzLit::new_eq(VarValPair::new(&v, 2));
+        let nl = PuzLit::new_neq(VarValPair::new(&v, 2));
+        let lw = PuzLit::new_eq(VarValPair::new(&w, 2));
+
+        let vvl = VarValPair::new(&v, 2);
+        let vvl3 = VarValPair::new(&v, 3);
+        let vvlw = VarValPair::new(&w, 2);
+
+        assert!(l.is_varval(&vvl));
+        assert!(nl.is_varval(&vvl));
+        assert!(!l.is_varval(&vvl3));
+        assert!(!nl.is_varval(&vvl3));
+        assert!(lw.is_varval(&vvlw));
+        assert!(!lw.is_varval(&vvl));
+
+        assert!(vvl.is_lit(&l));
+        assert!(vvl.is_lit(&nl));
+        assert!(!vvl3.is_lit(&l));
+        assert!(!vvl3.is_lit(&nl));
+        assert!(!vvl.is_lit(&lw));
+        assert!(!vvlw.is_lit(&l));
+        assert!(vvlw.is_lit(&lw));
+    }
+
+    #[test]
+    fn test_puzvar_to_css_string() {
+        let v = PuzVar::new("v.name", vec![]);
+        assert_eq!(v.to_css_string(), "v_dname");
+
+        let v_with_indices = PuzVar::new("v-name", vec![1, 2, 3]);
+        assert_eq!(v_with_indices.to_css_string(), "v_hname_i1_i2_i3");
+
+        let v_complex = PuzVar::new("v.name-test", vec![42]);
+        assert_eq!(v_complex.to_css_string(), "v_dname_htest_i42");
+
+        let v_other = PuzVar::new("v_é", vec![-1]);
+        assert_eq!(v_other.to_css_string(), "v_u_xe9__i-1");
+
+        let v_wide = PuzVar::new("格子 🧩", vec![]);
+        assert_eq!(v_wide.to_css_string(), "_x683c__x5b50__x20__x1f9e9_");
+    }
+
+    #[test]
+    fn test_css_strings_unicode_names() {
+        // The sudoku, with its grid renamed
+        let name = "grille_é格";
+        let dir = tempfile::tempdir().unwrap();
+        let rename = |file: &str| {
+            let text = std::fs::read_to_string(format!("./tst/{file}")).unwrap();
+            let path = dir.path().join(file);
+            std::fs::write(&path, text.replace("grid", name)).unwrap();
+            path
+        };
+        let puzzle = parse::parse_eprime_with_dimacs(
+            &rename("sudoku-4x4.eprime"),
+            &rename("sudoku-4x4.json"),
+            &rename("sudoku-4x4.dimacs"),
+        )
+        .unwrap();
+        assert_eq!(puzzle.eprime.grid_var(), name);
+
+        let by_css = puzzle.varvals_by_css();
+        let pairs: BTreeSet<VarValPair> = puzzle.litmap.keys().map(PuzLit::varval).collect();
+        assert_eq!(by_css.len(), pairs.len());
+        let cell = VarValPair::new(&PuzVar::new(name, vec![1, 2]), 3);
+        assert_eq!(by_css[&cell.to_css_string()], cell);
+
+        // Every value highlighted in a step is a plain class, which maps back
+        let mut plan = crate::problem::planner::PuzzlePlanner::new(
+            crate::problem::solver::PuzzleSolver::new(Arc::new(puzzle)).unwrap(),
+        );
+        let step = plan.next_step();
+        let drawn = serde_json::to_string(&plan.step_problem(&step)).unwrap();
+        let classes: BTreeSet<&str> = drawn
+            .split(|c: char| c == '"' || c.is_whitespace())
+            .filter_map(|word| word.strip_prefix("highlight_"))
+            .filter(|class| class.starts_with("lit_"))
+            .collect();
+        assert!(!classes.is_empty());
+        for class in classes {
+            assert!(
+                class
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'),
+                "{class}"
+            );
+            assert_eq!(by_css[class].var().name(), name, "{class}");
+        }
+    }
+
+    #[test]
+    fn test_css_strings_do_not_collide() {
+        // These all gave "x_1_2" when names and indices were joined by "_"
+        let vars = [
+            PuzVar::new("x_1", vec![2]),
+            PuzVar::new("x", vec![1, 2]),
+            PuzVar::new("x_1_2", vec![]),
+            PuzVar::new("x.1", vec![2]),
+            PuzVar::new("x-1", vec![2]),
+        ];
+        let css: BTreeSet<String> = vars.iter().map(PuzVar::to_css_string).collect();
+        assert_eq!(css.len(), vars.len(), "{css:?}");
+
+        // Every value of the bundled puzzles, including auxiliary variables
+        for (model, name) in [
+            ("sudoku-4x4", "sudoku-4x4"),
+            ("sudoku-4x4", "sudoku-4x4-open"),
+            ("row-sum", "row-sum"),
+        ] {
+            let puzzle = parse::parse_eprime_with_dimacs(
+                &format!("./tst/{model}.eprime").into(),
+                &format!("./tst/{name}.json").into(),
+                &format!("./tst/{name}.dimacs").into(),
+            )
+            .unwrap();
+            let pairs: BTreeSet<VarValPair> = puzzle.litmap.keys().map(PuzLit::varval).collect();
+            let css: BTreeSet<String> = pairs.iter().map(VarValPair::to_css_string).collect();
+            assert_eq!(css.len(), pairs.len(), "{name}");
+            for c in &css {
+                assert!(
+                    c.chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'),
+                    "{c}"
+                );
+            }
+
+            let vars: BTreeSet<&PuzVar> = pairs.iter().map(VarValPair::var).collect();
+            let css: BTreeSet<String> = vars.iter().map(|v| v.to_css_string()).collect();
+            assert_eq!(css.len(), vars.len(), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_varvalpair_to_css_string() {
+        let v = PuzVar::new("v.name", vec![1, 2]);
+        let pair = VarValPair::new(&v, 42);
+        assert_eq!(pair.to_css_string(), "lit_v_dname_i1_i2_v42");
+
+        let w = PuzVar::new("w-name", vec![]);
+        let pair_no_indices = VarValPair::new(&w, 7);
+        assert_eq!(pair_no_indices.to_css_string(), "lit_w_hname_v7");
+
+        let negative = VarValPair::new(&PuzVar::new("x", vec![1]), -3);
+        assert_eq!(negative.to_css_string(), "lit_x_i1_v-3");
+    }
+
+    #[test]
+    fn test_nice_puzlit_list_html() {
+        let v = PuzVar::new("v", vec![]);
+        let w = PuzVar::new("w", vec![]);
+        let x = PuzVar::new("x", vec![1, 2]);
+
+        // Test case 1: Single positive literal
+        let lit1 = PuzLit::new_eq(VarValPair::new(&v, 2));
+        assert!(PuzLit::nice_puzlit_list_html(&[lit1.clone()]).contains("v[] = 2"));
+
+        // Test case 2: Multiple positive literals for different variables
+        let lit2 = PuzLit::new_eq(VarValPair::new(&w, 3));
+        let lit3 = PuzLit::new_eq(VarValPair::new(&x, 5));
+        assert!(PuzLit::nice_puzlit_list_html(&[lit1, lit2, lit3]).contains("x[1, 2] = 5"));
+
+        // Test case 3: Single negative literal
+        let neq1 = PuzLit::new_neq(VarValPair::new(&v, 2));
+        assert!(PuzLit::nice_puzlit_list_html(&[neq1.clone()]).contains("v[] != 2"));
+
+        // Test case 4: Multiple negative literals for same variable
+        let neq2 = PuzLit::new_neq(VarValPair::new(&v, 3));
+        let neq3 = PuzLit::new_neq(VarValPair::new(&v, 4));
+        assert!(PuzLit::nice_puzlit_list_html(&[neq1, neq2, neq3]).contains("v[] != 2 or 3 or 4"));
+
+        // Test case 5: Mix of positive and negative literals
+        let mix1 = PuzLit::new_eq(VarValPair::new(&v, 5));
+        let mix2 = PuzLit::new_neq(VarValPair::new(&w, 1));
+        let mix3 = PuzLit::new_neq(VarValPair::new(&w, 2));
+        let mix4 = PuzLit::new_eq(VarValPair::new(&x, 7));
+        assert!(
+            ["v[] = 5", "w[] != 1 or 2", "x[1, 2] = 7"]
+                .iter()
+                .all(|s| PuzLit::nice_puzlit_list_html([&mix1, &mix2, &mix3, &mix4]).contains(s))
+        );
+
+        // Test case 6: Empty list
+        assert_eq!(PuzLit::nice_puzlit_list_html(&[]), "");
+    }
+}
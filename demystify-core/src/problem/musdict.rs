@@ -6,6 +6,8 @@ use rustsat::types::Lit;
 #[derive(Clone)]
 pub struct MusDict {
     muses: HashMap<Lit, BTreeSet<MusContext>>,
+    /// Literals whose search ran out of time before finding a MUS
+    timed_out: BTreeSet<Lit>,
 }
 
 impl Default for MusDict {
@@ -24,6 +26,7 @@ impl MusDict {
     pub fn new() -> Self {
         MusDict {
             muses: HashMap::new(),
+            timed_out: BTreeSet::new(),
         }
     }
 
@@ -41,6 +44,7 @@ impl MusDict {
     /// * `lit` - The literal associated with the mus.
     /// * `new_mus` - The new mus to be added.
     pub fn add_mus(&mut self, lit: Lit, new_mus: BTreeSet<Lit>) {
+        self.timed_out.remove(&lit);
         if let Some(mus_list) = self.muses.get_mut(&lit) {
             let len = if let Some(element) = mus_list.iter().next() {
                 element.mus_len()
@@ -69,6 +73,41 @@ impl MusDict {
         }
     }
 
+    /// Adds every mus of `other`, as with [`MusDict::add_mus`].
+    pub fn merge(&mut self, other: MusDict) {
+        for (lit, mus_list) in other.muses {
+            for mc in mus_list {
+                self.add_mus(lit, mc.mus);
+            }
+        }
+        for lit in other.timed_out {
+            self.mark_timed_out(lit);
+        }
+    }
+
+    /// Records that the search for a MUS of `lit` ran out of time, unless
+    /// one has already been found.
+    pub fn mark_timed_out(&mut self, lit: Lit) {
+        if !self.muses.contains_key(&lit) {
+            self.timed_out.insert(lit);
+        }
+    }
+
+    /// The literals with no MUS because their search ran out of time, see
+    /// [`MusConfig::literal_time_limit`](super::solver::MusConfig::literal_time_limit).
+    #[must_use]
+    pub fn timed_out(&self) -> &BTreeSet<Lit> {
+        &self.timed_out
+    }
+
+    /// Keeps only the literals, and their muses, for which `f` returns true.
+    /// Literals which timed out are kept if `f` is true for them with no
+    /// muses.
+    pub fn retain(&mut self, mut f: impl FnMut(&Lit, &BTreeSet<MusContext>) -> bool) {
+        self.muses.retain(|lit, mus_list| f(lit, mus_list));
+        self.timed_out.retain(|lit| f(lit, &BTreeSet::new()));
+    }
+
     /// Returns a reference to the muses in the dictionary.
     #[must_use]
     pub fn muses(&self) -> &HashMap<Lit, BTreeSet<MusContext>> {
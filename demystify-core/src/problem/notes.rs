@@ -0,0 +1,136 @@
+//! Free text notes on the steps of a solve and on the cells of a puzzle, such
+//! as a teacher's comments on a walkthrough. Notes are shown with the steps
+//! they belong to by [`PuzzlePlanner`](super::planner::PuzzlePlanner).
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// What a note is attached to.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteTarget {
+    /// The `n`th step applied, counting from 1
+    Step(usize),
+    /// A cell, given by its indices
+    Cell(Vec<i64>),
+}
+
+impl std::fmt::Display for NoteTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NoteTarget::Step(n) => write!(f, "Step {n}"),
+            NoteTarget::Cell(indices) => {
+                let indices: Vec<_> = indices.iter().map(ToString::to_string).collect();
+                write!(f, "Cell ({})", indices.join(", "))
+            }
+        }
+    }
+}
+
+/// A single note, as stored in saved sessions.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Note {
+    pub target: NoteTarget,
+    pub text: String,
+}
+
+/// At most one note for each step and cell.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Vec<Note>", into = "Vec<Note>")]
+pub struct Notes(BTreeMap<NoteTarget, String>);
+
+impl Notes {
+    /// Sets the note on `target`, replacing any earlier one. A blank `text`
+    /// removes the note.
+    pub fn set(&mut self, target: NoteTarget, text: &str) {
+        let text = text.trim();
+        if text.is_empty() {
+            self.0.remove(&target);
+        } else {
+            self.0.insert(target, text.to_owned());
+        }
+    }
+
+    #[must_use]
+    pub fn get(&self, target: &NoteTarget) -> Option<&str> {
+        self.0.get(target).map(String::as_str)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&NoteTarget, &str)> {
+        self.0.iter().map(|(t, n)| (t, n.as_str()))
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The notes on `targets` as HTML, one `div` each, or an empty string if
+    /// there are none.
+    #[must_use]
+    pub fn html<'a>(&self, targets: impl IntoIterator<Item = &'a NoteTarget>) -> String {
+        targets
+            .into_iter()
+            .filter_map(|t| {
+                self.get(t).map(|text| {
+                    format!(
+                        r#"<div class="note"><b>{}:</b> {}</div>"#,
+                        tera::escape_html(&t.to_string()),
+                        tera::escape_html(text)
+                    )
+                })
+            })
+            .collect()
+    }
+}
+
+impl From<Vec<Note>> for Notes {
+    fn from(notes: Vec<Note>) -> Self {
+        let mut n = Notes::default();
+        for note in notes {
+            n.set(note.target, &note.text);
+        }
+        n
+    }
+}
+
+impl From<Notes> for Vec<Note> {
+    fn from(notes: Notes) -> Self {
+        notes
+            .0
+            .into_iter()
+            .map(|(target, text)| Note { target, text })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notes() {
+        let mut notes = Notes::default();
+        notes.set(NoteTarget::Step(2), "Look at <the> box");
+        notes.set(NoteTarget::Cell(vec![1, 2]), "A hidden single");
+        notes.set(NoteTarget::Cell(vec![3, 3]), "  ");
+        assert_eq!(notes.iter().count(), 2);
+
+        let html = notes.html(&[NoteTarget::Step(2), NoteTarget::Step(3)]);
+        assert_eq!(
+            html,
+            r#"<div class="note"><b>Step 2:</b> Look at &lt;the&gt; box</div>"#
+        );
+
+        let json = serde_json::to_string(&notes).unwrap();
+        assert_eq!(serde_json::from_str::<Notes>(&json).unwrap(), notes);
+
+        notes.set(NoteTarget::Step(2), "");
+        assert_eq!(notes.get(&NoteTarget::Step(2)), None);
+        assert_eq!(
+            notes.get(&NoteTarget::Cell(vec![1, 2])),
+            Some("A hidden single")
+        );
+    }
+}
@@ -0,0 +1,2533 @@
+#![allow(clippy::needless_range_loop)]
+/// This module contains the implementation of parsing and processing functions for problem files.
+/// It includes functions for parsing DIMACS files, extracting annotations from Essence' files,
+/// and creating data structures to represent the parsed information.
+///
+/// The main struct in this module is `PuzzleParse`, which represents the result of parsing a DIMACS file.
+/// It contains various fields to store the parsed information, such as the annotations from the Essence' file,
+/// the SAT instance parsed from the DIMACS file, mappings between literals and SAT integers, and more.
+use anyhow::{Context, bail};
+use itertools::Itertools;
+use regex::Regex;
+use rustsat::instances::Cnf;
+use rustsat::types::{Clause, Lit};
+
+use std::cmp::max;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use std::fs;
+use std::io::BufReader;
+use std::io::prelude::*;
+
+use serde::{Deserialize, Serialize};
+use std::mem::forget;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tempfile::TempDir;
+use thiserror::Error;
+use tracing::{debug, info, warn};
+
+use std::fs::File;
+use std::io;
+
+use crate::problem::util::compress::copy_decompressed;
+use crate::problem::util::exec::{
+    Executor, ProgramRunner, SystemExecutor, ToolFailure, keep_failed_runs,
+};
+use crate::problem::util::{param, parsing};
+use crate::problem::{PuzLit, PuzVar};
+
+use super::VarValPair;
+use super::explain::Fact;
+use super::graph::{ConstraintGraph, GraphNodes};
+use super::util::lru::LruCache;
+use super::util::{FindVarConnections, safe_insert};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EPrimeAnnotations {
+    /// The set of variables in the Essence' file.
+    pub vars: BTreeSet<String>,
+    /// The set of auxiliary variables in the Essence'file.
+    pub auxvars: BTreeSet<String>,
+    /// The constraints in the Essence' file, represented as a mapping from constraint name to constraint expression.
+    pub cons: BTreeMap<String, String>,
+    /// The Essence' expressions which mention each constraint, from the `such that` sections
+    pub con_exprs: BTreeMap<String, String>,
+    /// 'reveal', which allow extra information to be added during solving.
+    pub reveal: BTreeMap<String, String>,
+    /// values from the 'reveal' map (for ease of searching)
+    pub reveal_values: BTreeSet<String>,
+    /// The parameters read from the param file
+    params: BTreeMap<String, serde_json::value::Value>,
+    /// The kind of puzzle
+    pub kind: Option<String>,
+    /// The words used for variables and values in explanations
+    pub vocabulary: Vocabulary,
+    /// How much each constraint should be avoided in explanations, from
+    /// `$#CONWEIGHT`. Constraints without a weight weigh 1.
+    pub con_weights: BTreeMap<String, f64>,
+    /// How a grid variable flattened to a 1-D array is drawn, from
+    /// `$#GRIDMAP`
+    pub grid_map: Option<GridMap>,
+}
+
+/// The words a model gives for its variables and their values, with `$#NAME`
+/// and `$#VALUE`, so explanations need not show internal names and numbers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Vocabulary {
+    /// A template for the name of each variable, filled in like a `$#CON`
+    /// description, such as `"cell ({{index[0]}},{{index[1]}})"`
+    pub names: BTreeMap<String, String>,
+    /// The word for each value of a variable, such as `"a mine"` for 1
+    pub values: BTreeMap<String, BTreeMap<i64, String>>,
+    /// How values of a variable are drawn in the grid, when not as their
+    /// number, found by [`Vocabulary::find_glyphs`]
+    pub glyphs: BTreeMap<String, BTreeMap<i64, String>>,
+}
+
+impl Vocabulary {
+    /// Fills in [`Vocabulary::glyphs`] for the `$#VAR`s `vars`, whose values
+    /// are in `domains`, so that two digit values do not overflow their
+    /// cells. A value is drawn as its `$#VALUE` word if that is a single
+    /// character. Otherwise, if every value of a variable is from 0 to 35 and
+    /// some are above 9, those above 9 are drawn as letters, `A` for 10 up
+    /// to `Z` for 35, as in a 16x16 sudoku with values 1 to 9 and A to G.
+    pub fn find_glyphs(
+        &mut self,
+        vars: &BTreeSet<String>,
+        domains: &BTreeMap<PuzVar, BTreeSet<i64>>,
+    ) {
+        let mut values: BTreeMap<&String, BTreeSet<i64>> = BTreeMap::new();
+        for (var, domain) in domains {
+            if vars.contains(var.name()) {
+                values.entry(var.name()).or_default().extend(domain);
+            }
+        }
+
+        self.glyphs.clear();
+        for (name, values) in values {
+            let words = self.values.get(name);
+            let lettered = values.iter().all(|v| (0..=35).contains(v))
+                && values.last().is_some_and(|&v| v > 9);
+            let glyphs: BTreeMap<i64, String> = values
+                .into_iter()
+                .filter_map(|val| {
+                    let word = words
+                        .and_then(|w| w.get(&val))
+                        .filter(|w| w.chars().count() == 1);
+                    if let Some(word) = word {
+                        Some((val, word.clone()))
+                    } else if lettered && val > 9 {
+                        let letter = char::from(b'A' + u8::try_from(val - 10).ok()?);
+                        Some((val, letter.to_string()))
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+            if !glyphs.is_empty() {
+                self.glyphs.insert(name.clone(), glyphs);
+            }
+        }
+    }
+}
+
+/// The order in which a [`GridMap`] numbers the cells of its grid.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GridOrder {
+    /// Along the first row, then the second, and so on
+    Row,
+    /// Down the first column, then the second, and so on
+    Column,
+}
+
+/// A grid variable which a model flattens to a 1-D array indexed by cell
+/// number, from `$#GRIDMAP <var> <width> <height> <order>`, so it can be
+/// drawn (and clicked on) as a 2-D grid. The width and height are each a
+/// number or the name of an int param.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct GridMap {
+    /// The flattened `$#VAR`, indexed from 1
+    pub var: String,
+    pub width: i64,
+    pub height: i64,
+    pub order: GridOrder,
+}
+
+impl GridMap {
+    /// The (1-indexed) row and column of the cell with (1-indexed) number
+    /// `index`, or `None` if it is outside the grid.
+    #[must_use]
+    pub fn cell(&self, index: i64) -> Option<Vec<i64>> {
+        if index < 1 || index > self.width * self.height {
+            return None;
+        }
+        let k = index - 1;
+        let (i, j) = match self.order {
+            GridOrder::Row => (k / self.width, k % self.width),
+            GridOrder::Column => (k % self.height, k / self.height),
+        };
+        Some(vec![i + 1, j + 1])
+    }
+
+    /// The number of the cell with (1-indexed) row and column `cell`, the
+    /// inverse of [`GridMap::cell`].
+    #[must_use]
+    pub fn index(&self, cell: &[i64]) -> Option<i64> {
+        let &[i, j] = cell else {
+            return None;
+        };
+        if !(1..=self.height).contains(&i) || !(1..=self.width).contains(&j) {
+            return None;
+        }
+        Some(match self.order {
+            GridOrder::Row => (i - 1) * self.width + j,
+            GridOrder::Column => (j - 1) * self.height + i,
+        })
+    }
+
+    /// The cell of `var`, if it is an element of the flattened variable.
+    #[must_use]
+    pub fn cell_of(&self, var: &PuzVar) -> Option<Vec<i64>> {
+        match var.indices().as_slice() {
+            &[index] if *var.name() == self.var => self.cell(index),
+            _ => None,
+        }
+    }
+
+    /// Lays out `values`, indexed like the flattened variable, as a grid.
+    #[must_use]
+    pub fn reshape(&self, values: &[i64]) -> Vec<Vec<Option<i64>>> {
+        let width = usize::try_from(self.width).unwrap_or(0);
+        let height = usize::try_from(self.height).unwrap_or(0);
+        let mut grid = vec![vec![None; width]; height];
+        for (index, &val) in (1..).zip(values) {
+            if let Some(cell) = self.cell(index) {
+                grid[cell[0] as usize - 1][cell[1] as usize - 1] = Some(val);
+            }
+        }
+        grid
+    }
+}
+
+/// A `$#GRIDMAP` as written, before its width and height are looked up in
+/// the params.
+struct GridMapSpec {
+    var: String,
+    width: String,
+    height: String,
+    order: GridOrder,
+}
+
+impl GridMapSpec {
+    fn resolve(self, eprime: &EPrimeAnnotations) -> anyhow::Result<GridMap> {
+        let size = |s: &str| -> anyhow::Result<i64> {
+            let size = match s.parse() {
+                Ok(size) => size,
+                Err(_) => eprime.param_i64(s)?,
+            };
+            if size <= 0 {
+                bail!("The width and height in a GRIDMAP must be positive: {s} = {size}");
+            }
+            Ok(size)
+        };
+        Ok(GridMap {
+            width: size(&self.width)?,
+            height: size(&self.height)?,
+            var: self.var,
+            order: self.order,
+        })
+    }
+}
+
+impl EPrimeAnnotations {
+    /// The name of `var` in explanations, from its `$#NAME` if it has one.
+    #[must_use]
+    pub fn var_name(&self, var: &PuzVar) -> String {
+        self.vocabulary
+            .names
+            .get(var.name())
+            .and_then(|template| {
+                parsing::parse_constraint_name(template, &self.params, var.indices())
+                    .inspect_err(|e| warn!(target: "parser", "Cannot name {var}: {e:#}"))
+                    .ok()
+            })
+            .unwrap_or_else(|| var.to_string())
+    }
+
+    /// `fact` in the words given by the model, such as "cell (1,2) is a
+    /// mine", or `None` if there are none for its variable. A variable with
+    /// only [`Vocabulary::glyphs`] keeps its usual wording, with the values
+    /// drawn as in the grid, such as "grid[1, 2] = B".
+    #[must_use]
+    pub fn describe_fact(&self, fact: &Fact) -> Option<String> {
+        let name = fact.var.name();
+        let words = self.vocabulary.values.get(name);
+        let glyphs = self.vocabulary.glyphs.get(name);
+        let worded = words.is_some() || self.vocabulary.names.contains_key(name);
+        if !worded && glyphs.is_none() {
+            return None;
+        }
+        let values = fact
+            .values
+            .iter()
+            .map(|val| {
+                words
+                    .and_then(|w| w.get(val))
+                    .or_else(|| glyphs.and_then(|g| g.get(val)))
+                    .map_or_else(|| val.to_string(), Clone::clone)
+            })
+            .join(" or ");
+        if worded {
+            let is = if fact.equal { "is" } else { "is not" };
+            Some(format!("{} {is} {values}", self.var_name(&fact.var)))
+        } else {
+            let op = if fact.equal { "=" } else { "!=" };
+            Some(format!("{} {op} {values}", fact.var))
+        }
+    }
+
+    /// The `$#VAR` drawn as the grid: the only one, or else the one called
+    /// `grid`.
+    #[must_use]
+    pub fn grid_var(&self) -> &str {
+        match self.vars.iter().collect_vec().as_slice() {
+            [only] => only.as_str(),
+            _ => "grid",
+        }
+    }
+
+    /// The (1-indexed) row and column of `var` in the grid: its cell in the
+    /// [`GridMap`] if it is an element of a flattened grid, and otherwise
+    /// its own indices.
+    #[must_use]
+    pub fn cell_of(&self, var: &PuzVar) -> Vec<i64> {
+        self.grid_map
+            .as_ref()
+            .and_then(|map| map.cell_of(var))
+            .unwrap_or_else(|| var.indices().clone())
+    }
+
+    /// All the parameters, as read from the param file
+    #[must_use]
+    pub fn params(&self) -> &BTreeMap<String, serde_json::value::Value> {
+        &self.params
+    }
+
+    #[must_use]
+    pub fn has_param(&self, s: &str) -> bool {
+        self.params.contains_key(s)
+    }
+
+    /// The param `mask`, for boards which are not rectangles (such as hex
+    /// grids or grids with holes). A non-zero value marks a position of the
+    /// grid which is not a cell, shown as `true`.
+    pub fn mask(&self) -> anyhow::Result<Option<Vec<Vec<bool>>>> {
+        if !self.has_param("mask") {
+            return Ok(None);
+        }
+        Ok(Some(
+            self.param_grid("mask")?
+                .into_iter()
+                .map(|row| row.into_iter().map(|c| c.is_some_and(|c| c != 0)).collect())
+                .collect(),
+        ))
+    }
+
+    /// Whether the position with (1-indexed) `indices` is not a cell, as
+    /// marked by [`EPrimeAnnotations::mask`].
+    #[must_use]
+    pub fn is_masked(&self, indices: &[i64]) -> bool {
+        self.mask()
+            .ok()
+            .flatten()
+            .is_some_and(|mask| in_mask(&mask, indices))
+    }
+
+    pub fn param_bool(&self, s: &str) -> anyhow::Result<bool> {
+        serde_json::from_value(
+            self.params
+                .get(s)
+                .context(format!("Missing param: {s}"))?
+                .clone(),
+        )
+        .context(format!("Param {s} is not bool"))
+    }
+
+    pub fn param_i64(&self, s: &str) -> anyhow::Result<i64> {
+        serde_json::from_value(
+            self.params
+                .get(s)
+                .context(format!("Missing param: {s}"))?
+                .clone(),
+        )
+        .context(format!("Param {s} is not int"))
+    }
+
+    pub fn param_vec_i64(&self, s: &str) -> anyhow::Result<Vec<i64>> {
+        // Conjure produces arrays as maps, so we need to fix up
+        let map: BTreeMap<i64, i64> = serde_json::from_value(
+            self.params
+                .get(s)
+                .context(format!("Missing param: {s}"))?
+                .clone(),
+        )
+        .context(format!("Param {s} is not an array of ints"))?;
+
+        matrix_values(map, s)
+    }
+
+    pub fn param_vec_vec_i64(&self, s: &str) -> anyhow::Result<Vec<Vec<i64>>> {
+        // Conjure produces arrays as maps, so we need to fix up
+        let map: BTreeMap<i64, BTreeMap<i64, i64>> = serde_json::from_value(
+            self.params
+                .get(s)
+                .context(format!("Missing param: {s}"))?
+                .clone(),
+        )
+        .context(format!("Param {s} is not a 2d array of ints"))?;
+
+        matrix_values(map, s)?
+            .into_iter()
+            .map(|row| matrix_values(row, s))
+            .collect()
+    }
+
+    /// A 3d array of ints, such as a list of paths which are each a list of
+    /// cells.
+    pub fn param_vec_vec_vec_i64(&self, s: &str) -> anyhow::Result<Vec<Vec<Vec<i64>>>> {
+        // Conjure produces arrays as maps, which are in index order
+        let map: BTreeMap<i64, BTreeMap<i64, BTreeMap<i64, i64>>> = serde_json::from_value(
+            self.params
+                .get(s)
+                .context(format!("Missing param: {s}"))?
+                .clone(),
+        )
+        .context(format!("Param {s} is not a 3d array of ints"))?;
+
+        matrix_values(map, s)?
+            .into_iter()
+            .map(|m| {
+                matrix_values(m, s)?
+                    .into_iter()
+                    .map(|r| matrix_values(r, s))
+                    .collect()
+            })
+            .collect()
+    }
+
+    pub fn param_vec_string(&self, s: &str) -> anyhow::Result<Vec<String>> {
+        let map: BTreeMap<i64, serde_json::Value> = serde_json::from_value(
+            self.params
+                .get(s)
+                .context(format!("Missing param: {s}"))?
+                .clone(),
+        )
+        .context(format!("Param {s} is not an array of strings"))?;
+
+        Ok(matrix_values(map, s)?
+            .iter()
+            .map(serde_json::Value::to_string)
+            .collect())
+    }
+
+    pub fn param_vec_vec_string(&self, s: &str) -> anyhow::Result<Vec<Vec<String>>> {
+        // Conjure produces arrays as maps, so we need to fix up
+        let map: BTreeMap<i64, BTreeMap<i64, serde_json::Value>> = serde_json::from_value(
+            self.params
+                .get(s)
+                .context(format!("Missing param: {s}"))?
+                .clone(),
+        )
+        .context(format!("Param {s} is not a 2d array of strings"))?;
+
+        matrix_values(map, s)?
+            .into_iter()
+            .map(|row| {
+                Ok(matrix_values(row, s)?
+                    .iter()
+                    .map(serde_json::Value::to_string)
+                    .collect())
+            })
+            .collect()
+    }
+
+    /// The param `s`, laid out as the grid: a 2d array of ints and nulls,
+    /// or (with a [`GridMap`]) a 1d array indexed like the flattened grid.
+    pub fn param_grid(&self, s: &str) -> anyhow::Result<Vec<Vec<Option<i64>>>> {
+        if let Some(map) = &self.grid_map
+            && let Ok(values) = self.param_vec_i64(s)
+        {
+            return Ok(map.reshape(&values));
+        }
+        self.param_vec_vec_option_i64(s)
+    }
+
+    pub fn param_vec_vec_option_i64(&self, s: &str) -> anyhow::Result<Vec<Vec<Option<i64>>>> {
+        // Conjure produces arrays as maps, so we need to fix up
+        let map: BTreeMap<i64, BTreeMap<i64, Option<i64>>> = serde_json::from_value(
+            self.params
+                .get(s)
+                .context(format!("Missing param: {s}"))?
+                .clone(),
+        )
+        .context(format!("Param {s} is not a 2d array of ints and nulls"))?;
+
+        matrix_values(map, s)?
+            .into_iter()
+            .map(|row| matrix_values(row, s))
+            .collect()
+    }
+}
+
+/// The values of one dimension of the matrix param `s`, which conjure writes
+/// as a map from each index to its value. The indices can start anywhere,
+/// such as at 0 for a matrix indexed by `int(0..n-1)`, so the values are
+/// kept in order of their index, and the first is always at position 0,
+/// lining up with the first row or column of the grid. There cannot be gaps
+/// between the indices.
+fn matrix_values<T>(map: BTreeMap<i64, T>, s: &str) -> anyhow::Result<Vec<T>> {
+    if let (Some(&first), Some(&last)) = (map.keys().next(), map.keys().next_back())
+        && last
+            .checked_sub(first)
+            .and_then(|span| usize::try_from(span).ok())
+            != Some(map.len() - 1)
+    {
+        bail!("Malformed param? {s} is indexed from {first} to {last}, with gaps");
+    }
+    Ok(map.into_values().collect())
+}
+
+/// Represents the result of parsing a DIMACS file.
+
+#[derive(Debug, Clone, PartialEq)]
+
+pub struct PuzzleParse {
+    /// The annotations from the Essence' file
+    pub eprime: EPrimeAnnotations,
+    /// The clauses parsed from the DIMACS file, shared with every solver
+    pub cnf: Option<Arc<Cnf>>,
+    /// A mapping from literals in the direct representation to their corresponding SAT integer.
+    pub litmap: BTreeMap<PuzLit, Lit>,
+    /// A mapping from SAT integers to the direct representation.
+    pub invlitmap: BTreeMap<Lit, BTreeSet<PuzLit>>,
+    /// A mapping from each variable to its domain
+    pub domainmap: BTreeMap<PuzVar, BTreeSet<i64>>,
+    /// List of all literals representing constraints in the problem, and their English-readable name
+    pub conset: BTreeMap<Lit, String>,
+    /// Inverse of conset
+    pub invconset: BTreeMap<String, Lit>,
+    /// Lits of all literals in each constraint
+    pub varlits_in_con: BTreeMap<Lit, Vec<Lit>>,
+    /// List of all literals in a VAR in the direct encoding
+    pub varset_lits: BTreeSet<Lit>,
+    /// Lits of all literals in a VAR in the direct encoding, representing a variable becoming unassigned
+    pub varset_lits_neg: BTreeSet<Lit>,
+    /// List of all literals which turn on CON
+    pub conset_lits: BTreeSet<Lit>,
+
+    /// A mapping from variables in the order representation to their corresponding SAT integers.
+    /// These are generally not useful, but are sometimes used when scanning
+    /// the entire problem
+    pub order_encoding_map: BTreeMap<PuzVar, HashSet<Lit>>,
+    /// A mapping from lits to the order representation they represent.
+    /// These are generally not useful, but are sometimes used when scanning
+    /// the entire problem
+    pub inv_order_encoding_map: BTreeMap<Lit, PuzVar>,
+    /// List of all literals in tbe order encoding of a variable
+    /// These are generally not useful, but are sometimes used when scanning
+    /// the SAT instance
+    pub order_encoding_all_lits: BTreeSet<Lit>,
+
+    /// Whenever a lit 'x' is proved, then `reveal_map`(x) should also be
+    /// added to the known lits.
+    pub reveal_map: BTreeMap<Lit, Lit>,
+
+    /// The VARs whose direct encoding says, with a binary clause for each
+    /// pair of values, that they take at most one value. The SAT solver then
+    /// deduces `x != j` from `x = i` by itself, so these need not be given to
+    /// it, see [`KnownLits`](super::solver::known::KnownLits).
+    pub at_most_one_vars: BTreeSet<PuzVar>,
+
+    /// The results of [`PuzzleParse::constraint_scope`] and
+    /// [`PuzzleParse::direct_or_ordered_lit_to_varvalpair`], which are asked
+    /// for again for every constraint each time a step is drawn.
+    scope_cache: ScopeCache,
+}
+
+/// How many constraint scopes, and how many lits, [`ScopeCache`] holds. A
+/// step only shows a few constraints, but a 25x25 sudoku has more than half
+/// a million, so the cache is bounded rather than filled with all of them.
+const SCOPE_CACHE_CAPACITY: usize = 1 << 17;
+
+/// See [`PuzzleParse::scope_cache`]. Both maps are worked out from
+/// `invlitmap`, `inv_order_encoding_map`, `domainmap`, `invconset` and
+/// `varlits_in_con`, so must be cleared with
+/// [`PuzzleParse::clear_scope_cache`] if any of those change.
+#[derive(Debug, Clone)]
+struct ScopeCache {
+    /// From the lit of each constraint to its scope
+    scopes: LruCache<Lit, Arc<BTreeSet<VarValPair>>>,
+    /// From each lit to the `VarValPair`s it is about
+    varvals: LruCache<Lit, BTreeSet<VarValPair>>,
+}
+
+impl Default for ScopeCache {
+    fn default() -> Self {
+        ScopeCache {
+            scopes: LruCache::new(SCOPE_CACHE_CAPACITY),
+            varvals: LruCache::new(SCOPE_CACHE_CAPACITY),
+        }
+    }
+}
+
+/// The cache only remembers what can be worked out again, so it is ignored
+/// when comparing puzzles.
+impl PartialEq for ScopeCache {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl PuzzleParse {
+    #[must_use]
+    pub fn new_from_eprime(
+        vars: BTreeSet<String>,
+        auxvars: BTreeSet<String>,
+        cons: BTreeMap<String, String>,
+        reveal: BTreeMap<String, String>,
+        params: BTreeMap<String, serde_json::value::Value>,
+        kind: Option<String>,
+    ) -> PuzzleParse {
+        PuzzleParse {
+            eprime: EPrimeAnnotations {
+                vars,
+                auxvars,
+                cons,
+                con_exprs: BTreeMap::new(),
+                reveal: reveal.clone(),
+                reveal_values: reveal.values().cloned().collect(),
+                params,
+                kind,
+                vocabulary: Vocabulary::default(),
+                con_weights: BTreeMap::new(),
+                grid_map: None,
+            },
+            cnf: None,
+            litmap: BTreeMap::new(),
+            invlitmap: BTreeMap::new(),
+            domainmap: BTreeMap::new(),
+            order_encoding_map: BTreeMap::new(),
+            inv_order_encoding_map: BTreeMap::new(),
+            order_encoding_all_lits: BTreeSet::new(),
+            conset: BTreeMap::new(),
+            invconset: BTreeMap::new(),
+            varlits_in_con: BTreeMap::new(),
+            varset_lits: BTreeSet::new(),
+            varset_lits_neg: BTreeSet::new(),
+            conset_lits: BTreeSet::new(),
+            reveal_map: BTreeMap::new(),
+            at_most_one_vars: BTreeSet::new(),
+            scope_cache: ScopeCache::default(),
+        }
+    }
+
+    fn finalise(&mut self) -> anyhow::Result<()> {
+        {
+            let mut newlitmap = BTreeMap::new();
+            // Make sure 'litmap' contains both positive and negative version of every problem literal
+            for (key, &value) in &self.litmap {
+                if let Some(&val) = self.litmap.get(&key.neg()) {
+                    if val != -value {
+                        bail!(
+                            "Malformed Savilerow DIMACS output: Issue with {:?}",
+                            (key, value)
+                        );
+                    }
+                } else {
+                    safe_insert(&mut newlitmap, key.neg(), -value)?;
+                }
+            }
+            self.litmap.extend(newlitmap);
+        }
+
+        // Set up inverse of 'litmap', mapping from integers to PuzLit objects
+        for (key, value) in &self.litmap {
+            self.invlitmap
+                .entry(*value)
+                .or_default()
+                .insert(key.clone());
+        }
+
+        // Get the domain of each variable quickly
+        for lit in self.litmap.keys() {
+            let var_id = lit.var();
+            if lit.sign() {
+                self.domainmap.entry(var_id).or_default().insert(lit.val());
+            }
+        }
+
+        for (puzlit, &lit) in &self.litmap {
+            let var = puzlit.var();
+
+            let name = var.name();
+            if self.eprime.vars.contains(name) {
+                self.varset_lits.insert(lit);
+                if !puzlit.sign() {
+                    self.varset_lits_neg.insert(lit);
+                }
+            } else if self.eprime.auxvars.contains(name) {
+                // don't care about aux vars
+            } else if self.eprime.cons.contains_key(name) {
+                // constraints are specially dealt with above
+            } else if self.eprime.reveal_values.contains(name) {
+                // reveal_values are dealt with below,
+                // as we need all of 'varset' to be complete first
+                // So here we just allow the name
+            } else {
+                bail!("Cannot identify {:?}, should it be marked as AUX?", puzlit);
+            }
+        }
+
+        for (puzlit, &lit) in &self.litmap {
+            let var = puzlit.var();
+            let name = var.name();
+            if self.eprime.reveal.contains_key(name) && puzlit.sign() {
+                let mut index = puzlit.varval().var().indices().clone();
+                index.push(puzlit.varval().val);
+
+                let target_name = self.eprime.reveal.get(name).unwrap();
+
+                let target_puzvar = PuzVar::new(target_name, index);
+                let target_varvalpair = VarValPair::new(&target_puzvar, 1);
+                let target_puzlit = PuzLit::new_eq(target_varvalpair);
+
+                if let Some(&target_lit) = self.litmap.get(&target_puzlit) {
+                    safe_insert(&mut self.reveal_map, lit, target_lit)
+                        .context("Some variable used in two 'REVEAL'")?;
+                } else {
+                    info!("Can't find {target_puzlit} from {puzlit}");
+                }
+            }
+        }
+
+        let mut usedconstraintnames: HashSet<String> = HashSet::new();
+
+        let cnf = self.cnf.clone().context("No DIMACS has been read")?;
+        let fvc = FindVarConnections::new(&cnf, &self.all_var_related_lits());
+        self.at_most_one_vars = self.find_at_most_one_vars(&cnf);
+
+        // Tidy up and check constraints
+        for (varid, vals) in &self.domainmap {
+            if let Some(template_string) = self.eprime.cons.get(varid.name()) {
+                debug!(target: "parser", "Found {:?} in constraint {:?}", varid, varid.name());
+
+                if !vals.contains(&0) {
+                    bail!(format!("CON {:?} cannot be made false", varid));
+                }
+
+                if !vals.contains(&1) {
+                    bail!(format!("CON {:?} cannot be made true", varid));
+                }
+
+                if vals.len() != 2 {
+                    bail!(format!(
+                        "CON {:?} domain is {:?}, should be (0,1)",
+                        varid, vals
+                    ));
+                }
+
+                let constraintname = parsing::parse_constraint_name(
+                    template_string,
+                    &self.eprime.params,
+                    &varid.indices,
+                )?;
+
+                // Check is we have used this name before
+                if usedconstraintnames.contains(&constraintname) {
+                    bail!(format!(
+                        "CON name {:?} used twice. This should already have substitutions done, so if you see '{{' or '}}' check your formatting string.",
+                        constraintname
+                    ))
+                }
+                usedconstraintnames.insert(constraintname.clone());
+
+                // TODO: Skip constraints which are already parsed,
+                // or trivial (parse.py 270 -- 291)
+
+                let puzlit = PuzLit::new_eq(VarValPair::new(varid, 1));
+                let lit = *self.litmap.get(&puzlit).unwrap();
+                safe_insert(&mut self.conset, lit, constraintname.clone())?;
+                safe_insert(&mut self.invconset, constraintname.clone(), lit)?;
+                self.conset_lits.insert(lit);
+                safe_insert(&mut self.varlits_in_con, lit, fvc.get_connections(lit))?;
+                info!(
+                    "MAP {} {:?}",
+                    &constraintname,
+                    self.varlits_in_con.get(&lit).unwrap()
+                );
+            }
+        }
+
+        self.eprime
+            .vocabulary
+            .find_glyphs(&self.eprime.vars, &self.domainmap);
+
+        self.clear_scope_cache();
+        Ok(())
+    }
+
+    /// The VARs for [`PuzzleParse::at_most_one_vars`].
+    fn find_at_most_one_vars(&self, cnf: &Cnf) -> BTreeSet<PuzVar> {
+        let binary: HashSet<(Lit, Lit)> = cnf
+            .iter()
+            .filter_map(|clause| match clause[..] {
+                [a, b] => Some((a.min(b), a.max(b))),
+                _ => None,
+            })
+            .collect();
+        // A pair of values is excluded by the clause (!a or !b)
+        let excludes = |a: Lit, b: Lit| {
+            let (a, b) = (!a, !b);
+            a == !b || binary.contains(&(a.min(b), a.max(b)))
+        };
+
+        self.domainmap
+            .iter()
+            .filter(|(var, _)| self.eprime.vars.contains(var.name()))
+            .filter(|(var, domain)| {
+                let lits = domain
+                    .iter()
+                    .map(|&val| self.litmap[&PuzLit::new_eq(VarValPair::new(var, val))])
+                    .collect_vec();
+                lits.iter()
+                    .tuple_combinations()
+                    .all(|(&a, &b)| excludes(a, b))
+            })
+            .map(|(var, _)| var.clone())
+            .collect()
+    }
+
+    #[must_use]
+    pub fn lit_is_con(&self, lit: &Lit) -> bool {
+        self.conset_lits.contains(lit)
+    }
+
+    #[must_use]
+    pub fn lit_to_con(&self, lit: &Lit) -> &String {
+        assert!(self.lit_is_con(lit));
+        self.conset.get(lit).unwrap()
+    }
+
+    #[must_use]
+    pub fn lit_is_var(&self, lit: &Lit) -> bool {
+        self.varset_lits.contains(lit)
+    }
+
+    #[must_use]
+    pub fn lit_to_vars(&self, lit: &Lit) -> &BTreeSet<PuzLit> {
+        self.invlitmap.get(lit).expect("IE: Bad lit")
+    }
+
+    /// Every value of the puzzle by its [`VarValPair::to_css_string`], to
+    /// find which value a `highlight_lit_*` class of the HTML stands for
+    /// without parsing the class.
+    #[must_use]
+    pub fn varvals_by_css(&self) -> BTreeMap<String, VarValPair> {
+        self.litmap
+            .keys()
+            .map(|puzlit| (puzlit.varval().to_css_string(), puzlit.varval()))
+            .collect()
+    }
+
+    /// `lit` in terms of the puzzle, for the query log of
+    /// [`SatQuery::describe`](crate::satcore::SatQuery::describe): the
+    /// constraint it turns on or off, or the literals of variables it stands
+    /// for, or else its number in the DIMACS file.
+    #[must_use]
+    pub fn describe_lit(&self, lit: Lit) -> String {
+        if let Some(con) = self.conset.get(&lit) {
+            format!("[{con}]")
+        } else if let Some(con) = self.conset.get(&!lit) {
+            format!("not [{con}]")
+        } else if let Some(puzlits) = self.invlitmap.get(&lit) {
+            puzlits.iter().join(" / ")
+        } else if let Some(var) = self.inv_order_encoding_map.get(&lit) {
+            format!("{lit} (order encoding of {var})")
+        } else {
+            lit.to_string()
+        }
+    }
+
+    // All lits included in both the direct and ordered encoding
+    // of VARs
+    #[must_use]
+    pub fn all_var_related_lits(&self) -> HashSet<Lit> {
+        let ordered_var: BTreeSet<Lit> = self
+            .order_encoding_map
+            .iter()
+            .filter(|(k, _)| self.eprime.vars.contains(k.name()))
+            .flat_map(|(_, v)| v)
+            .copied()
+            .collect();
+
+        self.varset_lits.union(&ordered_var).copied().collect()
+    }
+
+    // All VarValPairs included in VARs
+    #[must_use]
+    pub fn all_var_varvals(&self) -> BTreeSet<VarValPair> {
+        self.varset_lits
+            .iter()
+            .flat_map(|x| self.lit_to_vars(x))
+            .map(super::PuzLit::varval)
+            .collect()
+    }
+
+    /// Given a collection of Lits representing both direct and ordered
+    /// representations, collect them into a collection of `VarValPair`s
+    #[must_use]
+    pub fn direct_or_ordered_lit_to_varvalpair(&self, lit: &Lit) -> BTreeSet<VarValPair> {
+        self.scope_cache.varvals.get_or_insert_with(lit, || {
+            self.direct_or_ordered_lit_to_varvalpair_uncached(lit)
+        })
+    }
+
+    fn direct_or_ordered_lit_to_varvalpair_uncached(&self, lit: &Lit) -> BTreeSet<VarValPair> {
+        let direct_lits = self.invlitmap.get(lit).cloned().unwrap_or_default();
+
+        let order_lits = if let Some(var) = self.inv_order_encoding_map.get(lit) {
+            self.domainmap
+                .get(var)
+                .unwrap()
+                .iter()
+                .map(|&d| VarValPair::new(var, d))
+                .collect_vec()
+        } else {
+            vec![]
+        };
+
+        direct_lits
+            .into_iter()
+            .map(|x| x.varval())
+            .chain(order_lits)
+            .collect()
+    }
+
+    #[must_use]
+    pub fn has_facts(&self) -> bool {
+        !self.eprime.reveal.is_empty()
+    }
+
+    #[must_use]
+    pub fn constraints(&self) -> BTreeSet<String> {
+        self.invconset.keys().cloned().collect()
+    }
+
+    /// The `VarValPair`s the constraint `con` mentions. These are cached, as
+    /// drawing a step asks for the scope of each of its constraints.
+    #[must_use]
+    pub fn constraint_scope(&self, con: &String) -> Arc<BTreeSet<VarValPair>> {
+        let lit = self.invconset.get(con).expect("IE: Bad constraint name");
+
+        self.scope_cache
+            .scopes
+            .get_or_insert_with(lit, || Arc::new(self.constraint_scope_uncached(con)))
+    }
+
+    /// As [`PuzzleParse::constraint_scope`], without the cache. This is for
+    /// going through every constraint once, which on a big puzzle would
+    /// only push out the scopes worth keeping.
+    #[must_use]
+    pub(crate) fn constraint_scope_uncached(&self, con: &String) -> BTreeSet<VarValPair> {
+        let lit = self.invconset.get(con).expect("IE: Bad constraint name");
+
+        let lits = self.varlits_in_con.get(lit).expect("IE: Bad constraint");
+        lits.iter()
+            .flat_map(|l| self.direct_or_ordered_lit_to_varvalpair_uncached(l))
+            .collect()
+    }
+
+    /// Forgets the cached results of [`PuzzleParse::constraint_scope`] and
+    /// [`PuzzleParse::direct_or_ordered_lit_to_varvalpair`]. This must be
+    /// called after changing the maps they are worked out from.
+    pub fn clear_scope_cache(&self) {
+        self.scope_cache.scopes.clear();
+        self.scope_cache.varvals.clear();
+    }
+
+    /// The name of the `$#CON` variable a constraint description belongs to.
+    #[must_use]
+    pub fn constraint_name(&self, con: &String) -> Option<String> {
+        let lit = self.invconset.get(con)?;
+        self.invlitmap
+            .get(lit)?
+            .first()
+            .map(|p| p.var().name().clone())
+    }
+
+    /// The weight of the constraint `lit`, from its `$#CONWEIGHT`, or 1 if
+    /// it has none. See [`EPrimeAnnotations::con_weights`].
+    #[must_use]
+    pub fn constraint_weight(&self, lit: &Lit) -> f64 {
+        self.invlitmap
+            .get(lit)
+            .and_then(|p| p.first())
+            .and_then(|p| self.eprime.con_weights.get(p.var().name()))
+            .copied()
+            .unwrap_or(1.0)
+    }
+
+    /// The Essence' expression behind a constraint description, see
+    /// [`EPrimeAnnotations::con_exprs`].
+    #[must_use]
+    pub fn constraint_expression(&self, con: &String) -> Option<&String> {
+        self.eprime.con_exprs.get(&self.constraint_name(con)?)
+    }
+
+    /// The bipartite graph between constraints and the variables (or
+    /// `VarValPair`s) in their scope.
+    #[must_use]
+    pub fn constraint_graph(&self, nodes: GraphNodes) -> ConstraintGraph {
+        ConstraintGraph::new(self, nodes)
+    }
+
+    pub fn filter_out_constraint(&mut self, con: &str) {
+        assert!(
+            self.eprime.cons.contains_key(con),
+            "Filtered constraint is not present: {con}"
+        );
+        let mut new_conset_lits = BTreeSet::new();
+        for l in &self.conset_lits {
+            let puzvars = self.invlitmap.get(l).unwrap();
+            if !puzvars.iter().all(|p| p.var().name() == con) {
+                new_conset_lits.insert(*l);
+            }
+        }
+        eprintln!(
+            "Removing {}: {} -> {}",
+            con,
+            self.conset_lits.len(),
+            new_conset_lits.len()
+        );
+        self.conset_lits = new_conset_lits;
+    }
+
+    /// Keeps only the constraints of the `$#CON`s named in `names`.
+    pub fn retain_constraints_named(&mut self, names: &BTreeSet<String>) {
+        let invlitmap = &self.invlitmap;
+        self.conset_lits.retain(|l| {
+            invlitmap
+                .get(l)
+                .is_some_and(|puzvars| puzvars.iter().any(|p| names.contains(p.var().name())))
+        });
+    }
+
+    /// Keeps only the constraints with one of `cells` (the indices of a
+    /// variable) in their scope.
+    pub fn retain_constraints_touching(&mut self, cells: &BTreeSet<Vec<i64>>) {
+        let touching: BTreeSet<Lit> = self
+            .conset_lits
+            .iter()
+            .copied()
+            .filter(|con| {
+                self.varlits_in_con.get(con).is_some_and(|lits| {
+                    lits.iter()
+                        .flat_map(|l| self.direct_or_ordered_lit_to_varvalpair(l))
+                        .any(|vvp| cells.contains(vvp.var().indices()))
+                })
+            })
+            .collect();
+        self.conset_lits = touching;
+    }
+
+    #[must_use]
+    pub fn get_matrix_indices(&self, var: &str) -> Option<Vec<i64>> {
+        let mut domain: Option<Vec<i64>> = None;
+        for key in self.domainmap.keys() {
+            if key.name() == var {
+                let indices = key.indices();
+                if let Some(mut current_domain) = domain {
+                    for i in 0..current_domain.len() {
+                        current_domain[i] = max(current_domain[i], indices[i]);
+                    }
+                    domain = Some(current_domain);
+                } else {
+                    domain = Some(indices.clone());
+                }
+            }
+        }
+        domain
+    }
+}
+
+/// A name declared by a `$#` annotation which cannot be used. Names may be
+/// prefixes of each other (such as `row` and `row_sum`), as savilerow's names
+/// are split at the indices on their end, but must be unambiguous once split.
+#[derive(Error, Clone, Debug, PartialEq, Eq)]
+pub enum NameError {
+    #[error("{0} defined twice")]
+    Duplicate(String),
+    #[error("{0} ends with what savilerow would read as an index, such as _00001")]
+    IndexSuffix(String),
+    #[error("{0} is named like savilerow's auxiliary variables, which are ignored")]
+    SavileRowAux(String),
+}
+
+/// Records that `name` has been declared, checking it can be told apart from
+/// the names savilerow generates.
+fn declare_name(all_names: &mut HashSet<String>, name: &str) -> Result<(), NameError> {
+    if parsing::is_savile_row_aux(name) {
+        return Err(NameError::SavileRowAux(name.to_owned()));
+    }
+    if parsing::parse_savile_row_name(name)
+        .is_ok_and(|var| var.is_some_and(|var| !var.indices().is_empty()))
+    {
+        return Err(NameError::IndexSuffix(name.to_owned()));
+    }
+    if !all_names.insert(name.to_owned()) {
+        return Err(NameError::Duplicate(name.to_owned()));
+    }
+    Ok(())
+}
+
+/// Whether `mask` (as from [`EPrimeAnnotations::mask`]) marks the position
+/// with (1-indexed) `indices` as not being a cell. Positions outside the mask
+/// are cells.
+#[must_use]
+pub fn in_mask(mask: &[Vec<bool>], indices: &[i64]) -> bool {
+    let [i, j] = indices else {
+        return false;
+    };
+    let (Ok(i), Ok(j)) = (usize::try_from(*i - 1), usize::try_from(*j - 1)) else {
+        return false;
+    };
+    mask.get(i)
+        .and_then(|row| row.get(j))
+        .copied()
+        .unwrap_or(false)
+}
+
+struct ParsedEprimeData {
+    vars: BTreeSet<String>,
+    auxvars: BTreeSet<String>,
+    cons: BTreeMap<String, String>,
+    con_exprs: BTreeMap<String, String>,
+    factvars: BTreeMap<String, String>,
+    kind: Option<String>,
+    vocabulary: Vocabulary,
+    con_weights: BTreeMap<String, f64>,
+    grid_map: Option<GridMapSpec>,
+}
+
+fn parse_eprime_file(in_path: &PathBuf) -> anyhow::Result<ParsedEprimeData> {
+    info!(target: "parser", "reading DIMACS {:?}", in_path);
+
+    let mut vars: BTreeSet<String> = BTreeSet::new();
+    let mut puzzle: BTreeSet<String> = BTreeSet::new();
+
+    let mut auxvars: BTreeSet<String> = BTreeSet::new();
+
+    let mut cons: BTreeMap<String, String> = BTreeMap::new();
+
+    let mut factvars: BTreeMap<String, String> = BTreeMap::new();
+
+    let mut kind: Option<String> = None;
+
+    let mut vocabulary = Vocabulary::default();
+
+    let mut con_weights: BTreeMap<String, f64> = BTreeMap::new();
+
+    let mut grid_map: Option<GridMapSpec> = None;
+
+    let conmatch = Regex::new(r#"\$#CON (.*) "(.*)" *$"#).unwrap();
+    let namematch = Regex::new(r#"^\$#NAME (\S+) "(.*)" *$"#).unwrap();
+    let valuematch = Regex::new(r#"^\$#VALUE (\S+) (-?\d+) "(.*)" *$"#).unwrap();
+
+    let file = File::open(in_path)?;
+    let reader = io::BufReader::new(file);
+
+    let mut all_names = HashSet::new();
+
+    for line in reader.lines() {
+        let line = line?;
+
+        if line.contains("$#") {
+            debug!(target: "parser", "line {:?}", line);
+            let parts: Vec<&str> = line.split_whitespace().collect();
+
+            if line.starts_with("$#VAR") {
+                let v = parts[1].to_string();
+                info!(target: "parser", "Found VAR: '{}'", v);
+
+                declare_name(&mut all_names, &v)?;
+
+                vars.insert(v);
+            } else if line.starts_with("$#PUZZLE") {
+                let v = parts[1].to_string();
+                info!(target: "parser", "Found PUZZLE: '{}'", v);
+
+                declare_name(&mut all_names, &v)?;
+
+                puzzle.insert(v);
+            } else if line.starts_with("$#CONWEIGHT ") {
+                if parts.len() != 3 {
+                    bail!("Invalid format, should be $#CONWEIGHT <con> <weight> : {line}");
+                }
+                let con = parts[1].to_owned();
+                if !cons.contains_key(&con) {
+                    bail!("{con} from a CONWEIGHT must first be defined as a CON");
+                }
+                let weight: f64 = parts[2]
+                    .parse()
+                    .with_context(|| format!("Invalid weight in: {line}"))?;
+                if !(weight.is_finite() && weight > 0.0) {
+                    bail!("The weight in a CONWEIGHT must be positive: {line}");
+                }
+                info!(target: "parser", "Found CONWEIGHT: '{}' {}", con, weight);
+                safe_insert(&mut con_weights, con, weight)?;
+            } else if line.starts_with("$#CON") {
+                info!(target: "parser", "{}", line);
+                let captures = conmatch
+                    .captures(&line)
+                    .unwrap_or_else(|| panic!("Broken line: {line}"));
+
+                let con_name = captures.get(1).unwrap().as_str().to_string();
+                let con_value = captures.get(2).unwrap().as_str().to_string();
+
+                info!(target: "parser", "Found CON: '{}' '{}'", con_name, con_value);
+
+                declare_name(&mut all_names, &con_name)?;
+
+                safe_insert(&mut cons, con_name, con_value)?;
+            } else if line.starts_with("$#AUX") {
+                let v = parts[1].to_string();
+                info!(target: "parser", "Found Aux VAR: '{}'", v);
+
+                declare_name(&mut all_names, &v)?;
+
+                auxvars.insert(v);
+            } else if line.starts_with("$#KIND") {
+                let v = parts[1].to_string();
+                if kind.is_some() {
+                    bail!("Cannot have two 'KIND' statements");
+                }
+                kind = Some(v);
+            } else if line.starts_with("$#REVEAL ") {
+                if parts.len() != 3 {
+                    bail!(format!(
+                        "Invalid format, should be $#REVEAL <orig> <reveal> : {line} > {parts:?}"
+                    ));
+                }
+
+                let key = parts[1].to_owned();
+                let value = parts[2].to_owned();
+
+                if !vars.contains(&key) {
+                    bail!(format!(
+                        "{key} from a REVEAL must be first be defined as a VAR"
+                    ));
+                }
+
+                declare_name(&mut all_names, &value)?;
+
+                safe_insert(&mut factvars, key, value)?;
+            } else if line.starts_with("$#GRIDMAP ") {
+                if parts.len() != 5 {
+                    bail!(
+                        "Invalid format, should be $#GRIDMAP <var> <width> <height> <order> : {line}"
+                    );
+                }
+                let var = parts[1].to_owned();
+                if !vars.contains(&var) {
+                    bail!("{var} from a GRIDMAP must first be defined as a VAR");
+                }
+                let order = match parts[4] {
+                    "row" => GridOrder::Row,
+                    "column" => GridOrder::Column,
+                    _ => bail!("The order in a GRIDMAP must be 'row' or 'column': {line}"),
+                };
+                if grid_map.is_some() {
+                    bail!("Cannot have two 'GRIDMAP' statements");
+                }
+                info!(target: "parser", "Found GRIDMAP: '{}' {} {} {:?}", var, parts[2], parts[3], order);
+                grid_map = Some(GridMapSpec {
+                    var,
+                    width: parts[2].to_owned(),
+                    height: parts[3].to_owned(),
+                    order,
+                });
+            } else if line.starts_with("$#NAME ") {
+                let Some(captures) = namematch.captures(&line) else {
+                    bail!("Invalid format, should be $#NAME <var> \"<name>\" : {line}");
+                };
+                let var = captures[1].to_owned();
+                if !vars.contains(&var) {
+                    bail!("{var} from a NAME must first be defined as a VAR");
+                }
+                info!(target: "parser", "Found NAME: '{}' '{}'", var, &captures[2]);
+                safe_insert(&mut vocabulary.names, var, captures[2].to_owned())?;
+            } else if line.starts_with("$#VALUE ") {
+                let Some(captures) = valuematch.captures(&line) else {
+                    bail!("Invalid format, should be $#VALUE <var> <value> \"<word>\" : {line}");
+                };
+                let var = captures[1].to_owned();
+                if !vars.contains(&var) {
+                    bail!("{var} from a VALUE must first be defined as a VAR");
+                }
+                let value: i64 = captures[2].parse()?;
+                info!(target: "parser", "Found VALUE: '{}' {} '{}'", var, value, &captures[3]);
+                safe_insert(
+                    vocabulary.values.entry(var).or_default(),
+                    value,
+                    captures[3].to_owned(),
+                )?;
+            } else {
+                bail!(format!("Do not understand line '{line}'"));
+            }
+        }
+    }
+
+    info!(target: "parser", "Names parsed from ESSENCE': vars: {:?} auxvars: {:?} cons {:?}", vars, auxvars, cons);
+
+    let con_exprs = parsing::constraint_expressions(&fs::read_to_string(in_path)?, cons.keys());
+
+    Ok(ParsedEprimeData {
+        vars,
+        auxvars,
+        cons,
+        con_exprs,
+        factvars,
+        kind,
+        vocabulary,
+        con_weights,
+        grid_map,
+    })
+}
+
+fn parse_eprime(
+    executor: &dyn Executor,
+    in_path: &PathBuf,
+    eprimeparam: &PathBuf,
+) -> anyhow::Result<PuzzleParse> {
+    let parsed_eprime = parse_eprime_file(in_path)?;
+    let params = read_essence_param(executor, eprimeparam)?;
+
+    let mut eprimeparse = PuzzleParse::new_from_eprime(
+        parsed_eprime.vars,
+        parsed_eprime.auxvars,
+        parsed_eprime.cons,
+        parsed_eprime.factvars,
+        params,
+        parsed_eprime.kind,
+    );
+    eprimeparse.eprime.con_exprs = parsed_eprime.con_exprs;
+    eprimeparse.eprime.vocabulary = parsed_eprime.vocabulary;
+    eprimeparse.eprime.con_weights = parsed_eprime.con_weights;
+    eprimeparse.eprime.grid_map = parsed_eprime
+        .grid_map
+        .map(|spec| spec.resolve(&eprimeparse.eprime))
+        .transpose()?;
+
+    Ok(eprimeparse)
+}
+
+/// The clauses of a DIMACS file written by savilerow, and the variables
+/// described in its `c Var` comments.
+struct DimacsContents {
+    cnf: Cnf,
+    litmap: BTreeMap<PuzLit, Lit>,
+    order_encoding_map: BTreeMap<PuzVar, HashSet<Lit>>,
+    inv_order_encoding_map: BTreeMap<Lit, PuzVar>,
+}
+
+/// Reads a DIMACS file in a single pass, one line at a time, building the
+/// clauses straight into the [`Cnf`] used for solving.
+fn read_dimacs_file(in_path: &PathBuf) -> anyhow::Result<DimacsContents> {
+    let dvarmatch = Regex::new(r"c Var '(.*)' direct represents '(.*)' with '(.*)'").unwrap();
+    let ovarmatch = Regex::new(r"c Var '(.*)' order represents '(.*)' with '(.*)'").unwrap();
+
+    let file = File::open(in_path)?;
+    // Every clause takes at least two bytes ("0\n"), which bounds how many
+    // a header can truthfully claim
+    let max_clauses = usize::try_from(file.metadata()?.len() / 2).unwrap_or(usize::MAX);
+    let mut reader = io::BufReader::new(file);
+
+    let mut cnf = Cnf::new();
+    let mut expected_clauses = None;
+    let mut clause = Clause::new();
+
+    let mut litmap = BTreeMap::new();
+    let mut order_encoding_map: BTreeMap<PuzVar, HashSet<Lit>> = BTreeMap::new();
+    let mut inv_order_encoding_map = BTreeMap::new();
+
+    let mut buf = String::new();
+    loop {
+        buf.clear();
+        if reader.read_line(&mut buf)? == 0 {
+            break;
+        }
+        let line = buf.trim_end();
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with('p') {
+            let header: Vec<&str> = line.split_whitespace().collect();
+            let clauses = match header[..] {
+                ["p", "cnf", _, clauses] => clauses.parse::<usize>().ok(),
+                _ => None,
+            };
+            let Some(clauses) = clauses else {
+                bail!("Invalid DIMACS header '{line}'");
+            };
+            cnf = Cnf::with_capacity(clauses.min(max_clauses));
+            expected_clauses = Some(clauses);
+            continue;
+        }
+        if !line.starts_with('c') {
+            if expected_clauses.is_none() {
+                bail!("DIMACS clause before the 'p cnf' header: '{line}'");
+            }
+            for num in line.split_ascii_whitespace() {
+                let num: i32 = num
+                    .parse()
+                    .with_context(|| format!("Invalid literal '{num}' in DIMACS"))?;
+                if num == 0 {
+                    cnf.add_clause(std::mem::take(&mut clause));
+                } else {
+                    clause.add(Lit::from_ipasir(num)?);
+                }
+            }
+            continue;
+        }
+        if line.starts_with("c Var") {
+            let dmatch = dvarmatch.captures(line);
+            let omatch = ovarmatch.captures(line);
+            if !(dmatch.is_some() || omatch.is_some()) {
+                bail!("Failed to parse '{:?}'", line);
+            }
+
+            if let Some(match_) = dmatch {
+                let litval = match_[3].parse::<i64>().unwrap();
+
+                if !parsing::is_savile_row_aux(&match_[1]) && litval != 9_223_372_036_854_775_807 {
+                    let satlit = Lit::from_ipasir(
+                        i32::try_from(litval)
+                            .with_context(|| format!("Number too large: {litval}"))?,
+                    )?;
+                    let varid = crate::problem::util::parsing::parse_savile_row_name(&match_[1])
+                        .with_context(|| {
+                            format!("Failed parsing savile row name {}", &match_[1])
+                        })?;
+                    if let Some(varid) = varid {
+                        let puzlit = PuzLit::new_eq(VarValPair::new(
+                            &varid,
+                            match_[2].parse::<i64>().unwrap(),
+                        ));
+                        safe_insert(&mut litmap, puzlit, satlit)?;
+                    }
+                }
+            } else {
+                let match_ = omatch.unwrap();
+                let litval = match_[3].parse::<i64>().unwrap();
+                info!(target: "parser", "matches: {:?}", match_);
+                if !parsing::is_savile_row_aux(&match_[1]) && litval != 9_223_372_036_854_775_807 {
+                    let satlit = Lit::from_ipasir(i32::try_from(litval)?)?;
+                    let varid = crate::problem::util::parsing::parse_savile_row_name(&match_[1])
+                        .with_context(|| {
+                            format!("Failed parsing savile row name {}", &match_[1])
+                        })?;
+
+                    if let Some(varid) = varid {
+                        order_encoding_map
+                            .entry(varid.clone())
+                            .or_default()
+                            .insert(satlit);
+                        order_encoding_map
+                            .entry(varid.clone())
+                            .or_default()
+                            .insert(-satlit);
+                        if let Some(val) = inv_order_encoding_map.get(&satlit) {
+                            if *val != varid {
+                                bail!("{} used for two variables: {} {}", satlit, val, varid);
+                            }
+                        }
+                        safe_insert(&mut inv_order_encoding_map, satlit, varid.clone())?;
+                        safe_insert(&mut inv_order_encoding_map, -satlit, varid.clone())?;
+                    }
+                }
+            }
+        }
+    }
+
+    if !clause.is_empty() {
+        bail!("DIMACS ends part way through a clause");
+    }
+    match expected_clauses {
+        None => bail!("DIMACS has no 'p cnf' header"),
+        Some(expected) if expected != cnf.len() => bail!(
+            "DIMACS header promises {expected} clauses, but there are {}",
+            cnf.len()
+        ),
+        Some(_) => {}
+    }
+
+    Ok(DimacsContents {
+        cnf,
+        litmap,
+        order_encoding_map,
+        inv_order_encoding_map,
+    })
+}
+
+fn update_puzzle_parse_with_maps(
+    dimacs: &mut PuzzleParse,
+    litmap: BTreeMap<PuzLit, Lit>,
+    order_encoding_map: BTreeMap<PuzVar, HashSet<Lit>>,
+    inv_order_encoding_map: BTreeMap<Lit, PuzVar>,
+) -> anyhow::Result<()> {
+    // Update litmap
+    dimacs.litmap = litmap;
+
+    // Update order encoding maps
+    dimacs.order_encoding_map = order_encoding_map;
+    dimacs.inv_order_encoding_map = inv_order_encoding_map;
+
+    // Rebuild order_encoding_all_lits from order_encoding_map
+    dimacs.order_encoding_all_lits = BTreeSet::new();
+    for lits in dimacs.order_encoding_map.values() {
+        for &lit in lits {
+            dimacs.order_encoding_all_lits.insert(lit);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn parse_essence(eprimein: &Path, eprimeparamin: &Path) -> anyhow::Result<PuzzleParse> {
+    parse_essence_with(&SystemExecutor, eprimein, eprimeparamin)
+}
+
+/// As [`parse_essence`], running conjure and savilerow with `executor`.
+pub fn parse_essence_with(
+    executor: &dyn Executor,
+    eprimein: &Path,
+    eprimeparamin: &Path,
+) -> anyhow::Result<PuzzleParse> {
+    //let mut litmap = BTreeMap::new();
+    //let mut varlist = Vec::new();
+
+    let tdir = TempDir::new().unwrap();
+
+    // Inputs may be gzip or zstd compressed, the external tools need them uncompressed
+    let eprime = copy_decompressed(eprimein, tdir.path())?;
+    let eprimeparam = copy_decompressed(eprimeparamin, tdir.path())?;
+
+    info!("Parsing Essence in TempDir: {tdir:?}");
+
+    let finaleprime: PathBuf;
+    let finaleprimeparam: PathBuf;
+
+    info!(target: "parser", "Handling {:?}", eprime);
+
+    // Check if file extension is .essence
+    let is_essence = eprime.extension().is_some_and(|ext| ext == "essence");
+
+    // If input is essence, translate to essence' for savilerow
+    if is_essence {
+        info!(target: "parser", "Running {:?} {:?} through conjure", eprime, eprimeparam);
+        let args = [
+            "solve".into(),
+            "-o".into(),
+            ".".into(),
+            eprime.file_name().unwrap().to_owned(),
+            eprimeparam.file_name().unwrap().to_owned(),
+        ];
+
+        if let Err(e) = ProgramRunner::run_with(executor, "conjure", &args, tdir.path()) {
+            return Err(failed_run(tdir, e));
+        }
+
+        finaleprime = tdir.path().join("model000001.eprime");
+
+        let option_param = fs::read_dir(tdir.path())
+            .unwrap()
+            .filter_map(Result::ok)
+            .find(|d| d.path().extension().and_then(|s| s.to_str()) == Some("param"))
+            .map(|d| d.path());
+
+        if let Some(fname) = option_param {
+            finaleprimeparam = fname;
+        } else {
+            bail!("Could not find 'param' file generated by SavileRow");
+        }
+    } else {
+        finaleprime = eprime.clone();
+        finaleprimeparam = eprimeparam.clone();
+    }
+
+    info!(target: "parser", "Running savilerow on {:?} {:?}", finaleprime, finaleprimeparam);
+
+    let args = [
+        "-in-eprime".into(),
+        finaleprime.file_name().unwrap().to_owned(),
+        "-in-param".into(),
+        finaleprimeparam.file_name().unwrap().to_owned(),
+        "-sat-output-mapping".into(),
+        "-sat".into(),
+        "-sat-family".into(),
+        "lingeling".into(),
+        "-S0".into(),
+        "-O0".into(),
+        "-reduce-domains".into(),
+        "-aggregate".into(),
+    ];
+
+    if let Err(e) = ProgramRunner::run_with(executor, "savilerow", &args, tdir.path()) {
+        return Err(failed_run(tdir, e).context(
+            "savilerow failed. The most likely reason for this is your file is malformed.",
+        ));
+    }
+
+    let original_input_path = PathBuf::from(&eprime);
+
+    // Need to put '.dimacs' on the end in this slightly horrible way.
+    let in_dimacs_path = PathBuf::from(finaleprimeparam.to_str().unwrap().to_owned() + ".dimacs");
+
+    let mut eprimeparse = parse_eprime(executor, &original_input_path, &finaleprimeparam)?;
+
+    load_dimacs(&in_dimacs_path, &mut eprimeparse)?;
+
+    forget(tdir);
+
+    Ok(eprimeparse)
+}
+
+/// Cleans up after a tool failed in `tdir`. The files are kept to help work
+/// out what went wrong, if [`keep_failed_runs`] is set.
+fn failed_run(tdir: TempDir, mut e: anyhow::Error) -> anyhow::Error {
+    if keep_failed_runs() {
+        forget(tdir);
+    } else if let Some(failure) = e.downcast_mut::<ToolFailure>() {
+        failure.forget_artifacts();
+    }
+    e
+}
+
+/// Builds a [`PuzzleParse`] from an ESSENCE' model, its parameters and the DIMACS
+/// savilerow produced for them, without running any external tools.
+///
+/// The parameters must be JSON in the format produced by `conjure pretty
+/// --output-format json`. This is intended for fixed puzzles, where the
+/// DIMACS can be generated once and stored.
+pub fn parse_eprime_with_dimacs(
+    eprimein: &PathBuf,
+    paramjson: &PathBuf,
+    dimacs: &PathBuf,
+) -> anyhow::Result<PuzzleParse> {
+    let parsed_eprime = parse_eprime_file(eprimein)?;
+    let file = fs::File::open(paramjson)
+        .with_context(|| format!("Failed to open param file {paramjson:?}"))?;
+    let params =
+        serde_json::from_reader(BufReader::new(file)).context("Failed reading json param file")?;
+
+    let mut eprimeparse = PuzzleParse::new_from_eprime(
+        parsed_eprime.vars,
+        parsed_eprime.auxvars,
+        parsed_eprime.cons,
+        parsed_eprime.factvars,
+        params,
+        parsed_eprime.kind,
+    );
+    eprimeparse.eprime.con_exprs = parsed_eprime.con_exprs;
+    eprimeparse.eprime.vocabulary = parsed_eprime.vocabulary;
+    eprimeparse.eprime.con_weights = parsed_eprime.con_weights;
+    eprimeparse.eprime.grid_map = parsed_eprime
+        .grid_map
+        .map(|spec| spec.resolve(&eprimeparse.eprime))
+        .transpose()?;
+
+    load_dimacs(dimacs, &mut eprimeparse)?;
+
+    Ok(eprimeparse)
+}
+
+fn load_dimacs(in_dimacs_path: &PathBuf, eprimeparse: &mut PuzzleParse) -> anyhow::Result<()> {
+    let contents = read_dimacs_file(in_dimacs_path).context("reading dimacs")?;
+
+    eprimeparse.cnf = Some(Arc::new(contents.cnf));
+
+    update_puzzle_parse_with_maps(
+        eprimeparse,
+        contents.litmap,
+        contents.order_encoding_map,
+        contents.inv_order_encoding_map,
+    )?;
+
+    eprimeparse.finalise().context("finalisation of parsing failed. The most likely reason for this is you gave a puzzle which has no solutions!")?;
+
+    Ok(())
+}
+
+/// Reads a parameter file, Essence or JSON, in the JSON form conjure
+/// produces. See [`param::parse_essence_param`].
+pub fn read_params(path: &Path) -> anyhow::Result<BTreeMap<String, serde_json::value::Value>> {
+    let params = if path.extension().is_some_and(|e| e == "json") {
+        fs::read_to_string(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|text| Ok(serde_json::from_str(&text)?))
+    } else {
+        read_essence_param(&SystemExecutor, &path.to_path_buf())
+    };
+    params.with_context(|| format!("Failed to read parameters {}", path.display()))
+}
+
+fn read_essence_param(
+    executor: &dyn Executor,
+    eprimeparam: &PathBuf,
+) -> anyhow::Result<BTreeMap<String, serde_json::value::Value>> {
+    if eprimeparam.ends_with(".json") {
+        info!(target: "parser", "Reading params {:?} as json", eprimeparam);
+        let file = fs::File::open(eprimeparam).unwrap();
+        let reader = BufReader::new(file);
+        serde_json::from_reader(reader).context("Failed reading json param file")
+    } else {
+        // Most parameter files are simple enough to read directly, which is
+        // much quicker than starting conjure
+        let params = fs::read_to_string(eprimeparam)
+            .map_err(anyhow::Error::from)
+            .and_then(|text| param::parse_essence_param(&text));
+        match params {
+            Ok(params) => Ok(params),
+            Err(e) => {
+                info!(target: "parser", "Reading params {:?} with conjure, as {:#}", eprimeparam, e);
+                pretty_print_essence(executor, eprimeparam, "json")
+            }
+        }
+    }
+}
+
+fn pretty_print_essence(
+    executor: &dyn Executor,
+    file: &PathBuf,
+    format: &str,
+) -> anyhow::Result<BTreeMap<String, serde_json::value::Value>> {
+    let tdir = TempDir::new().unwrap();
+    let temp_file = tdir.path().join(file.file_name().unwrap());
+    fs::copy(file, &temp_file)?;
+
+    info!(target: "parser", "Pretty printing {:?} as {}", temp_file, format);
+    let args = [
+        "pretty".into(),
+        "--output-format".into(),
+        format.into(),
+        temp_file.file_name().unwrap().to_owned(),
+    ];
+
+    let output = match ProgramRunner::run_with(executor, "conjure", &args, tdir.path()) {
+        Ok(output) => output,
+        Err(e) => {
+            // Keep the files around, to help work out what went wrong
+            forget(tdir);
+            return Err(e.context("Conjure pretty-printing failed"));
+        }
+    };
+
+    serde_json::from_slice(&output.stdout).context("Failed to parse JSON produced by conjure")
+}
+
+#[cfg(test)]
+mod tests {
+
+    use test_log::test;
+
+    use rustsat::instances::Cnf;
+
+    use super::{
+        GridMap, GridOrder, PuzzleParse, parse_essence_with, pretty_print_essence,
+        read_essence_param,
+    };
+    use crate::problem::util::exec::{FakeExecutor, FakeRun, SystemExecutor, ToolFailure};
+    use crate::problem::util::test_utils::sudoku_4x4;
+    use crate::problem::{PuzLit, PuzVar, VarValPair};
+
+    use std::{
+        collections::{BTreeMap, BTreeSet},
+        fs,
+        path::PathBuf,
+    };
+
+    #[test]
+    fn test_parse_eprime_with_dimacs() {
+        let puz = super::parse_eprime_with_dimacs(
+            &PathBuf::from("./tst/sudoku-4x4.eprime"),
+            &PathBuf::from("./tst/sudoku-4x4.json"),
+            &PathBuf::from("./tst/sudoku-4x4.dimacs"),
+        )
+        .unwrap();
+
+        assert_eq!(puz.eprime.kind, Some("Sudoku".to_string()));
+        assert_eq!(
+            puz.eprime.param_vec_vec_i64("fixed").unwrap()[0],
+            vec![1, 0, 0, 4]
+        );
+        assert_eq!(puz.varset_lits.len(), 4 * 4 * 4 * 2);
+        // 4 rows, 4 columns and 4 boxes, each with 6 pairs of cells and 4 values
+        assert_eq!(puz.conset_lits.len(), 12 * 6 * 4);
+
+        let names: BTreeSet<_> = puz
+            .constraints()
+            .iter()
+            .filter_map(|c| puz.constraint_name(c))
+            .collect();
+        assert_eq!(
+            names,
+            BTreeSet::from(["box_alldiff", "con_alldiff", "row_alldiff"].map(str::to_string))
+        );
+
+        assert_eq!(puz.eprime.con_exprs.len(), 3);
+        let con = puz.constraints().into_iter().next().unwrap();
+        let expr = puz.constraint_expression(&con).unwrap();
+        assert!(expr.contains(&puz.constraint_name(&con).unwrap()));
+        assert!(!expr.contains("fixed[i,j]"));
+    }
+
+    #[test]
+    fn test_at_most_one_vars() {
+        let puz = sudoku_4x4();
+        assert_eq!(puz.at_most_one_vars.len(), 16);
+
+        // Without the clause saying a cell cannot be both 1 and 2
+        let var = PuzVar::new("grid", vec![1, 1]);
+        let lit = |val| !puz.litmap[&PuzLit::new_eq(VarValPair::new(&var, val))];
+        let pair = [lit(1), lit(2)];
+        let cnf: Cnf = puz
+            .cnf
+            .as_ref()
+            .unwrap()
+            .iter()
+            .filter(|c| !(c.len() == 2 && pair.iter().all(|l| c.iter().any(|m| m == l))))
+            .cloned()
+            .collect();
+        let vars = puz.find_at_most_one_vars(&cnf);
+        assert_eq!(vars.len(), 15);
+        assert!(!vars.contains(&var));
+    }
+
+    #[test]
+    fn test_scope_cache() {
+        let mut puz = sudoku_4x4();
+        assert!(puz.scope_cache.scopes.is_empty());
+
+        let cons = puz.constraints();
+        let scopes: Vec<_> = cons.iter().map(|c| puz.constraint_scope(c)).collect();
+        assert_eq!(puz.scope_cache.scopes.len(), cons.len());
+        // Each constraint says two cells in a row, column or box differ on one value
+        assert!(scopes.iter().all(|s| s.len() == 2));
+        assert!(
+            cons.iter()
+                .zip(&scopes)
+                .all(|(c, s)| **s == puz.constraint_scope_uncached(c))
+        );
+        let again: Vec<_> = cons.iter().map(|c| puz.constraint_scope(c)).collect();
+        assert_eq!(scopes, again);
+
+        let lit = *puz.varset_lits.first().unwrap();
+        assert_eq!(
+            puz.direct_or_ordered_lit_to_varvalpair(&lit),
+            puz.direct_or_ordered_lit_to_varvalpair_uncached(&lit)
+        );
+        assert_eq!(puz.scope_cache.varvals.len(), 1);
+
+        // Copies are equal, but do not share the cache
+        let copy = puz.clone();
+        assert_eq!(copy, puz);
+        assert!(copy.scope_cache.scopes.is_empty());
+
+        // Changing what a lit means is seen once the cache is cleared
+        let con = cons.first().unwrap();
+        let con_lit = puz.invconset[con];
+        puz.varlits_in_con.get_mut(&con_lit).unwrap().truncate(1);
+        assert_eq!(puz.constraint_scope(con).len(), 2);
+        puz.clear_scope_cache();
+        assert_eq!(puz.constraint_scope(con).len(), 1);
+    }
+
+    #[test]
+    fn test_parse_prefix_names() {
+        let puz = super::parse_eprime_with_dimacs(
+            &PathBuf::from("./tst/row-sum.eprime"),
+            &PathBuf::from("./tst/row-sum.json"),
+            &PathBuf::from("./tst/row-sum.dimacs"),
+        )
+        .unwrap();
+
+        let names: BTreeSet<_> = puz
+            .constraints()
+            .iter()
+            .filter_map(|c| puz.constraint_name(c))
+            .collect();
+        assert_eq!(
+            names,
+            BTreeSet::from(["row", "row_sum"].map(str::to_string))
+        );
+        assert!(!puz.eprime.con_exprs["row"].contains("row_sum"));
+        assert!(puz.eprime.con_exprs["row_sum"].contains("= 3"));
+
+        // The last cell needs both constraints
+        let solver = crate::problem::solver::PuzzleSolver::new(std::sync::Arc::new(puz)).unwrap();
+        let mut planner = crate::problem::planner::PuzzlePlanner::new(solver);
+        let cons: BTreeSet<String> = planner
+            .quick_solve()
+            .into_iter()
+            .flatten()
+            .flat_map(|(_, cons)| cons)
+            .collect();
+        assert_eq!(
+            cons,
+            BTreeSet::from(
+                [
+                    "the cells in row 1 are all different",
+                    "cells (1,1) and (1,2) add up to 3"
+                ]
+                .map(str::to_string)
+            )
+        );
+    }
+
+    #[test]
+    fn test_read_dimacs_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let dimacs = dir.path().join("test.dimacs");
+        let read = |text: &str| {
+            fs::write(&dimacs, text).unwrap();
+            super::read_dimacs_file(&dimacs)
+        };
+
+        // Clauses can be split over lines, or share them
+        let contents = read(
+            "p cnf 3 3\nc Var 'x_00001' direct represents '1' with '1'\n\n1 -2\n 3 0 -1 0\n2 0\n",
+        )
+        .unwrap();
+        assert_eq!(contents.cnf.len(), 3);
+        assert_eq!(
+            contents.cnf.iter().map(|c| c.len()).collect::<Vec<_>>(),
+            vec![3, 1, 1]
+        );
+        assert_eq!(contents.litmap.len(), 1);
+
+        for (text, error) in [
+            ("p cnf 2 2\n1 2 0\n-1", "part way through a clause"),
+            ("p cnf 2 2\n1 2 0\n", "promises 2 clauses, but there are 1"),
+            // Far more clauses than the file could hold, which must not be
+            // allocated up front
+            (
+                "p cnf 2 1000000000000000000\n1 2 0\n",
+                "promises 1000000000000000000 clauses, but there are 1",
+            ),
+            ("1 2 0\n", "before the 'p cnf' header"),
+            (
+                "c Var 'x_00001' direct represents '1' with '1'\n",
+                "no 'p cnf' header",
+            ),
+            ("p cnf 2\n", "Invalid DIMACS header"),
+            ("p cnf 2 1\n1 x 0\n", "Invalid literal 'x'"),
+        ] {
+            let err = read(text).err().unwrap();
+            assert!(format!("{err:#}").contains(error), "{text}: {err:#}");
+        }
+    }
+
+    #[test]
+    fn test_declare_name() {
+        use super::{NameError, declare_name};
+
+        let mut names = std::collections::HashSet::new();
+        for name in [
+            "row",
+            "row_sum",
+            "rows",
+            "aux",
+            "auxiliary",
+            "aux_1",
+            "grid_1",
+        ] {
+            assert_eq!(declare_name(&mut names, name), Ok(()));
+        }
+        assert_eq!(
+            declare_name(&mut names, "row_sum"),
+            Err(NameError::Duplicate("row_sum".to_string()))
+        );
+        assert_eq!(
+            declare_name(&mut names, "row_00001"),
+            Err(NameError::IndexSuffix("row_00001".to_string()))
+        );
+        assert_eq!(
+            declare_name(&mut names, "aux12"),
+            Err(NameError::SavileRowAux("aux12".to_string()))
+        );
+
+        // Errors can be told apart after passing through anyhow
+        let dir = tempfile::tempdir().unwrap();
+        let eprime = dir.path().join("model.eprime");
+        fs::write(&eprime, "$#VAR grid\n$#CON grid \"twice\"\n").unwrap();
+        let err = super::parse_eprime_file(&eprime).err().unwrap();
+        assert_eq!(
+            err.downcast_ref::<NameError>(),
+            Some(&NameError::Duplicate("grid".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_vocabulary() {
+        use crate::problem::{PuzVar, explain::Fact};
+
+        let dir = tempfile::tempdir().unwrap();
+        let eprime = dir.path().join("model.eprime");
+        fs::write(
+            &eprime,
+            "$#VAR grid\n$#VAR count\n$#NAME grid \"cell ({{index[0]}},{{index[1]}})\"\n\
+             $#VALUE grid 1 \"a mine\"\n$#VALUE grid 0 \"safe\"\n",
+        )
+        .unwrap();
+        let parsed = super::parse_eprime_file(&eprime).unwrap();
+
+        let mut puz = super::PuzzleParse::new_from_eprime(
+            parsed.vars,
+            parsed.auxvars,
+            parsed.cons,
+            parsed.factvars,
+            std::collections::BTreeMap::new(),
+            None,
+        );
+        puz.eprime.vocabulary = parsed.vocabulary;
+
+        let fact = |var: &str, equal, values: Vec<i64>| Fact {
+            var: PuzVar::new(var, vec![2, 3]),
+            equal,
+            values,
+            text: None,
+        };
+        let describe = |f: Fact| puz.eprime.describe_fact(&f);
+        assert_eq!(
+            describe(fact("grid", true, vec![1])).as_deref(),
+            Some("cell (2,3) is a mine")
+        );
+        assert_eq!(
+            describe(fact("grid", false, vec![0, 2])).as_deref(),
+            Some("cell (2,3) is not safe or 2")
+        );
+        assert_eq!(describe(fact("count", true, vec![1])), None);
+        assert_eq!(
+            fact("grid", true, vec![0]).worded(&puz.eprime).to_string(),
+            "cell (2,3) is safe"
+        );
+
+        fs::write(&eprime, "$#VALUE grid 1 \"a mine\"\n").unwrap();
+        let err = super::parse_eprime_file(&eprime).err().unwrap();
+        assert!(err.to_string().contains("must first be defined as a VAR"));
+    }
+
+    #[test]
+    fn test_glyphs() {
+        use crate::problem::{PuzVar, explain::Fact};
+
+        let dir = tempfile::tempdir().unwrap();
+        let eprime = dir.path().join("model.eprime");
+        fs::write(
+            &eprime,
+            "$#VAR grid\n$#VAR mines\n$#VALUE mines 1 \"*\"\n$#VALUE mines 0 \"none\"\n",
+        )
+        .unwrap();
+        let parsed = super::parse_eprime_file(&eprime).unwrap();
+
+        let mut puz = super::PuzzleParse::new_from_eprime(
+            parsed.vars,
+            parsed.auxvars,
+            parsed.cons,
+            parsed.factvars,
+            std::collections::BTreeMap::new(),
+            None,
+        );
+        puz.eprime.vocabulary = parsed.vocabulary;
+
+        let domains: std::collections::BTreeMap<PuzVar, BTreeSet<i64>> = [
+            (PuzVar::new("grid", vec![1, 1]), (1..=16).collect()),
+            (PuzVar::new("mines", vec![1, 1]), [0, 1].into()),
+        ]
+        .into();
+        puz.eprime
+            .vocabulary
+            .find_glyphs(&puz.eprime.vars, &domains);
+        let glyphs = &puz.eprime.vocabulary.glyphs;
+        assert_eq!(glyphs["grid"].len(), 7);
+        assert_eq!(glyphs["grid"][&10], "A");
+        assert_eq!(glyphs["grid"][&16], "G");
+        assert_eq!(glyphs["mines"], [(1, "*".to_owned())].into());
+
+        let fact = |var: &str, equal, values: Vec<i64>| Fact {
+            var: PuzVar::new(var, vec![2, 3]),
+            equal,
+            values,
+            text: None,
+        };
+        assert_eq!(
+            puz.eprime
+                .describe_fact(&fact("grid", true, vec![11]))
+                .as_deref(),
+            Some("grid[2, 3] = B")
+        );
+        assert_eq!(
+            puz.eprime
+                .describe_fact(&fact("grid", false, vec![9, 12]))
+                .as_deref(),
+            Some("grid[2, 3] != 9 or C")
+        );
+        assert_eq!(
+            puz.eprime
+                .describe_fact(&fact("mines", true, vec![1]))
+                .as_deref(),
+            Some("mines[2, 3] is *")
+        );
+
+        // Values which all fit in one digit are drawn as they are
+        let domains: std::collections::BTreeMap<PuzVar, BTreeSet<i64>> =
+            [(PuzVar::new("grid", vec![1, 1]), (1..=9).collect())].into();
+        puz.eprime
+            .vocabulary
+            .find_glyphs(&puz.eprime.vars, &domains);
+        assert!(!puz.eprime.vocabulary.glyphs.contains_key("grid"));
+    }
+
+    #[test]
+    fn test_con_weights() {
+        let dir = tempfile::tempdir().unwrap();
+        let eprime = dir.path().join("model.eprime");
+        let parse = |text: &str| {
+            fs::write(&eprime, text).unwrap();
+            super::parse_eprime_file(&eprime)
+        };
+
+        let parsed = parse("$#VAR grid\n$#CON rows \"rows\"\n$#CONWEIGHT rows 2.5\n").unwrap();
+        assert_eq!(parsed.con_weights, [("rows".to_owned(), 2.5)].into());
+
+        let err = |text| parse(text).err().unwrap().to_string();
+        assert!(err("$#CONWEIGHT rows 2\n").contains("must first be defined as a CON"));
+        assert!(err("$#CON rows \"rows\"\n$#CONWEIGHT rows 0\n").contains("must be positive"));
+        assert!(err("$#CON rows \"rows\"\n$#CONWEIGHT rows heavy\n").contains("Invalid weight"));
+    }
+
+    #[test]
+    fn test_param_index_ranges() {
+        // As conjure writes matrices indexed by int(0..2), int(3..4) and
+        // int(-1..0), and ones with gaps
+        let params = serde_json::json!({
+            "zero": {"0": 5, "1": 6, "2": 7},
+            "offset": {"3": {"-1": 1, "0": 2}, "4": {"-1": 3, "0": null}},
+            "labels": {"0": {"0": "a", "1": "b"}},
+            "paths": {"0": {"0": {"0": 1, "1": 2}}},
+            "gap": {"1": 1, "3": 2},
+            "wide": {"-9223372036854775808": 1, "9223372036854775807": 2},
+        });
+        let puz = PuzzleParse::new_from_eprime(
+            BTreeSet::new(),
+            BTreeSet::new(),
+            BTreeMap::new(),
+            BTreeMap::new(),
+            serde_json::from_value(params).unwrap(),
+            None,
+        );
+        let eprime = &puz.eprime;
+
+        assert_eq!(eprime.param_vec_i64("zero").unwrap(), vec![5, 6, 7]);
+        assert_eq!(
+            eprime.param_vec_string("zero").unwrap(),
+            vec!["5", "6", "7"]
+        );
+        assert_eq!(
+            eprime.param_vec_vec_option_i64("offset").unwrap(),
+            vec![vec![Some(1), Some(2)], vec![Some(3), None]]
+        );
+        assert!(eprime.param_vec_vec_i64("offset").is_err());
+        assert_eq!(
+            eprime.param_vec_vec_string("labels").unwrap(),
+            vec![vec![r#""a""#, r#""b""#]]
+        );
+        assert_eq!(
+            eprime.param_vec_vec_vec_i64("paths").unwrap(),
+            vec![vec![vec![1, 2]]]
+        );
+
+        let err = eprime.param_vec_i64("gap").unwrap_err().to_string();
+        assert!(err.contains("indexed from 1 to 3, with gaps"), "{err}");
+        // Too far apart to subtract, which must not overflow
+        assert!(eprime.param_vec_i64("wide").is_err());
+    }
+
+    #[test]
+    fn test_grid_map() {
+        let dir = tempfile::tempdir().unwrap();
+        let eprime = dir.path().join("model.eprime");
+        let parse = |text: &str| {
+            fs::write(&eprime, text).unwrap();
+            super::parse_eprime_file(&eprime)
+        };
+
+        let parsed = parse("$#VAR cells\n$#GRIDMAP cells 3 n column\n").unwrap();
+        let spec = parsed.grid_map.unwrap();
+        let mut puz = PuzzleParse::new_from_eprime(
+            parsed.vars,
+            parsed.auxvars,
+            parsed.cons,
+            parsed.factvars,
+            [("n".to_owned(), 2.into())].into(),
+            None,
+        );
+        let map = spec.resolve(&puz.eprime).unwrap();
+        assert_eq!((map.width, map.height), (3, 2));
+
+        // Numbered down each column, then along the rows
+        assert_eq!(map.cell(1), Some(vec![1, 1]));
+        assert_eq!(map.cell(2), Some(vec![2, 1]));
+        assert_eq!(map.cell(3), Some(vec![1, 2]));
+        assert_eq!(map.cell(6), Some(vec![2, 3]));
+        assert_eq!(map.cell(7), None);
+        for index in 1..=6 {
+            assert_eq!(map.index(&map.cell(index).unwrap()), Some(index));
+        }
+        assert_eq!(
+            map.cell_of(&PuzVar::new("cells", vec![4])),
+            Some(vec![2, 2])
+        );
+        assert_eq!(map.cell_of(&PuzVar::new("other", vec![4])), None);
+
+        let rows = GridMap {
+            order: GridOrder::Row,
+            ..map.clone()
+        };
+        assert_eq!(rows.cell(3), Some(vec![1, 3]));
+        assert_eq!(rows.cell(4), Some(vec![2, 1]));
+        assert_eq!(
+            rows.reshape(&[1, 2, 3, 4]),
+            [vec![Some(1), Some(2), Some(3)], vec![Some(4), None, None]]
+        );
+
+        // 1-D params are laid out as the grid, and 2-D ones are left alone
+        puz.eprime.params.insert(
+            "fixed".to_owned(),
+            serde_json::json!({"1": 1, "2": 0, "3": 0, "4": 2, "5": 0, "6": 3}),
+        );
+        puz.eprime.grid_map = Some(map);
+        assert_eq!(
+            puz.eprime.param_grid("fixed").unwrap(),
+            [
+                vec![Some(1), Some(0), Some(0)],
+                vec![Some(0), Some(2), Some(3)]
+            ]
+        );
+        assert_eq!(
+            puz.eprime.cell_of(&PuzVar::new("cells", vec![5])),
+            vec![1, 3]
+        );
+        assert_eq!(
+            puz.eprime.cell_of(&PuzVar::new("grid", vec![1, 2])),
+            vec![1, 2]
+        );
+
+        let err = |text| parse(text).err().unwrap().to_string();
+        assert!(err("$#GRIDMAP cells 3 2 row\n").contains("must first be defined as a VAR"));
+        assert!(err("$#VAR cells\n$#GRIDMAP cells 3 2 diagonal\n").contains("'row' or 'column'"));
+        assert!(err("$#VAR cells\n$#GRIDMAP cells 3 row\n").contains("Invalid format"));
+    }
+
+    #[test]
+    fn test_parse_essence_binairo() {
+        let eprime_path = "./tst/binairo.eprime";
+        let eprimeparam_path = "./tst/binairo-1.param";
+
+        let puz =
+            crate::problem::util::test_utils::build_puzzleparse(eprime_path, eprimeparam_path);
+
+        assert!(!puz.has_facts());
+
+        assert!(!puz.eprime.has_param("q"));
+
+        assert!(puz.eprime.has_param("n"));
+
+        assert_eq!(puz.eprime.param_i64("n").unwrap(), 6);
+
+        assert!(puz.eprime.has_param("start_grid"));
+
+        assert!(puz.eprime.param_vec_i64("start_grid").is_err());
+
+        let initial: Vec<Vec<i64>> = puz.eprime.param_vec_vec_i64("start_grid").unwrap();
+
+        assert_eq!(initial[0], vec![2, 2, 2, 0, 0, 2]);
+        assert_eq!(initial[5], vec![2, 0, 2, 2, 1, 1]);
+    }
+
+    #[test]
+    fn test_parse_essence_minesweeper() {
+        let eprime_path = "./tst/minesweeper.eprime";
+        let eprimeparam_path = "./tst/minesweeperPrinted.param";
+
+        let puz =
+            crate::problem::util::test_utils::build_puzzleparse(eprime_path, eprimeparam_path);
+
+        assert!(puz.has_facts());
+
+        assert!(!puz.eprime.has_param("q"));
+
+        assert!(puz.eprime.has_param("width"));
+
+        assert_eq!(puz.eprime.param_i64("width").unwrap(), 5);
+    }
+
+    #[test]
+    fn test_filter_constraint_binairo() {
+        let eprime_path = "./tst/binairo.eprime";
+        let eprimeparam_path = "./tst/binairo-1.param";
+
+        let puz =
+            crate::problem::util::test_utils::build_puzzleparse(eprime_path, eprimeparam_path);
+
+        let mut filter1_puz = puz.clone();
+
+        filter1_puz.filter_out_constraint("rowwhite");
+
+        assert_eq!(puz.conset_lits.len() - filter1_puz.conset_lits.len(), 6);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_filter_constraint_fail_binairo() {
+        let eprime_path = "./tst/binairo.eprime";
+        let eprimeparam_path = "./tst/binairo-1.param";
+
+        let puz =
+            crate::problem::util::test_utils::build_puzzleparse(eprime_path, eprimeparam_path);
+
+        let mut filter_fail_puz = puz.clone();
+
+        filter_fail_puz.filter_out_constraint("row");
+    }
+
+    #[test]
+    fn test_parse_essence_little() {
+        let eprime_path = "./tst/little1.eprime";
+        let eprimeparam_path = "./tst/little1.param";
+
+        let puz =
+            crate::problem::util::test_utils::build_puzzleparse(eprime_path, eprimeparam_path);
+
+        assert_eq!(puz.eprime.vars.len(), 1);
+        assert_eq!(puz.eprime.cons.len(), 1);
+        assert_eq!(puz.eprime.auxvars.len(), 0);
+        assert_eq!(puz.eprime.kind, Some("Tiny".to_string()));
+
+        assert!(puz.eprime.has_param("n"));
+
+        assert_eq!(puz.eprime.param_i64("n").unwrap(), 4);
+
+        assert!(puz.eprime.param_bool("n").is_err());
+
+        assert!(!puz.eprime.param_bool("b1").unwrap());
+        assert!(puz.eprime.param_bool("b2").unwrap());
+
+        assert_eq!(puz.eprime.param_vec_i64("l").unwrap(), vec![2, 4, 6, 8]);
+
+        assert_eq!(
+            puz.eprime.param_vec_string("l").unwrap(),
+            vec!["2", "4", "6", "8"]
+        );
+
+        assert_eq!(
+            puz.eprime.param_vec_vec_string("l2").unwrap(),
+            vec![vec!["1", "2"], vec!["3", "4"]]
+        );
+
+        assert_eq!(
+            puz.eprime.param_vec_string("lb").unwrap(),
+            vec!["false", "false", "true", "false"]
+        );
+
+        assert_eq!(
+            puz.eprime.param_vec_vec_string("lb2").unwrap(),
+            vec![vec!["false", "true"], vec!["true", "false"]]
+        );
+
+        // These next two may become '3' at some point, when we do better
+        // at rejecting useless constraints
+        assert_eq!(puz.conset.len(), 4);
+        assert_eq!(puz.conset_lits.len(), 4);
+        assert_eq!(puz.varset_lits.len(), 4 * 4 * 2); // 4 variables, 4 domain values, 2 pos+neg lits
+        let cons = puz.constraints();
+
+        assert!(puz.conset_lits.iter().all(|l| puz.lit_is_con(l)));
+        assert!(puz.varset_lits.iter().all(|l| !puz.lit_is_con(l)));
+        assert!(puz.conset_lits.iter().all(|l| !puz.lit_is_var(l)));
+        assert!(puz.varset_lits.iter().all(|l| puz.lit_is_var(l)));
+
+        let scopes: Vec<_> = cons.iter().map(|c| (c, puz.constraint_scope(c))).collect();
+
+        insta::assert_debug_snapshot!(scopes);
+    }
+
+    #[test]
+    fn test_parse_sudoku_little() {
+        let eprime_path = "./tst/little-sudoku.eprime";
+        let eprimeparam_path = "./tst/little-sudoku.param";
+
+        let puz =
+            crate::problem::util::test_utils::build_puzzleparse(eprime_path, eprimeparam_path);
+
+        assert_eq!(puz.eprime.vars.len(), 1);
+        assert_eq!(puz.eprime.cons.len(), 1);
+        assert_eq!(puz.eprime.auxvars.len(), 0);
+        assert_eq!(puz.eprime.kind, Some("Sudoku".to_string()));
+
+        assert!(puz.eprime.has_param("n"));
+
+        assert_eq!(puz.eprime.param_i64("n").unwrap(), 3);
+
+        let cons = puz.constraints();
+
+        let scopes: Vec<_> = cons.iter().map(|c| (c, puz.constraint_scope(c))).collect();
+
+        insta::assert_debug_snapshot!(scopes);
+    }
+
+    #[test]
+    fn test_parse_sudoku_little_2() {
+        let eprime_path = "./tst/little-sudoku-2.eprime";
+        let eprimeparam_path = "./tst/little-sudoku-2.param";
+
+        let puz =
+            crate::problem::util::test_utils::build_puzzleparse(eprime_path, eprimeparam_path);
+
+        assert_eq!(puz.eprime.vars.len(), 1);
+        assert_eq!(puz.eprime.cons.len(), 1);
+        assert_eq!(puz.eprime.auxvars.len(), 0);
+        assert_eq!(puz.eprime.kind, Some("Sudoku".to_string()));
+
+        assert!(puz.eprime.has_param("n"));
+
+        assert_eq!(puz.eprime.param_i64("n").unwrap(), 3);
+
+        let cons = puz.constraints();
+
+        let scopes: Vec<_> = cons.iter().map(|c| (c, puz.constraint_scope(c))).collect();
+
+        insta::assert_debug_snapshot!(scopes);
+    }
+
+    #[test]
+    fn pretty_print() {
+        let eprime_path = "./tst/binairo.eprime";
+        let parse = pretty_print_essence(&SystemExecutor, &PathBuf::from(eprime_path), "astjson");
+        // Do not want to output the whole tree
+        let k: BTreeSet<_> = parse.unwrap().keys().cloned().collect();
+        insta::assert_debug_snapshot!(k);
+    }
+
+    const SUDOKU_EPRIME: &str = "./tst/sudoku-4x4.eprime";
+    const SUDOKU_PARAM: &str = "./tst/sudoku-4x4.json";
+
+    /// What savilerow writes for the sudoku, given `SUDOKU_PARAM` as its param file
+    fn savilerow_output() -> FakeRun {
+        FakeRun::success("").with_file(
+            "sudoku-4x4.json.dimacs",
+            fs::read("./tst/sudoku-4x4.dimacs").unwrap(),
+        )
+    }
+
+    /// What `conjure pretty` prints for `SUDOKU_PARAM`
+    fn conjure_pretty_output() -> FakeRun {
+        FakeRun::success(fs::read(SUDOKU_PARAM).unwrap())
+    }
+
+    #[test]
+    fn test_parse_essence_fake_tools() {
+        let fake = FakeExecutor::new([savilerow_output(), conjure_pretty_output()]);
+        let puz = parse_essence_with(&fake, SUDOKU_EPRIME.as_ref(), SUDOKU_PARAM.as_ref()).unwrap();
+        assert_eq!(puz.conset_lits.len(), 12 * 6 * 4);
+
+        let tools: Vec<_> = fake.calls().into_iter().map(|(tool, _)| tool).collect();
+        assert_eq!(tools, vec!["savilerow", "conjure"]);
+    }
+
+    #[test]
+    fn test_parse_essence_missing_tool() {
+        let fake = FakeExecutor::new([FakeRun::missing()]);
+        let err =
+            parse_essence_with(&fake, SUDOKU_EPRIME.as_ref(), SUDOKU_PARAM.as_ref()).unwrap_err();
+        assert!(format!("{err:#}").contains("'savilerow' -- have you installed it?"));
+        assert!(err.downcast_ref::<ToolFailure>().is_none());
+    }
+
+    #[test]
+    fn test_parse_essence_tool_fails() {
+        let fake = FakeExecutor::new([FakeRun::failure(1, "Syntax error on line 3")]);
+        let err =
+            parse_essence_with(&fake, SUDOKU_EPRIME.as_ref(), SUDOKU_PARAM.as_ref()).unwrap_err();
+        assert!(err.to_string().contains("most likely reason"));
+
+        let failure = err.downcast_ref::<ToolFailure>().unwrap();
+        assert_eq!(failure.tool, "savilerow");
+        assert_eq!(failure.exit_code, Some(1));
+        assert_eq!(failure.stderr, "Syntax error on line 3");
+        assert!(failure.args.contains(&"sudoku-4x4.eprime".to_owned()));
+        // The directory is deleted, unless asked to keep it
+        assert_eq!(failure.artifacts, None);
+    }
+
+    #[test]
+    fn test_parse_essence_partial_output() {
+        // savilerow claims success, but writes no DIMACS
+        let fake = FakeExecutor::new([FakeRun::success(""), conjure_pretty_output()]);
+        let err =
+            parse_essence_with(&fake, SUDOKU_EPRIME.as_ref(), SUDOKU_PARAM.as_ref()).unwrap_err();
+        assert!(err.to_string().contains("reading dimacs"));
+
+        // conjure is cut off part way through printing the parameters
+        let json = fs::read(SUDOKU_PARAM).unwrap();
+        let fake = FakeExecutor::new([
+            savilerow_output(),
+            FakeRun::success(&json[..json.len() / 2]),
+        ]);
+        let err =
+            parse_essence_with(&fake, SUDOKU_EPRIME.as_ref(), SUDOKU_PARAM.as_ref()).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("Failed to parse JSON produced by conjure")
+        );
+
+        // The DIMACS is truncated
+        let dimacs = fs::read("./tst/sudoku-4x4.dimacs").unwrap();
+        let fake = FakeExecutor::new([
+            FakeRun::success("").with_file("sudoku-4x4.json.dimacs", &dimacs[..dimacs.len() / 2]),
+            conjure_pretty_output(),
+        ]);
+        assert!(parse_essence_with(&fake, SUDOKU_EPRIME.as_ref(), SUDOKU_PARAM.as_ref()).is_err());
+    }
+
+    #[test]
+    fn test_read_essence_param_without_conjure() {
+        let fake = FakeExecutor::new([]);
+        let params = read_essence_param(&fake, &PathBuf::from("./tst/little1.param")).unwrap();
+        assert_eq!(params["n"], 4);
+        assert_eq!(params["l2"]["2"]["1"], 3);
+        assert!(fake.calls().is_empty());
+
+        // Anything more complicated is left to conjure
+        let dir = tempfile::tempdir().unwrap();
+        let param = dir.path().join("set.param");
+        fs::write(&param, "letting s be {1, 2}").unwrap();
+        let fake = FakeExecutor::new([FakeRun::success(r#"{"s": [1, 2]}"#)]);
+        let params = read_essence_param(&fake, &param).unwrap();
+        assert_eq!(params["s"][1], 2);
+        let tools: Vec<_> = fake.calls().into_iter().map(|(tool, _)| tool).collect();
+        assert_eq!(tools, vec!["conjure"]);
+    }
+}
@@ -0,0 +1,4230 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, bail};
+use itertools::Itertools;
+use rand::{SeedableRng, seq::SliceRandom};
+use rand_chacha::ChaCha20Rng;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use rustsat::types::Lit;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{
+    draw::HtmlOptions,
+    json::{CandidateCount, Problem, Puzzle},
+    problem::{
+        PuzVar, VarValPair,
+        musdict::{MusContext, merge_muscontexts},
+    },
+    satcore::{get_solver_calls, get_solver_calls_saved},
+};
+
+use super::{
+    PuzLit,
+    analysis::{NontrivialUsage, difficulty_spikes, mus_constraint_names},
+    explain::{Deduction, EitherOr, Explanation, Fact},
+    groups::GridStats,
+    history::{HistoryConfig, StepHistory},
+    musdict::MusDict,
+    notes::{NoteTarget, Notes},
+    parse::PuzzleParse,
+    propagate::Propagator,
+    repro::ReproReport,
+    rules::RuleCoverage,
+    solver::{
+        CancelFlag, MusConfig, MusSearch, MusSearchResult, PuzzleSolver, SearchBudget, SearchPhase,
+        known::KnownLits, skip_search_error,
+    },
+    symmetry,
+    trace::{OpenCell, SolveSummary, SolveTrace, TraceStep},
+    util::memory::MemoryUsage,
+};
+
+#[derive(Clone)]
+pub struct PlannerConfig {
+    pub mus_config: MusConfig,
+    pub merge_small_threshold: i64,
+    pub skip_small_threshold: i64,
+    pub expand_to_all_deductions: bool,
+    /// When choosing between equally small MUSes, prefer those which deduce an
+    /// assignment (`Some(true)`) or an elimination (`Some(false)`).
+    /// `None` keeps the solver's order.
+    pub prefer_assignments: Option<bool>,
+    /// If set, shrink the MUS chosen for each step with a deletion-based pass,
+    /// spending at most this long on each one.
+    pub minimize_presented_mus: Option<Duration>,
+    /// Layout of the HTML produced by the `*_html*` methods
+    pub html_options: HtmlOptions,
+    /// Overlay the number of values left in each cell (min/max/mean over a
+    /// full solve) on the difficulty output. This solves a copy of the puzzle.
+    pub difficulty_candidate_counts: bool,
+    /// Give up on the difficulty of a literal after searching this long for
+    /// its MUSes, and show it as unknown, so a few hard literals cannot hold
+    /// up the rest. See [`MusConfig::literal_time_limit`].
+    pub difficulty_literal_time: Option<Duration>,
+    /// Before searching for MUSes, look for deductions which need at most one
+    /// constraint using only an unsat core per literal, which is much cheaper.
+    /// All of these are shown together as a single step.
+    pub chained_singles: bool,
+    /// Before using the SAT solver, look for deductions which follow by unit
+    /// propagation from the clauses of at most one constraint, such as a cell
+    /// with a single value left. See [`Propagator`].
+    pub simple_facts: bool,
+    /// When choosing between equally small MUSes, prefer those whose cells
+    /// are close together, as measured by [`PuzzlePlanner::mus_spread`]. The
+    /// spread is multiplied by this weight and added to a penalty of 1 for
+    /// not matching `prefer_assignments`, so weights above 1 put locality
+    /// first. 0 turns this off.
+    pub locality_weight: f64,
+    /// Stop [`PuzzlePlanner::quick_solve`], [`PuzzlePlanner::quick_solve_html_with`]
+    /// and similar methods early, leaving the rest of the puzzle unsolved. See
+    /// [`PuzzlePlanner::quick_solve_until_stopped`] to find out why it stopped.
+    pub stop_condition: Option<StopCondition>,
+    /// Once nothing more can be deduced, end the text and HTML solves with
+    /// the pairs of values of which one must be taken, as found by
+    /// [`PuzzlePlanner::either_or_facts`].
+    pub either_or: bool,
+    /// Start the text, HTML and MUS solves with a [`StepKind::Setup`] step,
+    /// listing what follows from the puzzle before any constraint is used.
+    /// Otherwise these facts are known from the start without being shown.
+    pub setup_step: bool,
+    /// How the weights of constraints given by `$#CONWEIGHT` are used to
+    /// choose between MUSes. Puzzles without weights are not affected.
+    pub constraint_weighting: ConstraintWeighting,
+    /// Merge consecutive steps whose deductions are all about the same
+    /// cells, such as `x != 3` followed by `x = 5`, as long as together they
+    /// use at most this many constraints. This applies to the text, HTML
+    /// and MUS solves, but not to single steps such as
+    /// [`PuzzlePlanner::next_step`].
+    pub merge_same_cell: Option<usize>,
+    /// End the text and HTML solves with a [`SolveSummary`], saying whether
+    /// the puzzle was solved and how much work it took. Traces always have
+    /// one.
+    pub summary: bool,
+    /// How much to prefer explaining the deductions about each cell (the
+    /// indices of a variable, as in [`StopCondition::CellDeduced`]), where
+    /// cells not listed have weight 1. When choosing the next step, the size
+    /// of each MUS found is divided by the weight of its cell, so cells with
+    /// larger weights are explained sooner. Cells with weight 0 are never explained,
+    /// to avoid spoiling them: once nothing else can be deduced, their
+    /// deductions are made silently, without a step.
+    pub cell_weights: BTreeMap<Vec<i64>, f64>,
+    /// Choose randomly, from this seed, between the MUSes which are ranked
+    /// equally best, so the same puzzle can be walked through differently
+    /// each time. Otherwise the first found is shown. The same seed makes
+    /// the same choice between the same MUSes, but which MUSes are found can
+    /// still differ between runs, so replay a [`ReproReport`] to repeat a
+    /// solve exactly.
+    pub variety_seed: Option<u64>,
+    /// How much of the [`StepHistory`] kept by
+    /// [`PuzzlePlanner::record_and_apply_step`] stays in memory
+    pub history: HistoryConfig,
+}
+
+/// See [`PlannerConfig::constraint_weighting`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConstraintWeighting {
+    /// Choose the smallest MUSes, and then the lightest of those
+    #[default]
+    TieBreak,
+    /// Choose the lightest MUSes, so a MUS with several light constraints
+    /// can be preferred to a smaller one with a heavy constraint. Only MUSes
+    /// found by the search are compared, and it stops at the smallest size.
+    WeightedSize,
+}
+
+/// A point at which to stop solving, see [`PlannerConfig::stop_condition`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StopCondition {
+    /// Once the cell with these indices has been given a value
+    CellDeduced(Vec<i64>),
+    /// Once this many steps have been made
+    Steps(usize),
+    /// Before the first step which needs a MUS larger than this
+    DifficultyAbove(usize),
+}
+
+impl std::str::FromStr for StopCondition {
+    type Err = String;
+
+    /// Parses `cell:1,2`, `steps:10` or `difficulty:3`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err =
+            || format!("Invalid stop condition: {s} (expected cell:1,2, steps:N or difficulty:N)");
+        let (kind, value) = s.split_once(':').ok_or_else(err)?;
+        match kind.trim().to_lowercase().as_str() {
+            "cell" => value
+                .split(',')
+                .map(|i| i.trim().parse())
+                .collect::<Result<_, _>>()
+                .map(StopCondition::CellDeduced)
+                .map_err(|_| err()),
+            "steps" => value
+                .trim()
+                .parse()
+                .map(StopCondition::Steps)
+                .map_err(|_| err()),
+            "difficulty" => value
+                .trim()
+                .parse()
+                .map(StopCondition::DifficultyAbove)
+                .map_err(|_| err()),
+            _ => Err(err()),
+        }
+    }
+}
+
+impl std::fmt::Display for StopCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StopCondition::CellDeduced(cell) => write!(f, "cell:{}", cell.iter().join(",")),
+            StopCondition::Steps(n) => write!(f, "steps:{n}"),
+            StopCondition::DifficultyAbove(n) => write!(f, "difficulty:{n}"),
+        }
+    }
+}
+
+/// The weight of one cell, see [`PlannerConfig::cell_weights`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct CellWeight {
+    pub cell: Vec<i64>,
+    pub weight: f64,
+}
+
+impl std::str::FromStr for CellWeight {
+    type Err = String;
+
+    /// Parses `1,2=0.5`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || {
+            format!("Invalid cell weight: {s} (expected the cell then a weight, such as 1,2=0.5)")
+        };
+        let (cell, weight) = s.split_once('=').ok_or_else(err)?;
+        let cell = cell
+            .split(',')
+            .map(|i| i.trim().parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| err())?;
+        let weight: f64 = weight.trim().parse().map_err(|_| err())?;
+        if !weight.is_finite() || weight < 0.0 {
+            return Err(format!(
+                "Invalid cell weight: {s} (weights cannot be negative)"
+            ));
+        }
+        Ok(CellWeight { cell, weight })
+    }
+}
+
+/// Why [`PuzzlePlanner::quick_solve_until_stopped`] stopped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// Nothing was left to deduce, apart from pinned literals
+    Solved,
+    /// The [`PlannerConfig::stop_condition`] was reached
+    Condition(StopCondition),
+    /// Nothing more can be deduced, but these cells are still open, so the
+    /// puzzle has more than one solution and can only be finished by
+    /// guessing. See [`SolveSummary::guess`].
+    Stuck { remaining_cells: Vec<OpenCell> },
+    /// The [`SearchBudget`] given to [`PuzzlePlanner::quick_solve_within`]
+    /// ran out, or was cancelled, during a search
+    OutOfBudget,
+}
+
+/// The steps made by [`PuzzlePlanner::quick_solve_until_stopped`].
+#[derive(Clone, Debug)]
+pub struct StoppedSolve {
+    pub steps: Vec<Vec<MusContext>>,
+    pub reason: StopReason,
+}
+
+impl Default for PlannerConfig {
+    fn default() -> Self {
+        Self {
+            mus_config: MusConfig::default(),
+            merge_small_threshold: 1,
+            skip_small_threshold: 0,
+            expand_to_all_deductions: true,
+            prefer_assignments: None,
+            minimize_presented_mus: None,
+            html_options: HtmlOptions::default(),
+            difficulty_candidate_counts: false,
+            difficulty_literal_time: None,
+            chained_singles: false,
+            simple_facts: false,
+            locality_weight: 0.0,
+            stop_condition: None,
+            either_or: false,
+            setup_step: false,
+            constraint_weighting: ConstraintWeighting::default(),
+            merge_same_cell: None,
+            summary: false,
+            cell_weights: BTreeMap::new(),
+            variety_seed: None,
+            history: HistoryConfig::default(),
+        }
+    }
+}
+
+/// The steps found by [`PuzzlePlanner::solve_for`] before it stopped.
+#[derive(Clone)]
+pub struct PartialTrace {
+    /// The steps found and applied, as returned by [`PuzzlePlanner::quick_solve_muses`]
+    pub steps: Vec<Vec<MusContext>>,
+    /// How many provable literals are left, which is 0 if the solve finished
+    pub remaining: usize,
+    /// The search for the next step, if the time ran out during it
+    pub resume: Option<MusSearch>,
+}
+
+impl PartialTrace {
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        self.remaining == 0
+    }
+}
+
+/// One step of a solve, which can be drawn with
+/// [`PuzzlePlanner::step_problem`] and applied with [`PuzzlePlanner::apply_step`].
+#[derive(Clone, Debug)]
+pub struct Step {
+    /// The MUSes shown together in this step
+    pub muses: Vec<MusContext>,
+    /// How many equally small deductions there were. When this is more than
+    /// `muses.len()`, the step shows one of several it could have shown.
+    pub alternatives: usize,
+    /// How many of the alternatives are the same reasoning as the first MUS
+    /// shown, in different places of the grid (the same kinds of constraint
+    /// and deductions, moved across the grid), counting that one. Only looked
+    /// for when one MUS is shown out of several, and otherwise 1.
+    pub symmetric: usize,
+    pub kind: StepKind,
+}
+
+/// What a [`Step`] shows.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum StepKind {
+    /// Deductions made using constraints
+    #[default]
+    Deduction,
+    /// The facts which follow from the puzzle without using any constraint
+    /// (MUSes of size 0), one MUS per variable. These are known from the
+    /// start, so the step is shown before the first deduction and is not
+    /// applied. See [`PlannerConfig::setup_step`].
+    Setup,
+}
+
+impl Step {
+    /// The literals deduced by this step.
+    #[must_use]
+    pub fn lits(&self) -> Vec<Lit> {
+        self.muses.iter().flat_map(|mc| &mc.lits).copied().collect()
+    }
+}
+
+/// Why a planner restricted by [`PuzzlePlanner::allow_only`] can deduce
+/// nothing more, from [`PuzzlePlanner::stuck`].
+#[derive(Clone, Debug)]
+pub struct StuckReport {
+    /// The easiest step using every constraint, as a teaser of the rule to
+    /// allow next. It is not applied.
+    pub step: Step,
+    pub explanation: Explanation,
+    /// The `$#CON`s the step uses which are not allowed
+    pub disallowed: BTreeSet<String>,
+}
+
+impl StuckReport {
+    fn headline(&self) -> String {
+        format!(
+            "Stuck under the current rule set. The easiest next step also needs {}",
+            self.disallowed.iter().join(", ")
+        )
+    }
+
+    /// The headline, then the step as in [`Explanation::text`].
+    #[must_use]
+    pub fn text(&self) -> String {
+        format!("{}:\n{}", self.headline(), self.explanation.text())
+    }
+
+    #[must_use]
+    pub fn html(&self) -> String {
+        let mut html = format!("<b>{}:</b><br/>", tera::escape_html(&self.headline()));
+        for deduction in &self.explanation.deductions {
+            let _ = write!(
+                html,
+                "{}, because:<br/>{}<br/>",
+                deduction.facts_html(),
+                deduction
+                    .constraints
+                    .iter()
+                    .map(|s| tera::escape_html(s))
+                    .join("<br/>")
+            );
+        }
+        html
+    }
+}
+
+/// The rules a planner is restricted to, see [`PuzzlePlanner::allow_only`].
+struct AllowedRules {
+    names: BTreeSet<String>,
+    /// A solver using every constraint, for [`PuzzlePlanner::stuck`]. Its
+    /// known literals are only brought up to date when it is used.
+    unrestricted: PuzzleSolver,
+}
+
+/// The result of [`PuzzlePlanner::next_step_budgeted`].
+pub enum BudgetedStep {
+    /// The search finished in time
+    Done(Step),
+    /// The search ran out of budget. The problem shows the puzzle as it is.
+    Truncated {
+        problem: Box<Problem>,
+        search: MusSearch,
+    },
+}
+
+/// The `PuzzlePlanner` struct represents a puzzle planner that can be used to solve puzzles.
+pub struct PuzzlePlanner {
+    psolve: PuzzleSolver,
+    config: PlannerConfig,
+    repro: Option<ReproReport>,
+    singles_stats: ChainedSinglesStats,
+    simple_stats: SimpleFactsStats,
+    /// Built the first time [`PlannerConfig::simple_facts`] is used
+    propagator: Option<Arc<Propagator>>,
+    step_stats: StepStats,
+    /// Literals the planner must not deduce, see [`PuzzlePlanner::pin_lit`]
+    pinned: BTreeSet<Lit>,
+    notes: Notes,
+    /// The number of steps applied with [`PuzzlePlanner::apply_step`]
+    steps_taken: usize,
+    /// The step which deduced each known literal, with 0 for those known
+    /// from the start
+    deduced_in: BTreeMap<Lit, usize>,
+    /// Whether the [`StepKind::Setup`] step has been shown
+    setup_shown: bool,
+    tally: SolveTally,
+    allowed: Option<AllowedRules>,
+    /// Kept by [`PuzzlePlanner::incremental_muses_with_larger`]
+    difficulty_cache: Option<DifficultyCache>,
+    /// The steps applied by [`PuzzlePlanner::record_and_apply_step`]
+    history: StepHistory,
+}
+
+/// The MUSes found by [`PuzzlePlanner::incremental_muses_with_larger`], with
+/// the literals which were known when they were found.
+#[derive(Clone)]
+struct DifficultyCache {
+    known: BTreeSet<Lit>,
+    muses: MusDict,
+}
+
+/// What is already known about a literal, from
+/// [`PuzzlePlanner::literal_status`]. The steps are those which made the
+/// deduction, with 0 for the start of the puzzle, or `None` if it is not
+/// known (such as in a restored session).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LiteralStatus {
+    /// No variable can take this value
+    NoSuchLiteral,
+    /// The position is left out of the board by the param `mask`, see
+    /// [`EPrimeAnnotations::mask`](crate::problem::parse::EPrimeAnnotations::mask)
+    NotACell,
+    /// Nothing is known about the literal yet, so it may be deduced
+    Unknown,
+    /// The literal's variable is already known to be `lit`
+    Solved { lit: PuzLit, step: Option<usize> },
+    /// The literal is already known to be false
+    RuledOut { lit: PuzLit, step: Option<usize> },
+}
+
+impl std::fmt::Display for LiteralStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let since = |step: &Option<usize>| match step {
+            Some(0) => " from the start of the puzzle".to_owned(),
+            Some(step) => format!(" since step {step}"),
+            None => String::new(),
+        };
+        match self {
+            LiteralStatus::NoSuchLiteral => write!(f, "There is no such value to explain"),
+            LiteralStatus::NotACell => write!(f, "This is not a cell of the puzzle"),
+            LiteralStatus::Unknown => write!(f, "Nothing is known about this value yet"),
+            LiteralStatus::Solved { lit, step } | LiteralStatus::RuledOut { lit, step } => {
+                write!(f, "It is already known that {lit}{}", since(step))
+            }
+        }
+    }
+}
+
+/// How much work was done by the chained singles fast path, see
+/// [`PlannerConfig::chained_singles`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChainedSinglesStats {
+    /// Steps made only of single constraint deductions
+    pub steps: usize,
+    /// Literals deduced in those steps
+    pub deductions: usize,
+    /// Solver calls made while looking for them, including rounds which found
+    /// nothing. Counted with [`get_solver_calls`], so includes other work in
+    /// the process running at the same time.
+    pub solver_calls: i64,
+}
+
+/// How much work was done without the SAT solver, see
+/// [`PlannerConfig::simple_facts`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SimpleFactsStats {
+    /// Steps found by unit propagation
+    pub steps: usize,
+    /// Literals deduced in those steps
+    pub deductions: usize,
+}
+
+/// The steps chosen by a planner, and the sizes of their MUSes.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StepStats {
+    pub steps: usize,
+    /// How many MUSes of each size (number of constraints) were used
+    pub mus_sizes: BTreeMap<usize, usize>,
+    /// The memory used once each step was chosen, where it can be measured
+    pub memory: Vec<MemoryUsage>,
+}
+
+/// The work done by the steps applied so far, for
+/// [`PuzzlePlanner::solve_summary`].
+#[derive(Clone, Debug)]
+struct SolveTally {
+    started: Instant,
+    solver_calls: i64,
+    steps: usize,
+    /// Deductions using each `$#CON`, by name
+    deductions: BTreeMap<String, usize>,
+    /// The size of the largest MUS applied
+    difficulty: usize,
+    /// The size of the largest MUS of each step
+    sizes: Vec<usize>,
+    nontrivial: NontrivialUsage,
+}
+
+impl SolveTally {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            solver_calls: get_solver_calls(),
+            steps: 0,
+            deductions: BTreeMap::new(),
+            difficulty: 0,
+            sizes: vec![],
+            nontrivial: NontrivialUsage::new(),
+        }
+    }
+
+    fn record(&mut self, puzzle: &PuzzleParse, step: &Step) {
+        if step.kind != StepKind::Deduction || step.muses.is_empty() {
+            return;
+        }
+        self.steps += 1;
+        self.sizes.push(
+            step.muses
+                .iter()
+                .map(MusContext::mus_len)
+                .max()
+                .unwrap_or(0),
+        );
+        for mc in &step.muses {
+            self.difficulty = self.difficulty.max(mc.mus_len());
+            for name in mus_constraint_names(puzzle, mc) {
+                *self.deductions.entry(name).or_default() += 1;
+            }
+        }
+        self.nontrivial.add_step(puzzle, &step.muses);
+    }
+}
+
+impl StepStats {
+    fn record(&mut self, muses: &[MusContext]) {
+        self.steps += 1;
+        for mc in muses {
+            *self.mus_sizes.entry(mc.mus.len()).or_default() += 1;
+        }
+        self.memory.extend(MemoryUsage::sample());
+    }
+
+    /// The most memory resident at once by the last step, if it was
+    /// measured.
+    #[must_use]
+    pub fn peak_memory_kb(&self) -> Option<u64> {
+        self.memory.iter().map(|usage| usage.peak_kb).max()
+    }
+}
+
+type FilterType = Box<dyn Fn(&Lit, &mut PuzzlePlanner) -> bool>;
+
+/// What each step of a quick solve deduced, and with which constraints.
+type QuickSolveSteps = Vec<Vec<(BTreeSet<PuzLit>, Vec<String>)>>;
+
+/// A `PuzzlePlanner` is responsible for finding minimal unsatisfiable subsets (MUSes) in a puzzle
+/// and using them to generate solution steps.
+///
+/// The planner works by identifying the smallest sets of constraints that lead to logical deductions,
+/// allowing it to generate human-understandable solution steps. It can also analyze the difficulty
+/// of different parts of the puzzle and present solutions in various formats including HTML.
+///
+///
+/// The planner can find different types of MUSes:
+/// - Smallish MUSes (more efficient)
+/// - All MUSes including larger ones
+/// - Filtered MUSes that match specific criteria
+///
+/// It can also track the puzzle's state by marking literals as deduced and
+/// checking overall solvability.
+impl PuzzlePlanner {
+    /// Creates a new `PuzzlePlanner` instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `psolve` - The `PuzzleSolver` instance used for solving the puzzle.
+    ///
+    /// # Returns
+    ///
+    /// A new `PuzzlePlanner` instance.
+    #[must_use]
+    pub fn new(psolve: PuzzleSolver) -> PuzzlePlanner {
+        let mut pp = PuzzlePlanner {
+            psolve,
+            config: PlannerConfig::default(),
+            repro: None,
+            singles_stats: ChainedSinglesStats::default(),
+            simple_stats: SimpleFactsStats::default(),
+            propagator: None,
+            step_stats: StepStats::default(),
+            pinned: BTreeSet::new(),
+            notes: Notes::default(),
+            steps_taken: 0,
+            deduced_in: BTreeMap::new(),
+            setup_shown: false,
+            tally: SolveTally::new(),
+            allowed: None,
+            difficulty_cache: None,
+            history: StepHistory::default(),
+        };
+        pp.mark_trivial_lits_as_deduced();
+        pp
+    }
+
+    /// Creates a new `PuzzlePlanner` instance with a custom configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `psolve` - The `PuzzleSolver` instance used for solving the puzzle.
+    /// * `config` - The custom configuration for the planner.
+    ///
+    /// # Returns
+    ///
+    /// A new `PuzzlePlanner` instance with the specified configuration.
+    #[must_use]
+    pub fn new_with_config(psolve: PuzzleSolver, config: PlannerConfig) -> PuzzlePlanner {
+        let mut pp = PuzzlePlanner {
+            psolve,
+            config,
+            repro: None,
+            singles_stats: ChainedSinglesStats::default(),
+            simple_stats: SimpleFactsStats::default(),
+            propagator: None,
+            step_stats: StepStats::default(),
+            pinned: BTreeSet::new(),
+            notes: Notes::default(),
+            steps_taken: 0,
+            deduced_in: BTreeMap::new(),
+            setup_shown: false,
+            tally: SolveTally::new(),
+            allowed: None,
+            difficulty_cache: None,
+            history: StepHistory::default(),
+        };
+        pp.mark_trivial_lits_as_deduced();
+        pp
+    }
+
+    /// Creates an independent planner for the same puzzle, at the same point
+    /// in the solve, so several users can each solve their own copy.
+    /// The copy does not record a reproducibility report.
+    #[must_use]
+    pub fn fork(&self) -> PuzzlePlanner {
+        PuzzlePlanner {
+            psolve: self.psolve.fork(),
+            config: self.config.clone(),
+            repro: None,
+            singles_stats: ChainedSinglesStats::default(),
+            simple_stats: SimpleFactsStats::default(),
+            propagator: self.propagator.clone(),
+            step_stats: StepStats::default(),
+            pinned: self.pinned.clone(),
+            notes: self.notes.clone(),
+            steps_taken: self.steps_taken,
+            deduced_in: self.deduced_in.clone(),
+            setup_shown: self.setup_shown,
+            tally: self.tally.clone(),
+            allowed: self.allowed.as_ref().map(|allowed| AllowedRules {
+                names: allowed.names.clone(),
+                unrestricted: allowed.unrestricted.fork(),
+            }),
+            difficulty_cache: self.difficulty_cache.clone(),
+            history: self.history.fork(),
+        }
+    }
+
+    /// Returns a [`MusDict`] of all minimal unsatisfiable subsets (MUSes) of the puzzle,
+    /// for the literals which steps may explain, see [`PlannerConfig::cell_weights`].
+    pub fn all_smallish_muses(&mut self) -> MusDict {
+        let varlits = self.target_varlits();
+        self.psolve
+            .get_many_vars_small_mus_quick(&varlits, &self.config.mus_config, None)
+    }
+
+    /// Returns a [`MusDict`] of all minimal unsatisfiable subsets (MUSes) of the puzzle.
+    pub fn all_muses_with_larger(&mut self) -> MusDict {
+        let varlits = self.unpinned_varlits();
+        self.psolve
+            .get_many_vars_small_mus_quick(&varlits, &self.difficulty_mus_config(), None)
+    }
+
+    /// The [`MusConfig`] used to find the difficulty of each literal.
+    fn difficulty_mus_config(&self) -> MusConfig {
+        let mut config = self.config.mus_config;
+        config.find_bigger = true;
+        config.literal_time_limit = self.config.difficulty_literal_time;
+        config
+    }
+
+    /// As [`PuzzlePlanner::all_muses_with_larger`], but starting from the
+    /// MUSes found by the last call. A MUS still proves its literal once more
+    /// is known, so only those with a constraint about a newly known literal
+    /// (which may now have a smaller MUS) are dropped and searched for again,
+    /// along with any literals which had none. The result can be larger than
+    /// a fresh search would find, but after one step most MUSes are kept.
+    /// If something has become unknown since the last call, such as after an
+    /// undo, everything is searched for again.
+    pub fn incremental_muses_with_larger(&mut self) -> MusDict {
+        let known: BTreeSet<Lit> = self.get_all_known_lits().iter().copied().collect();
+        let varlits = self.unpinned_varlits();
+
+        let mut muses = match self.difficulty_cache.take() {
+            Some(cache) if cache.known.is_subset(&known) => {
+                let touched = self.constraints_touching(known.difference(&cache.known));
+                let mut muses = cache.muses;
+                muses.retain(|lit, mus_list| {
+                    varlits.contains(lit) && mus_list.iter().all(|mc| mc.mus.is_disjoint(&touched))
+                });
+                muses
+            }
+            _ => MusDict::new(),
+        };
+
+        let missing: BTreeSet<Lit> = varlits
+            .iter()
+            .filter(|lit| !muses.muses().contains_key(lit))
+            .copied()
+            .collect();
+        if !missing.is_empty() {
+            muses.merge(self.psolve.get_many_vars_small_mus_quick(
+                &missing,
+                &self.difficulty_mus_config(),
+                None,
+            ));
+        }
+
+        self.difficulty_cache = Some(DifficultyCache {
+            known,
+            muses: muses.clone(),
+        });
+        muses
+    }
+
+    /// The constraints with a variable of one of `lits` in their scope.
+    fn constraints_touching<'a>(&self, lits: impl Iterator<Item = &'a Lit>) -> BTreeSet<Lit> {
+        let puzzle = self.psolve.puzzleparse();
+        let varvals: BTreeSet<VarValPair> = lits
+            .flat_map(|l| puzzle.invlitmap.get(l).into_iter().flatten())
+            .map(PuzLit::varval)
+            .collect();
+        if varvals.is_empty() {
+            return BTreeSet::new();
+        }
+        puzzle
+            .conset_lits
+            .iter()
+            .copied()
+            .filter(|con| {
+                puzzle.varlits_in_con.get(con).is_some_and(|lits| {
+                    lits.iter()
+                        .flat_map(|l| puzzle.direct_or_ordered_lit_to_varvalpair(l))
+                        .any(|vvp| varvals.contains(&vvp))
+                })
+            })
+            .collect()
+    }
+
+    /// Returns a [`MusDict`] of all minimal unsatisfiable subsets (MUSes) of the puzzle which satisfy a filter.
+    pub fn filtered_muses(&mut self, filter: FilterType) -> MusDict {
+        let varlits = self.unpinned_varlits();
+        let varlits: BTreeSet<_> = varlits.into_iter().filter(|l| filter(l, self)).collect();
+        self.psolve
+            .get_many_vars_small_mus_quick(&varlits, &self.config.mus_config, None)
+    }
+
+    /// Returns a vector of the smallest MUSes of the puzzle.
+    ///
+    /// # Returns
+    ///
+    /// A vector of tuples, where each tuple contains a literal and its corresponding MUS.
+    pub fn smallest_muses(&mut self) -> Vec<MusContext> {
+        //let mut t = QuickTimer::new("smallest_muses");
+        let muses = self.all_smallish_muses();
+        self.smallest_in(&muses)
+    }
+
+    /// The lightest MUS of each literal in `muses` which is as small as the
+    /// smallest one, measured as set by [`PlannerConfig::constraint_weighting`].
+    fn smallest_in(&self, muses: &MusDict) -> Vec<MusContext> {
+        let lightest = muses
+            .muses()
+            .values()
+            .filter_map(|v| {
+                v.iter()
+                    .min_by(|a, b| self.mus_weight(a).total_cmp(&self.mus_weight(b)))
+            })
+            .collect_vec();
+
+        let size = |m: &MusContext| {
+            let size = match self.config.constraint_weighting {
+                ConstraintWeighting::TieBreak => m.mus_len() as f64,
+                ConstraintWeighting::WeightedSize => self.mus_weight(m),
+            };
+            match m.lits.first() {
+                Some(lit) if !self.config.cell_weights.is_empty() => size / self.target_weight(lit),
+                _ => size,
+            }
+        };
+        let Some(min) = lightest.iter().map(|m| size(m)).min_by(f64::total_cmp) else {
+            return vec![];
+        };
+
+        lightest
+            .into_iter()
+            .filter(|m| size(m) <= min)
+            .cloned()
+            .collect()
+    }
+
+    /// The total weight of the constraints in a MUS, see
+    /// [`EPrimeAnnotations::con_weights`](crate::problem::parse::EPrimeAnnotations::con_weights).
+    /// Without any weights, this is its size.
+    #[must_use]
+    pub fn mus_weight(&self, mc: &MusContext) -> f64 {
+        let puzzle = self.psolve.puzzleparse();
+        if puzzle.eprime.con_weights.is_empty() {
+            return mc.mus_len() as f64;
+        }
+        mc.mus.iter().map(|c| puzzle.constraint_weight(c)).sum()
+    }
+
+    /// Returns a vector of the smallest MUSes of the puzzle based on the planner's configuration.
+    ///
+    /// # Returns
+    ///
+    /// A vector of tuples, where each tuple contains a literal and its corresponding MUS.
+    pub fn smallest_muses_with_config(&mut self) -> Vec<MusContext> {
+        self.next_step().muses
+    }
+
+    /// All the deductions which need at most one constraint, found with the
+    /// fast path described in [`PlannerConfig::chained_singles`], merged so
+    /// each constraint appears once.
+    fn single_constraint_muses(&mut self) -> Vec<MusContext> {
+        let calls = get_solver_calls();
+        let varlits = self.target_varlits();
+        let md = self.psolve.get_single_constraint_muses(&varlits);
+
+        // As in the general search, deductions which need no constraints at
+        // all are shown separately from those which need one.
+        let muses = merge_muscontexts(&self.smallest_in(&md));
+
+        self.singles_stats.solver_calls += get_solver_calls() - calls;
+        if !muses.is_empty() {
+            self.singles_stats.steps += 1;
+            self.singles_stats.deductions += muses.iter().map(|m| m.lits.len()).sum::<usize>();
+        }
+        muses
+    }
+
+    /// The work done so far by the chained singles fast path.
+    #[must_use]
+    pub fn chained_singles_stats(&self) -> ChainedSinglesStats {
+        self.singles_stats
+    }
+
+    /// The work done so far without the SAT solver.
+    #[must_use]
+    pub fn simple_facts_stats(&self) -> SimpleFactsStats {
+        self.simple_stats
+    }
+
+    /// The steps this planner has chosen so far. Steps replayed with
+    /// [`PuzzlePlanner::replay`] are not chosen, so are not counted.
+    #[must_use]
+    pub fn step_stats(&self) -> &StepStats {
+        &self.step_stats
+    }
+
+    fn choose_and_record(&mut self, muses: Vec<MusContext>) -> Step {
+        let (alternatives, symmetric, muses) = self.choose_smallest_muses(muses);
+        if !muses.is_empty() {
+            self.step_stats.record(&muses);
+            if let Some(repro) = self.repro.as_mut() {
+                repro.record(self.psolve.puzzleparse(), alternatives, &muses);
+            }
+        }
+        Step {
+            muses,
+            alternatives,
+            symmetric,
+            kind: StepKind::Deduction,
+        }
+    }
+
+    /// The step made of every deduction which needs at most one constraint,
+    /// if [`PlannerConfig::chained_singles`] is set and there are any.
+    fn chained_singles_step(&mut self) -> Option<Step> {
+        if !self.config.chained_singles {
+            return None;
+        }
+        let singles = self.single_constraint_muses();
+        if singles.is_empty() {
+            return None;
+        }
+        self.step_stats.record(&singles);
+        if let Some(repro) = self.repro.as_mut() {
+            repro.record(self.psolve.puzzleparse(), singles.len(), &singles);
+        }
+        Some(Step {
+            alternatives: singles.len(),
+            muses: singles,
+            symmetric: 1,
+            kind: StepKind::Deduction,
+        })
+    }
+
+    /// The step made of the deductions found by unit propagation, if
+    /// [`PlannerConfig::simple_facts`] is set and there are any. As with the
+    /// SAT solver, those which need no constraint come first.
+    fn simple_facts_step(&mut self) -> Option<Step> {
+        if !self.config.simple_facts {
+            return None;
+        }
+        let propagator = self
+            .propagator
+            .get_or_insert_with(|| Arc::new(Propagator::new(self.psolve.puzzleparse())))
+            .clone();
+        let candidates = self.psolve.get_literals_to_try_solving();
+        let md = propagator.deductions(&self.psolve.get_known_lits(), |lit| {
+            candidates.contains(&!lit)
+                && !self.pinned.contains(&lit)
+                && self.target_weight(&lit) > 0.0
+        });
+        if md.is_empty() {
+            return None;
+        }
+
+        let muses = merge_muscontexts(&self.smallest_in(&md));
+        self.simple_stats.steps += 1;
+        self.simple_stats.deductions += muses.iter().map(|m| m.lits.len()).sum::<usize>();
+        self.step_stats.record(&muses);
+        if let Some(repro) = self.repro.as_mut() {
+            repro.record(self.psolve.puzzleparse(), muses.len(), &muses);
+        }
+        Some(Step {
+            alternatives: muses.len(),
+            muses,
+            symmetric: 1,
+            kind: StepKind::Deduction,
+        })
+    }
+
+    /// Given the smallest MUSes, returns how many distinct ones there are,
+    /// how many of them are the same reasoning as the first one chosen (see
+    /// [`Step::symmetric`]), and the ones chosen.
+    fn choose_smallest_muses(&mut self, muses: Vec<MusContext>) -> (usize, usize, Vec<MusContext>) {
+        if muses.is_empty() {
+            return (0, 1, muses);
+        }
+
+        // Merge identical MUSes
+        let mut muses = merge_muscontexts(&muses);
+
+        // The weight and score of each MUS, which are only worked out when
+        // they can differ
+        let mut ranks = vec![(0.0, 0.0); muses.len()];
+        let weight = self.config.locality_weight;
+        let con_weighted = !self.psolve.puzzleparse().eprime.con_weights.is_empty();
+        if con_weighted || self.config.prefer_assignments.is_some() || weight > 0.0 {
+            let grid = if weight > 0.0 { self.grid_extent() } else { 0 };
+            let mut scored = muses
+                .into_iter()
+                .map(|m| {
+                    let mismatch = self
+                        .config
+                        .prefer_assignments
+                        .is_some_and(|prefer| self.mus_deduces_assignment(&m) != prefer);
+                    let spread = if weight > 0.0 {
+                        self.spread_in(&m, grid)
+                    } else {
+                        0.0
+                    };
+                    let score = f64::from(u8::from(mismatch)) + weight * spread;
+                    (self.mus_weight(&m), score, m)
+                })
+                .collect_vec();
+            // Lighter MUSes first, then by score. A stable sort, so ties keep
+            // the solver's order
+            scored.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1)));
+            ranks = scored.iter().map(|&(w, s, _)| (w, s)).collect();
+            muses = scored.into_iter().map(|(_, _, m)| m).collect();
+        }
+
+        if let Some(seed) = self.config.variety_seed {
+            let (first_weight, first_score) = ranks[0];
+            let size = muses[0].mus_len();
+            let ties = ranks
+                .iter()
+                .zip(&muses)
+                .take_while(|((w, s), m)| {
+                    w.total_cmp(&first_weight).is_eq()
+                        && s.total_cmp(&first_score).is_eq()
+                        && m.mus_len() == size
+                })
+                .count();
+            // Sorted first, so the choice does not depend on the order the
+            // solver found them in. Each step has its own stream, so undoing
+            // a step and making it again makes the same choice.
+            let mut rng = ChaCha20Rng::seed_from_u64(seed);
+            rng.set_stream(self.steps_taken as u64);
+            muses[..ties].sort();
+            muses[..ties].shuffle(&mut rng);
+        }
+
+        let candidates = muses.len();
+
+        // Return all MUSes if they are small enough
+        if muses[0].mus_len() as i64 <= self.config.merge_small_threshold {
+            return (candidates, 1, muses);
+        }
+
+        // The first group holds the first MUS, which is the one chosen
+        let symmetric = symmetry::cluster(self.psolve.puzzleparse(), &muses)[0].len();
+
+        // Todo: Try to pick a 'good' MUS, instead of the first one?
+
+        let chosen = match self.config.minimize_presented_mus {
+            Some(budget) => self.psolve.minimize_mus(&muses[0], budget),
+            None => muses[0].clone(),
+        };
+
+        if self.config.expand_to_all_deductions {
+            let mut expanded = self.psolve.get_all_lits_solved_by_mus(&chosen);
+            expanded
+                .lits
+                .retain(|l| !self.pinned.contains(l) && self.target_weight(l) > 0.0);
+            (candidates, symmetric, vec![expanded])
+        } else {
+            (candidates, symmetric, vec![chosen])
+        }
+    }
+
+    /// Returns true if any of the literals deduced by a MUS is an assignment
+    /// (rather than an elimination).
+    fn mus_deduces_assignment(&self, mc: &MusContext) -> bool {
+        mc.lits
+            .iter()
+            .flat_map(|l| self.psolve.puzzleparse().lit_to_vars(l))
+            .any(PuzLit::sign)
+    }
+
+    /// How spread out the cells used by a MUS are: the half perimeter of the
+    /// bounding box of the cells it deduces and the cells its constraints
+    /// mention, as a fraction of that of the whole grid. So 0 means a single
+    /// cell and 1 means the MUS spans the grid. Only variables with two
+    /// indices are counted.
+    pub fn mus_spread(&self, mc: &MusContext) -> f64 {
+        self.spread_in(mc, self.grid_extent())
+    }
+
+    fn spread_in(&self, mc: &MusContext, grid: i64) -> f64 {
+        if grid == 0 {
+            return 0.0;
+        }
+        let puzzle = self.psolve.puzzleparse();
+        let deduced = mc
+            .lits
+            .iter()
+            .flat_map(|l| puzzle.lit_to_vars(l))
+            .map(|p| puzzle.eprime.cell_of(&p.var()));
+        let scope = mc.mus.iter().flat_map(|c| {
+            let scope = puzzle.constraint_scope(puzzle.lit_to_con(c));
+            scope
+                .iter()
+                .map(|vv| puzzle.eprime.cell_of(vv.var()))
+                .collect_vec()
+        });
+        let extent = Self::extent(deduced.chain(scope));
+        extent as f64 / grid as f64
+    }
+
+    /// The half perimeter of the bounding box of every cell in the puzzle.
+    fn grid_extent(&self) -> i64 {
+        let puzzle = self.psolve.puzzleparse();
+        Self::extent(
+            puzzle
+                .all_var_varvals()
+                .into_iter()
+                .map(|vv| puzzle.eprime.cell_of(vv.var())),
+        )
+    }
+
+    fn extent(cells: impl Iterator<Item = Vec<i64>>) -> i64 {
+        let cells = cells.filter_map(|cell| match cell.as_slice() {
+            &[i, j] => Some((i, j)),
+            _ => None,
+        });
+        let Some(((min_i, max_i), (min_j, max_j))) = cells.fold(None, |acc, (i, j)| match acc {
+            None => Some(((i, i), (j, j))),
+            Some(((a, b), (c, d))) => Some(((a.min(i), b.max(i)), (c.min(j), d.max(j)))),
+        }) else {
+            return 0;
+        };
+        (max_i - min_i) + (max_j - min_j)
+    }
+
+    /// Converts a MUS to a user-friendly MUS representation.
+    ///
+    /// # Arguments
+    ///
+    /// * `mus` - The MUS tuple to convert.
+    ///
+    /// # Returns
+    ///
+    /// A tuple containing a set of user-friendly literals and a vector of user-friendly constraints.
+    pub fn mus_to_user_mus(&self, mc: &MusContext) -> (BTreeSet<PuzLit>, Vec<String>) {
+        let lits = &mc.lits;
+        let x = &mc.mus;
+        (
+            lits.iter()
+                .flat_map(|l| self.psolve.puzzleparse().lit_to_vars(l))
+                .cloned()
+                .collect(),
+            x.iter()
+                .map(|c| self.psolve.puzzleparse().lit_to_con(c))
+                .cloned()
+                .collect_vec(),
+        )
+    }
+
+    /// Returns the provable literals which need no 'user' constraints (MUSes of size 0).
+    /// Before any steps are taken, these are the clues given in the puzzle.
+    pub fn trivial_lits(&mut self) -> BTreeSet<Lit> {
+        let varlits = self.psolve.get_provable_varlits().clone();
+        self.psolve.get_many_vars_mus_size_0(&varlits)
+    }
+
+    /// Deal with MUSes of 0 (which mean the puzzle has deduction that can be made without
+    /// any 'user' constraints. These often arise from initial setup.
+    pub fn mark_trivial_lits_as_deduced(&mut self) {
+        for l in self.trivial_lits() {
+            self.mark_lit_as_deduced(&l);
+            self.deduced_in.entry(l).or_insert(0);
+        }
+    }
+
+    /// The [`StepKind::Setup`] step, with one MUS for each variable with
+    /// facts known from the start, or `None` if there are none.
+    #[must_use]
+    pub fn setup_step(&self) -> Option<Step> {
+        let puzzle = self.psolve.puzzleparse();
+        let mut by_var: BTreeMap<PuzVar, BTreeSet<Lit>> = BTreeMap::new();
+        for (&lit, &step) in &self.deduced_in {
+            if step != 0 {
+                continue;
+            }
+            if let Some(puzlit) = puzzle.lit_to_vars(&lit).first() {
+                by_var.entry(puzlit.var()).or_default().insert(lit);
+            }
+        }
+        if by_var.is_empty() {
+            return None;
+        }
+        let muses: Vec<MusContext> = by_var
+            .into_values()
+            .map(|lits| MusContext::new_multi_lit(lits, BTreeSet::new()))
+            .collect();
+        Some(Step {
+            alternatives: muses.len(),
+            muses,
+            symmetric: 1,
+            kind: StepKind::Setup,
+        })
+    }
+
+    /// The setup step, the first time this is called with
+    /// [`PlannerConfig::setup_step`] set.
+    fn take_setup_step(&mut self) -> Option<Step> {
+        if !self.config.setup_step || self.setup_shown {
+            return None;
+        }
+        self.setup_shown = true;
+        self.setup_step()
+    }
+
+    /// Marks a literal as deduced.
+    ///
+    /// This method should only be called if there are no solutions with the negation of the literal.
+    ///
+    /// # Arguments
+    ///
+    /// * `lit` - The literal to mark as deduced.
+    pub fn mark_lit_as_deduced(&mut self, lit: &Lit) {
+        self.psolve.add_known_lit(*lit);
+    }
+
+    /// Marks multiple literals as deduced.
+    ///
+    /// This method should only be called if there are no solutions with the negation of the literals.
+    ///
+    /// # Arguments
+    ///
+    /// * `lits` - A slice of literals to mark as deduced.
+    pub fn mark_lits_as_deduced(&mut self, lits: &[Lit]) {
+        for lit in lits {
+            self.psolve.add_known_lit(*lit);
+        }
+    }
+
+    /// Restores deductions made in an earlier solve of the same puzzle, such
+    /// as a saved session, each with the step it was deduced in. The
+    /// literals are replayed in the order they become provable, and fail if
+    /// any of them cannot be deduced, so a restored position can never make
+    /// the puzzle inconsistent. Nothing is restored if this fails.
+    pub fn restore_deductions(&mut self, lits: &BTreeMap<Lit, usize>) -> anyhow::Result<()> {
+        let mut replay = self.fork();
+        let mut left: BTreeSet<Lit> = lits.keys().copied().collect();
+        loop {
+            let known: BTreeSet<Lit> = replay.get_all_known_lits().iter().copied().collect();
+            left.retain(|lit| {
+                if known.contains(lit) {
+                    replay.deduced_in.entry(*lit).or_insert(lits[lit]);
+                }
+                !known.contains(lit)
+            });
+            if left.is_empty() {
+                break;
+            }
+            let provable = replay.psolve.try_get_provable_varlits()?;
+            let now: Vec<Lit> = left.intersection(provable).copied().collect();
+            if now.is_empty() {
+                let lit = left.first().unwrap();
+                let shown = replay
+                    .psolve
+                    .puzzleparse()
+                    .lit_to_vars(lit)
+                    .first()
+                    .map_or_else(|| lit.to_string(), ToString::to_string);
+                bail!(
+                    "{shown} cannot be deduced from the puzzle, along with {} other restored facts",
+                    left.len() - 1
+                );
+            }
+            for lit in now {
+                replay.mark_lit_as_deduced(&lit);
+            }
+        }
+        self.psolve = replay.psolve;
+        self.deduced_in = replay.deduced_in;
+        Ok(())
+    }
+
+    /// The step which deduced `lit`, with 0 for facts known from the start,
+    /// or `None` if it is not known.
+    #[must_use]
+    pub fn deduced_in(&self, lit: &Lit) -> Option<usize> {
+        self.deduced_in.get(lit).copied()
+    }
+
+    /// Pins `lit`, so the planner will not deduce it, or use any MUS which
+    /// only deduces it, until it is unpinned. This can be used to show a
+    /// different route through a puzzle. Pins are kept as steps are taken.
+    pub fn pin_lit(&mut self, lit: Lit) {
+        self.pinned.insert(lit);
+    }
+
+    /// Allows the planner to deduce `lit` again.
+    pub fn unpin_lit(&mut self, lit: &Lit) {
+        self.pinned.remove(lit);
+    }
+
+    /// Returns the literals pinned with [`PuzzlePlanner::pin_lit`].
+    pub fn pinned_lits(&self) -> &BTreeSet<Lit> {
+        &self.pinned
+    }
+
+    /// Pins the literals for `lit_def` (the indices of a variable, followed
+    /// by a value), both that the variable takes the value and that it does
+    /// not. If any of them is already pinned, they are all unpinned instead.
+    /// Returns true if the literals are now pinned.
+    pub fn toggle_pin_literal(&mut self, lit_def: &[i64]) -> bool {
+        let lits: BTreeSet<Lit> = self
+            .psolve
+            .puzzleparse()
+            .varset_lits
+            .iter()
+            .filter(|lit| {
+                let puzzle = self.psolve.puzzleparse();
+                puzzle.lit_to_vars(lit).iter().any(|p| {
+                    let mut indices = puzzle.eprime.cell_of(&p.var());
+                    indices.push(p.val());
+                    indices == lit_def
+                })
+            })
+            .copied()
+            .collect();
+
+        if lits.iter().any(|l| self.pinned.contains(l)) {
+            for l in &lits {
+                self.pinned.remove(l);
+            }
+            false
+        } else {
+            self.pinned.extend(lits);
+            true
+        }
+    }
+
+    /// The provable literals which are not pinned, which are the ones the
+    /// planner looks for deductions of.
+    fn unpinned_varlits(&mut self) -> BTreeSet<Lit> {
+        self.psolve
+            .get_provable_varlits()
+            .difference(&self.pinned)
+            .copied()
+            .collect()
+    }
+
+    /// The weight of `lit` as the target of a step: the weight in
+    /// [`PlannerConfig::cell_weights`] of the cell it is about, or 1.
+    fn target_weight(&self, lit: &Lit) -> f64 {
+        if self.config.cell_weights.is_empty() {
+            return 1.0;
+        }
+        let puzzle = self.psolve.puzzleparse();
+        puzzle
+            .invlitmap
+            .get(lit)
+            .into_iter()
+            .flatten()
+            .find_map(|p| {
+                self.config
+                    .cell_weights
+                    .get(&puzzle.eprime.cell_of(&p.var()))
+            })
+            .copied()
+            .unwrap_or(1.0)
+    }
+
+    /// The provable literals which steps may explain: those which are
+    /// neither pinned nor about a cell with weight 0 in
+    /// [`PlannerConfig::cell_weights`]. If only such cells are left to
+    /// deduce, their deductions are made silently until something else can
+    /// be deduced, or nothing can.
+    fn target_varlits(&mut self) -> BTreeSet<Lit> {
+        loop {
+            let varlits = self.unpinned_varlits();
+            if self.config.cell_weights.is_empty() {
+                return varlits;
+            }
+            let (targets, excluded): (BTreeSet<Lit>, BTreeSet<Lit>) = varlits
+                .into_iter()
+                .partition(|lit| self.target_weight(lit) > 0.0);
+            if !targets.is_empty() || excluded.is_empty() {
+                return targets;
+            }
+            info!(target: "planner", "silently deducing {} literals of excluded cells", excluded.len());
+            self.mark_lits_as_deduced(&excluded.into_iter().collect_vec());
+        }
+    }
+
+    /// The cells and values which are pinned, for display.
+    fn pinned_varvals(&self) -> BTreeSet<VarValPair> {
+        self.pinned
+            .iter()
+            .flat_map(|l| self.psolve.puzzleparse().lit_to_vars(l))
+            .map(PuzLit::varval)
+            .collect()
+    }
+
+    /// The notes on steps and cells, which are shown by
+    /// [`PuzzlePlanner::step_problem`] and [`PuzzlePlanner::state_problem`].
+    #[must_use]
+    pub fn notes(&self) -> &Notes {
+        &self.notes
+    }
+
+    pub fn notes_mut(&mut self) -> &mut Notes {
+        &mut self.notes
+    }
+
+    /// The number of steps applied so far, which is the number of the step
+    /// most recently shown. Notes on step `steps_taken() + 1` are shown with
+    /// the next step.
+    #[must_use]
+    pub fn steps_taken(&self) -> usize {
+        self.steps_taken
+    }
+
+    /// Sets the number of steps taken, when restoring a position whose
+    /// deductions were marked without [`PuzzlePlanner::apply_step`].
+    pub fn set_steps_taken(&mut self, steps: usize) {
+        self.steps_taken = steps;
+    }
+
+    /// Returns a reference to the vector of all known literals.
+    ///
+    /// This includes literals that have been marked as deduced and literals from 'REVEAL' statements.
+    ///
+    /// # Returns
+    ///
+    /// All known literals, in the order they became known.
+    #[must_use]
+    pub fn get_all_known_lits(&self) -> Vec<Lit> {
+        self.psolve.get_known_lits()
+    }
+
+    /// Solves the puzzle quickly and returns a sequence of steps.
+    ///
+    /// # Returns
+    ///
+    /// A vector of tuples, where each tuple contains a set of user-friendly literals and a vector of user-friendly constraints.
+    pub fn quick_solve(&mut self) -> Vec<Vec<(BTreeSet<PuzLit>, Vec<String>)>> {
+        self.quick_solve_impl(false, None).0
+    }
+
+    /// Solves the puzzle quickly and returns a sequence of steps, printing info on progress as solving runs
+    ///
+    /// # Returns
+    ///
+    /// A vector of tuples, where each tuple contains a set of user-friendly literals and a vector of user-friendly constraints.
+    pub fn quick_solve_with_progress(&mut self) -> Vec<Vec<(BTreeSet<PuzLit>, Vec<String>)>> {
+        self.quick_solve_impl(true, None).0
+    }
+
+    /// As [`PuzzlePlanner::quick_solve`], but each step's search stops once
+    /// `budget` runs out, or its flag is cancelled. The steps made before
+    /// that are still applied, but the result is an error.
+    pub fn quick_solve_within(&mut self, budget: &SearchBudget) -> anyhow::Result<QuickSolveSteps> {
+        let (steps, reason) = self.quick_solve_impl(false, Some(budget));
+        if reason == StopReason::OutOfBudget {
+            bail!("The solve ran out of time after {} steps", steps.len());
+        }
+        Ok(steps)
+    }
+
+    /// Solves the puzzle quickly and returns a sequence of steps, without
+    /// mapping the MUSes to a user-friendly representation.
+    ///
+    /// # Returns
+    ///
+    /// A vector of steps, where each step is a vector of the MUSes used in that step.
+    pub fn quick_solve_muses(&mut self) -> Vec<Vec<MusContext>> {
+        self.quick_solve_muses_impl(false, None).steps
+    }
+
+    /// As [`PuzzlePlanner::quick_solve_muses`], also returning whether the
+    /// puzzle was solved or [`PlannerConfig::stop_condition`] was reached.
+    pub fn quick_solve_until_stopped(&mut self) -> StoppedSolve {
+        self.quick_solve_muses_impl(false, None)
+    }
+
+    /// Whether the stop condition has been reached, before searching for the
+    /// next step.
+    fn stop_before_search(&self, steps: usize) -> bool {
+        match &self.config.stop_condition {
+            Some(StopCondition::Steps(n)) => steps >= *n,
+            Some(StopCondition::CellDeduced(cell)) => self
+                .get_all_known_lits()
+                .iter()
+                .flat_map(|l| self.psolve.lit_to_puzlit(l))
+                .any(|p| p.sign() && self.psolve.puzzleparse().eprime.cell_of(&p.var()) == *cell),
+            Some(StopCondition::DifficultyAbove(_)) | None => false,
+        }
+    }
+
+    /// Whether the stop condition has been reached, given the MUSes of the
+    /// next step.
+    fn stop_before_step(&self, muses: &[MusContext]) -> bool {
+        match &self.config.stop_condition {
+            Some(StopCondition::DifficultyAbove(limit)) => {
+                muses.first().is_some_and(|m| m.mus_len() > *limit)
+            }
+            _ => false,
+        }
+    }
+
+    fn quick_solve_impl(
+        &mut self,
+        progress: bool,
+        budget: Option<&SearchBudget>,
+    ) -> (QuickSolveSteps, StopReason) {
+        let solve = self.quick_solve_muses_impl(progress, budget);
+        let steps = solve
+            .steps
+            .into_iter()
+            .map(|muses| {
+                // Map the 'muses' to a user-friendly representation
+                muses
+                    .iter()
+                    .map(|mus| self.mus_to_user_mus(mus))
+                    .collect_vec()
+            })
+            .collect();
+        (steps, solve.reason)
+    }
+
+    /// The next step, as [`PuzzlePlanner::next_step`], or `None` if `budget`
+    /// ran out before it was found. A cancelled budget also stops steps which
+    /// need no search.
+    fn next_step_in_budget(&mut self, budget: Option<&SearchBudget>) -> Option<Step> {
+        match budget {
+            None => Some(self.next_step()),
+            Some(budget) if budget.cancel.as_ref().is_some_and(CancelFlag::is_cancelled) => None,
+            Some(budget) => match self.next_step_budgeted(budget, None) {
+                BudgetedStep::Done(step) => Some(step),
+                BudgetedStep::Truncated { .. } => None,
+            },
+        }
+    }
+
+    fn quick_solve_muses_impl(
+        &mut self,
+        progress: bool,
+        budget: Option<&SearchBudget>,
+    ) -> StoppedSolve {
+        let mut solvesteps = vec![];
+        // The setup step does not count towards StopCondition::Steps
+        if let Some(setup) = self.take_setup_step() {
+            solvesteps.push(setup.muses);
+        }
+        let setup = solvesteps.len();
+        'litloop: while !self.target_varlits().is_empty() {
+            if self.stop_before_search(solvesteps.len() - setup) {
+                return StoppedSolve {
+                    steps: self.merge_setup_aside(solvesteps, setup),
+                    reason: StopReason::Condition(self.config.stop_condition.clone().unwrap()),
+                };
+            }
+
+            let Some(step) = self.next_step_in_budget(budget) else {
+                return StoppedSolve {
+                    steps: self.merge_setup_aside(solvesteps, setup),
+                    reason: StopReason::OutOfBudget,
+                };
+            };
+            let muses = step.muses;
+
+            if self.stop_before_step(&muses) {
+                return StoppedSolve {
+                    steps: self.merge_setup_aside(solvesteps, setup),
+                    reason: StopReason::Condition(self.config.stop_condition.clone().unwrap()),
+                };
+            }
+
+            for mus in &muses {
+                for lit in &mus.lits {
+                    self.mark_lit_as_deduced(lit);
+                }
+            }
+
+            if !muses.is_empty() && muses[0].mus_len() as i64 <= self.config.skip_small_threshold {
+                continue 'litloop;
+            }
+
+            if progress {
+                eprintln!(
+                    "{} steps, just found {} muses of size {}, {} left, {} solver calls so far ({} saved)",
+                    solvesteps.len(),
+                    muses.len(),
+                    muses[0].mus_len(),
+                    self.unpinned_varlits().len(),
+                    get_solver_calls(),
+                    get_solver_calls_saved(),
+                );
+            } else {
+                info!(target: "planner",
+                    "{} steps, just found {} muses of size {}, {} left, {} solver calls so far ({} saved)",
+                    solvesteps.len(),
+                    muses.len(),
+                    muses[0].mus_len(),
+                    self.unpinned_varlits().len(),
+                    get_solver_calls(),
+                    get_solver_calls_saved(),
+                );
+            }
+            // Add these muses to the solving steps
+            solvesteps.push(muses);
+        }
+        let steps = self.merge_setup_aside(solvesteps, setup);
+        let open = self.open_cells();
+        if self.needs_guess(&open) {
+            info!(target: "planner", "stuck, with {} cells open", open.len());
+            return StoppedSolve {
+                steps,
+                reason: StopReason::Stuck {
+                    remaining_cells: open,
+                },
+            };
+        }
+        info!(target: "planner", "solved!");
+        StoppedSolve {
+            steps,
+            reason: StopReason::Solved,
+        }
+    }
+
+    /// Merges `steps` as in [`PuzzlePlanner::merge_same_cell_steps`], apart
+    /// from the first `setup` of them.
+    fn merge_setup_aside(
+        &self,
+        mut steps: Vec<Vec<MusContext>>,
+        setup: usize,
+    ) -> Vec<Vec<MusContext>> {
+        let rest = steps.split_off(setup);
+        steps.extend(self.merge_same_cell_steps(rest));
+        steps
+    }
+
+    /// The cells of the values deduced by `muses`.
+    fn deduced_cells(&self, muses: &[MusContext]) -> BTreeSet<Vec<i64>> {
+        muses
+            .iter()
+            .flat_map(|mc| &mc.lits)
+            .flat_map(|l| self.psolve.lit_to_puzlit(l))
+            .map(|p| self.psolve.puzzleparse().eprime.cell_of(&p.var()))
+            .collect()
+    }
+
+    /// Whether the step made of `next` can be merged into the step made of
+    /// `muses`, as described in [`PlannerConfig::merge_same_cell`].
+    fn can_merge(&self, muses: &[MusContext], next: &[MusContext]) -> bool {
+        let Some(limit) = self.config.merge_same_cell else {
+            return false;
+        };
+        let cells = self.deduced_cells(muses);
+        if cells.is_empty() || cells != self.deduced_cells(next) {
+            return false;
+        }
+        let constraints: BTreeSet<&Lit> = muses.iter().chain(next).flat_map(|mc| &mc.mus).collect();
+        constraints.len() <= limit
+    }
+
+    /// Merges consecutive steps of a solve whose deductions are all about
+    /// the same cells, as described in [`PlannerConfig::merge_same_cell`].
+    /// Does nothing if that is not set.
+    #[must_use]
+    pub fn merge_same_cell_steps(&self, steps: Vec<Vec<MusContext>>) -> Vec<Vec<MusContext>> {
+        let mut merged: Vec<Vec<MusContext>> = vec![];
+        for step in steps {
+            match merged.last_mut() {
+                Some(last) if self.can_merge(last, &step) => last.extend(step),
+                _ => merged.push(step),
+            }
+        }
+        merged
+    }
+
+    /// Adds the steps following `step` which can be merged into it, as
+    /// described in [`PlannerConfig::merge_same_cell`]. Returns the first
+    /// step which could not be merged, which comes next. Nothing is
+    /// applied. A merged step is not shown as a choice between alternatives.
+    fn merge_following(&mut self, step: &mut Step) -> Option<Step> {
+        if self.config.merge_same_cell.is_none() || step.kind != StepKind::Deduction {
+            return None;
+        }
+        let known = self.psolve.known().clone();
+        let mut following = None;
+        loop {
+            for lit in step.lits() {
+                self.mark_lit_as_deduced(&lit);
+            }
+            if self.target_varlits().is_empty() {
+                break;
+            }
+            let next = self.next_step();
+            if self.stop_before_step(&next.muses) || !self.can_merge(&step.muses, &next.muses) {
+                following = Some(next);
+                break;
+            }
+            step.muses.extend(next.muses);
+            step.alternatives = step.muses.len();
+            step.symmetric = 1;
+        }
+        self.psolve.restore_known(known);
+        following
+    }
+
+    /// Runs the same loop as [`PuzzlePlanner::quick_solve_muses`], but stops
+    /// once `duration` has passed, even in the middle of a step. The steps
+    /// found so far are applied, so calling [`PuzzlePlanner::resume_for`]
+    /// with the returned trace carries on where this stopped.
+    pub fn solve_for(&mut self, duration: Duration) -> PartialTrace {
+        self.solve_until(Instant::now() + duration, None)
+    }
+
+    /// Continues a solve stopped by [`PuzzlePlanner::solve_for`] for up to
+    /// `duration` more. Only the new steps are returned.
+    pub fn resume_for(&mut self, duration: Duration, trace: PartialTrace) -> PartialTrace {
+        self.solve_until(Instant::now() + duration, trace.resume)
+    }
+
+    fn solve_until(&mut self, deadline: Instant, mut resume: Option<MusSearch>) -> PartialTrace {
+        let mut steps = vec![];
+
+        loop {
+            let remaining = self.target_varlits().len();
+            let now = Instant::now();
+            if remaining == 0 || now >= deadline {
+                return PartialTrace {
+                    steps,
+                    remaining,
+                    resume,
+                };
+            }
+
+            let budget = SearchBudget {
+                max_time: Some(deadline - now),
+                max_solver_calls: None,
+                cancel: None,
+            };
+
+            match self.next_step_budgeted(&budget, resume.take()) {
+                BudgetedStep::Done(step) => {
+                    if step.muses.is_empty() {
+                        // Nothing more can be deduced, which only happens if the
+                        // puzzle has no solution
+                        return PartialTrace {
+                            steps,
+                            remaining,
+                            resume: None,
+                        };
+                    }
+                    self.apply_step(&step);
+                    if step.muses[0].mus_len() as i64 > self.config.skip_small_threshold {
+                        steps.push(step.muses);
+                    }
+                }
+                BudgetedStep::Truncated { search, .. } => {
+                    return PartialTrace {
+                        steps,
+                        remaining,
+                        resume: Some(search),
+                    };
+                }
+            }
+        }
+    }
+
+    /// Starts recording the MUSes chosen at each step, so the solve can later
+    /// be reproduced with [`PuzzlePlanner::replay`].
+    pub fn record_repro(&mut self) {
+        self.repro = Some(ReproReport::new());
+    }
+
+    /// Returns the choices recorded since [`PuzzlePlanner::record_repro`], and stops recording.
+    pub fn take_repro_report(&mut self) -> Option<ReproReport> {
+        self.repro.take()
+    }
+
+    /// Repeats a solve recorded in `report`, without any search, returning the
+    /// same steps as [`PuzzlePlanner::quick_solve_muses`]. Each recorded MUS is
+    /// checked before it is used.
+    pub fn replay(&mut self, report: &ReproReport) -> anyhow::Result<Vec<Vec<MusContext>>> {
+        report.check_version()?;
+
+        let mut solvesteps = vec![];
+        for (i, step) in report.steps.iter().enumerate() {
+            let muses = step
+                .muses
+                .iter()
+                .map(|m| m.to_muscontext(self.psolve.puzzleparse()))
+                .collect::<anyhow::Result<Vec<_>>>()
+                .with_context(|| format!("Step {i} of the report"))?;
+
+            for mus in &muses {
+                if !self.psolve.mus_proves_lits(mus) {
+                    bail!("Step {i} of the report does not follow from the steps before it");
+                }
+            }
+
+            for mus in &muses {
+                for lit in &mus.lits {
+                    self.mark_lit_as_deduced(lit);
+                }
+            }
+
+            if !muses.is_empty() && muses[0].mus_len() as i64 > self.config.skip_small_threshold {
+                solvesteps.push(muses);
+            }
+        }
+        Ok(solvesteps)
+    }
+
+    /// Checks the solvability of the current problem state. This can be used
+    /// to both check if a problem is inconsistent, or how much of the problem
+    /// does not have a unique solution
+    ///
+    /// # Returns
+    /// - `Some(i64)`: If the problem is not inconsistent, return the number of literals
+    ///   which are not fixed to a single value.
+    /// - `None`: If the problem is has no solution.
+    pub fn check_solvability(&mut self) -> Option<i64> {
+        while !self.psolve.get_provable_varlits().is_empty() {
+            let lits = self.psolve.get_provable_varlits().clone();
+
+            for l in lits {
+                self.mark_lit_as_deduced(&l);
+            }
+        }
+
+        if self.psolve.is_currently_solvable() {
+            let lits = self.psolve.get_literals_to_try_solving();
+
+            for l in &lits {
+                self.solver().lit_to_puzlit(l);
+            }
+
+            Some(lits.len().try_into().unwrap())
+        } else {
+            None
+        }
+    }
+
+    /// Pairs of values of which one must be taken, when neither can be
+    /// deduced on its own, simplest first. Only two values of the same
+    /// number, in different cells which share a constraint, are paired.
+    /// These are most useful once nothing more can be deduced, which (as
+    /// anything deducible is skipped) only happens in puzzles without a
+    /// unique solution.
+    pub fn either_or_facts(&mut self) -> Vec<EitherOr> {
+        let provable = self.psolve.get_provable_varlits().clone();
+        let puzzle = self.psolve.puzzleparse();
+        let known = self.psolve.known();
+
+        // The undecided values, which are not deducible either way
+        let open: BTreeMap<VarValPair, Lit> = puzzle
+            .varset_lits
+            .iter()
+            .filter(|l| {
+                !known.decides(puzzle, **l) && !provable.contains(l) && !provable.contains(&!**l)
+            })
+            .flat_map(|&l| {
+                puzzle
+                    .lit_to_vars(&l)
+                    .iter()
+                    .filter(|p| p.sign())
+                    .map(move |p| (p.varval(), l))
+            })
+            .collect();
+
+        let mut pairs: BTreeSet<(Lit, Lit)> = BTreeSet::new();
+        for con in &puzzle.conset_lits {
+            let mut by_val: BTreeMap<i64, BTreeSet<Lit>> = BTreeMap::new();
+            for l in puzzle.varlits_in_con.get(con).into_iter().flatten() {
+                for vvp in puzzle.direct_or_ordered_lit_to_varvalpair(l) {
+                    if let Some(&lit) = open.get(&vvp) {
+                        by_val.entry(vvp.val()).or_default().insert(lit);
+                    }
+                }
+            }
+            for lits in by_val.values() {
+                pairs.extend(lits.iter().copied().tuple_combinations::<(Lit, Lit)>());
+            }
+        }
+
+        let order = self.config.mus_config.shrink_order;
+        let mut found: Vec<(Vec<Lit>, Lit, Lit)> = pairs
+            .into_par_iter()
+            .filter_map(|(a, b)| {
+                let mus = skip_search_error(
+                    SearchPhase::EitherOr,
+                    self.psolve.get_either_mus(a, b, order),
+                )??;
+                (!mus.is_empty()).then_some((mus, a, b))
+            })
+            .collect();
+        found.sort_by_key(|(mus, a, b)| (mus.len(), *a, *b));
+
+        let fact = |lit: &Lit| {
+            let puzlit = puzzle
+                .lit_to_vars(lit)
+                .iter()
+                .find(|p| p.sign())
+                .expect("IE: either/or literal has no value");
+            Fact {
+                var: puzlit.var(),
+                equal: true,
+                values: vec![puzlit.val()],
+                text: None,
+            }
+            .worded(&puzzle.eprime)
+        };
+        found
+            .iter()
+            .map(|(mus, a, b)| EitherOr {
+                either: fact(a),
+                or: fact(b),
+                constraints: mus.iter().map(|c| puzzle.lit_to_con(c).clone()).collect(),
+            })
+            .collect()
+    }
+
+    pub fn get_provable_varlits(&mut self) -> BTreeSet<Lit> {
+        self.psolve.get_provable_varlits().clone()
+    }
+
+    pub fn get_provable_varlits_including_reveals(&mut self) -> BTreeSet<Lit> {
+        let mut all_lits = BTreeSet::new();
+
+        while !self.psolve.get_provable_varlits().is_empty() {
+            let varlits = self.psolve.get_provable_varlits().clone();
+
+            for v in &varlits {
+                self.mark_lit_as_deduced(v);
+            }
+
+            all_lits.extend(varlits.into_iter());
+        }
+
+        all_lits
+    }
+
+    /// Solves the puzzle quickly, returning the HTML of the solve and whether
+    /// it finished within `budget`. Each step is drawn by `draw`, as
+    /// `PlannerHtml::quick_solve_html` in demystify-render does, and is
+    /// followed by the facts, the reason the solve is stuck and the summary,
+    /// as set by the config. The steps made before `budget` runs out are
+    /// still applied.
+    pub fn quick_solve_html_with(
+        &mut self,
+        budget: Option<&SearchBudget>,
+        draw: &dyn Fn(&Problem) -> String,
+    ) -> (String, bool) {
+        let mut html = String::new();
+        if let Some(setup) = self.take_setup_step() {
+            html += &draw(&self.step_problem(&setup));
+            html += "<br/>";
+        }
+        let mut steps = 0;
+        let mut following = None;
+        while !self.target_varlits().is_empty() && !self.stop_before_search(steps) {
+            let step = match following.take() {
+                Some(step) => Some(step),
+                None => self.next_step_in_budget(budget),
+            };
+            let Some(mut step) = step else {
+                return (html, false);
+            };
+            if self.stop_before_step(&step.muses) {
+                break;
+            }
+            following = self.merge_following(&mut step);
+            html += &draw(&self.step_problem(&step));
+            self.apply_step(&step);
+            html += "<br/>";
+            steps += 1;
+        }
+        if self.config.either_or && self.unpinned_varlits().is_empty() {
+            for fact in self.either_or_facts() {
+                html += &fact.html();
+            }
+        }
+        if let Some(stuck) = self.stuck() {
+            html += &stuck.html();
+        }
+        let summary = self.solve_summary();
+        if self.config.summary {
+            html += &summary.html();
+        } else {
+            html += &summary.guess_html().unwrap_or_default();
+        }
+        (html, true)
+    }
+
+    /// As [`PuzzlePlanner::quick_solve_html_with`], but as plain text.
+    pub fn quick_solve_text(&mut self) -> String {
+        let mut text = String::new();
+        if let Some(setup) = self.take_setup_step() {
+            text += "Setup:\n";
+            text += &self.explain_step(&setup).text();
+            text += "\n";
+        }
+        let mut steps = 0;
+        let mut following = None;
+        while !self.target_varlits().is_empty() && !self.stop_before_search(steps) {
+            let mut step = following.take().unwrap_or_else(|| self.next_step());
+            if self.stop_before_step(&step.muses) {
+                break;
+            }
+            following = self.merge_following(&mut step);
+            steps += 1;
+            let _ = writeln!(text, "Step {steps}:");
+            text += &self.explain_step(&step).text();
+            text += "\n";
+            self.apply_step(&step);
+        }
+        if self.config.either_or && self.unpinned_varlits().is_empty() {
+            for fact in self.either_or_facts() {
+                text += &fact.text();
+            }
+        }
+        if let Some(stuck) = self.stuck() {
+            text += &stuck.text();
+        }
+        let summary = self.solve_summary();
+        if self.config.summary {
+            text += &summary.text();
+        } else {
+            text += &summary.guess_text().unwrap_or_default();
+        }
+        text
+    }
+
+    /// As [`PuzzlePlanner::quick_solve_html_with`], but keeping the grid and
+    /// statements of each step, so they can be rendered later.
+    pub fn quick_solve_trace(&mut self) -> SolveTrace {
+        let mut trace = SolveTrace::new();
+        if let Some(setup) = self.take_setup_step() {
+            trace.steps.push(TraceStep {
+                title: "Setup".to_owned(),
+                problem: self.step_problem(&setup),
+            });
+        }
+        let mut steps = 0;
+        let mut following = None;
+        while !self.target_varlits().is_empty() && !self.stop_before_search(steps) {
+            let mut step = following.take().unwrap_or_else(|| self.next_step());
+            if self.stop_before_step(&step.muses) {
+                break;
+            }
+            following = self.merge_following(&mut step);
+            steps += 1;
+            trace.steps.push(TraceStep {
+                title: format!("Step {steps}"),
+                problem: self.step_problem(&step),
+            });
+            self.apply_step(&step);
+        }
+        trace.summary = Some(self.solve_summary());
+        trace
+    }
+
+    /// The variables whose value is not known.
+    fn open_cells(&self) -> Vec<OpenCell> {
+        self.candidates()
+            .into_iter()
+            .filter(|(_, values)| values.len() > 1)
+            .map(|(var, values)| OpenCell { var, values })
+            .collect()
+    }
+
+    /// Whether nothing more can be deduced with `open` cells left, so the
+    /// puzzle has more than one solution. This is only known once the
+    /// provable literals have been looked for, and is false when the rules
+    /// are restricted, as [`PuzzlePlanner::stuck`] covers that.
+    fn needs_guess(&self, open: &[OpenCell]) -> bool {
+        !open.is_empty()
+            && self.allowed.is_none()
+            && self
+                .psolve
+                .known()
+                .provable()
+                .is_some_and(BTreeSet::is_empty)
+    }
+
+    /// How the solve has gone so far: whether the puzzle is solved, the
+    /// deductions made using each `$#CON` by the steps applied, and the
+    /// time and solver calls since this planner was created (or forked
+    /// from, for a fork). If the solve is stuck, it suggests where to guess.
+    #[must_use]
+    pub fn solve_summary(&self) -> SolveSummary {
+        let open = self.open_cells();
+        let guess = if self.needs_guess(&open) {
+            open.iter().min_by_key(|cell| cell.values.len()).cloned()
+        } else {
+            None
+        };
+        SolveSummary {
+            solved: open.is_empty(),
+            steps: self.tally.steps,
+            deductions: self.tally.deductions.clone(),
+            difficulty: self.tally.difficulty,
+            dominant: self.tally.nontrivial.dominant(),
+            spikes: difficulty_spikes(&self.tally.sizes),
+            solver_calls: get_solver_calls() - self.tally.solver_calls,
+            millis: u64::try_from(self.tally.started.elapsed().as_millis()).unwrap_or(u64::MAX),
+            open,
+            guess,
+        }
+    }
+
+    /// Finds the next step and applies it, returning the puzzle with the
+    /// step shown on it and the literals deduced. The caller should not apply
+    /// the literals again.
+    pub fn quick_solve_step(&mut self) -> (Problem, Vec<Lit>) {
+        let step = self.next_step();
+        let problem = self.step_problem(&step);
+        self.apply_step(&step);
+        (problem, step.lits())
+    }
+
+    /// Finds the easiest next step, without applying it.
+    pub fn next_step(&mut self) -> Step {
+        if let Some(step) = self.simple_facts_step() {
+            return step;
+        }
+        if let Some(step) = self.chained_singles_step() {
+            return step;
+        }
+        let muses = self.smallest_muses();
+        self.choose_and_record(muses)
+    }
+
+    /// As [`PuzzlePlanner::next_step`], but stops searching once `budget`
+    /// runs out. In that case the returned [`MusSearch`] can be passed back
+    /// as `resume` to carry on. A `resume` from a different point in the
+    /// solve is ignored. Nothing is applied either way.
+    pub fn next_step_budgeted(
+        &mut self,
+        budget: &SearchBudget,
+        resume: Option<MusSearch>,
+    ) -> BudgetedStep {
+        let varlits = self.target_varlits();
+        let resume = resume.filter(|search| search.lits == varlits);
+
+        if resume.is_none()
+            && let Some(step) = self
+                .simple_facts_step()
+                .or_else(|| self.chained_singles_step())
+        {
+            return BudgetedStep::Done(step);
+        }
+
+        match self.psolve.get_many_vars_small_mus_budgeted(
+            &varlits,
+            &self.config.mus_config,
+            resume,
+            budget,
+        ) {
+            MusSearchResult::Complete(muses) => {
+                BudgetedStep::Done(self.choose_and_record(self.smallest_in(&muses)))
+            }
+            MusSearchResult::Truncated(search) => {
+                let description = match search.muses.min() {
+                    Some(min) => format!(
+                        "Computation truncated. The easiest deduction found so far uses {min} constraints, but there may be easier ones."
+                    ),
+                    None => "Computation truncated before any deduction was found.".to_string(),
+                };
+                let problem = Box::new(self.state_problem(&description));
+                BudgetedStep::Truncated { problem, search }
+            }
+        }
+    }
+
+    /// What is already known about `lit_def` (the indices of a variable,
+    /// followed by a value), from the literals deduced so far. Only
+    /// [`LiteralStatus::Unknown`] literals are worth searching for with
+    /// [`PuzzlePlanner::next_step_for_literal`].
+    pub fn literal_status(&self, lit_def: &[i64]) -> LiteralStatus {
+        let Some((&val, indices)) = lit_def.split_last() else {
+            return LiteralStatus::NoSuchLiteral;
+        };
+        let puzzle = self.psolve.puzzleparse();
+        if puzzle.eprime.is_masked(indices) {
+            return LiteralStatus::NotACell;
+        }
+        let known = self.psolve.known();
+
+        let mut exists = false;
+        let mut solved = None;
+        let mut ruled_out = None;
+        for lit in &puzzle.varset_lits {
+            for puzlit in puzzle.lit_to_vars(lit) {
+                if puzzle.eprime.cell_of(&puzlit.var()) != indices {
+                    continue;
+                }
+                exists |= puzlit.val() == val;
+                if !known.contains(puzzle, *lit) {
+                    continue;
+                }
+                let step = self.deduced_in.get(lit).copied();
+                if puzlit.sign() {
+                    solved = Some(LiteralStatus::Solved {
+                        lit: puzlit.clone(),
+                        step,
+                    });
+                } else if puzlit.val() == val {
+                    ruled_out = Some(LiteralStatus::RuledOut {
+                        lit: puzlit.clone(),
+                        step,
+                    });
+                }
+            }
+        }
+
+        if !exists {
+            return LiteralStatus::NoSuchLiteral;
+        }
+        solved.or(ruled_out).unwrap_or(LiteralStatus::Unknown)
+    }
+
+    /// The easiest step which deduces the literal `lit_def` (the indices of
+    /// a variable, followed by a value), without applying it. Returns `None`
+    /// if that literal cannot be deduced yet.
+    pub fn next_step_for_literal(&mut self, lit_def: Vec<i64>) -> Option<Step> {
+        let muses = self.filtered_muses(Box::new(move |lit, planner| {
+            let solver: &PuzzleSolver = planner.solver();
+            for puzlit in solver.lit_to_puzlit(lit) {
+                let mut indices = solver.puzzleparse().eprime.cell_of(&puzlit.var());
+                indices.push(puzlit.val());
+                if indices == lit_def {
+                    return true;
+                }
+            }
+            false
+        }));
+
+        let min = muses.min()?;
+
+        let mut vec = vec![];
+
+        for v in muses.muses().values() {
+            if let Some(m) = v.iter().next() {
+                if m.mus_len() == min {
+                    vec.push(m.clone());
+                }
+            }
+        }
+
+        Some(Step {
+            alternatives: vec.len(),
+            muses: vec,
+            symmetric: 1,
+            kind: StepKind::Deduction,
+        })
+    }
+
+    /// The easiest step which only uses constraints with one of `scope_cells`
+    /// (the indices of variables) in their scope, without applying it. The
+    /// step has no MUSes if those constraints deduce nothing new.
+    pub fn next_step_within(&mut self, scope_cells: &BTreeSet<Vec<i64>>) -> Step {
+        let mut restricted = self.fork();
+        restricted.psolve = self.psolve.restricted_to_cells(scope_cells);
+        restricted.next_step()
+    }
+
+    /// Only makes steps using the constraints of the `$#CON`s named in
+    /// `names`, such as to teach a subset of the rules, replacing any rules
+    /// allowed before. Once these deduce nothing more, solves stop early and
+    /// [`PuzzlePlanner::stuck`] gives the easiest step which needs another
+    /// rule, so rules can be unlocked one at a time.
+    pub fn allow_only(&mut self, names: &BTreeSet<String>) -> anyhow::Result<()> {
+        let known = self.known_for_unrestricted();
+        let unrestricted = match &mut self.allowed {
+            Some(allowed) => {
+                allowed.unrestricted.restore_known(known);
+                &allowed.unrestricted
+            }
+            None => &self.psolve,
+        };
+        let restricted = unrestricted.restricted_to_constraints(names)?;
+        let previous = std::mem::replace(&mut self.psolve, restricted);
+        let unrestricted = match self.allowed.take() {
+            Some(allowed) => allowed.unrestricted,
+            None => previous,
+        };
+        self.allowed = Some(AllowedRules {
+            names: names.clone(),
+            unrestricted,
+        });
+        Ok(())
+    }
+
+    /// Allows every rule again, undoing [`PuzzlePlanner::allow_only`].
+    pub fn allow_all(&mut self) {
+        if let Some(mut allowed) = self.allowed.take() {
+            allowed
+                .unrestricted
+                .restore_known(self.known_for_unrestricted());
+            self.psolve = allowed.unrestricted;
+        }
+    }
+
+    /// The `$#CON`s steps may use, if restricted by
+    /// [`PuzzlePlanner::allow_only`].
+    #[must_use]
+    pub fn allowed_constraints(&self) -> Option<&BTreeSet<String>> {
+        self.allowed.as_ref().map(|allowed| &allowed.names)
+    }
+
+    /// The known literals, for a solver using a different set of constraints.
+    fn known_for_unrestricted(&self) -> KnownLits {
+        let mut known = self.psolve.known().clone();
+        known.forget_provable();
+        known
+    }
+
+    /// If the rules are restricted by [`PuzzlePlanner::allow_only`], and
+    /// they deduce nothing more but the puzzle is not finished, the easiest
+    /// step using every rule. This is `None` otherwise.
+    pub fn stuck(&mut self) -> Option<StuckReport> {
+        if self.allowed.is_none() || !self.unpinned_varlits().is_empty() {
+            return None;
+        }
+        let known = self.known_for_unrestricted();
+        let allowed = self.allowed.as_mut()?;
+        allowed.unrestricted.restore_known(known);
+        let varlits: BTreeSet<Lit> = allowed
+            .unrestricted
+            .get_provable_varlits()
+            .difference(&self.pinned)
+            .copied()
+            .collect();
+        if varlits.is_empty() {
+            return None;
+        }
+        let md = allowed.unrestricted.get_many_vars_small_mus_quick(
+            &varlits,
+            &self.config.mus_config,
+            None,
+        );
+
+        let muses = merge_muscontexts(&self.smallest_in(&md));
+        // The MUSes may use constraints the restricted puzzle does not have
+        let puzzle = self.allowed.as_ref()?.unrestricted.puzzleparse();
+        let step = Step {
+            alternatives: muses.len(),
+            muses: vec![muses.first()?.clone()],
+            symmetric: symmetry::cluster(puzzle, &muses)[0].len(),
+            kind: StepKind::Deduction,
+        };
+        // Explain the step with every constraint, as the restricted puzzle
+        // does not know those which are not allowed
+        let mut unrestricted = self.fork();
+        unrestricted.allow_all();
+        let explanation = unrestricted.explain_step(&step);
+        let allowed = self.allowed_constraints()?;
+        let disallowed = mus_constraint_names(unrestricted.puzzle(), &step.muses[0])
+            .into_iter()
+            .filter(|name| !allowed.contains(name))
+            .collect();
+        Some(StuckReport {
+            step,
+            explanation,
+            disallowed,
+        })
+    }
+
+    /// The MUSes of [`PuzzlePlanner::next_step_within`].
+    pub fn muses_within(&mut self, scope_cells: &BTreeSet<Vec<i64>>) -> Vec<MusContext> {
+        self.next_step_within(scope_cells).muses
+    }
+
+    /// As [`PuzzlePlanner::quick_solve_step`], for the step given by
+    /// [`PuzzlePlanner::next_step_for_literal`].
+    ///
+    /// Literals which are already known, or cannot be deduced yet, are
+    /// described in the error instead, and nothing is applied.
+    pub fn quick_solve_step_for_literal(
+        &mut self,
+        lit_def: Vec<i64>,
+    ) -> Result<(Problem, Vec<Lit>), String> {
+        let status = self.literal_status(&lit_def);
+        if status != LiteralStatus::Unknown {
+            return Err(status.to_string());
+        }
+        let Some(step) = self.next_step_for_literal(lit_def) else {
+            return Err(
+                "This value cannot be deduced yet, as it is pinned or the puzzle does not decide it"
+                    .to_owned(),
+            );
+        };
+        let problem = self.step_problem(&step);
+        self.apply_step(&step);
+        Ok((problem, step.lits()))
+    }
+
+    /// Explains why all of `lits` are forced together, rather than each on
+    /// its own: a small set of constraints which, with what is known, proves
+    /// every one of them, found by [`PuzzleSolver::get_set_mus`]. Returns
+    /// `None` if some of `lits` are not literals of the puzzle, or cannot be
+    /// deduced yet. Nothing is applied.
+    pub fn explain_set(&mut self, lits: &[PuzLit]) -> Option<MusContext> {
+        let litmap = &self.psolve.puzzleparse().litmap;
+        let lits: BTreeSet<Lit> = lits
+            .iter()
+            .map(|l| litmap.get(l).copied())
+            .collect::<Option<_>>()?;
+        skip_search_error(SearchPhase::CheckMus, self.psolve.get_set_mus(&lits))?
+    }
+
+    /// [`PuzzlePlanner::explain_set`] shown as a step, with every one of
+    /// `lits` marked as deduced.
+    pub fn explain_set_problem(&mut self, lits: &[PuzLit]) -> Option<Problem> {
+        let mc = self.explain_set(lits)?;
+        let step = Step {
+            muses: vec![mc],
+            alternatives: 1,
+            symmetric: 1,
+            kind: StepKind::Deduction,
+        };
+        Some(self.step_problem(&step))
+    }
+
+    /// The puzzle with the size of the smallest MUS of each literal drawn on
+    /// it, from [`PuzzlePlanner::all_muses_with_larger`].
+    pub fn quick_generate_difficulties(&mut self) -> Problem {
+        let base_muses = self.all_muses_with_larger();
+        self.difficulties_problem(&base_muses, None)
+    }
+
+    /// As [`PuzzlePlanner::quick_generate_difficulties`], with the MUSes
+    /// from [`PuzzlePlanner::incremental_muses_with_larger`], which is much
+    /// quicker when called again after each step.
+    pub fn incremental_difficulties(&mut self) -> Problem {
+        let base_muses = self.incremental_muses_with_larger();
+        self.difficulties_problem(&base_muses, None)
+    }
+
+    /// As [`PuzzlePlanner::incremental_difficulties`], but each step of
+    /// the solve behind [`PlannerConfig::difficulty_candidate_counts`] stops
+    /// once `budget` runs out, or its flag is cancelled. The candidate counts
+    /// are then left out.
+    pub fn incremental_difficulties_within(&mut self, budget: &SearchBudget) -> Problem {
+        let base_muses = self.incremental_muses_with_larger();
+        self.difficulties_problem(&base_muses, Some(budget))
+    }
+
+    fn difficulties_problem(
+        &mut self,
+        base_muses: &MusDict,
+        budget: Option<&SearchBudget>,
+    ) -> Problem {
+        let base_difficulties: BTreeMap<Lit, usize> = base_muses
+            .muses()
+            .iter()
+            .filter(|(_, v)| !v.is_empty())
+            .map(|(k, v)| (*k, v.iter().next().unwrap().mus_len()))
+            .collect();
+
+        self.display_difficulty_step(base_difficulties, base_muses.timed_out(), budget)
+    }
+
+    /// The puzzle with `step` shown on it, from the current state of the
+    /// puzzle. This does not change what is known, so should be called
+    /// before [`PuzzlePlanner::apply_step`].
+    pub fn step_problem(&mut self, step: &Step) -> Problem {
+        let varlits = self.psolve.get_provable_varlits().clone();
+
+        let tosolve_varvals: BTreeSet<_> = varlits
+            .iter()
+            .flat_map(|x| self.psolve.lit_to_puzlit(x))
+            .map(super::PuzLit::varval)
+            .collect();
+
+        let known_puzlits: BTreeSet<PuzLit> = self
+            .get_all_known_lits()
+            .iter()
+            .flat_map(|x| self.psolve.lit_to_puzlit(x))
+            .cloned()
+            .collect();
+
+        let explanation = self.explain_step(step);
+        let all_deduced = explanation.deduced();
+
+        let cells: BTreeSet<_> = all_deduced
+            .iter()
+            .map(|p| NoteTarget::Cell(self.psolve.puzzleparse().eprime.cell_of(&p.var())))
+            .collect();
+        // The setup step comes before step 1, and is never applied
+        let number = match step.kind {
+            StepKind::Deduction => self.steps_taken + 1,
+            StepKind::Setup => 0,
+        };
+        let step_note = NoteTarget::Step(number);
+        let notes = self.notes.html(std::iter::once(&step_note).chain(&cells));
+        let pre_string = format!("{notes}{}", explanation.intro_html());
+
+        let mut problem = Problem::new_from_puzzle_and_mus(
+            &self.psolve,
+            &tosolve_varvals,
+            &known_puzlits,
+            &all_deduced,
+            &explanation.description_statements(),
+            &pre_string,
+        )
+        .expect("Cannot make puzzle json");
+        problem.mark_pinned(&self.pinned_varvals());
+        problem.mark_step(number);
+        self.add_grid_stats(&mut problem);
+        if let Some(state) = problem.state.as_mut() {
+            state.explanation = Some(explanation);
+        }
+        problem
+    }
+
+    /// What `step` deduces and why, from which every output is made.
+    #[must_use]
+    pub fn explain_step(&self, step: &Step) -> Explanation {
+        Explanation {
+            deductions: step
+                .muses
+                .iter()
+                .map(|mus| {
+                    let (deduced, constraints) = self.mus_to_user_mus(mus);
+                    Deduction::new(deduced, constraints).worded(&self.psolve.puzzleparse().eprime)
+                })
+                .collect(),
+            alternatives: step.alternatives,
+            symmetric: step.symmetric,
+            kind: step.kind,
+        }
+    }
+
+    /// Marks everything deduced by `step` as known.
+    pub fn apply_step(&mut self, step: &Step) {
+        self.tally.record(self.psolve.puzzleparse(), step);
+        for lit in step.muses.iter().flat_map(|mc| &mc.lits) {
+            self.mark_lit_as_deduced(lit);
+            self.deduced_in.entry(*lit).or_insert(self.steps_taken + 1);
+        }
+        if !step.muses.is_empty() {
+            self.steps_taken += 1;
+        }
+    }
+
+    /// Shows `step` as [`PuzzlePlanner::step_problem`] does, then applies
+    /// it, keeping what was shown in [`PuzzlePlanner::history`] if the step
+    /// deduced anything.
+    pub fn record_and_apply_step(&mut self, step: &Step) -> Problem {
+        let problem = self.step_problem(step);
+        self.apply_step(step);
+        if !step.muses.is_empty() {
+            let step = TraceStep {
+                title: format!("Step {}", self.steps_taken),
+                problem: problem.clone(),
+            };
+            self.history.push(step, &self.config.history);
+        }
+        problem
+    }
+
+    /// The steps applied by [`PuzzlePlanner::record_and_apply_step`], oldest
+    /// first.
+    #[must_use]
+    pub fn history(&self) -> &StepHistory {
+        &self.history
+    }
+
+    /// The current state of the puzzle, without any deduction. Notes on
+    /// cells are shown after `description`.
+    pub fn state_problem(&mut self, description: &str) -> Problem {
+        let varlits = self.psolve.get_provable_varlits().clone();
+
+        let tosolve_varvals: BTreeSet<_> = varlits
+            .iter()
+            .flat_map(|x| self.psolve.lit_to_puzlit(x))
+            .map(super::PuzLit::varval)
+            .collect();
+
+        let known_puzlits: BTreeSet<PuzLit> = self
+            .get_all_known_lits()
+            .iter()
+            .flat_map(|x| self.psolve.lit_to_puzlit(x))
+            .cloned()
+            .collect();
+
+        let cell_notes = self.notes.html(
+            self.notes
+                .iter()
+                .map(|(t, _)| t)
+                .filter(|t| matches!(t, NoteTarget::Cell(_))),
+        );
+
+        let mut problem = Problem::new_from_puzzle_and_state(
+            &self.psolve,
+            &tosolve_varvals,
+            &known_puzlits,
+            &BTreeSet::new(),
+            &format!("{description}{cell_notes}"),
+        )
+        .expect("Cannot make puzzle json");
+        problem.mark_pinned(&self.pinned_varvals());
+        self.add_grid_stats(&mut problem);
+        problem
+    }
+
+    /// The puzzle as it was once `step` steps had been applied (0 being the
+    /// start), with the values then known but no candidates, such as to
+    /// preview earlier points of the solve with
+    /// [`thumbnail_svg`](crate::web::thumbnail_svg). Values known without a
+    /// step, such as those of a restored session, are shown from the start.
+    pub fn problem_after_step(&self, step: usize) -> anyhow::Result<Problem> {
+        let known: BTreeSet<PuzLit> = self
+            .get_all_known_lits()
+            .iter()
+            .filter(|lit| self.deduced_in.get(lit).is_none_or(|&s| s <= step))
+            .flat_map(|lit| self.psolve.lit_to_puzlit(lit))
+            .cloned()
+            .collect();
+        Problem::new_from_puzzle_and_state(
+            &self.psolve,
+            &BTreeSet::new(),
+            &known,
+            &BTreeSet::new(),
+            "",
+        )
+    }
+
+    /// The current state of the puzzle, with the cells covered by each
+    /// `$#CON` shaded in its own colour and listed, as in [`RuleCoverage`],
+    /// to show the rules of the puzzle before stepping through its solve.
+    pub fn rules_problem(&mut self) -> Problem {
+        let mut problem = self.state_problem(
+            "The rules of the puzzle. Tick a rule to shade the cells it covers, or hover over it to highlight them.",
+        );
+        let rules = RuleCoverage::all(self.psolve.puzzleparse(), &problem.puzzle);
+        if let Some(state) = problem.state.as_mut() {
+            state.statements = Some(
+                rules
+                    .iter()
+                    .enumerate()
+                    .map(|(n, rule)| rule.statement(n, n == 0))
+                    .collect(),
+            );
+            state.rule_coverage = Some(rules);
+        }
+        problem
+    }
+
+    /// Draws the size of the MUS of each literal in `base_difficulties`, and
+    /// marks those in `unknown` as having an unknown difficulty.
+    pub fn quick_display_difficulty_step(
+        &mut self,
+        base_difficulties: BTreeMap<Lit, usize>,
+        unknown: &BTreeSet<Lit>,
+    ) -> Problem {
+        self.display_difficulty_step(base_difficulties, unknown, None)
+    }
+
+    fn display_difficulty_step(
+        &mut self,
+        base_difficulties: BTreeMap<Lit, usize>,
+        unknown: &BTreeSet<Lit>,
+        budget: Option<&SearchBudget>,
+    ) -> Problem {
+        // Make a nicer map
+
+        let mut vvpmap: BTreeMap<VarValPair, usize> = BTreeMap::new();
+
+        for (lit, &val) in &base_difficulties {
+            for puzlit in self.psolve.puzzleparse().lit_to_vars(lit) {
+                let vvp = puzlit.varval();
+                vvpmap.insert(vvp, val);
+            }
+        }
+
+        let unknown_varvals: BTreeSet<VarValPair> = unknown
+            .iter()
+            .flat_map(|lit| self.psolve.puzzleparse().lit_to_vars(lit))
+            .map(PuzLit::varval)
+            .filter(|vvp| !vvpmap.contains_key(vvp))
+            .collect();
+
+        let varlits = self.psolve.get_provable_varlits().clone();
+
+        let tosolve_varvals: BTreeSet<_> = varlits
+            .iter()
+            .flat_map(|x| self.psolve.lit_to_puzlit(x))
+            .map(super::PuzLit::varval)
+            .collect();
+
+        let known_puzlits: BTreeSet<PuzLit> = self
+            .get_all_known_lits()
+            .iter()
+            .flat_map(|x| self.psolve.lit_to_puzlit(x))
+            .cloned()
+            .collect();
+
+        // The steps so far which stand out, to go back to
+        let mut description = "The difficulty of the problem".to_owned();
+        for spike in difficulty_spikes(&self.tally.sizes) {
+            let _ = write!(description, "<br/>Note: {spike}");
+        }
+
+        let candidate_counts = if self.config.difficulty_candidate_counts {
+            let counts = self.candidate_count_trace_impl(budget);
+            if counts.is_none() {
+                description += "<br/>Note: the candidate counts took too long, so are not shown";
+            }
+            counts
+        } else {
+            None
+        };
+
+        let mut problem = Problem::new_from_puzzle_and_difficulty(
+            &self.psolve,
+            &tosolve_varvals,
+            &known_puzlits,
+            &vvpmap,
+            &unknown_varvals,
+            &description,
+        )
+        .expect("Cannot make puzzle json");
+        problem.mark_pinned(&self.pinned_varvals());
+
+        if let Some(counts) = candidate_counts {
+            problem
+                .add_candidate_counts(&counts)
+                .expect("Cannot add candidate counts");
+        }
+
+        problem
+    }
+
+    /// The number of values each variable can still take, given what is
+    /// currently known.
+    pub fn candidate_counts(&self) -> BTreeMap<PuzVar, usize> {
+        self.candidates()
+            .into_iter()
+            .map(|(var, values)| (var, values.len()))
+            .collect()
+    }
+
+    /// How far each row, column and region of the grid has been solved.
+    pub fn grid_stats(&self) -> anyhow::Result<GridStats> {
+        let parse = self.psolve.puzzleparse();
+        let puzzle = Puzzle::new_from_puzzle(parse)?;
+        Ok(GridStats::new(parse, &puzzle, &self.candidates()))
+    }
+
+    /// Adds [`PuzzlePlanner::grid_stats`] to `problem`, to be drawn in the
+    /// margins, if [`HtmlOptions::margin_stats`] is set.
+    fn add_grid_stats(&self, problem: &mut Problem) {
+        if self.config.html_options.margin_stats
+            && let Some(state) = problem.state.as_mut()
+        {
+            state.grid_stats = Some(GridStats::new(
+                self.psolve.puzzleparse(),
+                &problem.puzzle,
+                &self.candidates(),
+            ));
+        }
+    }
+
+    /// The values each variable can still take, given what is currently
+    /// known. A variable whose value is known has just that value.
+    pub fn candidates(&self) -> BTreeMap<PuzVar, Vec<i64>> {
+        let known: BTreeSet<&PuzLit> = self
+            .get_all_known_lits()
+            .iter()
+            .flat_map(|x| self.psolve.lit_to_puzlit(x))
+            .collect();
+
+        let mut candidates: BTreeMap<PuzVar, Vec<i64>> = BTreeMap::new();
+        let mut assigned: BTreeMap<PuzVar, i64> = BTreeMap::new();
+
+        for vv in self.psolve.puzzleparse().all_var_varvals() {
+            if known.contains(&PuzLit::new_eq(vv.clone())) {
+                assigned.insert(vv.var().clone(), vv.val());
+            }
+            let values = candidates.entry(vv.var().clone()).or_default();
+            if !known.contains(&PuzLit::new_neq(vv.clone())) {
+                values.push(vv.val());
+            }
+        }
+
+        for (var, val) in assigned {
+            candidates.insert(var, vec![val]);
+        }
+
+        candidates
+    }
+
+    /// Solves a copy of the puzzle step by step, and summarises how many
+    /// values each variable could take across all the steps. This shows
+    /// where a puzzle stays 'wide' for a long time.
+    pub fn candidate_count_trace(&self) -> BTreeMap<PuzVar, CandidateCount> {
+        self.candidate_count_trace_impl(None).unwrap_or_default()
+    }
+
+    /// [`PuzzlePlanner::candidate_count_trace`], or `None` if `budget` ran
+    /// out during one of the steps.
+    fn candidate_count_trace_impl(
+        &self,
+        budget: Option<&SearchBudget>,
+    ) -> Option<BTreeMap<PuzVar, CandidateCount>> {
+        let mut planner = self.fork();
+        let mut trace: BTreeMap<PuzVar, CandidateCount> = BTreeMap::new();
+
+        loop {
+            for (var, count) in planner.candidate_counts() {
+                trace
+                    .entry(var)
+                    .and_modify(|c| c.add(count))
+                    .or_insert_with(|| CandidateCount::new(count));
+            }
+
+            if planner.target_varlits().is_empty() {
+                break;
+            }
+
+            let muses = planner.next_step_in_budget(budget)?.muses;
+            if muses.is_empty() {
+                break;
+            }
+            for lit in muses.iter().flat_map(|mus| &mus.lits) {
+                planner.mark_lit_as_deduced(lit);
+            }
+        }
+
+        Some(trace)
+    }
+
+    /// Returns a reference to the puzzle being solved.
+    ///
+    /// # Returns
+    ///
+    /// A reference to the `PuzzleParse` instance representing the puzzle being solved.
+    pub fn puzzle(&self) -> &PuzzleParse {
+        self.psolve.puzzleparse()
+    }
+
+    /// Returns a reference to the planner's configuration.
+    #[must_use]
+    pub fn config(&self) -> &PlannerConfig {
+        &self.config
+    }
+
+    /// Returns a mutable reference to the planner's configuration.
+    pub fn config_mut(&mut self) -> &mut PlannerConfig {
+        &mut self.config
+    }
+
+    /// Returns a mutable reference to the solver. Warning, incorrect use of underlying
+    /// solver can result in incorrect answers.
+    pub fn solver(&mut self) -> &mut PuzzleSolver {
+        &mut self.psolve
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, sync::Arc, time::Duration};
+
+    use crate::problem::{
+        PuzLit, PuzVar, VarValPair,
+        analysis::mus_constraint_names,
+        musdict::{MusContext, MusDict, merge_muscontexts},
+        notes::NoteTarget,
+        parse::PuzzleParse,
+        planner::{
+            BudgetedStep, CellWeight, ConstraintWeighting, LiteralStatus, PlannerConfig,
+            PuzzlePlanner, StepKind, StopCondition, StopReason,
+        },
+        solver::{CancelFlag, PuzzleSolver, SearchBudget},
+        trace::SolveTrace,
+        util::test_utils::{
+            sudoku_4x4, sudoku_4x4_planner, sudoku_4x4_planner_with, sudoku_4x4_with,
+        },
+    };
+    use itertools::Itertools;
+    use rustsat::types::Lit;
+    use test_log::test;
+
+    use crate::json::Problem;
+
+    /// Stands in for drawing a step, so tests can check what would be drawn
+    fn json(problem: &Problem) -> String {
+        serde_json::to_string(problem).unwrap()
+    }
+
+    #[test]
+    fn test_plan_little_essence() {
+        let result = crate::problem::util::test_utils::build_puzzleparse(
+            "./tst/little1.eprime",
+            "./tst/little1.param",
+        );
+
+        let result = Arc::new(result);
+
+        let puz = PuzzleSolver::new(result).unwrap();
+
+        let mut plan = PuzzlePlanner::new(puz);
+
+        let sequence = plan.quick_solve();
+
+        assert_eq!(sequence.iter().flatten().collect_vec().len(), 8);
+
+        for (litset, cons) in sequence.iter().flatten() {
+            assert!(!litset.is_empty());
+            // It should be trivial to prove we only need one
+            // constraint here, but MUS algorithms be tricky, if
+            // this next line starts failing, it can be commented out.
+            assert!(cons.len() <= 1);
+        }
+    }
+
+    #[test]
+    fn test_plan_sudoku_from_dimacs() {
+        let mut plan = sudoku_4x4_planner();
+
+        let sequence = plan.quick_solve();
+
+        assert!(!sequence.is_empty());
+        assert!(plan.get_provable_varlits().is_empty());
+        assert_eq!(plan.check_solvability(), Some(0));
+    }
+
+    #[test]
+    fn test_candidate_counts_sudoku() {
+        let mut plan = sudoku_4x4_planner();
+
+        let counts = plan.candidate_counts();
+        assert_eq!(counts.len(), 16);
+        // The given in the top left corner
+        assert_eq!(counts[&PuzVar::new("grid", vec![1, 1])], 1);
+
+        let trace = plan.candidate_count_trace();
+        assert_eq!(trace.len(), 16);
+        // Solving a fork leaves the planner where it was
+        assert!(!plan.get_provable_varlits().is_empty());
+        for count in trace.values() {
+            assert_eq!(count.min, 1);
+            assert!(count.max <= 4);
+            assert!(count.mean() >= 1.0 && count.mean() <= count.max as f64);
+        }
+        let blank = &trace[&PuzVar::new("grid", vec![1, 2])];
+        assert!(blank.max > 1);
+
+        plan.config_mut().difficulty_candidate_counts = true;
+        let problem = plan.quick_generate_difficulties();
+        assert!(problem.state.unwrap().candidate_counts.is_some());
+
+        // A cancelled budget leaves the counts out, rather than solving
+        let cancel = CancelFlag::default();
+        cancel.cancel();
+        let problem = plan.incremental_difficulties_within(&SearchBudget {
+            cancel: Some(cancel),
+            ..SearchBudget::default()
+        });
+        let state = problem.state.unwrap();
+        assert!(state.candidate_counts.is_none());
+        assert!(state.description.unwrap().contains("took too long"));
+    }
+
+    #[test]
+    fn test_incremental_difficulties_sudoku() {
+        let mut plan = sudoku_4x4_planner();
+
+        let check = |plan: &mut PuzzlePlanner, muses: &MusDict| {
+            let provable = plan.solver().get_provable_varlits().clone();
+            for (lit, mus_list) in muses.muses() {
+                assert!(provable.contains(lit));
+                for mc in mus_list {
+                    assert!(plan.solver().mus_proves_lits(mc));
+                }
+            }
+        };
+
+        let first = plan.incremental_muses_with_larger();
+        assert!(!first.is_empty());
+        check(&mut plan, &first);
+        // Nothing has changed, so nothing is searched for again
+        let again = plan.incremental_muses_with_larger();
+        assert_eq!(again.muses(), first.muses());
+
+        let known: BTreeSet<Lit> = plan.get_all_known_lits().iter().copied().collect();
+        let step = plan.next_step();
+        plan.apply_step(&step);
+        let after = plan.incremental_muses_with_larger();
+        check(&mut plan, &after);
+        assert!(
+            step.lits()
+                .iter()
+                .all(|lit| !after.muses().contains_key(lit))
+        );
+        // MUSes which do not use the deduced literals are kept
+        let deduced: BTreeSet<Lit> = plan
+            .get_all_known_lits()
+            .iter()
+            .filter(|lit| !known.contains(lit))
+            .copied()
+            .collect();
+        let touched = plan.constraints_touching(deduced.iter());
+        for (lit, mus_list) in first.muses() {
+            if after.muses().contains_key(lit)
+                && mus_list.iter().all(|mc| mc.mus.is_disjoint(&touched))
+            {
+                assert_eq!(&after.muses()[lit], mus_list);
+            }
+        }
+
+        let problem = plan.incremental_difficulties();
+        let description = problem.state.unwrap().description.unwrap();
+        assert!(description.contains("The difficulty of the problem"));
+    }
+
+    #[test]
+    fn test_variety_seed_sudoku() {
+        let config = PlannerConfig {
+            merge_small_threshold: 0,
+            expand_to_all_deductions: false,
+            ..PlannerConfig::default()
+        };
+        let mut plan = sudoku_4x4_planner_with(config);
+        let muses = plan.smallest_muses();
+        assert!(muses.len() > 1);
+        let mut reversed = muses.clone();
+        reversed.reverse();
+
+        let chosen = |plan: &mut PuzzlePlanner, seed: Option<u64>, muses: &[MusContext]| {
+            plan.config_mut().variety_seed = seed;
+            plan.choose_smallest_muses(muses.to_vec()).2
+        };
+        // Without a seed the first (once merged) is shown, and with one the
+        // order found in does not matter
+        let first = merge_muscontexts(&muses)[0].clone();
+        assert_eq!(chosen(&mut plan, None, &muses), [first]);
+        let choices: BTreeSet<_> = (0..20)
+            .map(|seed| {
+                let choice = chosen(&mut plan, Some(seed), &muses);
+                assert_eq!(choice, chosen(&mut plan, Some(seed), &reversed));
+                choice
+            })
+            .collect();
+        assert!(choices.len() > 1);
+    }
+
+    #[test]
+    fn test_difficulty_literal_time_sudoku() {
+        let mut plan = sudoku_4x4_planner();
+
+        let muses = plan.all_muses_with_larger();
+        assert!(muses.timed_out().is_empty());
+        let drawn = json(&plan.quick_generate_difficulties());
+        assert!(!drawn.contains("Unknown difficulty"));
+
+        // With no time, every literal is given up on
+        plan.config_mut().difficulty_literal_time = Some(Duration::ZERO);
+        let muses = plan.all_muses_with_larger();
+        assert!(muses.muses().is_empty());
+        assert_eq!(muses.timed_out(), &plan.unpinned_varlits());
+        let drawn = json(&plan.quick_generate_difficulties());
+        assert!(drawn.contains("Unknown difficulty (out of time)"));
+
+        // They are searched for again once there is time
+        let muses = plan.incremental_muses_with_larger();
+        assert_eq!(muses.timed_out().len(), plan.unpinned_varlits().len());
+        plan.config_mut().difficulty_literal_time = None;
+        let muses = plan.incremental_muses_with_larger();
+        assert!(muses.timed_out().is_empty());
+        assert!(!muses.is_empty());
+    }
+
+    #[test]
+    fn test_budgeted_step_sudoku() {
+        let mut plan = sudoku_4x4_planner();
+        // Skip the tiny MUS scan, which is not limited by the budget
+        plan.config_mut().mus_config.find_bigger = true;
+
+        let known = plan.get_all_known_lits();
+
+        let nothing = SearchBudget {
+            max_time: Some(Duration::ZERO),
+            max_solver_calls: None,
+            cancel: None,
+        };
+        let BudgetedStep::Truncated { problem, search } = plan.next_step_budgeted(&nothing, None)
+        else {
+            panic!("Search should have run out of budget");
+        };
+        assert!(json(&problem).contains("Computation truncated"));
+        assert_eq!(plan.get_all_known_lits(), known);
+
+        let cancel = CancelFlag::default();
+        cancel.cancel();
+        let cancelled = SearchBudget {
+            cancel: Some(cancel),
+            ..SearchBudget::default()
+        };
+        let BudgetedStep::Truncated { search, .. } =
+            plan.next_step_budgeted(&cancelled, Some(search))
+        else {
+            panic!("Cancelled search should stop");
+        };
+
+        let BudgetedStep::Done(step) =
+            plan.next_step_budgeted(&SearchBudget::default(), Some(search))
+        else {
+            panic!("Search without a budget should finish");
+        };
+        assert!(!step.lits().is_empty());
+        assert_eq!(plan.get_all_known_lits(), known);
+    }
+
+    #[test]
+    fn test_solve_for_sudoku() {
+        let mut plan = sudoku_4x4_planner();
+        let known = plan.get_all_known_lits();
+
+        let trace = plan.solve_for(Duration::ZERO);
+        assert!(trace.steps.is_empty());
+        assert!(!trace.is_complete());
+        assert_eq!(plan.get_all_known_lits(), known);
+
+        let mut full = plan.fork();
+        let expected = full.quick_solve_muses();
+
+        let trace = plan.resume_for(Duration::from_secs(600), trace);
+        assert!(trace.is_complete());
+        assert!(trace.resume.is_none());
+        assert_eq!(trace.steps.len(), expected.len());
+        assert_eq!(plan.get_all_known_lits(), full.get_all_known_lits());
+    }
+
+    #[test]
+    fn test_pin_literal_sudoku() {
+        let mut plan = sudoku_4x4_planner();
+
+        // Cell (1,2) of the 4x4 is 2
+        assert!(plan.toggle_pin_literal(&[1, 2, 2]));
+        let pinned = plan.pinned_lits().clone();
+        assert_eq!(pinned.len(), 2);
+        assert!(json(&plan.state_problem("")).contains("litpinned"));
+
+        let steps = plan.quick_solve_muses();
+        assert!(!steps.is_empty());
+        assert!(
+            steps
+                .iter()
+                .flatten()
+                .all(|mc| mc.lits.is_disjoint(&pinned))
+        );
+        assert!(
+            plan.get_all_known_lits()
+                .iter()
+                .all(|l| !pinned.contains(l))
+        );
+        assert!(!plan.get_provable_varlits().is_disjoint(&pinned));
+
+        assert!(!plan.toggle_pin_literal(&[1, 2, 2]));
+        assert!(plan.pinned_lits().is_empty());
+        plan.quick_solve_muses();
+        assert!(plan.get_provable_varlits().is_empty());
+    }
+
+    #[test]
+    fn test_notes_sudoku() {
+        let mut plan = sudoku_4x4_planner();
+
+        plan.notes_mut().set(NoteTarget::Step(2), "The second step");
+        plan.notes_mut()
+            .set(NoteTarget::Cell(vec![1, 2]), "Only 2 fits here");
+        assert!(json(&plan.state_problem("")).contains("Only 2 fits here"));
+
+        let step = plan.next_step();
+        assert!(!json(&plan.step_problem(&step)).contains("The second step"));
+        plan.apply_step(&step);
+        assert_eq!(plan.steps_taken(), 1);
+
+        // Forks keep the notes, and the step count
+        let mut fork = plan.fork();
+        let step = fork.next_step();
+        assert!(json(&fork.step_problem(&step)).contains("The second step"));
+
+        let html = plan.quick_solve_html_with(None, &json).0;
+        assert_eq!(html.matches("The second step").count(), 1);
+        assert!(html.contains("Only 2 fits here"));
+    }
+
+    #[test]
+    fn test_literal_status_sudoku() {
+        let mut plan = sudoku_4x4_planner();
+
+        // A given, clicked on its own value or another
+        let given = LiteralStatus::Solved {
+            lit: PuzLit::new_eq(VarValPair::new(&PuzVar::new("grid", vec![1, 1]), 1)),
+            step: Some(0),
+        };
+        assert_eq!(plan.literal_status(&[1, 1, 1]), given);
+        assert_eq!(plan.literal_status(&[1, 1, 4]), given);
+        assert!(given.to_string().ends_with("from the start of the puzzle"));
+
+        assert_eq!(
+            plan.literal_status(&[1, 1, 7]),
+            LiteralStatus::NoSuchLiteral
+        );
+        assert_eq!(
+            plan.literal_status(&[9, 9, 1]),
+            LiteralStatus::NoSuchLiteral
+        );
+        assert_eq!(plan.literal_status(&[]), LiteralStatus::NoSuchLiteral);
+
+        // Column 2 already has a 3, so this is ruled out by the first step
+        assert_eq!(plan.literal_status(&[1, 2, 3]), LiteralStatus::Unknown);
+        let (problem, lits) = plan.quick_solve_step_for_literal(vec![1, 2, 3]).unwrap();
+        assert!(!lits.is_empty());
+        assert!(!json(&problem).contains("already known"));
+        let ruled_out = plan.literal_status(&[1, 2, 3]);
+        assert_eq!(
+            ruled_out,
+            LiteralStatus::RuledOut {
+                lit: PuzLit::new_neq(VarValPair::new(&PuzVar::new("grid", vec![1, 2]), 3)),
+                step: Some(1),
+            }
+        );
+
+        // Clicking it again does not search, or apply another step
+        let described = plan
+            .quick_solve_step_for_literal(vec![1, 2, 3])
+            .unwrap_err();
+        assert_eq!(described, ruled_out.to_string());
+        assert!(described.ends_with("since step 1"));
+        assert_eq!(plan.steps_taken(), 1);
+
+        // Forks remember when literals were deduced
+        assert_eq!(plan.fork().literal_status(&[1, 2, 3]), ruled_out);
+    }
+
+    #[test]
+    fn test_explain_set_sudoku() {
+        let mut plan = sudoku_4x4_planner();
+
+        let provable: Vec<PuzLit> = plan
+            .solver()
+            .get_provable_varlits()
+            .clone()
+            .iter()
+            .take(3)
+            .map(|l| plan.solver().lit_to_puzlit(l).first().unwrap().clone())
+            .collect();
+        let mc = plan.explain_set(&provable).unwrap();
+        assert_eq!(mc.lits.len(), provable.len());
+
+        // The constraints prove all the literals, and none can be left out
+        let solver = plan.solver();
+        assert!(solver.mus_proves_lits(&mc));
+        for con in &mc.mus {
+            let mut smaller = mc.clone();
+            smaller.mus.remove(con);
+            assert!(!solver.mus_proves_lits(&smaller));
+        }
+
+        let drawn = json(&plan.explain_set_problem(&provable).unwrap());
+        assert!(drawn.contains("litpos") || drawn.contains("litneg"));
+        // Nothing is applied
+        assert_eq!(plan.steps_taken(), 0);
+
+        // A literal which cannot be deduced spoils the set
+        let mut both = provable.clone();
+        both.push(provable[0].neg());
+        assert!(plan.explain_set(&both).is_none());
+    }
+
+    #[test]
+    fn test_setup_step_sudoku() {
+        let config = PlannerConfig {
+            setup_step: true,
+            stop_condition: Some(StopCondition::Steps(1)),
+            ..PlannerConfig::default()
+        };
+
+        // One MUS for each of the 8 givens, using no constraints
+        let plan = sudoku_4x4_planner();
+        let setup = plan.setup_step().unwrap();
+        assert_eq!(setup.kind, StepKind::Setup);
+        assert_eq!(setup.muses.len(), 8);
+        assert!(setup.muses.iter().all(|m| m.mus.is_empty()));
+
+        let mut plan = sudoku_4x4_planner_with(config);
+        let steps = plan.quick_solve_muses();
+        // The setup step does not count towards the stop condition
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[0].len(), 8);
+        assert!(!steps[1][0].mus.is_empty());
+        // It is only shown once
+        plan.config_mut().stop_condition = None;
+        let steps = plan.quick_solve_muses();
+        assert!(steps.iter().all(|step| !step[0].mus.is_empty()));
+
+        let config = PlannerConfig {
+            setup_step: true,
+            ..PlannerConfig::default()
+        };
+        let mut plan = sudoku_4x4_planner_with(config);
+        let text = plan.quick_solve_text();
+        assert!(text.starts_with("Setup:\ngrid[1, 1] = 1\n"), "{text}");
+        assert!(text.contains("Step 1:"));
+
+        let mut plan = sudoku_4x4_planner();
+        plan.config_mut().setup_step = true;
+        let html = plan.quick_solve_html_with(None, &json).0;
+        assert_eq!(html.matches("how the puzzle is set up").count(), 1);
+    }
+
+    #[test]
+    fn test_either_or_sudoku_open() {
+        let result = sudoku_4x4_with("sudoku-4x4-open.json", "sudoku-4x4-open.dimacs");
+
+        let config = PlannerConfig {
+            either_or: true,
+            ..PlannerConfig::default()
+        };
+        let mut plan =
+            PuzzlePlanner::new_with_config(PuzzleSolver::new(Arc::new(result)).unwrap(), config);
+
+        // The puzzle has several solutions, so the solve ends with cells
+        // still open, and the 1 in the second column goes in one of them
+        let text = plan.quick_solve_text();
+        assert!(plan.get_provable_varlits().is_empty());
+        assert!(text.contains("Either grid[3, 2] = 1 or grid[4, 2] = 1\n  because "));
+
+        let facts = plan.either_or_facts();
+        assert!(!facts.is_empty());
+        for fact in &facts {
+            assert_eq!(fact.either.values, fact.or.values);
+            assert_ne!(fact.either.var, fact.or.var);
+            assert!(!fact.constraints.is_empty());
+        }
+        assert!(
+            facts
+                .windows(2)
+                .all(|w| w[0].constraints.len() <= w[1].constraints.len())
+        );
+    }
+
+    #[test]
+    fn test_quick_solve_trace() {
+        let config = PlannerConfig {
+            merge_small_threshold: 0,
+            setup_step: true,
+            ..PlannerConfig::default()
+        };
+        let mut plan = sudoku_4x4_planner_with(config);
+        let trace = plan.quick_solve_trace();
+        assert!(plan.unpinned_varlits().is_empty());
+
+        assert_eq!(trace.steps[0].title, "Setup");
+        for (n, step) in (1..).zip(&trace.steps[1..]) {
+            assert_eq!(step.title, format!("Step {n}"));
+            let state = step.problem.state.as_ref().unwrap();
+            assert!(!state.statements.as_ref().unwrap().is_empty());
+        }
+
+        // The setup step is not counted in the summary
+        let summary = trace.summary.as_ref().unwrap();
+        assert!(summary.solved);
+        assert_eq!(summary.steps, trace.steps.len() - 1);
+
+        let json = serde_json::to_string(&trace).unwrap();
+        assert_eq!(SolveTrace::from_reader(json.as_bytes()).unwrap(), trace);
+    }
+
+    #[test]
+    fn test_solve_summary() {
+        let config = PlannerConfig {
+            merge_small_threshold: 0,
+            summary: true,
+            ..PlannerConfig::default()
+        };
+
+        let mut plan = sudoku_4x4_planner_with(config.clone());
+        let text = plan.quick_solve_text();
+        let summary = plan.solve_summary();
+        assert!(summary.solved);
+        assert!(summary.open.is_empty());
+        assert_eq!(summary.steps, plan.steps_taken());
+        assert!(summary.difficulty >= 1);
+        // Every step uses at least one of the three kinds of constraint
+        assert!(summary.deductions.values().sum::<usize>() >= summary.steps);
+        assert!(
+            summary
+                .deductions
+                .keys()
+                .all(|k| ["row_alldiff", "con_alldiff", "box_alldiff"].contains(&k.as_str()))
+        );
+        let ending = &text[text.find("Summary:\n").unwrap()..];
+        assert!(ending.starts_with("Summary:\nSolved in "), "{ending}");
+        assert!(!ending.contains("Open:"));
+
+        let mut plan = sudoku_4x4_planner_with(PlannerConfig {
+            stop_condition: Some(StopCondition::Steps(1)),
+            ..config
+        });
+        let html = plan.quick_solve_html_with(None, &json).0;
+        let summary = plan.solve_summary();
+        assert!(!summary.solved);
+        assert_eq!(summary.steps, 1);
+        assert!(!summary.open.is_empty());
+        assert!(summary.open.iter().all(|c| c.values.len() > 1));
+        assert!(html.contains("<b>Summary</b><br/>Not solved after 1 step, with "));
+        assert!(html.contains("Open: "));
+        assert!(plan.solve_summary().text().contains("Open: grid[1, "));
+    }
+
+    #[test]
+    fn test_allow_only() {
+        let names = |names: &[&str]| names.iter().map(|&n| n.to_owned()).collect();
+        let mut plan = sudoku_4x4_planner();
+
+        let err = plan.allow_only(&names(&["row_alldiff", "diagonal"]));
+        assert!(
+            err.unwrap_err()
+                .to_string()
+                .starts_with("diagonal is not a constraint")
+        );
+        assert_eq!(plan.allowed_constraints(), None);
+        assert!(plan.stuck().is_none());
+
+        plan.allow_only(&names(&["row_alldiff"])).unwrap();
+        let steps = plan.quick_solve_muses();
+        assert!(!steps.is_empty());
+        for mc in steps.iter().flatten() {
+            assert_eq!(
+                mus_constraint_names(plan.puzzle(), mc),
+                names(&["row_alldiff"])
+            );
+        }
+        let stuck = plan.stuck().unwrap();
+        assert!(!stuck.disallowed.is_empty());
+        assert!(!stuck.disallowed.contains("row_alldiff"));
+        assert!(
+            stuck
+                .text()
+                .starts_with("Stuck under the current rule set. The easiest next step also needs ")
+        );
+        assert!(stuck.html().contains("because:<br/>"));
+
+        // Unlocking the rest finishes the puzzle
+        plan.allow_only(&names(&["row_alldiff", "con_alldiff", "box_alldiff"]))
+            .unwrap();
+        plan.quick_solve_muses();
+        assert!(plan.stuck().is_none());
+        assert!(plan.solve_summary().solved);
+        plan.allow_all();
+        assert_eq!(plan.allowed_constraints(), None);
+        assert!(plan.get_provable_varlits().is_empty());
+    }
+
+    #[test]
+    fn test_merge_same_cell_sudoku() {
+        let planner = |merge_same_cell| {
+            let config = PlannerConfig {
+                // Otherwise the whole puzzle is one step
+                merge_small_threshold: 0,
+                merge_same_cell,
+                ..PlannerConfig::default()
+            };
+            sudoku_4x4_planner_with(config)
+        };
+
+        let plain = planner(None).quick_solve_muses();
+        let plan = planner(Some(3));
+        let merged = plan.merge_same_cell_steps(plain.clone());
+        assert!(merged.len() < plain.len());
+        // The same deductions, in the same order
+        assert_eq!(
+            merged.concat().iter().map(|mc| &mc.lits).collect_vec(),
+            plain.concat().iter().map(|mc| &mc.lits).collect_vec()
+        );
+        for step in &merged {
+            let constraints: BTreeSet<_> = step.iter().flat_map(|mc| &mc.mus).collect();
+            assert!(step.len() == 1 || constraints.len() <= 3);
+        }
+        assert_eq!(planner(Some(3)).quick_solve_muses(), merged);
+
+        let plain_text = planner(None).quick_solve_text();
+        let mut plan = planner(Some(3));
+        let text = plan.quick_solve_text();
+        assert_eq!(text.matches("Step ").count(), merged.len(), "{text}");
+        assert!(text.matches("Step ").count() < plain_text.matches("Step ").count());
+        assert!(plan.unpinned_varlits().is_empty());
+        assert_eq!(plan.steps_taken(), merged.len());
+    }
+
+    #[test]
+    fn test_quick_solve_within() {
+        let budget = SearchBudget::default();
+        assert_eq!(
+            sudoku_4x4_planner().quick_solve_within(&budget).unwrap(),
+            sudoku_4x4_planner().quick_solve()
+        );
+        let mut plan = sudoku_4x4_planner();
+        assert!(plan.quick_solve_html_with(Some(&budget), &json).1);
+        assert!(plan.target_varlits().is_empty());
+
+        // Once cancelled, the solve stops before its next step
+        let cancel = CancelFlag::default();
+        cancel.cancel();
+        let budget = SearchBudget {
+            cancel: Some(cancel),
+            ..SearchBudget::default()
+        };
+        let mut plan = sudoku_4x4_planner();
+        let unsolved = plan.target_varlits().len();
+        assert!(plan.quick_solve_within(&budget).is_err());
+        assert!(!plan.quick_solve_html_with(Some(&budget), &json).1);
+        assert_eq!(plan.target_varlits().len(), unsolved);
+    }
+
+    #[test]
+    fn test_stop_condition_sudoku() {
+        let planner = |stop: &str| {
+            let config = PlannerConfig {
+                stop_condition: Some(stop.parse().unwrap()),
+                // Otherwise the whole puzzle is one step
+                merge_small_threshold: 0,
+                ..PlannerConfig::default()
+            };
+            sudoku_4x4_planner_with(config)
+        };
+
+        let solve = planner("steps:2").quick_solve_until_stopped();
+        assert_eq!(solve.steps.len(), 2);
+        assert_eq!(solve.reason, StopReason::Condition(StopCondition::Steps(2)));
+
+        // Cell (1,1) is a clue, so nothing is needed
+        let solve = planner("cell:1,1").quick_solve_until_stopped();
+        assert!(solve.steps.is_empty());
+
+        let mut plan = planner("cell:1,2");
+        let solve = plan.quick_solve_until_stopped();
+        assert_eq!(
+            solve.reason,
+            StopReason::Condition(StopCondition::CellDeduced(vec![1, 2]))
+        );
+        assert!(!plan.get_provable_varlits().is_empty());
+        assert!(plan.quick_solve_html_with(None, &json).0.is_empty());
+
+        let solve = planner("difficulty:1").quick_solve_until_stopped();
+        assert!(solve.steps.iter().flatten().all(|m| m.mus_len() <= 1));
+
+        let solve = planner("steps:1000").quick_solve_until_stopped();
+        assert_eq!(solve.reason, StopReason::Solved);
+
+        assert_eq!(
+            "cell: 1, 2".parse::<StopCondition>().unwrap().to_string(),
+            "cell:1,2"
+        );
+        assert!("cells:1".parse::<StopCondition>().is_err());
+    }
+
+    #[test]
+    fn test_cell_weights_sudoku() {
+        let planner = |weights: &[(Vec<i64>, f64)]| {
+            let config = PlannerConfig {
+                cell_weights: weights.iter().cloned().collect(),
+                // Otherwise the whole puzzle is one step
+                merge_small_threshold: 0,
+                ..PlannerConfig::default()
+            };
+            sudoku_4x4_planner_with(config)
+        };
+        let cells = |plan: &mut PuzzlePlanner, muses: &[MusContext]| -> BTreeSet<Vec<i64>> {
+            let solver: &PuzzleSolver = plan.solver();
+            muses
+                .iter()
+                .flat_map(|mc| &mc.lits)
+                .flat_map(|l| solver.lit_to_puzlit(l))
+                .map(|p| solver.puzzleparse().eprime.cell_of(&p.var()))
+                .collect()
+        };
+
+        // Cells with less weight are explained later
+        let mut plain = planner(&[]);
+        let first = plain.next_step();
+        let first_cells = cells(&mut plain, &first.muses);
+        let weights: Vec<_> = first_cells.iter().map(|c| (c.clone(), 0.5)).collect();
+        let mut weighted = planner(&weights);
+        let step = weighted.next_step();
+        assert_ne!(step.lits(), first.lits());
+
+        // A cell with weight 0 is never explained, but is still deduced
+        let mut plan = planner(&[(vec![1, 2], 0.0)]);
+        let steps = plan.quick_solve_muses();
+        assert!(!steps.is_empty());
+        for step in &steps {
+            assert!(!cells(&mut plan, step).contains(&vec![1, 2]));
+        }
+        assert!(plan.get_provable_varlits().is_empty());
+        let solver: &PuzzleSolver = plan.solver();
+        assert!(
+            solver
+                .get_known_lits()
+                .iter()
+                .flat_map(|l| solver.lit_to_puzlit(l))
+                .any(|p| p.sign() && *p.var().indices() == [1, 2])
+        );
+
+        let weight: CellWeight = "1, 2=0.5".parse().unwrap();
+        assert_eq!(
+            weight,
+            CellWeight {
+                cell: vec![1, 2],
+                weight: 0.5
+            }
+        );
+        assert!("1,2".parse::<CellWeight>().is_err());
+        assert!("1,2=-1".parse::<CellWeight>().is_err());
+    }
+
+    #[test]
+    fn test_stuck_needs_guess() {
+        let result = Arc::new(sudoku_4x4_with(
+            "sudoku-4x4-open.json",
+            "sudoku-4x4-open.dimacs",
+        ));
+        let mut plan = PuzzlePlanner::new(PuzzleSolver::new(result.clone()).unwrap());
+        let solve = plan.quick_solve_until_stopped();
+        let StopReason::Stuck { remaining_cells } = solve.reason else {
+            panic!("{:?}", solve.reason);
+        };
+        let summary = plan.solve_summary();
+        assert_eq!(remaining_cells, summary.open);
+        let guess = summary.guess.clone().unwrap();
+        assert!(
+            remaining_cells
+                .iter()
+                .all(|c| c.values.len() >= guess.values.len())
+        );
+        assert!(summary.text().contains("Stuck after "));
+        assert!(
+            summary
+                .text()
+                .contains(&format!("{guess} has the fewest values left"))
+        );
+        assert!(summary.guess_html().unwrap().contains("js_highlighter"));
+
+        let text = PuzzlePlanner::new(PuzzleSolver::new(result).unwrap()).quick_solve_text();
+        assert!(text.ends_with(&summary.guess_text().unwrap()), "{text}");
+    }
+
+    #[test]
+    fn test_locality_sudoku() {
+        // Take one MUS per step, so the choice between them matters
+        let config = PlannerConfig {
+            merge_small_threshold: 0,
+            expand_to_all_deductions: false,
+            ..PlannerConfig::default()
+        };
+        let plan = sudoku_4x4_planner_with(config);
+
+        // The spread and constraint groups of the MUS used at each step
+        let solve = |weight: f64| {
+            let mut plan = plan.fork();
+            plan.config_mut().locality_weight = weight;
+            let steps = plan.quick_solve_muses();
+            steps
+                .iter()
+                .flatten()
+                .map(|mc| {
+                    let groups: BTreeSet<String> = mc
+                        .mus
+                        .iter()
+                        .filter_map(|c| plan.puzzle().constraint_name(plan.puzzle().lit_to_con(c)))
+                        .collect();
+                    (format!("{:.2}", plan.mus_spread(mc)), groups)
+                })
+                .collect_vec()
+        };
+
+        let mean = |steps: &[(String, BTreeSet<String>)]| -> f64 {
+            let total: f64 = steps.iter().map(|(s, _)| s.parse::<f64>().unwrap()).sum();
+            total / steps.len() as f64
+        };
+
+        let base = solve(0.0);
+        let local = solve(10.0);
+
+        assert!(mean(&local) < mean(&base));
+        insta::assert_debug_snapshot!((base, local));
+    }
+
+    #[test]
+    fn test_prefer_assignments_sudoku() {
+        let plan = sudoku_4x4_planner();
+        let puz = plan.puzzle();
+        let lit = |s: &str| puz.litmap[&s.parse::<PuzLit>().unwrap()];
+        let (assign, elim) = (lit("grid[1, 2]=2"), lit("grid[1, 2]!=1"));
+        let mut cons = puz.conset.keys().copied();
+        let (first, second) = (cons.next().unwrap(), cons.next().unwrap());
+
+        // Two MUSes of one constraint each, which are otherwise kept in the
+        // order of their constraints
+        let chosen = |prefer, assign_con: Lit, elim_con: Lit| {
+            let config = PlannerConfig {
+                merge_small_threshold: 0,
+                expand_to_all_deductions: false,
+                prefer_assignments: prefer,
+                ..PlannerConfig::default()
+            };
+            let muses = vec![
+                MusContext::new(assign, BTreeSet::from([assign_con])),
+                MusContext::new(elim, BTreeSet::from([elim_con])),
+            ];
+            let (_, _, chosen) = sudoku_4x4_planner_with(config).choose_smallest_muses(muses);
+            assert_eq!(chosen.len(), 1);
+            chosen[0].mus.clone()
+        };
+
+        // The elimination comes first
+        assert_eq!(chosen(None, second, first), BTreeSet::from([first]));
+        assert_eq!(chosen(Some(false), second, first), BTreeSet::from([first]));
+        assert_eq!(chosen(Some(true), second, first), BTreeSet::from([second]));
+
+        // The assignment comes first
+        assert_eq!(chosen(None, first, second), BTreeSet::from([first]));
+        assert_eq!(chosen(Some(true), first, second), BTreeSet::from([first]));
+        assert_eq!(chosen(Some(false), first, second), BTreeSet::from([second]));
+    }
+
+    #[test]
+    fn test_constraint_weights_sudoku() {
+        let mut result = sudoku_4x4();
+
+        let planner = |result: &PuzzleParse, weighting| {
+            let puz = PuzzleSolver::new(Arc::new(result.clone())).unwrap();
+            // Take one MUS per step, so the choice between them matters
+            let config = PlannerConfig {
+                merge_small_threshold: 0,
+                expand_to_all_deductions: false,
+                constraint_weighting: weighting,
+                ..PlannerConfig::default()
+            };
+            PuzzlePlanner::new_with_config(puz, config)
+        };
+        let is_row = |puz: &PuzzleParse, c: &Lit| {
+            puz.constraint_name(puz.lit_to_con(c)).as_deref() == Some("row_alldiff")
+        };
+
+        // The size of the MUS used at each step, and whether it uses a row
+        let solve = |result: &PuzzleParse, weighting| {
+            let mut plan = planner(result, weighting);
+            let steps = plan.quick_solve_muses();
+            steps
+                .iter()
+                .flatten()
+                .map(|mc| (mc.mus_len(), mc.mus.iter().any(|c| is_row(result, c))))
+                .collect_vec()
+        };
+        let rows = |steps: &[(usize, bool)]| steps.iter().filter(|(_, row)| *row).count();
+
+        let base = solve(&result, ConstraintWeighting::TieBreak);
+        result
+            .eprime
+            .con_weights
+            .insert("row_alldiff".to_owned(), 10.0);
+        let tiebreak = solve(&result, ConstraintWeighting::TieBreak);
+
+        // Rows are still used when needed, but later and less often, without
+        // making any step harder
+        assert!(base[0].1);
+        assert!(!tiebreak[0].1);
+        assert!(rows(&tiebreak) < rows(&base));
+        assert_eq!(
+            tiebreak.iter().map(|s| s.0).collect_vec(),
+            base.iter().map(|s| s.0).collect_vec()
+        );
+
+        // A MUS of one row, against a larger one of lighter constraints
+        let row = *result.conset.keys().find(|c| is_row(&result, c)).unwrap();
+        let others = result
+            .conset
+            .keys()
+            .filter(|c| !is_row(&result, c))
+            .take(2)
+            .copied()
+            .collect::<BTreeSet<_>>();
+        let mut lits = result.varset_lits.iter().copied();
+        let (a, b) = (lits.next().unwrap(), lits.next().unwrap());
+        let mut muses = MusDict::new();
+        muses.add_mus(a, BTreeSet::from([row]));
+        muses.add_mus(b, others.clone());
+
+        let smallest = |weighting| planner(&result, weighting).smallest_in(&muses);
+        assert_eq!(
+            smallest(ConstraintWeighting::TieBreak),
+            vec![MusContext::new(a, BTreeSet::from([row]))]
+        );
+        assert_eq!(
+            smallest(ConstraintWeighting::WeightedSize),
+            vec![MusContext::new(b, others)]
+        );
+    }
+
+    #[test]
+    fn test_chained_singles_sudoku() {
+        let mut plain = sudoku_4x4_planner();
+        let mut fast = plain.fork();
+        fast.config_mut().chained_singles = true;
+
+        plain.quick_solve_muses();
+        let fast_steps = fast.quick_solve_muses();
+
+        assert!(!fast_steps.is_empty());
+        assert_eq!(fast.check_solvability(), Some(0));
+        assert_eq!(
+            fast.get_all_known_lits().len(),
+            plain.get_all_known_lits().len()
+        );
+
+        let stats = fast.chained_singles_stats();
+        assert!(stats.steps > 0);
+        assert!(stats.deductions > 0);
+        assert!(stats.solver_calls > 0);
+        assert_eq!(plain.chained_singles_stats(), Default::default());
+    }
+
+    #[test]
+    fn test_simple_facts_sudoku() {
+        let mut plain = sudoku_4x4_planner();
+        let mut fast = plain.fork();
+        fast.config_mut().simple_facts = true;
+
+        plain.quick_solve_muses();
+        fast.quick_solve_muses();
+
+        assert_eq!(fast.check_solvability(), Some(0));
+        assert_eq!(
+            fast.get_all_known_lits().len(),
+            plain.get_all_known_lits().len()
+        );
+        // The steps are no harder than those found with the SAT solver
+        assert_eq!(
+            fast.step_stats().mus_sizes.keys().max(),
+            plain.step_stats().mus_sizes.keys().max()
+        );
+
+        let stats = fast.simple_facts_stats();
+        assert!(stats.steps > 0);
+        assert!(stats.deductions > 0);
+        assert_eq!(plain.simple_facts_stats(), Default::default());
+    }
+
+    #[test]
+    fn test_problem_after_step() {
+        let mut plan = sudoku_4x4_planner();
+
+        // Only the cells then solved, without any candidates
+        let solved = |plan: &PuzzlePlanner, step: usize| {
+            let problem = plan.problem_after_step(step).unwrap();
+            let cells: Vec<_> = problem
+                .state
+                .unwrap()
+                .knowledge_grid
+                .unwrap()
+                .into_iter()
+                .flatten()
+                .flatten()
+                .collect();
+            assert!(cells.iter().all(|lits| lits.len() == 1));
+            cells.len()
+        };
+
+        // The first steps may only rule out values, without solving a cell
+        let mut steps = 0;
+        while solved(&plan, steps) == 8 {
+            assert!(!plan.get_provable_varlits().is_empty());
+            let step = plan.next_step();
+            plan.apply_step(&step);
+            steps += 1;
+        }
+        assert!(steps > 0);
+        assert_eq!(solved(&plan, 0), 8);
+        assert_eq!(solved(&plan, steps - 1), 8);
+        assert_eq!(solved(&plan, steps), solved(&plan, usize::MAX));
+    }
+
+    #[test]
+    fn test_step_problem_metadata() {
+        use crate::json::{Class, LitStatus};
+
+        let mut plan = sudoku_4x4_planner();
+        let mut constraints_seen = false;
+
+        while !plan.get_provable_varlits().is_empty() {
+            let step = plan.next_step();
+            let state = plan.step_problem(&step).state.unwrap();
+            let lits: Vec<_> = state
+                .knowledge_grid
+                .unwrap()
+                .into_iter()
+                .flatten()
+                .flatten()
+                .flatten()
+                .collect();
+
+            for lit in &lits {
+                let meta = lit.meta.as_ref().unwrap();
+                let classes = &lit.classes;
+                assert_eq!(
+                    meta.status == LitStatus::Known,
+                    classes.contains(&Class::LitKnown)
+                );
+                assert_eq!(
+                    meta.status == LitStatus::Given,
+                    classes.contains(&Class::LitGiven)
+                );
+                assert_eq!(meta.deduced == Some(true), classes.contains(&Class::LitPos));
+                assert_eq!(
+                    meta.deduced == Some(false),
+                    classes.contains(&Class::LitNeg)
+                );
+                assert_eq!(
+                    !meta.constraints.is_empty(),
+                    classes.contains(&Class::LitInMus)
+                );
+                if meta.deduced.is_some() {
+                    assert_eq!(meta.step, Some(plan.steps_taken() + 1));
+                    assert!(meta.group.is_some());
+                } else {
+                    assert_eq!(meta.step, None);
+                }
+            }
+
+            // The 8 values filled in by the puzzle, but not the 0s marking
+            // empty cells
+            let givens = lits
+                .iter()
+                .filter(|l| l.meta.as_ref().unwrap().status == LitStatus::Given);
+            assert_eq!(givens.count(), 8);
+
+            for statement in state.statements.unwrap() {
+                if statement.constraint.is_some() {
+                    constraints_seen = true;
+                    assert_eq!(statement.scope_cells.len(), statement.scope.len());
+                    assert!(lits.iter().any(|l| {
+                        l.meta
+                            .as_ref()
+                            .unwrap()
+                            .constraints
+                            .contains(&statement.content)
+                    }));
+                }
+            }
+
+            plan.apply_step(&step);
+        }
+
+        assert!(constraints_seen);
+    }
+
+    #[test]
+    fn test_step_alternatives_sudoku() {
+        let config = PlannerConfig {
+            merge_small_threshold: 0,
+            ..PlannerConfig::default()
+        };
+        let mut plan = sudoku_4x4_planner_with(config);
+
+        // The first step has many equally small deductions, of which one is shown
+        let step = plan.next_step();
+        assert_eq!(step.muses.len(), 1);
+        assert!(step.alternatives > 1, "{}", step.alternatives);
+        let drawn = json(&plan.step_problem(&step));
+        assert!(
+            drawn.contains(&format!("one of {} equally simple", step.alternatives)),
+            "{drawn}"
+        );
+        let explanation = plan.step_problem(&step).state.unwrap().explanation.unwrap();
+        assert_eq!(explanation, plan.explain_step(&step));
+        assert!(
+            explanation
+                .text()
+                .starts_with(&format!("One of {} ", step.alternatives))
+        );
+        // Those which are the same reasoning elsewhere are counted too
+        assert!((1..=step.alternatives).contains(&step.symmetric));
+        assert_eq!(explanation.symmetric, step.symmetric);
+        assert_eq!(
+            drawn.contains("symmetric places"),
+            step.symmetric > 1,
+            "{drawn}"
+        );
+
+        // Singles are all shown, so there is no choice to mention
+        plan.config_mut().chained_singles = true;
+        let step = plan.next_step();
+        assert_eq!(step.alternatives, step.muses.len());
+        assert_eq!(step.symmetric, 1);
+        assert!(!json(&plan.step_problem(&step)).contains("equally simple"));
+    }
+
+    #[test]
+    fn test_muses_within_sudoku() {
+        let mut plan = sudoku_4x4_planner();
+
+        let row: BTreeSet<Vec<i64>> = (1..=4).map(|j| vec![1, j]).collect();
+        let step = plan.next_step_within(&row);
+        assert!(!step.muses.is_empty());
+        for con in step.muses.iter().flat_map(|m| &m.mus) {
+            let scope = plan
+                .puzzle()
+                .constraint_scope(plan.puzzle().lit_to_con(con));
+            assert!(scope.iter().any(|vvp| row.contains(vvp.var().indices())));
+        }
+
+        // The step can be applied to the whole puzzle
+        plan.apply_step(&step);
+        assert_eq!(plan.steps_taken(), 1);
+        // Only deductions which need no constraints are left
+        let muses = plan.muses_within(&BTreeSet::new());
+        assert!(muses.iter().all(|m| m.mus.is_empty()));
+    }
+
+    #[test]
+    fn test_step_problem_has_no_side_effects() {
+        let mut plan = sudoku_4x4_planner();
+
+        let known = plan.get_all_known_lits();
+        let provable = plan.get_provable_varlits();
+
+        let step = plan.next_step();
+        assert!(!step.lits().is_empty());
+        assert_eq!(plan.get_all_known_lits(), known);
+
+        let problem = plan.step_problem(&step);
+        assert_eq!(plan.step_problem(&step), problem);
+        assert_eq!(plan.state_problem(""), plan.state_problem(""));
+        assert_eq!(plan.get_all_known_lits(), known);
+        assert_eq!(plan.get_provable_varlits(), provable);
+
+        plan.apply_step(&step);
+        let applied = plan.get_all_known_lits().len();
+        let deduced: BTreeSet<_> = step.lits().into_iter().collect();
+        assert_eq!(applied, known.len() + deduced.len());
+
+        // Applying the same step again adds nothing new
+        plan.apply_step(&step);
+        assert_eq!(plan.get_all_known_lits().len(), applied);
+    }
+
+    #[test]
+    fn test_fork_sudoku_from_dimacs() {
+        let mut plan = sudoku_4x4_planner();
+        let mut other = plan.fork();
+
+        let start = plan.get_all_known_lits().len();
+        assert_eq!(other.get_all_known_lits().len(), start);
+
+        let (_, lits) = plan.quick_solve_step();
+        assert!(!lits.is_empty());
+
+        // Progress in one copy does not leak into the other
+        assert!(plan.get_all_known_lits().len() > start);
+        assert_eq!(other.get_all_known_lits().len(), start);
+        assert!(std::ptr::eq(plan.puzzle(), other.puzzle()));
+
+        other.quick_solve();
+        assert_eq!(other.check_solvability(), Some(0));
+    }
+
+    #[test]
+    fn test_replay_sudoku_from_dimacs() {
+        let mut plan = sudoku_4x4_planner();
+        plan.record_repro();
+        let sequence = plan.quick_solve_muses();
+        let report = plan.take_repro_report().unwrap();
+
+        assert!(!report.steps.is_empty());
+
+        let mut replay = sudoku_4x4_planner();
+        let replayed = replay.replay(&report).unwrap();
+
+        assert_eq!(sequence, replayed);
+        assert!(replay.get_provable_varlits().is_empty());
+    }
+
+    #[test]
+    fn test_solvability_little_essence() {
+        let result = crate::problem::util::test_utils::build_puzzleparse(
+            "./tst/little1.eprime",
+            "./tst/little1.param",
+        );
+
+        let result = Arc::new(result);
+
+        let puz = PuzzleSolver::new(result).unwrap();
+
+        let mut plan = PuzzlePlanner::new(puz);
+
+        assert_eq!(plan.check_solvability(), Some(0));
+    }
+
+    // This test doesn't really do any deep tests,
+    // just do a full end-to-end run
+    #[test]
+    fn test_plan_binairo_essence() {
+        let result = crate::problem::util::test_utils::build_puzzleparse(
+            "./tst/binairo.eprime",
+            "./tst/binairo-1.param",
+        );
+
+        let result = Arc::new(result);
+
+        let puz = PuzzleSolver::new(result).unwrap();
+
+        let mut plan = PuzzlePlanner::new(puz);
+
+        let sequence = plan.quick_solve();
+
+        assert_eq!(sequence.iter().flatten().collect_vec().len(), 21);
+
+        for (litset, cons) in sequence.iter().flatten() {
+            assert!(!litset.is_empty());
+            // If this next line starts failing, it can be commented out.
+            assert!(cons.len() <= 2);
+        }
+    }
+
+    // This test doesn't really do any deep tests,
+    // just do a full end-to-end run
+    #[test]
+    fn test_plan_minesweeper_essence() {
+        let result = crate::problem::util::test_utils::build_puzzleparse(
+            "./tst/minesweeper.eprime",
+            "./tst/minesweeperPrinted.param",
+        );
+
+        let result = Arc::new(result);
+
+        let puz = PuzzleSolver::new(result).unwrap();
+
+        let mut plan = PuzzlePlanner::new(puz);
+
+        let sequence = plan.quick_solve();
+
+        assert_eq!(sequence.iter().flatten().collect_vec().len(), 9);
+
+        for (litset, cons) in sequence.iter().flatten() {
+            assert!(!litset.is_empty());
+            // If this next line starts failing, it can be commented out.
+            assert!(cons.len() <= 2);
+        }
+    }
+
+    #[test]
+    fn test_varlits_minesweeper_essence() {
+        let result = crate::problem::util::test_utils::build_puzzleparse(
+            "./tst/minesweeper.eprime",
+            "./tst/minesweeperPrinted.param",
+        );
+
+        let result = Arc::new(result);
+
+        let puz = PuzzleSolver::new(result).unwrap();
+
+        let mut plan = PuzzlePlanner::new(puz);
+
+        let first_step = plan.get_provable_varlits();
+
+        let all_steps = plan.get_provable_varlits_including_reveals();
+
+        let first_step: BTreeSet<_> = first_step
+            .into_iter()
+            .map(|x| plan.psolve.lit_to_puzlit(&x).clone())
+            .collect();
+
+        let all_steps: BTreeSet<_> = all_steps
+            .into_iter()
+            .map(|x| plan.psolve.lit_to_puzlit(&x).clone())
+            .collect();
+
+        insta::assert_debug_snapshot!(first_step);
+        insta::assert_debug_snapshot!(all_steps);
+    }
+
+    #[test]
+    fn test_plan_minesweeper_wall_essence() {
+        let result = crate::problem::util::test_utils::build_puzzleparse(
+            "./tst/minesweeper.eprime",
+            "./tst/minesweeperWall.param",
+        );
+
+        let result = Arc::new(result);
+
+        let puz = PuzzleSolver::new(result).unwrap();
+
+        let mut plan = PuzzlePlanner::new(puz);
+
+        let sequence = plan.quick_solve();
+
+        // Warning: This number may change as MUS detection / merging improves.
+        // Changes should be sanity checked by printing out the sequence.
+        assert_eq!(sequence.iter().flatten().collect_vec().len(), 8);
+
+        for (litset, cons) in sequence.iter().flatten() {
+            assert!(!litset.is_empty());
+            // If this next line starts failing, it can be commented out.
+            assert!(cons.len() <= 2);
+        }
+    }
+
+    #[test]
+    fn test_solvability_minesweeper_wall_essence() {
+        let result = crate::problem::util::test_utils::build_puzzleparse(
+            "./tst/minesweeper.eprime",
+            "./tst/minesweeperWall.param",
+        );
+
+        let result = Arc::new(result);
+
+        let puz = PuzzleSolver::new(result).unwrap();
+
+        let mut plan = PuzzlePlanner::new(puz);
+
+        assert_eq!(plan.check_solvability(), Some(20));
+    }
+
+    // This test doesn't really do any deep tests,
+    // just do a full end-to-end run
+    #[test]
+    fn test_plan_binairo_essence_drawn() {
+        let result = crate::problem::util::test_utils::build_puzzleparse(
+            "./tst/binairo.eprime",
+            "./tst/binairo-1.param",
+        );
+
+        let result = Arc::new(result);
+
+        let puz = PuzzleSolver::new(result).unwrap();
+
+        let mut plan = PuzzlePlanner::new(puz);
+
+        let _ = plan.quick_solve_html_with(None, &json).0;
+    }
+}
@@ -0,0 +1,166 @@
+//! Simple deductions found by unit propagation, without the SAT solver.
+//!
+//! Many steps of a solve are easy, such as a cell with one value left, or a
+//! value ruled out by a single constraint. Unit propagation over the clauses
+//! of the puzzle finds most of these far more cheaply than probing with the
+//! SAT solver. The clauses which mention no constraint (such as those saying
+//! each cell takes one value) always apply, and the clauses of one constraint
+//! are added at a time, so each deduction comes with the constraints it
+//! needs, as a MUS would. Unit propagation is incomplete, so anything it
+//! misses is left to the SAT solver.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use rustsat::types::Lit;
+
+use super::{musdict::MusDict, parse::PuzzleParse};
+
+/// The clauses of a puzzle, arranged for unit propagation.
+#[derive(Clone, Debug, Default)]
+pub struct Propagator {
+    clauses: Vec<Vec<Lit>>,
+    /// The constraint each clause belongs to, or `None` for clauses which
+    /// mention no constraint
+    owner: Vec<Option<Lit>>,
+    /// The clauses of each constraint
+    by_con: BTreeMap<Lit, Vec<usize>>,
+    /// The clauses containing each literal
+    occurs: HashMap<Lit, Vec<usize>>,
+}
+
+impl Propagator {
+    /// Arranges the clauses of `puzzle`. A clause belongs to a constraint if
+    /// it mentions that constraint once, negated, and no other. Clauses which
+    /// mention constraints in any other way are left out, which only means
+    /// fewer deductions are found.
+    #[must_use]
+    pub fn new(puzzle: &PuzzleParse) -> Self {
+        let mut propagator = Self::default();
+        let Some(cnf) = puzzle.cnf.as_ref() else {
+            return propagator;
+        };
+
+        for clause in cnf.iter() {
+            let lits: Vec<Lit> = clause.iter().copied().collect();
+            let cons: Vec<Lit> = lits
+                .iter()
+                .copied()
+                .filter(|l| puzzle.conset_lits.contains(l) || puzzle.conset_lits.contains(&!*l))
+                .collect();
+            let owner = match cons.as_slice() {
+                [] => None,
+                [con] if !puzzle.conset_lits.contains(con) => Some(!*con),
+                _ => continue,
+            };
+
+            let index = propagator.clauses.len();
+            if let Some(con) = owner {
+                propagator.by_con.entry(con).or_default().push(index);
+            }
+            for &l in &lits {
+                propagator.occurs.entry(l).or_default().push(index);
+            }
+            propagator.clauses.push(lits);
+            propagator.owner.push(owner);
+        }
+        propagator
+    }
+
+    /// The deductions which follow by unit propagation from `known`, each
+    /// with the constraints it needs. If any need no constraint, only those
+    /// are returned. Otherwise each constraint is tried on its own, giving
+    /// MUSes of size 1. Only literals for which `wanted` holds are returned.
+    #[must_use]
+    pub fn deductions(&self, known: &[Lit], wanted: impl Fn(Lit) -> bool) -> MusDict {
+        let mut md = MusDict::new();
+        let mut assigned: HashSet<Lit> = known.iter().copied().collect();
+        let mut trail = vec![];
+
+        let base = (0..self.clauses.len()).filter(|&i| self.owner[i].is_none());
+        if !self.propagate(&mut assigned, &mut trail, base.collect(), None) {
+            // The known literals are inconsistent, so leave it to the solver
+            return md;
+        }
+        for &lit in trail.iter().filter(|&&l| wanted(l)) {
+            md.add_mus(lit, BTreeSet::new());
+        }
+        if !md.is_empty() {
+            return md;
+        }
+        trail.clear();
+
+        for (&con, clauses) in &self.by_con {
+            assigned.insert(con);
+            if self.propagate(&mut assigned, &mut trail, clauses.clone(), Some(con)) {
+                for &lit in trail.iter().filter(|&&l| wanted(l)) {
+                    md.add_mus(lit, BTreeSet::from([con]));
+                }
+            }
+            assigned.remove(&con);
+            for lit in trail.drain(..) {
+                assigned.remove(&lit);
+            }
+        }
+        md
+    }
+
+    /// Unit propagation from the clauses in `queue`, using only those which
+    /// mention no constraint or belong to `con`. Each literal found is added
+    /// to `assigned` and `trail`. Returns false on a conflict.
+    fn propagate(
+        &self,
+        assigned: &mut HashSet<Lit>,
+        trail: &mut Vec<Lit>,
+        mut queue: Vec<usize>,
+        con: Option<Lit>,
+    ) -> bool {
+        while let Some(index) = queue.pop() {
+            if self.owner[index].is_some() && self.owner[index] != con {
+                continue;
+            }
+            let clause = &self.clauses[index];
+            if clause.iter().any(|l| assigned.contains(l)) {
+                continue;
+            }
+            let mut open = clause.iter().filter(|&l| !assigned.contains(&!*l));
+            match (open.next(), open.next()) {
+                (None, _) => return false,
+                (Some(&unit), None) => {
+                    assigned.insert(unit);
+                    trail.push(unit);
+                    if let Some(clauses) = self.occurs.get(&!unit) {
+                        queue.extend(clauses);
+                    }
+                }
+                _ => {}
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::problem::{solver::PuzzleSolver, util::test_utils::sudoku_4x4};
+
+    #[test]
+    fn test_deductions_are_provable() {
+        let puzzle = sudoku_4x4();
+        let propagator = Propagator::new(&puzzle);
+        let mut psolve = PuzzleSolver::new(Arc::new(puzzle)).unwrap();
+
+        let provable = psolve.get_provable_varlits().clone();
+        let candidates = psolve.get_literals_to_try_solving();
+        let md = propagator.deductions(&psolve.get_known_lits(), |l| candidates.contains(&!l));
+        assert!(!md.is_empty());
+        for (lit, muses) in md.muses() {
+            assert!(provable.contains(lit));
+            for mus in muses {
+                assert!(mus.mus_len() <= 1);
+            }
+        }
+    }
+}
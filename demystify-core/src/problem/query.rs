@@ -0,0 +1,504 @@
+//! A small language for picking out the steps of a solve recorded with
+//! `--repro-report`, such as `steps where mus_size > 4 and constraint ~ 'knight'`.
+//!
+//! A query is `[steps] [where] <condition>`. A condition compares a field with
+//! a number or a quoted string, using `=`, `!=`, `<`, `<=`, `>`, `>=`, or `~`
+//! (contains, ignoring case), and conditions are combined with `and`, `or`,
+//! `not` and brackets. The fields are:
+//!
+//! * `step`: the number of the step, counting from 1
+//! * `mus_size`: the number of constraints in each MUS of the step
+//! * `candidates`: how many equally small MUSes there were to choose from
+//! * `constraint`: the names of the `$#CON`s used
+//! * `cell`: the indices of the cells deduced, such as `'1,2'`
+//!
+//! A step can have several MUSes, constraints and cells, and a comparison
+//! holds if it holds for any of them.
+
+use std::collections::BTreeSet;
+
+use itertools::Itertools;
+use serde::Serialize;
+
+use super::{
+    PuzLit,
+    repro::{ReproReport, ReproStep},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    Step,
+    MusSize,
+    Candidates,
+    Constraint,
+    Cell,
+}
+
+impl Field {
+    const ALL: [(&'static str, Field); 5] = [
+        ("step", Field::Step),
+        ("mus_size", Field::MusSize),
+        ("candidates", Field::Candidates),
+        ("constraint", Field::Constraint),
+        ("cell", Field::Cell),
+    ];
+
+    fn name(self) -> &'static str {
+        Field::ALL.iter().find(|(_, f)| *f == self).unwrap().0
+    }
+
+    fn is_text(self) -> bool {
+        matches!(self, Field::Constraint | Field::Cell)
+    }
+
+    /// The values of this field for `step`, the `index`th step.
+    fn values(self, index: usize, step: &ReproStep) -> Vec<Value> {
+        match self {
+            Field::Step => vec![Value::Number(index as i64)],
+            Field::MusSize => step
+                .muses
+                .iter()
+                .map(|m| Value::Number(m.mus.iter().map(PuzLit::var).unique().count() as i64))
+                .collect(),
+            Field::Candidates => vec![Value::Number(step.candidates as i64)],
+            Field::Constraint => step
+                .muses
+                .iter()
+                .flat_map(|m| &m.mus)
+                .map(|p| p.var().name().clone())
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .map(Value::Text)
+                .collect(),
+            Field::Cell => step
+                .muses
+                .iter()
+                .flat_map(|m| &m.lits)
+                .map(|p| p.var().indices().iter().join(","))
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .map(Value::Text)
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+impl Op {
+    fn symbol(self) -> &'static str {
+        match self {
+            Op::Eq => "=",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::Contains => "~",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Value {
+    Number(i64),
+    Text(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Expr {
+    Compare(Field, Op, Value),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+}
+
+impl Expr {
+    fn matches(&self, index: usize, step: &ReproStep) -> bool {
+        match self {
+            Expr::Compare(field, op, value) => field
+                .values(index, step)
+                .iter()
+                .any(|v| compare(v, *op, value)),
+            Expr::And(a, b) => a.matches(index, step) && b.matches(index, step),
+            Expr::Or(a, b) => a.matches(index, step) || b.matches(index, step),
+            Expr::Not(a) => !a.matches(index, step),
+        }
+    }
+}
+
+fn compare(actual: &Value, op: Op, expected: &Value) -> bool {
+    match (actual, expected) {
+        (Value::Number(a), Value::Number(b)) => match op {
+            Op::Eq => a == b,
+            Op::Ne => a != b,
+            Op::Lt => a < b,
+            Op::Le => a <= b,
+            Op::Gt => a > b,
+            Op::Ge => a >= b,
+            Op::Contains => false,
+        },
+        (Value::Text(a), Value::Text(b)) => match op {
+            Op::Eq => a == b,
+            Op::Ne => a != b,
+            Op::Contains => a.to_lowercase().contains(&b.to_lowercase()),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Number(i64),
+    Text(String),
+    Op(Op),
+    Open,
+    Close,
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = s.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '(' || c == ')' {
+            chars.next();
+            tokens.push(if c == '(' { Token::Open } else { Token::Close });
+        } else if c == '\'' || c == '"' {
+            chars.next();
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    Some(d) if d == c => break,
+                    Some(d) => text.push(d),
+                    None => return Err(format!("Missing closing {c} after '{text}'")),
+                }
+            }
+            tokens.push(Token::Text(text));
+        } else if c.is_ascii_digit() || c == '-' {
+            chars.next();
+            let mut number = c.to_string();
+            while let Some(&d) = chars.peek()
+                && d.is_ascii_digit()
+            {
+                number.push(d);
+                chars.next();
+            }
+            let number = number
+                .parse()
+                .map_err(|_| format!("Invalid number '{number}'"))?;
+            tokens.push(Token::Number(number));
+        } else if c.is_alphabetic() || c == '_' {
+            let mut word = String::new();
+            while let Some(&d) = chars.peek()
+                && (d.is_alphanumeric() || d == '_')
+            {
+                word.push(d);
+                chars.next();
+            }
+            tokens.push(Token::Word(word));
+        } else {
+            chars.next();
+            let next = chars.peek().copied();
+            let (op, double) = match (c, next) {
+                ('=', Some('=')) => (Op::Eq, true),
+                ('!', Some('=')) => (Op::Ne, true),
+                ('<', Some('=')) => (Op::Le, true),
+                ('>', Some('=')) => (Op::Ge, true),
+                ('=', _) => (Op::Eq, false),
+                ('<', _) => (Op::Lt, false),
+                ('>', _) => (Op::Gt, false),
+                ('~', _) => (Op::Contains, false),
+                _ => return Err(format!("Unexpected '{c}'")),
+            };
+            if double {
+                chars.next();
+            }
+            tokens.push(Token::Op(op));
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Skips `keyword` (ignoring case) if it comes next.
+    fn keyword(&mut self, keyword: &str) -> bool {
+        if let Some(Token::Word(w)) = self.peek()
+            && w.eq_ignore_ascii_case(keyword)
+        {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.not()?;
+        while self.keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        if self.peek() == Some(&Token::Open) {
+            self.pos += 1;
+            let expr = self.or()?;
+            if self.next() != Some(Token::Close) {
+                return Err("Missing ')'".to_owned());
+            }
+            return Ok(expr);
+        }
+        self.compare()
+    }
+
+    fn compare(&mut self) -> Result<Expr, String> {
+        let fields = || Field::ALL.iter().map(|(name, _)| name).join(", ");
+
+        let field = match self.next() {
+            Some(Token::Word(w)) => Field::ALL
+                .iter()
+                .find(|(name, _)| w.eq_ignore_ascii_case(name))
+                .map(|&(_, field)| field)
+                .ok_or_else(|| format!("Unknown field '{w}', expected one of {}", fields()))?,
+            other => return Err(format!("Expected a field ({}), got {other:?}", fields())),
+        };
+        let op = match self.next() {
+            Some(Token::Op(op)) => op,
+            other => return Err(format!("Expected a comparison, got {other:?}")),
+        };
+        let value = match self.next() {
+            Some(Token::Number(n)) => Value::Number(n),
+            Some(Token::Text(t)) => Value::Text(t),
+            other => return Err(format!("Expected a number or quoted text, got {other:?}")),
+        };
+
+        match (&value, field.is_text()) {
+            (Value::Number(_), true) => {
+                return Err(format!("{} is compared with text, in quotes", field.name()));
+            }
+            (Value::Text(_), false) => {
+                return Err(format!("{} is compared with a number", field.name()));
+            }
+            _ => {}
+        }
+        let allowed = if field.is_text() {
+            matches!(op, Op::Eq | Op::Ne | Op::Contains)
+        } else {
+            op != Op::Contains
+        };
+        if !allowed {
+            return Err(format!(
+                "{} cannot be compared with {}",
+                field.name(),
+                op.symbol()
+            ));
+        }
+
+        Ok(Expr::Compare(field, op, value))
+    }
+}
+
+/// A parsed query, see the [module documentation](self).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Query(Expr);
+
+impl std::str::FromStr for Query {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+        };
+        parser.keyword("steps");
+        parser.keyword("where");
+        let expr = parser.or()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("Unexpected {token:?} at the end of the query"));
+        }
+        Ok(Query(expr))
+    }
+}
+
+/// Bump this whenever the layout of [`QueryDump`] changes.
+pub const QUERY_FORMAT_VERSION: u32 = 1;
+
+/// The steps matched by a query, as printed as JSON by `demystify-query`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct QueryDump<'a> {
+    pub format_version: u32,
+    pub steps: Vec<QueryMatch<'a>>,
+}
+
+impl<'a> QueryDump<'a> {
+    #[must_use]
+    pub fn new(steps: Vec<QueryMatch<'a>>) -> Self {
+        Self {
+            format_version: QUERY_FORMAT_VERSION,
+            steps,
+        }
+    }
+}
+
+/// A step which matched a [`Query`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct QueryMatch<'a> {
+    /// The number of the step, counting from 1
+    pub step: usize,
+    #[serde(flatten)]
+    pub trace: &'a ReproStep,
+}
+
+impl std::fmt::Display for QueryMatch<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Step {}", self.step)?;
+        if self.trace.candidates > 1 {
+            write!(f, " ({} candidates)", self.trace.candidates)?;
+        }
+        for mus in &self.trace.muses {
+            write!(
+                f,
+                "\n  {} by {}",
+                mus.lits.iter().join(", "),
+                mus.mus.iter().map(PuzLit::var).unique().join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Query {
+    /// The steps of `report` this query matches.
+    #[must_use]
+    pub fn matching_steps<'a>(&self, report: &'a ReproReport) -> Vec<QueryMatch<'a>> {
+        report
+            .steps
+            .iter()
+            .enumerate()
+            .map(|(i, trace)| QueryMatch { step: i + 1, trace })
+            .filter(|m| self.0.matches(m.step, m.trace))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::problem::{PuzVar, VarValPair, repro::RecordedMus};
+
+    use super::*;
+
+    fn lit(name: &str, indices: Vec<i64>, val: i64) -> PuzLit {
+        PuzLit::new_eq(VarValPair::new(&PuzVar::new(name, indices), val))
+    }
+
+    fn step(candidates: usize, cell: Vec<i64>, cons: &[&str]) -> ReproStep {
+        ReproStep {
+            candidates,
+            muses: vec![RecordedMus {
+                lits: vec![lit("grid", cell, 1)],
+                // Recorded MUSes have both literals of each constraint
+                mus: cons
+                    .iter()
+                    .flat_map(|c| [lit(c, vec![1], 0), lit(c, vec![1], 1)])
+                    .collect(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_query() {
+        let report = ReproReport {
+            steps: vec![
+                step(1, vec![1, 1], &["row"]),
+                step(2, vec![1, 2], &["row", "knight_move", "box", "col", "diag"]),
+                step(1, vec![2, 2], &["Knight_move", "box"]),
+            ],
+            ..ReproReport::default()
+        };
+
+        let steps = |q: &str| {
+            q.parse::<Query>()
+                .unwrap()
+                .matching_steps(&report)
+                .iter()
+                .map(|m| m.step)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            steps("steps where mus_size > 4 and constraint ~ 'knight'"),
+            vec![2]
+        );
+        assert_eq!(steps("constraint ~ \"KNIGHT\""), vec![2, 3]);
+        assert_eq!(steps("constraint = 'row'"), vec![1, 2]);
+        assert_eq!(steps("where not (step = 1 or cell = '2,2')"), vec![2]);
+        assert_eq!(steps("candidates >= 2 or step==3"), vec![2, 3]);
+        assert_eq!(steps("cell ~ '2'"), vec![2, 3]);
+        assert_eq!(steps("mus_size != 1"), vec![2, 3]);
+
+        for bad in [
+            "",
+            "size > 4",
+            "mus_size > 'a'",
+            "cell < '1'",
+            "constraint = 4",
+            "step ~ 1",
+            "(step = 1",
+            "step = 1 step",
+            "constraint = 'row",
+        ] {
+            assert!(bad.parse::<Query>().is_err(), "{bad}");
+        }
+
+        let m = &"step = 2".parse::<Query>().unwrap().matching_steps(&report)[0];
+        assert!(
+            m.to_string()
+                .starts_with("Step 2 (2 candidates)\n  grid[1, 2]=1 by row[1]")
+        );
+        let json = serde_json::to_value(m).unwrap();
+        assert_eq!(json["step"], 2);
+        assert_eq!(json["candidates"], 2);
+    }
+}
@@ -0,0 +1,164 @@
+//! Recording and replaying the choices made during a solve.
+//!
+//! MUSes are searched for in parallel, with shuffled constraints, so two runs
+//! on the same puzzle can explain it with different MUSes, even when seeded.
+//! A [`ReproReport`] records the outcome of every such choice, so a solve can
+//! be replayed exactly, for example when investigating a bug report about a
+//! particular explanation. This includes choices made at random by
+//! [`PlannerConfig::variety_seed`](super::planner::PlannerConfig::variety_seed).
+
+use anyhow::{Context, bail};
+use serde::{Deserialize, Serialize};
+
+use super::{PuzLit, musdict::MusContext, parse::PuzzleParse, util::format::Format};
+
+/// Bump this whenever the layout of [`ReproReport`] changes.
+pub const REPRO_FORMAT_VERSION: u32 = 1;
+
+/// How [`ReproReport`]s are read back. Add an upgrade from the previous
+/// version whenever [`REPRO_FORMAT_VERSION`] is bumped.
+pub const REPRO_FORMAT: Format = Format {
+    name: "Reproducibility report",
+    version: REPRO_FORMAT_VERSION,
+    oldest: 1,
+    upgrades: &[],
+};
+
+/// A single MUS, stored by name so it is stable across re-parses of the same puzzle.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedMus {
+    /// The literals deduced
+    pub lits: Vec<PuzLit>,
+    /// The `$#CON` constraints used to deduce them
+    pub mus: Vec<PuzLit>,
+}
+
+/// The choice made at one step of a solve.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReproStep {
+    /// How many equally small MUSes were available to choose between. When
+    /// this is more than one, which MUS was found first depends on thread
+    /// timing and random draws, so this step may differ between runs.
+    pub candidates: usize,
+    /// The MUSes which were used
+    pub muses: Vec<RecordedMus>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReproReport {
+    pub format_version: u32,
+    pub steps: Vec<ReproStep>,
+}
+
+impl Default for ReproReport {
+    fn default() -> Self {
+        Self {
+            format_version: REPRO_FORMAT_VERSION,
+            steps: vec![],
+        }
+    }
+}
+
+impl ReproReport {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records one step, where `candidates` equally small MUSes were found and `muses` were used.
+    pub fn record(&mut self, puzzle: &PuzzleParse, candidates: usize, muses: &[MusContext]) {
+        let to_puzlits = |lits: &std::collections::BTreeSet<rustsat::types::Lit>| {
+            lits.iter()
+                .flat_map(|l| puzzle.lit_to_vars(l))
+                .cloned()
+                .collect()
+        };
+
+        self.steps.push(ReproStep {
+            candidates,
+            muses: muses
+                .iter()
+                .map(|mc| RecordedMus {
+                    lits: to_puzlits(&mc.lits),
+                    mus: to_puzlits(&mc.mus),
+                })
+                .collect(),
+        });
+    }
+
+    /// Reads a report written as JSON, upgrading it if it was written by an
+    /// older version of demystify.
+    pub fn from_reader(reader: impl std::io::Read) -> anyhow::Result<Self> {
+        REPRO_FORMAT.from_reader(reader)
+    }
+
+    /// The steps which could have come out differently in another run.
+    #[must_use]
+    pub fn hazards(&self) -> Vec<usize> {
+        self.steps
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.candidates > 1)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Checks the report was written in a format this version understands.
+    pub fn check_version(&self) -> anyhow::Result<()> {
+        if self.format_version != REPRO_FORMAT_VERSION {
+            bail!(
+                "Reproducibility report has format version {}, expected {}",
+                self.format_version,
+                REPRO_FORMAT_VERSION
+            );
+        }
+        Ok(())
+    }
+}
+
+impl RecordedMus {
+    /// Converts back to a [`MusContext`] for `puzzle`.
+    pub fn to_muscontext(&self, puzzle: &PuzzleParse) -> anyhow::Result<MusContext> {
+        let lookup = |p: &PuzLit| {
+            puzzle
+                .litmap
+                .get(p)
+                .copied()
+                .with_context(|| format!("{p} from the report is not in this puzzle"))
+        };
+
+        Ok(MusContext::new_multi_lit(
+            self.lits
+                .iter()
+                .map(lookup)
+                .collect::<anyhow::Result<_>>()?,
+            self.mus.iter().map(lookup).collect::<anyhow::Result<_>>()?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repro_hazards() {
+        let mut report = ReproReport::new();
+        for candidates in [1, 3, 1, 2] {
+            report.steps.push(ReproStep {
+                candidates,
+                muses: vec![],
+            });
+        }
+        assert_eq!(report.hazards(), vec![1, 3]);
+        assert!(report.check_version().is_ok());
+
+        let json = serde_json::to_string(&report).unwrap();
+        assert_eq!(ReproReport::from_reader(json.as_bytes()).unwrap(), report);
+
+        report.format_version += 1;
+        assert!(report.check_version().is_err());
+        let json = serde_json::to_string(&report).unwrap();
+        assert!(ReproReport::from_reader(json.as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,123 @@
+//! Which cells the constraints of each `$#CON` cover, to show the rules of an
+//! unfamiliar kind of puzzle before stepping through its solve.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use super::parse::PuzzleParse;
+use crate::json::{Class, Puzzle, Statement};
+
+/// The cells of the grid covered by the constraints of one `$#CON`.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct RuleCoverage {
+    /// The name of the `$#CON`
+    pub name: String,
+    /// The description of one of its constraints, as an example
+    pub example: String,
+    /// The constraints with a cell of the grid in their scope
+    pub constraints: usize,
+    /// The (1-indexed) cells in the scope of any of those constraints
+    pub cells: BTreeSet<Vec<i64>>,
+}
+
+impl RuleCoverage {
+    /// The coverage of each `$#CON` of `parse` with a cell of the grid of
+    /// `puzzle` in its scope, by name. Only the scopes of the constraints
+    /// are used, so nothing is solved.
+    #[must_use]
+    pub fn all(parse: &PuzzleParse, puzzle: &Puzzle) -> Vec<RuleCoverage> {
+        let grid = parse.eprime.grid_var();
+        let mut rules: BTreeMap<String, RuleCoverage> = BTreeMap::new();
+        for con in &parse.conset_lits {
+            let Some(name) = parse
+                .invlitmap
+                .get(con)
+                .and_then(|puzlits| puzlits.first())
+                .map(|puzlit| puzlit.var().name().clone())
+            else {
+                continue;
+            };
+            let description = parse.lit_to_con(con);
+            let cells: BTreeSet<Vec<i64>> = parse
+                .constraint_scope_uncached(description)
+                .into_iter()
+                .filter(|vvp| vvp.var().name() == grid)
+                .map(|vvp| puzzle.cell_of(vvp.var()))
+                .filter(|cell| cell.len() == 2 && puzzle.is_cell(cell))
+                .collect();
+            if cells.is_empty() {
+                continue;
+            }
+            let rule = rules.entry(name.clone()).or_insert_with(|| RuleCoverage {
+                name,
+                example: description.clone(),
+                constraints: 0,
+                cells: BTreeSet::new(),
+            });
+            rule.constraints += 1;
+            rule.cells.extend(cells);
+        }
+        rules.into_values().collect()
+    }
+
+    /// Such as `row: 96 constraints over 16 cells, such as "..."`.
+    #[must_use]
+    pub fn describe(&self) -> String {
+        format!(
+            "{}: {} constraints over {} cells, such as \"{}\"",
+            self.name,
+            self.constraints,
+            self.cells.len(),
+            self.example
+        )
+    }
+
+    /// The `n`th rule of a list, with a box to show its cells (drawn in the
+    /// colour of `highlight_con<n>`), which is ticked if `shown`.
+    #[must_use]
+    pub fn statement(&self, n: usize, shown: bool) -> Statement {
+        Statement {
+            content: format!(
+                r#"<label><input type="checkbox" class="js_rule_toggle" data-rule="{n}"{}> {}</label>"#,
+                if shown { " checked" } else { "" },
+                tera::escape_html(&self.describe())
+            ),
+            classes: [Class::Highlight(n), Class::JsHighlighter].into(),
+            constraint: Some(self.name.clone()),
+            group: None,
+            scope: vec![],
+            scope_cells: self.cells.iter().cloned().collect(),
+            regions: vec![],
+            expression: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::problem::util::test_utils::sudoku_4x4_planner;
+
+    #[test]
+    fn test_rule_coverage() {
+        let mut plan = sudoku_4x4_planner();
+
+        let problem = plan.rules_problem();
+        let state = problem.state.as_ref().unwrap();
+        let rules = state.rule_coverage.as_ref().unwrap();
+        let names: Vec<_> = rules.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["box_alldiff", "con_alldiff", "row_alldiff"]);
+        for rule in rules {
+            // Every cell is in a row, a column and a box
+            assert_eq!(rule.cells.len(), 16);
+            assert!(rule.constraints > 0);
+            assert!(
+                rule.describe()
+                    .contains(" constraints over 16 cells, such as \"cells (")
+            );
+        }
+        assert_eq!(state.statements.as_ref().unwrap().len(), 3);
+        // Nothing is deduced
+        assert!(state.explanation.is_none());
+    }
+}
@@ -0,0 +1,253 @@
+//! A check that an installation of demystify works, run with
+//! `demystify self-test` before reporting a bug.
+//!
+//! The corpus puzzles of `demystify-core/tst`, those with a golden trace, are
+//! built into the binary. Each is read from
+//! its DIMACS, solved and compared with its golden trace, and the MUSes of its
+//! first few steps are checked to be minimal. Then conjure and savilerow are
+//! run (natively, or in Docker or Podman, as chosen with `--conjure`) on one
+//! of them, to check the external tools are set up.
+
+use std::{collections::BTreeSet, fs, path::Path, sync::Arc};
+
+use anyhow::{Context, bail};
+
+use super::{
+    assertions::CheckOutcome,
+    musdict::MusContext,
+    parse::{PuzzleParse, parse_eprime_with_dimacs, parse_essence_with},
+    planner::PuzzlePlanner,
+    solver::PuzzleSolver,
+    trace::SolveTrace,
+    util::{
+        exec::{Executor, ProgramRunner},
+        param::write_essence_param,
+    },
+};
+
+/// A corpus puzzle of `demystify-core/tst`, with its golden trace.
+struct Fixture {
+    name: &'static str,
+    model: &'static str,
+    param: &'static str,
+    dimacs: &'static str,
+    golden: &'static str,
+}
+
+macro_rules! corpus_file {
+    ($name:literal, $extension:literal) => {
+        include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tst/",
+            $name,
+            $extension
+        ))
+    };
+}
+
+/// The fixture `$name`, whose model is `$model.eprime` when several share it.
+macro_rules! fixture {
+    ($name:literal) => {
+        fixture!($name, $name)
+    };
+    ($name:literal, $model:literal) => {
+        Fixture {
+            name: $name,
+            model: corpus_file!($model, ".eprime"),
+            param: corpus_file!($name, ".json"),
+            dimacs: corpus_file!($name, ".dimacs"),
+            golden: corpus_file!($name, ".trace.json"),
+        }
+    };
+}
+
+const FIXTURES: [Fixture; 3] = [
+    fixture!("row-sum"),
+    fixture!("sudoku-4x4"),
+    fixture!("sudoku-4x4-open", "sudoku-4x4"),
+];
+
+/// The fixture conjure and savilerow are run on.
+const TOOLS_FIXTURE: usize = 1;
+
+/// How many steps of each solve have their MUSes checked to be minimal.
+const MINIMAL_STEPS: usize = 3;
+
+impl Fixture {
+    /// Writes the model, parameters and DIMACS into `dir`, and reads them
+    /// back.
+    fn parse(&self, dir: &Path) -> anyhow::Result<PuzzleParse> {
+        let model = dir.join(format!("{}.eprime", self.name));
+        let param = dir.join(format!("{}.json", self.name));
+        let dimacs = dir.join(format!("{}.dimacs", self.name));
+        fs::write(&model, self.model)?;
+        fs::write(&param, self.param)?;
+        fs::write(&dimacs, self.dimacs)?;
+        parse_eprime_with_dimacs(&model, &param, &dimacs)
+    }
+
+    /// Solves the puzzle, checking it makes the same deductions as the
+    /// golden trace.
+    fn check_trace(&self, puzzle: &Arc<PuzzleParse>) -> anyhow::Result<()> {
+        let expected = SolveTrace::from_reader(self.golden.as_bytes())?;
+        let mut planner = PuzzlePlanner::new(PuzzleSolver::new(puzzle.clone())?);
+        planner.quick_solve_trace().check_equivalent(&expected)
+    }
+
+    /// Runs savilerow on the model, through `executor`, checking the puzzle
+    /// has the same variables as when read from the DIMACS.
+    fn check_tools(
+        &self,
+        dir: &Path,
+        executor: &dyn Executor,
+        expected: &PuzzleParse,
+    ) -> anyhow::Result<()> {
+        let model = dir.join(format!("{}.eprime", self.name));
+        let param = dir.join(format!("{}.param", self.name));
+        fs::write(&model, self.model)?;
+        fs::write(
+            &param,
+            write_essence_param(&serde_json::from_str(self.param)?)?,
+        )?;
+
+        let found = parse_essence_with(executor, &model, &param)?;
+        let vars = |puzzle: &PuzzleParse| -> BTreeSet<_> {
+            puzzle
+                .all_var_varvals()
+                .iter()
+                .map(|vv| vv.var().clone())
+                .collect()
+        };
+        let (found, expected) = (vars(&found), vars(expected));
+        if found != expected {
+            bail!(
+                "savilerow made {} variables, but the DIMACS has {}",
+                found.len(),
+                expected.len()
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Checks the MUSes of the first [`MINIMAL_STEPS`] steps of solving
+/// `puzzle` prove what they deduce, and no longer do without any one of
+/// their constraints.
+fn check_minimal(puzzle: &Arc<PuzzleParse>) -> anyhow::Result<()> {
+    let mut planner = PuzzlePlanner::new(PuzzleSolver::new(puzzle.clone())?);
+    for _ in 0..MINIMAL_STEPS {
+        if planner.solver().get_provable_varlits().is_empty() {
+            break;
+        }
+        let step = planner.next_step();
+        let solver = planner.solver();
+        for mc in &step.muses {
+            if !solver.mus_proves_lits(mc) {
+                bail!(
+                    "A MUS of {} constraints does not prove what it deduces",
+                    mc.mus.len()
+                );
+            }
+            for con in &mc.mus {
+                let smaller = MusContext {
+                    lits: mc.lits.clone(),
+                    mus: mc.mus.iter().filter(|&c| c != con).copied().collect(),
+                };
+                if solver.mus_proves_lits(&smaller) {
+                    bail!(
+                        "A MUS of {} constraints still proves what it deduces without {}",
+                        mc.mus.len(),
+                        solver.puzzleparse().lit_to_con(con)
+                    );
+                }
+            }
+        }
+        planner.apply_step(&step);
+    }
+    Ok(())
+}
+
+fn outcome(name: String, result: anyhow::Result<()>) -> CheckOutcome {
+    CheckOutcome {
+        name,
+        failures: result.err().map(|e| format!("{e:#}")).into_iter().collect(),
+    }
+}
+
+/// Runs every check, running conjure and savilerow with `tools`, or not at
+/// all if it is `None`.
+pub fn run(tools: Option<&dyn Executor>) -> anyhow::Result<Vec<CheckOutcome>> {
+    let dir = tempfile::tempdir().context("Failed to create a directory for the puzzles")?;
+    let mut outcomes = vec![];
+    let mut parsed = vec![];
+    for fixture in &FIXTURES {
+        let name = format!("{}: read from DIMACS", fixture.name);
+        let puzzle = match fixture.parse(dir.path()) {
+            Ok(puzzle) => Arc::new(puzzle),
+            Err(e) => {
+                outcomes.push(outcome(name, Err(e)));
+                parsed.push(None);
+                continue;
+            }
+        };
+        outcomes.push(outcome(name, Ok(())));
+        outcomes.push(outcome(
+            format!("{}: solve matches the golden trace", fixture.name),
+            fixture.check_trace(&puzzle),
+        ));
+        outcomes.push(outcome(
+            format!("{}: MUSes are minimal", fixture.name),
+            check_minimal(&puzzle),
+        ));
+        parsed.push(Some(puzzle));
+    }
+
+    if let Some(executor) = tools {
+        outcomes.push(outcome(
+            "conjure runs".to_owned(),
+            ProgramRunner::get_conjure_version_with(executor)
+                .map(|_| ())
+                .map_err(anyhow::Error::msg),
+        ));
+        let fixture = &FIXTURES[TOOLS_FIXTURE];
+        if let Some(expected) = &parsed[TOOLS_FIXTURE] {
+            outcomes.push(outcome(
+                format!("{}: savilerow matches the DIMACS", fixture.name),
+                fixture.check_tools(dir.path(), executor, expected),
+            ));
+        }
+    }
+    Ok(outcomes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::problem::util::exec::{FakeExecutor, FakeRun};
+
+    #[test]
+    fn test_self_test() {
+        let outcomes = run(None).unwrap();
+        assert_eq!(outcomes.len(), 3 * FIXTURES.len());
+        for outcome in &outcomes {
+            assert!(outcome.passed(), "{outcome}");
+        }
+
+        // Tools which are not installed fail their checks, but nothing else
+        let outcomes = run(Some(&FakeExecutor::default())).unwrap();
+        let failed: Vec<_> = outcomes.iter().filter(|o| !o.passed()).collect();
+        assert_eq!(failed.len(), 2);
+        assert_eq!(failed[0].name, "conjure runs");
+        assert_eq!(failed[1].name, "sudoku-4x4: savilerow matches the DIMACS");
+
+        // savilerow is given the model and the parameters as Essence
+        let fixture = &FIXTURES[TOOLS_FIXTURE];
+        let tools = FakeExecutor::new([
+            FakeRun::success("Conjure v2.5.1"),
+            FakeRun::success("").with_file("sudoku-4x4.param.dimacs", fixture.dimacs),
+        ]);
+        let outcomes = run(Some(&tools)).unwrap();
+        assert!(outcomes.iter().all(CheckOutcome::passed));
+        assert_eq!(tools.calls()[1].0, "savilerow");
+    }
+}
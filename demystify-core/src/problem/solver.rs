@@ -0,0 +1,1717 @@
+#[doc(hidden)]
+pub mod known;
+#[doc(hidden)]
+pub mod mus;
+#[doc(hidden)]
+pub mod probe;
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::atomic::{AtomicBool, AtomicI64},
+};
+
+use std::sync::atomic::Ordering::Relaxed;
+
+use anyhow::{Context, bail};
+use itertools::Itertools;
+use rand_chacha::ChaCha20Rng;
+use rustsat::types::Lit;
+use thread_local::ThreadLocal;
+use tracing::info;
+
+use crate::problem::musdict::MusContext;
+use crate::satcore::{SatCore, SearchResult, ShrinkOrder};
+
+use self::{
+    known::KnownLits,
+    mus::MusFinder,
+    probe::{ConstraintStatus, ProbeOrder, Prober},
+};
+use super::{PuzLit, VarValPair, musdict::MusDict, parse::PuzzleParse};
+
+/// The strategy to use when finding a minimal unsatisfiable subset (MUS)
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Strategy {
+    /// Uses a quick algorithm that may find larger MUSes
+    Quick,
+    /// Uses a slicing technique to find smaller MUSes
+    Slice,
+    /// Uses a "cake cutting" technique to find small MUSes
+    Cake,
+    /// Uses 'cake cutting' for smaller MUSes, slice for larger
+    Dynamic,
+}
+
+impl Default for Strategy {
+    fn default() -> Self {
+        Self::Dynamic
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct MusConfig {
+    pub base_size_mus: i64,
+    pub mus_add_step: i64,
+    pub mus_mult_step: i64,
+    /// The number of times to search for a MUS of each literal, at each size
+    pub repeats: i64,
+    pub find_bigger: bool,
+    pub strategy: Strategy,
+    /// The order literals are deleted from each core while shrinking it
+    pub shrink_order: ShrinkOrder,
+    /// Search some literals more than `repeats` times, if their MUSes vary
+    pub adaptive: Option<AdaptiveRepeats>,
+    /// Stop searching for MUSes of a literal once this long has been spent
+    /// on it, across all sizes. A search which has started is not
+    /// interrupted, so a literal can take longer by up to one search.
+    /// Literals given up on without a MUS are listed by
+    /// [`MusDict::timed_out`].
+    pub literal_time_limit: Option<Duration>,
+}
+
+/// Searching again for MUSes of the literals whose MUSes vary in size.
+///
+/// After the first `repeats` searches for each literal, literals with at
+/// least two MUSes whose sizes have a variance above `variance_threshold` are
+/// searched once more, and this is repeated until no literal's variance is
+/// above the threshold, or each has been searched `max_repeats` times. Easy
+/// puzzles, where the same size of MUS is found each time, can then use a
+/// small `repeats` without missing smaller MUSes on hard ones.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AdaptiveRepeats {
+    pub max_repeats: i64,
+    pub variance_threshold: f64,
+}
+
+impl Default for AdaptiveRepeats {
+    fn default() -> Self {
+        Self {
+            max_repeats: 8,
+            variance_threshold: 0.0,
+        }
+    }
+}
+
+static REPEAT_SEARCHES: AtomicI64 = AtomicI64::new(0);
+static REPEAT_EXTRA_SEARCHES: AtomicI64 = AtomicI64::new(0);
+static REPEAT_ROUNDS: AtomicI64 = AtomicI64::new(0);
+
+/// Totals over every MUS search, for tuning [`MusConfig::repeats`] and
+/// [`AdaptiveRepeats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RepeatStats {
+    /// The number of searches for a MUS of a single literal
+    pub searches: i64,
+    /// How many of `searches` were extra ones made by [`AdaptiveRepeats`]
+    pub extra_searches: i64,
+    /// The number of rounds of extra searches
+    pub extra_rounds: i64,
+}
+
+impl std::fmt::Display for RepeatStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} MUS searches, {} of them extra searches in {} rounds for literals whose MUS sizes varied",
+            self.searches, self.extra_searches, self.extra_rounds
+        )
+    }
+}
+
+/// Get the statistics of all MUS searches made so far.
+pub fn get_repeat_stats() -> RepeatStats {
+    RepeatStats {
+        searches: REPEAT_SEARCHES.load(Relaxed),
+        extra_searches: REPEAT_EXTRA_SEARCHES.load(Relaxed),
+        extra_rounds: REPEAT_ROUNDS.load(Relaxed),
+    }
+}
+
+/// Reset the statistics returned by [`get_repeat_stats`].
+pub fn reset_repeat_stats() {
+    for stat in [&REPEAT_SEARCHES, &REPEAT_EXTRA_SEARCHES, &REPEAT_ROUNDS] {
+        stat.store(0, Relaxed);
+    }
+}
+
+/// The searches which skip a literal, rather than failing, when the SAT
+/// solver reaches its conflict limit. See [`get_search_error_stats`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SearchPhase {
+    /// Looking for MUSes of size 0 or 1, before the main search
+    TinyMuses,
+    /// The search for small MUSes of growing sizes
+    SmallMuses,
+    /// Finding any MUS, with [`MusFinder::many_vars_mus_first`]
+    FirstMuses,
+    /// See [`PlannerConfig::chained_singles`](crate::problem::planner::PlannerConfig::chained_singles)
+    ChainedSingles,
+    /// See [`PlannerConfig::either_or`](crate::problem::planner::PlannerConfig::either_or)
+    EitherOr,
+    /// Checking that the constraints of a MUS prove its literals
+    CheckMus,
+}
+
+impl SearchPhase {
+    pub const ALL: [SearchPhase; 6] = [
+        SearchPhase::TinyMuses,
+        SearchPhase::SmallMuses,
+        SearchPhase::FirstMuses,
+        SearchPhase::ChainedSingles,
+        SearchPhase::EitherOr,
+        SearchPhase::CheckMus,
+    ];
+}
+
+impl std::fmt::Display for SearchPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SearchPhase::TinyMuses => write!(f, "the tiny MUS scan"),
+            SearchPhase::SmallMuses => write!(f, "the small MUS search"),
+            SearchPhase::FirstMuses => write!(f, "the first MUS search"),
+            SearchPhase::ChainedSingles => write!(f, "chained singles"),
+            SearchPhase::EitherOr => write!(f, "either/or pairs"),
+            SearchPhase::CheckMus => write!(f, "checking MUSes"),
+        }
+    }
+}
+
+static SEARCH_ERRORS: [AtomicI64; SearchPhase::ALL.len()] =
+    [const { AtomicI64::new(0) }; SearchPhase::ALL.len()];
+
+/// The value of `result`, or `None` if the search failed, in which case the
+/// failure is counted against `phase`. For searches which can skip a
+/// literal they cannot finish.
+pub fn skip_search_error<T>(phase: SearchPhase, result: SearchResult<T>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(e) => {
+            info!(target: "solver", "Skipping a search in {phase}: {e}");
+            SEARCH_ERRORS[phase as usize].fetch_add(1, Relaxed);
+            None
+        }
+    }
+}
+
+/// How many searches were skipped in each [`SearchPhase`], as the SAT solver
+/// could not finish them. Phases with none are left out.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SearchErrorStats {
+    pub skipped: BTreeMap<SearchPhase, i64>,
+}
+
+impl SearchErrorStats {
+    #[must_use]
+    pub fn total(&self) -> i64 {
+        self.skipped.values().sum()
+    }
+
+    /// Fails if any search was skipped, for runs which must be complete.
+    pub fn check(&self) -> anyhow::Result<()> {
+        if self.total() > 0 {
+            bail!(
+                "{} searches were skipped as the SAT solver could not finish them: {self}",
+                self.total()
+            );
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for SearchErrorStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.skipped.is_empty() {
+            return write!(f, "none");
+        }
+        let phases: Vec<String> = self
+            .skipped
+            .iter()
+            .map(|(phase, n)| format!("{n} in {phase}"))
+            .collect();
+        write!(f, "{}", phases.join(", "))
+    }
+}
+
+/// Get the searches skipped so far, see [`skip_search_error`].
+pub fn get_search_error_stats() -> SearchErrorStats {
+    SearchErrorStats {
+        skipped: SearchPhase::ALL
+            .into_iter()
+            .map(|phase| (phase, SEARCH_ERRORS[phase as usize].load(Relaxed)))
+            .filter(|&(_, n)| n > 0)
+            .collect(),
+    }
+}
+
+/// Reset the statistics returned by [`get_search_error_stats`].
+pub fn reset_search_error_stats() {
+    for stat in &SEARCH_ERRORS {
+        stat.store(0, Relaxed);
+    }
+}
+
+/// How strictly searches skipped by [`skip_search_error`] are treated, for
+/// checking a collection of puzzles.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Strictness {
+    /// Report how many searches were skipped in each phase
+    Report,
+    /// Report them, and fail if there were any
+    Fail,
+}
+
+impl std::str::FromStr for Strictness {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "report" => Ok(Strictness::Report),
+            "fail" => Ok(Strictness::Fail),
+            _ => Err(format!("Invalid strictness: {s} (expected report or fail)")),
+        }
+    }
+}
+
+impl std::fmt::Display for Strictness {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Strictness::Report => write!(f, "report"),
+            Strictness::Fail => write!(f, "fail"),
+        }
+    }
+}
+
+/// The variance of `sizes`, or `None` if there are fewer than two.
+fn size_variance(sizes: &[usize]) -> Option<f64> {
+    if sizes.len() < 2 {
+        return None;
+    }
+    let n = sizes.len() as f64;
+    let mean = sizes.iter().sum::<usize>() as f64 / n;
+    Some(
+        sizes
+            .iter()
+            .map(|&s| (s as f64 - mean).powi(2))
+            .sum::<f64>()
+            / n,
+    )
+}
+
+impl Default for MusConfig {
+    fn default() -> Self {
+        Self {
+            base_size_mus: 2,
+            mus_add_step: 1,
+            mus_mult_step: 2,
+            repeats: 2,
+            find_bigger: false,
+            strategy: Strategy::default(),
+            shrink_order: ShrinkOrder::default(),
+            adaptive: None,
+            literal_time_limit: None,
+        }
+    }
+}
+
+impl MusConfig {
+    #[must_use]
+    pub fn new_with_repeats(repeats: i64) -> Self {
+        Self {
+            base_size_mus: 2,
+            mus_add_step: 1,
+            mus_mult_step: 2,
+            repeats,
+            find_bigger: false,
+            strategy: Strategy::default(),
+            shrink_order: ShrinkOrder::default(),
+            adaptive: None,
+            literal_time_limit: None,
+        }
+    }
+}
+
+/// Limits on how much work a MUS search may do, after which it stops and
+/// returns what it has found so far (see [`MusSearch`]).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SearchBudget {
+    pub max_time: Option<Duration>,
+    /// Compared against [`crate::satcore::get_solver_calls`], which counts the
+    /// calls of every search in the process, not only this one.
+    pub max_solver_calls: Option<i64>,
+    /// Stops the search, as if the budget had run out, once cancelled
+    pub cancel: Option<CancelFlag>,
+}
+
+impl SearchBudget {
+    fn start(&self) -> BudgetTracker {
+        BudgetTracker {
+            deadline: self.max_time.map(|t| Instant::now() + t),
+            call_limit: self
+                .max_solver_calls
+                .map(|c| crate::satcore::get_solver_calls() + c),
+            cancel: self.cancel.clone(),
+        }
+    }
+}
+
+/// A flag which another thread can set to stop a search early. Clones share
+/// the same flag.
+#[derive(Clone, Debug, Default)]
+pub struct CancelFlag(Arc<AtomicBool>);
+
+impl CancelFlag {
+    pub fn cancel(&self) {
+        self.0.store(true, Relaxed);
+    }
+
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Relaxed)
+    }
+}
+
+impl PartialEq for CancelFlag {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for CancelFlag {}
+
+struct BudgetTracker {
+    deadline: Option<Instant>,
+    call_limit: Option<i64>,
+    cancel: Option<CancelFlag>,
+}
+
+impl BudgetTracker {
+    fn exhausted(&self) -> bool {
+        self.deadline.is_some_and(|d| Instant::now() >= d)
+            || self
+                .call_limit
+                .is_some_and(|c| crate::satcore::get_solver_calls() >= c)
+            || self.cancel.as_ref().is_some_and(CancelFlag::is_cancelled)
+    }
+}
+
+/// A MUS search which ran out of budget, which can be passed back to
+/// [`PuzzleSolver::get_many_vars_small_mus_budgeted`] to carry on.
+#[derive(Clone)]
+pub struct MusSearch {
+    /// The literals being explained
+    pub lits: BTreeSet<Lit>,
+    /// The MUSes found so far
+    pub muses: MusDict,
+    /// The size of MUS which was being looked for when the search stopped
+    pub next_size: i64,
+}
+
+pub enum MusSearchResult {
+    Complete(MusDict),
+    Truncated(MusSearch),
+}
+
+#[derive(Copy, Clone, Default)]
+pub struct SolverConfig {
+    pub only_assignments: bool,
+    /// The order in which [`PuzzleSolver::get_provable_varlits`] probes
+    /// literals
+    pub probe_order: ProbeOrder,
+}
+
+/// What the [`Prober`] and [`MusFinder`] search with: the puzzle, what is
+/// known about it, and one SAT solver for each thread (made when a thread
+/// first needs one).
+#[derive(Clone, Copy)]
+pub struct SearchContext<'a> {
+    pub puzzle: &'a PuzzleParse,
+    pub known: &'a KnownLits,
+    pub cores: &'a ThreadLocal<SatCore>,
+}
+
+impl SearchContext<'_> {
+    /// The SAT solver for the current thread.
+    ///
+    /// # Panics
+    ///
+    /// If the puzzle has no CNF.
+    #[must_use]
+    pub fn satcore(&self) -> &SatCore {
+        self.cores
+            .get_or(|| SatCore::new(self.puzzle.cnf.clone().unwrap()).unwrap())
+    }
+}
+
+/// Represents a puzzle solver.
+///
+/// This keeps the [`KnownLits`] of a solve, and answers questions about the
+/// puzzle with a [`Prober`] and a [`MusFinder`], which can also be used
+/// directly from a [`PuzzleSolver::context`].
+pub struct PuzzleSolver {
+    satcore: ThreadLocal<SatCore>,
+    puzzleparse: Arc<PuzzleParse>,
+
+    known: KnownLits,
+
+    solver_config: SolverConfig,
+}
+
+impl PuzzleSolver {
+    /// Creates a new `PuzzleSolver` instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `puzzleparse` - The `PuzzleParse` instance containing puzzle information.
+    ///
+    /// # Returns
+    ///
+    /// A `PuzzleSolver` instance.
+    pub fn new(puzzleparse: Arc<PuzzleParse>) -> anyhow::Result<PuzzleSolver> {
+        Self::new_with_config(puzzleparse, SolverConfig::default())
+    }
+
+    /// Creates a new `PuzzleSolver` instance from a config
+    ///
+    /// # Arguments
+    ///
+    /// * `puzzleparse` - The `PuzzleParse` instance containing puzzle information.
+    /// * `solverconfig` - A `SolverConfig` object
+    ///
+    /// # Returns
+    ///
+    /// A `PuzzleSolver` instance.
+    pub fn new_with_config(
+        puzzleparse: Arc<PuzzleParse>,
+        solver_config: SolverConfig,
+    ) -> anyhow::Result<PuzzleSolver> {
+        Ok(PuzzleSolver {
+            satcore: ThreadLocal::new(),
+            puzzleparse,
+            known: KnownLits::default(),
+            solver_config,
+        })
+    }
+
+    /// Creates an independent copy of this `PuzzleSolver`, with the same known literals.
+    ///
+    /// The `PuzzleParse` is shared rather than copied, while the copy gets its own
+    /// SAT solvers, so the two can carry on solving separately.
+    ///
+    /// # Returns
+    ///
+    /// A `PuzzleSolver` instance.
+    #[must_use]
+    pub fn fork(&self) -> PuzzleSolver {
+        PuzzleSolver {
+            satcore: ThreadLocal::new(),
+            puzzleparse: self.puzzleparse.clone(),
+            known: self.known.clone(),
+            solver_config: self.solver_config,
+        }
+    }
+
+    /// As [`PuzzleSolver::fork`], but only using the constraints with one of
+    /// `cells` in their scope, as in
+    /// [`PuzzleParse::retain_constraints_touching`].
+    #[must_use]
+    pub fn restricted_to_cells(&self, cells: &BTreeSet<Vec<i64>>) -> PuzzleSolver {
+        let mut puzzleparse = (*self.puzzleparse).clone();
+        puzzleparse.retain_constraints_touching(cells);
+        let mut known = self.known.clone();
+        known.forget_provable();
+        PuzzleSolver {
+            satcore: ThreadLocal::new(),
+            puzzleparse: Arc::new(puzzleparse),
+            known,
+            solver_config: self.solver_config,
+        }
+    }
+
+    /// As [`PuzzleSolver::fork`], but only using the constraints of the
+    /// `$#CON`s named in `names`, as in
+    /// [`PuzzleParse::retain_constraints_named`].
+    pub fn restricted_to_constraints(
+        &self,
+        names: &BTreeSet<String>,
+    ) -> anyhow::Result<PuzzleSolver> {
+        let cons = &self.puzzleparse.eprime.cons;
+        if let Some(name) = names.iter().find(|name| !cons.contains_key(*name)) {
+            bail!(
+                "{name} is not a constraint of the puzzle, which has {}",
+                cons.keys().join(", ")
+            );
+        }
+        let mut puzzleparse = (*self.puzzleparse).clone();
+        puzzleparse.retain_constraints_named(names);
+        let mut known = self.known.clone();
+        known.forget_provable();
+        Ok(PuzzleSolver {
+            satcore: ThreadLocal::new(),
+            puzzleparse: Arc::new(puzzleparse),
+            known,
+            solver_config: self.solver_config,
+        })
+    }
+
+    /// The puzzle, the known literals and the SAT solvers, to search with.
+    #[must_use]
+    pub fn context(&self) -> SearchContext<'_> {
+        SearchContext {
+            puzzle: &self.puzzleparse,
+            known: &self.known,
+            cores: &self.satcore,
+        }
+    }
+
+    #[must_use]
+    pub fn prober(&self) -> Prober<'_> {
+        Prober::new(self.context())
+    }
+
+    #[must_use]
+    pub fn mus_finder(&self) -> MusFinder<'_> {
+        MusFinder::new(self.context())
+    }
+
+    /// Converts a `PuzLit` instance to a `Lit`.
+    ///
+    /// # Arguments
+    ///
+    /// * `puzlit` - The `PuzLit` instance to convert.
+    ///
+    /// # Returns
+    ///
+    /// The corresponding `Lit` instance.
+    pub fn puzlit_to_lit(&self, puzlit: &PuzLit) -> Lit {
+        *self.puzzleparse.litmap.get(puzlit).unwrap_or_else(|| {
+            dbg!(&self.puzzleparse.litmap);
+            panic!("Missing puzlit: {puzlit}");
+        })
+    }
+
+    /// Converts a `Lit` instance to a reference to the set of `PuzLit` instances it represents.
+    ///
+    /// # Arguments
+    ///
+    /// * `lit` - The `Lit` instance to convert.
+    ///
+    /// # Returns
+    ///
+    /// A reference to the set of `PuzLit` instances.
+    pub fn lit_to_puzlit(&self, lit: &Lit) -> &BTreeSet<PuzLit> {
+        self.puzzleparse
+            .invlitmap
+            .get(lit)
+            .unwrap_or_else(|| panic!("Mizzing lit: {lit}"))
+    }
+
+    /// Determines if the current puzzle state is solvable under the current assumptions. This only checks if the puzzle has at least one solution, not that the solution is unique.
+    ///
+    /// Note that for multi-step puzzles (like minesweeper), this only
+    /// checks if the current state of the puzzle has at least one solution.
+    ///
+    /// This method combines the literals from the puzzle's constraint set (`conset_lits`)
+    /// and the known literals to form a set of assumptions. It then attempts
+    /// to solve the puzzle using these assumptions. If the solver finds a solution, it
+    /// indicates that the puzzle is currently solvable under these assumptions.
+    ///
+    /// # Returns
+    ///
+    /// Returns `true` if the puzzle is solvable under the current assumptions, otherwise `false`.
+    ///
+    /// # Panics
+    ///
+    /// If [`PuzzleSolver::try_is_currently_solvable`] fails.
+    pub fn is_currently_solvable(&mut self) -> bool {
+        self.try_is_currently_solvable()
+            .unwrap_or_else(|e| panic!("Cannot check the puzzle has a solution: {e}"))
+    }
+
+    /// As [`PuzzleSolver::is_currently_solvable`], retrying with higher
+    /// conflict limits as in [`SatCore::must_succeed`].
+    pub fn try_is_currently_solvable(&mut self) -> SearchResult<bool> {
+        self.prober().is_solvable()
+    }
+
+    /// If the puzzle has no solution with the current known literals, returns
+    /// a minimal set of constraint and known literals which together have no
+    /// solution, showing why.
+    ///
+    /// Unlike most methods, this treats the known literals as assumptions, so
+    /// it can blame them.
+    pub fn explain_unsolvable(&self) -> Option<Vec<Lit>> {
+        self.prober().explain_unsolvable()
+    }
+
+    /// The literals of `lits` which follow from the constraints and the
+    /// known literals, so hold in every solution.
+    pub fn provable_puzlits(&self, lits: &[PuzLit]) -> anyhow::Result<Vec<PuzLit>> {
+        let sat_lits: Vec<Lit> = lits
+            .iter()
+            .map(|p| {
+                self.puzzleparse
+                    .litmap
+                    .get(p)
+                    .copied()
+                    .with_context(|| format!("{p} is not a literal of the puzzle"))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let negated: Vec<Lit> = sat_lits.iter().map(|&l| !l).collect();
+        let provable = self.prober().provable_varlits(&negated)?;
+
+        Ok(lits
+            .iter()
+            .zip(&sat_lits)
+            .filter(|(_, l)| provable.contains(l))
+            .map(|(p, _)| p.clone())
+            .collect())
+    }
+
+    /// Fails if any of `lits` can be deduced from the constraints and the
+    /// known literals. This is for checking a model does not deduce too
+    /// much, such as that `grid[1, 1]=5` cannot be deduced from the first
+    /// few clues.
+    pub fn assert_not_provable(&self, lits: &[PuzLit]) -> anyhow::Result<()> {
+        let provable = self.provable_puzlits(lits)?;
+        if !provable.is_empty() {
+            bail!(
+                "{} can be deduced, but should not be",
+                provable.iter().join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    /// Fails unless all of `lits` can be deduced from the constraints and
+    /// the known literals.
+    pub fn assert_provable(&self, lits: &[PuzLit]) -> anyhow::Result<()> {
+        let provable = self.provable_puzlits(lits)?;
+        let missing = lits.iter().filter(|p| !provable.contains(p)).collect_vec();
+        if !missing.is_empty() {
+            bail!(
+                "{} cannot be deduced, but should be",
+                missing.iter().join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    /// Retrieves variable literals which can be proved.
+    ///
+    /// # Returns
+    ///
+    /// A vector containing the provable variable literals.
+    ///
+    /// # Panics
+    ///
+    /// If [`PuzzleSolver::try_get_provable_varlits`] fails.
+    #[must_use]
+    pub fn get_provable_varlits(&mut self) -> &BTreeSet<Lit> {
+        self.try_get_provable_varlits()
+            .unwrap_or_else(|e| panic!("Cannot find the provable literals: {e}"))
+    }
+
+    /// As [`PuzzleSolver::get_provable_varlits`], retrying each probe with
+    /// higher conflict limits as in [`SatCore::must_succeed`].
+    pub fn try_get_provable_varlits(&mut self) -> SearchResult<&BTreeSet<Lit>> {
+        if self.known.provable().is_none() {
+            let lits: Vec<Lit> = self.get_literals_to_try_solving().into_iter().collect();
+            let prober = self.prober();
+            let lits = prober.order_for_probing(lits, self.solver_config.probe_order)?;
+            let provable = prober.provable_varlits(&lits)?;
+            self.known.set_provable(provable);
+            self.debug_assert_state_valid();
+        }
+
+        Ok(self.known.provable().unwrap())
+    }
+
+    /// Retrieves literals which can be proved by a particular MUS.
+    ///
+    /// # Returns
+    ///
+    /// A vector containing the provable variable literals.
+    #[must_use]
+    pub fn get_varlits_provable_by_mus(
+        &mut self,
+        candidates: &BTreeSet<Lit>,
+        mc: &MusContext,
+    ) -> BTreeSet<Lit> {
+        self.prober().varlits_provable_by_mus(candidates, mc)
+    }
+
+    /// Returns all literals in the scope of a MUS.
+    ///
+    /// This method collects all literals that are in the scope of the given MUS. The scope
+    /// is determined by looking at all constraints in the MUS and finding all literals that
+    /// are affected by those constraints.
+    ///
+    /// # Arguments
+    ///
+    /// * `base` - The base literal that is being proved by the MUS.
+    /// * `mus` - The Minimal Unsatisfiable Subset (MUS) as a vector of literals.
+    ///
+    /// # Returns
+    ///
+    /// A vector of literals that are in the scope of the given MUS.
+    fn get_all_lits_in_scope_for_mus(&mut self, mc: &MusContext) -> BTreeSet<Lit> {
+        // First get all lits in the scopes of all constraints in the MUS
+        let mut lits = BTreeSet::new();
+
+        for m in &mc.mus {
+            for l in self.puzzleparse().varlits_in_con.get(m).unwrap() {
+                lits.insert(*l);
+            }
+        }
+
+        // Then get the vars of all those lits
+        let mut vars = BTreeSet::new();
+
+        for l in lits {
+            for vvp in self.puzzleparse().direct_or_ordered_lit_to_varvalpair(&l) {
+                vars.insert(vvp.var().clone());
+            }
+        }
+
+        // Then get the lits we still need to find, and check if they are in any of those variables
+        let mut check_lits = BTreeSet::new();
+        // This should always be in here, but let's add it just in case something goes wrong.
+        for l in &mc.lits {
+            check_lits.insert(*l);
+        }
+
+        for l in self.get_provable_varlits().clone() {
+            // Get all variables which refer to that literal
+            for vvp in self.puzzleparse().direct_or_ordered_lit_to_varvalpair(&l) {
+                if vars.contains(vvp.var()) {
+                    check_lits.insert(l);
+                }
+            }
+        }
+
+        check_lits
+    }
+
+    /// A MUS which proves all of `lits` together, as in [`MusFinder::set_mus`].
+    pub fn get_set_mus(&self, lits: &BTreeSet<Lit>) -> SearchResult<Option<MusContext>> {
+        self.mus_finder().set_mus(lits)
+    }
+
+    /// Shrinks a MUS by deletion, as in [`MusFinder::minimize_mus`].
+    pub fn minimize_mus(&self, mc: &MusContext, budget: Duration) -> MusContext {
+        self.mus_finder().minimize_mus(mc, budget)
+    }
+
+    /// Checks if the constraints in a MUS, along with the known literals,
+    /// prove every literal it claims to.
+    #[must_use]
+    pub fn mus_proves_lits(&self, mc: &MusContext) -> bool {
+        self.prober().mus_proves_lits(mc)
+    }
+
+    /// Checks if `cons`, along with the known literals, proves every one of `lits`.
+    /// A solver timeout is treated as 'not proved'.
+    fn cons_prove_all(&self, cons: &[Lit], lits: &BTreeSet<Lit>) -> bool {
+        self.prober().cons_prove_all(cons, lits)
+    }
+
+    /// Returns all literals that a given MUS can deduce.
+    ///
+    /// This method collects all literals that are in the scope of the given MUS, then
+    /// checks which of them can be deduced by `mus`.
+    ///
+    /// # Arguments
+    ///
+    /// * `base` - The base literal that is being proved by the MUS.
+    /// * `mc` - The Minimal Unsatisfiable Subset (MUS).
+    ///
+    /// # Returns
+    ///
+    /// A new MUS.
+    pub fn get_all_lits_solved_by_mus(&mut self, mc: &MusContext) -> MusContext {
+        let candidates = self.get_all_lits_in_scope_for_mus(mc);
+        let filtered = self.get_varlits_provable_by_mus(&candidates, mc);
+        MusContext::new_with_more_lits(filtered, mc)
+    }
+
+    /// Checks a user's (possibly partial) assignment against each
+    /// constraint, as in [`Prober::check_assignment`].
+    pub fn check_assignment(
+        &self,
+        assignment: &[VarValPair],
+    ) -> anyhow::Result<Vec<ConstraintStatus>> {
+        self.prober().check_assignment(assignment)
+    }
+
+    /// Generate a random solution, as in [`Prober::random_solution`].
+    pub fn random_solution(
+        &mut self,
+        rng: &mut ChaCha20Rng,
+        steps: Option<usize>,
+    ) -> BTreeSet<Lit> {
+        self.prober().random_solution(rng, steps)
+    }
+
+    /// Returns the set of literals which we should still try solving (may be true, or false)
+    pub fn get_literals_to_try_solving(&mut self) -> BTreeSet<Lit> {
+        let lits = if self.solver_config.only_assignments {
+            &self.puzzleparse.varset_lits_neg
+        } else {
+            &self.puzzleparse.varset_lits
+        };
+        lits.iter()
+            .copied()
+            .filter(|&lit| !self.known.decides(&self.puzzleparse, lit))
+            .collect()
+    }
+
+    /// Sets a literal as known, which could previously be proved.
+    ///
+    /// # Arguments
+    ///
+    /// * `lit` - The literal to add.
+    pub fn add_known_lit(&mut self, lit: Lit) {
+        if self.known.contains(&self.puzzleparse, lit) {
+            return;
+        }
+        // The puzzle may have become unsolvable (in which case there are no
+        // solvable lits), but we didn't realise yet (as we don't check that
+        // at every addition of a known lit).
+        debug_assert!(self.get_provable_varlits().contains(&lit) || !self.is_currently_solvable());
+        self.known.add(&self.puzzleparse, lit);
+        self.debug_assert_state_valid();
+    }
+
+    /// Adds a literal which is known to be true, but cannot be proved true.
+    /// This exists because it invalidates a number of internal caches.
+    ///
+    /// # Arguments
+    ///
+    /// * `lit` - The literal to add.
+    pub fn add_not_provable_known_lit(&mut self, lit: Lit) {
+        self.known.add(&self.puzzleparse, lit);
+        self.known.forget_provable();
+        self.debug_assert_state_valid();
+    }
+
+    /// Get all literals known to be true, as in [`KnownLits::lits`].
+    #[must_use]
+    pub fn get_known_lits(&self) -> Vec<Lit> {
+        self.known.lits(&self.puzzleparse)
+    }
+
+    /// The known literals, which can be put back later with
+    /// [`PuzzleSolver::restore_known`].
+    #[must_use]
+    pub fn known(&self) -> &KnownLits {
+        &self.known
+    }
+
+    /// Puts back the known literals saved from [`PuzzleSolver::known`],
+    /// forgetting any added since.
+    pub fn restore_known(&mut self, known: KnownLits) {
+        self.known = known;
+        self.debug_assert_state_valid();
+    }
+
+    /// Checks the known literals are consistent, as in
+    /// [`KnownLits::check_valid`], and that any provable literals found are
+    /// among those which [`PuzzleSolver::get_literals_to_try_solving`] tries.
+    pub fn check_state_valid(&self) -> anyhow::Result<()> {
+        self.known.check_valid(&self.puzzleparse)?;
+        let to_solve = if self.solver_config.only_assignments {
+            &self.puzzleparse.varset_lits_neg
+        } else {
+            &self.puzzleparse.varset_lits
+        };
+        if let Some(lit) = self
+            .known
+            .provable()
+            .into_iter()
+            .flatten()
+            .find(|l| !to_solve.contains(l))
+        {
+            bail!(
+                "{} is listed as provable, but is not to be solved",
+                self.lit_to_puzlit(lit).iter().join(" / ")
+            );
+        }
+        Ok(())
+    }
+
+    /// Panics if [`PuzzleSolver::check_state_valid`] fails, in debug builds,
+    /// so a cache which is not kept up to date is caught where it goes
+    /// wrong, rather than when a step is later explained wrongly.
+    fn debug_assert_state_valid(&self) {
+        if cfg!(debug_assertions)
+            && let Err(e) = self.check_state_valid()
+        {
+            panic!("Invalid solver state: {e:#}");
+        }
+    }
+
+    /// Retrieves MUSes of size 0 or 1 for a given literal
+    ///
+    /// # Arguments
+    ///
+    /// * `lit` - The literal to find a proof for (so we invert for the MUS).
+    /// * `count` - the largest number of MUSes to return (or None for all MUSes)
+    ///
+    /// # Returns
+    ///
+    /// An optional vector of vectors, containing the MUS of variables, or `None` if no MUS is found.
+    pub fn get_var_mus_size_1(
+        &self,
+        lit: Lit,
+        count: Option<usize>,
+    ) -> SearchResult<Vec<Vec<Lit>>> {
+        self.mus_finder().var_mus_size_1(lit, count)
+    }
+
+    /// Finds the literals in `lits` which can be deduced from at most one
+    /// constraint, using a single unsatisfiable core for each literal and no
+    /// MUS search. A core with at most one constraint is always minimal, but
+    /// a literal whose core happens to be larger is not returned, even if it
+    /// has a MUS of size 1.
+    pub fn get_single_constraint_muses(&self, lits: &BTreeSet<Lit>) -> MusDict {
+        self.mus_finder().single_constraint_muses(lits)
+    }
+
+    /// Check if there is a MUS of size 0 for a given literal
+    ///
+    /// # Arguments
+    ///
+    /// * `lit` - The literal to find a proof for (so we invert for the MUS).
+    ///
+    /// # Returns
+    ///
+    /// A boolean, true if there is a MUS of size 0 for this literal.
+    pub fn check_var_mus_size_0(&self, lit: Lit) -> bool {
+        self.mus_finder().has_mus_size_0(lit)
+    }
+
+    /// Retrieves a minimal unsatisfiable subset (MUS) of variables which proves
+    /// a given literal is required
+    ///
+    /// # Arguments
+    ///
+    /// * `lit` - The literal to find a proof for.
+    /// * `order` - The order to try removing constraints in.
+    ///
+    /// # Returns
+    ///
+    /// An optional vector containing the MUS of variables, or `None` if no MUS is found.
+    pub fn get_var_mus_quick(
+        &self,
+        lit: Lit,
+        max_size: Option<i64>,
+        order: ShrinkOrder,
+    ) -> SearchResult<Option<Vec<Lit>>> {
+        self.mus_finder().var_mus_quick(lit, max_size, order)
+    }
+
+    pub fn get_var_mus_slice(
+        &self,
+        lit: Lit,
+        max_size: Option<i64>,
+        order: ShrinkOrder,
+    ) -> SearchResult<Option<Vec<Lit>>> {
+        self.mus_finder().var_mus_slice(lit, max_size, order)
+    }
+
+    pub fn get_var_mus_cake(
+        &self,
+        lit: Lit,
+        max_size: i64,
+        order: ShrinkOrder,
+    ) -> SearchResult<Option<Vec<Lit>>> {
+        self.mus_finder().var_mus_cake(lit, max_size, order)
+    }
+
+    /// A MUS which proves at least one of `a` and `b`, as in
+    /// [`MusFinder::either_mus`].
+    pub fn get_either_mus(
+        &self,
+        a: Lit,
+        b: Lit,
+        order: ShrinkOrder,
+    ) -> SearchResult<Option<Vec<Lit>>> {
+        self.mus_finder().either_mus(a, b, order)
+    }
+
+    /// Retrieves the literals which can be solved with a size 0 MUS.
+    ///
+    /// # Arguments
+    ///
+    /// * `lits` - The literals to check MUSes for.
+    ///
+    /// # Returns
+    ///
+    /// A vector of tuples, where each tuple contains a literal and its corresponding MUS of variables.
+    /// Literals where no MUS was found are omitted from the output.
+    pub fn get_many_vars_mus_size_0(&self, lits: &BTreeSet<Lit>) -> BTreeSet<Lit> {
+        self.mus_finder().many_vars_mus_size_0(lits)
+    }
+
+    /// Retrieves an explanation for each element of a list of literals. This will often be
+    /// much bigger than the minimum possible MUS size.
+    ///
+    /// # Arguments
+    ///
+    /// * `lits` - The literals to find the explanations for.
+    ///
+    /// # Returns
+    ///
+    /// A vector of tuples, where each tuple contains a literal and its corresponding MUS of variables.
+    /// Literals where no MUS was found are omitted from the output.
+    pub fn get_many_vars_mus_first(
+        &self,
+        lits: &BTreeSet<Lit>,
+        musdict: Option<MusDict>,
+    ) -> MusDict {
+        self.mus_finder().many_vars_mus_first(lits, musdict)
+    }
+
+    /// Retrieves small MUSes for each element of a list of literals
+    ///
+    /// # Arguments
+    ///
+    /// * `lits` - The literals to find the MUS for.
+    ///
+    /// # Returns
+    ///
+    /// A vector of tuples, where each tuple contains a literal and its corresponding MUS of variables.
+    /// Literals with large MUSes are skipped. The exact set of returned literals may vary.
+    pub fn get_many_vars_small_mus_quick(
+        &self,
+        lits: &BTreeSet<Lit>,
+        config: &MusConfig,
+        musdict: Option<MusDict>,
+    ) -> MusDict {
+        match self
+            .mus_finder()
+            .many_vars_small_mus(lits, config, musdict, &SearchBudget::default())
+        {
+            MusSearchResult::Complete(md) => md,
+            MusSearchResult::Truncated(_) => unreachable!("Search has no budget"),
+        }
+    }
+
+    /// As [`PuzzleSolver::get_many_vars_small_mus_quick`], but gives up when
+    /// `budget` runs out. Passing the [`MusSearch`] from a truncated search as
+    /// `resume` carries on from where it stopped.
+    pub fn get_many_vars_small_mus_budgeted(
+        &self,
+        lits: &BTreeSet<Lit>,
+        config: &MusConfig,
+        resume: Option<MusSearch>,
+        budget: &SearchBudget,
+    ) -> MusSearchResult {
+        let finder = self.mus_finder();
+        match resume {
+            Some(search) => finder.resume_small_muses(lits, config, search, budget),
+            None => finder.many_vars_small_mus(lits, config, None, budget),
+        }
+    }
+
+    /// Retrieves a reference to the `PuzzleParse` instance associated with the `PuzzleSolver`.
+    ///
+    /// # Returns
+    ///
+    /// A reference to the `PuzzleParse` instance.
+    #[must_use]
+    pub fn puzzleparse(&self) -> &PuzzleParse {
+        &self.puzzleparse
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{BTreeSet, HashSet},
+        sync::Arc,
+    };
+
+    use std::time::Duration;
+
+    use crate::problem::PuzLit;
+    use crate::problem::musdict::MusContext;
+    use crate::problem::solver::{
+        KnownLits, MusConfig, PuzzleSolver, SearchErrorStats, SearchPhase, SolverConfig,
+        Strictness, get_search_error_stats, skip_search_error,
+    };
+    use crate::problem::util::test_utils::{sudoku_4x4, sudoku_4x4_with};
+    use crate::satcore::{
+        QueryOutcome, SearchError, ShrinkOrder, set_query_log_size, take_query_log,
+    };
+
+    use rand::{Rng, SeedableRng, seq::IndexedRandom};
+    use test_log::test;
+
+    #[test]
+    fn test_skip_search_error() {
+        assert_eq!(skip_search_error(SearchPhase::EitherOr, Ok(3)), Some(3));
+        assert_eq!(
+            skip_search_error::<i32>(SearchPhase::EitherOr, Err(SearchError::Limit)),
+            None
+        );
+        // Other tests may skip searches at the same time
+        let stats = get_search_error_stats();
+        assert!(stats.skipped[&SearchPhase::EitherOr] >= 1);
+        assert!(stats.check().is_err());
+
+        let stats = SearchErrorStats {
+            skipped: [(SearchPhase::TinyMuses, 2), (SearchPhase::CheckMus, 1)].into(),
+        };
+        assert_eq!(stats.total(), 3);
+        assert_eq!(
+            stats.to_string(),
+            "2 in the tiny MUS scan, 1 in checking MUSes"
+        );
+        assert_eq!(SearchErrorStats::default().to_string(), "none");
+        assert!(SearchErrorStats::default().check().is_ok());
+
+        assert_eq!("fail".parse::<Strictness>(), Ok(Strictness::Fail));
+        assert_eq!(Strictness::Report.to_string(), "report");
+        assert!("always".parse::<Strictness>().is_err());
+    }
+
+    /// Random sequences of deductions, guesses, undos and what-ifs, checking
+    /// after each that the state is valid, and that the provable literals
+    /// kept from before are the ones found from scratch.
+    #[test]
+    fn test_fuzz_state_transitions() -> anyhow::Result<()> {
+        let puzzle = Arc::new(sudoku_4x4_with(
+            "sudoku-4x4-open.json",
+            "sudoku-4x4-open.dimacs",
+        ));
+        for seed in 0..6 {
+            let mut rng = rand_chacha::ChaCha20Rng::seed_from_u64(seed);
+            let mut puz = PuzzleSolver::new(puzzle.clone())?;
+            let mut undo: Vec<KnownLits> = vec![];
+            for _ in 0..25 {
+                match rng.random_range(0..5) {
+                    // Deduce something which can be proved
+                    0 | 1 => {
+                        let provable: Vec<_> = puz.get_provable_varlits().iter().copied().collect();
+                        if let Some(&lit) = provable.choose(&mut rng) {
+                            undo.push(puz.known().clone());
+                            puz.add_known_lit(lit);
+                        }
+                    }
+                    // Guess, undoing guesses which leave no solution
+                    2 => {
+                        let lits: Vec<_> = puz.get_literals_to_try_solving().into_iter().collect();
+                        if let Some(&lit) = lits.choose(&mut rng) {
+                            let before = puz.known().clone();
+                            puz.add_not_provable_known_lit(lit);
+                            if puz.is_currently_solvable() {
+                                undo.push(before);
+                            } else {
+                                puz.restore_known(before);
+                            }
+                        }
+                    }
+                    // Undo back to some earlier point
+                    3 => {
+                        if !undo.is_empty() {
+                            undo.truncate(rng.random_range(1..=undo.len()));
+                            puz.restore_known(undo.pop().unwrap());
+                        }
+                    }
+                    // What if a literal were known, then back to before
+                    _ => {
+                        let lits: Vec<_> = puz.get_literals_to_try_solving().into_iter().collect();
+                        if let Some(&lit) = lits.choose(&mut rng) {
+                            let provable = puz.get_provable_varlits().clone();
+                            let before = puz.known().clone();
+                            puz.add_not_provable_known_lit(lit);
+                            let _ = puz.try_get_provable_varlits();
+                            puz.restore_known(before);
+                            assert_eq!(puz.get_provable_varlits(), &provable);
+                        }
+                    }
+                }
+
+                puz.check_state_valid()?;
+                if let Some(provable) = puz.known().provable() {
+                    let mut known = puz.known().clone();
+                    known.forget_provable();
+                    let mut fresh = PuzzleSolver::new(puzzle.clone())?;
+                    fresh.restore_known(known);
+                    assert_eq!(fresh.get_provable_varlits(), provable, "seed {seed}");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_state_valid() -> anyhow::Result<()> {
+        let puzzle = Arc::new(sudoku_4x4());
+        let lit = |s: &str| puzzle.litmap[&s.parse::<PuzLit>().unwrap()];
+        let mut puz = PuzzleSolver::new(puzzle.clone())?;
+        puz.add_known_lit(lit("grid[1, 2]=2"));
+        puz.check_state_valid()?;
+
+        // A known literal which is still listed as provable
+        let mut wrong = KnownLits::default();
+        wrong.add(&puzzle, lit("grid[1, 2]!=1"));
+        wrong.set_provable([lit("grid[1, 2]!=1")].into());
+        let err = wrong.check_valid(&puzzle).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "grid[1, 2]!=1 is known, but still listed as provable"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_assert_not_provable() -> anyhow::Result<()> {
+        let puz = sudoku_4x4_with("sudoku-4x4-open.json", "sudoku-4x4-open.dimacs");
+        let mut puz = PuzzleSolver::new(Arc::new(puz))?;
+        let lit = |s: &str| s.parse::<PuzLit>().unwrap();
+
+        // The first row is 1 _ _ 4, so the middle cells are 2 and 3 in some order
+        puz.assert_provable(&[lit("grid[1, 1]=1"), lit("grid[1, 2]!=1")])?;
+        puz.assert_not_provable(&[lit("grid[1, 2]=2"), lit("grid[1, 2]=3")])?;
+        let err = puz
+            .assert_not_provable(&[lit("grid[1, 2]=2"), lit("grid[1, 2]!=4")])
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "grid[1, 2]!=4 can be deduced, but should not be"
+        );
+        let err = puz.assert_provable(&[lit("grid[1, 2]=2")]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "grid[1, 2]=2 cannot be deduced, but should be"
+        );
+        assert!(puz.assert_provable(&[lit("grid[9, 9]=1")]).is_err());
+
+        // Once the first is known, so is the second
+        let known = puz.puzlit_to_lit(&lit("grid[1, 2]=2"));
+        puz.add_not_provable_known_lit(known);
+        puz.assert_provable(&[lit("grid[1, 3]=3")])?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_log() -> anyhow::Result<()> {
+        let puz = sudoku_4x4();
+        let mut puz = PuzzleSolver::new(Arc::new(puz))?;
+        let lits = puz.get_provable_varlits().clone();
+
+        // Some literals follow from the clues alone, so need no constraint
+        set_query_log_size(1000);
+        let (lit, mus) = lits
+            .into_iter()
+            .find_map(|lit| {
+                let mus = puz
+                    .get_var_mus_quick(lit, None, ShrinkOrder::InOrder)
+                    .unwrap()?;
+                let parse = puz.puzzleparse();
+                mus.iter()
+                    .any(|l| parse.lit_is_con(l))
+                    .then_some((lit, mus))
+            })
+            .unwrap();
+        set_query_log_size(0);
+
+        // Other tests may make queries at the same time
+        let log = take_query_log();
+        let query = log
+            .iter()
+            .find(|q| q.core.is_some() && q.assumptions.contains(&!lit))
+            .unwrap();
+        assert_eq!(query.outcome, QueryOutcome::Unsat);
+        let parse = puz.puzzleparse();
+        let text = query.describe(|l| parse.describe_lit(l));
+        assert!(text.starts_with(&format!("Query {} on ", query.number)));
+        assert!(text.contains(&parse.describe_lit(!lit)));
+        let con = mus.iter().find(|l| parse.lit_is_con(l)).unwrap();
+        assert!(text.contains(&format!("[{}]", parse.lit_to_con(con))));
+        assert!(parse.describe_lit(lit).starts_with("grid["));
+        assert!(take_query_log().iter().all(|q| q.number > query.number));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_minimize_mus() -> anyhow::Result<()> {
+        let puz = sudoku_4x4();
+        let mut puz = PuzzleSolver::new(Arc::new(puz))?;
+
+        let lits = puz.get_provable_varlits().clone();
+        let allcons = puz.puzzleparse().conset_lits.clone();
+
+        for &lit in lits.iter().take(4) {
+            let mus: BTreeSet<_> = puz
+                .get_var_mus_quick(lit, None, ShrinkOrder::InOrder)?
+                .unwrap()
+                .into_iter()
+                .collect();
+
+            for mc in [
+                MusContext::new(lit, mus.clone()),
+                MusContext::new(lit, allcons.clone()),
+            ] {
+                let min = puz.minimize_mus(&mc, Duration::from_secs(60));
+                assert!(min.mus_len() <= mc.mus_len());
+                assert!(min.mus.is_subset(&mc.mus));
+                assert_eq!(min.lits, mc.lits);
+
+                // The result must still be a proof, and removing anything must break it
+                let cons: Vec<_> = min.mus.iter().copied().collect();
+                assert!(puz.cons_prove_all(&cons, &min.lits));
+                for i in 0..cons.len() {
+                    let mut smaller = cons.clone();
+                    smaller.remove(i);
+                    assert!(!puz.cons_prove_all(&smaller, &min.lits));
+                }
+            }
+
+            // With no time, nothing changes
+            let mc = MusContext::new(lit, allcons.clone());
+            assert_eq!(puz.minimize_mus(&mc, Duration::ZERO).mus, mc.mus);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_essence() -> anyhow::Result<()> {
+        let result = crate::problem::util::test_utils::build_puzzleparse(
+            "./tst/little1.eprime",
+            "./tst/little1.param",
+        );
+
+        let result = Arc::new(result);
+
+        let mut puz = PuzzleSolver::new(result)?;
+
+        let varlits = puz.get_provable_varlits().clone();
+
+        insta::assert_debug_snapshot!(varlits);
+        insta::assert_debug_snapshot!(puz.get_literals_to_try_solving());
+
+        assert_eq!(puz.get_known_lits(), vec![]);
+
+        let l = *varlits.first().unwrap();
+
+        puz.add_known_lit(l);
+
+        insta::assert_debug_snapshot!(puz.get_provable_varlits().clone());
+        insta::assert_debug_snapshot!(puz.get_literals_to_try_solving());
+
+        assert!(puz.get_known_lits().contains(&l));
+        assert_eq!(puz.get_known_lits().len(), 5);
+
+        assert_eq!(varlits.len(), 16);
+
+        // Do a basic check we get a MUS for every varlit
+        for &lit in &varlits {
+            let mus = puz.get_var_mus_quick(lit, None, ShrinkOrder::InOrder)?;
+            let mus_limit = puz.get_var_mus_quick(lit, Some(100), ShrinkOrder::InOrder)?;
+            assert!(mus.is_some());
+            assert!(mus_limit.is_some());
+            println!("{lit:?} {mus:?}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_essence_config() -> anyhow::Result<()> {
+        let result = crate::problem::util::test_utils::build_puzzleparse(
+            "./tst/little1.eprime",
+            "./tst/little1.param",
+        );
+
+        let result = Arc::new(result);
+
+        let mut puz = PuzzleSolver::new_with_config(
+            result,
+            SolverConfig {
+                only_assignments: true,
+                ..SolverConfig::default()
+            },
+        )?;
+
+        let varlits = puz.get_provable_varlits().clone();
+
+        assert_eq!(puz.get_known_lits(), vec![]);
+
+        let l = *varlits.first().unwrap();
+
+        puz.add_known_lit(l);
+
+        assert!(puz.get_known_lits().contains(&l));
+        assert_eq!(puz.get_known_lits().len(), 5);
+
+        assert_eq!(varlits.len(), 4);
+
+        // Do a basic check we get a MUS for every varlit
+        for &lit in &varlits {
+            let mus = puz.get_var_mus_quick(lit, None, ShrinkOrder::InOrder)?;
+            let mus_limit = puz.get_var_mus_quick(lit, Some(100), ShrinkOrder::InOrder)?;
+            assert!(mus.is_some());
+            assert!(mus_limit.is_some());
+            println!("{lit:?} {mus:?}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_known_lits() -> anyhow::Result<()> {
+        let result = crate::problem::util::test_utils::build_puzzleparse(
+            "./tst/little1.eprime",
+            "./tst/little1.param",
+        );
+
+        let result = Arc::new(result);
+
+        let mut puz = PuzzleSolver::new(result)?;
+
+        let varlits = puz.get_provable_varlits().clone();
+
+        assert_eq!(varlits.len(), 16);
+        for &lit in &varlits {
+            let puzlit = puz.lit_to_puzlit(&lit);
+            for p in puzlit {
+                let indices = p.var().indices;
+                assert_eq!(indices.len(), 1);
+                // In the solution, forAll i, x[i]=i
+                // and the lits are the 'provable' lits
+                assert_eq!(indices[0] == p.val(), p.sign());
+            }
+        }
+
+        // Do a basic check we get a MUS for every varlit
+        for &lit in &varlits {
+            let mus = puz
+                .get_var_mus_quick(lit, None, ShrinkOrder::InOrder)?
+                .unwrap();
+            let mus_limit = puz
+                .get_var_mus_quick(lit, Some(100), ShrinkOrder::InOrder)?
+                .unwrap();
+            let tiny_muses = puz.get_var_mus_size_1(lit, None)?;
+            let tiny_muses_1 = puz.get_var_mus_size_1(lit, Some(1))?;
+            let cake_mus = puz.get_var_mus_cake(lit, 3, ShrinkOrder::InOrder)?.unwrap();
+            assert_eq!(mus.len() == 1, !tiny_muses.is_empty());
+            assert_eq!(!tiny_muses_1.is_empty(), !tiny_muses.is_empty());
+            if mus.len() == 1 {
+                assert!(tiny_muses.iter().any(|x| x == &mus));
+                assert!(tiny_muses.iter().any(|x| x == &mus_limit));
+                assert!(tiny_muses.iter().any(|x| x == &tiny_muses_1[0]));
+                assert_eq!(cake_mus.len(), 1);
+            }
+            println!("{lit:?} {mus:?}");
+        }
+
+        // Check their negations have no mus (this isn't always true,
+        // only for puzzles with only one solution)
+        for &lit in &varlits {
+            let lit = !lit;
+            let mus = puz.get_var_mus_quick(lit, None, ShrinkOrder::InOrder)?;
+            let mus_limit = puz.get_var_mus_quick(lit, Some(100), ShrinkOrder::InOrder)?;
+            let tiny_muses = puz.get_var_mus_size_1(lit, None)?;
+            let tiny_muses_1 = puz.get_var_mus_size_1(lit, Some(1))?;
+            let cake_mus = puz.get_var_mus_cake(lit, 2, ShrinkOrder::InOrder)?;
+            assert!(mus.is_none());
+            assert!(mus_limit.is_none());
+            assert!(tiny_muses.is_empty());
+            assert!(tiny_muses_1.is_empty());
+            assert!(cake_mus.is_none());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_many_lits() -> anyhow::Result<()> {
+        let result = crate::problem::util::test_utils::build_puzzleparse(
+            "./tst/little1.eprime",
+            "./tst/little1.param",
+        );
+
+        let result = Arc::new(result);
+
+        let mut puz = PuzzleSolver::new(result)?;
+
+        let varlits = puz.get_provable_varlits().clone();
+
+        assert_eq!(varlits.len(), 16);
+        for &lit in &varlits {
+            let puzlit = puz.lit_to_puzlit(&lit);
+            for p in puzlit {
+                let indices = p.var().indices;
+                assert_eq!(indices.len(), 1);
+                // In the solution, forAll i, x[i]=i
+                // and the lits are the 'provable' lits
+                assert_eq!(indices[0] == p.val(), p.sign());
+            }
+        }
+
+        let muses = puz.get_many_vars_mus_first(&varlits, None);
+        let muses_quick = puz.get_many_vars_small_mus_quick(&varlits, &MusConfig::default(), None);
+
+        assert!(!muses.is_empty());
+        assert!(!muses_quick.is_empty());
+
+        let muses_2 = puz.get_many_vars_mus_first(
+            &(varlits.iter().map(|&x| !x).collect()),
+            Some(muses.clone()),
+        );
+        let muses_quick_2 = puz.get_many_vars_mus_first(
+            &(varlits.iter().map(|&x| !x).collect()),
+            Some(muses_quick.clone()),
+        );
+
+        assert!(!muses_2.is_empty());
+        assert!(!muses_quick_2.is_empty());
+
+        assert_eq!(muses.min(), muses_2.min());
+        assert_eq!(muses_quick.min(), muses_quick_2.min());
+
+        for (l, btree) in muses_2.muses() {
+            for mus in btree {
+                let list = puz.get_varlits_provable_by_mus(&varlits, mus);
+                let scopelist = puz.get_all_lits_solved_by_mus(mus);
+                assert!(&list.contains(l));
+                assert!(&scopelist.lits.contains(l));
+                assert_eq!(
+                    list.iter().collect::<HashSet<_>>(),
+                    scopelist.lits.iter().collect::<HashSet<_>>()
+                );
+            }
+        }
+
+        let neg_muses = puz.get_many_vars_mus_first(&(varlits.iter().map(|&x| !x).collect()), None);
+        let neg_muses_quick =
+            puz.get_many_vars_mus_first(&(varlits.iter().map(|&x| !x).collect()), None);
+
+        assert!(neg_muses.is_empty());
+        assert!(neg_muses_quick.is_empty());
+
+        let neg_muses_2 = puz.get_many_vars_mus_first(
+            &(varlits.iter().map(|&x| !x).collect()),
+            Some(neg_muses.clone()),
+        );
+        let neg_muses_quick_2 = puz.get_many_vars_mus_first(
+            &(varlits.iter().map(|&x| !x).collect()),
+            Some(neg_muses_quick.clone()),
+        );
+
+        assert!(neg_muses_2.is_empty());
+        assert!(neg_muses_quick_2.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_adaptive_repeats() -> anyhow::Result<()> {
+        use super::{AdaptiveRepeats, get_repeat_stats, size_variance};
+
+        assert_eq!(size_variance(&[3]), None);
+        assert_eq!(size_variance(&[2, 2, 2]), Some(0.0));
+        assert_eq!(size_variance(&[1, 3]), Some(1.0));
+
+        let puzzle = sudoku_4x4();
+        let mut puz = PuzzleSolver::new(Arc::new(puzzle))?;
+        let varlits = puz.get_provable_varlits().clone();
+
+        // Every literal has a tiny MUS, which would end the search early
+        let mut config = MusConfig::new_with_repeats(2);
+        config.find_bigger = true;
+        let fixed = puz.get_many_vars_small_mus_quick(&varlits, &config, None);
+        assert!(!fixed.is_empty());
+
+        // Every variance is above a negative threshold, so each literal with
+        // MUSes is searched for the most times allowed
+        config.adaptive = Some(AdaptiveRepeats {
+            max_repeats: 4,
+            variance_threshold: -1.0,
+        });
+        let before = get_repeat_stats();
+        let adaptive = puz.get_many_vars_small_mus_quick(&varlits, &config, None);
+        let after = get_repeat_stats();
+
+        assert!(!adaptive.is_empty());
+        assert!(after.extra_rounds >= before.extra_rounds + 2);
+        assert!(after.extra_searches >= before.extra_searches + 2 * adaptive.muses().len() as i64);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_random_solution_little() -> anyhow::Result<()> {
+        let result = crate::problem::util::test_utils::build_puzzleparse(
+            "./tst/little1.eprime",
+            "./tst/little1.param",
+        );
+
+        let result = Arc::new(result);
+
+        let mut gens = BTreeSet::new();
+
+        for i in 0..11 {
+            let mut rng = rand_chacha::ChaCha20Rng::seed_from_u64(i);
+
+            let mut puz = PuzzleSolver::new(result.clone())?;
+
+            let sol = if i == 11 {
+                puz.random_solution(&mut rng, None)
+            } else {
+                puz.random_solution(&mut rng, Some(i as usize))
+            };
+
+            gens.insert(sol);
+        }
+
+        assert_eq!(gens.len(), 1);
+
+        let sol = gens.into_iter().next().unwrap();
+
+        insta::assert_debug_snapshot!(sol);
+
+        let puz = PuzzleSolver::new(result)?;
+
+        let puzsol: BTreeSet<_> = sol
+            .into_iter()
+            .flat_map(|lit| puz.lit_to_puzlit(&lit))
+            .collect();
+
+        insta::assert_debug_snapshot!(puzsol);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_random_solution_wall() -> anyhow::Result<()> {
+        let result = crate::problem::util::test_utils::build_puzzleparse(
+            "./tst/minesweeper.eprime",
+            "./tst/minesweeperWall.param",
+        );
+
+        let result = Arc::new(result);
+
+        let mut rng = rand_chacha::ChaCha20Rng::seed_from_u64(2);
+
+        let mut puz = PuzzleSolver::new(result)?;
+
+        let sol = puz.random_solution(&mut rng, None);
+
+        insta::assert_debug_snapshot!(sol);
+
+        let puzsol: BTreeSet<_> = sol
+            .into_iter()
+            .flat_map(|lit| puz.lit_to_puzlit(&lit))
+            .collect();
+
+        insta::assert_debug_snapshot!(puzsol);
+
+        Ok(())
+    }
+}
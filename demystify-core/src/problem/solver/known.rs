@@ -0,0 +1,330 @@
+//! The literals known to be true at the current point of a solve.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use anyhow::bail;
+use itertools::Itertools;
+use rustsat::types::Lit;
+use tracing::info;
+
+use crate::problem::{PuzLit, PuzVar, VarValPair, parse::PuzzleParse};
+
+/// The literals known so far, in the order they became known, and (once
+/// something has looked for them) the literals which could be proved next.
+///
+/// For the variables in [`PuzzleParse::at_most_one_vars`], knowing `x = i`
+/// is stored as the value of `x`, and each `x != j` it implies is worked out
+/// when asked for. Every other known literal is stored.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KnownLits {
+    /// The stored literals, which are also what the SAT solver is given
+    lits: Vec<Lit>,
+    /// The same literals as `lits`, to quickly check if one is known
+    set: HashSet<Lit>,
+    /// The known value of each of [`PuzzleParse::at_most_one_vars`] which
+    /// has one
+    assigned: HashMap<PuzVar, i64>,
+    /// Cleared whenever knowing a new literal may make more literals provable
+    provable: Option<BTreeSet<Lit>>,
+}
+
+impl KnownLits {
+    /// Every known literal, in the order they became known. Each `x != j`
+    /// implied by knowing `x = i` comes straight after `x = i`.
+    #[must_use]
+    pub fn lits(&self, puzzle: &PuzzleParse) -> Vec<Lit> {
+        let mut lits = Vec::with_capacity(self.lits.len());
+        for &lit in &self.lits {
+            lits.push(lit);
+            for puzlit in lit_to_puzlit(puzzle, lit) {
+                let var = puzlit.var();
+                if puzlit.sign() && self.assigned.get(&var) == Some(&puzlit.val()) {
+                    lits.extend(
+                        ruled_out(puzzle, &var, puzlit.val()).filter(|l| !self.set.contains(l)),
+                    );
+                }
+            }
+        }
+        lits
+    }
+
+    /// The known literals the SAT solver needs to be told about. This leaves
+    /// out the values ruled out by knowing the value of a variable in
+    /// [`PuzzleParse::at_most_one_vars`], which the solver deduces itself,
+    /// so it stays much shorter than [`KnownLits::lits`] on puzzles with
+    /// large domains.
+    #[must_use]
+    pub fn assumptions(&self) -> &[Lit] {
+        &self.lits
+    }
+
+    #[must_use]
+    pub fn contains(&self, puzzle: &PuzzleParse, lit: Lit) -> bool {
+        self.set.contains(&lit) || self.rules_out(puzzle, lit)
+    }
+
+    /// Whether `lit` or its negation is known.
+    #[must_use]
+    pub fn decides(&self, puzzle: &PuzzleParse, lit: Lit) -> bool {
+        self.contains(puzzle, lit) || self.contains(puzzle, !lit)
+    }
+
+    /// The literals which can be proved, but are not known yet, if they have
+    /// been found since they last changed.
+    #[must_use]
+    pub fn provable(&self) -> Option<&BTreeSet<Lit>> {
+        self.provable.as_ref()
+    }
+
+    pub fn set_provable(&mut self, provable: BTreeSet<Lit>) {
+        self.provable = Some(provable);
+    }
+
+    /// Forgets the provable literals, so they are found again.
+    pub fn forget_provable(&mut self) {
+        self.provable = None;
+    }
+
+    /// Adds `lit`, without checking it can be deduced. Knowing `x = i` also
+    /// means knowing `x != j` for every other `j` in the domain of `x`, which
+    /// cleans up the output, and reveals anything `x = i` reveals. For
+    /// [`PuzzleParse::at_most_one_vars`] these are only recorded as the value
+    /// of `x`, otherwise they are stored, and given to the SAT solver.
+    pub fn add(&mut self, puzzle: &PuzzleParse, lit: Lit) {
+        if self.contains(puzzle, lit) {
+            return;
+        }
+        self.add_internal(puzzle, lit);
+        for puzlit in lit_to_puzlit(puzzle, lit) {
+            if puzlit.sign() {
+                let var = puzlit.var();
+                let val = puzlit.val();
+                if puzzle.at_most_one_vars.contains(&var) {
+                    self.assigned.insert(var.clone(), val);
+                    if let Some(provable) = self.provable.as_mut() {
+                        for l in ruled_out(puzzle, &var, val) {
+                            provable.remove(&l);
+                        }
+                    }
+                } else {
+                    for new_lit in ruled_out(puzzle, &var, val) {
+                        if !self.contains(puzzle, new_lit) {
+                            self.add_internal(puzzle, new_lit);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Checks the literals agree with each other and with `puzzle`, as they
+    /// should after any change: each is stored once, and only the values of
+    /// [`PuzzleParse::at_most_one_vars`] are recorded apart from them. Every
+    /// value but `i` is ruled out for a variable known to be `i`, whatever is
+    /// revealed by a known literal (from [`PuzzleParse::reveal_map`]) is known
+    /// too, and no known literal is still listed as provable.
+    pub fn check_valid(&self, puzzle: &PuzzleParse) -> anyhow::Result<()> {
+        let show = |lit: &Lit| lit_to_puzlit(puzzle, *lit).iter().join(" / ");
+        if self.set.len() != self.lits.len() {
+            bail!(
+                "{} literals are stored, but only {} are different",
+                self.lits.len(),
+                self.set.len()
+            );
+        }
+        if let Some(lit) = self.lits.iter().find(|l| !self.set.contains(l)) {
+            bail!("{} is known, but cannot be looked up", show(lit));
+        }
+        for (var, &val) in &self.assigned {
+            let lit = PuzLit::new_eq(VarValPair::new(var, val));
+            if !puzzle.at_most_one_vars.contains(var)
+                || !puzzle
+                    .litmap
+                    .get(&lit)
+                    .is_some_and(|l| self.set.contains(l))
+            {
+                bail!("{var} is recorded as {val}, but {lit} is not known");
+            }
+        }
+
+        for lit in &self.lits {
+            // Revealed literals are added on their own
+            let puzlits = lit_to_puzlit(puzzle, *lit)
+                .iter()
+                .filter(|p| p.sign() && puzzle.varset_lits.contains(lit));
+            for puzlit in puzlits {
+                let var = puzlit.var();
+                if puzzle.at_most_one_vars.contains(&var)
+                    && self.assigned.get(&var) != Some(&puzlit.val())
+                {
+                    bail!("{puzlit} is known, but {var} is not recorded as it");
+                }
+                for &val in &puzzle.domainmap[&var] {
+                    let other = PuzLit::new_neq(VarValPair::new(&var, val));
+                    if val != puzlit.val() && !self.contains(puzzle, puzzle.litmap[&other]) {
+                        bail!("{puzlit} is known, but {other} is not");
+                    }
+                }
+            }
+            if let Some(revealed) = puzzle.reveal_map.get(lit)
+                && !self.contains(puzzle, *revealed)
+            {
+                bail!(
+                    "{} is known, but {} which it reveals is not",
+                    show(lit),
+                    show(revealed)
+                );
+            }
+        }
+
+        if let Some(lit) = self
+            .provable
+            .iter()
+            .flatten()
+            .find(|&&l| self.contains(puzzle, l))
+        {
+            bail!("{} is known, but still listed as provable", show(lit));
+        }
+        Ok(())
+    }
+
+    /// Whether `lit` is `x != j` for one of
+    /// [`PuzzleParse::at_most_one_vars`], where `x = i` is known, so the SAT
+    /// solver deduces it.
+    fn rules_out(&self, puzzle: &PuzzleParse, lit: Lit) -> bool {
+        !self.assigned.is_empty()
+            && puzzle.invlitmap.get(&lit).is_some_and(|puzlits| {
+                puzlits.iter().any(|p| {
+                    !p.sign()
+                        && self
+                            .assigned
+                            .get(&p.var())
+                            .is_some_and(|&val| val != p.val())
+                })
+            })
+    }
+
+    /// Stores `lit`, and anything it reveals.
+    fn add_internal(&mut self, puzzle: &PuzzleParse, lit: Lit) {
+        if let Some(provable) = self.provable.as_mut() {
+            provable.remove(&lit);
+        }
+        self.push(lit);
+
+        for l in lit_to_puzlit(puzzle, lit) {
+            // Only reveal from positive varvalpairs
+            if !l.sign() {
+                continue;
+            }
+
+            let name = l.varval().var().name().clone();
+            if let Some(value) = puzzle.eprime.reveal.get(&name) {
+                // Build the 'reveal' variable
+                let mut vec = l.varval().var().indices().clone();
+                vec.push(l.varval().val());
+
+                let vvpair = VarValPair::new(&PuzVar::new(value, vec), 1);
+                let imply_lit = PuzLit::new_eq(vvpair);
+                info!(target: "solver", "{l} reveals {imply_lit}");
+
+                let puzlit = puzzle
+                    .litmap
+                    .get(&imply_lit)
+                    .expect("REVEAL variable missing: {imply_lit}");
+                if !self.contains(puzzle, *puzlit) {
+                    self.push(*puzlit);
+                }
+                self.provable = None;
+            }
+        }
+    }
+
+    fn push(&mut self, lit: Lit) {
+        self.lits.push(lit);
+        self.set.insert(lit);
+    }
+}
+
+/// The literals `var != j`, for each `j` in the domain of `var` but `val`.
+fn ruled_out<'a>(
+    puzzle: &'a PuzzleParse,
+    var: &'a PuzVar,
+    val: i64,
+) -> impl Iterator<Item = Lit> + 'a {
+    puzzle
+        .domainmap
+        .get(var)
+        .expect("Fatal error getting var")
+        .iter()
+        .filter(move |&&d| d != val)
+        .map(move |&d| {
+            let puzlit = PuzLit::new_neq(VarValPair::new(var, d));
+            *puzzle
+                .litmap
+                .get(&puzlit)
+                .unwrap_or_else(|| panic!("Missing puzlit: {puzlit}"))
+        })
+}
+
+fn lit_to_puzlit(puzzle: &PuzzleParse, lit: Lit) -> &BTreeSet<PuzLit> {
+    puzzle
+        .invlitmap
+        .get(&lit)
+        .unwrap_or_else(|| panic!("Mizzing lit: {lit}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::problem::util::test_utils::sudoku_4x4;
+
+    #[test]
+    fn test_known_lits_fill_domain() -> anyhow::Result<()> {
+        let puz = sudoku_4x4();
+
+        let var = PuzVar::new("grid", vec![1, 2]);
+        let lit = |val, eq: bool| {
+            let vvp = VarValPair::new(&var, val);
+            puz.litmap[&if eq {
+                PuzLit::new_eq(vvp)
+            } else {
+                PuzLit::new_neq(vvp)
+            }]
+        };
+
+        let mut known = KnownLits::default();
+        known.set_provable([lit(2, true), lit(3, false), lit(4, false)].into());
+        known.add(&puz, lit(2, true));
+
+        // The other values are ruled out, and so are no longer provable
+        assert_eq!(
+            known.lits(&puz),
+            [lit(2, true), lit(1, false), lit(3, false), lit(4, false)]
+        );
+        for val in [1, 3, 4] {
+            assert!(known.contains(&puz, lit(val, false)));
+            assert!(known.decides(&puz, lit(val, true)));
+        }
+        assert_eq!(known.provable(), Some(&BTreeSet::new()));
+        // The SAT solver rules out the other values itself
+        assert_eq!(known.assumptions(), [lit(2, true)]);
+
+        known.add(&puz, lit(2, true));
+        known.add(&puz, lit(3, false));
+        assert_eq!(known.lits(&puz).len(), 4);
+        known.check_valid(&puz)?;
+
+        // Values ruled out before the assignment are listed once
+        let mut early = KnownLits::default();
+        early.add(&puz, lit(3, false));
+        early.add(&puz, lit(2, true));
+        assert_eq!(
+            early.lits(&puz),
+            [lit(3, false), lit(2, true), lit(1, false), lit(4, false)]
+        );
+        assert_eq!(early.assumptions(), [lit(3, false), lit(2, true)]);
+        early.check_valid(&puz)?;
+
+        Ok(())
+    }
+}
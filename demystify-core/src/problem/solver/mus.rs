@@ -0,0 +1,639 @@
+//! Searches for minimal unsatisfiable subsets (MUSes) of the constraints,
+//! which explain why a literal must be true given what is known.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::atomic::{AtomicI64, AtomicU64};
+use std::time::{Duration, Instant};
+
+use itertools::Itertools;
+use rand::SeedableRng;
+use rand::seq::SliceRandom;
+use rayon::iter::{IntoParallelRefIterator, ParallelBridge, ParallelIterator};
+use rustsat::types::Lit;
+use tracing::info;
+
+use crate::problem::musdict::{MusContext, MusDict};
+use crate::satcore::{SearchResult, ShrinkOrder};
+
+use super::probe::Prober;
+use super::{
+    MusConfig, MusSearch, MusSearchResult, REPEAT_EXTRA_SEARCHES, REPEAT_ROUNDS, REPEAT_SEARCHES,
+    SearchBudget, SearchContext, SearchPhase, Strategy, size_variance, skip_search_error,
+};
+
+/// Finds MUSes for literals, see the [module documentation](self).
+#[derive(Clone, Copy)]
+pub struct MusFinder<'a> {
+    ctx: SearchContext<'a>,
+}
+
+impl<'a> MusFinder<'a> {
+    #[must_use]
+    pub fn new(ctx: SearchContext<'a>) -> Self {
+        Self { ctx }
+    }
+
+    fn is_constraint(&self, lit: &Lit) -> bool {
+        self.ctx.puzzle.conset_lits.contains(lit)
+    }
+
+    fn var_mus_size_1_loop(
+        &self,
+        lit: Lit,
+        count: Option<usize>,
+        lits: &[Lit],
+        muses: &mut BTreeSet<Vec<Lit>>,
+    ) -> SearchResult<()> {
+        if lits.is_empty() || count.is_some_and(|x| muses.len() >= x) {
+            return Ok(());
+        }
+
+        let mut lit_cpy = lits.to_vec();
+        lit_cpy.push(!lit);
+
+        let solvable = self
+            .ctx
+            .satcore()
+            .assumption_solve_with_core(self.ctx.known.assumptions(), &lit_cpy)?;
+
+        if let Some(core) = solvable {
+            if lits.len() == 1 {
+                muses.insert(lits.to_vec());
+            } else {
+                // This core can be found early. We might find it again later,
+                // but we add it here as it might make us find enough cores (in particular
+                // if we only want one))
+                if core.len() == 2 {
+                    let mus = core
+                        .iter()
+                        .copied()
+                        .filter(|x| lits.contains(x))
+                        .collect_vec();
+                    assert!(mus.len() == 1);
+                    muses.insert(mus);
+                }
+                let mid = lits.len() / 2;
+                let (left, right) = lits.split_at(mid);
+                self.var_mus_size_1_loop(lit, count, left, muses)?;
+                self.var_mus_size_1_loop(lit, count, right, muses)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// MUSes of size 0 or 1 which prove `lit`, at most `count` of them (or
+    /// all of them if `count` is `None`).
+    pub fn var_mus_size_1(&self, lit: Lit, count: Option<usize>) -> SearchResult<Vec<Vec<Lit>>> {
+        // First of all, check if there is a MUS of size 0,
+        // mainly because it makes the rest of this algorithm
+        // degenerate.
+        let just_lit = vec![!lit];
+
+        let solvable = self
+            .ctx
+            .satcore()
+            .assumption_solve(self.ctx.known.assumptions(), &just_lit)?;
+
+        if !solvable {
+            return Ok(vec![vec![]]);
+        }
+
+        let mut conset = self.ctx.puzzle.conset_lits.iter().copied().collect_vec();
+
+        let mut rng = rand_chacha::ChaCha20Rng::seed_from_u64(2);
+        conset.shuffle(&mut rng);
+
+        let mut muses: BTreeSet<Vec<Lit>> = BTreeSet::new();
+
+        let mid = conset.len() / 2;
+        let (left, right) = conset.split_at(mid);
+        self.var_mus_size_1_loop(lit, count, left, &mut muses)?;
+        self.var_mus_size_1_loop(lit, count, right, &mut muses)?;
+        Ok(muses.into_iter().collect_vec())
+    }
+
+    /// The literals in `lits` which can be deduced from at most one
+    /// constraint, using a single unsatisfiable core for each literal and no
+    /// MUS search, as in [`PuzzleSolver::get_single_constraint_muses`](super::PuzzleSolver::get_single_constraint_muses).
+    #[must_use]
+    pub fn single_constraint_muses(&self, lits: &BTreeSet<Lit>) -> MusDict {
+        let conset: Vec<Lit> = self.ctx.puzzle.conset_lits.iter().copied().collect();
+
+        let muses: Vec<_> = lits
+            .iter()
+            .par_bridge()
+            .filter_map(|&x| {
+                let mut assumptions = conset.clone();
+                assumptions.push(!x);
+                let core = skip_search_error(
+                    SearchPhase::ChainedSingles,
+                    self.ctx
+                        .satcore()
+                        .assumption_solve_with_core(self.ctx.known.assumptions(), &assumptions),
+                )??;
+                let mus: BTreeSet<Lit> =
+                    core.into_iter().filter(|l| self.is_constraint(l)).collect();
+                (mus.len() <= 1).then_some((x, mus))
+            })
+            .collect();
+
+        let mut md = MusDict::new();
+        for (lit, mus) in muses {
+            md.add_mus(lit, mus);
+        }
+        md
+    }
+
+    /// Whether the known literals alone prove `lit`. A solver timeout is
+    /// treated as 'no'.
+    #[must_use]
+    pub fn has_mus_size_0(&self, lit: Lit) -> bool {
+        let just_lit = vec![!lit];
+
+        self.ctx
+            .satcore()
+            .assumption_solve(self.ctx.known.assumptions(), &just_lit)
+            .is_ok_and(|solvable| !solvable)
+    }
+
+    /// The literals in `lits` which the known literals alone prove.
+    #[must_use]
+    pub fn many_vars_mus_size_0(&self, lits: &BTreeSet<Lit>) -> BTreeSet<Lit> {
+        lits.par_iter()
+            .filter(|&&x| self.has_mus_size_0(x))
+            .copied()
+            .collect()
+    }
+
+    /// Shrinks `lits` (which must include `!lit`) to a MUS of at most
+    /// `max_size` constraints, returning only the constraints.
+    fn shrink(
+        &self,
+        lits: &[Lit],
+        max_size: Option<i64>,
+        order: ShrinkOrder,
+    ) -> SearchResult<Option<Vec<Lit>>> {
+        let mus = self.ctx.satcore().quick_mus(
+            self.ctx.known.assumptions(),
+            lits,
+            max_size.map(|x| x + 1),
+            order,
+        )?;
+        Ok(mus.map(|m| m.into_iter().filter(|x| self.is_constraint(x)).collect()))
+    }
+
+    /// A MUS which proves `lit`, shrinking every constraint in `order`.
+    pub fn var_mus_quick(
+        &self,
+        lit: Lit,
+        max_size: Option<i64>,
+        order: ShrinkOrder,
+    ) -> SearchResult<Option<Vec<Lit>>> {
+        assert!(self.ctx.puzzle.varset_lits.contains(&lit));
+
+        let mut lits: Vec<Lit> = vec![];
+        lits.extend(self.ctx.puzzle.conset_lits.iter());
+        lits.push(!lit);
+        self.shrink(&lits, max_size, order)
+    }
+
+    /// As [`MusFinder::var_mus_quick`], but first drops a random slice of
+    /// the constraints, which likely leaves a MUS of `max_size`.
+    pub fn var_mus_slice(
+        &self,
+        lit: Lit,
+        max_size: Option<i64>,
+        order: ShrinkOrder,
+    ) -> SearchResult<Option<Vec<Lit>>> {
+        assert!(self.ctx.puzzle.varset_lits.contains(&lit));
+
+        let mut lits: Vec<Lit> = vec![];
+
+        let mut conset = self.ctx.puzzle.conset_lits.iter().copied().collect_vec();
+
+        conset.shuffle(&mut rand::rng());
+
+        // This code tries to deduce how many elements we can drop from 'conset', such that
+        // we will still have an 80% chance of leaving a MUS of size 'max_size'.
+        // The code is a bit more horrible than the simplest version, to make sure we do
+        // not break when very large, or small, MUSes are required.
+
+        let mut percentage_reduce = 0.4;
+
+        if let Some(size) = max_size
+            && size > 0
+        {
+            percentage_reduce = 1.0 - (size as f64) / (conset.len() as f64);
+        }
+
+        percentage_reduce = percentage_reduce.clamp(0.4, 0.9999);
+
+        let trims = (0.8_f64.ln() / (percentage_reduce.ln())) as i64;
+
+        let trims = trims.clamp(0, (conset.len() as i64) / 2);
+
+        info!(target: "solver", "trimming {} from {} because max_size = {:?}", trims, conset.len(), max_size);
+
+        lits.extend(conset.into_iter().skip(trims as usize));
+
+        lits.push(!lit);
+        self.shrink(&lits, max_size, order)
+    }
+
+    /// As [`MusFinder::var_mus_quick`], but splits the constraints into
+    /// `max_size + 1` pieces and tries leaving out each in turn, one of
+    /// which must miss any MUS of `max_size`.
+    pub fn var_mus_cake(
+        &self,
+        lit: Lit,
+        max_size: i64,
+        order: ShrinkOrder,
+    ) -> SearchResult<Option<Vec<Lit>>> {
+        assert!(self.ctx.puzzle.varset_lits.contains(&lit));
+
+        let mut conset = self.ctx.puzzle.conset_lits.iter().copied().collect_vec();
+
+        conset.shuffle(&mut rand::rng());
+
+        let conset_chunks: Vec<Vec<Lit>> = (0..=max_size)
+            .map(|i| {
+                conset
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(j, &lit)| {
+                        if j % (max_size as usize + 1) == i as usize {
+                            None
+                        } else {
+                            Some(lit)
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        for mut lits in conset_chunks {
+            lits.push(!lit);
+            if let Some(m) = self.shrink(&lits, Some(max_size), order)? {
+                return Ok(Some(m));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// A MUS which proves at least one of `a` and `b` is true, or `None` if
+    /// both can be false.
+    pub fn either_mus(&self, a: Lit, b: Lit, order: ShrinkOrder) -> SearchResult<Option<Vec<Lit>>> {
+        let mut lits: Vec<Lit> = self.ctx.puzzle.conset_lits.iter().copied().collect();
+        lits.push(!a);
+        lits.push(!b);
+        self.shrink(&lits, None, order)
+    }
+
+    /// One MUS for each of `lits`, added to `musdict`, without trying to
+    /// make them small.
+    #[must_use]
+    pub fn many_vars_mus_first(&self, lits: &BTreeSet<Lit>, musdict: Option<MusDict>) -> MusDict {
+        let muses: Vec<_> = lits
+            .par_iter()
+            .filter_map(|&x| {
+                let mus = skip_search_error(
+                    SearchPhase::FirstMuses,
+                    self.var_mus_quick(x, None, ShrinkOrder::InOrder),
+                )??;
+                Some((x, mus))
+            })
+            .collect();
+        let mut md = musdict.unwrap_or_default();
+        for (k, v) in muses {
+            md.add_mus(k, v.into_iter().collect());
+        }
+        md
+    }
+
+    /// Small MUSes for the literals in `lits`, added to `musdict`. This
+    /// first scans for tiny MUSes, then searches for MUSes of growing sizes
+    /// from `config.base_size_mus`, until some are found or `budget` runs
+    /// out.
+    #[must_use]
+    pub fn many_vars_small_mus(
+        &self,
+        lits: &BTreeSet<Lit>,
+        config: &MusConfig,
+        musdict: Option<MusDict>,
+        budget: &SearchBudget,
+    ) -> MusSearchResult {
+        self.search_small_muses(lits, config, musdict.unwrap_or_default(), None, budget)
+    }
+
+    /// Carries on a [`MusFinder::many_vars_small_mus`] search which ran out
+    /// of budget, from the size it stopped at.
+    #[must_use]
+    pub fn resume_small_muses(
+        &self,
+        lits: &BTreeSet<Lit>,
+        config: &MusConfig,
+        search: MusSearch,
+        budget: &SearchBudget,
+    ) -> MusSearchResult {
+        self.search_small_muses(lits, config, search.muses, Some(search.next_size), budget)
+    }
+
+    /// Searches for MUSes starting at size `resume_size`, or scans for tiny
+    /// MUSes first and then starts at `config.base_size_mus` if it is `None`.
+    fn search_small_muses(
+        &self,
+        lits: &BTreeSet<Lit>,
+        config: &MusConfig,
+        mut md: MusDict,
+        resume_size: Option<i64>,
+        budget: &SearchBudget,
+    ) -> MusSearchResult {
+        let tracker = budget.start();
+
+        let mut mus_size = resume_size.unwrap_or(config.base_size_mus);
+        let best_mus_size = AtomicI64::new(mus_size);
+
+        // A resumed search has already scanned for tiny MUSes
+        if resume_size.is_none() {
+            info!(target: "solve", "scanning for tiny muses");
+
+            let muses: Vec<_> = lits
+                .iter()
+                .par_bridge()
+                .filter_map(|&x| {
+                    let mus =
+                        skip_search_error(SearchPhase::TinyMuses, self.var_mus_size_1(x, Some(1)))?;
+                    Some((x, mus.into_iter().next()?))
+                })
+                .collect();
+
+            if !muses.is_empty() && !config.find_bigger {
+                info!(target: "solve", "found tiny muses");
+                for (k, v) in muses {
+                    md.add_mus(k, v.into_iter().collect());
+                }
+                return MusSearchResult::Complete(md);
+            }
+        }
+
+        let order = config.shrink_order;
+        // The time spent searching for each literal, in nanoseconds
+        let spent: BTreeMap<Lit, AtomicU64> =
+            lits.iter().map(|&x| (x, AtomicU64::new(0))).collect();
+        let over_time = |x: &Lit| {
+            config
+                .literal_time_limit
+                .is_some_and(|limit| spent[x].load(Relaxed) >= limit.as_nanos() as u64)
+        };
+        let mark_timed_out = |md: &mut MusDict| {
+            for &x in lits.iter().filter(|x| over_time(x)) {
+                md.mark_timed_out(x);
+            }
+        };
+        info!(target: "solver", "scanning for {} muses", lits.len());
+        loop {
+            info!(target: "solver", "scanning for muses size {}", mus_size);
+            best_mus_size.store(mus_size, Relaxed);
+            let search = |&x: &Lit| {
+                if tracker.exhausted() || over_time(&x) {
+                    return (x, Ok(None));
+                }
+                REPEAT_SEARCHES.fetch_add(1, Relaxed);
+                let start = Instant::now();
+
+                let mus_test_size = best_mus_size.load(Relaxed);
+                let mus_test_size = if config.find_bigger {
+                    mus_test_size + 3 * 3
+                } else {
+                    mus_test_size
+                };
+
+                let ret = match config.strategy {
+                    Strategy::Slice => self.var_mus_slice(x, Some(mus_test_size), order),
+                    Strategy::Cake => self.var_mus_cake(x, mus_test_size, order),
+                    Strategy::Quick => self.var_mus_quick(x, Some(mus_test_size), order),
+                    Strategy::Dynamic => {
+                        if mus_test_size < 5 {
+                            self.var_mus_cake(x, mus_test_size, order)
+                        } else {
+                            self.var_mus_slice(x, Some(mus_test_size), order)
+                        }
+                    }
+                };
+                spent[&x].fetch_add(start.elapsed().as_nanos() as u64, Relaxed);
+                if let Ok(Some(y)) = &ret {
+                    best_mus_size.fetch_min(y.len() as i64, Relaxed);
+                }
+                (x, ret)
+            };
+            let found = |(x, ret): (Lit, SearchResult<Option<Vec<Lit>>>)| {
+                Some((x, skip_search_error(SearchPhase::SmallMuses, ret)??))
+            };
+
+            let mut muses: Vec<_> = lits
+                .iter()
+                .flat_map(|x| std::iter::repeat_n(x, config.repeats as usize))
+                .par_bridge()
+                .map(search)
+                .filter_map(found)
+                .collect();
+
+            if let Some(adaptive) = config.adaptive {
+                let mut searched = config.repeats;
+                while searched < adaptive.max_repeats && !tracker.exhausted() {
+                    let mut sizes: BTreeMap<Lit, Vec<usize>> = BTreeMap::new();
+                    for (lit, mus) in &muses {
+                        sizes.entry(*lit).or_default().push(mus.len());
+                    }
+                    let varied: Vec<Lit> = sizes
+                        .into_iter()
+                        .filter(|(_, sizes)| {
+                            size_variance(sizes).is_some_and(|v| v > adaptive.variance_threshold)
+                        })
+                        .map(|(lit, _)| lit)
+                        .collect();
+                    if varied.is_empty() {
+                        break;
+                    }
+
+                    info!(target: "solver", "searching again for {} literals with varied muses", varied.len());
+                    REPEAT_ROUNDS.fetch_add(1, Relaxed);
+                    REPEAT_EXTRA_SEARCHES.fetch_add(varied.len() as i64, Relaxed);
+                    muses.extend(
+                        varied
+                            .par_iter()
+                            .map(search)
+                            .filter_map(found)
+                            .collect::<Vec<_>>(),
+                    );
+                    searched += 1;
+                }
+            }
+
+            for (k, v) in muses {
+                md.add_mus(k, v.into_iter().collect());
+            }
+
+            if let Some(mus_min) = md.min() {
+                let met_target = if config.find_bigger {
+                    (mus_min as i64) * 3 + 3 <= mus_size
+                } else {
+                    mus_min as i64 <= mus_size
+                };
+                if met_target {
+                    info!(target: "solver", "muses found!");
+                    mark_timed_out(&mut md);
+                    return MusSearchResult::Complete(md);
+                }
+            }
+            if tracker.exhausted() {
+                info!(target: "solver", "out of budget while looking for muses size {}", mus_size);
+                // This size may not have been fully searched, so start from it again
+                mark_timed_out(&mut md);
+                return MusSearchResult::Truncated(MusSearch {
+                    lits: lits.clone(),
+                    muses: md,
+                    next_size: mus_size,
+                });
+            }
+            // Make sure we stop, if something stupid has happened
+            if mus_size > i64::from(i32::MAX) {
+                info!(target: "solver", "no muses found!");
+                mark_timed_out(&mut md);
+                return MusSearchResult::Complete(md);
+            }
+            mus_size = mus_size * config.mus_mult_step + config.mus_add_step;
+        }
+    }
+
+    /// Shrinks a MUS by deletion: each constraint is removed in turn, and left
+    /// out if the remaining constraints still prove every literal in `mc`.
+    ///
+    /// If `budget` runs out, or a solver call times out, the MUS found so far
+    /// is returned. The result is never larger than `mc`.
+    #[must_use]
+    pub fn minimize_mus(&self, mc: &MusContext, budget: Duration) -> MusContext {
+        let start = Instant::now();
+        let prober = Prober::new(self.ctx);
+        let mut mus: Vec<Lit> = mc.mus.iter().copied().collect();
+
+        let mut i = 0;
+        while i < mus.len() && start.elapsed() < budget {
+            let mut candidate = mus.clone();
+            candidate.remove(i);
+            if prober.cons_prove_all(&candidate, &mc.lits) {
+                mus = candidate;
+            } else {
+                i += 1;
+            }
+        }
+
+        MusContext::new_multi_lit(mc.lits.clone(), mus.into_iter().collect())
+    }
+
+    /// A MUS which proves every one of `lits` together, or `None` if some of
+    /// them cannot be proved. This is a MUS of the constraints along with the
+    /// clause that not all of `lits` hold, found without adding that clause:
+    /// a core is found for each literal, then their union is shrunk as in
+    /// [`MusFinder::minimize_mus`], so each constraint left is needed for at
+    /// least one of `lits`.
+    ///
+    /// # Errors
+    ///
+    /// If finding a core does not finish, even with higher conflict limits.
+    pub fn set_mus(&self, lits: &BTreeSet<Lit>) -> SearchResult<Option<MusContext>> {
+        let constraints = &self.ctx.puzzle.conset_lits;
+        let satcore = self.ctx.satcore();
+
+        let mut mus = BTreeSet::new();
+        for &lit in lits {
+            let mut assumptions: Vec<Lit> = constraints.iter().copied().collect();
+            assumptions.push(!lit);
+            let core = satcore.must_succeed(|core| {
+                core.assumption_solve_with_core(self.ctx.known.assumptions(), &assumptions)
+            })?;
+            let Some(core) = core else {
+                return Ok(None);
+            };
+            mus.extend(core.into_iter().filter(|l| self.is_constraint(l)));
+        }
+
+        let mc = MusContext::new_multi_lit(lits.clone(), mus);
+        Ok(Some(self.minimize_mus(&mc, Duration::MAX)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use thread_local::ThreadLocal;
+
+    use crate::problem::musdict::MusContext;
+    use crate::problem::solver::known::KnownLits;
+    use crate::problem::solver::probe::Prober;
+    use crate::problem::solver::{MusSearchResult, SearchBudget, SearchContext};
+    use crate::problem::util::test_utils::sudoku_4x4;
+    use crate::satcore::ShrinkOrder;
+
+    use super::*;
+
+    #[test]
+    fn test_mus_finder_strategies() -> anyhow::Result<()> {
+        let puz = sudoku_4x4();
+        let known = KnownLits::default();
+        let cores = ThreadLocal::new();
+        let ctx = SearchContext {
+            puzzle: &puz,
+            known: &known,
+            cores: &cores,
+        };
+        let prober = Prober::new(ctx);
+        let finder = MusFinder::new(ctx);
+
+        let candidates: Vec<Lit> = puz.varset_lits.iter().copied().collect();
+        let provable = prober.provable_varlits(&candidates)?;
+        // Some literals are implied by the encoding alone, with no constraints
+        let lit = *provable
+            .iter()
+            .find(|&&l| !finder.has_mus_size_0(l))
+            .expect("Some deduction needs a constraint");
+
+        let proves = |mus: &Vec<Lit>| {
+            mus.iter().all(|c| puz.conset_lits.contains(c))
+                && prober.mus_proves_lits(&MusContext::new(lit, mus.iter().copied().collect()))
+        };
+
+        let quick = finder
+            .var_mus_quick(lit, None, ShrinkOrder::InOrder)?
+            .unwrap();
+        assert!(proves(&quick));
+        let slice = finder
+            .var_mus_slice(lit, Some(quick.len() as i64), ShrinkOrder::InOrder)?
+            .unwrap_or(quick.clone());
+        assert!(proves(&slice));
+        if let Some(cake) = finder.var_mus_cake(lit, quick.len() as i64, ShrinkOrder::InOrder)? {
+            assert!(proves(&cake));
+        }
+        for mus in finder.var_mus_size_1(lit, None)? {
+            assert!(mus.len() <= 1);
+            assert!(proves(&mus));
+        }
+
+        let lits: BTreeSet<Lit> = provable.iter().take(3).copied().collect();
+        let MusSearchResult::Complete(md) = finder.many_vars_small_mus(
+            &lits,
+            &MusConfig::default(),
+            None,
+            &SearchBudget::default(),
+        ) else {
+            panic!("Search has no budget");
+        };
+        assert!(md.min().is_some());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,646 @@
+//! Questions about the solutions of a puzzle, given what is known: whether
+//! it has any, which literals are true in all of them, and what one of them
+//! looks like.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::ops::Neg;
+
+use itertools::Itertools;
+use rand::Rng;
+use rand::seq::SliceRandom;
+use rand_chacha::ChaCha20Rng;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use rayon::slice::ParallelSlice;
+use rustsat::types::{Lit, TernaryVal, Var};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::problem::musdict::MusContext;
+use crate::problem::{PuzLit, VarValPair};
+use crate::satcore::{ProbeResult, SearchResult, record_solver_calls_saved};
+
+use super::{SearchContext, SearchPhase, skip_search_error};
+
+/// Whether an assignment keeps to a constraint, see [`Prober::check_assignment`].
+#[derive(Clone, Copy, PartialOrd, Ord, Hash, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ConstraintState {
+    /// Every variable in the scope is assigned, and the constraint holds
+    Satisfied,
+    /// No way of filling in the rest of the assignment satisfies the constraint
+    Violated,
+    /// The constraint may hold or not, depending on the unassigned variables
+    Undetermined,
+}
+
+/// The state of one constraint, with the cells in its scope.
+#[derive(Clone, PartialOrd, Ord, Hash, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ConstraintStatus {
+    /// The constraint's description
+    pub constraint: String,
+    pub state: ConstraintState,
+    /// The indices of the variables in the constraint's scope
+    pub cells: BTreeSet<Vec<i64>>,
+}
+
+/// The order in which [`PuzzleSolver::get_provable_varlits`](super::PuzzleSolver::get_provable_varlits)
+/// probes the literals which might be provable, see [`Prober::order_for_probing`].
+/// The literals found are the same whatever the order.
+///
+/// This matters little on small puzzles: solving a 9x9 sudoku one deduction
+/// at a time made 730 to 733 solver calls with each order, as the solutions
+/// found while probing already rule out most literals.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ProbeOrder {
+    /// The order the literals were given in
+    #[default]
+    InOrder,
+    /// Solve once, and only probe the literals which are false in that
+    /// solution, as the others cannot be proved false
+    Phase,
+    /// Literals which share a constraint with the most recently known
+    /// literals first, as that is where new deductions usually appear
+    Recent,
+}
+
+impl std::str::FromStr for ProbeOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "in-order" | "inorder" => Ok(ProbeOrder::InOrder),
+            "phase" => Ok(ProbeOrder::Phase),
+            "recent" => Ok(ProbeOrder::Recent),
+            _ => Err(format!(
+                "Invalid probe order: {s} (expected in-order, phase or recent)"
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for ProbeOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProbeOrder::InOrder => write!(f, "in-order"),
+            ProbeOrder::Phase => write!(f, "phase"),
+            ProbeOrder::Recent => write!(f, "recent"),
+        }
+    }
+}
+
+/// Probes a puzzle with the SAT solver, see the [module documentation](self).
+#[derive(Clone, Copy)]
+pub struct Prober<'a> {
+    ctx: SearchContext<'a>,
+}
+
+impl<'a> Prober<'a> {
+    #[must_use]
+    pub fn new(ctx: SearchContext<'a>) -> Self {
+        Self { ctx }
+    }
+
+    /// The constraints and the known literals, which most searches assume.
+    fn constraints_and_known(&self) -> Vec<Lit> {
+        let mut lits: Vec<Lit> = self.ctx.puzzle.conset_lits.iter().copied().collect();
+        lits.extend_from_slice(self.ctx.known.assumptions());
+        lits
+    }
+
+    /// Whether the puzzle has at least one solution, retrying with higher
+    /// conflict limits as in
+    /// [`SatCore::must_succeed`](crate::satcore::SatCore::must_succeed).
+    pub fn is_solvable(&self) -> SearchResult<bool> {
+        let lits = self.constraints_and_known();
+        self.ctx
+            .satcore()
+            .must_succeed(|core| core.assumption_solve(self.ctx.known.assumptions(), &lits))
+    }
+
+    /// If the puzzle has no solution, a minimal set of constraint and known
+    /// literals which together have no solution.
+    ///
+    /// Unlike most searches, this treats the known literals as assumptions,
+    /// so it can blame them.
+    #[must_use]
+    pub fn explain_unsolvable(&self) -> Option<Vec<Lit>> {
+        let assumptions = self.constraints_and_known();
+        let satcore = self.ctx.satcore();
+
+        let mut core = satcore
+            .must_succeed(|core| core.assumption_solve_with_core(&[], &assumptions))
+            .unwrap_or_else(|e| panic!("Cannot check the puzzle has a solution: {e}"))?;
+
+        // Cores are not always minimal, so remove anything which is not needed
+        let mut i = 0;
+        while i < core.len() {
+            let mut candidate = core.clone();
+            candidate.remove(i);
+            if matches!(satcore.assumption_solve(&[], &candidate), Ok(false)) {
+                core = candidate;
+            } else {
+                i += 1;
+            }
+        }
+
+        Some(core)
+    }
+
+    /// The negations of the literals in `lits` which are false in every
+    /// solution, probing in parallel batches and retrying each probe with
+    /// higher conflict limits.
+    pub fn provable_varlits(&self, lits: &[Lit]) -> SearchResult<BTreeSet<Lit>> {
+        let litorig = self.constraints_and_known();
+
+        // Probe in batches, so each thread can reuse the results of its
+        // earlier calls within a batch.
+        let batch_size = lits.len().div_ceil(rayon::current_num_threads() * 4).max(1);
+        let provable: Vec<Vec<Lit>> = lits
+            .par_chunks(batch_size)
+            .map(|batch| self.probe_varlits(&litorig, batch))
+            .collect::<SearchResult<_>>()?;
+
+        Ok(provable.into_iter().flatten().collect())
+    }
+
+    /// `lits` in the order to probe them with [`Prober::provable_varlits`].
+    /// With [`ProbeOrder::Phase`], literals which cannot be provable are left
+    /// out, at the cost of one solver call.
+    pub fn order_for_probing(
+        &self,
+        mut lits: Vec<Lit>,
+        order: ProbeOrder,
+    ) -> SearchResult<Vec<Lit>> {
+        match order {
+            ProbeOrder::InOrder => {}
+            ProbeOrder::Phase => {
+                let litorig = self.constraints_and_known();
+                let solution = self.ctx.satcore().must_succeed(|core| {
+                    core.assumption_solve_solution(self.ctx.known.assumptions(), &litorig)
+                })?;
+                // Without a solution, every literal is provable
+                if let Some(solution) = solution {
+                    let before = lits.len();
+                    lits.retain(|&l| solution.lit_value(l) != TernaryVal::True);
+                    record_solver_calls_saved(before - lits.len());
+                }
+            }
+            ProbeOrder::Recent => {
+                let recency = self.recency();
+                // A stable sort, so literals which are equally recent (or
+                // not touched at all) keep their order
+                lits.sort_by_key(|l| Reverse(recency.get(&l.var()).copied()));
+            }
+        }
+        Ok(lits)
+    }
+
+    /// For each SAT variable in the scope of a constraint, the latest
+    /// position in the known literals of one which shares a constraint with
+    /// it.
+    fn recency(&self) -> HashMap<Var, usize> {
+        let changed: HashMap<Var, usize> = self
+            .ctx
+            .known
+            .lits(self.ctx.puzzle)
+            .iter()
+            .enumerate()
+            .map(|(i, l)| (l.var(), i))
+            .collect();
+
+        let mut recency: HashMap<Var, usize> = HashMap::new();
+        for scope in self.ctx.puzzle.varlits_in_con.values() {
+            let Some(&latest) = scope.iter().filter_map(|l| changed.get(&l.var())).max() else {
+                continue;
+            };
+            for l in scope {
+                let r = recency.entry(l.var()).or_insert(latest);
+                *r = (*r).max(latest);
+            }
+        }
+        recency
+    }
+
+    /// Checks which of `batch` are provably false given `litorig`, returning
+    /// their negations.
+    ///
+    /// A solution found while probing one literal shows that every other
+    /// literal true in it cannot be proved false, so those are skipped. This
+    /// is where the saving comes from. Cores only help in the rare case that
+    /// one does not contain the probed literal: then the puzzle has no
+    /// solutions, so every remaining literal is provable without further
+    /// calls.
+    fn probe_varlits(&self, litorig: &[Lit], batch: &[Lit]) -> SearchResult<Vec<Lit>> {
+        let mut provable = vec![];
+        let mut satisfiable: HashSet<Lit> = HashSet::new();
+
+        for (i, &lit) in batch.iter().enumerate() {
+            if satisfiable.contains(&lit) {
+                record_solver_calls_saved(1);
+                continue;
+            }
+
+            let mut lits = litorig.to_vec();
+            lits.push(lit);
+            let result = self
+                .ctx
+                .satcore()
+                .must_succeed(|core| core.assumption_probe(self.ctx.known.assumptions(), &lits))?;
+
+            match result {
+                ProbeResult::Sat(solution) => {
+                    satisfiable.extend(
+                        batch[i + 1..]
+                            .iter()
+                            .filter(|&&l| solution.lit_value(l) == TernaryVal::True),
+                    );
+                }
+                ProbeResult::Unsat(core) => {
+                    if core.contains(&lit) {
+                        provable.push(!lit);
+                    } else {
+                        record_solver_calls_saved(batch.len() - i - 1);
+                        provable.extend(batch[i..].iter().map(|&l| !l));
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok(provable)
+    }
+
+    /// The literals in `candidates` which the constraints of `mc`, along with
+    /// the known literals, prove.
+    #[must_use]
+    pub fn varlits_provable_by_mus(
+        &self,
+        candidates: &BTreeSet<Lit>,
+        mc: &MusContext,
+    ) -> BTreeSet<Lit> {
+        let mus = &mc.mus;
+        assert!(mus.iter().all(|c| self.ctx.puzzle.conset_lits.contains(c)));
+
+        let mut litorig = mus.clone();
+        litorig.extend(self.ctx.known.assumptions());
+
+        candidates
+            .iter()
+            .filter_map(|&lit| {
+                // This literal should be provable, so we invert it for testing
+                let lit = !lit;
+                if !self.ctx.known.decides(self.ctx.puzzle, lit) {
+                    let mut lits = litorig.iter().copied().collect_vec();
+                    lits.push(lit);
+                    if !self
+                        .ctx
+                        .satcore()
+                        .must_succeed(|core| {
+                            core.assumption_solve(self.ctx.known.assumptions(), &lits)
+                        })
+                        .unwrap_or_else(|e| panic!("Cannot check a deduction: {e}"))
+                    {
+                        return Some(!lit);
+                    }
+                }
+                None
+            })
+            .collect()
+    }
+
+    /// Checks if the constraints in a MUS, along with the known literals,
+    /// prove every literal it claims to.
+    #[must_use]
+    pub fn mus_proves_lits(&self, mc: &MusContext) -> bool {
+        let cons: Vec<Lit> = mc.mus.iter().copied().collect();
+        self.cons_prove_all(&cons, &mc.lits)
+    }
+
+    /// Checks if `cons`, along with the known literals, proves every one of `lits`.
+    /// A solver timeout is treated as 'not proved', and counted as a
+    /// skipped search.
+    #[must_use]
+    pub fn cons_prove_all(&self, cons: &[Lit], lits: &BTreeSet<Lit>) -> bool {
+        lits.iter().all(|&lit| {
+            let mut assumptions = cons.to_vec();
+            assumptions.push(!lit);
+            let solvable = self
+                .ctx
+                .satcore()
+                .assumption_solve(self.ctx.known.assumptions(), &assumptions);
+            skip_search_error(SearchPhase::CheckMus, solvable) == Some(false)
+        })
+    }
+
+    /// Checks `assignment`, which may leave some variables out, against each
+    /// constraint on its own. The known literals are ignored, so this checks
+    /// a grid a user has filled in, rather than the current state of a solve.
+    ///
+    /// Each constraint is violated if it has no solution which extends the
+    /// assignment, or satisfied if it has one and every variable in its scope
+    /// is assigned. A solver timeout leaves the constraint undetermined.
+    ///
+    /// # Errors
+    ///
+    /// If a value in `assignment` is not in the domain of its variable.
+    pub fn check_assignment(
+        &self,
+        assignment: &[VarValPair],
+    ) -> anyhow::Result<Vec<ConstraintStatus>> {
+        let puzzle = self.ctx.puzzle;
+        let assumptions: Vec<Lit> = assignment
+            .iter()
+            .map(|vvp| {
+                puzzle
+                    .litmap
+                    .get(&PuzLit::new_eq(vvp.clone()))
+                    .copied()
+                    .with_context(|| format!("{vvp} is not a possible value"))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let assigned: BTreeSet<_> = assignment.iter().map(VarValPair::var).collect();
+
+        let statuses = puzzle
+            .conset_lits
+            .par_iter()
+            .map(|&con| {
+                let scope: BTreeSet<_> = puzzle
+                    .varlits_in_con
+                    .get(&con)
+                    .into_iter()
+                    .flatten()
+                    .flat_map(|l| puzzle.direct_or_ordered_lit_to_varvalpair(l))
+                    .map(|vvp| vvp.var().clone())
+                    .collect();
+
+                let mut lits = assumptions.clone();
+                lits.push(con);
+                let state = match self.ctx.satcore().assumption_solve(&[], &lits) {
+                    Ok(false) => ConstraintState::Violated,
+                    Ok(true) if scope.iter().all(|var| assigned.contains(var)) => {
+                        ConstraintState::Satisfied
+                    }
+                    _ => ConstraintState::Undetermined,
+                };
+
+                ConstraintStatus {
+                    constraint: puzzle.lit_to_con(&con).clone(),
+                    state,
+                    cells: scope.into_iter().map(|var| var.indices().clone()).collect(),
+                }
+            })
+            .collect();
+
+        Ok(statuses)
+    }
+
+    /// Generate a random solution. This will not enforce that the problem
+    /// has a unique solution, only that it has a solution. The solution
+    /// is generated by a random dive through all literals.
+    ///
+    /// All 'REVEAL' variables are forced to 'true'.
+    /// 'steps' is how many variables to assign randomly, if 'None',
+    /// then all variables are assigned randomly (which achieves the most
+    /// randomness).
+    pub fn random_solution(
+        &self,
+        rng: &mut ChaCha20Rng,
+        mut steps: Option<usize>,
+    ) -> BTreeSet<Lit> {
+        let satcore = self.ctx.satcore();
+        let known = self.ctx.known.assumptions();
+        let solve = |lits: &[Lit]| {
+            satcore
+                .must_succeed(|core| core.assumption_solve(known, lits))
+                .unwrap_or_else(|e| panic!("Cannot find a solution: {e}"))
+        };
+
+        let mut solution = vec![];
+
+        let mut litorig = self.constraints_and_known();
+        litorig.extend(self.ctx.puzzle.reveal_map.values().copied());
+
+        let mut lits_to_check = self.ctx.puzzle.varset_lits.iter().copied().collect_vec();
+
+        lits_to_check.shuffle(rng);
+
+        for &l in &lits_to_check {
+            let mut lits = litorig.clone();
+            let test_lit = if rng.random_bool(0.5) { l } else { l.neg() };
+
+            lits.push(test_lit);
+
+            if solve(&lits) {
+                solution.push(test_lit);
+                litorig.push(test_lit);
+            } else {
+                // This should never fail, but let's check
+                let test_lit = test_lit.neg();
+                let mut lits = litorig.clone();
+                lits.push(test_lit);
+                if solve(&lits) {
+                    solution.push(test_lit);
+                    litorig.push(test_lit);
+                } else {
+                    panic!("Trying to find a solution to a problem with no answer??!??")
+                }
+            }
+
+            if steps == Some(0) {
+                let sol = satcore
+                    .must_succeed(|core| core.assumption_solve_solution(known, &litorig))
+                    .unwrap_or_else(|e| panic!("Cannot find a solution: {e}"))
+                    .expect("Must be a solution, from previous call??!?");
+
+                for &l in &lits_to_check {
+                    match sol.lit_value(l) {
+                        TernaryVal::True => {
+                            solution.push(l);
+                        }
+                        TernaryVal::False => {}
+                        TernaryVal::DontCare => panic!("Missing assignment??!?"),
+                    }
+                }
+                return solution.into_iter().collect();
+            }
+            steps = steps.map(|x| x - 1);
+        }
+
+        solution.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use thread_local::ThreadLocal;
+
+    use crate::problem::solver::SearchContext;
+    use crate::problem::solver::known::KnownLits;
+    use crate::problem::util::test_utils::sudoku_4x4;
+
+    use super::*;
+
+    #[test]
+    fn test_prober_unsolvable() -> anyhow::Result<()> {
+        let puz = sudoku_4x4();
+        let cores = ThreadLocal::new();
+        let mut known = KnownLits::default();
+        let ctx = SearchContext {
+            puzzle: &puz,
+            known: &known,
+            cores: &cores,
+        };
+        let candidates: Vec<Lit> = puz.varset_lits.iter().copied().collect();
+        let provable = Prober::new(ctx).provable_varlits(&candidates)?;
+        assert!(Prober::new(ctx).is_solvable()?);
+        assert_eq!(Prober::new(ctx).explain_unsolvable(), None);
+
+        // Knowing the opposite of a deduction leaves no solutions
+        let lit = *provable.first().unwrap();
+        known.add(&puz, !lit);
+        let prober = Prober::new(SearchContext {
+            puzzle: &puz,
+            known: &known,
+            cores: &cores,
+        });
+        assert!(!prober.is_solvable()?);
+        let core = prober.explain_unsolvable().unwrap();
+        assert!(core.contains(&!lit));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_probe_reuses_solutions() -> anyhow::Result<()> {
+        let puz = sudoku_4x4();
+        let cores = ThreadLocal::new();
+        let known = KnownLits::default();
+        let prober = Prober::new(SearchContext {
+            puzzle: &puz,
+            known: &known,
+            cores: &cores,
+        });
+        let candidates: Vec<Lit> = puz.varset_lits.iter().copied().collect();
+        assert!(prober.is_solvable()?);
+
+        // Probed as one batch, the first solution found already shows a value
+        // of every cell which cannot be ruled out, so those probes are skipped
+        let saved = crate::satcore::get_solver_calls_saved();
+        let litorig = prober.constraints_and_known();
+        let provable: BTreeSet<Lit> = prober
+            .probe_varlits(&litorig, &candidates)?
+            .into_iter()
+            .collect();
+        assert!(crate::satcore::get_solver_calls_saved() > saved);
+        assert_eq!(provable, prober.provable_varlits(&candidates)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_probe_orders() -> anyhow::Result<()> {
+        let puz = sudoku_4x4();
+        let cores = ThreadLocal::new();
+        let mut known = KnownLits::default();
+        let candidates: Vec<Lit> = puz.varset_lits.iter().copied().collect();
+        let first = Prober::new(SearchContext {
+            puzzle: &puz,
+            known: &known,
+            cores: &cores,
+        })
+        .provable_varlits(&candidates)?;
+        let last = *first.last().unwrap();
+        known.add(&puz, last);
+
+        let prober = Prober::new(SearchContext {
+            puzzle: &puz,
+            known: &known,
+            cores: &cores,
+        });
+        let candidates = candidates
+            .into_iter()
+            .filter(|&l| !known.decides(&puz, l))
+            .collect_vec();
+        let expected = prober.provable_varlits(&candidates)?;
+        for order in [ProbeOrder::InOrder, ProbeOrder::Phase, ProbeOrder::Recent] {
+            let ordered = prober.order_for_probing(candidates.clone(), order)?;
+            assert_eq!(prober.provable_varlits(&ordered)?, expected, "{order}");
+        }
+
+        // Literals which are true in a solution are not probed
+        let phase = prober.order_for_probing(candidates.clone(), ProbeOrder::Phase)?;
+        assert!(phase.len() < candidates.len());
+
+        // The first literal probed shares a constraint with the one just known
+        let recent = prober.order_for_probing(candidates, ProbeOrder::Recent)?;
+        assert!(puz.varlits_in_con.values().any(|scope| {
+            scope.iter().any(|l| l.var() == last.var())
+                && scope.iter().any(|l| l.var() == recent[0].var())
+        }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_assignment() -> anyhow::Result<()> {
+        let puz = sudoku_4x4();
+        let cores = ThreadLocal::new();
+        let known = KnownLits::default();
+        let prober = Prober::new(SearchContext {
+            puzzle: &puz,
+            known: &known,
+            cores: &cores,
+        });
+        let states = |assignment: &[VarValPair]| -> anyhow::Result<Vec<ConstraintState>> {
+            Ok(prober
+                .check_assignment(assignment)?
+                .into_iter()
+                .map(|s| s.state)
+                .collect())
+        };
+
+        let mut solution: Vec<VarValPair> = prober
+            .random_solution(&mut rand::SeedableRng::seed_from_u64(1), None)
+            .iter()
+            .flat_map(|lit| &puz.invlitmap[lit])
+            .filter(|puzlit| puzlit.sign())
+            .map(PuzLit::varval)
+            .collect();
+        assert_eq!(solution.len(), 16);
+        solution.sort();
+
+        assert!(
+            states(&[])?
+                .iter()
+                .all(|&s| s == ConstraintState::Undetermined)
+        );
+        assert!(
+            states(&solution)?
+                .iter()
+                .all(|&s| s == ConstraintState::Satisfied)
+        );
+
+        // Copying one cell's value to its neighbour breaks the row they share
+        let statuses = {
+            let first = solution[0].clone();
+            solution[1] = VarValPair::new(solution[1].var(), first.val());
+            prober.check_assignment(&solution)?
+        };
+        let violated = statuses
+            .iter()
+            .filter(|s| s.state == ConstraintState::Violated)
+            .collect_vec();
+        assert!(!violated.is_empty());
+        assert!(
+            violated
+                .iter()
+                .all(|s| s.cells.contains(solution[1].var().indices()))
+        );
+
+        let missing = VarValPair::new(solution[0].var(), 7);
+        assert!(prober.check_assignment(&[missing]).is_err());
+
+        Ok(())
+    }
+}
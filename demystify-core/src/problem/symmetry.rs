@@ -0,0 +1,177 @@
+//! Spotting MUSes which are the same reasoning in different places, such as
+//! the same hidden single in two rows, so a step can say its reasoning
+//! applies in several places instead of counting them as unrelated
+//! alternatives.
+//!
+//! Two MUSes are the same reasoning if they have the same [`MusShape`]: the
+//! same kinds of constraint, over the same cells, deducing the same values,
+//! once each is moved so its cells start from the same corner. Only
+//! translations are looked for, so a row and a column are different shapes
+//! (as they are different kinds of constraint anyway).
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::{PuzVar, musdict::MusContext, parse::PuzzleParse};
+
+/// A `$#CON` name, with the variables in the constraint's scope.
+type NamedScope = (String, BTreeSet<(String, Vec<i64>)>);
+
+/// A MUS, with its cells relative to the smallest index of each dimension
+/// among the variables it mentions, so it is the same wherever it is in the
+/// grid.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MusShape {
+    /// The `$#CON` name of each constraint, with the variables in its scope
+    constraints: Vec<NamedScope>,
+    /// The variable, value and sign of each literal deduced
+    deduced: BTreeSet<(String, Vec<i64>, i64, bool)>,
+}
+
+impl MusShape {
+    /// The shape of `mc`. A constraint without a `$#CON` name is given by its
+    /// description, which usually names its cells, so it only matches
+    /// itself.
+    #[must_use]
+    pub fn of(puzzle: &PuzzleParse, mc: &MusContext) -> MusShape {
+        let deduced: Vec<_> = mc.lits.iter().flat_map(|l| puzzle.lit_to_vars(l)).collect();
+        let scopes: Vec<(String, Vec<PuzVar>)> = mc
+            .mus
+            .iter()
+            .map(|c| {
+                let con = puzzle.lit_to_con(c);
+                let name = puzzle.constraint_name(con).unwrap_or_else(|| con.clone());
+                let vars = puzzle
+                    .constraint_scope(con)
+                    .iter()
+                    .map(|vv| vv.var().clone())
+                    .collect();
+                (name, vars)
+            })
+            .collect();
+
+        // The corner of each number of dimensions
+        let mut corner: BTreeMap<usize, Vec<i64>> = BTreeMap::new();
+        let vars = deduced
+            .iter()
+            .map(|p| p.var())
+            .chain(scopes.iter().flat_map(|(_, vars)| vars.iter().cloned()));
+        for var in vars {
+            let indices = var.indices();
+            corner
+                .entry(indices.len())
+                .and_modify(|c| {
+                    for (c, &i) in c.iter_mut().zip(indices) {
+                        *c = (*c).min(i);
+                    }
+                })
+                .or_insert_with(|| indices.clone());
+        }
+        let relative = |var: &PuzVar| -> (String, Vec<i64>) {
+            let indices = var.indices();
+            let corner = &corner[&indices.len()];
+            (
+                var.name().clone(),
+                indices.iter().zip(corner).map(|(i, c)| i - c).collect(),
+            )
+        };
+
+        let mut constraints: Vec<_> = scopes
+            .iter()
+            .map(|(name, vars)| (name.clone(), vars.iter().map(relative).collect()))
+            .collect();
+        constraints.sort();
+        let deduced = deduced
+            .iter()
+            .map(|p| {
+                let (name, indices) = relative(&p.var());
+                (name, indices, p.val(), p.sign())
+            })
+            .collect();
+        MusShape {
+            constraints,
+            deduced,
+        }
+    }
+}
+
+/// Groups `muses` by their [`MusShape`], returning the indices of each
+/// group. Groups are in the order of their first MUS, and each lists its
+/// MUSes in order.
+#[must_use]
+pub fn cluster(puzzle: &PuzzleParse, muses: &[MusContext]) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = vec![];
+    let mut by_shape: BTreeMap<MusShape, usize> = BTreeMap::new();
+    for (i, mc) in muses.iter().enumerate() {
+        let shape = MusShape::of(puzzle, mc);
+        match by_shape.get(&shape) {
+            Some(&group) => groups[group].push(i),
+            None => {
+                by_shape.insert(shape, groups.len());
+                groups.push(vec![i]);
+            }
+        }
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::problem::{
+        PuzLit, planner::PuzzlePlanner, solver::PuzzleSolver, util::test_utils::sudoku_4x4,
+    };
+
+    #[test]
+    fn test_cluster_sudoku() {
+        let puzzle = sudoku_4x4();
+        let mut plan = PuzzlePlanner::new(PuzzleSolver::new(Arc::new(puzzle)).unwrap());
+        let muses: Vec<MusContext> = plan
+            .all_smallish_muses()
+            .muses()
+            .values()
+            .flatten()
+            .cloned()
+            .collect();
+        let puzzle = plan.puzzle();
+
+        // Every MUS is in exactly one group, of MUSes with the same shape
+        let groups = cluster(puzzle, &muses);
+        let mut all: Vec<usize> = groups.iter().flatten().copied().collect();
+        all.sort_unstable();
+        assert_eq!(all, (0..muses.len()).collect::<Vec<_>>());
+        for group in &groups {
+            let shape = MusShape::of(puzzle, &muses[group[0]]);
+            assert!(
+                group
+                    .iter()
+                    .all(|&i| MusShape::of(puzzle, &muses[i]) == shape)
+            );
+            assert!(group.windows(2).all(|w| w[0] < w[1]));
+        }
+
+        // The same reasoning in rows 1 and 3, but not in a column, or about
+        // another cell of the same row
+        let mc = |con: &str, lit: &str| {
+            MusContext::new(
+                puzzle.litmap[&lit.parse::<PuzLit>().unwrap()],
+                [puzzle.invconset[con]].into(),
+            )
+        };
+        let row = "cells (1,2) and (1,3) cannot both be 3 as they are in the same row";
+        let muses = [
+            mc(row, "grid[1, 3]!=3"),
+            mc(
+                "cells (1,2) and (3,2) cannot both be 3 as they are in the same column",
+                "grid[3, 2]!=3",
+            ),
+            mc(
+                "cells (3,2) and (3,3) cannot both be 3 as they are in the same row",
+                "grid[3, 3]!=3",
+            ),
+            mc(row, "grid[1, 2]!=3"),
+        ];
+        assert_eq!(cluster(puzzle, &muses), [vec![0, 2], vec![1], vec![3]]);
+    }
+}
@@ -0,0 +1,300 @@
+//! Statistics about each run, kept on the user's machine if they ask for
+//! them, so performance can be compared across versions over time.
+//!
+//! Nothing here is sent anywhere. Each run is one line of JSON appended to a
+//! local file, and records only the kind and size of the puzzle, not the
+//! puzzle itself or where it was read from.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::parse::PuzzleParse;
+use super::planner::StepStats;
+use super::util::{
+    format::{Format, unchanged},
+    memory::format_kb,
+};
+
+/// How each line of a [`StatsStore`] is read. Runs recorded before the format
+/// had a version are the same as version 1.
+pub const STATS_FORMAT: Format = Format {
+    name: "Run statistics",
+    version: 1,
+    oldest: 0,
+    upgrades: &[unchanged],
+};
+
+/// The statistics of a single run.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct RunStats {
+    pub format_version: u32,
+    /// The version of demystify which made the run
+    pub version: String,
+    /// The `$#KIND` of the puzzle, if it has one
+    pub kind: Option<String>,
+    /// The number of `$#VAR` variables
+    pub variables: usize,
+    /// The number of constraints
+    pub constraints: usize,
+    pub steps: usize,
+    /// How many MUSes of each size were used
+    pub mus_sizes: BTreeMap<usize, usize>,
+    pub solver_calls: i64,
+    pub parse_millis: u64,
+    pub solve_millis: u64,
+    /// The most memory resident at once, if it could be measured. Runs
+    /// recorded before this was measured have none.
+    pub peak_memory_kb: Option<u64>,
+}
+
+impl RunStats {
+    /// The statistics of solving `puzzle`, with the version of this build.
+    #[must_use]
+    pub fn new(puzzle: &PuzzleParse, steps: &StepStats) -> Self {
+        Self {
+            format_version: STATS_FORMAT.version,
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            kind: puzzle.eprime.kind.clone(),
+            variables: puzzle
+                .domainmap
+                .keys()
+                .filter(|var| puzzle.eprime.vars.contains(var.name()))
+                .count(),
+            constraints: puzzle.conset_lits.len(),
+            steps: steps.steps,
+            mus_sizes: steps.mus_sizes.clone(),
+            peak_memory_kb: steps.peak_memory_kb(),
+            ..Self::default()
+        }
+    }
+}
+
+/// A file of [`RunStats`], one per line.
+pub struct StatsStore {
+    path: PathBuf,
+}
+
+impl StatsStore {
+    #[must_use]
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// The store in the user's data directory (`$XDG_DATA_HOME/demystify`,
+    /// or `~/.local/share/demystify`), if either is set.
+    #[must_use]
+    pub fn in_data_dir() -> Option<Self> {
+        let dir = std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|h| Path::new(&h).join(".local/share")))?;
+        Some(Self::new(dir.join("demystify").join("stats.jsonl")))
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Adds `run` to the end of the store, creating it if needed.
+    pub fn append(&self, run: &RunStats) -> anyhow::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("Cannot create {}", dir.display()))?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Cannot open {}", self.path.display()))?;
+        writeln!(file, "{}", serde_json::to_string(run)?)?;
+        Ok(())
+    }
+
+    /// Every run in the store, or none if it does not exist yet, upgraded to
+    /// the current [`STATS_FORMAT`]. Lines which cannot be read (such as those
+    /// written by a newer version) are skipped.
+    pub fn load(&self) -> anyhow::Result<Vec<RunStats>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Cannot open {}", self.path.display()));
+            }
+        };
+
+        let mut runs = vec![];
+        for (num, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            match STATS_FORMAT.from_str(&line) {
+                Ok(run) => runs.push(run),
+                Err(e) => warn!(
+                    "Skipping line {} of {}: {e:#}",
+                    num + 1,
+                    self.path.display()
+                ),
+            }
+        }
+        Ok(runs)
+    }
+}
+
+/// The runs of one version, averaged.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VersionSummary {
+    pub runs: usize,
+    pub mean_steps: f64,
+    pub mean_solver_calls: f64,
+    pub mean_parse_millis: f64,
+    pub mean_solve_millis: f64,
+    /// Over the runs which measured it
+    pub mean_peak_memory_kb: Option<f64>,
+}
+
+/// A summary of many [`RunStats`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StatsSummary {
+    pub runs: usize,
+    /// The number of runs of each kind, with `None` for puzzles without one
+    pub kinds: BTreeMap<Option<String>, usize>,
+    /// The MUS sizes of every run together
+    pub mus_sizes: BTreeMap<usize, usize>,
+    pub versions: BTreeMap<String, VersionSummary>,
+}
+
+impl StatsSummary {
+    #[must_use]
+    pub fn new(runs: &[RunStats]) -> Self {
+        let mut summary = Self {
+            runs: runs.len(),
+            ..Self::default()
+        };
+
+        let mut by_version: BTreeMap<&String, Vec<&RunStats>> = BTreeMap::new();
+        for run in runs {
+            *summary.kinds.entry(run.kind.clone()).or_default() += 1;
+            for (&size, &count) in &run.mus_sizes {
+                *summary.mus_sizes.entry(size).or_default() += count;
+            }
+            by_version.entry(&run.version).or_default().push(run);
+        }
+
+        for (version, runs) in by_version {
+            let mean = |f: fn(&RunStats) -> f64| {
+                runs.iter().map(|r| f(r)).sum::<f64>() / runs.len() as f64
+            };
+            let memory: Vec<f64> = runs
+                .iter()
+                .filter_map(|r| r.peak_memory_kb)
+                .map(|kb| kb as f64)
+                .collect();
+            summary.versions.insert(
+                version.clone(),
+                VersionSummary {
+                    runs: runs.len(),
+                    mean_steps: mean(|r| r.steps as f64),
+                    mean_solver_calls: mean(|r| r.solver_calls as f64),
+                    mean_parse_millis: mean(|r| r.parse_millis as f64),
+                    mean_solve_millis: mean(|r| r.solve_millis as f64),
+                    mean_peak_memory_kb: (!memory.is_empty())
+                        .then(|| memory.iter().sum::<f64>() / memory.len() as f64),
+                },
+            );
+        }
+
+        summary
+    }
+}
+
+impl fmt::Display for StatsSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} runs", self.runs)?;
+        if self.runs == 0 {
+            return Ok(());
+        }
+
+        writeln!(f, "Kinds:")?;
+        for (kind, count) in &self.kinds {
+            writeln!(f, "  {}: {count}", kind.as_deref().unwrap_or("(none)"))?;
+        }
+
+        writeln!(f, "MUS sizes:")?;
+        for (size, count) in &self.mus_sizes {
+            writeln!(f, "  {size}: {count}")?;
+        }
+
+        writeln!(f, "Versions (mean per run):")?;
+        for (version, v) in &self.versions {
+            write!(
+                f,
+                "  {version}: {} runs, {:.1} steps, {:.0} solver calls, parsed in {:.0}ms, solved in {:.0}ms",
+                v.runs, v.mean_steps, v.mean_solver_calls, v.mean_parse_millis, v.mean_solve_millis
+            )?;
+            if let Some(kb) = v.mean_peak_memory_kb {
+                write!(f, ", {} at peak", format_kb(kb.round() as u64))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats_store_and_summary() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let store = StatsStore::new(dir.path().join("nested").join("stats.jsonl"));
+        assert!(store.load()?.is_empty());
+
+        let run = |version: &str, kind: Option<&str>, steps, millis| RunStats {
+            version: version.to_owned(),
+            kind: kind.map(str::to_owned),
+            steps,
+            mus_sizes: [(1, steps)].into(),
+            solve_millis: millis,
+            peak_memory_kb: (millis > 100).then_some(millis * 10),
+            ..RunStats::default()
+        };
+        store.append(&run("0.1.0", Some("Sudoku"), 4, 100))?;
+        store.append(&run("0.1.0", Some("Sudoku"), 6, 300))?;
+        fs::write(
+            store.path(),
+            fs::read_to_string(store.path())?
+                + "not a run\n"
+                + r#"{"format_version": 99, "version": "9.0.0"}"#
+                + "\n",
+        )?;
+        store.append(&run("0.2.0", None, 1, 50))?;
+
+        let runs = store.load()?;
+        assert_eq!(runs.len(), 3);
+        assert!(
+            runs.iter()
+                .all(|r| r.format_version == STATS_FORMAT.version)
+        );
+
+        let summary = StatsSummary::new(&runs);
+        assert_eq!(summary.kinds[&Some("Sudoku".to_owned())], 2);
+        assert_eq!(summary.kinds[&None], 1);
+        assert_eq!(summary.mus_sizes, [(1, 11)].into());
+        assert_eq!(summary.versions["0.1.0"].mean_steps, 5.0);
+        assert_eq!(summary.versions["0.1.0"].mean_solve_millis, 200.0);
+        // Only one of the runs measured its memory
+        assert_eq!(summary.versions["0.1.0"].mean_peak_memory_kb, Some(3000.0));
+        assert_eq!(summary.versions["0.2.0"].mean_peak_memory_kb, None);
+        let text = summary.to_string();
+        assert!(text.contains("0.2.0: 1 runs"));
+        assert!(text.contains("solved in 200ms, 2.9MB at peak\n"));
+        assert!(text.contains("solved in 50ms\n"));
+
+        Ok(())
+    }
+}
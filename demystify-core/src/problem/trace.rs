@@ -0,0 +1,728 @@
+//! A whole solve, step by step, as it is shown to the user.
+//!
+//! Unlike a [`ReproReport`](super::repro::ReproReport), which only records
+//! the MUSes chosen and needs the puzzle to replay them, a [`SolveTrace`]
+//! holds the grid and statements of every step, so it can be rendered on its
+//! own, for example as a PDF or as Markdown by demystify-render.
+//! [`SolveTrace::diff`] summarises what happened over several of its steps,
+//! for skimming long solves, and [`SolveSummary`] says how the solve ended.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write as _,
+    time::Duration,
+};
+
+use anyhow::{Context, bail};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    PuzLit, PuzVar, VarValPair,
+    analysis::{DifficultySpike, DominantConstraint},
+    explain::Fact,
+    util::format::{Format, unchanged},
+};
+use crate::json::Problem;
+
+/// Bump this whenever the layout of [`SolveTrace`] changes.
+pub const TRACE_FORMAT_VERSION: u32 = 6;
+
+/// How [`SolveTrace`]s are read back. Add an upgrade from the previous
+/// version whenever [`TRACE_FORMAT_VERSION`] is bumped.
+pub const TRACE_FORMAT: Format = Format {
+    name: "Solve trace",
+    version: TRACE_FORMAT_VERSION,
+    oldest: 1,
+    // Version 2 added the optional summary, and versions 3 and 4 the
+    // optional dominant constraint and guess to it. Versions 5 and 6 added
+    // the optional value glyphs and grid map of the puzzle
+    upgrades: &[unchanged, unchanged, unchanged, unchanged, unchanged],
+};
+
+/// One step of a [`SolveTrace`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceStep {
+    /// Such as "Step 3", or "Setup" for the setup step
+    pub title: String,
+    /// The grid before the step, with the deductions it makes marked, and
+    /// the statements explaining it
+    pub problem: Problem,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SolveTrace {
+    pub format_version: u32,
+    pub steps: Vec<TraceStep>,
+    /// How the solve ended
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<SolveSummary>,
+}
+
+impl Default for SolveTrace {
+    fn default() -> Self {
+        Self {
+            format_version: TRACE_FORMAT_VERSION,
+            steps: vec![],
+            summary: None,
+        }
+    }
+}
+
+impl SolveTrace {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads a trace written as JSON, upgrading it if it was written by an
+    /// older version of demystify.
+    pub fn from_reader(reader: impl std::io::Read) -> anyhow::Result<Self> {
+        TRACE_FORMAT.from_reader(reader)
+    }
+
+    /// What the steps from `first` to `last` (both included, counting from
+    /// 0) deduced between them, and the constraints they used.
+    pub fn diff(&self, first: usize, last: usize) -> anyhow::Result<TraceDiff> {
+        if last >= self.steps.len() {
+            bail!("The trace has only {} steps", self.steps.len());
+        }
+        if first > last {
+            bail!(
+                "{} comes after {}",
+                self.steps[first].title,
+                self.steps[last].title
+            );
+        }
+
+        let steps = &self.steps[first..=last];
+        let mut deduced = BTreeSet::new();
+        let mut constraints: Vec<ConstraintUse> = vec![];
+        for step in steps {
+            let explanation = step
+                .problem
+                .state
+                .as_ref()
+                .and_then(|s| s.explanation.as_ref())
+                .with_context(|| format!("{} has no explanation", step.title))?;
+            deduced.extend(explanation.deduced());
+
+            let used: BTreeSet<&String> = explanation
+                .deductions
+                .iter()
+                .flat_map(|d| &d.constraints)
+                .collect();
+            for constraint in used {
+                match constraints.iter_mut().find(|c| &c.constraint == constraint) {
+                    Some(c) => c.steps += 1,
+                    None => constraints.push(ConstraintUse {
+                        constraint: constraint.clone(),
+                        steps: 1,
+                    }),
+                }
+            }
+        }
+        // Stable, so constraints used equally often stay in order of first use
+        constraints.sort_by_key(|c| std::cmp::Reverse(c.steps));
+
+        let (known, eliminated) = Fact::from_lits(&deduced).into_iter().partition(|f| f.equal);
+        Ok(TraceDiff {
+            first: steps[0].title.clone(),
+            last: steps[steps.len() - 1].title.clone(),
+            steps: steps.len(),
+            known,
+            eliminated,
+            constraints,
+        })
+    }
+
+    /// The literals deduced by each step.
+    pub fn deduced(&self) -> anyhow::Result<Vec<BTreeSet<PuzLit>>> {
+        self.steps
+            .iter()
+            .map(|step| {
+                let explanation = step
+                    .problem
+                    .state
+                    .as_ref()
+                    .and_then(|s| s.explanation.as_ref())
+                    .with_context(|| format!("{} has no explanation", step.title))?;
+                Ok(explanation.deduced())
+            })
+            .collect()
+    }
+
+    /// Checks this trace makes the same deductions as `expected`: the same
+    /// literals in each step, and the same ending. How the steps are worded
+    /// and drawn, and the time and solver calls taken, may differ.
+    pub fn check_equivalent(&self, expected: &SolveTrace) -> anyhow::Result<()> {
+        let found = self.deduced()?;
+        let wanted = expected.deduced()?;
+        for (step, (f, w)) in self.steps.iter().zip(found.iter().zip(&wanted)) {
+            if f != w {
+                bail!(
+                    "{} deduces {} instead of {}",
+                    step.title,
+                    f.iter().join(", "),
+                    w.iter().join(", ")
+                );
+            }
+        }
+        if found.len() != wanted.len() {
+            bail!(
+                "The solve has {} steps instead of {}",
+                found.len(),
+                wanted.len()
+            );
+        }
+        if let (Some(found), Some(wanted)) = (&self.summary, &expected.summary)
+            && (found.solved, &found.open) != (wanted.solved, &wanted.open)
+        {
+            bail!(
+                "The solve ends with {} cells open instead of {}",
+                found.open.len(),
+                wanted.open.len()
+            );
+        }
+        Ok(())
+    }
+}
+
+/// What changed over some steps of a [`SolveTrace`], see [`SolveTrace::diff`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceDiff {
+    /// The titles of the first and last steps compared
+    pub first: String,
+    pub last: String,
+    /// How many steps were compared
+    pub steps: usize,
+    /// The values which became known
+    pub known: Vec<Fact>,
+    /// The values ruled out, for variables whose value is still not known
+    pub eliminated: Vec<Fact>,
+    /// The constraints used, most used first
+    pub constraints: Vec<ConstraintUse>,
+}
+
+/// A constraint used in a [`TraceDiff`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConstraintUse {
+    /// The description of the constraint, as in [`Deduction::constraints`](super::explain::Deduction::constraints)
+    pub constraint: String,
+    /// How many of the steps used it
+    pub steps: usize,
+}
+
+impl ConstraintUse {
+    fn uses(&self) -> String {
+        match self.steps {
+            1 => "in 1 step".to_owned(),
+            n => format!("in {n} steps"),
+        }
+    }
+}
+
+impl TraceDiff {
+    fn heading(&self) -> String {
+        if self.steps == 1 {
+            self.first.clone()
+        } else {
+            format!("{} to {} ({} steps)", self.first, self.last, self.steps)
+        }
+    }
+
+    /// The changes as plain text, with one line for each constraint used.
+    #[must_use]
+    pub fn text(&self) -> String {
+        let mut text = format!("{}:\n", self.heading());
+        if !self.known.is_empty() {
+            let _ = writeln!(text, "Known: {}", self.known.iter().join(", "));
+        }
+        if !self.eliminated.is_empty() {
+            let _ = writeln!(text, "Ruled out: {}", self.eliminated.iter().join(", "));
+        }
+        if !self.constraints.is_empty() {
+            text += "Using:\n";
+        }
+        for c in &self.constraints {
+            let _ = writeln!(text, "  {} ({})", c.constraint, c.uses());
+        }
+        text
+    }
+
+    /// The changes as HTML, where hovering over a fact highlights its values.
+    #[must_use]
+    pub fn html(&self) -> String {
+        let mut html = format!("<b>{}</b><br/>", tera::escape_html(&self.heading()));
+        if !self.known.is_empty() {
+            let _ = write!(
+                html,
+                "Known: {}<br/>",
+                self.known.iter().map(Fact::html).join(", ")
+            );
+        }
+        if !self.eliminated.is_empty() {
+            let _ = write!(
+                html,
+                "Ruled out: {}<br/>",
+                self.eliminated.iter().map(Fact::html).join(", ")
+            );
+        }
+        if !self.constraints.is_empty() {
+            html += "Using:<ul>";
+            for c in &self.constraints {
+                let _ = write!(
+                    html,
+                    "<li>{} ({})</li>",
+                    tera::escape_html(&c.constraint),
+                    c.uses()
+                );
+            }
+            html += "</ul>";
+        }
+        html
+    }
+}
+
+/// How a solve ended, shown after its last step. See
+/// [`PuzzlePlanner::solve_summary`](super::planner::PuzzlePlanner::solve_summary).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SolveSummary {
+    /// Whether the value of every variable is known
+    pub solved: bool,
+    /// The steps applied, not counting a setup step
+    pub steps: usize,
+    /// How many deductions used each `$#CON`, by name
+    pub deductions: BTreeMap<String, usize>,
+    /// The most constraints any step needed, as in the `difficulty:N` stop
+    /// condition
+    pub difficulty: usize,
+    /// The `$#CON` most steps needing more than one constraint used, if it
+    /// was used by nearly all of them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dominant: Option<DominantConstraint>,
+    /// The steps much harder or easier than those before them, so reviewers
+    /// can go straight to them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub spikes: Vec<DifficultySpike>,
+    /// Counted with [`get_solver_calls`](crate::satcore::get_solver_calls),
+    /// so includes other work in the process running at the same time
+    pub solver_calls: i64,
+    pub millis: u64,
+    /// The variables whose value is not known, if the puzzle is not solved
+    pub open: Vec<OpenCell>,
+    /// If nothing more could be deduced with cells still open, so the
+    /// puzzle has more than one solution, the open cell with the fewest
+    /// values left, as the place to guess
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub guess: Option<OpenCell>,
+}
+
+/// A variable whose value is not known at the end of a solve.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OpenCell {
+    pub var: PuzVar,
+    /// The values it can still take
+    pub values: Vec<i64>,
+}
+
+impl OpenCell {
+    /// The cell, highlighting the values it can still take.
+    #[must_use]
+    pub fn html(&self) -> String {
+        let classes = self
+            .values
+            .iter()
+            .map(|&val| {
+                format!(
+                    "highlight_{}",
+                    VarValPair::new(&self.var, val).to_css_string()
+                )
+            })
+            .join(" ");
+        format!(r#"<div style="display:inline" class="{classes} js_highlighter">{self}</div>"#)
+    }
+}
+
+impl std::fmt::Display for OpenCell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.var, self.values.iter().join(" or "))
+    }
+}
+
+impl SolveSummary {
+    fn outcome(&self) -> String {
+        let count = |n: usize, what: &str| match n {
+            1 => format!("1 {what}"),
+            n => format!("{n} {what}s"),
+        };
+        if self.solved && self.steps == 0 {
+            "Solved without any steps".to_owned()
+        } else if self.solved {
+            format!(
+                "Solved in {}, the hardest using {}",
+                count(self.steps, "step"),
+                count(self.difficulty, "constraint")
+            )
+        } else if self.guess.is_some() {
+            format!(
+                "Stuck after {}, with {} still open",
+                count(self.steps, "step"),
+                count(self.open.len(), "cell")
+            )
+        } else {
+            format!(
+                "Not solved after {}, with {} still open",
+                count(self.steps, "step"),
+                count(self.open.len(), "cell")
+            )
+        }
+    }
+
+    /// The `$#CON`s used, most used first.
+    fn deductions_by_use(&self) -> Vec<(&String, &usize)> {
+        self.deductions
+            .iter()
+            .sorted_by_key(|(_, n)| std::cmp::Reverse(**n))
+            .collect()
+    }
+
+    fn work(&self) -> String {
+        format!(
+            "{} solver calls in {:?}",
+            self.solver_calls,
+            Duration::from_millis(self.millis)
+        )
+    }
+
+    const STUCK: &str = "Nothing more can be deduced, so the puzzle has more than one solution";
+
+    /// Where to guess, if the solve got stuck, as plain text.
+    #[must_use]
+    pub fn guess_text(&self) -> Option<String> {
+        self.guess.as_ref().map(|guess| {
+            format!(
+                "{}. {guess} has the fewest values left, so is the place to guess.\n",
+                Self::STUCK
+            )
+        })
+    }
+
+    /// As [`SolveSummary::guess_text`], where hovering over the cell
+    /// highlights its values.
+    #[must_use]
+    pub fn guess_html(&self) -> Option<String> {
+        self.guess.as_ref().map(|guess| {
+            format!(
+                "{}. {} has the fewest values left, so is the place to guess.<br/>",
+                Self::STUCK,
+                guess.html()
+            )
+        })
+    }
+
+    /// The summary as plain text, with one line for each `$#CON` used.
+    #[must_use]
+    pub fn text(&self) -> String {
+        let mut text = format!("Summary:\n{}\n", self.outcome());
+        if !self.deductions.is_empty() {
+            text += "Deductions using:\n";
+        }
+        for (name, n) in self.deductions_by_use() {
+            let _ = writeln!(text, "  {name}: {n}");
+        }
+        if let Some(dominant) = &self.dominant {
+            let _ = writeln!(text, "Warning: {dominant}");
+        }
+        for spike in &self.spikes {
+            let _ = writeln!(text, "Note: {spike}");
+        }
+        let _ = writeln!(text, "{}", self.work());
+        if !self.open.is_empty() {
+            let _ = writeln!(text, "Open: {}", self.open.iter().join(", "));
+        }
+        text += &self.guess_text().unwrap_or_default();
+        text
+    }
+
+    /// The summary as Markdown, with a list item for each `$#CON` used.
+    #[must_use]
+    pub fn markdown(&self) -> String {
+        let mut md = format!("{}.\n\n", self.outcome());
+        if !self.deductions.is_empty() {
+            md += "Deductions using:\n\n";
+            for (name, n) in self.deductions_by_use() {
+                let _ = writeln!(md, "- `{name}`: {n}");
+            }
+            md.push('\n');
+        }
+        if let Some(dominant) = &self.dominant {
+            let _ = writeln!(
+                md,
+                "**Warning:** {}\n",
+                escape_markdown(&dominant.to_string())
+            );
+        }
+        for spike in &self.spikes {
+            let _ = writeln!(md, "**Note:** {}\n", escape_markdown(&spike.to_string()));
+        }
+        let _ = writeln!(md, "{}\n", self.work());
+        if !self.open.is_empty() {
+            let _ = writeln!(
+                md,
+                "Open: {}\n",
+                escape_markdown(&self.open.iter().join(", "))
+            );
+        }
+        md += &escape_markdown(&self.guess_text().unwrap_or_default());
+        md
+    }
+
+    /// The summary as HTML, where hovering over an open cell highlights the
+    /// values it can still take.
+    #[must_use]
+    pub fn html(&self) -> String {
+        let mut html = format!("<b>Summary</b><br/>{}<br/>", self.outcome());
+        if !self.deductions.is_empty() {
+            html += "Deductions using:<ul>";
+            for (name, n) in self.deductions_by_use() {
+                let _ = write!(html, "<li>{}: {n}</li>", tera::escape_html(name));
+            }
+            html += "</ul>";
+        }
+        if let Some(dominant) = &self.dominant {
+            let _ = write!(
+                html,
+                "<b>Warning:</b> {}<br/>",
+                tera::escape_html(&dominant.to_string())
+            );
+        }
+        for spike in &self.spikes {
+            let _ = write!(html, "<b>Note:</b> {spike}<br/>");
+        }
+        let _ = write!(html, "{}<br/>", self.work());
+        if !self.open.is_empty() {
+            let _ = write!(
+                html,
+                "Open: {}<br/>",
+                self.open.iter().map(OpenCell::html).join(", ")
+            );
+        }
+        html += &self.guess_html().unwrap_or_default();
+        html
+    }
+}
+
+/// `text` with the characters Markdown would format backslash escaped.
+#[must_use]
+pub fn escape_markdown(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\`*_[]<>|~#".contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+        sync::Arc,
+    };
+
+    use super::*;
+    use crate::problem::{
+        parse::parse_eprime_with_dimacs,
+        planner::{PlannerConfig, PuzzlePlanner},
+        solver::PuzzleSolver,
+        util::test_utils::sudoku_4x4,
+    };
+
+    #[test]
+    fn test_trace_diff() {
+        let puzzle = sudoku_4x4();
+        let config = PlannerConfig {
+            merge_small_threshold: 0,
+            ..PlannerConfig::default()
+        };
+        let solver = PuzzleSolver::new(Arc::new(puzzle)).unwrap();
+        let trace = PuzzlePlanner::new_with_config(solver, config).quick_solve_trace();
+        let last = trace.steps.len() - 1;
+
+        // By the end every cell is known, so nothing is left just ruled out
+        let whole = trace.diff(0, last).unwrap();
+        assert_eq!(whole.steps, trace.steps.len());
+        assert!(whole.eliminated.is_empty());
+        assert!(!whole.known.is_empty());
+        assert!(whole.known.iter().all(|f| f.equal));
+        assert!(
+            whole
+                .constraints
+                .windows(2)
+                .all(|w| w[0].steps >= w[1].steps)
+        );
+
+        // A single step is its own explanation
+        let first = trace.diff(0, 0).unwrap();
+        let explanation = trace.steps[0].problem.state.as_ref().unwrap();
+        let explanation = explanation.explanation.as_ref().unwrap();
+        assert_eq!(
+            first.known.len() + first.eliminated.len(),
+            Fact::from_lits(&explanation.deduced()).len()
+        );
+        assert!(first.constraints.iter().all(|c| c.steps == 1));
+        assert!(first.text().starts_with("Step 1:\n"));
+        assert!(first.html().contains("js_highlighter"));
+
+        let text = trace.diff(1, 2).unwrap().text();
+        assert!(text.starts_with("Step 2 to Step 3 (2 steps):\n"), "{text}");
+
+        assert!(trace.diff(2, 1).is_err());
+        assert!(trace.diff(0, last + 1).is_err());
+
+        assert!(trace.check_equivalent(&trace).is_ok());
+        let mut shorter = trace.clone();
+        shorter.steps.pop();
+        assert!(trace.check_equivalent(&shorter).is_err());
+        let mut swapped = trace.clone();
+        swapped.steps.swap(0, 1);
+        let err = trace.check_equivalent(&swapped).unwrap_err().to_string();
+        assert!(err.starts_with("Step 1 deduces "), "{err}");
+    }
+
+    #[test]
+    fn test_summary_spikes() {
+        let mut summary = SolveSummary {
+            solved: true,
+            steps: 6,
+            difficulty: 6,
+            ..SolveSummary::default()
+        };
+        let json = serde_json::to_string(&summary).unwrap();
+        assert!(!json.contains("spikes"));
+
+        summary.spikes = crate::problem::analysis::difficulty_spikes(&[1, 2, 1, 2, 6, 1]);
+        let note = "step 5 is unexpectedly hard, using 6 constraints against a mean of 1.5 before it (z = 4.5)";
+        assert!(summary.text().contains(&format!("\nNote: {note}\n")));
+        assert!(
+            summary
+                .markdown()
+                .contains(&format!("\n**Note:** {note}\n"))
+        );
+        assert!(
+            summary
+                .html()
+                .contains(&format!("<b>Note:</b> {note}<br/>"))
+        );
+        let json = serde_json::to_string(&summary).unwrap();
+        assert!(json.contains(r#""spikes":[{"step":5,"size":6,"mean":1.5,"z":4.5}]"#));
+        assert_eq!(
+            serde_json::from_str::<SolveSummary>(&json).unwrap(),
+            summary
+        );
+    }
+
+    #[test]
+    fn test_summary_dominant() {
+        let mut summary = SolveSummary {
+            solved: true,
+            steps: 5,
+            difficulty: 2,
+            ..SolveSummary::default()
+        };
+        let json = serde_json::to_string(&summary).unwrap();
+        assert!(!json.contains("dominant"));
+        assert!(!summary.text().contains("Warning"));
+
+        summary.dominant = Some(DominantConstraint {
+            name: "a<b".to_owned(),
+            steps: 4,
+            of: 5,
+        });
+        assert!(summary.text().contains(
+            "\nWarning: a<b is used by 4 of the 5 steps needing more than one constraint\n"
+        ));
+        assert!(
+            summary
+                .html()
+                .contains("<b>Warning:</b> a&lt;b is used by 4")
+        );
+        assert!(
+            summary
+                .markdown()
+                .contains("\n**Warning:** a\\<b is used by 4")
+        );
+        let json = serde_json::to_string(&summary).unwrap();
+        assert_eq!(
+            serde_json::from_str::<SolveSummary>(&json).unwrap(),
+            summary
+        );
+    }
+
+    /// The model of the corpus puzzle `name`: `name.eprime`, or for a
+    /// variant such as `sudoku-4x4-open`, the model it is a variant of.
+    fn corpus_model(dir: &Path, name: &str) -> PathBuf {
+        let mut model = name;
+        loop {
+            let path = dir.join(format!("{model}.eprime"));
+            if path.exists() {
+                return path;
+            }
+            let Some((base, _)) = model.rsplit_once('-') else {
+                panic!("No model for {name} in {}", dir.display());
+            };
+            model = base;
+        }
+    }
+
+    /// Solves each puzzle of the corpus in `tst` (those with a `.trace.json`,
+    /// see [`corpus_model`]), and checks the solve is equivalent to its golden
+    /// trace. Run with `DEMYSTIFY_BLESS=1` to write the golden traces
+    /// instead, after a change which is meant to alter the solves.
+    #[test]
+    fn test_golden_traces() {
+        let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("tst");
+        let bless = std::env::var_os("DEMYSTIFY_BLESS").is_some();
+
+        let mut names: Vec<String> = fs::read_dir(&corpus)
+            .unwrap()
+            .filter_map(|entry| {
+                let name = entry.unwrap().file_name().into_string().ok()?;
+                name.strip_suffix(".trace.json").map(ToOwned::to_owned)
+            })
+            .collect();
+        names.sort();
+        assert!(!names.is_empty());
+
+        for name in names {
+            let model = corpus_model(&corpus, &name);
+            let param = corpus.join(format!("{name}.json"));
+            let puzzle =
+                parse_eprime_with_dimacs(&model, &param, &param.with_extension("dimacs")).unwrap();
+            let solver = PuzzleSolver::new(Arc::new(puzzle)).unwrap();
+            let mut trace = PuzzlePlanner::new(solver).quick_solve_trace();
+            if let Some(summary) = trace.summary.as_mut() {
+                summary.solver_calls = 0;
+                summary.millis = 0;
+            }
+
+            let golden = param.with_extension("trace.json");
+            if bless {
+                let json = serde_json::to_string_pretty(&trace).unwrap();
+                fs::write(&golden, json + "\n").unwrap();
+                continue;
+            }
+            let expected = fs::File::open(&golden)
+                .context("No golden trace, bless with DEMYSTIFY_BLESS=1")
+                .and_then(SolveTrace::from_reader)
+                .unwrap();
+            if let Err(e) = trace.check_equivalent(&expected) {
+                panic!("{name}: {e:#}");
+            }
+        }
+    }
+}
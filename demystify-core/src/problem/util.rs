@@ -2,10 +2,18 @@ use std::collections::{BTreeMap, HashMap, HashSet};
 
 use anyhow::bail;
 use itertools::Itertools;
-use rustsat::{instances::SatInstance, types::Lit};
+use rustsat::{instances::Cnf, types::Lit};
 use tracing::info;
 
+pub mod compress;
 pub mod exec;
+pub mod fetch;
+pub mod format;
+pub mod kvstore;
+pub mod logging;
+pub mod lru;
+pub mod memory;
+pub mod param;
 pub mod parsing;
 
 pub fn safe_insert<K: Ord, V>(dict: &mut BTreeMap<K, V>, key: K, value: V) -> anyhow::Result<()> {
@@ -22,10 +30,9 @@ pub struct FindVarConnections {
 
 impl FindVarConnections {
     #[must_use]
-    pub fn new(sat: &SatInstance, all_var_lits: &HashSet<Lit>) -> FindVarConnections {
-        let (cnf, _) = sat.clone().into_cnf();
+    pub fn new(cnf: &Cnf, all_var_lits: &HashSet<Lit>) -> FindVarConnections {
         let mut lit_to_clauses: HashMap<Lit, HashSet<Lit>> = HashMap::new();
-        for clause in &cnf {
+        for clause in cnf {
             for &lit in clause {
                 let s = lit_to_clauses.entry(lit).or_default();
                 for &l in clause.iter() {
@@ -35,7 +42,7 @@ impl FindVarConnections {
         }
 
         // Blank out any literals in unit clauses
-        for clause in &cnf {
+        for clause in cnf {
             if clause.len() == 1 {
                 let &lit = clause.iter().next().unwrap();
                 lit_to_clauses.insert(lit, HashSet::new());
@@ -106,7 +113,7 @@ mod tests {
         let puz =
             crate::problem::util::test_utils::build_puzzleparse(eprime_path, eprimeparam_path);
 
-        let fvc = FindVarConnections::new(&puz.satinstance, &puz.all_var_related_lits());
+        let fvc = FindVarConnections::new(puz.cnf.as_ref().unwrap(), &puz.all_var_related_lits());
 
         for c in &puz.conset_lits {
             let lits = fvc.get_connections(*c);
@@ -124,11 +131,49 @@ mod tests {
     }
 }
 
-#[cfg(test)]
+/// Puzzles for tests, shared with the other crates in the workspace through
+/// the `test-utils` feature.
+#[cfg(any(test, feature = "test-utils"))]
 pub mod test_utils {
-    use std::fs;
+    use std::{fs, sync::Arc};
+
+    use crate::problem::{
+        parse::{PuzzleParse, parse_eprime_with_dimacs, parse_essence},
+        planner::{PlannerConfig, PuzzlePlanner},
+        solver::PuzzleSolver,
+    };
+
+    /// The directory of puzzles used by the tests
+    pub const TST: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tst");
+
+    /// The 4x4 sudoku in `tst`, read from its saved DIMACS so no tools are needed
+    #[must_use]
+    pub fn sudoku_4x4() -> PuzzleParse {
+        sudoku_4x4_with("sudoku-4x4.json", "sudoku-4x4.dimacs")
+    }
+
+    /// The 4x4 sudoku model with another param file from `tst`, and its DIMACS
+    #[must_use]
+    pub fn sudoku_4x4_with(param: &str, dimacs: &str) -> PuzzleParse {
+        parse_eprime_with_dimacs(
+            &format!("{TST}/sudoku-4x4.eprime").into(),
+            &format!("{TST}/{param}").into(),
+            &format!("{TST}/{dimacs}").into(),
+        )
+        .unwrap()
+    }
 
-    use crate::problem::parse::{PuzzleParse, parse_essence};
+    /// A planner, with the default config, for [`sudoku_4x4`]
+    #[must_use]
+    pub fn sudoku_4x4_planner() -> PuzzlePlanner {
+        sudoku_4x4_planner_with(PlannerConfig::default())
+    }
+
+    /// A planner, with the given config, for [`sudoku_4x4`]
+    #[must_use]
+    pub fn sudoku_4x4_planner_with(config: PlannerConfig) -> PuzzlePlanner {
+        PuzzlePlanner::new_with_config(PuzzleSolver::new(Arc::new(sudoku_4x4())).unwrap(), config)
+    }
 
     // Here we put some utility functions used in various places
     #[must_use]
@@ -0,0 +1,151 @@
+//! Reading gzip and zstd compressed input files.
+//!
+//! The format is detected from the first few bytes of the file, not its
+//! name, so mislabelled files (and uploads, where the name comes from the
+//! user) are handled correctly.
+
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, bail};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+impl Compression {
+    #[must_use]
+    pub fn detect(bytes: &[u8]) -> Compression {
+        if bytes.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else if bytes.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// Decompresses `bytes` if they are gzip or zstd compressed, otherwise returns them unchanged.
+/// Fails if the result is over `max_size` bytes, so that a small upload
+/// cannot decompress to fill the memory of a server.
+pub fn decompress(bytes: &[u8], max_size: usize) -> anyhow::Result<Vec<u8>> {
+    let mut out = vec![];
+    // One byte over the limit is enough to tell the output is too large
+    let limit = u64::try_from(max_size)
+        .unwrap_or(u64::MAX)
+        .saturating_add(1);
+    match Compression::detect(bytes) {
+        Compression::None => out = bytes.to_vec(),
+        Compression::Gzip => {
+            flate2::read::MultiGzDecoder::new(bytes)
+                .take(limit)
+                .read_to_end(&mut out)
+                .context("Failed to decompress gzip file")?;
+        }
+        Compression::Zstd => {
+            ruzstd::decoding::StreamingDecoder::new(bytes)
+                .context("Failed to read zstd header")?
+                .take(limit)
+                .read_to_end(&mut out)
+                .context("Failed to decompress zstd file")?;
+        }
+    }
+    if out.len() > max_size {
+        bail!("The file is larger than {max_size} bytes once decompressed");
+    }
+    Ok(out)
+}
+
+/// Removes a trailing `.gz` or `.zst` from a file name, so `sudoku.eprime.gz`
+/// becomes `sudoku.eprime`.
+#[must_use]
+pub fn strip_compressed_extension(name: &Path) -> PathBuf {
+    match name.extension().and_then(|e| e.to_str()) {
+        Some("gz" | "zst") => name.with_extension(""),
+        _ => name.to_path_buf(),
+    }
+}
+
+/// Copies `from` into the directory `to_dir`, decompressing it if needed,
+/// and returns the path of the copy.
+pub fn copy_decompressed(from: &Path, to_dir: &Path) -> anyhow::Result<PathBuf> {
+    let name = from
+        .file_name()
+        .with_context(|| format!("{from:?} is not a file"))?;
+    let to = to_dir.join(strip_compressed_extension(Path::new(name)));
+
+    let bytes = fs::read(from).with_context(|| format!("Failed to read {from:?}"))?;
+    let bytes =
+        decompress(&bytes, usize::MAX).with_context(|| format!("Failed to read {from:?}"))?;
+    fs::write(&to, bytes).with_context(|| format!("Failed to write {to:?}"))?;
+
+    Ok(to)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn test_decompress() {
+        let text = b"letting n be 4\n";
+
+        let mut gz = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        gz.write_all(text).unwrap();
+        let gz = gz.finish().unwrap();
+
+        // The smallest valid zstd frame holding `text`: a single raw block
+        let mut zst = vec![0x28, 0xb5, 0x2f, 0xfd, 0x20, text.len() as u8];
+        let block_header = (text.len() as u32) << 3 | 1;
+        zst.extend_from_slice(&block_header.to_le_bytes()[..3]);
+        zst.extend_from_slice(text);
+
+        assert_eq!(Compression::detect(text), Compression::None);
+        assert_eq!(Compression::detect(&gz), Compression::Gzip);
+        assert_eq!(Compression::detect(&zst), Compression::Zstd);
+
+        assert_eq!(decompress(text, 100).unwrap(), text);
+        assert_eq!(decompress(&gz, 100).unwrap(), text);
+        assert_eq!(decompress(&zst, 100).unwrap(), text);
+        assert_eq!(decompress(&gz, text.len()).unwrap(), text);
+
+        assert!(decompress(&gz[..gz.len() / 2], 100).is_err());
+
+        // A small file which decompresses to a lot is stopped at the limit
+        let mut bomb = flate2::write::GzEncoder::new(vec![], flate2::Compression::best());
+        bomb.write_all(&vec![0; 1 << 20]).unwrap();
+        let bomb = bomb.finish().unwrap();
+        let err = decompress(&bomb, 1000).unwrap_err();
+        assert!(err.to_string().contains("larger than 1000 bytes"), "{err}");
+        assert!(decompress(text, 5).is_err());
+        assert!(decompress(&zst, 5).is_err());
+    }
+
+    #[test]
+    fn test_strip_compressed_extension() {
+        assert_eq!(
+            strip_compressed_extension(Path::new("a.eprime.gz")),
+            PathBuf::from("a.eprime")
+        );
+        assert_eq!(
+            strip_compressed_extension(Path::new("a.param.zst")),
+            PathBuf::from("a.param")
+        );
+        assert_eq!(
+            strip_compressed_extension(Path::new("a.param")),
+            PathBuf::from("a.param")
+        );
+    }
+}
@@ -0,0 +1,899 @@
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Output};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, bail};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "external-tools")]
+use which::which;
+
+/// Enum representing the method used to run commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunMethod {
+    Native,
+    Docker,
+    Podman,
+}
+
+impl std::str::FromStr for RunMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "native" => Ok(RunMethod::Native),
+            "docker" => Ok(RunMethod::Docker),
+            "podman" => Ok(RunMethod::Podman),
+            _ => Err(format!("Invalid RunMethod: {s}")),
+        }
+    }
+}
+
+impl std::fmt::Display for RunMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RunMethod::Native => write!(f, "native"),
+            RunMethod::Docker => write!(f, "docker"),
+            RunMethod::Podman => write!(f, "podman"),
+        }
+    }
+}
+
+/// Global configuration for the runner
+pub static RUN_METHOD: OnceLock<RunMethod> = OnceLock::new();
+
+/// Get the current run method, auto-detecting if not already initialized
+pub fn get_run_method() -> RunMethod {
+    *RUN_METHOD.get_or_init(detect_run_method)
+}
+
+/// Set the run method explicitly
+pub fn set_run_method(method: RunMethod) {
+    let _ = RUN_METHOD.set(method);
+}
+
+/// Whether the directories of failed tool runs are kept, see
+/// [`set_keep_failed_runs`].
+static KEEP_FAILED_RUNS: AtomicBool = AtomicBool::new(false);
+
+/// Keep the directory a tool ran in when it fails, so its files can be
+/// inspected. This is off by default, as each failure leaves a directory
+/// behind, which a server should not do.
+pub fn set_keep_failed_runs(keep: bool) {
+    KEEP_FAILED_RUNS.store(keep, Ordering::Relaxed);
+}
+
+#[must_use]
+pub fn keep_failed_runs() -> bool {
+    KEEP_FAILED_RUNS.load(Ordering::Relaxed)
+}
+
+/// Without the `external-tools` feature nothing is ever run, so there is
+/// nothing to detect.
+#[cfg(not(feature = "external-tools"))]
+fn detect_run_method() -> RunMethod {
+    RunMethod::Native
+}
+
+/// Auto-detect the best available run method
+#[cfg(feature = "external-tools")]
+fn detect_run_method() -> RunMethod {
+    // Check if we have the necessary tools for native execution
+    if which("conjure").is_ok() && which("savilerow").is_ok() {
+        let output = Command::new("conjure")
+            .arg("--version")
+            .output()
+            .expect("Failed to execute conjure --version");
+
+        if !output.status.success() {
+            eprintln!(
+                "ERROR: The command 'conjure' does not appear to be constraint tool, you have another 'conjure' program installed:"
+            );
+            eprintln!("stdout: {}", String::from_utf8_lossy(&output.stdout));
+            eprintln!("stderr: {}", String::from_utf8_lossy(&output.stderr));
+            eprintln!("Going to try docker or podman instead.")
+        } else {
+            return RunMethod::Native;
+        }
+    }
+
+    // Check for container tools
+    if which("podman").is_ok() {
+        eprintln!("Using podman");
+        return RunMethod::Podman;
+    }
+
+    if which("docker").is_ok() {
+        eprintln!("Using docker");
+        return RunMethod::Docker;
+    }
+
+    // Default to native if we couldn't detect anything
+    // This might fail later, but at least we tried
+    RunMethod::Native
+}
+
+/// The image conjure and savilerow are run from, by [`RunMethod::Docker`]
+/// and [`RunMethod::Podman`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerConfig {
+    /// The image, with its registry and tag. `{arch}` is replaced by the
+    /// architecture of the platform, such as `amd64` or `arm64`, for
+    /// registries which tag each architecture separately.
+    pub image: String,
+    /// The platform to run, such as `linux/arm64`. If not set, the host's
+    /// architecture is used if the image has it, and otherwise
+    /// `linux/amd64`, which runs under emulation.
+    pub platform: Option<String>,
+}
+
+/// The image used when `DEMYSTIFY_CONTAINER_IMAGE` is not set.
+pub const DEFAULT_CONTAINER_IMAGE: &str = "ghcr.io/conjure-cp/conjure:main";
+
+impl Default for ContainerConfig {
+    fn default() -> Self {
+        Self {
+            image: DEFAULT_CONTAINER_IMAGE.to_owned(),
+            platform: None,
+        }
+    }
+}
+
+impl ContainerConfig {
+    /// The configuration given by `DEMYSTIFY_CONTAINER_IMAGE` and
+    /// `DEMYSTIFY_CONTAINER_PLATFORM`, or the defaults.
+    #[must_use]
+    pub fn from_env() -> Self {
+        let var = |name: &str| std::env::var(name).ok().filter(|v: &String| !v.is_empty());
+        Self {
+            image: var("DEMYSTIFY_CONTAINER_IMAGE")
+                .unwrap_or_else(|| DEFAULT_CONTAINER_IMAGE.to_owned()),
+            platform: var("DEMYSTIFY_CONTAINER_PLATFORM"),
+        }
+    }
+
+    /// The image to run on `platform`.
+    #[must_use]
+    pub fn image_for(&self, platform: &str) -> String {
+        self.image.replace("{arch}", platform_arch(platform))
+    }
+
+    /// The platforms to try, best first, on a host whose own platform is
+    /// `host`.
+    fn platforms(&self, host: &str) -> Vec<String> {
+        match &self.platform {
+            Some(platform) => vec![platform.clone()],
+            None if host == "linux/amd64" => vec![host.to_owned()],
+            None => vec![host.to_owned(), "linux/amd64".to_owned()],
+        }
+    }
+}
+
+static CONTAINER_CONFIG: OnceLock<ContainerConfig> = OnceLock::new();
+
+/// The container configuration, read from the environment if it has not
+/// been set.
+pub fn get_container_config() -> &'static ContainerConfig {
+    CONTAINER_CONFIG.get_or_init(ContainerConfig::from_env)
+}
+
+/// Set the container configuration explicitly, before anything is run.
+pub fn set_container_config(config: ContainerConfig) {
+    let _ = CONTAINER_CONFIG.set(config);
+}
+
+/// The platform containers run natively on this host, such as `linux/arm64`
+/// on Apple Silicon. Containers run Linux whatever the host's system.
+#[must_use]
+pub fn host_platform() -> String {
+    let arch = match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        "x86" => "386",
+        "powerpc64" => "ppc64le",
+        arch => arch,
+    };
+    format!("linux/{arch}")
+}
+
+/// The architecture of `platform`, such as `arm64` for `linux/arm64/v8`.
+fn platform_arch(platform: &str) -> &str {
+    platform.split('/').nth(1).unwrap_or(platform)
+}
+
+/// The image a container is run from, and the platform it runs on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerImage {
+    pub image: String,
+    pub platform: String,
+}
+
+impl ContainerImage {
+    /// The image `config` gives for its platform, or the host's, without
+    /// checking it exists.
+    fn unchecked(config: &ContainerConfig) -> Self {
+        let platform = config.platform.clone().unwrap_or_else(host_platform);
+        Self {
+            image: config.image_for(&platform),
+            platform,
+        }
+    }
+}
+
+/// The image checked by [`ensure_container_image`], once one has been found.
+static CONTAINER_IMAGE: Mutex<Option<ContainerImage>> = Mutex::new(None);
+
+/// Makes sure the image from [`get_container_config`] is present for
+/// `runtime` (`docker` or `podman`), pulling it if not, and returns it. The
+/// result is cached, so this only runs `runtime` the first time it succeeds.
+/// Pulls show their progress on standard error.
+pub fn ensure_container_image(runtime: &str) -> anyhow::Result<ContainerImage> {
+    let mut cached = CONTAINER_IMAGE.lock().unwrap();
+    if let Some(image) = cached.as_ref() {
+        return Ok(image.clone());
+    }
+    let image = ensure_container_image_with(
+        &RuntimeExecutor,
+        runtime,
+        get_container_config(),
+        &host_platform(),
+    )?;
+    *cached = Some(image.clone());
+    Ok(image)
+}
+
+/// As [`ensure_container_image`], running `runtime` with `executor`, on a
+/// host whose platform is `host`, without caching the result. Each platform
+/// of [`ContainerConfig::platform`] is tried in turn, using the image if it
+/// is already present for that architecture, and otherwise pulling it.
+pub fn ensure_container_image_with(
+    executor: &dyn Executor,
+    runtime: &str,
+    config: &ContainerConfig,
+    host: &str,
+) -> anyhow::Result<ContainerImage> {
+    let dir = std::env::temp_dir();
+    let mut failures = vec![];
+    for platform in config.platforms(host) {
+        let image = config.image_for(&platform);
+        let inspect: Vec<OsString> = vec![
+            "image".into(),
+            "inspect".into(),
+            "--format".into(),
+            "{{.Architecture}}".into(),
+            image.clone().into(),
+        ];
+        if let Ok(output) = executor.run(runtime, &inspect, &dir)
+            && output.status.success()
+            && String::from_utf8_lossy(&output.stdout).trim() == platform_arch(&platform)
+        {
+            return Ok(ContainerImage { image, platform });
+        }
+
+        eprintln!("Pulling {image} for {platform} with {runtime}, which may take a few minutes...");
+        let pull: Vec<OsString> = vec![
+            "pull".into(),
+            "--platform".into(),
+            platform.clone().into(),
+            image.clone().into(),
+        ];
+        match executor.run(runtime, &pull, &dir) {
+            Ok(output) if output.status.success() => {
+                return Ok(ContainerImage { image, platform });
+            }
+            Ok(output) => failures.push(format!(
+                "{image} for {platform}: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )),
+            Err(e) => bail!("Failed to run {runtime}: {e}"),
+        }
+    }
+    bail!(
+        "Could not pull the conjure image with {runtime}. Set DEMYSTIFY_CONTAINER_IMAGE or DEMYSTIFY_CONTAINER_PLATFORM to choose another.\n{}",
+        failures.join("\n")
+    )
+}
+
+/// Runs a container runtime itself, on the host. The progress of pulls is
+/// shown on standard error, so it does not mix with the output of demystify.
+struct RuntimeExecutor;
+
+impl Executor for RuntimeExecutor {
+    fn run(&self, tool: &str, args: &[OsString], dir: &Path) -> std::io::Result<Output> {
+        let mut cmd = Command::new(tool);
+        cmd.args(args).current_dir(dir);
+        if args.first().is_some_and(|a| a == "pull") {
+            cmd.stdout(std::io::stderr()).stderr(std::io::stderr());
+        }
+        cmd.output()
+    }
+}
+
+/// Details of a run of an external tool (such as conjure or savilerow) which failed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolFailure {
+    pub tool: String,
+    pub args: Vec<String>,
+    /// `None` if the tool was stopped by a signal
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    pub duration: Duration,
+    /// The directory the tool ran in, if it is kept so its files can be
+    /// inspected (see [`set_keep_failed_runs`])
+    pub artifacts: Option<PathBuf>,
+}
+
+impl ToolFailure {
+    /// A shortened view of the failure, keeping only the last `max_lines`
+    /// lines of each of stdout and stderr.
+    #[must_use]
+    pub fn trimmed(&self, max_lines: usize) -> String {
+        fn tail(s: &str, max_lines: usize) -> String {
+            let lines: Vec<&str> = s.lines().collect();
+            if lines.len() <= max_lines {
+                lines.join("\n")
+            } else {
+                format!(
+                    "[... {} lines skipped ...]\n{}",
+                    lines.len() - max_lines,
+                    lines[lines.len() - max_lines..].join("\n")
+                )
+            }
+        }
+
+        format!(
+            "{}\n{}\n{}",
+            self.summary(),
+            tail(&self.stdout, max_lines),
+            tail(&self.stderr, max_lines)
+        )
+    }
+
+    /// Forgets [`ToolFailure::artifacts`], for when the directory is about to
+    /// be deleted. Paths inside it, in the arguments and output, are made
+    /// relative to it.
+    pub fn forget_artifacts(&mut self) {
+        let Some(dir) = self.artifacts.take() else {
+            return;
+        };
+        let dir = dir.to_string_lossy().into_owned();
+        let inside = format!("{dir}/");
+        for text in self
+            .args
+            .iter_mut()
+            .chain([&mut self.stdout, &mut self.stderr])
+        {
+            *text = text.replace(&inside, "").replace(&dir, ".");
+        }
+    }
+
+    fn summary(&self) -> String {
+        let code = self
+            .exit_code
+            .map_or_else(|| "no exit code".to_owned(), |c| format!("exit code {c}"));
+        format!(
+            "{} failed with {} after {:.1}s",
+            self.tool,
+            code,
+            self.duration.as_secs_f64()
+        )
+    }
+}
+
+impl fmt::Display for ToolFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\n{}\n{}", self.summary(), self.stdout, self.stderr)
+    }
+}
+
+impl std::error::Error for ToolFailure {}
+
+/// Runs external tools. Code which needs a tool takes an `&dyn Executor`, so
+/// tests can replace the real tools with a [`FakeExecutor`].
+pub trait Executor: Send + Sync {
+    /// Runs `tool` with `args` in the directory `dir`. An `Err` means the tool
+    /// could not be started at all; a tool which ran but failed is an `Ok`
+    /// with an unsuccessful exit status.
+    fn run(&self, tool: &str, args: &[OsString], dir: &Path) -> std::io::Result<Output>;
+}
+
+/// Runs tools for real, natively or in a container as chosen by [`get_run_method`].
+///
+/// Without the `external-tools` feature, every run fails with
+/// [`std::io::ErrorKind::Unsupported`], and puzzles must be loaded from DIMACS
+/// (see [`parse_eprime_with_dimacs`](crate::problem::parse::parse_eprime_with_dimacs)).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemExecutor;
+
+impl Executor for SystemExecutor {
+    fn run(&self, tool: &str, args: &[OsString], dir: &Path) -> std::io::Result<Output> {
+        if cfg!(feature = "external-tools") {
+            ProgramRunner::prepare(tool, dir).args(args).output()
+        } else {
+            Err(tools_disabled(tool))
+        }
+    }
+}
+
+/// Runs tools directly on this machine, even when [`get_run_method`] chooses
+/// a container. This is for tools such as `curl`, which are not part of the
+/// conjure image.
+///
+/// Like [`SystemExecutor`], nothing is run without the `external-tools`
+/// feature.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NativeExecutor;
+
+impl Executor for NativeExecutor {
+    fn run(&self, tool: &str, args: &[OsString], dir: &Path) -> std::io::Result<Output> {
+        if cfg!(feature = "external-tools") {
+            Command::new(tool).current_dir(dir).args(args).output()
+        } else {
+            Err(tools_disabled(tool))
+        }
+    }
+}
+
+fn tools_disabled(tool: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        format!(
+            "demystify was built without the 'external-tools' feature, so cannot run {tool}. \
+             Load puzzles from DIMACS instead"
+        ),
+    )
+}
+
+/// What a [`FakeExecutor`] does for one run of a tool.
+#[derive(Debug, Clone, Default)]
+pub struct FakeRun {
+    /// If set, the tool cannot be started, as if it was not installed
+    pub missing: bool,
+    /// `None` if the tool was stopped by a signal
+    pub exit_code: Option<i32>,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    /// Files written into the directory the tool runs in, as (name, contents)
+    pub files: Vec<(String, Vec<u8>)>,
+}
+
+impl FakeRun {
+    /// A successful run, printing `stdout`.
+    #[must_use]
+    pub fn success(stdout: impl Into<Vec<u8>>) -> Self {
+        FakeRun {
+            exit_code: Some(0),
+            stdout: stdout.into(),
+            ..Default::default()
+        }
+    }
+
+    /// A run which exits with `code`, printing `stderr`.
+    #[must_use]
+    pub fn failure(code: i32, stderr: impl Into<Vec<u8>>) -> Self {
+        FakeRun {
+            exit_code: Some(code),
+            stderr: stderr.into(),
+            ..Default::default()
+        }
+    }
+
+    /// A tool which is not installed.
+    #[must_use]
+    pub fn missing() -> Self {
+        FakeRun {
+            missing: true,
+            ..Default::default()
+        }
+    }
+
+    /// Also writes the file `name` into the directory the tool runs in.
+    #[must_use]
+    pub fn with_file(mut self, name: &str, contents: impl Into<Vec<u8>>) -> Self {
+        self.files.push((name.to_owned(), contents.into()));
+        self
+    }
+}
+
+/// An [`Executor`] which replays a script of [`FakeRun`]s, one per call, and
+/// records the calls made. Running out of script is treated as a missing tool.
+#[derive(Debug, Default)]
+pub struct FakeExecutor {
+    script: Mutex<VecDeque<FakeRun>>,
+    calls: Mutex<Vec<(String, Vec<String>)>>,
+}
+
+impl FakeExecutor {
+    #[must_use]
+    pub fn new(script: impl IntoIterator<Item = FakeRun>) -> Self {
+        FakeExecutor {
+            script: Mutex::new(script.into_iter().collect()),
+            calls: Mutex::new(vec![]),
+        }
+    }
+
+    /// The (tool, args) of each call made so far.
+    #[must_use]
+    pub fn calls(&self) -> Vec<(String, Vec<String>)> {
+        self.calls.lock().unwrap().clone()
+    }
+}
+
+impl Executor for FakeExecutor {
+    fn run(&self, tool: &str, args: &[OsString], dir: &Path) -> std::io::Result<Output> {
+        self.calls.lock().unwrap().push((
+            tool.to_owned(),
+            args.iter()
+                .map(|a| a.to_string_lossy().into_owned())
+                .collect(),
+        ));
+
+        let run = self.script.lock().unwrap().pop_front().unwrap_or(FakeRun {
+            missing: true,
+            ..Default::default()
+        });
+
+        if run.missing {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{tool}: not found"),
+            ));
+        }
+
+        for (name, contents) in &run.files {
+            std::fs::write(dir.join(name), contents)?;
+        }
+
+        Ok(Output {
+            status: exit_status(run.exit_code),
+            stdout: run.stdout,
+            stderr: run.stderr,
+        })
+    }
+}
+
+#[cfg(unix)]
+fn exit_status(code: Option<i32>) -> ExitStatus {
+    use std::os::unix::process::ExitStatusExt;
+    // Without an exit code, report the tool as killed by SIGKILL
+    ExitStatus::from_raw(code.map_or(9, |c| c << 8))
+}
+
+#[cfg(windows)]
+fn exit_status(code: Option<i32>) -> ExitStatus {
+    use std::os::windows::process::ExitStatusExt;
+    ExitStatus::from_raw(code.unwrap_or(1) as u32)
+}
+
+/// Program runner to execute commands in different environments
+pub struct ProgramRunner;
+
+impl ProgramRunner {
+    /// Run `conjure --version` and return its output
+    pub fn get_conjure_version() -> Result<String, String> {
+        Self::get_conjure_version_with(&SystemExecutor)
+    }
+
+    /// Run `conjure --version` with `executor` and return its output
+    pub fn get_conjure_version_with(executor: &dyn Executor) -> Result<String, String> {
+        let current_dir =
+            std::env::current_dir().map_err(|e| format!("Failed to get current directory: {e}"))?;
+
+        let output = executor
+            .run("conjure", &["--version".into()], &current_dir)
+            .map_err(|e| format!("Failed to execute conjure: {e}"))?;
+
+        if output.status.success() {
+            Ok("Using ".to_owned()
+                + &get_run_method().to_string()
+                + " conjure, version:\n"
+                + &String::from_utf8_lossy(&output.stdout))
+        } else {
+            Err(format!(
+                "Conjure failed with status {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr)
+            ))
+        }
+    }
+
+    /// Run a command made by [`ProgramRunner::prepare`]. If the command runs
+    /// but does not succeed, the error contains a [`ToolFailure`], which can
+    /// be retrieved with [`anyhow::Error::downcast_ref`].
+    pub fn run(tool: &str, mut cmd: Command) -> anyhow::Result<Output> {
+        let start = Instant::now();
+        let output = if cfg!(feature = "external-tools") {
+            cmd.output()
+        } else {
+            Err(tools_disabled(tool))
+        };
+        let output =
+            output.with_context(|| format!("Failed to run '{tool}' -- have you installed it?"))?;
+
+        let args: Vec<OsString> = cmd.get_args().map(ToOwned::to_owned).collect();
+        Self::check(tool, &args, cmd.get_current_dir(), output, start)
+    }
+
+    /// Run `tool` with `args` in `dir` using `executor`, reporting failures
+    /// in the same way as [`ProgramRunner::run`].
+    pub fn run_with(
+        executor: &dyn Executor,
+        tool: &str,
+        args: &[OsString],
+        dir: &Path,
+    ) -> anyhow::Result<Output> {
+        let start = Instant::now();
+        let output = executor
+            .run(tool, args, dir)
+            .with_context(|| format!("Failed to run '{tool}' -- have you installed it?"))?;
+
+        Self::check(tool, args, Some(dir), output, start)
+    }
+
+    fn check(
+        tool: &str,
+        args: &[OsString],
+        dir: Option<&Path>,
+        output: Output,
+        start: Instant,
+    ) -> anyhow::Result<Output> {
+        if output.status.success() {
+            return Ok(output);
+        }
+
+        Err(ToolFailure {
+            tool: tool.to_owned(),
+            args: args
+                .iter()
+                .map(|a| a.to_string_lossy().into_owned())
+                .collect(),
+            exit_code: output.status.code(),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            duration: start.elapsed(),
+            artifacts: dir.map(Path::to_path_buf),
+        }
+        .into())
+    }
+
+    /// Prepare a `Command` to run a program, either natively or in a container
+    #[must_use]
+    pub fn prepare(program: &str, localdir: &Path) -> Command {
+        match get_run_method() {
+            RunMethod::Native => {
+                // Create a native command
+                let mut cmd = Command::new(program);
+                cmd.current_dir(localdir);
+                cmd
+            }
+            RunMethod::Docker | RunMethod::Podman => {
+                let container_cmd = if get_run_method() == RunMethod::Docker {
+                    "docker"
+                } else {
+                    "podman"
+                };
+
+                // If the image cannot be found, run anyway, so the
+                // runtime's own error is reported. Without external tools
+                // the command is never run, so nothing is pulled.
+                let image = if cfg!(feature = "external-tools") {
+                    ensure_container_image(container_cmd).unwrap_or_else(|e| {
+                        eprintln!("{e:#}");
+                        ContainerImage::unchecked(get_container_config())
+                    })
+                } else {
+                    ContainerImage::unchecked(get_container_config())
+                };
+
+                // Build the container command
+                let mut container_command = Command::new(container_cmd);
+                container_command
+                    .current_dir(localdir)
+                    .arg("run")
+                    .arg("--rm")
+                    .arg("--platform")
+                    .arg(&image.platform)
+                    .arg("-v")
+                    .arg(".:/workspace:Z")
+                    .arg("-w")
+                    .arg("/workspace")
+                    .arg(&image.image)
+                    .arg(program);
+
+                container_command
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{
+        ContainerConfig, ContainerImage, FakeExecutor, FakeRun, ProgramRunner, ToolFailure,
+        ensure_container_image_with,
+    };
+
+    #[test]
+    fn test_tool_failure_trimmed() {
+        let failure = ToolFailure {
+            tool: "savilerow".to_owned(),
+            args: vec![],
+            exit_code: Some(1),
+            stdout: "a\nb\nc".to_owned(),
+            stderr: "error".to_owned(),
+            duration: Duration::from_millis(1500),
+            artifacts: None,
+        };
+
+        assert_eq!(
+            failure.trimmed(2),
+            "savilerow failed with exit code 1 after 1.5s\n[... 1 lines skipped ...]\nb\nc\nerror"
+        );
+        assert_eq!(
+            failure.to_string(),
+            "savilerow failed with exit code 1 after 1.5s\na\nb\nc\nerror"
+        );
+    }
+
+    #[test]
+    fn test_fake_executor() {
+        let fake = FakeExecutor::new([
+            FakeRun::success("1.0"),
+            FakeRun::failure(2, "bad model"),
+            FakeRun::missing(),
+        ]);
+        let dir = tempfile::tempdir().unwrap();
+        let args = ["-in-eprime".into(), "model.eprime".into()];
+
+        let out = ProgramRunner::run_with(&fake, "savilerow", &args, dir.path()).unwrap();
+        assert_eq!(out.stdout, b"1.0");
+
+        let err = ProgramRunner::run_with(&fake, "savilerow", &args, dir.path()).unwrap_err();
+        let failure = err.downcast_ref::<ToolFailure>().unwrap();
+        assert_eq!(failure.exit_code, Some(2));
+        assert_eq!(failure.stderr, "bad model");
+        assert_eq!(failure.args, vec!["-in-eprime", "model.eprime"]);
+        assert_eq!(failure.artifacts.as_deref(), Some(dir.path()));
+
+        let mut failure = failure.clone();
+        failure.stderr = format!("cannot read {}/model.eprime", dir.path().display());
+        failure.forget_artifacts();
+        assert_eq!(failure.artifacts, None);
+        assert_eq!(failure.stderr, "cannot read model.eprime");
+
+        let err = ProgramRunner::run_with(&fake, "savilerow", &args, dir.path()).unwrap_err();
+        assert!(err.downcast_ref::<ToolFailure>().is_none());
+        assert!(err.to_string().contains("have you installed it?"));
+
+        assert_eq!(fake.calls().len(), 3);
+    }
+
+    #[test]
+    fn test_conjure_version_with() {
+        let fake = FakeExecutor::new([
+            FakeRun::success("Conjure v2.5.1"),
+            FakeRun::failure(1, "conjure: unknown option"),
+            FakeRun::missing(),
+        ]);
+
+        assert!(
+            ProgramRunner::get_conjure_version_with(&fake)
+                .unwrap()
+                .ends_with("Conjure v2.5.1")
+        );
+        assert!(
+            ProgramRunner::get_conjure_version_with(&fake)
+                .unwrap_err()
+                .contains("unknown option")
+        );
+        assert!(
+            ProgramRunner::get_conjure_version_with(&fake)
+                .unwrap_err()
+                .starts_with("Failed to execute conjure")
+        );
+        assert_eq!(
+            fake.calls()[0],
+            ("conjure".to_owned(), vec!["--version".to_owned()])
+        );
+    }
+
+    #[test]
+    fn test_container_image() {
+        let config = ContainerConfig {
+            image: "registry.example/conjure:main-{arch}".to_owned(),
+            platform: None,
+        };
+        assert_eq!(
+            config.image_for("linux/arm64/v8"),
+            "registry.example/conjure:main-arm64"
+        );
+        let image = |image: &str, platform: &str| ContainerImage {
+            image: image.to_owned(),
+            platform: platform.to_owned(),
+        };
+
+        // Already present for this host
+        let fake = FakeExecutor::new([FakeRun::success("amd64\n")]);
+        let found = ensure_container_image_with(&fake, "docker", &config, "linux/amd64");
+        assert_eq!(
+            found.unwrap(),
+            image("registry.example/conjure:main-amd64", "linux/amd64")
+        );
+        assert_eq!(
+            fake.calls()[0].1,
+            [
+                "image",
+                "inspect",
+                "--format",
+                "{{.Architecture}}",
+                "registry.example/conjure:main-amd64"
+            ]
+        );
+
+        // No arm64 image, so fall back to emulating amd64, which is present
+        let fake = FakeExecutor::new([
+            FakeRun::failure(1, "no such image"),
+            FakeRun::failure(1, "no matching manifest for linux/arm64"),
+            FakeRun::success("amd64\n"),
+        ]);
+        let found = ensure_container_image_with(&fake, "podman", &config, "linux/arm64");
+        assert_eq!(
+            found.unwrap(),
+            image("registry.example/conjure:main-amd64", "linux/amd64")
+        );
+        assert_eq!(
+            fake.calls()[1].1,
+            [
+                "pull",
+                "--platform",
+                "linux/arm64",
+                "registry.example/conjure:main-arm64"
+            ]
+        );
+
+        // Present for the wrong architecture, so pulled
+        let config = ContainerConfig {
+            platform: Some("linux/arm64".to_owned()),
+            ..ContainerConfig::default()
+        };
+        let fake = FakeExecutor::new([FakeRun::success("amd64\n"), FakeRun::success("")]);
+        let found = ensure_container_image_with(&fake, "docker", &config, "linux/amd64");
+        assert_eq!(
+            found.unwrap(),
+            image(super::DEFAULT_CONTAINER_IMAGE, "linux/arm64")
+        );
+        assert_eq!(fake.calls().len(), 2);
+
+        // An explicit platform is not fallen back from
+        let fake = FakeExecutor::new([
+            FakeRun::failure(1, "no such image"),
+            FakeRun::failure(1, "unauthorized"),
+        ]);
+        let err = ensure_container_image_with(&fake, "docker", &config, "linux/amd64");
+        assert!(format!("{:#}", err.unwrap_err()).contains("unauthorized"));
+
+        let fake = FakeExecutor::new([FakeRun::missing(), FakeRun::missing()]);
+        let err = ensure_container_image_with(&fake, "docker", &config, "linux/amd64");
+        assert!(
+            err.unwrap_err()
+                .to_string()
+                .starts_with("Failed to run docker")
+        );
+    }
+
+    #[cfg(not(feature = "external-tools"))]
+    #[test]
+    fn test_tools_disabled() {
+        let dir = std::env::current_dir().unwrap();
+        let err =
+            ProgramRunner::run_with(&super::SystemExecutor, "conjure", &[], &dir).unwrap_err();
+        assert!(format!("{err:#}").contains("external-tools"), "{err:#}");
+    }
+}
@@ -0,0 +1,384 @@
+//! Downloading models and parameters over HTTPS, so puzzles published online
+//! (such as in the conjure model repositories) can be tried without saving
+//! them first.
+//!
+//! Downloads are made with `curl`, which is run through an
+//! [`Executor`] like the other external tools (though never in a container),
+//! and are checked before they are used:
+//!
+//! - Only `https` URLs are fetched, also after any redirects.
+//! - Downloads larger than [`FetchLimits::max_bytes`] are stopped.
+//! - Web pages are rejected, as they are usually a page showing the file
+//!   rather than the file itself.
+//! - A URL ending in `#sha256=<hex>` must match that checksum, so a URL can be
+//!   pinned to one version of a file. The fragment is not sent to the server.
+//!
+//! The file is then used exactly as if it had been read from disk or uploaded.
+
+use std::{
+    ffi::OsString,
+    fmt::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, bail};
+use sha2::{Digest, Sha256};
+
+use super::exec::{Executor, NativeExecutor, ProgramRunner};
+
+/// Limits on what [`fetch`] downloads.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FetchLimits {
+    /// The largest file downloaded, in bytes
+    pub max_bytes: usize,
+    /// How long a download can take
+    pub timeout: Duration,
+    /// If set, only these hosts are fetched from, and redirects are not
+    /// followed, as they could lead anywhere
+    pub allowed_hosts: Option<Vec<String>>,
+}
+
+impl Default for FetchLimits {
+    fn default() -> Self {
+        Self {
+            max_bytes: 1 << 20,
+            timeout: Duration::from_secs(30),
+            allowed_hosts: None,
+        }
+    }
+}
+
+/// A URL to fetch, split into the parts which are checked.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FetchUrl {
+    /// The URL without any `#` fragment, which is what is requested
+    pub url: String,
+    pub host: String,
+    /// The last part of the path, which says what kind of file it is
+    pub file_name: String,
+    /// The checksum pinned with `#sha256=<hex>`, in lower case
+    pub sha256: Option<String>,
+}
+
+impl FetchUrl {
+    /// Splits up `url`, which must be `https`.
+    pub fn parse(url: &str) -> anyhow::Result<Self> {
+        let url = url.trim();
+        let (url, fragment) = match url.split_once('#') {
+            Some((url, fragment)) => (url, Some(fragment)),
+            None => (url, None),
+        };
+        let rest = url
+            .strip_prefix("https://")
+            .with_context(|| format!("Only https URLs can be fetched, not '{url}'"))?;
+
+        let authority_end = rest.find(['/', '?']).unwrap_or(rest.len());
+        let authority = &rest[..authority_end];
+        if authority.contains('@') {
+            bail!("URLs with a user name or password cannot be fetched: '{url}'");
+        }
+        let host = match authority.rsplit_once(':') {
+            Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) => host,
+            _ => authority,
+        };
+        if host.is_empty() {
+            bail!("'{url}' has no host");
+        }
+
+        let path = rest[authority_end..].split('?').next().unwrap_or("");
+        let file_name = path.rsplit('/').next().unwrap_or("");
+        // The file is saved under this name, so it must stay in its directory
+        if file_name.is_empty() || file_name == "." || file_name == ".." || file_name.contains('\\')
+        {
+            bail!("'{url}' does not name a file");
+        }
+
+        let sha256 = match fragment {
+            None => None,
+            Some(fragment) => {
+                let hex = fragment.strip_prefix("sha256=").with_context(|| {
+                    format!("Expected '#sha256=<hex>' in '{url}', not '#{fragment}'")
+                })?;
+                if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                    bail!("'{hex}' is not a SHA-256 checksum, which is 64 hex digits");
+                }
+                Some(hex.to_lowercase())
+            }
+        };
+
+        Ok(Self {
+            url: url.to_owned(),
+            host: host.to_lowercase(),
+            file_name: file_name.to_owned(),
+            sha256,
+        })
+    }
+
+    fn check_host(&self, limits: &FetchLimits) -> anyhow::Result<()> {
+        if let Some(hosts) = &limits.allowed_hosts
+            && !hosts.iter().any(|h| h.eq_ignore_ascii_case(&self.host))
+        {
+            bail!(
+                "Cannot fetch from {}, only from {}",
+                self.host,
+                hosts.join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    /// Checks what was downloaded from this URL, with the content type the
+    /// server gave.
+    pub fn check(
+        &self,
+        content_type: &str,
+        contents: Vec<u8>,
+        limits: &FetchLimits,
+    ) -> anyhow::Result<FetchedFile> {
+        if contents.len() > limits.max_bytes {
+            bail!(
+                "{} is larger than the limit of {} bytes",
+                self.url,
+                limits.max_bytes
+            );
+        }
+        if contents.is_empty() {
+            bail!("{} is empty", self.url);
+        }
+
+        let mime = content_type
+            .split(';')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_lowercase();
+        match mime.as_str() {
+            "text/html" | "application/xhtml+xml" => bail!(
+                "{} is a web page, not a model or parameter file. For a file on GitHub, use the link to its raw contents",
+                self.url
+            ),
+            ""
+            | "application/json"
+            | "application/octet-stream"
+            | "application/gzip"
+            | "application/x-gzip"
+            | "application/zstd" => {}
+            m if m.starts_with("text/") => {}
+            m => bail!(
+                "{} has content type '{m}', which is not a text file",
+                self.url
+            ),
+        }
+
+        if let Some(expected) = &self.sha256 {
+            let found = sha256_hex(&contents);
+            if &found != expected {
+                bail!(
+                    "{} has SHA-256 checksum {found}, not the {expected} it is pinned to",
+                    self.url
+                );
+            }
+        }
+
+        Ok(FetchedFile {
+            url: self.url.clone(),
+            file_name: self.file_name.clone(),
+            contents,
+        })
+    }
+}
+
+/// A file downloaded by [`fetch`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FetchedFile {
+    pub url: String,
+    /// The name of the file in the URL, such as `sudoku.eprime`
+    pub file_name: String,
+    /// As downloaded, so possibly compressed
+    pub contents: Vec<u8>,
+}
+
+impl FetchedFile {
+    /// Writes the file into `dir` under its own name, returning its path.
+    pub fn save_in(&self, dir: &Path) -> anyhow::Result<PathBuf> {
+        let path = dir.join(&self.file_name);
+        std::fs::write(&path, &self.contents)
+            .with_context(|| format!("Cannot write {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+/// The SHA-256 checksum of `bytes`, in lower case hex.
+#[must_use]
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .fold(String::new(), |mut hex, b| {
+            let _ = write!(hex, "{b:02x}");
+            hex
+        })
+}
+
+/// Downloads `url`, as described in the [module docs](self).
+pub fn fetch(url: &str, limits: &FetchLimits) -> anyhow::Result<FetchedFile> {
+    fetch_with(url, limits, &NativeExecutor)
+}
+
+/// As [`fetch`], running `curl` with `executor`.
+pub fn fetch_with(
+    url: &str,
+    limits: &FetchLimits,
+    executor: &dyn Executor,
+) -> anyhow::Result<FetchedFile> {
+    let target = FetchUrl::parse(url)?;
+    target.check_host(limits)?;
+
+    // curl stops as soon as the download passes --max-filesize, even when
+    // the server does not say how large the file is
+    let mut args: Vec<OsString> = vec![
+        "--silent".into(),
+        "--show-error".into(),
+        "--fail".into(),
+        "--proto".into(),
+        "=https".into(),
+        "--max-filesize".into(),
+        limits.max_bytes.to_string().into(),
+        "--max-time".into(),
+        limits.timeout.as_secs().max(1).to_string().into(),
+        // The content type goes to stderr, after any errors
+        "--write-out".into(),
+        "%{stderr}%{content_type}".into(),
+        "--output".into(),
+        "-".into(),
+    ];
+    if limits.allowed_hosts.is_none() {
+        args.extend(
+            ["--location", "--proto-redir", "=https", "--max-redirs", "5"].map(OsString::from),
+        );
+    }
+    args.push(target.url.clone().into());
+
+    let output = ProgramRunner::run_with(executor, "curl", &args, &std::env::temp_dir())
+        .with_context(|| format!("Failed to download {}", target.url))?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let content_type = stderr.lines().last().unwrap_or("");
+    target.check(content_type, output.stdout, limits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::problem::util::exec::{FakeExecutor, FakeRun};
+
+    // The SHA-256 of "abc"
+    const ABC: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    #[test]
+    fn test_fetch_url_parse() {
+        let url = FetchUrl::parse(&format!(
+            "https://Example.org:8443/models/sudoku.eprime?raw=true#sha256={}",
+            ABC.to_uppercase()
+        ))
+        .unwrap();
+        assert_eq!(
+            url,
+            FetchUrl {
+                url: "https://Example.org:8443/models/sudoku.eprime?raw=true".to_owned(),
+                host: "example.org".to_owned(),
+                file_name: "sudoku.eprime".to_owned(),
+                sha256: Some(ABC.to_owned()),
+            }
+        );
+
+        for bad in [
+            "http://example.org/sudoku.eprime",
+            "file:///etc/passwd",
+            "https://user:pw@example.org/sudoku.eprime",
+            "https:///sudoku.eprime",
+            "https://example.org/models/",
+            "https://example.org/models/..",
+            "https://example.org/sudoku.eprime#sha256=abc",
+            "https://example.org/sudoku.eprime#md5=abc",
+        ] {
+            assert!(FetchUrl::parse(bad).is_err(), "{bad}");
+        }
+
+        let limits = FetchLimits {
+            allowed_hosts: Some(vec!["raw.githubusercontent.com".to_owned()]),
+            ..FetchLimits::default()
+        };
+        assert!(url.check_host(&limits).is_err());
+        assert!(url.check_host(&FetchLimits::default()).is_ok());
+    }
+
+    #[test]
+    fn test_fetch_checks() {
+        let limits = FetchLimits {
+            max_bytes: 5,
+            ..FetchLimits::default()
+        };
+        let url = FetchUrl::parse("https://example.org/a.param").unwrap();
+        let fetched = url
+            .check("text/plain; charset=utf-8", b"abc".to_vec(), &limits)
+            .unwrap();
+        assert_eq!(fetched.file_name, "a.param");
+        assert!(url.check("", b"abc".to_vec(), &limits).is_ok());
+
+        let err = url
+            .check("text/html", b"abc".to_vec(), &limits)
+            .unwrap_err();
+        assert!(err.to_string().contains("web page"), "{err}");
+        assert!(url.check("image/png", b"abc".to_vec(), &limits).is_err());
+        assert!(
+            url.check("text/plain", b"abcdef".to_vec(), &limits)
+                .is_err()
+        );
+        assert!(url.check("text/plain", vec![], &limits).is_err());
+
+        let pinned = FetchUrl::parse(&format!("https://example.org/a.param#sha256={ABC}")).unwrap();
+        assert!(pinned.check("text/plain", b"abc".to_vec(), &limits).is_ok());
+        let err = pinned
+            .check("text/plain", b"abd".to_vec(), &limits)
+            .unwrap_err();
+        assert!(err.to_string().contains("pinned"), "{err}");
+    }
+
+    #[test]
+    fn test_fetch_with() {
+        let limits = FetchLimits {
+            max_bytes: 5,
+            ..FetchLimits::default()
+        };
+        let executor = FakeExecutor::new([
+            FakeRun {
+                stderr: b"text/plain".to_vec(),
+                ..FakeRun::success("abc")
+            },
+            // curl's exit code when a download is larger than --max-filesize
+            FakeRun::failure(63, "curl: (63) Maximum file size exceeded"),
+            FakeRun {
+                stderr: b"text/plain".to_vec(),
+                ..FakeRun::success("abcdef")
+            },
+        ]);
+
+        let fetched = fetch_with("https://example.org/a.param", &limits, &executor).unwrap();
+        assert_eq!(fetched.contents, b"abc");
+        let (tool, args) = &executor.calls()[0];
+        assert_eq!(tool, "curl");
+        let max = args.iter().position(|a| a == "--max-filesize").unwrap();
+        assert_eq!(args[max + 1], "5");
+        assert!(args.iter().any(|a| a == "--location"));
+        assert_eq!(args.last().unwrap(), "https://example.org/a.param");
+
+        let err = fetch_with("https://example.org/b.param", &limits, &executor).unwrap_err();
+        assert!(format!("{err:#}").contains("Maximum file size"), "{err:#}");
+        // Checked again, in case curl did not stop
+        let err = fetch_with("https://example.org/c.param", &limits, &executor).unwrap_err();
+        assert!(err.to_string().contains("larger than the limit"), "{err}");
+        // Bad URLs are refused before curl is run
+        assert!(fetch_with("http://example.org/a.param", &limits, &executor).is_err());
+        assert_eq!(executor.calls().len(), 3);
+    }
+}
@@ -0,0 +1,161 @@
+//! Versions of the files demystify writes and later reads back, such as
+//! reproducibility reports and saved sessions.
+//!
+//! Each such file has a `format_version` field. Files from older versions are
+//! upgraded on load, one version at a time, while files written by a newer
+//! version of demystify are rejected with an error saying so, rather than
+//! failing on some field which has changed. Files with no version at all were
+//! written before versions were added, and count as version 0.
+
+use anyhow::{Context, bail};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+/// Changes a file of one version into the layout of the next version. The
+/// `format_version` field is updated separately.
+pub type Upgrade = fn(&mut Map<String, Value>) -> anyhow::Result<()>;
+
+/// An upgrade for versions whose layout is the same as the next one, such as
+/// files written before they had a version.
+pub fn unchanged(_: &mut Map<String, Value>) -> anyhow::Result<()> {
+    Ok(())
+}
+
+/// A versioned file format.
+#[derive(Clone, Copy, Debug)]
+pub struct Format {
+    /// What the files hold, for error messages, such as "Saved session"
+    pub name: &'static str,
+    /// The version written by this build
+    pub version: u32,
+    /// The oldest version which can still be read
+    pub oldest: u32,
+    /// The upgrade from each version from `oldest` to the next, so there
+    /// should be `version - oldest` of them
+    pub upgrades: &'static [Upgrade],
+}
+
+impl Format {
+    /// Reads `value`, upgrading it to the current version if needed.
+    pub fn parse<T: DeserializeOwned>(&self, value: Value) -> anyhow::Result<T> {
+        let Value::Object(mut map) = value else {
+            bail!("{} is not a JSON object", self.name);
+        };
+        let found = match map.get("format_version") {
+            None => 0,
+            Some(v) => v
+                .as_u64()
+                .and_then(|v| u32::try_from(v).ok())
+                .with_context(|| format!("{} has an invalid format version {v}", self.name))?,
+        };
+
+        if found > self.version {
+            bail!(
+                "{} has format version {found}, but this version of demystify reads at most version {}. It was written by a newer version of demystify, which is needed to read it",
+                self.name,
+                self.version
+            );
+        }
+        if found < self.oldest {
+            bail!(
+                "{} has format version {found}, which is too old for this version of demystify (the oldest it reads is {})",
+                self.name,
+                self.oldest
+            );
+        }
+
+        for version in found..self.version {
+            let upgrade = self.upgrades[(version - self.oldest) as usize];
+            upgrade(&mut map).with_context(|| {
+                format!(
+                    "Failed to upgrade {} from format version {version}",
+                    self.name.to_lowercase()
+                )
+            })?;
+        }
+        map.insert("format_version".to_owned(), self.version.into());
+
+        serde_json::from_value(Value::Object(map))
+            .with_context(|| format!("{} is malformed", self.name))
+    }
+
+    /// As [`Format::parse`], reading JSON from `reader`.
+    pub fn from_reader<T: DeserializeOwned>(
+        &self,
+        reader: impl std::io::Read,
+    ) -> anyhow::Result<T> {
+        let value = serde_json::from_reader(reader)
+            .with_context(|| format!("{} is not valid JSON", self.name))?;
+        self.parse(value)
+    }
+
+    /// As [`Format::parse`], reading JSON from `s`.
+    pub fn from_str<T: DeserializeOwned>(&self, s: &str) -> anyhow::Result<T> {
+        self.from_reader(s.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Thing {
+        format_version: u32,
+        cells: Vec<String>,
+    }
+
+    /// Version 1 had a single `cell`, which became a list in version 2.
+    fn cell_to_cells(map: &mut Map<String, Value>) -> anyhow::Result<()> {
+        let cell = map.remove("cell").context("No cell")?;
+        map.insert("cells".to_owned(), Value::Array(vec![cell]));
+        Ok(())
+    }
+
+    const THING: Format = Format {
+        name: "Thing",
+        version: 2,
+        oldest: 0,
+        upgrades: &[unchanged, cell_to_cells],
+    };
+
+    #[test]
+    fn test_format_upgrades() {
+        let expected = Thing {
+            format_version: 2,
+            cells: vec!["x".to_owned()],
+        };
+        for json in [
+            r#"{"cell": "x"}"#,
+            r#"{"format_version": 1, "cell": "x"}"#,
+            r#"{"format_version": 2, "cells": ["x"]}"#,
+        ] {
+            assert_eq!(THING.from_str::<Thing>(json).unwrap(), expected);
+        }
+
+        let err = THING
+            .from_str::<Thing>(r#"{"format_version": 3, "cells": []}"#)
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("newer version of demystify"),
+            "{err}"
+        );
+
+        let err = THING
+            .from_str::<Thing>(r#"{"format_version": 1}"#)
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("Failed to upgrade thing from format version 1"));
+
+        let newer_only = Format {
+            oldest: 1,
+            upgrades: &[cell_to_cells],
+            ..THING
+        };
+        let err = newer_only
+            .from_str::<Thing>(r#"{"cell": "x"}"#)
+            .unwrap_err();
+        assert!(err.to_string().contains("too old"), "{err}");
+    }
+}
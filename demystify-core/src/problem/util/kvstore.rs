@@ -0,0 +1,158 @@
+//! Somewhere to put data which need not stay in memory, such as the older
+//! steps of a [`StepHistory`](crate::problem::history::StepHistory).
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{Context, bail};
+
+/// A store of byte strings by key. Keys are made of letters, digits, `-` and
+/// `_`, so they can be used as file names.
+pub trait KvStore: Send + Sync {
+    fn put(&self, key: &str, value: &[u8]) -> anyhow::Result<()>;
+    /// The value stored under `key`, or `None` if there is none.
+    fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>>;
+    /// Removes `key`, if it is stored.
+    fn remove(&self, key: &str) -> anyhow::Result<()>;
+}
+
+fn check_key(key: &str) -> anyhow::Result<()> {
+    if key.is_empty()
+        || !key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        bail!("'{key}' is not a valid key");
+    }
+    Ok(())
+}
+
+/// Keeps each value in a file named after its key, in one directory.
+#[derive(Debug)]
+pub struct DirStore {
+    dir: PathBuf,
+}
+
+impl DirStore {
+    /// Stores values in `dir`, which is made if it does not exist.
+    pub fn new(dir: &Path) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(dir).with_context(|| format!("Cannot create {}", dir.display()))?;
+        Ok(Self {
+            dir: dir.to_owned(),
+        })
+    }
+
+    /// Removes every file in the directory, such as those left by an
+    /// earlier run.
+    pub fn clear(&self) -> anyhow::Result<()> {
+        let entries = std::fs::read_dir(&self.dir)
+            .with_context(|| format!("Cannot read {}", self.dir.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.is_file() {
+                std::fs::remove_file(&path)
+                    .with_context(|| format!("Cannot remove {}", path.display()))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn path(&self, key: &str) -> anyhow::Result<PathBuf> {
+        check_key(key)?;
+        Ok(self.dir.join(key))
+    }
+}
+
+impl KvStore for DirStore {
+    fn put(&self, key: &str, value: &[u8]) -> anyhow::Result<()> {
+        let path = self.path(key)?;
+        std::fs::write(&path, value).with_context(|| format!("Cannot write {}", path.display()))
+    }
+
+    fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let path = self.path(key)?;
+        match std::fs::read(&path) {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Cannot read {}", path.display())),
+        }
+    }
+
+    fn remove(&self, key: &str) -> anyhow::Result<()> {
+        let path = self.path(key)?;
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Cannot remove {}", path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Keeps values in memory, for tests.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    values: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl MemoryStore {
+    /// The number of values stored.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.values.lock().unwrap().len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl KvStore for MemoryStore {
+    fn put(&self, key: &str, value: &[u8]) -> anyhow::Result<()> {
+        check_key(key)?;
+        self.values
+            .lock()
+            .unwrap()
+            .insert(key.to_owned(), value.to_vec());
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self.values.lock().unwrap().get(key).cloned())
+    }
+
+    fn remove(&self, key: &str) -> anyhow::Result<()> {
+        self.values.lock().unwrap().remove(key);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dir_store() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let store = DirStore::new(&dir.path().join("history"))?;
+        assert_eq!(store.get("a-1")?, None);
+        store.put("a-1", b"abc")?;
+        assert_eq!(store.get("a-1")?, Some(b"abc".to_vec()));
+        store.remove("a-1")?;
+        store.remove("a-1")?;
+        assert_eq!(store.get("a-1")?, None);
+
+        // Keys cannot leave the directory
+        assert!(store.put("../a", b"abc").is_err());
+        assert!(store.get("").is_err());
+
+        store.put("a-2", b"abc")?;
+        store.clear()?;
+        assert_eq!(store.get("a-2")?, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,340 @@
+//! Setting up `tracing` output, for both the command line tools and the web
+//! server.
+
+use std::{
+    ffi::OsString,
+    fmt::Write as _,
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use tracing::{
+    Event, Subscriber,
+    field::{Field, Visit},
+};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::{
+    EnvFilter,
+    fmt::{FmtContext, FormatEvent, FormatFields, format::FmtSpan, format::Writer},
+    registry::LookupSpan,
+};
+
+/// How each log line is written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable text
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("Invalid log format: {s} (expected text or json)")),
+        }
+    }
+}
+
+impl std::fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LogFormat::Text => write!(f, "text"),
+            LogFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+/// Where, and how, to write logs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogConfig {
+    /// The file to write to, or standard error if `None`
+    pub file: Option<PathBuf>,
+    /// Start a new file once this many bytes have been written to it (see
+    /// [`RotatingFile`])
+    pub max_size: Option<u64>,
+    /// The number of earlier files kept when rotating
+    pub keep: usize,
+    pub format: LogFormat,
+    /// Which events to log, in the syntax of [`EnvFilter`], such as `info`
+    /// or `trace,solver=off`
+    pub filter: String,
+    pub timestamps: bool,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            file: None,
+            max_size: None,
+            keep: 3,
+            format: LogFormat::Text,
+            filter: "info".to_owned(),
+            timestamps: true,
+        }
+    }
+}
+
+impl LogConfig {
+    /// Everything, written to `file` without timestamps, as the `--trace`
+    /// option of the command line tools does.
+    #[must_use]
+    pub fn trace_file(file: PathBuf, max_size: Option<u64>) -> Self {
+        Self {
+            file: Some(file),
+            max_size,
+            filter: "trace".to_owned(),
+            timestamps: false,
+            ..Self::default()
+        }
+    }
+
+    /// Installs a global subscriber writing logs as described. Logs are
+    /// written on a background thread, until the returned guard is dropped.
+    pub fn init(&self) -> anyhow::Result<WorkerGuard> {
+        let (writer, guard) = match &self.file {
+            Some(path) => {
+                let file = RotatingFile::create(path, self.max_size, self.keep)
+                    .map_err(|e| anyhow!("Failed to create log file {path:?}: {e}"))?;
+                tracing_appender::non_blocking(file)
+            }
+            None => tracing_appender::non_blocking(io::stderr()),
+        };
+
+        let filter = EnvFilter::try_new(&self.filter)
+            .map_err(|e| anyhow!("Invalid log filter '{}': {e}", self.filter))?;
+
+        let builder = tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_ansi(false)
+            .with_writer(writer);
+
+        let result = match (self.format, self.timestamps) {
+            (LogFormat::Json, timestamps) => {
+                builder.event_format(JsonFormat { timestamps }).try_init()
+            }
+            (LogFormat::Text, true) => builder.with_span_events(FmtSpan::ACTIVE).try_init(),
+            (LogFormat::Text, false) => builder
+                .with_span_events(FmtSpan::ACTIVE)
+                .without_time()
+                .try_init(),
+        };
+        result.map_err(|e| anyhow!("Failed to set up logging: {e}"))?;
+
+        Ok(guard)
+    }
+}
+
+/// A file which is moved aside once it reaches a maximum size, like
+/// `logrotate`. When `demystify.trace` is full it becomes
+/// `demystify.trace.1`, the old `demystify.trace.1` becomes
+/// `demystify.trace.2`, and so on, keeping at most `keep` old files.
+pub struct RotatingFile {
+    path: PathBuf,
+    max_size: Option<u64>,
+    keep: usize,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    /// Creates (or truncates) `path`. With no `max_size`, this is just a file.
+    pub fn create(path: &Path, max_size: Option<u64>, keep: usize) -> io::Result<Self> {
+        Ok(Self {
+            path: path.to_owned(),
+            max_size,
+            keep,
+            file: File::create(path)?,
+            size: 0,
+        })
+    }
+
+    /// The name of the `n`th oldest file kept.
+    #[must_use]
+    pub fn rotated_path(path: &Path, n: usize) -> PathBuf {
+        let mut name = OsString::from(path);
+        name.push(format!(".{n}"));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        for n in (1..self.keep).rev() {
+            let from = Self::rotated_path(&self.path, n);
+            if from.exists() {
+                std::fs::rename(from, Self::rotated_path(&self.path, n + 1))?;
+            }
+        }
+        if self.keep > 0 {
+            std::fs::rename(&self.path, Self::rotated_path(&self.path, 1))?;
+        }
+        self.file = File::create(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self
+            .max_size
+            .is_some_and(|max_size| self.size > 0 && self.size + buf.len() as u64 > max_size)
+        {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Writes each event as a JSON object on one line, with the `level`,
+/// `target`, the names of the enclosing `spans` and the event's `fields`.
+struct JsonFormat {
+    timestamps: bool,
+}
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> std::fmt::Result {
+        let mut fields = JsonFields::default();
+        event.record(&mut fields);
+
+        let mut line = serde_json::Map::new();
+        if self.timestamps {
+            let time = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default();
+            line.insert("time".to_owned(), time.as_secs_f64().into());
+        }
+        let metadata = event.metadata();
+        line.insert("level".to_owned(), metadata.level().as_str().into());
+        line.insert("target".to_owned(), metadata.target().into());
+        if let Some(scope) = ctx.event_scope() {
+            let spans: Vec<serde_json::Value> =
+                scope.from_root().map(|span| span.name().into()).collect();
+            line.insert("spans".to_owned(), spans.into());
+        }
+        line.insert("fields".to_owned(), fields.0.into());
+
+        writeln!(writer, "{}", serde_json::Value::Object(line))
+    }
+}
+
+#[derive(Default)]
+struct JsonFields(serde_json::Map<String, serde_json::Value>);
+
+impl Visit for JsonFields {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        let mut text = String::new();
+        let _ = write!(text, "{value:?}");
+        self.0.insert(field.name().to_owned(), text.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_owned(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_owned(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_owned(), value.into());
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_owned(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_owned(), value.into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[test]
+    fn test_rotating_file() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("demystify.trace");
+        let mut file = RotatingFile::create(&path, Some(10), 2)?;
+
+        for line in ["aaaaaa\n", "bbbbbb\n", "cccccc\n", "dddddd\n"] {
+            file.write_all(line.as_bytes())?;
+        }
+        file.flush()?;
+
+        let read = |n| std::fs::read_to_string(RotatingFile::rotated_path(&path, n));
+        assert_eq!(std::fs::read_to_string(&path)?, "dddddd\n");
+        assert_eq!(read(1)?, "cccccc\n");
+        assert_eq!(read(2)?, "bbbbbb\n");
+        assert!(read(3).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_format() {
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .event_format(JsonFormat { timestamps: false })
+            .with_writer(move || SharedBuffer(writer.clone()))
+            .finish();
+
+        tracing::subscriber::with_default(subscriber, || {
+            let _span = tracing::info_span!("solve").entered();
+            tracing::info!(target: "solver", size = 3, "found a mus");
+        });
+
+        let output = String::from_utf8(buffer.lock().unwrap().clone()).unwrap();
+        let line: serde_json::Value = serde_json::from_str(output.trim()).unwrap();
+        assert_eq!(
+            line,
+            serde_json::json!({
+                "level": "INFO",
+                "target": "solver",
+                "spans": ["solve"],
+                "fields": {"message": "found a mus", "size": 3},
+            })
+        );
+    }
+
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! A small thread-safe least recently used cache.
+//!
+//! Lookups only take a read lock, so many threads rendering the same puzzle
+//! do not wait on each other once the cache is warm.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::Hash,
+    sync::{
+        RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+/// Maps keys to values computed on first use, holding at most `capacity`
+/// entries. When it is full, the least recently used half is forgotten.
+pub struct LruCache<K, V> {
+    capacity: usize,
+    clock: AtomicU64,
+    entries: RwLock<HashMap<K, (V, AtomicU64)>>,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> LruCache<K, V> {
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "An LRU cache must hold at least one entry");
+        LruCache {
+            capacity,
+            clock: AtomicU64::new(0),
+            entries: RwLock::new(HashMap::new()),
+        }
+    }
+
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.read().unwrap().len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The value for `key`, calling `make` to work it out if it is not
+    /// cached. `make` is called without holding any lock, so two threads
+    /// may both work out the same value.
+    pub fn get_or_insert_with(&self, key: &K, make: impl FnOnce() -> V) -> V {
+        let now = self.clock.fetch_add(1, Ordering::Relaxed);
+        if let Some((value, used)) = self.entries.read().unwrap().get(key) {
+            used.store(now, Ordering::Relaxed);
+            return value.clone();
+        }
+
+        let value = make();
+        let mut entries = self.entries.write().unwrap();
+        if entries.len() >= self.capacity {
+            let mut ages: Vec<u64> = entries
+                .values()
+                .map(|(_, used)| used.load(Ordering::Relaxed))
+                .collect();
+            let half = ages.len() / 2;
+            let (_, &mut oldest_kept, _) = ages.select_nth_unstable(half);
+            entries.retain(|_, (_, used)| used.load(Ordering::Relaxed) >= oldest_kept);
+        }
+        entries.insert(key.clone(), (value.clone(), AtomicU64::new(now)));
+        value
+    }
+
+    pub fn clear(&self) {
+        self.entries.write().unwrap().clear();
+    }
+}
+
+/// A copy starts empty, as the cached values may not hold for whatever the
+/// copy is changed into.
+impl<K: Hash + Eq + Clone, V: Clone> Clone for LruCache<K, V> {
+    fn clone(&self) -> Self {
+        LruCache::new(self.capacity)
+    }
+}
+
+impl<K, V> fmt::Debug for LruCache<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LruCache")
+            .field("capacity", &self.capacity)
+            .field("len", &self.entries.read().unwrap().len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    #[test]
+    fn test_lru_cache() {
+        let cache = LruCache::new(4);
+        let calls = Cell::new(0);
+        let square = |k: &i32| {
+            calls.set(calls.get() + 1);
+            k * k
+        };
+
+        for k in 0..4 {
+            assert_eq!(cache.get_or_insert_with(&k, || square(&k)), k * k);
+        }
+        assert_eq!(calls.get(), 4);
+        assert_eq!(cache.len(), 4);
+
+        // Cached values are not worked out again
+        assert_eq!(cache.get_or_insert_with(&0, || square(&0)), 0);
+        assert_eq!(cache.get_or_insert_with(&1, || square(&1)), 1);
+        assert_eq!(calls.get(), 4);
+
+        // Adding to a full cache forgets the least recently used half, which
+        // is 2 and 3 as 0 and 1 were just looked up
+        assert_eq!(cache.get_or_insert_with(&4, || square(&4)), 16);
+        assert_eq!(calls.get(), 5);
+        assert_eq!(cache.len(), 3);
+        cache.get_or_insert_with(&0, || square(&0));
+        cache.get_or_insert_with(&1, || square(&1));
+        assert_eq!(calls.get(), 5);
+        cache.get_or_insert_with(&2, || square(&2));
+        assert_eq!(calls.get(), 6);
+
+        let copy = cache.clone();
+        assert!(copy.is_empty());
+        assert_eq!(copy.capacity(), 4);
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,83 @@
+//! The memory used by this process, so memory regressions show up in the
+//! statistics of a run alongside the time it took.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// The resident set size of the process, in kilobytes, as read from
+/// `/proc/self/status`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MemoryUsage {
+    /// What is resident now (`VmRSS`)
+    pub current_kb: u64,
+    /// The most that has been resident at once (`VmHWM`)
+    pub peak_kb: u64,
+}
+
+impl MemoryUsage {
+    /// The memory used now, or `None` where there is no `/proc`, such as on
+    /// macOS and Windows.
+    #[must_use]
+    pub fn sample() -> Option<Self> {
+        Self::from_status(&std::fs::read_to_string("/proc/self/status").ok()?)
+    }
+
+    /// Reads the `VmRSS` and `VmHWM` lines of a `/proc/<pid>/status` file.
+    fn from_status(status: &str) -> Option<Self> {
+        let field = |name: &str| -> Option<u64> {
+            let line = status
+                .lines()
+                .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))?;
+            line.trim().strip_suffix("kB")?.trim().parse().ok()
+        };
+        Some(Self {
+            current_kb: field("VmRSS")?,
+            peak_kb: field("VmHWM")?,
+        })
+    }
+}
+
+/// Kilobytes as megabytes, to one decimal place.
+#[must_use]
+pub fn format_kb(kb: u64) -> String {
+    format!("{:.1}MB", kb as f64 / 1024.0)
+}
+
+impl fmt::Display for MemoryUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} resident, {} at peak",
+            format_kb(self.current_kb),
+            format_kb(self.peak_kb)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_usage() {
+        let status =
+            "Name:\tdemystify\nVmPeak:\t  900000 kB\nVmHWM:\t    20480 kB\nVmRSS:\t    10240 kB\n";
+        let usage = MemoryUsage::from_status(status).unwrap();
+        assert_eq!(
+            usage,
+            MemoryUsage {
+                current_kb: 10240,
+                peak_kb: 20480
+            }
+        );
+        assert_eq!(usage.to_string(), "10.0MB resident, 20.0MB at peak");
+        assert_eq!(MemoryUsage::from_status("Name:\tdemystify\n"), None);
+
+        if cfg!(target_os = "linux") {
+            let usage = MemoryUsage::sample().unwrap();
+            assert!(usage.current_kb > 0);
+            assert!(usage.peak_kb >= usage.current_kb);
+        }
+    }
+}
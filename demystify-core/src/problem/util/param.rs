@@ -0,0 +1,427 @@
+//! Reads the simple Essence parameter files most puzzles use, without
+//! running conjure, so they load quickly (and can be read where no external
+//! programs can be run, such as in WASM).
+//!
+//! Only `letting <name> be <value>` is understood, where a value is an int,
+//! a bool, or a matrix of values, optionally with an `int(a..b)` index
+//! domain. Anything else is an error, and the caller should fall back to
+//! `conjure pretty`. The result is the same JSON conjure produces, where a
+//! matrix is an object from its indices to its entries.
+//!
+//! Essence has no null, so an empty cell is written as a value the model
+//! treats as blank (such as 0 in a sudoku).
+//!
+//! [`MergedParams`] combines several parameter files, and single values
+//! given on the command line, and [`write_essence_param`] writes the result
+//! back out for savilerow.
+
+use std::{collections::BTreeMap, fmt};
+
+use anyhow::{Context, bail};
+use serde_json::Value;
+
+/// Parses the parameters in `text`, see the [module documentation](self).
+pub fn parse_essence_param(text: &str) -> anyhow::Result<BTreeMap<String, Value>> {
+    let mut parser = Parser {
+        tokens: tokenize(text)?,
+        pos: 0,
+    };
+
+    let mut params = BTreeMap::new();
+    while parser.pos < parser.tokens.len() {
+        parser.expect("letting")?;
+        let (name, line) = parser.next()?;
+        if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            bail!("Expected a name on line {line}, found '{name}'");
+        }
+        parser.expect("be")?;
+        let value = parser.value()?;
+        if params.insert(name.to_owned(), value).is_some() {
+            bail!("'{name}' is given twice, the second time on line {line}");
+        }
+    }
+
+    Ok(params)
+}
+
+/// Parses a single value, such as `6` or `[[1, 0], [0, 1]]`, in the same
+/// form as in a parameter file, or as JSON.
+pub fn parse_essence_value(text: &str) -> anyhow::Result<Value> {
+    match parse_essence_param(&format!("letting x be {text}")) {
+        Ok(mut params) => Ok(params.remove("x").unwrap()),
+        Err(e) => serde_json::from_str(text).map_err(|_| e),
+    }
+}
+
+/// Writes `params` as an Essence parameter file, which
+/// [`parse_essence_param`] reads back. Only ints, bools and matrices can be
+/// written.
+pub fn write_essence_param(params: &BTreeMap<String, Value>) -> anyhow::Result<String> {
+    let mut text = String::new();
+    for (name, value) in params {
+        let value = essence_value(value)
+            .with_context(|| format!("Cannot write '{name}' as an Essence parameter"))?;
+        text.push_str(&format!("letting {name} be {value}\n"));
+    }
+    Ok(text)
+}
+
+fn essence_value(value: &Value) -> anyhow::Result<String> {
+    match value {
+        Value::Bool(b) => Ok(b.to_string()),
+        Value::Number(n) => n
+            .as_i64()
+            .map(|i| i.to_string())
+            .with_context(|| format!("{n} is not an int")),
+        Value::Object(entries) => {
+            let mut entries = entries
+                .iter()
+                .map(|(index, entry)| {
+                    let index: i64 = index
+                        .parse()
+                        .with_context(|| format!("The matrix index '{index}' is not an int"))?;
+                    Ok((index, entry))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            entries.sort_by_key(|&(index, _)| index);
+            let (Some(&(first, _)), Some(&(last, _))) = (entries.first(), entries.last()) else {
+                bail!("Empty matrix");
+            };
+            if usize::try_from(last - first + 1).ok() != Some(entries.len()) {
+                bail!("The matrix indices {first}..{last} have gaps");
+            }
+            let entries = entries
+                .iter()
+                .map(|(_, entry)| essence_value(entry))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            Ok(format!("[{}; int({first}..{last})]", entries.join(", ")))
+        }
+        other => bail!("{other} is not an int, a bool or a matrix"),
+    }
+}
+
+/// A parameter given by more than one source, with different values.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParamConflict {
+    pub name: String,
+    /// The source whose value was replaced
+    pub replaced: String,
+    /// The source whose value is used
+    pub winner: String,
+}
+
+impl fmt::Display for ParamConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "'{}' from {} is overridden by {}",
+            self.name, self.replaced, self.winner
+        )
+    }
+}
+
+/// Parameters from several sources, merged in order so later sources
+/// override earlier ones.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MergedParams {
+    pub values: BTreeMap<String, Value>,
+    /// The source of each value
+    pub sources: BTreeMap<String, String>,
+    /// Every value which was replaced by a different one
+    pub conflicts: Vec<ParamConflict>,
+}
+
+impl MergedParams {
+    /// Adds `params`, read from `source`.
+    pub fn add(&mut self, source: &str, params: BTreeMap<String, Value>) {
+        for (name, value) in params {
+            if let Some(old) = self.values.get(&name)
+                && *old != value
+            {
+                self.conflicts.push(ParamConflict {
+                    name: name.clone(),
+                    replaced: self.sources[&name].clone(),
+                    winner: source.to_owned(),
+                });
+            }
+            self.sources.insert(name.clone(), source.to_owned());
+            self.values.insert(name, value);
+        }
+    }
+
+    /// Adds a single parameter given as `name=value`, see
+    /// [`parse_essence_value`].
+    pub fn set(&mut self, assignment: &str) -> anyhow::Result<()> {
+        let Some((name, value)) = assignment.split_once('=') else {
+            bail!("Expected a parameter as name=value, got '{assignment}'");
+        };
+        let name = name.trim();
+        if !name.starts_with(|c: char| c.is_ascii_alphabetic())
+            || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            bail!("'{name}' is not a parameter name");
+        }
+        let value = parse_essence_value(value)
+            .with_context(|| format!("Cannot read the value of '{name}'"))?;
+        self.add(
+            &format!("--let {name}"),
+            BTreeMap::from([(name.to_owned(), value)]),
+        );
+        Ok(())
+    }
+}
+
+/// Splits `text` into names, numbers and symbols, each with its line number.
+/// Comments and the `language` line are skipped.
+fn tokenize(text: &str) -> anyhow::Result<Vec<(&str, usize)>> {
+    let mut tokens = vec![];
+
+    for (num, line) in text.lines().enumerate() {
+        let num = num + 1;
+        let line = line.split('$').next().unwrap();
+        if line.trim_start().starts_with("language ") {
+            continue;
+        }
+
+        let mut rest = line.trim_start();
+        while !rest.is_empty() {
+            let len = if rest.starts_with("..") {
+                2
+            } else if rest.starts_with(['[', ']', ',', ';', '(', ')', '-']) {
+                1
+            } else {
+                let len = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                if len == 0 {
+                    bail!(
+                        "Unsupported character '{}' on line {num}",
+                        rest.chars().next().unwrap()
+                    );
+                }
+                len
+            };
+            tokens.push((&rest[..len], num));
+            rest = rest[len..].trim_start();
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<(&'a str, usize)>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn next(&mut self) -> anyhow::Result<(&'a str, usize)> {
+        let token = *self
+            .tokens
+            .get(self.pos)
+            .context("Unexpected end of parameters")?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).map(|&(t, _)| t)
+    }
+
+    fn expect(&mut self, expected: &str) -> anyhow::Result<()> {
+        let (token, line) = self.next()?;
+        if token != expected {
+            bail!("Expected '{expected}' on line {line}, found '{token}'");
+        }
+        Ok(())
+    }
+
+    fn int(&mut self) -> anyhow::Result<i64> {
+        let (token, line) = self.next()?;
+        if token == "-" {
+            return Ok(-self.int()?);
+        }
+        token
+            .parse()
+            .with_context(|| format!("Expected an int on line {line}, found '{token}'"))
+    }
+
+    fn value(&mut self) -> anyhow::Result<Value> {
+        match self.peek() {
+            Some("true") => {
+                self.pos += 1;
+                Ok(Value::Bool(true))
+            }
+            Some("false") => {
+                self.pos += 1;
+                Ok(Value::Bool(false))
+            }
+            Some("[") => self.matrix(),
+            _ => Ok(self.int()?.into()),
+        }
+    }
+
+    fn matrix(&mut self) -> anyhow::Result<Value> {
+        let (_, line) = self.next()?;
+
+        let mut entries = vec![];
+        while !matches!(self.peek(), Some("]" | ";")) {
+            entries.push(self.value()?);
+            // Essence allows a comma after the last entry
+            if !matches!(self.peek(), Some("]" | ";")) {
+                self.expect(",")?;
+            }
+        }
+        if entries.is_empty() {
+            bail!("Empty matrix on line {line}");
+        }
+
+        let mut first = 1;
+        if self.peek() == Some(";") {
+            self.pos += 1;
+            self.expect("int")?;
+            self.expect("(")?;
+            first = self.int()?;
+            self.expect("..")?;
+            let last = self.int()?;
+            self.expect(")")?;
+            if usize::try_from(last - first + 1).ok() != Some(entries.len()) {
+                bail!(
+                    "The matrix starting on line {line} has {} entries, but is indexed by int({first}..{last})",
+                    entries.len()
+                );
+            }
+        }
+        self.expect("]")?;
+
+        Ok(Value::Object(
+            (first..)
+                .zip(entries)
+                .map(|(i, entry)| (i.to_string(), entry))
+                .collect(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_essence_param() {
+        let params = parse_essence_param(
+            "language ESSENCE' 1.0\n\
+             $ A comment, with [brackets]\n\
+             letting n be 4 $ another\n\
+             letting b be false\n\
+             letting neg be - 3\n\
+             letting grid be\n[\n  [1, 0],\n  [-2, 3,],\n]\n\
+             letting shifted be [true, false; int(0..1)]\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            Value::Object(params.into_iter().collect()),
+            json!({
+                "n": 4,
+                "b": false,
+                "neg": -3,
+                "grid": {"1": {"1": 1, "2": 0}, "2": {"1": -2, "2": 3}},
+                "shifted": {"0": true, "1": false},
+            })
+        );
+    }
+
+    #[test]
+    fn test_write_essence_param() {
+        let text = "letting b be false\n\
+                    letting grid be [[1, -2; int(1..2)], [0, 3; int(1..2)]; int(1..2)]\n\
+                    letting n be 4\n\
+                    letting shifted be [true, false; int(0..1)]\n";
+        let params = parse_essence_param(text).unwrap();
+        assert_eq!(write_essence_param(&params).unwrap(), text);
+
+        // Matrix indices are ordered as numbers, not strings
+        let long: BTreeMap<String, Value> = BTreeMap::from([(
+            "l".to_owned(),
+            json!({"1": 1, "2": 2, "10": 10, "3": 3, "4": 4, "5": 5, "6": 6, "7": 7, "8": 8, "9": 9}),
+        )]);
+        assert_eq!(
+            write_essence_param(&long).unwrap(),
+            "letting l be [1, 2, 3, 4, 5, 6, 7, 8, 9, 10; int(1..10)]\n"
+        );
+
+        for value in [
+            json!([1, 2]),
+            json!({"1": 1, "3": 3}),
+            json!(1.5),
+            json!({}),
+        ] {
+            let params = BTreeMap::from([("x".to_owned(), value)]);
+            assert!(write_essence_param(&params).is_err());
+        }
+    }
+
+    #[test]
+    fn test_merged_params() {
+        let mut merged = MergedParams::default();
+        merged.add(
+            "base.param",
+            parse_essence_param("letting n be 4\nletting b be true").unwrap(),
+        );
+        merged.add(
+            "override.param",
+            parse_essence_param("letting n be 6\nletting b be true").unwrap(),
+        );
+        merged.set("grid=[[1, 0], [0, 1]]").unwrap();
+        merged.set("n = 9").unwrap();
+        merged.set(r#"j={"1": 2}"#).unwrap();
+
+        assert_eq!(
+            Value::Object(merged.values.clone().into_iter().collect()),
+            json!({
+                "n": 9,
+                "b": true,
+                "grid": {"1": {"1": 1, "2": 0}, "2": {"1": 0, "2": 1}},
+                "j": {"1": 2},
+            })
+        );
+        assert_eq!(merged.sources["b"], "override.param");
+        assert_eq!(merged.sources["n"], "--let n");
+        // b has the same value in both files, so is not a conflict
+        assert_eq!(
+            merged
+                .conflicts
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec![
+                "'n' from base.param is overridden by override.param",
+                "'n' from override.param is overridden by --let n",
+            ]
+        );
+
+        assert!(merged.set("n").is_err());
+        assert!(merged.set("1n=2").is_err());
+        assert!(merged.set("n=[1, 2").is_err());
+    }
+
+    #[test]
+    fn test_parse_essence_param_errors() {
+        for (text, error) in [
+            ("letting s be {1, 2}", "Unsupported character '{'"),
+            ("letting x be 1 + 2", "Unsupported character '+'"),
+            ("letting x be [1, 2", "Unexpected end"),
+            ("letting x be [1 2]", "Expected ','"),
+            ("letting x be []", "Empty matrix"),
+            ("letting x be [1, 2; int(1..3)]", "indexed by int(1..3)"),
+            ("letting x be 1\nletting x be 2", "'x' is given twice"),
+            ("given x\nletting y be 1", "Expected 'letting' on line 1"),
+        ] {
+            let err = parse_essence_param(text).unwrap_err();
+            assert!(format!("{err:#}").contains(error), "{text}: {err:#}");
+        }
+    }
+}
@@ -53,6 +53,16 @@ pub fn parse_savile_row_name(n: &str) -> anyhow::Result<Option<PuzVar>> {
     Ok(Some(PuzVar::new(&name, indices)))
 }
 
+/// Is `n` one of the auxiliary variables savilerow adds, such as `aux12`?
+/// These are skipped when reading DIMACS, but a model's own variables may
+/// start with `aux`, as long as it is not followed only by digits.
+#[must_use]
+pub fn is_savile_row_aux(n: &str) -> bool {
+    let (name, _) = split_savile_row_name(n);
+    name.strip_prefix("aux")
+        .is_some_and(|num| !num.is_empty() && num.chars().all(|c| c.is_ascii_digit()))
+}
+
 pub fn parse_constraint_name(
     template: &str,
     params: &BTreeMap<String, serde_json::value::Value>,
@@ -65,6 +75,81 @@ pub fn parse_constraint_name(
         .context("Could not parse description of variable or constraint")
 }
 
+/// Finds the Essence\' expressions which mention each of `names`, so a `$#CON`
+/// can be shown alongside the constraints it switches on.
+///
+/// Comments are removed, then each `such that` block is split into its
+/// top-level, comma separated constraints. Every constraint which mentions a
+/// name (as a whole word) is included, with whitespace collapsed, joined by
+/// `",\n"`. Names which are never mentioned are left out.
+#[must_use]
+pub fn constraint_expressions<'a>(
+    eprime: &str,
+    names: impl IntoIterator<Item = &'a String>,
+) -> BTreeMap<String, String> {
+    let declaration = regex::Regex::new(
+        r"^\s*(find|letting|given|such\s+that|minimising|maximising|branching|language)\b",
+    )
+    .unwrap();
+
+    let mut in_such_that = false;
+    let mut blocks: Vec<String> = vec![];
+
+    for line in eprime.lines() {
+        let line = line.split('$').next().unwrap_or_default();
+        if let Some(m) = declaration.find(line) {
+            in_such_that = m.as_str().trim_start().starts_with("such");
+            if in_such_that {
+                blocks.push(line[m.end()..].to_string());
+            }
+            continue;
+        }
+        if in_such_that && let Some(block) = blocks.last_mut() {
+            block.push('\n');
+            block.push_str(line);
+        }
+    }
+
+    let mut constraints: Vec<String> = vec![];
+    for block in &blocks {
+        let mut depth = 0i64;
+        let mut current = String::new();
+        for c in block.chars() {
+            match c {
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth -= 1,
+                _ => {}
+            }
+            if c == ',' && depth == 0 {
+                constraints.push(std::mem::take(&mut current));
+            } else {
+                current.push(c);
+            }
+        }
+        constraints.push(current);
+    }
+
+    let constraints: Vec<String> = constraints
+        .iter()
+        .map(|c| c.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|c| !c.is_empty())
+        .collect();
+
+    let mut out = BTreeMap::new();
+    for name in names {
+        let word = regex::Regex::new(&format!(r"\b{}\b", regex::escape(name))).unwrap();
+        let matching: Vec<&str> = constraints
+            .iter()
+            .filter(|c| word.is_match(c))
+            .map(String::as_str)
+            .collect();
+        if !matching.is_empty() {
+            out.insert(name.clone(), matching.join(",\n"));
+        }
+    }
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use crate::problem::parse::PuzzleParse;
@@ -114,6 +199,12 @@ mod tests {
         let expectedcon = Some(PuzVar::new("con1", vec![]));
         assert_eq!(parse_savile_row_name(ncon).unwrap(), expectedcon);
 
+        assert!(is_savile_row_aux("aux12"));
+        assert!(is_savile_row_aux("aux12_00001"));
+        assert!(!is_savile_row_aux("aux"));
+        assert!(!is_savile_row_aux("auxiliary_00001"));
+        assert!(!is_savile_row_aux("aux1x"));
+
         let ne = "var3x";
         assert_eq!(
             parse_savile_row_name(ne)?,
@@ -153,4 +244,31 @@ mod tests {
             expected
         );
     }
+
+    #[test]
+    fn test_constraint_expressions() {
+        let eprime = "language ESSENCE' 1.0
+$#CON row \"rows\"
+find row: matrix indexed by [int(1..2)] of bool
+find grid: matrix indexed by [int(1..2)] of int(1..2)
+such that
+    $ a comment mentioning row, which is ignored
+    forAll i: int(1..2).
+        row[i] -> allDiff(grid[i, ..]),
+    grid[1] = 1,
+    row_extra = 1
+";
+        let names = ["row".to_string(), "grid".to_string(), "col".to_string()];
+        let exprs = constraint_expressions(eprime, &names);
+
+        assert_eq!(
+            exprs.get("row").unwrap(),
+            "forAll i: int(1..2). row[i] -> allDiff(grid[i, ..])"
+        );
+        assert_eq!(
+            exprs.get("grid").unwrap(),
+            "forAll i: int(1..2). row[i] -> allDiff(grid[i, ..]),\ngrid[1] = 1"
+        );
+        assert!(!exprs.contains_key("col"));
+    }
 }
@@ -0,0 +1,56 @@
+//! The SAT solver behind [`SatCore`](super::SatCore). This is batsat, which
+//! is pure Rust, unless the `glucose` feature is turned on.
+
+#[cfg(feature = "glucose")]
+pub type Solver = rustsat_glucose::core::Glucose;
+
+#[cfg(not(feature = "glucose"))]
+pub type Solver = rustsat_batsat::Solver<ConflictLimit>;
+
+/// Stops `solver` after `limit` conflicts, or never if `limit` is negative.
+#[cfg(feature = "glucose")]
+pub fn set_conflict_limit(solver: &mut Solver, limit: i64) {
+    solver.set_limit(rustsat_glucose::Limit::Conflicts(limit));
+}
+
+/// Stops `solver` after `limit` conflicts, or never if `limit` is negative.
+#[cfg(not(feature = "glucose"))]
+pub fn set_conflict_limit(solver: &mut Solver, limit: i64) {
+    solver.batsat_mut().cb_mut().limit = limit;
+}
+
+/// Batsat has no conflict budget of its own, so this counts the clauses it
+/// learns (one per conflict) and asks it to stop once there are too many.
+#[cfg(not(feature = "glucose"))]
+#[derive(Debug)]
+pub struct ConflictLimit {
+    conflicts: i64,
+    limit: i64,
+}
+
+#[cfg(not(feature = "glucose"))]
+impl Default for ConflictLimit {
+    fn default() -> Self {
+        ConflictLimit {
+            conflicts: 0,
+            limit: -1,
+        }
+    }
+}
+
+#[cfg(not(feature = "glucose"))]
+impl batsat::Callbacks for ConflictLimit {
+    fn on_start(&mut self) {
+        self.conflicts = 0;
+    }
+
+    fn on_new_clause(&mut self, _clause: &[batsat::Lit], kind: batsat::ClauseKind) {
+        if kind == batsat::ClauseKind::Learnt {
+            self.conflicts += 1;
+        }
+    }
+
+    fn stop(&self) -> bool {
+        self.limit >= 0 && self.conflicts >= self.limit
+    }
+}
@@ -0,0 +1,872 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use itertools::Itertools;
+use rand::seq::SliceRandom;
+use rustsat::instances::Cnf;
+use rustsat::solvers::{Solve, SolveIncremental, SolverResult};
+use rustsat::types::{Assignment, Lit};
+use tracing::info;
+
+use std::sync::atomic::Ordering::Relaxed;
+
+mod backend;
+
+pub use backend::Solver;
+
+/// Represents a SAT solver core.
+/// The public interface to the solver is stateless.
+/// Internally, we fix some values in the solver (represented by the)
+/// 'fixed' set. Whenever we need to remove values from this set,
+/// we restart the solver. This is not externally visible.
+pub struct SatCore {
+    pub solver: Arc<Mutex<Solver>>,
+    pub cnf: Arc<Cnf>,
+    pub fixed: RefCell<HashSet<Lit>>,
+    /// How often each literal has been part of a MUS found by
+    /// [`SatCore::quick_mus`], used by [`ShrinkOrder::Activity`]
+    activity: RefCell<HashMap<Lit, u32>>,
+}
+
+// Solvers can sometimes time out, so we add a conflict limit.
+// We also set a 'counter', which checks if the solver is frequently hitting it's limit, if so
+// we increase the limit
+static CONFLICT_LIMIT: std::sync::atomic::AtomicI64 = std::sync::atomic::AtomicI64::new(1000);
+static CONFLICT_COUNT: std::sync::atomic::AtomicI64 = std::sync::atomic::AtomicI64::new(0);
+static SOLVER_CALLS: std::sync::atomic::AtomicI64 = std::sync::atomic::AtomicI64::new(0);
+static SOLVER_CALLS_SAVED: std::sync::atomic::AtomicI64 = std::sync::atomic::AtomicI64::new(0);
+
+/// Set the global conflict limit used for the SAT
+/// solver (0 = no limit)
+pub fn set_global_conflict_limit(val: i64) {
+    CONFLICT_LIMIT.store(val, Relaxed);
+}
+
+static MUST_SUCCEED_CEILING: std::sync::atomic::AtomicI64 = std::sync::atomic::AtomicI64::new(0);
+static MUST_SUCCEED_RETRIES: std::sync::atomic::AtomicI64 = std::sync::atomic::AtomicI64::new(0);
+
+thread_local! {
+    /// The conflict limit used instead of [`CONFLICT_LIMIT`] while
+    /// [`SatCore::must_succeed`] retries a search on this thread
+    static LIMIT_OVERRIDE: Cell<Option<i64>> = const { Cell::new(None) };
+}
+
+/// Set the highest conflict limit [`SatCore::must_succeed`] retries
+/// searches with (0 = no ceiling)
+pub fn set_must_succeed_conflict_ceiling(val: i64) {
+    MUST_SUCCEED_CEILING.store(val, Relaxed);
+}
+
+/// Get the number of times [`SatCore::must_succeed`] has retried a search
+/// with a higher limit.
+pub fn get_must_succeed_retries() -> i64 {
+    MUST_SUCCEED_RETRIES.load(Relaxed)
+}
+
+/// Get the number of solver calls made.
+///
+/// # Returns
+///
+/// The number of solver calls.
+pub fn get_solver_calls() -> i64 {
+    SOLVER_CALLS.load(Relaxed)
+}
+
+/// Record that `count` solver calls were avoided, by reusing the
+/// result of an earlier call.
+pub fn record_solver_calls_saved(count: usize) {
+    SOLVER_CALLS_SAVED.fetch_add(count as i64, Relaxed);
+}
+
+/// Get the number of solver calls avoided by reusing earlier results.
+///
+/// # Returns
+///
+/// The number of solver calls saved.
+pub fn get_solver_calls_saved() -> i64 {
+    SOLVER_CALLS_SAVED.load(Relaxed)
+}
+
+static SHRINK_CALLS: std::sync::atomic::AtomicI64 = std::sync::atomic::AtomicI64::new(0);
+static SHRINK_INITIAL_SIZE: std::sync::atomic::AtomicI64 = std::sync::atomic::AtomicI64::new(0);
+static SHRINK_FINAL_SIZE: std::sync::atomic::AtomicI64 = std::sync::atomic::AtomicI64::new(0);
+static SHRINK_DELETION_TESTS: std::sync::atomic::AtomicI64 = std::sync::atomic::AtomicI64::new(0);
+static SHRINK_NANOS: std::sync::atomic::AtomicI64 = std::sync::atomic::AtomicI64::new(0);
+
+/// Totals over every call of [`SatCore::quick_mus`] which found a core to
+/// shrink, for comparing [`ShrinkOrder`]s.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ShrinkStats {
+    pub calls: i64,
+    /// The sum of the sizes of the first cores found
+    pub initial_core_size: i64,
+    /// The sum of the sizes of the cores returned
+    pub final_size: i64,
+    /// The number of solver calls made while trying to delete literals
+    pub deletion_tests: i64,
+    pub time: Duration,
+}
+
+impl ShrinkStats {
+    /// The mean number of deletion tests per call.
+    #[must_use]
+    pub fn mean_deletion_tests(&self) -> f64 {
+        self.deletion_tests as f64 / self.calls.max(1) as f64
+    }
+
+    /// The mean fraction of the first core which was removed.
+    #[must_use]
+    pub fn mean_reduction(&self) -> f64 {
+        1.0 - self.final_size as f64 / self.initial_core_size.max(1) as f64
+    }
+}
+
+impl std::fmt::Display for ShrinkStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} shrinks from {} to {} literals, with {} deletion tests ({:.1} per shrink) in {:?}",
+            self.calls,
+            self.initial_core_size,
+            self.final_size,
+            self.deletion_tests,
+            self.mean_deletion_tests(),
+            self.time
+        )
+    }
+}
+
+/// Get the statistics of all MUS shrinking done so far.
+pub fn get_shrink_stats() -> ShrinkStats {
+    ShrinkStats {
+        calls: SHRINK_CALLS.load(Relaxed),
+        initial_core_size: SHRINK_INITIAL_SIZE.load(Relaxed),
+        final_size: SHRINK_FINAL_SIZE.load(Relaxed),
+        deletion_tests: SHRINK_DELETION_TESTS.load(Relaxed),
+        time: Duration::from_nanos(SHRINK_NANOS.load(Relaxed) as u64),
+    }
+}
+
+/// Reset the statistics returned by [`get_shrink_stats`].
+pub fn reset_shrink_stats() {
+    for stat in [
+        &SHRINK_CALLS,
+        &SHRINK_INITIAL_SIZE,
+        &SHRINK_FINAL_SIZE,
+        &SHRINK_DELETION_TESTS,
+        &SHRINK_NANOS,
+    ] {
+        stat.store(0, Relaxed);
+    }
+}
+
+static QUERY_LOG_SIZE: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+static QUERY_COUNT: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+type QueryLog = Arc<Mutex<VecDeque<SatQuery>>>;
+
+/// The query log of every thread which has made a query, so they can all be
+/// read by [`take_query_log`]
+static QUERY_LOGS: Mutex<Vec<QueryLog>> = Mutex::new(Vec::new());
+
+thread_local! {
+    /// The latest queries made on this thread, while the query log is on
+    static QUERY_LOG: QueryLog = {
+        let log = QueryLog::default();
+        QUERY_LOGS.lock().unwrap().push(log.clone());
+        log
+    };
+}
+
+/// Keep the last `size` queries each thread makes of the SAT solver, to be
+/// read by [`take_query_log`] (0 = keep none, the default).
+pub fn set_query_log_size(size: usize) {
+    QUERY_LOG_SIZE.store(size, Relaxed);
+}
+
+/// Take the queries kept since [`set_query_log_size`] was called, from every
+/// thread, in the order they were made.
+pub fn take_query_log() -> Vec<SatQuery> {
+    let logs = QUERY_LOGS.lock().unwrap();
+    logs.iter()
+        .flat_map(|log| std::mem::take(&mut *log.lock().unwrap()))
+        .sorted_by_key(|query| query.number)
+        .collect()
+}
+
+/// How a [`SatQuery`] ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QueryOutcome {
+    Sat,
+    Unsat,
+    /// The conflict limit was reached
+    Interrupted,
+}
+
+impl From<&SolverResult> for QueryOutcome {
+    fn from(result: &SolverResult) -> Self {
+        match result {
+            SolverResult::Sat => QueryOutcome::Sat,
+            SolverResult::Unsat => QueryOutcome::Unsat,
+            SolverResult::Interrupted => QueryOutcome::Interrupted,
+        }
+    }
+}
+
+/// One call of the SAT solver, kept by the query log to reconstruct why an
+/// explanation came out as it did.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SatQuery {
+    /// Counts the queries of every thread, from when the program started
+    pub number: u64,
+    pub thread: String,
+    /// The literals fixed in the solver, which are the known literals
+    pub fixed: Vec<Lit>,
+    pub assumptions: Vec<Lit>,
+    pub outcome: QueryOutcome,
+    /// The assumptions which cannot all hold, if the core was asked for
+    pub core: Option<Vec<Lit>>,
+}
+
+impl SatQuery {
+    /// The query over several lines, with each literal written by
+    /// `describe_lit`, such as
+    /// [`PuzzleParse::describe_lit`](crate::problem::parse::PuzzleParse::describe_lit).
+    pub fn describe(&self, describe_lit: impl Fn(Lit) -> String) -> String {
+        let list = |lits: &[Lit]| lits.iter().map(|&l| describe_lit(l)).join(", ");
+        let mut text = format!(
+            "Query {} on {}: {:?}\n  fixed ({}): {}\n  assumed ({}): {}\n",
+            self.number,
+            self.thread,
+            self.outcome,
+            self.fixed.len(),
+            list(&self.fixed),
+            self.assumptions.len(),
+            list(&self.assumptions)
+        );
+        if let Some(core) = &self.core {
+            text += &format!("  core ({}): {}\n", core.len(), list(core));
+        }
+        text
+    }
+}
+
+/// The order in which [`SatCore::quick_mus`] tries to delete literals from
+/// the core.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ShrinkOrder {
+    /// The order the literals were given in
+    #[default]
+    InOrder,
+    /// A random order, different on each call
+    Random,
+    /// Literals which have rarely been part of earlier MUSes first, as they
+    /// are the most likely to be removable
+    Activity,
+}
+
+impl std::str::FromStr for ShrinkOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "in-order" | "inorder" => Ok(ShrinkOrder::InOrder),
+            "random" => Ok(ShrinkOrder::Random),
+            "activity" => Ok(ShrinkOrder::Activity),
+            _ => Err(format!(
+                "Invalid shrink order: {s} (expected in-order, random or activity)"
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for ShrinkOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShrinkOrder::InOrder => write!(f, "in-order"),
+            ShrinkOrder::Random => write!(f, "random"),
+            ShrinkOrder::Activity => write!(f, "activity"),
+        }
+    }
+}
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SearchError {
+    #[error("The SAT solver reached it's search limit")]
+    Limit,
+    #[error(
+        "The SAT solver could not finish within {0} conflicts, the most allowed for a search which must succeed"
+    )]
+    CeilingReached(i64),
+}
+
+pub type SearchResult<T> = std::result::Result<T, SearchError>;
+
+/// The result of [`SatCore::assumption_probe`], keeping whatever the solver
+/// produced so that it can be reused to answer other queries. In practice
+/// this is the solution, see `Prober::probe_varlits`.
+#[derive(Debug)]
+pub enum ProbeResult {
+    /// A solution satisfying the assumptions
+    Sat(Assignment),
+    /// A subset of the assumptions which cannot be satisfied together
+    Unsat(Vec<Lit>),
+}
+
+impl SatCore {
+    /// Creates a new `SatCore` instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `cnf` - The CNF formula to solve.
+    ///
+    /// # Returns
+    ///
+    /// A `SatCore` instance.
+    pub fn new(cnf: Arc<Cnf>) -> anyhow::Result<SatCore> {
+        let mut solver = Solver::default();
+        solver.add_cnf_ref(&cnf)?;
+
+        Ok(SatCore {
+            solver: Arc::new(Mutex::new(solver)),
+            cnf,
+            fixed: RefCell::new(HashSet::new()),
+            activity: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// Fix the follow list of literals. As search progresses, we often want to fix a list
+    /// of literals (the known values), but as solvers are in a threadpool, we want to
+    /// treat solvers as memoryless. Therefore, we fix values, and also reboot the solver if
+    /// we discover that we need to fix less literals than the already fixed list
+    /// (stored in fixed)
+    fn fix_values(&self, lits: &[Lit]) {
+        let mut fixed = self.fixed.borrow_mut();
+
+        {
+            let mut solver = self.solver.lock().unwrap();
+
+            for &l in lits {
+                if !fixed.contains(&l) {
+                    solver.add_unit(l).expect("FATAL: Solver bug 1");
+                    fixed.insert(l);
+                }
+            }
+        }
+
+        // As we added all 'lits' to 'fixed', if there are more things in 'fixed'
+        // something we don't want is in fixed.
+        if fixed.len() > lits.len() {
+            eprintln!("Rebooting solver");
+            let mut solver = Solver::default();
+            solver.add_cnf_ref(&self.cnf).expect("FATAL: Solver bug 2");
+            fixed.clear();
+            for &l in lits {
+                if !fixed.contains(&l) {
+                    solver.add_unit(l).expect("FATAL: Solver bug 3");
+                    fixed.insert(l);
+                }
+            }
+            let mut mutex_solver = self.solver.lock().unwrap();
+            *mutex_solver = solver;
+        }
+    }
+
+    /// Adds a query to the query log of this thread, if it is on.
+    fn log_query(&self, lits: &[Lit], solve: &SolverResult, core: Option<&[Lit]>) {
+        let size = QUERY_LOG_SIZE.load(Relaxed);
+        if size == 0 {
+            return;
+        }
+        let query = SatQuery {
+            number: QUERY_COUNT.fetch_add(1, Relaxed),
+            thread: format!("{:?}", std::thread::current().id()),
+            fixed: self.fixed.borrow().iter().copied().sorted().collect(),
+            assumptions: lits.to_vec(),
+            outcome: solve.into(),
+            core: core.map(<[Lit]>::to_vec),
+        };
+        QUERY_LOG.with(|log| {
+            let mut log = log.lock().unwrap();
+            while log.len() >= size {
+                log.pop_front();
+            }
+            log.push_back(query);
+        });
+    }
+
+    fn do_solve_assumps(solver: &mut MutexGuard<Solver>, lits: &[Lit]) -> SolverResult {
+        //let _timer = QuickTimer::new("sat".to_owned());
+        let limit_override = LIMIT_OVERRIDE.get();
+        backend::set_conflict_limit(
+            solver,
+            limit_override.unwrap_or_else(|| CONFLICT_LIMIT.load(Relaxed)),
+        );
+        SOLVER_CALLS.fetch_add(1, Relaxed);
+        let solve = solver.solve_assumps(lits).unwrap();
+        backend::set_conflict_limit(solver, -1);
+
+        // Retries are not counted, as they have their own limit
+        if matches!(solve, SolverResult::Interrupted) && limit_override.is_none() {
+            //eprintln!("SAT solver limit tripped");
+            // This code may well have some race conditions, but
+            // if we are in this situation, I don't mind if we
+            // end up increasing the limit even more than intended,
+            // as long as it is increased, and the counter reset.
+            let count = CONFLICT_COUNT.fetch_add(1, Relaxed);
+            if count > 1000 {
+                let limit = CONFLICT_LIMIT.load(Relaxed);
+                eprintln!(
+                    "Warning: The puzzle is hard to solve, increasing limits in SAT solver from {} to {}",
+                    limit,
+                    limit * 10
+                );
+                CONFLICT_LIMIT.store(CONFLICT_LIMIT.load(Relaxed) * 10, Relaxed);
+                CONFLICT_COUNT.store(0, Relaxed);
+            } else {
+                let _ = CONFLICT_COUNT.fetch_update(Relaxed, Relaxed, |count| {
+                    if count > 0 { Some(count - 1) } else { Some(0) }
+                });
+            }
+        }
+
+        solve
+    }
+
+    /// Runs `search`, which should be a call to one of the searches of this
+    /// `SatCore`, for a result which is needed to carry on at all. If it
+    /// reaches the conflict limit, it is run again with ten times the limit,
+    /// until it finishes or the limit passes the ceiling set by
+    /// [`set_must_succeed_conflict_ceiling`].
+    ///
+    /// # Errors
+    ///
+    /// [`SearchError::CeilingReached`] if the search does not finish within
+    /// the ceiling.
+    pub fn must_succeed<T>(
+        &self,
+        search: impl FnMut(&SatCore) -> SearchResult<T>,
+    ) -> SearchResult<T> {
+        self.must_succeed_from(
+            CONFLICT_LIMIT.load(Relaxed),
+            MUST_SUCCEED_CEILING.load(Relaxed),
+            search,
+        )
+    }
+
+    fn must_succeed_from<T>(
+        &self,
+        mut limit: i64,
+        ceiling: i64,
+        mut search: impl FnMut(&SatCore) -> SearchResult<T>,
+    ) -> SearchResult<T> {
+        let outer = LIMIT_OVERRIDE.get();
+        let result = loop {
+            LIMIT_OVERRIDE.set(Some(limit));
+            match search(self) {
+                Err(SearchError::Limit) if limit > 0 => {}
+                result => break result,
+            }
+            if ceiling > 0 && limit >= ceiling {
+                break Err(SearchError::CeilingReached(ceiling));
+            }
+            limit = limit.saturating_mul(10);
+            if ceiling > 0 {
+                limit = limit.min(ceiling);
+            }
+            info!(target: "solver", "Retrying search with a conflict limit of {}", limit);
+            MUST_SUCCEED_RETRIES.fetch_add(1, Relaxed);
+        };
+        LIMIT_OVERRIDE.set(outer);
+        result
+    }
+
+    /// Solves the CNF formula with the given assumption and known
+    /// values.
+    ///
+    /// # Arguments
+    ///
+    /// * `known` - Literal known to be true
+    /// * `lits` - The assumptions to use during solving.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the formula is satisfiable, `false` if it is unsatisfiable.
+    pub fn assumption_solve(&self, known: &[Lit], lits: &[Lit]) -> SearchResult<bool> {
+        self.fix_values(known);
+        let mut solver = self.solver.lock().unwrap();
+        let solve = SatCore::do_solve_assumps(&mut solver, lits);
+        self.log_query(lits, &solve, None);
+        let result = match solve {
+            rustsat::solvers::SolverResult::Sat => Ok(true),
+            rustsat::solvers::SolverResult::Unsat => Ok(false),
+            rustsat::solvers::SolverResult::Interrupted => Err(SearchError::Limit),
+        };
+        info!(target: "solver", "Solution to {:?} is {:?}", lits, result);
+        result
+    }
+
+    /// Solves the CNF formula with the given assumptions and returns the full solution.
+    ///
+    /// # Arguments
+    ///
+    /// * `known` - The known literals.
+    /// * `lits` - The assumptions to use during solving.
+    ///
+    /// # Returns
+    ///
+    /// The full solution if the formula is satisfiable, `None` if it is unsatisfiable.
+    pub fn assumption_solve_solution(
+        &self,
+        known: &[Lit],
+        lits: &[Lit],
+    ) -> SearchResult<Option<Assignment>> {
+        self.fix_values(known);
+        let mut solver = self.solver.lock().unwrap();
+        let solve = SatCore::do_solve_assumps(&mut solver, lits);
+        self.log_query(lits, &solve, None);
+        let result = match solve {
+            rustsat::solvers::SolverResult::Sat => Ok(Some(solver.full_solution().unwrap())),
+            rustsat::solvers::SolverResult::Unsat => Ok(None),
+            rustsat::solvers::SolverResult::Interrupted => Err(SearchError::Limit),
+        };
+        info!(target: "solver", "Solution to {:?} is {:?}", lits, result);
+        result
+    }
+
+    /// Solves the CNF formula with the given assumptions, returning either the
+    /// full solution or the unsatisfiable core.
+    ///
+    /// # Arguments
+    ///
+    /// * `known` - The known literals.
+    /// * `lits` - The assumptions to use during solving.
+    ///
+    /// # Returns
+    ///
+    /// The solution if the formula is satisfiable, the core if it is unsatisfiable.
+    pub fn assumption_probe(&self, known: &[Lit], lits: &[Lit]) -> SearchResult<ProbeResult> {
+        self.fix_values(known);
+        let mut solver = self.solver.lock().unwrap();
+        let solve = SatCore::do_solve_assumps(&mut solver, lits);
+        let result = match solve {
+            rustsat::solvers::SolverResult::Sat => {
+                Ok(ProbeResult::Sat(solver.full_solution().unwrap()))
+            }
+            rustsat::solvers::SolverResult::Unsat => Ok(ProbeResult::Unsat(
+                solver.core().unwrap().into_iter().map(|l| !l).collect(),
+            )),
+            rustsat::solvers::SolverResult::Interrupted => Err(SearchError::Limit),
+        };
+        let core = match &result {
+            Ok(ProbeResult::Unsat(core)) => Some(core.as_slice()),
+            _ => None,
+        };
+        self.log_query(lits, &solve, core);
+        result
+    }
+
+    /// Solves the CNF formula with the given assumptions and returns the unsatisfiable core.
+    ///
+    /// # Arguments
+    ///
+    /// * `known` - The known literals.
+    /// * `lits` - The assumptions to use during solving.
+    ///
+    /// # Returns
+    ///
+    /// The unsatisfiable core if the formula is unsatisfiable, `None` if it is satisfiable.
+    pub fn assumption_solve_with_core(
+        &self,
+        known: &[Lit],
+        lits: &[Lit],
+    ) -> SearchResult<Option<Vec<Lit>>> {
+        self.fix_values(known);
+        self.raw_assumption_solve_with_core(lits)
+    }
+
+    /// Solves the CNF formula with the given assumptions and returns the unsatisfiable core.
+    /// *Not memoryless*: Uses whatever set of values are already fixed in the solver.
+    ///
+    /// # Arguments
+    ///
+    /// * `lits` - The assumptions to use during solving.
+    ///
+    /// # Returns
+    ///
+    /// The unsatisfiable core if the formula is unsatisfiable, `None` if it is satisfiable.
+    fn raw_assumption_solve_with_core(&self, lits: &[Lit]) -> SearchResult<Option<Vec<Lit>>> {
+        let mut solver = self.solver.lock().unwrap();
+        let solve = SatCore::do_solve_assumps(&mut solver, lits);
+        let result = match solve {
+            rustsat::solvers::SolverResult::Sat => Ok(None),
+            rustsat::solvers::SolverResult::Unsat => Ok(Some(
+                solver.core().unwrap().into_iter().map(|l| !l).collect(),
+            )),
+            rustsat::solvers::SolverResult::Interrupted => Err(SearchError::Limit),
+        };
+        let core = result.as_ref().ok().and_then(Option::as_deref);
+        self.log_query(lits, &solve, core);
+        result
+    }
+
+    /// Finds a minimal unsatisfiable subset (MUS) of literals given a set of known literals.
+    ///
+    /// # Arguments
+    ///
+    /// * `known` - The known literals.
+    /// * `lits` - The set of literals to search over.
+    /// * `order` - The order to try deleting literals from the core in.
+    ///
+    /// # Returns
+    ///
+    /// The minimal unsatisfiable subset (MUS) of literals, if one exists.
+    /// Each call which finds a core is counted in [`get_shrink_stats`].
+    pub fn quick_mus(
+        &self,
+        known: &[Lit],
+        lits: &[Lit],
+        max_size: Option<i64>,
+        order: ShrinkOrder,
+    ) -> SearchResult<Option<Vec<Lit>>> {
+        self.fix_values(known);
+        let start = Instant::now();
+        let core = self.raw_assumption_solve_with_core(lits)?;
+        let Some(core) = core else {
+            return Ok(None);
+        };
+        let initial_size = core.len();
+        let mut deletion_tests = 0;
+        let result = self.shrink_core(core, lits, max_size, order, &mut deletion_tests);
+
+        let final_size = match &result {
+            Ok(Some(mus)) => {
+                let mut activity = self.activity.borrow_mut();
+                for &l in mus {
+                    *activity.entry(l).or_default() += 1;
+                }
+                mus.len()
+            }
+            _ => initial_size,
+        };
+        let time = start.elapsed();
+        info!(target: "solver", "shrunk core from {} to {} with {} deletion tests in {:?}", initial_size, final_size, deletion_tests, time);
+        SHRINK_CALLS.fetch_add(1, Relaxed);
+        SHRINK_INITIAL_SIZE.fetch_add(initial_size as i64, Relaxed);
+        SHRINK_FINAL_SIZE.fetch_add(final_size as i64, Relaxed);
+        SHRINK_DELETION_TESTS.fetch_add(deletion_tests, Relaxed);
+        SHRINK_NANOS.fetch_add(time.as_nanos() as i64, Relaxed);
+
+        result
+    }
+
+    /// Deletes literals from `core`, a core of `lits`, until it is minimal.
+    fn shrink_core(
+        &self,
+        mut core: Vec<Lit>,
+        lits: &[Lit],
+        max_size: Option<i64>,
+        order: ShrinkOrder,
+        deletion_tests: &mut i64,
+    ) -> SearchResult<Option<Vec<Lit>>> {
+        let mut known_size = 0;
+        let mut candidates = lits.to_vec();
+        match order {
+            ShrinkOrder::InOrder => {}
+            ShrinkOrder::Random => candidates.shuffle(&mut rand::rng()),
+            ShrinkOrder::Activity => {
+                let activity = self.activity.borrow();
+                candidates.sort_by_key(|l| activity.get(l).copied().unwrap_or(0));
+            }
+        }
+
+        let mut known_core = Vec::new();
+        // Need to make a copy for actually searching over
+        for lit in candidates {
+            let location = core.iter().position(|&x| x == lit);
+            if let Some(location) = location {
+                let mut check_core = core.clone();
+                check_core.remove(location);
+                *deletion_tests += 1;
+                let candidate = self.raw_assumption_solve_with_core(&check_core)?;
+                if let Some(found) = candidate {
+                    core = found;
+                } else {
+                    known_size += 1;
+                    known_core.push(lit);
+                    if let Some(max_size) = max_size {
+                        if known_size == max_size {
+                            // If there is a MUS, this has to be it!
+                            assert!(known_core.len() as i64 == max_size);
+                            let core = self.raw_assumption_solve_with_core(&known_core)?;
+                            if let Some(found) = &core {
+                                assert!(found.len() as i64 == known_size);
+                            }
+                            return Ok(core);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(Some(
+            core.into_iter().filter(|x| lits.contains(x)).collect_vec(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustsat::lit;
+
+    use super::*;
+
+    fn create_cnf() -> Arc<Cnf> {
+        let mut cnf = Cnf::new();
+        cnf.add_binary(lit![0], lit![1]);
+        cnf.add_binary(lit![0], !lit![1]);
+        Arc::new(cnf)
+    }
+
+    #[test]
+    fn test_assumption_solve_solution() -> anyhow::Result<()> {
+        let solver = SatCore::new(create_cnf())?;
+        let result = solver.assumption_solve_solution(&[], &[lit![1], lit![2]])?;
+        assert!(result.is_some());
+        let result = solver.assumption_solve_solution(&[], &[lit![0]])?;
+        assert!(result.is_some());
+        let result = solver.assumption_solve_solution(&[], &[!lit![0]])?;
+        assert!(result.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_assumption_solve_core() -> anyhow::Result<()> {
+        let solver = SatCore::new(create_cnf())?;
+        let result = solver.assumption_solve_solution(&[], &[lit![1], lit![2]])?;
+        assert!(result.is_some());
+        let result = solver.assumption_solve_solution(&[], &[lit![0]])?;
+        assert!(result.is_some());
+        let result = solver.assumption_solve_solution(&[], &[!lit![0]])?;
+        assert!(result.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_assumption_probe() -> anyhow::Result<()> {
+        let solver = SatCore::new(create_cnf())?;
+        let result = solver.assumption_probe(&[], &[lit![1], lit![2]])?;
+        match result {
+            ProbeResult::Sat(sol) => {
+                assert_eq!(sol.lit_value(lit![0]), rustsat::types::TernaryVal::True);
+            }
+            ProbeResult::Unsat(_) => panic!("Expected a solution"),
+        }
+        let result = solver.assumption_probe(&[], &[lit![2], !lit![0]])?;
+        match result {
+            ProbeResult::Sat(_) => panic!("Expected a core"),
+            ProbeResult::Unsat(core) => assert_eq!(core, vec![!lit![0]]),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_assumption_quick_mus() -> anyhow::Result<()> {
+        let solver = SatCore::new(create_cnf())?;
+        let result = solver.quick_mus(&[], &[lit![1], lit![2]], None, ShrinkOrder::InOrder)?;
+        assert!(result.is_none());
+        let result = solver.quick_mus(&[], &[lit![0]], None, ShrinkOrder::InOrder)?;
+        assert!(result.is_none());
+        let result = solver.quick_mus(&[], &[!lit![0]], None, ShrinkOrder::InOrder)?;
+        assert!(result.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_assumption_quick_mus_known() -> anyhow::Result<()> {
+        let solver = SatCore::new(create_cnf())?;
+        let result = solver.quick_mus(&[], &[lit![1], lit![2]], None, ShrinkOrder::InOrder)?;
+        assert!(result.is_none());
+        let result =
+            solver.quick_mus(&[!lit![0]], &[lit![1], lit![2]], None, ShrinkOrder::InOrder)?;
+        assert_eq!(result, Some(vec![]));
+        let result = solver.quick_mus(&[], &[lit![1], lit![2]], None, ShrinkOrder::InOrder)?;
+        assert!(result.is_none());
+
+        Ok(())
+    }
+    /// The pigeonhole problem, with one more pigeon than holes, which needs
+    /// many conflicts to show has no solution.
+    fn pigeonhole_cnf(holes: u32) -> Arc<Cnf> {
+        let pigeon = |p: u32, h: u32| lit![p * holes + h];
+        let mut cnf = Cnf::new();
+        for p in 0..=holes {
+            cnf.add_clause((0..holes).map(|h| pigeon(p, h)).collect());
+        }
+        for h in 0..holes {
+            for p in 0..=holes {
+                for q in (p + 1)..=holes {
+                    cnf.add_binary(!pigeon(p, h), !pigeon(q, h));
+                }
+            }
+        }
+        Arc::new(cnf)
+    }
+
+    #[test]
+    fn test_must_succeed() -> anyhow::Result<()> {
+        let solver = SatCore::new(pigeonhole_cnf(6))?;
+        let solve = |core: &SatCore| core.assumption_solve(&[], &[]);
+
+        LIMIT_OVERRIDE.set(Some(1));
+        assert!(matches!(solve(&solver), Err(SearchError::Limit)));
+        LIMIT_OVERRIDE.set(None);
+
+        let retries = get_must_succeed_retries();
+        assert!(!solver.must_succeed_from(1, 0, solve)?);
+        assert!(get_must_succeed_retries() > retries);
+        assert_eq!(LIMIT_OVERRIDE.get(), None);
+
+        // A new solver, as the first has learnt enough to answer quickly
+        let solver = SatCore::new(pigeonhole_cnf(6))?;
+        assert!(matches!(
+            solver.must_succeed_from(1, 5, solve),
+            Err(SearchError::CeilingReached(5))
+        ));
+        assert_eq!(LIMIT_OVERRIDE.get(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_quick_mus_shrink_orders() -> anyhow::Result<()> {
+        // Only 3 and 4 conflict
+        let mut cnf = Cnf::new();
+        cnf.add_binary(!lit![3], !lit![4]);
+        let solver = SatCore::new(Arc::new(cnf))?;
+        let lits = [lit![1], lit![2], lit![3], lit![4]];
+
+        let before = get_shrink_stats();
+        for order in [
+            ShrinkOrder::InOrder,
+            ShrinkOrder::Random,
+            ShrinkOrder::Activity,
+        ] {
+            let mut mus = solver.quick_mus(&[], &lits, None, order)?.unwrap();
+            mus.sort();
+            assert_eq!(mus, vec![lit![3], lit![4]]);
+            assert_eq!(order.to_string().parse::<ShrinkOrder>(), Ok(order));
+        }
+        let after = get_shrink_stats();
+        // Other tests may be shrinking at the same time
+        assert!(after.calls >= before.calls + 3);
+        assert!(after.final_size >= before.final_size + 6);
+        assert!(after.deletion_tests >= before.deletion_tests + 6);
+
+        assert!("backwards".parse::<ShrinkOrder>().is_err());
+        Ok(())
+    }
+}
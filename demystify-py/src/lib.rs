@@ -0,0 +1,196 @@
+//! Python bindings for demystify.
+//!
+//! ```python
+//! import demystify
+//!
+//! puzzle = demystify.Puzzle.load("sudoku.eprime", "sudoku.param")
+//! while not puzzle.is_solved():
+//!     for deduction in puzzle.step():
+//!         print(deduction["deduced"], deduction["constraints"])
+//! ```
+//!
+//! Results are returned as plain Python lists and dicts, so they can be used
+//! directly with json or pandas.
+
+use std::{collections::BTreeMap, collections::BTreeSet, path::PathBuf, sync::Arc};
+
+use demystify::problem::{
+    PuzLit,
+    parse::{self, PuzzleParse},
+    planner::{PuzzlePlanner, Step},
+    solver::PuzzleSolver,
+};
+use pyo3::{exceptions::PyRuntimeError, prelude::*};
+
+/// One deduction, which is a dict in Python.
+#[derive(Clone, Debug, PartialEq, Eq, IntoPyObject)]
+pub struct Deduction {
+    /// The literals deduced, such as `grid[1, 2]=3`
+    pub deduced: Vec<String>,
+    /// The constraints the deduction needs
+    pub constraints: Vec<String>,
+}
+
+impl Deduction {
+    fn new((lits, constraints): (BTreeSet<PuzLit>, Vec<String>)) -> Self {
+        Self {
+            deduced: lits.iter().map(ToString::to_string).collect(),
+            constraints,
+        }
+    }
+}
+
+fn to_pyerr(e: anyhow::Error) -> PyErr {
+    PyRuntimeError::new_err(format!("{e:#}"))
+}
+
+/// A puzzle being explained, one step at a time.
+#[pyclass(unsendable, module = "demystify")]
+pub struct Puzzle {
+    planner: PuzzlePlanner,
+}
+
+impl Puzzle {
+    fn new(puzzle: PuzzleParse) -> PyResult<Self> {
+        let solver = PuzzleSolver::new(Arc::new(puzzle)).map_err(to_pyerr)?;
+        Ok(Self {
+            planner: PuzzlePlanner::new(solver),
+        })
+    }
+
+    fn deductions(&self, step: &Step) -> Vec<Deduction> {
+        step.muses
+            .iter()
+            .map(|mus| Deduction::new(self.planner.mus_to_user_mus(mus)))
+            .collect()
+    }
+}
+
+#[pymethods]
+impl Puzzle {
+    /// Loads an Essence or Essence' model and its parameters. This needs
+    /// conjure and savilerow.
+    #[staticmethod]
+    pub fn load(model: PathBuf, param: PathBuf) -> PyResult<Self> {
+        Self::new(parse::parse_essence(&model, &param).map_err(to_pyerr)?)
+    }
+
+    /// Loads an Essence' model, with JSON parameters and the DIMACS savilerow
+    /// made from them, without running any external tools.
+    #[staticmethod]
+    pub fn from_dimacs(model: PathBuf, param_json: PathBuf, dimacs: PathBuf) -> PyResult<Self> {
+        Self::new(parse::parse_eprime_with_dimacs(&model, &param_json, &dimacs).map_err(to_pyerr)?)
+    }
+
+    /// Makes the easiest next step, and returns its deductions. Returns an
+    /// empty list once the puzzle is solved.
+    pub fn step(&mut self) -> Vec<Deduction> {
+        if self.is_solved() {
+            return vec![];
+        }
+        let step = self.planner.next_step();
+        self.planner.apply_step(&step);
+        self.deductions(&step)
+    }
+
+    /// The easiest way to deduce `literal`, given as the indices of a cell
+    /// followed by a value, or `None` if it cannot be deduced yet. The
+    /// deduction is only made if `apply` is true.
+    #[pyo3(signature = (literal, apply = false))]
+    pub fn hint(&mut self, literal: Vec<i64>, apply: bool) -> Option<Vec<Deduction>> {
+        let step = self.planner.next_step_for_literal(literal)?;
+        if apply {
+            self.planner.apply_step(&step);
+        }
+        Some(self.deductions(&step))
+    }
+
+    /// The number of constraints needed to deduce each literal which can be
+    /// deduced now, keyed by the literal.
+    pub fn difficulty(&mut self) -> BTreeMap<String, usize> {
+        let muses = self.planner.all_muses_with_larger();
+        let mut difficulty = BTreeMap::new();
+        for (lit, muses) in muses.muses() {
+            let Some(mus) = muses.iter().next() else {
+                continue;
+            };
+            for puzlit in self.planner.puzzle().lit_to_vars(lit) {
+                difficulty.insert(puzlit.to_string(), mus.mus_len());
+            }
+        }
+        difficulty
+    }
+
+    /// Every step needed to solve the puzzle from here, without changing it.
+    pub fn trace(&self) -> Vec<Vec<Deduction>> {
+        self.planner
+            .fork()
+            .quick_solve()
+            .into_iter()
+            .map(|step| step.into_iter().map(Deduction::new).collect())
+            .collect()
+    }
+
+    /// The literals known so far, including the clues.
+    pub fn known(&self) -> Vec<String> {
+        let puzzle = self.planner.puzzle();
+        self.planner
+            .get_all_known_lits()
+            .iter()
+            .filter(|l| puzzle.lit_is_var(l))
+            .flat_map(|l| puzzle.lit_to_vars(l))
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    pub fn is_solved(&mut self) -> bool {
+        self.planner.get_provable_varlits().is_empty()
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "demystify")]
+fn demystify_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Puzzle>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use demystify::problem::util::test_utils::sudoku_4x4_planner;
+
+    use super::*;
+
+    fn sudoku() -> Puzzle {
+        Puzzle {
+            planner: sudoku_4x4_planner(),
+        }
+    }
+
+    #[test]
+    fn test_sudoku_session() {
+        let mut puzzle = sudoku();
+        let clues = puzzle.known().len();
+
+        let trace = puzzle.trace();
+        assert!(!trace.is_empty());
+        assert_eq!(puzzle.known().len(), clues);
+
+        let difficulty = puzzle.difficulty();
+        assert!(difficulty.values().all(|&d| d >= 1));
+
+        // Cell (1,2) is 2
+        let hint = puzzle.hint(vec![1, 2, 2], false).unwrap();
+        assert!(
+            hint.iter()
+                .any(|d| d.deduced.contains(&"grid[1, 2]=2".to_owned()))
+        );
+        assert_eq!(puzzle.known().len(), clues);
+
+        while !puzzle.is_solved() {
+            assert!(!puzzle.step().is_empty());
+        }
+        assert!(puzzle.step().is_empty());
+        assert!(puzzle.known().contains(&"grid[1, 2]=2".to_owned()));
+    }
+}
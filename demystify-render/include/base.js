@@ -0,0 +1,313 @@
+// The "highlight_*" classes of an element. Values are named as in
+// VarValPair::to_css_string, which gives each value a different class, so
+// classes are only ever matched whole and never split apart.
+function highlightClasses(element) {
+  return [...element.classList].filter((c) => c.startsWith("highlight_"));
+}
+
+function applyHighlight(element) {
+  for (const className of highlightClasses(element)) {
+    for (const el of document.getElementsByClassName(className)) {
+      el.classList.add("selected");
+    }
+  }
+}
+
+function removeHighlight(element) {
+  for (const className of highlightClasses(element)) {
+    for (const el of document.getElementsByClassName(className)) {
+      el.classList.remove("selected");
+    }
+  }
+}
+
+function applyHighlightFunctions() {
+  const elements = document.getElementsByClassName("js_highlighter");
+  for (let i = 0; i < elements.length; i++) {
+    const element = elements[i];
+    element.addEventListener("mouseover", () => {
+      applyHighlight(element);
+    });
+    element.addEventListener("mouseleave", () => {
+      removeHighlight(element);
+    });
+  }
+}
+
+// Hide statements which do not mention the search text. Group headers stay
+// visible while any statement in their group matches.
+function filterStatements(list, text) {
+  text = text.toLowerCase();
+  const matching = new Set();
+  list.querySelectorAll("div[data-group]:not(.js_group_header)").forEach((el) => {
+    const haystack = [
+      el.textContent,
+      el.dataset.constraint || "",
+      el.dataset.scope || "",
+    ].join(" ").toLowerCase();
+    const matches = haystack.includes(text);
+    el.classList.toggle("filtered", !matches);
+    if (matches) {
+      matching.add(el.dataset.group);
+    }
+  });
+  list.querySelectorAll("div.js_group_header").forEach((el) => {
+    el.classList.toggle("filtered", text !== "" && !matching.has(el.dataset.group));
+  });
+}
+
+function applyStatementFunctions() {
+  document.querySelectorAll(".constraintlist").forEach((list) => {
+    // doJavascript runs after every htmx swap, so only set up each list once
+    if (list.dataset.jsReady) {
+      return;
+    }
+    list.dataset.jsReady = "true";
+    const search = list.querySelector(".js_statement_search");
+    if (search) {
+      search.addEventListener("input", () => filterStatements(list, search.value));
+    }
+    list.querySelectorAll("div.js_group_header").forEach((header) => {
+      header.addEventListener("click", () => {
+        const collapsed = header.classList.toggle("collapsed");
+        list
+          .querySelectorAll(`div[data-group="${header.dataset.group}"]:not(.js_group_header)`)
+          .forEach((el) => el.classList.toggle("collapsed", collapsed));
+      });
+    });
+  });
+}
+
+// Pan (by dragging) and zoom (with the mouse wheel or buttons) the puzzle,
+// by changing the SVG's viewBox.
+function applyZoomFunctions() {
+  document.querySelectorAll(".puzzlepane").forEach((pane) => {
+    const svg = pane.querySelector("svg.puzzle");
+    if (!svg || pane.dataset.jsReady) {
+      return;
+    }
+    pane.dataset.jsReady = "true";
+
+    const initial = svg.getAttribute("viewBox").split(/[\s,]+/).map(Number);
+    let view = initial.slice();
+    const setView = () => svg.setAttribute("viewBox", view.join(" "));
+
+    // Scale the view by 'factor', keeping the point (x, y) fixed
+    const zoom = (factor, x, y) => {
+      view = [
+        x - (x - view[0]) * factor,
+        y - (y - view[1]) * factor,
+        view[2] * factor,
+        view[3] * factor,
+      ];
+      setView();
+    };
+    const centre = () => [view[0] + view[2] / 2, view[1] + view[3] / 2];
+    const toSvg = (evt) => {
+      const r = svg.getBoundingClientRect();
+      return [
+        view[0] + ((evt.clientX - r.left) / r.width) * view[2],
+        view[1] + ((evt.clientY - r.top) / r.height) * view[3],
+      ];
+    };
+
+    pane.querySelectorAll(".js_zoom").forEach((btn) => {
+      btn.addEventListener("click", () => {
+        if (btn.dataset.zoom === "in") {
+          zoom(0.8, ...centre());
+        } else if (btn.dataset.zoom === "out") {
+          zoom(1.25, ...centre());
+        } else {
+          view = initial.slice();
+          setView();
+        }
+      });
+    });
+
+    svg.addEventListener(
+      "wheel",
+      (evt) => {
+        evt.preventDefault();
+        zoom(evt.deltaY < 0 ? 0.8 : 1.25, ...toSvg(evt));
+      },
+      { passive: false },
+    );
+
+    let drag = null;
+    let dragged = false;
+    svg.addEventListener("pointerdown", (evt) => {
+      drag = { x: evt.clientX, y: evt.clientY, view: view.slice() };
+      dragged = false;
+    });
+    svg.addEventListener("pointermove", (evt) => {
+      if (!drag) {
+        return;
+      }
+      const dx = evt.clientX - drag.x;
+      const dy = evt.clientY - drag.y;
+      // Small movements are still clicks
+      if (!dragged && Math.hypot(dx, dy) < 5) {
+        return;
+      }
+      dragged = true;
+      const r = svg.getBoundingClientRect();
+      view[0] = drag.view[0] - (dx / r.width) * view[2];
+      view[1] = drag.view[1] - (dy / r.height) * view[3];
+      setView();
+    });
+    const endDrag = () => {
+      drag = null;
+    };
+    svg.addEventListener("pointerup", endDrag);
+    svg.addEventListener("pointerleave", endDrag);
+    // Don't treat the end of a drag as clicking on a cell
+    svg.addEventListener(
+      "click",
+      (evt) => {
+        if (dragged) {
+          evt.stopPropagation();
+          dragged = false;
+        }
+      },
+      true,
+    );
+  });
+}
+
+// Right clicking on a value pins it, so the planner will not deduce it,
+// or unpins it if it is already pinned.
+function applyPinFunctions() {
+  if (typeof htmx === "undefined") {
+    return;
+  }
+  document.querySelectorAll("g.literal").forEach((lit) => {
+    if (lit.dataset.jsReady) {
+      return;
+    }
+    lit.dataset.jsReady = "true";
+    lit.addEventListener("contextmenu", (evt) => {
+      evt.preventDefault();
+      htmx.ajax("POST", "/pinLiteral", { source: lit, target: "#mainSpace" });
+    });
+  });
+}
+
+// Dragging over cells with shift held selects them, then asks what can be
+// deduced using only the constraints which touch the selected cells.
+function applyRegionFunctions() {
+  if (typeof htmx === "undefined") {
+    return;
+  }
+  document.querySelectorAll("svg.puzzle").forEach((svg) => {
+    if (svg.dataset.regionReady) {
+      return;
+    }
+    svg.dataset.regionReady = "true";
+
+    let selected = null;
+    let justSelected = false;
+    const selectAt = (evt) => {
+      const el = document.elementFromPoint(evt.clientX, evt.clientY);
+      const cell = el && el.closest('g[id^="C_"]');
+      if (cell && svg.contains(cell) && !selected.has(cell.id)) {
+        selected.add(cell.id);
+        cell.classList.add("regionselected");
+      }
+    };
+
+    // These run before the listeners which pan the puzzle, and stop them
+    svg.addEventListener(
+      "pointerdown",
+      (evt) => {
+        if (!evt.shiftKey) {
+          return;
+        }
+        evt.preventDefault();
+        evt.stopPropagation();
+        selected = new Set();
+        selectAt(evt);
+      },
+      true,
+    );
+    svg.addEventListener(
+      "pointermove",
+      (evt) => {
+        if (selected) {
+          evt.stopPropagation();
+          selectAt(evt);
+        }
+      },
+      true,
+    );
+    svg.addEventListener(
+      "pointerup",
+      (evt) => {
+        if (!selected) {
+          return;
+        }
+        evt.stopPropagation();
+        const cells = [...selected].map((id) => id.slice(2)).join(" ");
+        selected = null;
+        justSelected = true;
+        htmx.ajax("POST", "/deduceInRegion", {
+          target: "#mainSpace",
+          values: { cells },
+        });
+      },
+      true,
+    );
+    // Don't treat the end of a selection as clicking on a cell
+    svg.addEventListener(
+      "click",
+      (evt) => {
+        if (justSelected) {
+          evt.stopPropagation();
+          justSelected = false;
+        }
+      },
+      true,
+    );
+  });
+}
+
+// Ticking a rule in the list of rules shades the cells it covers.
+function applyRuleFunctions() {
+  document.querySelectorAll("input.js_rule_toggle").forEach((toggle) => {
+    if (toggle.dataset.jsReady) {
+      return;
+    }
+    toggle.dataset.jsReady = "true";
+    toggle.addEventListener("change", () => {
+      document
+        .querySelectorAll(`g.rulecover[data-rule="${toggle.dataset.rule}"]`)
+        .forEach((el) => el.classList.toggle("rulehidden", !toggle.checked));
+    });
+  });
+}
+
+function doJavascript() {
+  applyHighlightFunctions();
+  applyStatementFunctions();
+  applyZoomFunctions();
+  applyPinFunctions();
+  applyRegionFunctions();
+  applyRuleFunctions();
+
+  document.addEventListener("htmx:beforeRequest", function () {
+    document.querySelectorAll("button").forEach((btn) => {
+      if (!btn.disabled) {
+        btn.disabled = true;
+        btn.dataset.htmxDisabled = "true"; // Mark this button
+      }
+    });
+    document.body.style.cursor = "wait";
+  });
+
+  document.addEventListener("htmx:afterRequest", function () {
+    document.querySelectorAll('[data-htmx-disabled="true"]').forEach((btn) => {
+      btn.disabled = false;
+      delete btn.dataset.htmxDisabled; // Clean up
+    });
+    document.body.style.cursor = "default";
+  });
+}
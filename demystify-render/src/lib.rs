@@ -0,0 +1,294 @@
+//! Draws the puzzles and solves of demystify: as HTML and SVG for the web
+//! interface, and as PDF and Markdown. This is kept apart from
+//! demystify-core, so changing how puzzles look does not rebuild the solver.
+//! Most programs should use the `demystify` crate, where this is
+//! `demystify::web`.
+
+#![allow(dead_code)]
+
+pub mod markdown;
+pub mod pdf;
+pub mod planner;
+pub mod puzsvg;
+
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::{Arc, LazyLock},
+};
+
+pub use demystify_core::draw::{HtmlOptions, Orientation};
+pub use demystify_core::json::kinds;
+use demystify_core::json::{Problem, Statement};
+use demystify_core::problem::util::lru::LruCache;
+
+use self::puzsvg::{CandidateLayout, PuzzleDraw};
+
+/// How many drawings of puzzles [`draw_svg`] keeps. Refreshing, or showing
+/// the difficulties of, a state which has not changed draws exactly the same
+/// puzzle, which for a large board is a noticeable wait.
+const SVG_CACHE_CAPACITY: usize = 64;
+
+/// What a drawing depends on: the problem, and the options which change it.
+type SvgKey = (Problem, Option<bool>, CandidateLayout, Option<u32>);
+
+/// Drawings, by a fingerprint of their [`SvgKey`]. The full key is kept with
+/// each drawing, so two keys with the same fingerprint are told apart.
+type SvgCache = LruCache<u64, Arc<(SvgKey, Arc<String>)>>;
+
+static SVG_CACHE: LazyLock<SvgCache> = LazyLock::new(|| LruCache::new(SVG_CACHE_CAPACITY));
+
+#[must_use]
+pub fn base_css() -> &'static str {
+    include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/include/base.css"))
+}
+
+#[must_use]
+pub fn base_javascript() -> &'static str {
+    include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/include/base.js"))
+}
+
+#[must_use]
+pub fn create_html(puzjson: &Problem) -> String {
+    create_html_with_options(puzjson, &HtmlOptions::default())
+}
+
+#[must_use]
+pub fn create_html_with_options(puzjson: &Problem, options: &HtmlOptions) -> String {
+    let svg = draw_svg(puzjson, options);
+
+    let statements = if let Some(ref state) = puzjson.state {
+        let constraints = if let Some(ref statements) = state.statements {
+            map_statements(statements, options.show_expressions)
+        } else {
+            String::new()
+        };
+
+        let description = state.description.clone().unwrap_or(String::new());
+
+        description + "\n" + &constraints
+    } else {
+        String::new()
+    };
+
+    let two_div_template = r#"
+{% for warning in warnings %}
+    <div class="alert alert-warning">{{ warning | escape }}</div>
+{% endfor %}
+    <div class="puzzlelayout" style="display: flex; flex-wrap: wrap; {% if stacked %}flex-direction: column;{% endif %}">
+    <div class="puzzlepane" style="{% if stacked %}width: 100%; max-width: {{ puzzle_width }}px;{% else %}flex: 0 1 {{ puzzle_width }}px; max-width: 100%;{% endif %} height: {{ height }}px; border: 1px solid black;">
+        <div class="zoomcontrols">
+            <button type="button" class="js_zoom" data-zoom="in" title="Zoom in">+</button>
+            <button type="button" class="js_zoom" data-zoom="out" title="Zoom out">&minus;</button>
+            <button type="button" class="js_zoom" data-zoom="reset" title="Reset zoom">&#8634;</button>
+        </div>
+        {{ svg }}
+    </div>
+    <div style="{% if stacked %}width: 100%; max-height: {{ height }}px;{% else %}flex: 1 1 {{ statements_width }}px; height: {{ height }}px;{% endif %} border: 1px solid black; overflow-y: auto;">
+        {{ statements }}
+    </div>
+</div>
+"#;
+
+    let mut context = tera::Context::new();
+
+    context.insert("statements", &statements);
+    context.insert("svg", svg.as_str());
+    context.insert("warnings", &puzjson.warnings);
+    context.insert("stacked", &(options.orientation == Orientation::Stacked));
+    context.insert("puzzle_width", &options.puzzle_width);
+    context.insert("statements_width", &options.statements_width);
+    context.insert("height", &options.height);
+
+    tera::Tera::one_off(two_div_template, &context, false).expect("IE: Failed templating")
+}
+
+/// A small drawing of `puzjson`, `size` pixels across, with only its given
+/// and solved cells, such as to preview a puzzle or an earlier point of its
+/// solve. These are kept with the other drawings, so previews of the same
+/// state are only drawn once.
+#[must_use]
+pub fn thumbnail_svg(puzjson: &Problem, size: u32) -> Arc<String> {
+    draw_svg(
+        puzjson,
+        &HtmlOptions {
+            thumbnail: Some(size),
+            ..HtmlOptions::default()
+        },
+    )
+}
+
+/// The SVG of `puzjson` drawn with `options`, cached for the whole process
+/// by the problem (with its state) and the options which change the drawing.
+/// Every deduction changes the state, so a drawing is never reused once the
+/// puzzle has moved on.
+fn draw_svg(puzjson: &Problem, options: &HtmlOptions) -> Arc<String> {
+    draw_svg_in(&SVG_CACHE, puzjson, options)
+}
+
+/// The fingerprint [`SvgCache`] keys a drawing by.
+fn svg_fingerprint(puzjson: &Problem, options: &HtmlOptions) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (
+        puzjson,
+        options.binary_cells,
+        options.candidates,
+        options.thumbnail,
+    )
+        .hash(&mut hasher);
+    hasher.finish()
+}
+
+fn draw_svg_in(cache: &SvgCache, puzjson: &Problem, options: &HtmlOptions) -> Arc<String> {
+    let draw = || {
+        let pd = PuzzleDraw::new(&puzjson.puzzle.kind)
+            .with_binary(options.binary_cells)
+            .with_candidates(options.candidates)
+            .with_thumbnail(options.thumbnail);
+        Arc::new(pd.draw_puzzle(puzjson).to_string())
+    };
+    let entry = cache.get_or_insert_with(&svg_fingerprint(puzjson, options), || {
+        let key = (
+            puzjson.clone(),
+            options.binary_cells,
+            options.candidates,
+            options.thumbnail,
+        );
+        Arc::new((key, draw()))
+    });
+    let ((problem, binary_cells, candidates, thumbnail), svg) = &*entry;
+    if problem == puzjson
+        && *binary_cells == options.binary_cells
+        && *candidates == options.candidates
+        && *thumbnail == options.thumbnail
+    {
+        svg.clone()
+    } else {
+        // Another drawing has the same fingerprint, which is kept
+        draw()
+    }
+}
+
+fn map_statements(statements: &Vec<Statement>, show_expressions: bool) -> String {
+    let constraint_template = r#"
+    <div class="constraintlist">
+    <input type="search" class="js_statement_search" placeholder="Search constraints">
+{% for statement in statements %}
+    <div class="{% for class in statement.classes %}{{ class }} {% endfor %}"
+        {%- if statement.constraint %} data-constraint="{{ statement.constraint }}"{% endif %}
+        {%- if statement.group is number %} data-group="{{ statement.group }}"{% endif %}
+        {%- if statement.scope %} data-scope="{{ statement.scope | join(sep=" ") }}"{% endif %}>
+        {{ statement.content }}
+        {%- if show_expressions and statement.expression %}
+        <details class="expression"><summary>Expression</summary><pre>{{ statement.expression | escape }}</pre></details>
+        {%- endif %}
+    </div>
+{% endfor %}
+</div>
+"#;
+
+    let mut context = tera::Context::new();
+
+    context.insert("statements", statements);
+    context.insert("show_expressions", &show_expressions);
+
+    tera::Tera::one_off(constraint_template, &context, false)
+        .expect("IE: Fatal internal formatting error")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, sync::Arc};
+
+    use test_log::test;
+
+    use demystify_core::json::Problem;
+
+    use demystify_core::problem::util::{lru::LruCache, test_utils::TST};
+
+    use super::{
+        HtmlOptions, Orientation, create_html, create_html_with_options, draw_svg_in,
+        svg_fingerprint, thumbnail_svg,
+    };
+
+    #[test]
+    fn test_html_options() -> anyhow::Result<()> {
+        let problem: Problem = serde_json::from_reader(File::open(format!("{TST}/sudoku.json"))?)?;
+
+        let html = create_html(&problem);
+        assert!(html.contains("flex: 0 1 550px"));
+        assert!(html.contains("js_zoom"));
+
+        let html = create_html_with_options(
+            &problem,
+            &HtmlOptions {
+                puzzle_width: 900,
+                orientation: Orientation::Stacked,
+                ..HtmlOptions::default()
+            },
+        );
+        assert!(html.contains("flex-direction: column"));
+        assert!(html.contains("max-width: 900px"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_svg_cache() -> anyhow::Result<()> {
+        let mut problem: Problem =
+            serde_json::from_reader(File::open(format!("{TST}/sudoku.json"))?)?;
+        let options = HtmlOptions::default();
+        // A cache of its own, as other tests draw with the shared one
+        let cache = LruCache::new(16);
+        let draw = |problem: &Problem, options: &HtmlOptions| draw_svg_in(&cache, problem, options);
+
+        // The same state is only drawn once, whatever the layout around it
+        let svg = draw(&problem, &options);
+        let stacked = HtmlOptions {
+            orientation: Orientation::Stacked,
+            ..options
+        };
+        assert!(Arc::ptr_eq(&svg, &draw(&problem, &stacked)));
+
+        // Options which change the drawing, and changes to the state, are not
+        let binary = HtmlOptions {
+            binary_cells: Some(true),
+            ..options
+        };
+        assert!(!Arc::ptr_eq(&svg, &draw(&problem, &binary)));
+
+        // Thumbnails leave out the candidates, and are cached too
+        let small = HtmlOptions {
+            thumbnail: Some(120),
+            ..HtmlOptions::default()
+        };
+        let thumbnail = draw(&problem, &small);
+        assert!(Arc::ptr_eq(&thumbnail, &draw(&problem, &small)));
+        assert_eq!(thumbnail, thumbnail_svg(&problem, 120));
+        assert!(thumbnail.contains(r#"width="120""#));
+        assert!(svg.contains(r#"width="500""#));
+        assert!(thumbnail.len() < svg.len());
+        assert_eq!(cache.len(), 3);
+
+        let mut unsolved = problem.clone();
+        unsolved.state = None;
+        let changed = draw(&unsolved, &options);
+        assert!(!Arc::ptr_eq(&svg, &changed));
+        assert_ne!(svg, changed);
+
+        // A different drawing with the same fingerprint is not reused
+        problem.state = None;
+        let other = LruCache::new(16);
+        other.get_or_insert_with(&svg_fingerprint(&problem, &options), || {
+            let key = (
+                unsolved.clone(),
+                Some(true),
+                options.candidates,
+                options.thumbnail,
+            );
+            Arc::new((key, svg.clone()))
+        });
+        assert_eq!(draw_svg_in(&other, &problem, &options), changed);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,234 @@
+//! GitHub-flavoured Markdown of a [`SolveTrace`], for pasting into issues and
+//! wikis, with a heading for each step.
+//!
+//! Each grid is drawn as a table, or as an SVG image embedded in the file
+//! (which some renderers, including GitHub's, do not show). Beneath it, the
+//! deductions of the step are written out, and the constraints explaining
+//! them are in a collapsible `<details>` block, so long steps can be
+//! skimmed.
+
+use std::fmt::Write as _;
+
+use itertools::Itertools;
+
+use demystify_core::{
+    json::{Class, Problem, StateLit, kinds::SupportedKinds},
+    problem::trace::{SolveTrace, escape_markdown as escape},
+};
+
+use super::{HtmlOptions, draw_svg, pdf::strip_html};
+
+/// How [`trace_to_markdown`] draws the grid of each step.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum MarkdownGrid {
+    /// A table, with the candidates of each cell which is not known
+    #[default]
+    Table,
+    /// The same SVG as the web interface, embedded as a data URI
+    Svg,
+}
+
+const LEGEND: &str = "Given values are in bold, values deduced by a step are in italics, and values it rules out are struck through.";
+
+/// Renders `trace` as Markdown, as described in the [module docs](self).
+#[must_use]
+pub fn trace_to_markdown(trace: &SolveTrace, grid: MarkdownGrid) -> String {
+    let mut md = String::from("# Solve\n\n");
+    if grid == MarkdownGrid::Table {
+        let _ = writeln!(md, "{LEGEND}\n");
+    }
+    for step in &trace.steps {
+        let _ = writeln!(md, "## {}\n", escape(&step.title));
+        match grid {
+            MarkdownGrid::Table => md += &grid_table(&step.problem),
+            MarkdownGrid::Svg => {
+                let svg = draw_svg(&step.problem, &HtmlOptions::default());
+                let _ = writeln!(
+                    md,
+                    "<img alt=\"{}\" src=\"data:image/svg+xml;base64,{}\"/>",
+                    tera::escape_html(&step.title),
+                    base64(svg.as_bytes())
+                );
+            }
+        }
+        md.push('\n');
+        md += &statements(&step.problem);
+    }
+    if let Some(summary) = &trace.summary {
+        let _ = write!(md, "## Summary\n\n{}", summary.markdown());
+    }
+    md
+}
+
+/// The grid of `problem` as a table, with its labels, or the numbers of its
+/// rows and columns, around the edge.
+fn grid_table(problem: &Problem) -> String {
+    let puzzle = &problem.puzzle;
+    let features = SupportedKinds::features(&puzzle.kind);
+    let knowledge = problem
+        .state
+        .as_ref()
+        .and_then(|s| s.knowledge_grid.as_ref());
+    let labels = |labels: &Option<Vec<String>>, n: i64| -> Vec<String> {
+        match labels {
+            Some(labels) => labels.iter().map(|l| escape(l)).collect(),
+            None => (1..=n).map(|i| i.to_string()).collect(),
+        }
+    };
+    let right = puzzle.right_labels.as_ref();
+
+    let mut header = vec![String::new()];
+    header.extend(labels(&puzzle.top_labels, puzzle.width));
+    header.extend(right.map(|_| String::new()));
+    let mut rows = vec![header];
+    let left = labels(&puzzle.left_labels, puzzle.height);
+    for i in 0..puzzle.height {
+        let mut row = vec![left.get(i as usize).cloned().unwrap_or_default()];
+        for j in 0..puzzle.width {
+            let given = puzzle
+                .start_grid
+                .as_ref()
+                .and_then(|g| g.get(i as usize)?.get(j as usize).copied().flatten())
+                .filter(|&v| Some(v) != features.blank_input_val);
+            let lits = knowledge
+                .and_then(|k| k.get(i as usize)?.get(j as usize))
+                .and_then(Option::as_ref);
+            row.push(if !puzzle.is_cell(&[i + 1, j + 1]) {
+                String::new()
+            } else if let Some(val) = given {
+                format!("**{}**", escape(&puzzle.value_text(val)))
+            } else if let Some(lits) = lits {
+                lits.iter()
+                    .map(|lit| candidate(&puzzle.value_text(lit.val), lit))
+                    .join(" ")
+            } else {
+                String::new()
+            });
+        }
+        if let Some(right) = right {
+            row.push(right.get(i as usize).map(|l| escape(l)).unwrap_or_default());
+        }
+        rows.push(row);
+    }
+    if let Some(bottom) = &puzzle.bottom_labels {
+        let mut row = vec![String::new()];
+        row.extend(bottom.iter().map(|l| escape(l)));
+        rows.push(row);
+    }
+
+    let columns = rows[0].len();
+    let mut table = String::new();
+    for (n, row) in rows.iter().enumerate() {
+        let cells = (0..columns).map(|c| row.get(c).map_or("", String::as_str));
+        let _ = writeln!(table, "| {} |", cells.format(" | "));
+        if n == 0 {
+            let _ = writeln!(table, "|{}", "---|".repeat(columns));
+        }
+    }
+    table
+}
+
+/// One candidate of a cell, marked if the step deduces it or rules it out.
+fn candidate(val: &str, lit: &StateLit) -> String {
+    let val = escape(val);
+    if lit.classes.contains(&Class::LitPos) {
+        format!("_{val}_")
+    } else if lit.classes.contains(&Class::LitNeg) {
+        format!("~~{val}~~")
+    } else {
+        val
+    }
+}
+
+/// The warnings and description of the step, then its statements, in a
+/// collapsible block.
+fn statements(problem: &Problem) -> String {
+    let mut md = String::new();
+    for warning in &problem.warnings {
+        let _ = writeln!(md, "> **Warning:** {}\n", escape(warning));
+    }
+    let Some(state) = &problem.state else {
+        return md;
+    };
+    if let Some(description) = &state.description {
+        for paragraph in strip_html(description).lines() {
+            let _ = writeln!(md, "{}\n", escape(paragraph));
+        }
+    }
+    let statements = state.statements.as_deref().unwrap_or_default();
+    if !statements.is_empty() {
+        let _ = writeln!(
+            md,
+            "<details>\n<summary>Constraints ({})</summary>\n",
+            statements.len()
+        );
+        for statement in statements {
+            let text = escape(&strip_html(&statement.content).replace('\n', " "));
+            if statement.classes.contains(&Class::GroupHeader) {
+                let _ = writeln!(md, "- **{text}**");
+            } else {
+                let _ = writeln!(md, "- {text}");
+            }
+        }
+        md += "\n</details>\n\n";
+    }
+    md
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (u32::from(b) << (16 - 8 * i)));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[((n >> (18 - 6 * i)) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use demystify_core::problem::util::test_utils::TST;
+
+    use super::*;
+
+    #[test]
+    fn test_escape_and_base64() {
+        assert_eq!(escape("a*b_c | x<y"), "a\\*b\\_c \\| x\\<y");
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn test_trace_to_markdown() -> anyhow::Result<()> {
+        let trace =
+            SolveTrace::from_reader(std::fs::File::open(format!("{TST}/sudoku-4x4.trace.json"))?)?;
+        let md = trace_to_markdown(&trace, MarkdownGrid::Table);
+        assert_eq!(md, trace_to_markdown(&trace, MarkdownGrid::Table));
+        assert!(md.starts_with("# Solve\n\n"));
+        for step in &trace.steps {
+            assert!(md.contains(&format!("\n## {}\n", step.title)));
+        }
+        assert!(md.contains("\n|  | 1 | 2 | 3 | 4 |\n|---|---|---|---|---|\n| 1 | **1** | "));
+        assert_eq!(md.matches("<details>").count(), trace.steps.len());
+        assert!(md.contains("\n## Summary\n\nSolved in 2 steps"));
+
+        let svg = trace_to_markdown(&trace, MarkdownGrid::Svg);
+        assert!(!svg.contains("| 1 | **1** |"));
+        assert_eq!(
+            svg.matches("src=\"data:image/svg+xml;base64,").count(),
+            trace.steps.len()
+        );
+        Ok(())
+    }
+}
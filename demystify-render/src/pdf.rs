@@ -0,0 +1,587 @@
+//! Printable PDFs of a [`SolveTrace`], such as for handouts, with one step
+//! per page: the grid, followed by the statements explaining the step.
+//!
+//! The PDF is written directly, without a browser. The grid is drawn with
+//! PDF's own lines and rectangles, and text uses the standard Helvetica
+//! fonts, so no fonts are embedded. Statements which do not fit on a page
+//! carry on to the next. The same trace always gives the same bytes.
+
+use std::{collections::BTreeSet, fmt::Write as _, io::Write as _};
+
+use itertools::Itertools;
+
+use demystify_core::{
+    json::{Class, LineKind, Problem, Puzzle, StateLit, kinds::SupportedKinds},
+    problem::trace::{SolveTrace, TraceStep},
+};
+
+/// A4, in points
+const PAGE_WIDTH: f64 = 595.0;
+const PAGE_HEIGHT: f64 = 842.0;
+const MARGIN: f64 = 40.0;
+/// The largest the grid is drawn, including its labels
+const GRID_SIZE: f64 = 380.0;
+/// The largest a cell is drawn, so small grids are not huge
+const MAX_CELL: f64 = 48.0;
+const TITLE_SIZE: f64 = 16.0;
+const TEXT_SIZE: f64 = 10.0;
+const LEADING: f64 = 13.0;
+/// Roughly the width of the average Helvetica character, in ems, used to
+/// wrap statements
+const CHAR_WIDTH: f64 = 0.52;
+/// The width of every digit in Helvetica, in ems
+const DIGIT_WIDTH: f64 = 0.556;
+
+type Rgb = (f64, f64, f64);
+
+const BLACK: Rgb = (0.0, 0.0, 0.0);
+const KNOWN: Rgb = (0.1, 0.2, 0.6);
+const DEDUCED: Rgb = (0.65, 0.9, 0.65);
+const RULED_OUT: Rgb = (1.0, 0.75, 0.75);
+
+/// The cage colours of [`PuzzleDraw`](super::puzsvg::PuzzleDraw), lightened
+/// so values can be read on top of them when printed.
+const CAGE_COLOURS: [Rgb; 6] = [
+    (0.85, 0.93, 0.93),
+    (0.93, 0.87, 0.9),
+    (0.88, 0.93, 0.85),
+    (0.96, 0.94, 0.82),
+    (0.87, 0.88, 0.95),
+    (0.95, 0.89, 0.86),
+];
+
+/// Renders `trace` as a PDF, as described in the [module docs](self).
+#[must_use]
+pub fn trace_to_pdf(trace: &SolveTrace) -> Vec<u8> {
+    let pages = trace.steps.iter().flat_map(draw_step).collect_vec();
+    write_pdf(&pages)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Font {
+    Regular,
+    Bold,
+}
+
+/// The content of one page, with `y` measured down from the top of the page.
+#[derive(Default)]
+struct Page {
+    ops: String,
+}
+
+impl Page {
+    fn fill(&mut self, (r, g, b): Rgb) {
+        let _ = writeln!(self.ops, "{r:.3} {g:.3} {b:.3} rg");
+    }
+
+    fn stroke(&mut self, (r, g, b): Rgb, width: f64) {
+        let _ = writeln!(self.ops, "{r:.3} {g:.3} {b:.3} RG {width:.2} w");
+    }
+
+    fn rect(&mut self, x: f64, y: f64, w: f64, h: f64) {
+        let _ = writeln!(
+            self.ops,
+            "{x:.2} {:.2} {w:.2} {h:.2} re f",
+            PAGE_HEIGHT - y - h
+        );
+    }
+
+    fn polyline(&mut self, points: &[(f64, f64)]) {
+        for (n, (x, y)) in points.iter().enumerate() {
+            let op = if n == 0 { "m" } else { "l" };
+            let _ = writeln!(self.ops, "{x:.2} {:.2} {op}", PAGE_HEIGHT - y);
+        }
+        self.ops += "S\n";
+    }
+
+    fn line(&mut self, x0: f64, y0: f64, x1: f64, y1: f64) {
+        self.polyline(&[(x0, y0), (x1, y1)]);
+    }
+
+    /// Writes `text` with its baseline at `y`.
+    fn text(&mut self, font: Font, size: f64, x: f64, y: f64, text: &str) {
+        let font = match font {
+            Font::Regular => "F1",
+            Font::Bold => "F2",
+        };
+        let _ = writeln!(
+            self.ops,
+            "BT /{font} {size:.2} Tf {x:.2} {:.2} Td {} Tj ET",
+            PAGE_HEIGHT - y,
+            pdf_string(text)
+        );
+    }
+
+    /// Writes a number centred in the square of side `size` at `(x, y)`.
+    fn number(&mut self, font: Font, x: f64, y: f64, size: f64, val: &str) {
+        let font_size = size * 0.7;
+        let width = val.len() as f64 * DIGIT_WIDTH * font_size;
+        self.text(
+            font,
+            font_size,
+            x + (size - width) / 2.0,
+            y + (size + font_size * 0.7) / 2.0,
+            val,
+        );
+    }
+}
+
+/// Where the grid of a puzzle goes on the page.
+struct Layout {
+    /// The top left corner of the first cell
+    x: f64,
+    y: f64,
+    cell: f64,
+}
+
+impl Layout {
+    fn new(puzzle: &Puzzle, top: f64) -> Self {
+        let side = |labels: &Option<Vec<String>>| f64::from(u8::from(labels.is_some()));
+        let (left, top_labels) = (side(&puzzle.left_labels), side(&puzzle.top_labels));
+        let cols = puzzle.width as f64 + left + side(&puzzle.right_labels);
+        let rows = puzzle.height as f64 + top_labels + side(&puzzle.bottom_labels);
+        let cell = (GRID_SIZE / cols.max(rows).max(1.0)).min(MAX_CELL);
+        Self {
+            x: MARGIN + left * cell,
+            y: top + top_labels * cell,
+            cell,
+        }
+    }
+
+    /// The top left corner of the cell in row `i` and column `j`, counting
+    /// from 0. Labels are at -1 and one past the last row or column.
+    fn corner(&self, i: i64, j: i64) -> (f64, f64) {
+        (self.x + j as f64 * self.cell, self.y + i as f64 * self.cell)
+    }
+
+    fn centre(&self, i: i64, j: i64) -> (f64, f64) {
+        let (x, y) = self.corner(i, j);
+        (x + self.cell / 2.0, y + self.cell / 2.0)
+    }
+}
+
+/// The pages for one step: usually one, but more if its statements do not
+/// fit.
+fn draw_step(step: &TraceStep) -> Vec<Page> {
+    let mut page = Page::default();
+    page.text(
+        Font::Bold,
+        TITLE_SIZE,
+        MARGIN,
+        MARGIN + TITLE_SIZE,
+        &step.title,
+    );
+
+    let top = MARGIN + TITLE_SIZE * 2.0;
+    let puzzle = &step.problem.puzzle;
+    let layout = Layout::new(puzzle, top);
+    draw_grid(&mut page, &step.problem, &layout);
+
+    let mut lines: Vec<(Font, String)> = vec![];
+    for warning in &step.problem.warnings {
+        lines.extend(wrap(&format!("Warning: {warning}")).map(|l| (Font::Regular, l)));
+    }
+    if let Some(state) = &step.problem.state {
+        if let Some(description) = &state.description {
+            for paragraph in strip_html(description).lines() {
+                lines.extend(wrap(paragraph).map(|l| (Font::Bold, l)));
+            }
+        }
+        for statement in state.statements.iter().flatten() {
+            let font = if statement.classes.contains(&Class::GroupHeader) {
+                Font::Bold
+            } else {
+                Font::Regular
+            };
+            let text = strip_html(&statement.content).replace('\n', " ");
+            lines.extend(wrap(&format!("- {text}")).map(|l| (font, l)));
+        }
+    }
+
+    let grid_rows = layout.y + (puzzle.height as f64 + 1.0) * layout.cell;
+    let mut y = grid_rows.max(top + GRID_SIZE / 2.0) + LEADING;
+    let mut pages = vec![];
+    for (font, line) in lines {
+        if y > PAGE_HEIGHT - MARGIN {
+            pages.push(std::mem::take(&mut page));
+            let title = format!("{} (continued)", step.title);
+            page.text(Font::Bold, TITLE_SIZE, MARGIN, MARGIN + TITLE_SIZE, &title);
+            y = top;
+        }
+        page.text(font, TEXT_SIZE, MARGIN, y, &line);
+        y += LEADING;
+    }
+    pages.push(page);
+    pages
+}
+
+fn draw_grid(page: &mut Page, problem: &Problem, layout: &Layout) {
+    let puzzle = &problem.puzzle;
+    let features = SupportedKinds::features(&puzzle.kind);
+    let cell = layout.cell;
+    let (width, height) = (puzzle.width, puzzle.height);
+    let hole = |i: i64, j: i64| {
+        i < 0 || j < 0 || i >= height || j >= width || !puzzle.is_cell(&[i + 1, j + 1])
+    };
+    let cage = |i: i64, j: i64| {
+        puzzle
+            .cages
+            .as_ref()
+            .and_then(|c| c.get(i as usize)?.get(j as usize).copied().flatten())
+    };
+
+    // Cages are filled, so the values go on top
+    if puzzle.cages.is_some() {
+        let ids: BTreeSet<i64> = (0..height)
+            .flat_map(|i| (0..width).filter_map(move |j| cage(i, j)))
+            .collect();
+        for i in 0..height {
+            for j in 0..width {
+                if let Some(id) = cage(i, j)
+                    && !hole(i, j)
+                {
+                    let colour = ids.iter().position(|&c| c == id).unwrap();
+                    page.fill(CAGE_COLOURS[colour % CAGE_COLOURS.len()]);
+                    let (x, y) = layout.corner(i, j);
+                    page.rect(x, y, cell, cell);
+                }
+            }
+        }
+    }
+
+    page.ops += "1 J 1 j\n";
+    for line in &puzzle.lines {
+        let (colour, width) = match line.kind {
+            LineKind::Thermo => ((0.8, 0.8, 0.8), 0.25),
+            LineKind::Arrow => ((0.6, 0.6, 0.6), 0.04),
+            LineKind::Whisper => ((0.4, 0.75, 0.4), 0.12),
+        };
+        let points = line
+            .cells
+            .iter()
+            .filter_map(|c| match c[..] {
+                [row, col] => Some(layout.centre(row - 1, col - 1)),
+                _ => None,
+            })
+            .collect_vec();
+        page.stroke(colour, width * cell);
+        page.polyline(&points);
+    }
+    page.ops += "0 J 0 j\n";
+
+    let state = problem.state.as_ref();
+    let knowledge = state.and_then(|s| s.knowledge_grid.as_ref());
+    let given = |i: i64, j: i64| {
+        puzzle
+            .start_grid
+            .as_ref()
+            .and_then(|g| g.get(i as usize)?.get(j as usize).copied().flatten())
+            .filter(|&v| Some(v) != features.blank_input_val)
+    };
+    for i in 0..height {
+        for j in 0..width {
+            if hole(i, j) {
+                continue;
+            }
+            let (x, y) = layout.corner(i, j);
+            if let Some(val) = given(i, j) {
+                page.fill(BLACK);
+                page.number(Font::Bold, x, y, cell, &puzzle.value_text(val));
+            } else if let Some(lits) = knowledge
+                .and_then(|k| k.get(i as usize)?.get(j as usize))
+                .and_then(Option::as_ref)
+            {
+                draw_candidates(page, puzzle, x, y, cell, lits);
+            }
+        }
+    }
+
+    // Edges between two cells are thin, unless they border a box or cage,
+    // while edges with a cell on only one side are the outline
+    let edge_width = |a: (i64, i64), b: (i64, i64), box_edge: bool| {
+        let (a_cell, b_cell) = (!hole(a.0, a.1), !hole(b.0, b.1));
+        if !a_cell && !b_cell {
+            None
+        } else if !(a_cell && b_cell) || cage(a.0, a.1) != cage(b.0, b.1) {
+            Some(2.0)
+        } else if features.sudoku_borders && box_edge {
+            Some(1.2)
+        } else {
+            Some(0.4)
+        }
+    };
+    for i in 0..=height {
+        for j in 0..=width {
+            let (x, y) = layout.corner(i, j);
+            if i < height
+                && let Some(w) = edge_width((i, j - 1), (i, j), j % 3 == 0)
+            {
+                page.stroke(BLACK, w);
+                page.line(x, y, x, y + cell);
+            }
+            if j < width
+                && let Some(w) = edge_width((i - 1, j), (i, j), i % 3 == 0)
+            {
+                page.stroke(BLACK, w);
+                page.line(x, y, x + cell, y);
+            }
+        }
+    }
+
+    page.fill(BLACK);
+    let sides = [
+        (&puzzle.top_labels, (-1, 0), (0, 1)),
+        (&puzzle.bottom_labels, (height, 0), (0, 1)),
+        (&puzzle.left_labels, (0, -1), (1, 0)),
+        (&puzzle.right_labels, (0, width), (1, 0)),
+    ];
+    for (labels, (i, j), (di, dj)) in sides {
+        for (n, label) in (0..).zip(labels.iter().flatten()) {
+            let (x, y) = layout.corner(i + n * di, j + n * dj);
+            page.number(Font::Regular, x, y, cell, label);
+        }
+    }
+}
+
+/// Draws what is known about a cell which is not given: its value once it is
+/// known, or else its candidates, marking those the step deduces or rules out.
+fn draw_candidates(page: &mut Page, puzzle: &Puzzle, x: f64, y: f64, cell: f64, lits: &[StateLit]) {
+    if let [lit] = lits {
+        if lit.classes.contains(&Class::LitPos) {
+            page.fill(DEDUCED);
+            page.rect(x, y, cell, cell);
+        }
+        page.fill(KNOWN);
+        page.number(Font::Regular, x, y, cell, &puzzle.value_text(lit.val));
+        return;
+    }
+
+    let per_row = (lits.len() as f64).sqrt().ceil() as usize;
+    let little = cell * 0.9 / per_row as f64;
+    for (n, lit) in lits.iter().enumerate() {
+        let lx = x + cell * 0.05 + (n % per_row) as f64 * little;
+        let ly = y + cell * 0.05 + (n / per_row) as f64 * little;
+        let ruled_out = lit.classes.contains(&Class::LitNeg);
+        if lit.classes.contains(&Class::LitPos) {
+            page.fill(DEDUCED);
+            page.rect(lx, ly, little, little);
+        } else if ruled_out {
+            page.fill(RULED_OUT);
+            page.rect(lx, ly, little, little);
+        }
+        page.fill(BLACK);
+        page.number(Font::Regular, lx, ly, little, &puzzle.value_text(lit.val));
+        if ruled_out {
+            page.stroke(BLACK, 0.5);
+            page.line(lx, ly + little, lx + little, ly);
+        }
+    }
+}
+
+/// The text of some HTML, with one line for each line break, paragraph or
+/// list item.
+pub(super) fn strip_html(html: &str) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text += &rest[..start];
+        let end = rest[start..]
+            .find('>')
+            .map_or(rest.len(), |e| start + e + 1);
+        let tag = rest[start + 1..end].trim_start_matches('/').to_lowercase();
+        if ["br", "p", "li", "div"]
+            .iter()
+            .any(|t| tag.starts_with(t) && !tag[t.len()..].starts_with(char::is_alphanumeric))
+        {
+            text.push('\n');
+        }
+        rest = &rest[end..];
+    }
+    text += rest;
+
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    text.lines()
+        .map(|l| l.split_whitespace().join(" "))
+        .filter(|l| !l.is_empty())
+        .join("\n")
+}
+
+/// Splits `text` into lines which fit across the page.
+fn wrap(text: &str) -> impl Iterator<Item = String> {
+    let max = ((PAGE_WIDTH - 2.0 * MARGIN) / (TEXT_SIZE * CHAR_WIDTH)) as usize;
+    let mut lines: Vec<String> = vec![];
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > max {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line += word;
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines.into_iter()
+}
+
+/// `text` as a PDF string in the Latin-1 part of `WinAnsiEncoding`, with
+/// symbols outside it spelled out.
+fn pdf_string(text: &str) -> String {
+    let mut out = String::from("(");
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            ' '..='~' => out.push(c),
+            '\u{a0}'..='\u{ff}' => {
+                let _ = write!(out, "\\{:03o}", u32::from(c));
+            }
+            '≠' => out += "!=",
+            '≤' => out += "<=",
+            '≥' => out += ">=",
+            '→' => out += "->",
+            '–' | '—' | '−' => out.push('-'),
+            '‘' | '’' => out.push('\''),
+            '“' | '”' => out.push('"'),
+            '…' => out += "...",
+            _ => out.push('?'),
+        }
+    }
+    out.push(')');
+    out
+}
+
+/// Puts the pages together into a PDF file.
+fn write_pdf(pages: &[Page]) -> Vec<u8> {
+    // Objects 1 to 4 are the catalog, page tree and fonts, then each page is
+    // followed by its contents
+    let font = |name: &str| {
+        format!("<< /Type /Font /Subtype /Type1 /BaseFont /{name} /Encoding /WinAnsiEncoding >>")
+            .into_bytes()
+    };
+    let kids = (0..pages.len())
+        .map(|n| format!("{} 0 R", 5 + 2 * n))
+        .join(" ");
+    let mut objects = vec![
+        b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+        format!("<< /Type /Pages /Kids [{kids}] /Count {} >>", pages.len()).into_bytes(),
+        font("Helvetica"),
+        font("Helvetica-Bold"),
+    ];
+    for (n, page) in pages.iter().enumerate() {
+        objects.push(
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {PAGE_WIDTH} {PAGE_HEIGHT}] /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+                6 + 2 * n
+            )
+            .into_bytes(),
+        );
+        let mut zip = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+        zip.write_all(page.ops.as_bytes())
+            .expect("IE: writing to memory failed");
+        let contents = zip.finish().expect("IE: writing to memory failed");
+        let mut stream = format!(
+            "<< /Length {} /Filter /FlateDecode >>\nstream\n",
+            contents.len()
+        )
+        .into_bytes();
+        stream.extend(contents);
+        stream.extend(b"\nendstream");
+        objects.push(stream);
+    }
+
+    let mut pdf = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+    let mut offsets = vec![];
+    for (n, object) in (1..).zip(&objects) {
+        offsets.push(pdf.len());
+        pdf.extend(format!("{n} 0 obj\n").bytes());
+        pdf.extend(object);
+        pdf.extend(b"\nendobj\n");
+    }
+    let xref = pdf.len();
+    let _ = write!(pdf, "xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+    for offset in offsets {
+        let _ = writeln!(pdf, "{offset:010} 00000 n ");
+    }
+    let _ = write!(
+        pdf,
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+        objects.len() + 1
+    );
+    pdf
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::*;
+    use demystify_core::{json::Statement, problem::util::test_utils::TST};
+
+    #[test]
+    fn test_strip_html_and_strings() {
+        assert_eq!(
+            strip_html(
+                "<b>Row 1</b> &amp; <span class=\"x\">col&nbsp;2</span><br/>are <p>all different</p>"
+            ),
+            "Row 1 & col 2\nare\nall different"
+        );
+        assert_eq!(strip_html("a <bold>b</bold>"), "a b");
+        assert_eq!(pdf_string("x(1) ≠ 2 \\ é"), "(x\\(1\\) != 2 \\\\ \\351)");
+        assert!(wrap(&"word ".repeat(100)).all(|l| l.len() <= 100));
+    }
+
+    #[test]
+    fn test_trace_to_pdf() -> anyhow::Result<()> {
+        let problem: Problem = serde_json::from_reader(File::open(format!("{TST}/sudoku.json"))?)?;
+        let mut long = problem.clone();
+        let statement = Statement {
+            content: "<b>A</b> long statement, which has to be wrapped over several lines of the page as it does not fit across it".to_owned(),
+            classes: BTreeSet::new(),
+            constraint: None,
+            group: None,
+            scope: vec![],
+            scope_cells: vec![],
+            regions: vec![],
+            expression: None,
+        };
+        long.state.as_mut().unwrap().statements = Some(vec![statement; 30]);
+
+        let mut trace = SolveTrace::new();
+        for (title, problem) in [("Step 1", problem), ("Step 2", long)] {
+            trace.steps.push(TraceStep {
+                title: title.to_owned(),
+                problem,
+            });
+        }
+        let pdf = trace_to_pdf(&trace);
+        assert_eq!(pdf, trace_to_pdf(&trace));
+        assert!(pdf.starts_with(b"%PDF-1.4\n"));
+        assert!(pdf.ends_with(b"%%EOF\n"));
+
+        let text = String::from_utf8_lossy(&pdf);
+        // The second step does not fit on one page
+        assert_eq!(text.matches("/Type /Page ").count(), 3);
+
+        // Each entry of the cross reference table points at its object
+        let xref = text.find("\nxref\n").unwrap() + 1;
+        for (n, entry) in text[xref..].lines().skip(3).take(10).enumerate() {
+            let offset: usize = entry[..10].parse()?;
+            assert!(
+                pdf[offset..].starts_with(format!("{} 0 obj", n + 1).as_bytes()),
+                "{entry}"
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,279 @@
+//! The HTML of a [`PuzzlePlanner`]'s steps, drawn with
+//! [`create_html_with_options`] and the planner's
+//! [`PlannerConfig::html_options`](demystify_core::problem::planner::PlannerConfig::html_options).
+//!
+//! The planner itself only says what to draw, as a [`Problem`], so that it
+//! does not depend on this crate. Bring [`PlannerHtml`] into scope (it is in
+//! `demystify::prelude`) to call these methods on a planner.
+
+use anyhow::bail;
+use demystify_core::{
+    json::Problem,
+    problem::{
+        PuzLit,
+        planner::{PuzzlePlanner, Step},
+        solver::SearchBudget,
+    },
+};
+use rustsat::types::Lit;
+
+use crate::create_html_with_options;
+
+/// The methods of a [`PuzzlePlanner`] which return HTML.
+pub trait PlannerHtml {
+    /// Draws `problem` with the planner's options.
+    fn draw(&self, problem: &Problem) -> String;
+
+    /// Renders `step` as HTML, from the current state of the puzzle. This
+    /// does not change what is known, so should be called before
+    /// [`PuzzlePlanner::apply_step`].
+    fn render_step(&mut self, step: &Step) -> String;
+
+    /// Renders `step` as [`PlannerHtml::render_step`] does, then applies it,
+    /// keeping what was drawn in [`PuzzlePlanner::history`] if the step
+    /// deduced anything.
+    fn render_and_apply_step(&mut self, step: &Step) -> String;
+
+    /// Renders step `index` (counting from 0) of [`PuzzlePlanner::history`]
+    /// again, as it was drawn.
+    fn render_history_step(&self, index: usize) -> anyhow::Result<String>;
+
+    /// Renders the current state of the puzzle, without any deduction.
+    fn render_state(&mut self) -> String;
+
+    /// [`PuzzlePlanner::rules_problem`] as HTML.
+    fn rules_html(&mut self) -> String;
+
+    /// Solves the puzzle quickly and returns a sequence of steps in HTML format.
+    fn quick_solve_html(&mut self) -> String;
+
+    /// As [`PlannerHtml::quick_solve_html`], but each step's search stops
+    /// once `budget` runs out, or its flag is cancelled. The steps made
+    /// before that are still applied, but the result is an error.
+    fn quick_solve_html_within(&mut self, budget: &SearchBudget) -> anyhow::Result<String>;
+
+    /// Finds the next step, renders it and applies it, returning the HTML and
+    /// the literals deduced. The caller should not apply the literals again.
+    fn quick_solve_html_step(&mut self) -> (String, Vec<Lit>);
+
+    /// As [`PlannerHtml::quick_solve_html_step`], for the step given by
+    /// [`PuzzlePlanner::next_step_for_literal`].
+    ///
+    /// Literals which are already known, or cannot be deduced yet, are
+    /// described instead.
+    fn quick_solve_html_step_for_literal(&mut self, lit_def: Vec<i64>) -> (String, Vec<Lit>);
+
+    /// [`PuzzlePlanner::explain_set`] drawn as a step, with every one of
+    /// `lits` marked as deduced.
+    fn explain_set_html(&mut self, lits: &[PuzLit]) -> Option<String>;
+
+    /// [`PuzzlePlanner::quick_generate_difficulties`] as HTML.
+    fn quick_generate_html_difficulties(&mut self) -> String;
+
+    /// [`PuzzlePlanner::incremental_difficulties`] as HTML.
+    fn incremental_html_difficulties(&mut self) -> String;
+
+    /// [`PuzzlePlanner::incremental_difficulties_within`] as HTML.
+    fn incremental_html_difficulties_within(&mut self, budget: &SearchBudget) -> String;
+}
+
+impl PlannerHtml for PuzzlePlanner {
+    fn draw(&self, problem: &Problem) -> String {
+        create_html_with_options(problem, &self.config().html_options)
+    }
+
+    fn render_step(&mut self, step: &Step) -> String {
+        let problem = self.step_problem(step);
+        self.draw(&problem)
+    }
+
+    fn render_and_apply_step(&mut self, step: &Step) -> String {
+        let problem = self.record_and_apply_step(step);
+        self.draw(&problem)
+    }
+
+    fn render_history_step(&self, index: usize) -> anyhow::Result<String> {
+        let step = self.history().get(index)?;
+        Ok(self.draw(&step.problem))
+    }
+
+    fn render_state(&mut self) -> String {
+        let problem = self.state_problem("The initial puzzle state");
+        self.draw(&problem)
+    }
+
+    fn rules_html(&mut self) -> String {
+        let problem = self.rules_problem();
+        self.draw(&problem)
+    }
+
+    fn quick_solve_html(&mut self) -> String {
+        let options = self.config().html_options;
+        self.quick_solve_html_with(None, &|problem| create_html_with_options(problem, &options))
+            .0
+    }
+
+    fn quick_solve_html_within(&mut self, budget: &SearchBudget) -> anyhow::Result<String> {
+        let options = self.config().html_options;
+        let (html, finished) = self.quick_solve_html_with(Some(budget), &|problem| {
+            create_html_with_options(problem, &options)
+        });
+        if !finished {
+            bail!("The solve ran out of time before it finished");
+        }
+        Ok(html)
+    }
+
+    fn quick_solve_html_step(&mut self) -> (String, Vec<Lit>) {
+        let (problem, lits) = self.quick_solve_step();
+        (self.draw(&problem), lits)
+    }
+
+    fn quick_solve_html_step_for_literal(&mut self, lit_def: Vec<i64>) -> (String, Vec<Lit>) {
+        match self.quick_solve_step_for_literal(lit_def) {
+            Ok((problem, lits)) => (self.draw(&problem), lits),
+            Err(description) => (description, vec![]),
+        }
+    }
+
+    fn explain_set_html(&mut self, lits: &[PuzLit]) -> Option<String> {
+        let problem = self.explain_set_problem(lits)?;
+        Some(self.draw(&problem))
+    }
+
+    fn quick_generate_html_difficulties(&mut self) -> String {
+        let problem = self.quick_generate_difficulties();
+        self.draw(&problem)
+    }
+
+    fn incremental_html_difficulties(&mut self) -> String {
+        let problem = self.incremental_difficulties();
+        self.draw(&problem)
+    }
+
+    fn incremental_html_difficulties_within(&mut self, budget: &SearchBudget) -> String {
+        let problem = self.incremental_difficulties_within(budget);
+        self.draw(&problem)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use demystify_core::problem::{
+        PuzLit,
+        planner::{PlannerConfig, PuzzlePlanner},
+        solver::{CancelFlag, PuzzleSolver, SearchBudget},
+        util::test_utils::{sudoku_4x4_planner, sudoku_4x4_planner_with, sudoku_4x4_with},
+    };
+    use itertools::Itertools;
+
+    use super::PlannerHtml;
+
+    #[test]
+    fn test_quick_solve_html() {
+        let mut plan = sudoku_4x4_planner_with(PlannerConfig {
+            summary: true,
+            ..PlannerConfig::default()
+        });
+        let html = plan.quick_solve_html();
+        assert!(html.contains("<svg"));
+        assert!(html.contains("<b>Summary</b><br/>Solved in "));
+
+        let cancel = CancelFlag::default();
+        cancel.cancel();
+        let budget = SearchBudget {
+            cancel: Some(cancel),
+            ..SearchBudget::default()
+        };
+        let mut plan = sudoku_4x4_planner();
+        assert!(plan.quick_solve_html_within(&budget).is_err());
+        assert!(
+            plan.quick_solve_html_within(&SearchBudget::default())
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_render_and_apply_step() -> anyhow::Result<()> {
+        let mut plan = sudoku_4x4_planner();
+        let step = plan.next_step();
+        let html = plan.render_step(&step);
+        assert_eq!(plan.render_and_apply_step(&step), html);
+        assert_eq!(plan.steps_taken(), 1);
+        assert_eq!(plan.render_history_step(0)?, html);
+        assert!(plan.render_history_step(1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_html_statement_attributes() {
+        let mut plan = sudoku_4x4_planner();
+        let mut with_expressions = plan.fork();
+        with_expressions.config_mut().html_options.show_expressions = true;
+
+        let (html, lits) = plan.quick_solve_html_step();
+
+        assert!(!lits.is_empty());
+        assert!(html.contains("js_statement_search"));
+        assert!(html.contains(r#"data-group="0""#));
+        assert!(html.contains(r#"data-constraint=""#));
+        assert!(html.contains(r#"data-scope="grid_"#));
+        assert!(!html.contains(r#"class="expression""#));
+
+        let (html, _) = with_expressions.quick_solve_html_step();
+        assert!(html.contains(r#"class="expression""#));
+        // Expressions are escaped
+        assert!(html.contains("-&gt;"));
+    }
+
+    #[test]
+    fn test_explain_set_html() {
+        let mut plan = sudoku_4x4_planner();
+
+        let provable: Vec<PuzLit> = plan
+            .solver()
+            .get_provable_varlits()
+            .clone()
+            .iter()
+            .take(3)
+            .map(|l| plan.solver().lit_to_puzlit(l).first().unwrap().clone())
+            .collect();
+        let html = plan.explain_set_html(&provable).unwrap();
+        for lit in &provable {
+            let class = if lit.sign() { "litpos" } else { "litneg" };
+            let id = format!(
+                r#"id="D_{}_{}""#,
+                lit.var().indices().iter().join("_"),
+                lit.val()
+            );
+            let drawn = html.split("<g").find(|g| g.contains(&id)).unwrap();
+            assert!(drawn.contains(class), "{lit} is not marked in {drawn}");
+        }
+        assert_eq!(plan.steps_taken(), 0);
+    }
+
+    #[test]
+    fn test_rules_html() {
+        let mut plan = sudoku_4x4_planner();
+
+        let html = plan.rules_html();
+        assert!(html.contains(r#"class="rulecover highlight_con0""#));
+        assert!(html.contains(r#"class="rulecover highlight_con2 rulehidden""#));
+        assert!(html.contains(r#"data-rule="1""#));
+        assert!(html.contains("js_rule_toggle"));
+    }
+
+    #[test]
+    fn test_margin_stats_html() {
+        let puzzle = sudoku_4x4_with("sudoku-4x4-regions.json", "sudoku-4x4.dimacs");
+        let mut plan = PuzzlePlanner::new(PuzzleSolver::new(Arc::new(puzzle)).unwrap());
+
+        plan.config_mut().html_options.margin_stats = true;
+        let (html, _) = plan.quick_solve_html_step();
+        assert!(html.contains(r#"class="gridstats""#));
+        assert!(html.contains("<title>Row 1: 2 of 4 cells solved, "));
+        assert!(html.contains("<title>Column 4: "));
+    }
+}
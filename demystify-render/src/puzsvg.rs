@@ -0,0 +1,1218 @@
+#![allow(clippy::ptr_arg)]
+#![allow(clippy::needless_range_loop)]
+
+use std::collections::BTreeSet;
+
+pub use demystify_core::draw::CandidateLayout;
+use demystify_core::json::{CandidateCount, Class, LineKind, PathSpec, StateLit};
+
+use demystify_core::json::kinds::SupportedKinds;
+use demystify_core::json::{Problem, Puzzle};
+use demystify_core::problem::groups::{GridStats, GroupStats};
+use demystify_core::problem::rules::RuleCoverage;
+use itertools::Itertools;
+use svg::Node;
+
+use svg::node::element;
+
+struct Decorations {
+    sudoku_grid: bool,
+    blank_input_val: Option<i64>,
+}
+
+impl Decorations {
+    pub fn new(kind: &str) -> Decorations {
+        let features = SupportedKinds::features(kind);
+        Decorations {
+            sudoku_grid: features.sudoku_borders,
+            blank_input_val: features.blank_input_val,
+        }
+    }
+}
+
+pub struct PuzzleDraw {
+    base_width: f64,
+    mid_width: f64,
+    thick_width: f64,
+    decorations: Decorations,
+    binary: Option<bool>,
+    candidates: CandidateLayout,
+    thumbnail: Option<u32>,
+}
+
+impl Default for PuzzleDraw {
+    fn default() -> Self {
+        Self::new("")
+    }
+}
+
+impl PuzzleDraw {
+    #[must_use]
+    pub fn new(kind: &str) -> Self {
+        PuzzleDraw {
+            base_width: 0.005,
+            mid_width: 0.01,
+            thick_width: 0.02,
+            decorations: Decorations::new(kind),
+            binary: None,
+            candidates: CandidateLayout::default(),
+            thumbnail: None,
+        }
+    }
+
+    /// Whether to draw cells as filled (1) or empty (0) circles, rather than
+    /// as numbers. If `None`, this is done when the puzzle's
+    /// [`binary_domain`](Puzzle::binary_domain) is set.
+    #[must_use]
+    pub fn with_binary(mut self, binary: Option<bool>) -> Self {
+        self.binary = binary;
+        self
+    }
+
+    #[must_use]
+    pub fn with_candidates(mut self, candidates: CandidateLayout) -> Self {
+        self.candidates = candidates;
+        self
+    }
+
+    /// Draw a small preview, `size` pixels across, with only the given and
+    /// solved cells, the borders and the labels: no candidates, lines, rule
+    /// shading or margin statistics.
+    #[must_use]
+    pub fn with_thumbnail(mut self, size: Option<u32>) -> Self {
+        self.thumbnail = size;
+        self
+    }
+}
+
+impl PuzzleDraw {
+    #[must_use]
+    pub fn draw_puzzle(&self, puzjson: &Problem) -> svg::Document {
+        let puzzle = &puzjson.puzzle;
+        let binary = self.binary.unwrap_or(puzzle.binary_domain);
+
+        let thumbnail = self.thumbnail.is_some();
+
+        let mut out = self.draw_grid(puzzle);
+        // Under the cells, so the digits stay readable
+        if !thumbnail {
+            out.append(self.draw_lines(puzzle));
+        }
+        if let Some(rules) = puzjson
+            .state
+            .as_ref()
+            .and_then(|s| s.rule_coverage.as_ref())
+            .filter(|_| !thumbnail)
+        {
+            out.append(draw_rule_coverage(puzzle, rules));
+        }
+
+        let mut cells = self.make_cells(puzzle);
+
+        if let Some(start_grid) = &puzzle.start_grid {
+            self.fill_fixed_state(&mut cells, puzzle, start_grid, binary);
+        }
+
+        if let Some(state) = &puzjson.state {
+            if let Some(knowledge_grid) = &state.knowledge_grid {
+                self.fill_knowledge(&mut cells, puzzle, knowledge_grid, binary);
+            }
+            if let Some(candidate_counts) = &state.candidate_counts
+                && !thumbnail
+            {
+                self.fill_candidate_counts(&mut cells, candidate_counts);
+            }
+        }
+
+        /*
+            if ("solution_grid" in puzzle) {
+              const solutionCpy = structuredClone(puzzle["solution_grid"]);
+              if ("start_grid" in puzzle) {
+                for (let i = 0; i < puzzle["start_grid"].length; i++) {
+                  for (let j = 0; j < puzzle["start_grid"][i].length; j++) {
+                    const cell = puzzle["start_grid"][i][j];
+                    if (cell) {
+                      solutionCpy[i][j] = null;
+                    }
+                  }
+                }
+              }
+              this.fillFixedState(out, solutionCpy, { color: "grey" });
+            }
+        */
+
+        let mut cellgrp = element::Group::new();
+
+        for (i, row) in (1..).zip(cells) {
+            for (j, c) in (1..).zip(row) {
+                if puzzle.is_cell(&[i, j]) {
+                    cellgrp.append(c);
+                }
+            }
+        }
+
+        out.append(cellgrp);
+
+        let grid_stats = puzjson
+            .state
+            .as_ref()
+            .and_then(|s| s.grid_stats.as_ref())
+            .filter(|_| !thumbnail);
+        let out = self.fill_outside_labels(out, puzzle, grid_stats);
+
+        let mut final_grp = element::Group::new();
+        final_grp.assign("transform", "translate(50,50) scale(400)");
+        final_grp.append(out);
+
+        let size = self.thumbnail.unwrap_or(500);
+        let doc = svg::Document::new()
+            .set("viewBox", (0, 0, 500, 500))
+            .set("width", size)
+            .set("height", size)
+            .set("class", "puzzle");
+        doc.add(final_grp)
+    }
+
+    fn fill_outside_labels(
+        &self,
+        mut grid: element::Group,
+        p: &Puzzle,
+        grid_stats: Option<&GridStats>,
+    ) -> element::Group {
+        let mut label_group = element::Group::new();
+        label_group.assign("class", "labels");
+
+        let step = 1.0 / std::cmp::min(p.width, p.height) as f64;
+
+        let mut puz_bounds = (0.0, step * (p.width as f64), 0.0, step * (p.height as f64));
+
+        // Add top labels
+        let label_groups = [
+            &p.top_labels,
+            &p.bottom_labels,
+            &p.left_labels,
+            &p.right_labels,
+        ];
+
+        #[allow(clippy::type_complexity)]
+        let label_positions: Vec<(
+            Box<dyn Fn(usize) -> i64>,
+            Box<dyn Fn(usize) -> i64>,
+            Box<dyn Fn(&mut (f64, f64, f64, f64))>,
+        )> = vec![
+            (
+                Box::new(|i| i as i64),
+                Box::new(|_| -1),
+                Box::new(|bounds| bounds.0 -= step),
+            ),
+            (
+                Box::new(|i| i as i64),
+                Box::new(|_| p.height),
+                Box::new(|bounds| bounds.1 += step),
+            ),
+            (
+                Box::new(|_| -1),
+                Box::new(|i| i as i64),
+                Box::new(|bounds| bounds.2 -= step),
+            ),
+            (
+                Box::new(|_| p.width),
+                Box::new(|i| i as i64),
+                Box::new(|bounds| bounds.3 += step),
+            ),
+        ];
+
+        for (labels, position) in label_groups.iter().zip(label_positions.iter()) {
+            if let Some(labels) = labels {
+                // Update grid bounds
+                position.2(&mut puz_bounds);
+                for (i, label) in labels.iter().enumerate() {
+                    let mut node = svg::node::element::Text::new(label);
+                    node.assign("font-size", 1);
+                    node.assign("transform", "translate(0.2, 0.9)");
+                    let mut g = make_cell(position.0(i), position.1(i), step);
+                    g.append(node);
+                    label_group.append(g);
+                }
+            }
+        }
+
+        if let Some(grid_stats) = grid_stats {
+            label_group.append(draw_grid_stats(grid_stats, p, step, &mut puz_bounds));
+        }
+
+        grid.append(label_group);
+
+        let max_scale = f64::min(
+            1.0 / (-puz_bounds.0 + puz_bounds.1),
+            1.0 / (-puz_bounds.2 + puz_bounds.3),
+        );
+
+        let mut resized_grid = element::Group::new();
+        resized_grid.assign(
+            "transform",
+            format!(
+                "translate({},{}) scale({},{})",
+                -puz_bounds.0, -puz_bounds.2, max_scale, max_scale
+            ),
+        );
+        resized_grid.append(grid);
+
+        resized_grid
+    }
+
+    fn fixed_cell_is_used(&self, cell: Option<i64>) -> bool {
+        cell.is_some_and(|c| Some(c) != self.decorations.blank_input_val)
+    }
+
+    fn fill_fixed_state(
+        &self,
+        cells: &mut Vec<Vec<element::Group>>,
+        puzzle: &Puzzle,
+        contents: &Vec<Vec<Option<i64>>>,
+        binary: bool,
+    ) {
+        for i in 0..contents.len() {
+            for j in 0..contents[i].len() {
+                if self.fixed_cell_is_used(contents[i][j]) {
+                    let cell = contents[i][j].unwrap();
+                    if binary && (cell == 0 || cell == 1) {
+                        let mut group = element::Group::new();
+                        group.assign("class", "litgiven");
+                        group.append(binary_circle(cell, 0.5, 0.35));
+                        cells[i][j].append(group);
+                        continue;
+                    }
+
+                    let s = puzzle.value_text(cell);
+
+                    let mut node = svg::node::element::Text::new(s);
+                    node.assign("font-size", 1);
+                    node.assign("transform", "translate(0.2, 0.9)");
+                    node.assign("class", "litgiven");
+
+                    cells[i][j].append(node);
+                }
+            }
+        }
+    }
+
+    fn fill_knowledge(
+        &self,
+        cells: &mut Vec<Vec<element::Group>>,
+        puzzle: &Puzzle,
+        contents: &Vec<Vec<Option<Vec<StateLit>>>>,
+        binary: bool,
+    ) {
+        let fixed_contents = &puzzle.start_grid;
+        for i in 0..contents.len() {
+            for j in 0..contents[i].len() {
+                // The only reason we have 'fixed_contents' is because we do not want to
+                // put knowledge in these cells
+                if fixed_contents
+                    .as_ref()
+                    .is_some_and(|c| self.fixed_cell_is_used(c[i][j]))
+                {
+                    continue;
+                }
+
+                if let Some(cell) = &contents[i][j] {
+                    if self.thumbnail.is_some() && cell.len() != 1 {
+                        continue;
+                    }
+                    if binary && cell.iter().all(|l| l.val == 0 || l.val == 1) {
+                        self.fill_binary_cell(&mut cells[i][j], i, j, cell);
+                        continue;
+                    }
+
+                    self.fill_candidates(&mut cells[i][j], puzzle, i, j, cell);
+                }
+            }
+        }
+    }
+
+    /// Draws the candidates of cell `(i, j)` (counting from 0) in a grid,
+    /// leaving some out as set by [`CandidateLayout`].
+    fn fill_candidates(
+        &self,
+        cell: &mut element::Group,
+        puzzle: &Puzzle,
+        i: usize,
+        j: usize,
+        lits: &[StateLit],
+    ) {
+        let shown: Vec<&StateLit> = if self.candidates.hides(lits.len()) {
+            cell.append(candidate_badge(
+                lits,
+                lits.iter().all(|l| !is_highlighted(l)),
+            ));
+            lits.iter().filter(|l| is_highlighted(l)).collect()
+        } else {
+            lits.iter().collect()
+        };
+
+        // Find the right size of grid to fit our values in
+        let sqrt_length = (shown.len() as f64).sqrt().ceil() as usize;
+        let little_step = 0.9 / sqrt_length as f64;
+        for (n, state) in shown.into_iter().enumerate() {
+            let (a, b) = (n / sqrt_length, n % sqrt_length);
+            let s = puzzle.value_text(state.val);
+
+            let mut group = svg::node::element::Group::new();
+            group.assign(
+                "transform",
+                format!(
+                    "translate({}, {})",
+                    0.05 + (b as f64 * little_step),
+                    0.05 + (a as f64 + 1.0) * little_step
+                ),
+            );
+
+            let mut rect = svg::node::element::Rectangle::new();
+            rect.assign("width", little_step);
+            rect.assign("height", little_step);
+            rect.assign("y", -little_step);
+            rect.assign("class", "litbox");
+            group.append(rect);
+
+            let mut node = svg::node::element::Text::new(s);
+            node.assign("font-size", little_step);
+            node.assign("x", little_step / 2.0);
+            node.assign("y", -little_step / 3.0);
+            node.assign("dominant-baseline", "middle");
+            node.assign("text-anchor", "middle");
+
+            group.append(node);
+
+            mark_literal(&mut group, i, j, state);
+
+            cell.append(group);
+        }
+    }
+
+    /// Draws a cell of a 0/1 grid. Once the value of the cell is decided,
+    /// because only one candidate is left or one was just deduced (either
+    /// way), it is drawn as a single large circle, so a deduced 0 is shown as
+    /// a filled circle. Otherwise both candidates are drawn as small circles.
+    fn fill_binary_cell(&self, cell: &mut element::Group, i: usize, j: usize, lits: &[StateLit]) {
+        let decided = if let [lit] = lits {
+            Some((lit, lit.val))
+        } else if let Some(lit) = lits.iter().find(|l| l.classes.contains(&Class::LitPos)) {
+            Some((lit, lit.val))
+        } else {
+            lits.iter()
+                .find(|l| l.classes.contains(&Class::LitNeg))
+                .map(|lit| (lit, 1 - lit.val))
+        };
+
+        if let Some((lit, val)) = decided {
+            let mut group = element::Group::new();
+            let mut rect = element::Rectangle::new();
+            rect.assign("width", 1);
+            rect.assign("height", 1);
+            rect.assign("class", "litbox");
+            group.append(rect);
+            group.append(binary_circle(val, 0.5, 0.35));
+            mark_literal(&mut group, i, j, lit);
+            cell.append(group);
+            return;
+        }
+
+        for lit in lits {
+            let x = if lit.val == 0 { 0.05 } else { 0.5 };
+            let mut group = element::Group::new();
+            group.assign("transform", format!("translate({x}, 0.275)"));
+            let mut rect = element::Rectangle::new();
+            rect.assign("width", 0.45);
+            rect.assign("height", 0.45);
+            rect.assign("class", "litbox");
+            group.append(rect);
+            group.append(binary_circle(lit.val, 0.225, 0.15));
+            mark_literal(&mut group, i, j, lit);
+            cell.append(group);
+        }
+    }
+
+    /// Writes 'min-max' candidates in the bottom corner of each cell, with the
+    /// mean in a tooltip.
+    fn fill_candidate_counts(
+        &self,
+        cells: &mut Vec<Vec<element::Group>>,
+        counts: &Vec<Vec<Option<CandidateCount>>>,
+    ) {
+        for i in 0..counts.len().min(cells.len()) {
+            for j in 0..counts[i].len().min(cells[i].len()) {
+                if let Some(count) = &counts[i][j] {
+                    let title = element::Title::new(format!(
+                        "Candidates over {} steps: min {}, max {}, mean {:.1}",
+                        count.steps,
+                        count.min,
+                        count.max,
+                        count.mean()
+                    ));
+
+                    let mut node = element::Text::new(format!("{}-{}", count.min, count.max));
+                    node.assign("font-size", 0.12);
+                    node.assign("x", 1.04);
+                    node.assign("y", 1.04);
+                    node.assign("text-anchor", "end");
+                    node.assign("class", "candidatecount");
+                    node.append(title);
+
+                    cells[i][j].append(node);
+                }
+            }
+        }
+    }
+
+    fn draw_grid(&self, puzzle: &Puzzle) -> element::Group {
+        let mut topgrp = element::Group::new();
+
+        let mut grp = element::Group::new();
+
+        let width = usize::try_from(puzzle.width).expect("negative width?");
+        let height = usize::try_from(puzzle.height).expect("negative height?");
+        let cages = &puzzle.cages;
+        // Whether the cell in row `j` and column `i` (counting from 0) is
+        // left out by the mask
+        let hole = |j: usize, i: usize| !puzzle.is_cell(&[j as i64 + 1, i as i64 + 1]);
+
+        let step = 1.0 / std::cmp::min(width, height) as f64;
+
+        let colours_list = [
+            "#85586f", "#d6efed", "#957dad", "#ac7d88", "#b7d3df", "#e0bbe4", "#deb6ab", "#c9bbcf",
+            "#fec8d8", "#f8ecd1", "#898aa6", "#ffdfd3", "#c4dfaa", "#f5f0bb", "#e6e1cd", "#d6b1dd",
+        ];
+
+        let mut cagegrp = element::Group::new();
+
+        if let Some(cages) = &cages {
+            let colours: BTreeSet<_> = cages.iter().flatten().filter_map(|cell| *cell).collect();
+
+            for i in 0..width {
+                for j in 0..height {
+                    if let Some(cell) = cages[j][i]
+                        && !hole(j, i)
+                    {
+                        let col = colours.iter().position(|&c| c == cell).unwrap();
+                        let i_f = i as f64;
+                        let j_f = j as f64;
+                        let path = format!(
+                            "M {} {} H {} V {} H {} Z",
+                            step * i_f,
+                            step * j_f,
+                            step * (i_f + 1.0),
+                            step * (j_f + 1.0),
+                            step * i_f
+                        );
+
+                        let mut p = element::Path::new();
+                        p.assign("d", path);
+                        p.assign("fill", colours_list[col]);
+                        cagegrp.append(p);
+                    }
+                }
+            }
+        }
+
+        grp.append(cagegrp);
+
+        let mut outlinegrp = element::Group::new();
+
+        // Edges between two cells are drawn thin (unless they are the border
+        // of a box or cage), edges with a cell on one side form the outline,
+        // and edges between two holes are not drawn
+        for i in 0..=width {
+            for j in 0..height {
+                let left = i > 0 && !hole(j, i - 1);
+                let right = i < width && !hole(j, i);
+                if !left && !right {
+                    continue;
+                }
+                let mut stroke = self.base_width;
+                if !(left && right) {
+                    stroke = self.thick_width;
+                } else {
+                    if self.decorations.sudoku_grid && i % 3 == 0 {
+                        stroke = self.mid_width;
+                    }
+                    if let Some(cages) = cages {
+                        if cages[j][i] != cages[j][i - 1] {
+                            stroke = self.thick_width;
+                        }
+                    }
+                }
+                let i_f = i as f64;
+                let j_f = j as f64;
+
+                let path = format!(
+                    "M {} {} L {} {}",
+                    step * i_f,
+                    step * j_f,
+                    step * i_f,
+                    step * (j_f + 1.0)
+                );
+                let mut p = element::Path::new();
+                p.assign("d", path);
+                p.assign("stroke", "black");
+                p.assign("stroke-width", stroke);
+                p.assign("stroke-linecap", "round");
+                outlinegrp = outlinegrp.add(p);
+            }
+        }
+
+        for i in 0..width {
+            for j in 0..=height {
+                let above = j > 0 && !hole(j - 1, i);
+                let below = j < height && !hole(j, i);
+                if !above && !below {
+                    continue;
+                }
+                let mut stroke = self.base_width;
+                if !(above && below) {
+                    stroke = self.thick_width;
+                } else {
+                    if self.decorations.sudoku_grid && j % 3 == 0 {
+                        stroke = self.mid_width;
+                    }
+                    if let Some(cages) = cages {
+                        if cages[j][i] != cages[j - 1][i] {
+                            stroke = self.thick_width;
+                        }
+                    }
+                }
+                let i_f = i as f64;
+                let j_f = j as f64;
+
+                let path = format!(
+                    "M {} {} L {} {}",
+                    step * i_f,
+                    step * j_f,
+                    step * (i_f + 1.0),
+                    step * j_f
+                );
+                let mut p = element::Path::new();
+                p.assign("d", path);
+                p.assign("stroke", "black");
+                p.assign("stroke-width", stroke);
+                p.assign("stroke-linecap", "round");
+                outlinegrp.append(p);
+            }
+        }
+
+        grp.append(outlinegrp);
+
+        // Drawn over the grid lines, in a colour so they are not confused
+        // with the borders of cages
+        let mut regiongrp = element::Group::new();
+        regiongrp.assign("class", "regionborders");
+
+        if let Some(regions) = &puzzle.regions {
+            let mut border = |x1: usize, y1: usize, x2: usize, y2: usize| {
+                let path = format!(
+                    "M {} {} L {} {}",
+                    step * x1 as f64,
+                    step * y1 as f64,
+                    step * x2 as f64,
+                    step * y2 as f64
+                );
+                let mut p = element::Path::new();
+                p.assign("d", path);
+                p.assign("stroke", "#b03a2e");
+                p.assign("stroke-width", self.thick_width);
+                p.assign("stroke-linecap", "round");
+                regiongrp.append(p);
+            };
+
+            for j in 0..height {
+                for i in 1..width {
+                    if regions[j][i] != regions[j][i - 1] && !hole(j, i) && !hole(j, i - 1) {
+                        border(i, j, i, j + 1);
+                    }
+                }
+            }
+            for j in 1..height {
+                for i in 0..width {
+                    if regions[j][i] != regions[j - 1][i] && !hole(j, i) && !hole(j - 1, i) {
+                        border(i, j, i + 1, j);
+                    }
+                }
+            }
+        }
+
+        grp.append(regiongrp);
+
+        topgrp.append(grp);
+        topgrp
+    }
+
+    /// Draws each of [`Puzzle::lines`] through the centres of its cells.
+    fn draw_lines(&self, puzzle: &Puzzle) -> element::Group {
+        let step = 1.0 / std::cmp::min(puzzle.width, puzzle.height) as f64;
+
+        let mut linegrp = element::Group::new();
+        linegrp.assign("class", "lines");
+        for line in &puzzle.lines {
+            linegrp.append(draw_line(line, step));
+        }
+        linegrp
+    }
+
+    fn make_cells(&self, puzzle: &Puzzle) -> Vec<Vec<element::Group>> {
+        let step = 1.0 / std::cmp::min(puzzle.width, puzzle.height) as f64;
+
+        let mut out = Vec::new();
+        for i in 0..puzzle.height {
+            out.push(vec![]);
+            for j in 0..puzzle.width {
+                let g = make_cell(i, j, step);
+
+                out.last_mut().unwrap().push(g);
+            }
+        }
+
+        out
+    }
+}
+
+/// Makes `group` a clickable literal, for the value `state` of cell `(i, j)`
+/// (counting from 0).
+fn mark_literal(group: &mut element::Group, i: usize, j: usize, state: &StateLit) {
+    let id = format!("D_{}_{}_{}", i + 1, j + 1, state.val);
+    group.assign("id", id.clone());
+    group.assign("name", id);
+    group.assign("hx-post", "/clickLiteral");
+    group.assign("hx-target", "#mainSpace");
+    let classes = std::iter::once("literal".to_owned())
+        .chain(state.classes.iter().map(Class::to_string))
+        .join(" ");
+    group.assign("class", classes);
+}
+
+/// Whether a candidate is drawn by [`CandidateLayout::only_highlighted`].
+fn is_highlighted(lit: &StateLit) -> bool {
+    lit.classes.iter().any(|c| {
+        matches!(
+            c,
+            Class::LitPos | Class::LitNeg | Class::LitInMus | Class::LitPinned
+        )
+    })
+}
+
+/// The number of candidates in a cell, for when some are not drawn, with
+/// them all listed in a tooltip. It fills the cell if `alone`, and is in the
+/// top corner otherwise.
+fn candidate_badge(lits: &[StateLit], alone: bool) -> element::Text {
+    let title = element::Title::new(format!(
+        "{} candidates: {}",
+        lits.len(),
+        lits.iter().map(|l| l.val).join(", ")
+    ));
+    let mut node = element::Text::new(lits.len().to_string());
+    if alone {
+        node.assign("font-size", 0.5);
+        node.assign("x", 0.5);
+        node.assign("y", 0.5);
+        node.assign("dominant-baseline", "middle");
+    } else {
+        node.assign("font-size", 0.2);
+        node.assign("x", 1.04);
+        node.assign("y", 0.1);
+    }
+    node.assign("text-anchor", if alone { "middle" } else { "end" });
+    node.assign("class", "candidatebadge");
+    node.append(title);
+    node
+}
+
+/// Draws `line`, in a grid whose cells are `step` wide.
+fn draw_line(line: &PathSpec, step: f64) -> element::Group {
+    let points: Vec<(f64, f64)> = line
+        .cells
+        .iter()
+        .filter_map(|cell| match cell[..] {
+            [row, col] => Some((step * (col as f64 - 0.5), step * (row as f64 - 0.5))),
+            _ => None,
+        })
+        .collect();
+    let (kind, colour, width) = match line.kind {
+        LineKind::Thermo => ("thermo", "#c8c8c8", 0.25),
+        LineKind::Arrow => ("arrow", "#a0a0a0", 0.04),
+        LineKind::Whisper => ("whisper", "#60c060", 0.12),
+    };
+
+    let mut group = element::Group::new();
+    let classes = ["line".to_owned(), format!("line_{kind}")]
+        .into_iter()
+        .chain(line.classes.iter().map(Class::to_string))
+        .join(" ");
+    group.assign("class", classes);
+
+    let Some(&(x0, y0)) = points.first() else {
+        return group;
+    };
+
+    let mut d = format!("M {x0} {y0}");
+    for (x, y) in &points[1..] {
+        d += &format!(" L {x} {y}");
+    }
+    // The head of an arrow, pointing along its last segment
+    if line.kind == LineKind::Arrow
+        && let [.., (xa, ya), (xb, yb)] = points[..]
+    {
+        let len = f64::hypot(xb - xa, yb - ya);
+        let (dx, dy) = ((xb - xa) / len * step, (yb - ya) / len * step);
+        let back = (xb - 0.25 * dx, yb - 0.25 * dy);
+        d += &format!(
+            " M {} {} L {xb} {yb} L {} {}",
+            back.0 - 0.15 * dy,
+            back.1 + 0.15 * dx,
+            back.0 + 0.15 * dy,
+            back.1 - 0.15 * dx
+        );
+    }
+
+    let mut path = element::Path::new();
+    path.assign("d", d);
+    path.assign("fill", "none");
+    path.assign("stroke", colour);
+    path.assign("stroke-width", width * step);
+    path.assign("stroke-linecap", "round");
+    path.assign("stroke-linejoin", "round");
+
+    // Thermometers start from a bulb, and arrows from a circle
+    match line.kind {
+        LineKind::Thermo => {
+            let mut bulb = element::Circle::new();
+            bulb.assign("cx", x0);
+            bulb.assign("cy", y0);
+            bulb.assign("r", 0.35 * step);
+            bulb.assign("fill", colour);
+            bulb.assign("class", "bulb");
+            group.append(bulb);
+        }
+        LineKind::Arrow => {
+            let mut circle = element::Circle::new();
+            circle.assign("cx", x0);
+            circle.assign("cy", y0);
+            circle.assign("r", 0.4 * step);
+            circle.assign("fill", "white");
+            circle.assign("stroke", colour);
+            circle.assign("stroke-width", width * step);
+            group.append(path);
+            group.append(circle);
+            return group;
+        }
+        LineKind::Whisper => {}
+    }
+    group.append(path);
+    group
+}
+
+/// A circle of radius `r` centred at `(c, c)`, filled for 1 and empty for 0.
+fn binary_circle(val: i64, c: f64, r: f64) -> element::Circle {
+    let mut circle = element::Circle::new();
+    circle.assign("cx", c);
+    circle.assign("cy", c);
+    circle.assign("r", r);
+    circle.assign("class", if val == 1 { "binaryone" } else { "binaryzero" });
+    circle
+}
+
+/// Shades the cells covered by each rule, in the colour of
+/// `highlight_con<n>` for the `n`th. Only the first is shown to start with,
+/// the others are shown by ticking them in the list of rules.
+fn draw_rule_coverage(puzzle: &Puzzle, rules: &[RuleCoverage]) -> element::Group {
+    let step = 1.0 / std::cmp::min(puzzle.width, puzzle.height) as f64;
+
+    let mut group = element::Group::new();
+    group.assign("class", "rules");
+    for (n, rule) in rules.iter().enumerate() {
+        let mut cover = element::Group::new();
+        let hidden = if n == 0 { "" } else { " rulehidden" };
+        cover.assign("class", format!("rulecover highlight_con{n}{hidden}"));
+        cover.assign("data-rule", n);
+        cover.append(element::Title::new(rule.describe()));
+        for cell in &rule.cells {
+            let mut rect = element::Rectangle::new();
+            rect.assign("x", step * (cell[1] - 1) as f64);
+            rect.assign("y", step * (cell[0] - 1) as f64);
+            rect.assign("width", step);
+            rect.assign("height", step);
+            cover.append(rect);
+        }
+        group.append(cover);
+    }
+    group
+}
+
+/// Writes how far each row is solved after its last cell, and each column
+/// below its last cell, beyond any labels there, with the rest of the
+/// [`GridStats`] in a tooltip. `bounds` is grown to fit them.
+fn draw_grid_stats(
+    grid_stats: &GridStats,
+    p: &Puzzle,
+    step: f64,
+    bounds: &mut (f64, f64, f64, f64),
+) -> element::Group {
+    let mut group = element::Group::new();
+    group.assign("class", "gridstats");
+
+    if !grid_stats.rows.is_empty() {
+        let col = p.width + i64::from(p.bottom_labels.is_some());
+        bounds.1 = bounds.1.max(step * (col + 1) as f64);
+        for stats in &grid_stats.rows {
+            group.append(group_stats_cell(stats, stats.index - 1, col, step));
+        }
+    }
+    if !grid_stats.columns.is_empty() {
+        let row = p.height + i64::from(p.right_labels.is_some());
+        bounds.3 = bounds.3.max(step * (row + 1) as f64);
+        for stats in &grid_stats.columns {
+            group.append(group_stats_cell(stats, row, stats.index - 1, step));
+        }
+    }
+    group
+}
+
+/// The label of `stats`, in the cell `(i, j)` (counting from 0) of the margin.
+fn group_stats_cell(stats: &GroupStats, i: i64, j: i64, step: f64) -> element::Group {
+    let mut node = element::Text::new(stats.label());
+    node.assign("font-size", 0.4);
+    node.assign("transform", "translate(0.1, 0.65)");
+    node.append(element::Title::new(stats.describe()));
+    let mut g = make_cell(i, j, step);
+    g.append(node);
+    g
+}
+
+fn make_cell(i: i64, j: i64, step: f64) -> element::Group {
+    let i_f = i as f64;
+    let j_f = j as f64;
+
+    let mut g = element::Group::new();
+    g.assign("id", format!("C_{}_{}", i + 1, j + 1));
+    g.assign(
+        "transform",
+        format!(
+            "translate({} {}) scale({})",
+            step * (j_f + 0.05),
+            step * (i_f + 0.05),
+            step * 0.9
+        ),
+    );
+    g
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{BTreeMap, BTreeSet},
+        fs::File,
+        sync::Arc,
+    };
+
+    use test_log::test;
+
+    use demystify_core::{
+        json::{Class, DescriptionStatement, LineKind, Problem, Puzzle, State, StateLit},
+        problem::{
+            solver::PuzzleSolver,
+            util::test_utils::{TST, sudoku_4x4_with},
+        },
+    };
+
+    use crate::puzsvg::{CandidateLayout, PuzzleDraw};
+
+    #[test]
+    fn test_svg_sudoku() -> anyhow::Result<()> {
+        let svg_path = format!("{TST}/sudoku.json");
+
+        let file = File::open(svg_path)?;
+        let problem: Problem = serde_json::from_reader(file)?;
+
+        let puz_draw = PuzzleDraw::new(&problem.puzzle.kind);
+
+        let svg = puz_draw.draw_puzzle(&problem);
+
+        let _ = svg.to_string();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_svg_regions() -> anyhow::Result<()> {
+        let puz = sudoku_4x4_with("sudoku-4x4-regions.json", "sudoku-4x4.dimacs");
+        let mut problem = Problem::new_from_puzzle(&puz)?;
+
+        let svg = PuzzleDraw::new("Star-Battle")
+            .draw_puzzle(&problem)
+            .to_string();
+        // The four 2x2 regions are split by a line across and a line down,
+        // each four cells long
+        let regions = svg.split(r#"class="regionborders""#).nth(1).unwrap();
+        let regions = &regions[..regions.find("</g>").unwrap()];
+        assert_eq!(regions.matches("<path").count(), 8);
+
+        problem.puzzle.regions = None;
+        let svg = PuzzleDraw::new("Star-Battle")
+            .draw_puzzle(&problem)
+            .to_string();
+        assert!(!svg.contains("#b03a2e"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_svg_mask() -> anyhow::Result<()> {
+        let puz = sudoku_4x4_with("sudoku-4x4-mask.json", "sudoku-4x4.dimacs");
+        let solver = PuzzleSolver::new(Arc::new(puz))?;
+        let problem = Problem::new_from_puzzle_and_state(
+            &solver,
+            &solver.puzzleparse().all_var_varvals(),
+            &BTreeSet::new(),
+            &BTreeSet::new(),
+            "",
+        )?;
+        let count_edges = |svg: &str| svg.matches(r#"stroke="black""#).count();
+
+        let svg = PuzzleDraw::new("Sudoku").draw_puzzle(&problem).to_string();
+        assert!(!svg.contains(r#"id="C_1_2""#));
+        assert!(!svg.contains("D_1_2_"));
+        assert!(!svg.contains(r#"id="C_4_4""#));
+        assert!(svg.contains(r#"id="D_1_3_2""#));
+
+        // Only the edges on the outside of the board are lost, two for the
+        // corner and one for the cell on the top row, as their other edges
+        // become part of the outline
+        let mut full = problem.clone();
+        full.puzzle.mask = None;
+        let full = PuzzleDraw::new("Sudoku").draw_puzzle(&full).to_string();
+        assert_eq!(count_edges(&full), 40);
+        assert_eq!(count_edges(&svg), 37);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_svg_lines() -> anyhow::Result<()> {
+        let puz = sudoku_4x4_with("sudoku-4x4-lines.json", "sudoku-4x4.dimacs");
+        let solver = PuzzleSolver::new(Arc::new(puz))?;
+
+        let problem = Problem::new_from_puzzle(solver.puzzleparse())?;
+        let lines = &problem.puzzle.lines;
+        assert_eq!(
+            lines.iter().map(|l| l.kind).collect::<Vec<_>>(),
+            vec![
+                LineKind::Thermo,
+                LineKind::Thermo,
+                LineKind::Arrow,
+                LineKind::Whisper
+            ]
+        );
+        // The second thermometer is padded with a 0 cell
+        assert_eq!(lines[1].cells, vec![vec![4, 4], vec![3, 4]]);
+        assert!(problem.warnings.is_empty());
+
+        let svg = PuzzleDraw::new("Sudoku").draw_puzzle(&problem).to_string();
+        assert_eq!(svg.matches(r#"class="line line_thermo""#).count(), 2);
+        assert_eq!(svg.matches(r#"class="bulb""#).count(), 2);
+        assert!(svg.contains(r#"class="line line_arrow""#));
+
+        // A constraint on the two cells of the whisper highlights it
+        let deduction = DescriptionStatement::new(
+            "Something".to_owned(),
+            vec!["cells (1,1) and (1,2) cannot both be 3 as they are in the same row".to_owned()],
+        );
+        let problem = Problem::new_from_puzzle_and_mus(
+            &solver,
+            &solver.puzzleparse().all_var_varvals(),
+            &BTreeSet::new(),
+            &BTreeSet::new(),
+            &[deduction],
+            "",
+        )?;
+        let lines = &problem.puzzle.lines;
+        assert!(lines[3].classes.contains(&Class::Highlight(0)));
+        assert!(lines[..3].iter().all(|l| l.classes.is_empty()));
+
+        let svg = PuzzleDraw::new("Sudoku").draw_puzzle(&problem).to_string();
+        assert!(
+            svg.contains(r#"class="line line_whisper highlight_con0 js_highlighter litinmus""#)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_svg_binary() {
+        let lit = |val, classes: &[Class]| StateLit {
+            val,
+            classes: classes.iter().cloned().collect(),
+            meta: None,
+        };
+        let problem = Problem {
+            puzzle: Puzzle {
+                kind: "Binairo".to_owned(),
+                width: 3,
+                height: 1,
+                start_grid: None,
+                solution_grid: None,
+                cages: None,
+                regions: None,
+                top_labels: None,
+                bottom_labels: None,
+                left_labels: None,
+                right_labels: None,
+                binary_domain: true,
+                lines: vec![],
+                mask: None,
+                value_glyphs: BTreeMap::new(),
+                grid_map: None,
+            },
+            state: Some(State {
+                knowledge_grid: Some(vec![vec![
+                    Some(vec![lit(0, &[]), lit(1, &[])]),
+                    Some(vec![lit(0, &[Class::LitNeg]), lit(1, &[])]),
+                    Some(vec![lit(0, &[Class::LitKnown])]),
+                ]]),
+                statements: None,
+                description: None,
+                candidate_counts: None,
+                explanation: None,
+                grid_stats: None,
+                rule_coverage: None,
+            }),
+            warnings: vec![],
+        };
+
+        let svg = PuzzleDraw::new("Binairo").draw_puzzle(&problem).to_string();
+        assert_eq!(svg.matches("<circle").count(), 4);
+        assert_eq!(svg.matches("binaryone").count(), 2);
+        assert!(!svg.contains("<text"));
+        // Ruling out 0 shows a 1, but clicking it still explains the deduction
+        assert!(svg.contains(r#"id="D_1_2_0""#));
+
+        let svg = PuzzleDraw::new("Binairo")
+            .with_binary(Some(false))
+            .draw_puzzle(&problem)
+            .to_string();
+        assert!(!svg.contains("<circle"));
+    }
+
+    #[test]
+    fn test_svg_candidate_layout() {
+        // One cell for each domain size, with the first value deduced
+        let cell = |size: i64| {
+            Some(
+                (1..=size)
+                    .map(|val| StateLit {
+                        val,
+                        classes: if val == 1 {
+                            [Class::LitPos].into()
+                        } else {
+                            BTreeSet::new()
+                        },
+                        meta: None,
+                    })
+                    .collect(),
+            )
+        };
+        let sizes = [4, 9, 16, 25];
+        let problem = Problem {
+            puzzle: Puzzle {
+                kind: "Sudoku".to_owned(),
+                width: 4,
+                height: 1,
+                start_grid: None,
+                solution_grid: None,
+                cages: None,
+                regions: None,
+                top_labels: None,
+                bottom_labels: None,
+                left_labels: None,
+                right_labels: None,
+                binary_domain: false,
+                lines: vec![],
+                mask: None,
+                value_glyphs: BTreeMap::new(),
+                grid_map: None,
+            },
+            state: Some(State {
+                knowledge_grid: Some(vec![sizes.iter().map(|&n| cell(n)).collect()]),
+                statements: None,
+                description: None,
+                candidate_counts: None,
+                explanation: None,
+                grid_stats: None,
+                rule_coverage: None,
+            }),
+            warnings: vec![],
+        };
+        let draw = |candidates| {
+            PuzzleDraw::new("Sudoku")
+                .with_candidates(candidates)
+                .draw_puzzle(&problem)
+                .to_string()
+        };
+        let drawn = |svg: &str, col: usize| svg.matches(&format!("id=\"D_1_{col}_")).count();
+        let badges = |svg: &str| svg.matches(r#"class="candidatebadge""#).count();
+
+        let svg = draw(CandidateLayout::default());
+        for (col, &size) in (1..).zip(&sizes) {
+            assert_eq!(drawn(&svg, col), size as usize);
+        }
+        assert_eq!(badges(&svg), 0);
+
+        // Cells which do not fit in 3x3 show only the deduced value
+        let svg = draw(CandidateLayout {
+            max_grid: Some(3),
+            ..CandidateLayout::default()
+        });
+        let counts: Vec<_> = (1..=4).map(|col| drawn(&svg, col)).collect();
+        assert_eq!(counts, [4, 9, 1, 1]);
+        assert_eq!(badges(&svg), 2);
+        assert!(svg.contains("16 candidates: 1, 2, 3,"));
+
+        let svg = draw(CandidateLayout {
+            hide_above: Some(4),
+            ..CandidateLayout::default()
+        });
+        let counts: Vec<_> = (1..=4).map(|col| drawn(&svg, col)).collect();
+        assert_eq!(counts, [4, 1, 1, 1]);
+
+        let svg = draw(CandidateLayout {
+            only_highlighted: true,
+            ..CandidateLayout::default()
+        });
+        assert!((1..=4).all(|col| drawn(&svg, col) == 1));
+        assert_eq!(badges(&svg), 4);
+        // The deduced value is drawn as large as a cell with one candidate
+        assert!(svg.contains(r#"<text dominant-baseline="middle" font-size="0.9""#));
+
+        // With nothing highlighted, the count fills the cell
+        let mut plain = problem.clone();
+        for lit in plain
+            .state
+            .as_mut()
+            .unwrap()
+            .knowledge_grid
+            .as_mut()
+            .unwrap()[0]
+            .iter_mut()
+            .flatten()
+            .flatten()
+        {
+            lit.classes.clear();
+        }
+        let svg = PuzzleDraw::new("Sudoku")
+            .with_candidates(CandidateLayout {
+                only_highlighted: true,
+                ..CandidateLayout::default()
+            })
+            .draw_puzzle(&plain)
+            .to_string();
+        assert_eq!(svg.matches("D_1_").count(), 0);
+        assert_eq!(svg.matches(r#"font-size="0.5""#).count(), 4);
+    }
+}
@@ -0,0 +1,570 @@
+//! Building the web server from a [`WebConfig`].
+
+use std::convert::Infallible;
+
+use anyhow::Context;
+use axum::body::Body;
+use axum::extract::DefaultBodyLimit;
+use axum::http::{HeaderValue, Request};
+use axum::response::Response;
+use axum::routing::post;
+use axum::{Json, Router, routing::get};
+use axum_session::{Session, SessionConfig, SessionLayer, SessionNullPool, SessionStore};
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpListener;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+use crate::config::{SessionBackend, WebConfig};
+use crate::{campaign, docs, limits, race, sessions, timing, wrap};
+
+/// Builds the router for the whole site, as described by `config`.
+pub async fn router(config: &WebConfig) -> anyhow::Result<Router> {
+    let session_store = match config.session_backend {
+        SessionBackend::Memory => {
+            let session_config =
+                SessionConfig::default().with_table_name(config.session_table.clone());
+            SessionStore::<SessionNullPool>::new(None, session_config)
+                .await
+                .context("Failed to create session store")?
+        }
+    };
+
+    let cors = if config.cors_origins.is_empty() {
+        CorsLayer::new().allow_origin(Any)
+    } else {
+        let origins = config
+            .cors_origins
+            .iter()
+            .map(|o| HeaderValue::from_str(o).with_context(|| format!("Invalid CORS origin '{o}'")))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        CorsLayer::new().allow_origin(AllowOrigin::list(origins))
+    };
+
+    limits::set_limits(config.puzzle_limits);
+    limits::set_upload_limit(config.upload_limit);
+    sessions::set_session_dir(config.session_dir.clone());
+    sessions::set_history(config.history_config()?);
+    wrap::set_url_import(config.url_import_limits());
+    if let Some(secs) = config.session_idle_timeout {
+        sessions::spawn_eviction(std::time::Duration::from_secs(secs));
+    }
+    race::spawn_eviction();
+
+    if let Some(dir) = &config.examples_dir {
+        let count = wrap::load_examples_dir(dir)?;
+        eprintln!("Loaded {count} examples from {dir:?}");
+    }
+    if let Some(path) = &config.campaign {
+        let count = campaign::load(path)?;
+        eprintln!("Loaded a campaign of {count} levels from {path:?}");
+    }
+
+    macro_rules! serve_static_file {
+        ($path:expr) => {
+            get(move |_: Request<Body>| async {
+                let file_content: &'static str =
+                    include_str!(concat!(env!("CARGO_MANIFEST_DIR"), $path));
+                Ok::<_, Infallible>(Response::new(Body::from(file_content)))
+            })
+        };
+    }
+
+    let mut app = Router::new()
+        .route("/greet", get(greet))
+        .route("/greetX", get(greet_x))
+        .route("/getExampleNames", get(wrap::get_example_names))
+        .route("/uploadPuzzle", post(wrap::upload_files))
+        .route("/importUrl", post(wrap::import_url))
+        .route("/loadExample", post(wrap::load_example))
+        .route("/submitExample", post(wrap::submit_example))
+        .route("/refresh", post(wrap::refresh))
+        .route("/showRules", post(wrap::show_rules))
+        .route("/defaultPuzzle", post(wrap::default_puzzle))
+        .route("/quickFullSolve", post(wrap::dump_full_solve))
+        .route("/bestNextStep", post(wrap::best_next_step))
+        .route("/operation", get(wrap::get_operation))
+        .route("/operation/cancel", post(wrap::cancel_operation))
+        .route("/getDifficulties", post(wrap::get_difficulties))
+        .route("/clickLiteral", post(wrap::click_literal))
+        .route("/pinLiteral", post(wrap::pin_literal))
+        .route("/deduceInRegion", post(wrap::deduce_in_region))
+        .route("/notes", get(wrap::get_notes))
+        .route("/gridStats", get(wrap::grid_stats))
+        .route("/notes/step", post(wrap::note_step))
+        .route("/notes/cell", post(wrap::note_cell))
+        .route("/exportWalkthrough", get(wrap::export_walkthrough))
+        .route("/thumbnail", get(wrap::thumbnail))
+        .route("/pastStep", get(wrap::past_step))
+        .route("/setSettings", post(wrap::set_settings))
+        .route("/exportSession", get(wrap::export_session))
+        .route("/importSession", post(wrap::import_session))
+        .route("/toolLogs", get(wrap::tool_logs))
+        .route("/race/create", post(wrap::create_race))
+        .route("/race/join", post(wrap::join_race))
+        .route("/race/standings", get(wrap::race_standings))
+        .route("/campaign", get(wrap::campaign_levels))
+        .route("/campaign/start", post(wrap::start_campaign_level))
+        .route("/api-docs/openapi.json", get(docs::openapi_json))
+        .route("/docs", get(docs::swagger_ui))
+        .route(
+            "/ext/htmx.js",
+            serve_static_file!("/html/website/ext/htmx.js"),
+        )
+        .route(
+            "/ext/bootstrap.min.css",
+            serve_static_file!("/html/website/ext/bootstrap.min.css"),
+        )
+        .route(
+            "/ext/bootstrap.bundle.min.js",
+            serve_static_file!("/html/website/ext/bootstrap.bundle.min.js"),
+        )
+        .route(
+            "/ext/response-targets.js",
+            serve_static_file!("/html/website/ext/response-targets.js"),
+        )
+        .route(
+            "/ext/swagger-ui.css",
+            serve_static_file!("/html/website/ext/swagger-ui.css"),
+        )
+        .route(
+            "/ext/swagger-ui-bundle.js",
+            serve_static_file!("/html/website/ext/swagger-ui-bundle.js"),
+        )
+        .route("/", serve_static_file!("/html/website/index.html"))
+        .route(
+            "/base/base.css",
+            get(move |_: Request<Body>| async {
+                Ok::<_, Infallible>(Response::new(Body::from(demystify::web::base_css())))
+            }),
+        )
+        .route(
+            "/base/base.js",
+            get(move |_: Request<Body>| async {
+                Ok::<_, Infallible>(Response::new(Body::from(demystify::web::base_javascript())))
+            }),
+        )
+        .layer(DefaultBodyLimit::max(config.upload_limit))
+        .layer(cors)
+        .layer(SessionLayer::new(session_store));
+
+    let debug_timing = config.debug_timing;
+    if let Some(token) = &config.admin_token {
+        let token: std::sync::Arc<str> = token.as_str().into();
+        let mut admin = Router::new()
+            .route("/admin/sessions", get(sessions::list_endpoint))
+            .route("/admin/sessions/{id}/evict", post(sessions::evict_endpoint))
+            .route(
+                "/admin/sessions/{id}/persist",
+                post(sessions::persist_endpoint),
+            );
+        if debug_timing {
+            admin = admin.route("/admin/timings", get(timing::timings_endpoint));
+        }
+        let admin = admin.layer(axum::middleware::from_fn(move |request, next| {
+            sessions::require_token(token.clone(), request, next)
+        }));
+        app = app.merge(admin);
+    }
+    let app = app.layer(axum::middleware::from_fn(move |request, next| {
+        timing::middleware(debug_timing, request, next)
+    }));
+
+    Ok(app)
+}
+
+/// Serves the site on `listener` until the server stops.
+pub async fn serve(listener: TcpListener, config: &WebConfig) -> anyhow::Result<()> {
+    let app = router(config).await?;
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+async fn greet(session: Session<SessionNullPool>) -> String {
+    let mut count: usize = session.get("count").unwrap_or(0);
+
+    count += 1;
+    session.set("count", count);
+
+    count.to_string()
+}
+
+#[derive(Clone, PartialOrd, Ord, Hash, Debug, PartialEq, Eq, Deserialize, Serialize)]
+
+struct Obj {
+    val: i32,
+}
+
+async fn greet_x(session: Session<SessionNullPool>, Json(obj): Json<Obj>) -> Result<Json<Obj>, ()> {
+    let mut count: i32 = session.get("count").unwrap_or(0);
+
+    count += 1;
+    session.set("count", count);
+
+    let o: Obj = Obj {
+        val: obj.val * 100 + count,
+    };
+
+    Ok(Json(o))
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    use super::*;
+
+    /// Sends a raw HTTP/1.1 request and returns the whole response.
+    async fn request(addr: std::net::SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = vec![];
+        stream.read_to_end(&mut response).await.unwrap();
+        String::from_utf8_lossy(&response).into_owned()
+    }
+
+    #[tokio::test]
+    async fn test_serve_with_config() {
+        let config = WebConfig {
+            bind: "127.0.0.1:0".parse().unwrap(),
+            cors_origins: vec!["https://example.org".to_owned()],
+            upload_limit: 100,
+            ..Default::default()
+        };
+
+        let listener = TcpListener::bind(config.bind).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server_config = config.clone();
+        tokio::spawn(async move { serve(listener, &server_config).await.unwrap() });
+
+        let response = request(
+            addr,
+            "GET /greet HTTP/1.1\r\nHost: localhost\r\nOrigin: https://example.org\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        assert!(
+            response
+                .to_lowercase()
+                .contains("access-control-allow-origin: https://example.org"),
+            "{response}"
+        );
+        assert!(response.ends_with('1'), "{response}");
+        assert!(!response.contains("server-timing"), "{response}");
+
+        let response = request(
+            addr,
+            "GET /admin/timings HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 404"), "{response}");
+
+        let body = "x".repeat(config.upload_limit + 1);
+        let response = request(
+            addr,
+            &format!(
+                "POST /setSettings HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            ),
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 413"), "{response}");
+    }
+
+    #[tokio::test]
+    async fn test_debug_timing() {
+        let config = WebConfig {
+            bind: "127.0.0.1:0".parse().unwrap(),
+            debug_timing: true,
+            admin_token: Some("secret".to_owned()),
+            ..Default::default()
+        };
+        let listener = TcpListener::bind(config.bind).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { serve(listener, &config).await.unwrap() });
+
+        let get = |path: &str, headers: &str| {
+            format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n{headers}Connection: close\r\n\r\n")
+        };
+
+        let response = request(addr, &get("/greet", "")).await;
+        assert!(response.contains("server-timing: queue;dur="), "{response}");
+
+        // The timings are only for admins
+        let response = request(addr, &get("/admin/timings", "")).await;
+        assert!(response.starts_with("HTTP/1.1 401"), "{response}");
+        let response = request(
+            addr,
+            &get("/admin/timings", "Authorization: Bearer wrong\r\n"),
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 401"), "{response}");
+
+        let response = request(
+            addr,
+            &get("/admin/timings", "Authorization: Bearer secret\r\n"),
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        assert!(response.contains(r#""/greet":{"requests":"#), "{response}");
+        assert!(response.contains(r#""p99":"#), "{response}");
+    }
+
+    /// The cookies set by `response`, ready to send back.
+    fn cookies(response: &str) -> String {
+        response
+            .lines()
+            .filter_map(|l| {
+                l.strip_prefix("set-cookie: ")
+                    .or_else(|| l.strip_prefix("Set-Cookie: "))
+            })
+            .filter_map(|c| c.split(';').next())
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    #[tokio::test]
+    async fn test_notes_endpoints() {
+        let config = WebConfig {
+            bind: "127.0.0.1:0".parse().unwrap(),
+            ..Default::default()
+        };
+        let listener = TcpListener::bind(config.bind).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { serve(listener, &config).await.unwrap() });
+
+        let post = |path: &str, cookie: &str, body: &str| {
+            format!(
+                "POST {path} HTTP/1.1\r\nHost: localhost\r\nCookie: {cookie}\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+        };
+        let get = |path: &str, cookie: &str| {
+            format!(
+                "GET {path} HTTP/1.1\r\nHost: localhost\r\nCookie: {cookie}\r\nConnection: close\r\n\r\n"
+            )
+        };
+
+        let response = request(addr, &post("/defaultPuzzle", "", "")).await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        let cookie = cookies(&response);
+        assert!(!cookie.is_empty(), "{response}");
+
+        let response = request(addr, &post("/bestNextStep", &cookie, "")).await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        let first_step = response.split_once("\r\n\r\n").unwrap().1.to_owned();
+
+        let response = request(addr, &post("/notes/step", &cookie, "step=&text=First+step")).await;
+        assert!(response.ends_with("Note saved."), "{response}");
+        let response = request(addr, &post("/notes/step", &cookie, "step=2&text=Next+one")).await;
+        assert!(response.ends_with("Note saved."), "{response}");
+        let response = request(addr, &post("/notes/cell", &cookie, "cell=x_1&text=Bad")).await;
+        assert!(response.starts_with("HTTP/1.1 500"), "{response}");
+
+        let response = request(addr, &get("/notes", &cookie)).await;
+        assert!(
+            response.contains(r#"{"target":{"step":1},"text":"First step"}"#),
+            "{response}"
+        );
+
+        let response = request(addr, &get("/exportWalkthrough", &cookie)).await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        assert!(response.contains("Next one"), "{response}");
+        assert!(!response.contains("First step"), "{response}");
+
+        let response = request(addr, &get("/thumbnail?step=0&size=80", &cookie)).await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        assert!(response.contains("image/svg+xml"), "{response}");
+        assert!(response.contains(r#"width="80""#), "{response}");
+        let response = request(addr, &get("/thumbnail?size=5000", &cookie)).await;
+        assert!(response.starts_with("HTTP/1.1 500"), "{response}");
+
+        let response = request(addr, &get("/pastStep?step=1", &cookie)).await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        assert!(response.ends_with(&first_step), "{response}");
+        for step in [0, 2] {
+            let response = request(addr, &get(&format!("/pastStep?step={step}"), &cookie)).await;
+            assert!(response.starts_with("HTTP/1.1 500"), "{response}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_click_known_literal() {
+        let config = WebConfig {
+            bind: "127.0.0.1:0".parse().unwrap(),
+            ..Default::default()
+        };
+        let listener = TcpListener::bind(config.bind).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { serve(listener, &config).await.unwrap() });
+
+        let click = |cookie: &str, id: &str| {
+            format!(
+                "POST /clickLiteral HTTP/1.1\r\nHost: localhost\r\nCookie: {cookie}\r\nHX-Trigger: {id}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            )
+        };
+
+        let response = request(
+            addr,
+            "POST /defaultPuzzle HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+        let cookie = cookies(&response);
+
+        // The top left cell is given
+        let response = request(addr, &click(&cookie, "D_1_1_2")).await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        assert!(
+            response.contains("It is already known that grid[1, 1]=1 from the start of the puzzle"),
+            "{response}"
+        );
+        assert!(response.contains(r#"hx-post="/refresh""#), "{response}");
+
+        let response = request(addr, &click(&cookie, "D_1_1_9")).await;
+        assert!(response.contains("There is no such value"), "{response}");
+
+        let response = request(addr, &click(&cookie, "D_1_2_3")).await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        assert!(!response.contains("alert-info"), "{response}");
+    }
+
+    #[tokio::test]
+    async fn test_admin_sessions() {
+        let config = WebConfig {
+            bind: "127.0.0.1:0".parse().unwrap(),
+            admin_token: Some("secret".to_owned()),
+            ..Default::default()
+        };
+        let listener = TcpListener::bind(config.bind).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { serve(listener, &config).await.unwrap() });
+
+        let admin = |method: &str, path: &str, token: &str| {
+            format!(
+                "{method} {path} HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer {token}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            )
+        };
+
+        let response = request(
+            addr,
+            "POST /defaultPuzzle HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+        let cookie = cookies(&response);
+        let id = cookie
+            .split("; ")
+            .find_map(|c| c.strip_prefix("session="))
+            .unwrap()
+            .to_owned();
+
+        let response = request(addr, &admin("GET", "/admin/sessions", "wrong")).await;
+        assert!(response.starts_with("HTTP/1.1 401"), "{response}");
+
+        let response = request(addr, &admin("GET", "/admin/sessions", "secret")).await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        assert!(
+            response.contains(&format!(r#"{{"id":"{id}","#)),
+            "{response}"
+        );
+        assert!(
+            response.contains(r#""variables":16,"clauses":408"#),
+            "{response}"
+        );
+
+        // The demo puzzle has no files to save
+        let persist = format!("/admin/sessions/{id}/persist");
+        let response = request(addr, &admin("POST", &persist, "secret")).await;
+        assert!(response.starts_with("HTTP/1.1 500"), "{response}");
+
+        let evict = format!("/admin/sessions/{id}/evict");
+        let response = request(addr, &admin("POST", &evict, "secret")).await;
+        assert!(response.ends_with(&format!("Evicted {id}")), "{response}");
+        let response = request(addr, &admin("POST", &evict, "secret")).await;
+        assert!(response.contains("No such session"), "{response}");
+
+        let response = request(
+            addr,
+            &format!(
+                "POST /bestNextStep HTTP/1.1\r\nHost: localhost\r\nCookie: {cookie}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            ),
+        )
+        .await;
+        assert!(response.contains("No solver"), "{response}");
+    }
+
+    #[tokio::test]
+    async fn test_submit_example_model() {
+        let config = WebConfig {
+            bind: "127.0.0.1:0".parse().unwrap(),
+            ..Default::default()
+        };
+        let listener = TcpListener::bind(config.bind).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { serve(listener, &config).await.unwrap() });
+
+        let submit = |example: &str, file_name: &str, model: &str| {
+            let body = format!(
+                "--b\r\nContent-Disposition: form-data; name=\"example_name\"\r\n\r\n{example}\r\n\
+                 --b\r\nContent-Disposition: form-data; name=\"param_content\"\r\n\r\nletting n be 4\r\n\
+                 --b\r\nContent-Disposition: form-data; name=\"model\"; filename=\"{file_name}\"\r\n\r\n{model}\r\n\
+                 --b--\r\n"
+            );
+            format!(
+                "POST /submitExample HTTP/1.1\r\nHost: localhost\r\nContent-Type: multipart/form-data; boundary=b\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+        };
+
+        // Browsers send an empty file when none is chosen, which is ignored
+        let response = request(addr, &submit("Missing", "", "")).await;
+        assert!(
+            response.contains("Example 'Missing' not found"),
+            "{response}"
+        );
+
+        let response = request(addr, &submit("Sudoku", "rules.txt", "find x : int(1..4)")).await;
+        assert!(
+            response.contains("Only expecting a .eprime or .essence model, not 'rules.txt'"),
+            "{response}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_import_url_checks() {
+        let config = WebConfig {
+            bind: "127.0.0.1:0".parse().unwrap(),
+            ..Default::default()
+        };
+        let listener = TcpListener::bind(config.bind).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { serve(listener, &config).await.unwrap() });
+
+        let import = |model_url: &str| {
+            let body = format!(
+                "model_url={model_url}&param_url=https%3A%2F%2Fraw.githubusercontent.com%2Fa.param"
+            );
+            format!(
+                "POST /importUrl HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+        };
+
+        // Both are rejected before anything is downloaded
+        let response = request(addr, &import("https%3A%2F%2Fexample.org%2Fa.eprime")).await;
+        assert!(response.contains("Failed to download puzzle"), "{response}");
+        assert!(
+            response.contains("Cannot fetch from example.org, only from raw.githubusercontent.com"),
+            "{response}"
+        );
+
+        let response = request(
+            addr,
+            &import("http%3A%2F%2Fraw.githubusercontent.com%2Fa.eprime"),
+        )
+        .await;
+        assert!(
+            response.contains("Only https URLs can be fetched"),
+            "{response}"
+        );
+    }
+}
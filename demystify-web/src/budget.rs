@@ -0,0 +1,89 @@
+//! Limits on how long a single request may spend searching, and the searches
+//! which ran out of time, so they can be continued by a later request.
+//!
+//! A continuation is only kept for the session which made it, and is
+//! forgotten once it is used (or when that session starts another). If the
+//! puzzle has changed since, the planner ignores it and starts again.
+
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
+
+use anyhow::bail;
+use axum_session::{Session, SessionNullPool};
+use demystify::problem::solver::{MusSearch, SearchBudget};
+use uuid::Uuid;
+
+/// Seconds of search per request, unless `DEMYSTIFY_REQUEST_SECS` is set.
+const DEFAULT_REQUEST_SECS: u64 = 20;
+
+/// The budget for one request. Set `DEMYSTIFY_REQUEST_SECS` and
+/// `DEMYSTIFY_REQUEST_SOLVER_CALLS` to change it, where `0` means no limit.
+pub fn request_budget() -> SearchBudget {
+    let env = |name: &str| std::env::var(name).ok().and_then(|v| v.parse::<u64>().ok());
+
+    let secs = env("DEMYSTIFY_REQUEST_SECS").unwrap_or(DEFAULT_REQUEST_SECS);
+    let calls = env("DEMYSTIFY_REQUEST_SOLVER_CALLS").unwrap_or(0);
+
+    SearchBudget {
+        max_time: (secs > 0).then(|| Duration::from_secs(secs)),
+        max_solver_calls: (calls > 0).then(|| i64::try_from(calls).unwrap_or(i64::MAX)),
+        cancel: None,
+    }
+}
+
+/// Seconds a whole solve may take, unless `DEMYSTIFY_SOLVE_SECS` is set.
+const DEFAULT_SOLVE_SECS: u64 = 120;
+
+/// How long a request which solves the rest of the puzzle may take, after
+/// which it is cancelled. Set `DEMYSTIFY_SOLVE_SECS` to change it, where `0`
+/// means no limit.
+pub fn solve_limit() -> Duration {
+    let secs = std::env::var("DEMYSTIFY_SOLVE_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_SOLVE_SECS);
+    if secs == 0 {
+        Duration::MAX
+    } else {
+        Duration::from_secs(secs)
+    }
+}
+
+struct Continuation {
+    token: Uuid,
+    search: MusSearch,
+}
+
+/// Continuations, keyed by session id.
+fn continuations() -> &'static Mutex<HashMap<Uuid, Continuation>> {
+    static CONTINUATIONS: OnceLock<Mutex<HashMap<Uuid, Continuation>>> = OnceLock::new();
+    CONTINUATIONS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Keeps `search` for this session, replacing any older one, and returns the
+/// token needed to continue it.
+pub fn store(session: &Session<SessionNullPool>, search: MusSearch) -> Uuid {
+    let token = Uuid::new_v4();
+    continuations().lock().unwrap().insert(
+        session.get_session_id().uuid(),
+        Continuation { token, search },
+    );
+    token
+}
+
+/// Removes and returns the search stored under `token` for this session.
+pub fn take(session: &Session<SessionNullPool>, token: Uuid) -> anyhow::Result<MusSearch> {
+    let mut continuations = continuations().lock().unwrap();
+    let id = session.get_session_id().uuid();
+    match continuations.remove(&id) {
+        Some(c) if c.token == token => Ok(c.search),
+        Some(c) => {
+            continuations.insert(id, c);
+            bail!("This computation has been replaced by a newer one, please try again")
+        }
+        None => bail!("This computation has expired, please try again"),
+    }
+}
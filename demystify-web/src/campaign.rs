@@ -0,0 +1,188 @@
+//! Campaign mode, where the levels of a [`Campaign`] are played in order,
+//! each unlocked as earlier ones are finished.
+//!
+//! The manifest is read once, from
+//! [`WebConfig::campaign`](crate::config::WebConfig::campaign). Each
+//! session's progress is kept in the session, and saved with the session by
+//! `/exportSession`, so it is not lost when the session is imported
+//! elsewhere.
+
+use std::{fmt::Write, path::Path, sync::OnceLock};
+
+use anyhow::{Context, anyhow, bail};
+use axum_session::{Session, SessionNullPool};
+use demystify::problem::{
+    campaign::{Campaign, CampaignProgress, Unlock},
+    parse::PuzzleParse,
+    planner::PuzzlePlanner,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{limits, saved::SessionFiles, util::escape_html};
+
+static CAMPAIGN: OnceLock<Campaign> = OnceLock::new();
+
+/// The level a session is playing, and the levels it has finished.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CampaignState {
+    /// The index of the level being played, if the puzzle is from the
+    /// campaign
+    pub level: Option<usize>,
+    pub progress: CampaignProgress,
+}
+
+/// Reads the campaign from the manifest at `path`, returning how many levels
+/// it has. Can only be called once.
+pub fn load(path: &Path) -> anyhow::Result<usize> {
+    let campaign = Campaign::from_file(path)?;
+    let levels = campaign.levels.len();
+    CAMPAIGN
+        .set(campaign)
+        .map_err(|_| anyhow!("A campaign has already been loaded"))?;
+    Ok(levels)
+}
+
+fn campaign() -> Option<&'static Campaign> {
+    CAMPAIGN.get()
+}
+
+/// This session's progress through the campaign.
+pub fn state(session: &Session<SessionNullPool>) -> CampaignState {
+    session.get("campaign").unwrap_or_default()
+}
+
+pub fn set_state(session: &Session<SessionNullPool>, state: &CampaignState) {
+    session.set("campaign", state);
+}
+
+/// Reads level `index`, if this session has unlocked it, and marks it as the
+/// level being played. Also returns the files to save the session with,
+/// unless the level is read from DIMACS, as the files alone can only be
+/// read again with conjure.
+pub fn start_level(
+    session: &Session<SessionNullPool>,
+    index: usize,
+) -> anyhow::Result<(PuzzleParse, Option<SessionFiles>)> {
+    let campaign = campaign().context("No campaign is configured on this server")?;
+    let level = campaign
+        .levels
+        .get(index)
+        .with_context(|| format!("The campaign has no level {}", index + 1))?;
+    let mut state = state(session);
+    if !campaign.is_unlocked(index, &state.progress) {
+        bail!("'{}' has not been unlocked yet", level.name);
+    }
+
+    let puzzle = level.puzzle()?;
+    limits::check(&puzzle)?;
+    let files = if level.dimacs.is_some() {
+        None
+    } else {
+        let name = |path: &Path| {
+            path.file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned()
+        };
+        Some(SessionFiles {
+            model_name: name(&level.model),
+            model: std::fs::read_to_string(&level.model).context("Failed to read model file")?,
+            param_name: name(&level.param),
+            param: std::fs::read_to_string(&level.param).context("Failed to read param file")?,
+        })
+    };
+
+    state.level = Some(index);
+    set_state(session, &state);
+    Ok((puzzle, files))
+}
+
+/// Forgets the level being played, when another puzzle is loaded, keeping
+/// the levels finished.
+pub fn leave(session: &Session<SessionNullPool>) {
+    let mut state = state(session);
+    if state.level.take().is_some() {
+        set_state(session, &state);
+    }
+}
+
+/// Records that a step has been applied, finishing the level being played if
+/// the puzzle is now solved. Returns whether the level was finished by this
+/// step.
+pub fn record_step(session: &Session<SessionNullPool>, planner: &PuzzlePlanner) -> bool {
+    let mut state = state(session);
+    let Some(level) = state.level else {
+        return false;
+    };
+    let summary = planner.solve_summary();
+    if !summary.solved {
+        return false;
+    }
+    let newly = !state.progress.is_finished(level);
+    state.progress.finish(level, summary.difficulty);
+    set_state(session, &state);
+    newly
+}
+
+/// A notice that the level being played is finished, with a link back to
+/// the list of levels.
+#[must_use]
+pub fn finished_html() -> String {
+    r##"<div class="alert alert-success">Level finished!
+        <button class="btn btn-success ms-2" hx-get="/campaign" hx-target="#campaignLevels">See what it unlocked</button>
+    </div>"##
+        .to_owned()
+}
+
+/// The levels of the campaign, with the ones this session can play as
+/// buttons which start them.
+pub fn levels_html(session: &Session<SessionNullPool>) -> String {
+    let Some(campaign) = campaign() else {
+        return "No campaign is configured on this server.".to_owned();
+    };
+    let state = state(session);
+
+    let mut html = format!(
+        r#"<h5>{}</h5><ol class="list-group list-group-numbered">"#,
+        escape_html(&campaign.name)
+    );
+    for (i, level) in campaign.levels.iter().enumerate() {
+        let name = escape_html(&level.name);
+        if !campaign.is_unlocked(i, &state.progress) {
+            let needs = match level.unlock {
+                Unlock::Difficulty(difficulty) => {
+                    format!("Finish a level of difficulty {difficulty} to unlock")
+                }
+                _ => "Finish the level before to unlock".to_owned(),
+            };
+            let _ = write!(
+                html,
+                r#"<li class="list-group-item text-muted">{name} <span class="badge bg-secondary">{needs}</span></li>"#
+            );
+            continue;
+        }
+        let status = state
+            .progress
+            .finished
+            .get(&i)
+            .map(|difficulty| {
+                format!(
+                    r#"<span class="badge bg-success">Finished, difficulty {difficulty}</span>"#
+                )
+            })
+            .unwrap_or_default();
+        let current = if state.level == Some(i) {
+            " active"
+        } else {
+            ""
+        };
+        let _ = write!(
+            html,
+            r##"<li class="list-group-item{current}">
+                <button class="btn btn-link p-0" hx-post="/campaign/start" hx-vals='{{"level": {i}}}' hx-target="#mainSpace" hx-indicator="#indicator">{name}</button>
+                {status}
+            </li>"##
+        );
+    }
+    html + "</ol>"
+}
@@ -0,0 +1,387 @@
+//! Configuration of the web server.
+//!
+//! Settings are read from the TOML file named by `DEMYSTIFY_CONFIG`, if it is
+//! set, and then any of the environment variables below override them:
+//!
+//! | Setting           | Environment variable         |
+//! |-------------------|------------------------------|
+//! | `bind`            | `DEMYSTIFY_BIND`             |
+//! | `cors_origins`    | `DEMYSTIFY_CORS_ORIGINS`     |
+//! | `upload_limit`    | `DEMYSTIFY_UPLOAD_LIMIT`     |
+//! | `worker_threads`  | `DEMYSTIFY_WORKER_THREADS`   |
+//! | `examples_dir`    | `DEMYSTIFY_EXAMPLES_DIR`     |
+//! | `session_backend` | `DEMYSTIFY_SESSION_BACKEND`  |
+//! | `session_table`   | `DEMYSTIFY_SESSION_TABLE`    |
+//! | `log_format`      | `DEMYSTIFY_LOG_FORMAT`       |
+//! | `log_filter`      | `DEMYSTIFY_LOG`              |
+//! | `log_file`        | `DEMYSTIFY_LOG_FILE`         |
+//! | `log_max_size`    | `DEMYSTIFY_LOG_MAX_SIZE`     |
+//! | `puzzle_limits.max_variables` | `DEMYSTIFY_MAX_VARIABLES` |
+//! | `puzzle_limits.max_domain`    | `DEMYSTIFY_MAX_DOMAIN`    |
+//! | `puzzle_limits.max_clauses`   | `DEMYSTIFY_MAX_CLAUSES`   |
+//! | `debug_timing`    | `DEMYSTIFY_DEBUG_TIMING`     |
+//! | `admin_token`     | `DEMYSTIFY_ADMIN_TOKEN`      |
+//! | `session_idle_timeout` | `DEMYSTIFY_SESSION_IDLE_TIMEOUT` |
+//! | `session_dir`     | `DEMYSTIFY_SESSION_DIR`      |
+//! | `url_import_hosts` | `DEMYSTIFY_URL_IMPORT_HOSTS` |
+//! | `campaign`        | `DEMYSTIFY_CAMPAIGN`         |
+//! | `history_full_steps` | `DEMYSTIFY_HISTORY_FULL_STEPS` |
+//! | `history_dir`     | `DEMYSTIFY_HISTORY_DIR`      |
+//!
+//! `DEMYSTIFY_CORS_ORIGINS` and `DEMYSTIFY_URL_IMPORT_HOSTS` are comma
+//! separated lists.
+
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{Context, bail};
+use demystify::problem::{
+    history::HistoryConfig,
+    util::{
+        fetch::FetchLimits,
+        kvstore::{DirStore, KvStore},
+        logging::{LogConfig, LogFormat},
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::limits::PuzzleLimits;
+
+/// Where sessions are stored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionBackend {
+    /// In memory, so sessions are lost when the server restarts
+    #[default]
+    Memory,
+}
+
+impl std::str::FromStr for SessionBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "memory" => Ok(SessionBackend::Memory),
+            _ => bail!("Unknown session backend '{s}', the only one supported is 'memory'"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebConfig {
+    /// The address to listen on
+    pub bind: SocketAddr,
+    /// The origins allowed to make cross-origin requests. If empty, any origin is allowed.
+    pub cors_origins: Vec<String>,
+    /// The largest request body accepted, in bytes, which limits the size of uploads
+    pub upload_limit: usize,
+    /// The number of threads handling requests. If not set, one per CPU.
+    pub worker_threads: Option<usize>,
+    /// A directory of extra examples, laid out like `examples/eprime`: each
+    /// model `name.eprime` (or `name.essence`) has a directory `name` holding
+    /// its `.param` files, and the first of these is shown.
+    pub examples_dir: Option<PathBuf>,
+    pub session_backend: SessionBackend,
+    /// The name of the table sessions are stored in
+    pub session_table: String,
+    /// `text` or `json`
+    pub log_format: LogFormat,
+    /// Which events to log, such as `info` or `debug,solver=off`
+    pub log_filter: String,
+    /// Log to this file instead of standard error
+    pub log_file: Option<PathBuf>,
+    /// Start a new log file after this many bytes, keeping the last 3
+    pub log_max_size: Option<u64>,
+    /// The largest puzzle accepted, checked once it has been parsed
+    pub puzzle_limits: PuzzleLimits,
+    /// Return how long each request spent waiting and solving in a
+    /// `Server-Timing` header, and summarise recent requests at
+    /// `/admin/timings` if `admin_token` is set. Timings are logged either
+    /// way.
+    pub debug_timing: bool,
+    /// Serve the routes for inspecting and evicting sessions under
+    /// `/admin/sessions`, to requests with this bearer token. See
+    /// [`sessions`](crate::sessions).
+    pub admin_token: Option<String>,
+    /// Evict sessions which have not been used for this many seconds
+    pub session_idle_timeout: Option<u64>,
+    /// Save evicted sessions in this directory, so they can be restored when
+    /// their user comes back
+    pub session_dir: Option<PathBuf>,
+    /// The hosts puzzles can be imported from by URL, with `/importUrl`. If
+    /// empty, importing from URLs is turned off. Downloads are limited to
+    /// `upload_limit` bytes.
+    pub url_import_hosts: Vec<String>,
+    /// A campaign manifest, whose levels are offered in order, each unlocked
+    /// as earlier ones are finished. See [`campaign`](crate::campaign).
+    pub campaign: Option<PathBuf>,
+    /// Keep this many of the latest steps of each session as they were
+    /// shown, for `/pastStep`. Older steps keep only what changed, so a long
+    /// session does not keep a copy of the whole puzzle for every step. If
+    /// not set, every step is kept in full.
+    pub history_full_steps: Option<usize>,
+    /// Write the steps older than `history_full_steps` to files in this
+    /// directory, rather than keeping them in memory. Use a directory of its
+    /// own, as it is emptied when the server starts.
+    pub history_dir: Option<PathBuf>,
+}
+
+impl Default for WebConfig {
+    fn default() -> Self {
+        Self {
+            bind: SocketAddr::from(([0, 0, 0, 0], 8008)),
+            cors_origins: vec![],
+            upload_limit: 2 * 1024 * 1024,
+            worker_threads: None,
+            examples_dir: None,
+            session_backend: SessionBackend::Memory,
+            session_table: "sessions_table".to_owned(),
+            log_format: LogFormat::Text,
+            log_filter: "info".to_owned(),
+            log_file: None,
+            log_max_size: None,
+            puzzle_limits: PuzzleLimits::default(),
+            debug_timing: false,
+            admin_token: None,
+            session_idle_timeout: None,
+            session_dir: None,
+            url_import_hosts: vec!["raw.githubusercontent.com".to_owned()],
+            campaign: None,
+            history_full_steps: Some(20),
+            history_dir: None,
+        }
+    }
+}
+
+impl WebConfig {
+    /// Reads the configuration from the file named by `DEMYSTIFY_CONFIG` (if
+    /// set) and the environment, as described in the [module docs](self).
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mut config = match std::env::var_os("DEMYSTIFY_CONFIG") {
+            Some(path) => Self::from_file(Path::new(&path))?,
+            None => Self::default(),
+        };
+        config.apply_env(|name| std::env::var(name).ok())?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {path:?}"))?;
+        Self::from_toml(&text).with_context(|| format!("Failed to parse config file {path:?}"))
+    }
+
+    /// Parses a configuration from TOML. Settings which are not given keep their defaults.
+    pub fn from_toml(text: &str) -> anyhow::Result<Self> {
+        Ok(toml::from_str(text)?)
+    }
+
+    /// Overrides settings with the environment variables returned by `var`.
+    pub fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> anyhow::Result<()> {
+        fn parse<T: std::str::FromStr>(name: &str, value: &str) -> anyhow::Result<T>
+        where
+            T::Err: std::fmt::Display,
+        {
+            value
+                .trim()
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid value '{value}' for {name}: {e}"))
+        }
+
+        if let Some(v) = var("DEMYSTIFY_BIND") {
+            self.bind = parse("DEMYSTIFY_BIND", &v)?;
+        }
+        fn list(value: &str) -> Vec<String> {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|o| !o.is_empty())
+                .map(str::to_owned)
+                .collect()
+        }
+
+        if let Some(v) = var("DEMYSTIFY_CORS_ORIGINS") {
+            self.cors_origins = list(&v);
+        }
+        if let Some(v) = var("DEMYSTIFY_UPLOAD_LIMIT") {
+            self.upload_limit = parse("DEMYSTIFY_UPLOAD_LIMIT", &v)?;
+        }
+        if let Some(v) = var("DEMYSTIFY_WORKER_THREADS") {
+            self.worker_threads = Some(parse("DEMYSTIFY_WORKER_THREADS", &v)?);
+        }
+        if let Some(v) = var("DEMYSTIFY_EXAMPLES_DIR") {
+            self.examples_dir = Some(PathBuf::from(v));
+        }
+        if let Some(v) = var("DEMYSTIFY_SESSION_BACKEND") {
+            self.session_backend = parse("DEMYSTIFY_SESSION_BACKEND", &v)?;
+        }
+        if let Some(v) = var("DEMYSTIFY_SESSION_TABLE") {
+            self.session_table = v;
+        }
+        if let Some(v) = var("DEMYSTIFY_LOG_FORMAT") {
+            self.log_format = parse("DEMYSTIFY_LOG_FORMAT", &v)?;
+        }
+        if let Some(v) = var("DEMYSTIFY_LOG") {
+            self.log_filter = v;
+        }
+        if let Some(v) = var("DEMYSTIFY_LOG_FILE") {
+            self.log_file = Some(PathBuf::from(v));
+        }
+        if let Some(v) = var("DEMYSTIFY_LOG_MAX_SIZE") {
+            self.log_max_size = Some(parse("DEMYSTIFY_LOG_MAX_SIZE", &v)?);
+        }
+        if let Some(v) = var("DEMYSTIFY_MAX_VARIABLES") {
+            self.puzzle_limits.max_variables = parse("DEMYSTIFY_MAX_VARIABLES", &v)?;
+        }
+        if let Some(v) = var("DEMYSTIFY_MAX_DOMAIN") {
+            self.puzzle_limits.max_domain = parse("DEMYSTIFY_MAX_DOMAIN", &v)?;
+        }
+        if let Some(v) = var("DEMYSTIFY_MAX_CLAUSES") {
+            self.puzzle_limits.max_clauses = parse("DEMYSTIFY_MAX_CLAUSES", &v)?;
+        }
+        if let Some(v) = var("DEMYSTIFY_DEBUG_TIMING") {
+            self.debug_timing = parse("DEMYSTIFY_DEBUG_TIMING", &v)?;
+        }
+        if let Some(v) = var("DEMYSTIFY_ADMIN_TOKEN") {
+            self.admin_token = Some(v).filter(|t| !t.is_empty());
+        }
+        if let Some(v) = var("DEMYSTIFY_SESSION_IDLE_TIMEOUT") {
+            self.session_idle_timeout = Some(parse("DEMYSTIFY_SESSION_IDLE_TIMEOUT", &v)?);
+        }
+        if let Some(v) = var("DEMYSTIFY_SESSION_DIR") {
+            self.session_dir = Some(PathBuf::from(v));
+        }
+        if let Some(v) = var("DEMYSTIFY_URL_IMPORT_HOSTS") {
+            self.url_import_hosts = list(&v);
+        }
+        if let Some(v) = var("DEMYSTIFY_CAMPAIGN") {
+            self.campaign = Some(PathBuf::from(v));
+        }
+        if let Some(v) = var("DEMYSTIFY_HISTORY_FULL_STEPS") {
+            self.history_full_steps = Some(parse("DEMYSTIFY_HISTORY_FULL_STEPS", &v)?);
+        }
+        if let Some(v) = var("DEMYSTIFY_HISTORY_DIR") {
+            self.history_dir = Some(PathBuf::from(v));
+        }
+        Ok(())
+    }
+
+    /// What `/importUrl` may fetch, or `None` if it is turned off.
+    #[must_use]
+    pub fn url_import_limits(&self) -> Option<FetchLimits> {
+        if self.url_import_hosts.is_empty() {
+            return None;
+        }
+        Some(FetchLimits {
+            max_bytes: self.upload_limit,
+            allowed_hosts: Some(self.url_import_hosts.clone()),
+            ..FetchLimits::default()
+        })
+    }
+
+    /// How the steps of each session are kept, emptying `history_dir` if
+    /// it is set.
+    pub fn history_config(&self) -> anyhow::Result<HistoryConfig> {
+        let store = match &self.history_dir {
+            Some(dir) => {
+                let store = DirStore::new(dir)?;
+                store.clear()?;
+                Some(Arc::new(store) as Arc<dyn KvStore>)
+            }
+            None => None,
+        };
+        Ok(HistoryConfig {
+            keep_full: self.history_full_steps,
+            store,
+        })
+    }
+
+    /// How the server logs.
+    #[must_use]
+    pub fn log_config(&self) -> LogConfig {
+        LogConfig {
+            file: self.log_file.clone(),
+            max_size: self.log_max_size,
+            format: self.log_format,
+            filter: self.log_filter.clone(),
+            ..LogConfig::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn test_config_toml_and_env() {
+        let mut config = WebConfig::from_toml(
+            r#"
+            bind = "127.0.0.1:9000"
+            cors_origins = ["https://example.org"]
+            worker_threads = 2
+            log_format = "json"
+
+            [puzzle_limits]
+            max_variables = 81
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.bind, SocketAddr::from(([127, 0, 0, 1], 9000)));
+        assert_eq!(config.worker_threads, Some(2));
+        assert_eq!(config.upload_limit, WebConfig::default().upload_limit);
+        assert_eq!(config.log_config().format, LogFormat::Json);
+        assert_eq!(config.log_config().file, None);
+        assert_eq!(config.puzzle_limits.max_variables, 81);
+        assert_eq!(
+            config.puzzle_limits.max_domain,
+            PuzzleLimits::default().max_domain
+        );
+
+        let env = HashMap::from([
+            ("DEMYSTIFY_CORS_ORIGINS", "https://a.org, https://b.org"),
+            ("DEMYSTIFY_UPLOAD_LIMIT", "1000"),
+            ("DEMYSTIFY_LOG", "debug"),
+            ("DEMYSTIFY_LOG_MAX_SIZE", "4096"),
+            ("DEMYSTIFY_MAX_CLAUSES", "5000"),
+            ("DEMYSTIFY_SESSION_IDLE_TIMEOUT", "3600"),
+            ("DEMYSTIFY_URL_IMPORT_HOSTS", ""),
+            ("DEMYSTIFY_CAMPAIGN", "levels/campaign.yaml"),
+            ("DEMYSTIFY_HISTORY_FULL_STEPS", "5"),
+        ]);
+        config
+            .apply_env(|name| env.get(name).map(|v| (*v).to_owned()))
+            .unwrap();
+        assert_eq!(config.cors_origins, vec!["https://a.org", "https://b.org"]);
+        assert_eq!(config.upload_limit, 1000);
+        assert_eq!(config.worker_threads, Some(2));
+        assert_eq!(config.log_config().filter, "debug");
+        assert_eq!(config.log_config().max_size, Some(4096));
+        assert_eq!(config.puzzle_limits.max_clauses, 5000);
+        assert_eq!(config.session_idle_timeout, Some(3600));
+        assert_eq!(config.url_import_limits(), None);
+        assert_eq!(config.campaign, Some(PathBuf::from("levels/campaign.yaml")));
+        assert_eq!(config.history_full_steps, Some(5));
+        assert_eq!(config.history_config().unwrap().keep_full, Some(5));
+        assert_eq!(
+            WebConfig::default()
+                .url_import_limits()
+                .and_then(|l| l.allowed_hosts),
+            Some(vec!["raw.githubusercontent.com".to_owned()])
+        );
+
+        assert!(WebConfig::from_toml("port = 8008").is_err());
+        assert!(
+            config
+                .apply_env(|name| (name == "DEMYSTIFY_SESSION_BACKEND").then(|| "redis".to_owned()))
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use axum::{Json, response::Html};
+use utoipa::OpenApi;
+
+use crate::wrap;
+
+/// OpenAPI description of the routes served by `demystify-web`.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "demystify-web",
+        description = "Step-by-step explanations of how to solve constraint puzzles. \
+            Most routes return HTML fragments, intended to be used with htmx, and \
+            act on the puzzle stored in the caller's session."
+    ),
+    paths(
+        wrap::default_puzzle,
+        wrap::get_example_names,
+        wrap::upload_files,
+        wrap::import_url,
+        wrap::load_example,
+        wrap::submit_example,
+        wrap::refresh,
+        wrap::show_rules,
+        wrap::dump_full_solve,
+        wrap::best_next_step,
+        wrap::get_operation,
+        wrap::cancel_operation,
+        wrap::get_difficulties,
+        wrap::click_literal,
+        wrap::pin_literal,
+        wrap::deduce_in_region,
+        wrap::get_notes,
+        wrap::grid_stats,
+        wrap::note_step,
+        wrap::note_cell,
+        wrap::export_walkthrough,
+        wrap::thumbnail,
+        wrap::past_step,
+        wrap::set_settings,
+        wrap::export_session,
+        wrap::import_session,
+        wrap::tool_logs,
+        wrap::create_race,
+        wrap::join_race,
+        wrap::race_standings,
+        wrap::campaign_levels,
+        wrap::start_campaign_level,
+    ),
+    components(schemas(
+        wrap::UploadForm,
+        wrap::ImportUrlForm,
+        wrap::ExampleParams,
+        wrap::SubmitExampleForm,
+        wrap::OperationParams,
+        wrap::ThumbnailParams,
+        wrap::PastStepParams,
+        wrap::SettingsParams,
+        wrap::StepNoteParams,
+        wrap::CellNoteParams,
+        wrap::RegionParams,
+        wrap::ImportForm,
+        wrap::CreateRaceParams,
+        wrap::JoinRaceParams,
+        wrap::RaceRoom,
+        wrap::CampaignLevelParams,
+        crate::race::Standing
+    ))
+)]
+pub struct ApiDoc;
+
+pub async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// A Swagger UI page for browsing the API, reading the spec from `/api-docs/openapi.json`.
+/// Swagger UI 5.17.14 is served from `html/website/ext`, like htmx and
+/// bootstrap, so the page does not load scripts from a CDN.
+pub async fn swagger_ui() -> Html<&'static str> {
+    Html(
+        r###"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="utf-8">
+    <title>demystify-web API</title>
+    <link rel="stylesheet" href="/ext/swagger-ui.css">
+</head>
+<body>
+    <div id="swagger-ui"></div>
+    <script src="/ext/swagger-ui-bundle.js"></script>
+    <script>
+        window.onload = () => {
+            window.ui = SwaggerUIBundle({ url: "/api-docs/openapi.json", dom_id: "#swagger-ui" });
+        };
+    </script>
+</body>
+</html>
+"###,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_openapi_paths() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let paths = &spec["paths"];
+        assert!(paths["/bestNextStep"]["post"].is_object());
+        assert!(paths["/operation"]["get"].is_object());
+        assert!(paths["/race/standings"]["get"].is_object());
+        assert!(paths["/campaign/start"]["post"].is_object());
+        assert!(spec["components"]["schemas"]["Standing"].is_object());
+    }
+
+    #[tokio::test]
+    async fn test_swagger_ui_is_local() {
+        let Html(page) = swagger_ui().await;
+        assert!(page.contains(r#"src="/ext/swagger-ui-bundle.js""#));
+        assert!(page.contains(r#"href="/ext/swagger-ui.css""#));
+        assert!(!page.contains("https://"));
+    }
+}
@@ -1,2 +1,13 @@
+pub mod app;
+pub mod budget;
+pub mod campaign;
+pub mod config;
+pub mod docs;
+pub mod limits;
+pub mod operation;
+pub mod race;
+pub mod saved;
+pub mod sessions;
+pub mod timing;
 pub mod util;
 pub mod wrap;
@@ -0,0 +1,199 @@
+//! Limits on the size of the puzzles the server will explain.
+//!
+//! Large puzzles (such as 25x25 sudokus) take far too long to explain on a
+//! shared server, so they are turned away after parsing with a command to
+//! explain them locally instead. Uploads are also limited in size, both as
+//! sent and once decompressed.
+
+use std::sync::{
+    RwLock,
+    atomic::{AtomicUsize, Ordering::Relaxed},
+};
+
+use demystify::problem::parse::PuzzleParse;
+use serde::{Deserialize, Serialize};
+
+/// The largest puzzle accepted, see [`PuzzleSize`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PuzzleLimits {
+    pub max_variables: usize,
+    pub max_domain: usize,
+    pub max_clauses: usize,
+}
+
+impl PuzzleLimits {
+    /// Allows 16x16 sudokus, and puzzles of similar size.
+    pub const DEFAULT: Self = Self {
+        max_variables: 400,
+        max_domain: 16,
+        max_clauses: 1_000_000,
+    };
+
+    /// The ways in which `size` is over these limits, which are all within
+    /// them if this is empty.
+    #[must_use]
+    pub fn exceeded(&self, size: &PuzzleSize) -> Vec<String> {
+        let mut over = vec![];
+        if size.variables > self.max_variables {
+            over.push(format!(
+                "{} variables (at most {})",
+                size.variables, self.max_variables
+            ));
+        }
+        if size.max_domain > self.max_domain {
+            over.push(format!(
+                "a variable with {} values (at most {})",
+                size.max_domain, self.max_domain
+            ));
+        }
+        if size.clauses > self.max_clauses {
+            over.push(format!(
+                "{} clauses (at most {})",
+                size.clauses, self.max_clauses
+            ));
+        }
+        over
+    }
+}
+
+impl Default for PuzzleLimits {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// How large a parsed puzzle is.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PuzzleSize {
+    /// The number of `$#VAR` variables (the cells of the grid)
+    pub variables: usize,
+    /// The most values any of those variables can take
+    pub max_domain: usize,
+    /// The number of clauses in the SAT encoding
+    pub clauses: usize,
+}
+
+impl PuzzleSize {
+    #[must_use]
+    pub fn new(puzzle: &PuzzleParse) -> Self {
+        let vars: Vec<_> = puzzle
+            .domainmap
+            .iter()
+            .filter(|(var, _)| puzzle.eprime.vars.contains(var.name()))
+            .collect();
+        Self {
+            variables: vars.len(),
+            max_domain: vars.iter().map(|(_, dom)| dom.len()).max().unwrap_or(0),
+            clauses: puzzle.cnf.as_ref().map_or(0, |cnf| cnf.len()),
+        }
+    }
+}
+
+/// A puzzle which is over the server's [`PuzzleLimits`].
+#[derive(Debug)]
+pub struct PuzzleTooLarge {
+    pub size: PuzzleSize,
+    /// The ways in which it is too large, from [`PuzzleLimits::exceeded`]
+    pub reasons: Vec<String>,
+}
+
+impl std::fmt::Display for PuzzleTooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "This puzzle is too large to explain here, as it has {}",
+            self.reasons.join(", and ")
+        )
+    }
+}
+
+impl std::error::Error for PuzzleTooLarge {}
+
+static LIMITS: RwLock<PuzzleLimits> = RwLock::new(PuzzleLimits::DEFAULT);
+
+/// The default of [`WebConfig::upload_limit`](crate::config::WebConfig::upload_limit).
+static UPLOAD_LIMIT: AtomicUsize = AtomicUsize::new(2 * 1024 * 1024);
+
+/// Sets the limit returned by [`upload_limit`], from
+/// [`WebConfig::upload_limit`](crate::config::WebConfig::upload_limit).
+pub fn set_upload_limit(bytes: usize) {
+    UPLOAD_LIMIT.store(bytes, Relaxed);
+}
+
+/// The largest upload accepted, in bytes. Compressed uploads may not
+/// decompress to more than this either.
+#[must_use]
+pub fn upload_limit() -> usize {
+    UPLOAD_LIMIT.load(Relaxed)
+}
+
+/// Sets the limits applied by [`check`], from
+/// [`WebConfig::puzzle_limits`](crate::config::WebConfig::puzzle_limits).
+pub fn set_limits(limits: PuzzleLimits) {
+    *LIMITS.write().unwrap() = limits;
+}
+
+/// Fails with [`PuzzleTooLarge`] if `puzzle` is over the limits.
+pub fn check(puzzle: &PuzzleParse) -> Result<(), PuzzleTooLarge> {
+    let size = PuzzleSize::new(puzzle);
+    let reasons = LIMITS.read().unwrap().exceeded(&size);
+    if reasons.is_empty() {
+        Ok(())
+    } else {
+        Err(PuzzleTooLarge { size, reasons })
+    }
+}
+
+/// A command line which explains `model` and `param` with demystify, for
+/// pasting into a shell in the directory holding them.
+#[must_use]
+pub fn local_command(model: &str, param: &str) -> String {
+    format!(
+        "demystify --model {} --param {} --html > demystify.html",
+        shell_quote(model),
+        shell_quote(param)
+    )
+}
+
+fn shell_quote(s: &str) -> String {
+    if !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || "._-/+".contains(c))
+    {
+        s.to_owned()
+    } else {
+        format!("'{}'", s.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limits_and_command() {
+        let limits = PuzzleLimits {
+            max_variables: 81,
+            ..PuzzleLimits::default()
+        };
+        let size = |variables, max_domain| PuzzleSize {
+            variables,
+            max_domain,
+            clauses: 1000,
+        };
+        assert!(limits.exceeded(&size(81, 9)).is_empty());
+        assert_eq!(
+            limits.exceeded(&size(625, 25)),
+            vec![
+                "625 variables (at most 81)",
+                "a variable with 25 values (at most 16)"
+            ]
+        );
+
+        assert_eq!(
+            local_command("sudoku.eprime", "big one's.param"),
+            r"demystify --model sudoku.eprime --param 'big one'\''s.param' --html > demystify.html"
+        );
+    }
+}
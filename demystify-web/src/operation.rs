@@ -0,0 +1,316 @@
+//! Long computations on a session's puzzle, which run on their own thread so
+//! that they do not hold up other requests.
+//!
+//! A session has at most one operation in flight. The request which starts
+//! it waits a while for the result, and if it is not ready by then returns a
+//! fragment which polls `/operation` until it is. Meanwhile, other requests
+//! from the same session which need the puzzle get a [`Busy`] response,
+//! offering to wait for the operation or to cancel it.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, OnceLock},
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use axum_session::{Session, SessionNullPool};
+use demystify::{
+    problem::{planner::PuzzlePlanner, solver::CancelFlag},
+    service::PlannerService,
+};
+use uuid::Uuid;
+
+use crate::{
+    timing::{self, Phase, Timings},
+    util::{self, get_solver_global},
+};
+
+/// Seconds a request waits for its operation, unless
+/// `DEMYSTIFY_OPERATION_WAIT_SECS` is set.
+const DEFAULT_WAIT_SECS: u64 = 5;
+
+fn wait_time() -> Duration {
+    let secs = std::env::var("DEMYSTIFY_OPERATION_WAIT_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_WAIT_SECS);
+    Duration::from_secs(secs)
+}
+
+/// The puzzle is in use by another request from the same session.
+#[derive(Debug)]
+pub struct Busy {
+    /// The operation using it, if it is one
+    pub operation: Option<Uuid>,
+}
+
+impl std::fmt::Display for Busy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The puzzle is busy with an earlier request")
+    }
+}
+
+impl std::error::Error for Busy {}
+
+impl Busy {
+    /// The message shown in place of the result, with buttons to wait for or
+    /// cancel the operation.
+    #[must_use]
+    pub fn html(&self) -> String {
+        match self.operation {
+            Some(id) => format!(
+                r##"<div class="alert alert-warning">Still working on an earlier request.
+        <button class="btn btn-primary" hx-get="/operation?id={id}" hx-target="#mainSpace">Wait for it</button>
+        <button class="btn btn-secondary" hx-post="/operation/cancel" hx-target="#mainSpace">Cancel it</button>
+        </div>"##
+            ),
+            None => r#"<div class="alert alert-warning">Still working on an earlier request, please try again.</div>"#
+                .to_string(),
+        }
+    }
+}
+
+struct Operation {
+    id: Uuid,
+    name: String,
+    cancel: CancelFlag,
+    /// `None` while running
+    result: Option<Result<String, String>>,
+    /// Where the time went, added to the request which takes the result
+    timings: Timings,
+}
+
+/// Operations, keyed by session id.
+fn operations() -> &'static Mutex<HashMap<Uuid, Operation>> {
+    static OPERATIONS: OnceLock<Mutex<HashMap<Uuid, Operation>>> = OnceLock::new();
+    OPERATIONS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// The id of the operation this session is running, if any.
+#[must_use]
+pub fn current(session: &Session<SessionNullPool>) -> Option<Uuid> {
+    current_for(session.get_session_id().uuid())
+}
+
+fn current_for(session_id: Uuid) -> Option<Uuid> {
+    operations()
+        .lock()
+        .unwrap()
+        .get(&session_id)
+        .filter(|op| op.result.is_none())
+        .map(|op| op.id)
+}
+
+/// The fragment shown while operation `id` runs, which asks for the result
+/// every second.
+fn polling_html(id: Uuid, name: &str) -> String {
+    format!(
+        r##"<div class="alert alert-info" hx-get="/operation?id={id}" hx-trigger="load delay:1s" hx-target="#mainSpace">
+        Still working on {name}&hellip;
+        <button class="btn btn-secondary" hx-post="/operation/cancel" hx-target="#mainSpace">Cancel</button>
+        </div>"##
+    )
+}
+
+/// Runs `f` on the session's puzzle with a [`PlannerService`], and returns its
+/// result if it finishes within the wait time, or otherwise a fragment which
+/// polls for it. `f` should stop early once its [`CancelFlag`] is set, for
+/// example by passing it in a [`SearchBudget`](demystify::problem::solver::SearchBudget).
+pub async fn run<F>(
+    session: &Session<SessionNullPool>,
+    name: &str,
+    f: F,
+) -> Result<String, util::AppError>
+where
+    F: FnOnce(&mut PuzzlePlanner, &CancelFlag) -> anyhow::Result<String> + Send + 'static,
+{
+    let solver = get_solver_global(session).await?;
+    Ok(run_for(
+        session.get_session_id().uuid(),
+        solver,
+        name,
+        wait_time(),
+        f,
+    )
+    .await?)
+}
+
+async fn run_for<F>(
+    session_id: Uuid,
+    solver: Arc<Mutex<PuzzlePlanner>>,
+    name: &str,
+    wait: Duration,
+    f: F,
+) -> anyhow::Result<String>
+where
+    F: FnOnce(&mut PuzzlePlanner, &CancelFlag) -> anyhow::Result<String> + Send + 'static,
+{
+    let id = Uuid::new_v4();
+
+    let mut task = {
+        let mut operations = operations().lock().unwrap();
+        if let Some(op) = operations.get(&session_id)
+            && op.result.is_none()
+        {
+            return Err(Busy {
+                operation: Some(op.id),
+            }
+            .into());
+        }
+        let task = PlannerService::from_shared(solver).spawn(move |planner, cancel| {
+            Ok(timing::collect(|| {
+                timing::time(Phase::Solver, || f(planner, cancel))
+            }))
+        });
+        // Any unclaimed result from an earlier operation is dropped
+        operations.insert(
+            session_id,
+            Operation {
+                id,
+                name: name.to_owned(),
+                cancel: task.cancel_flag().clone(),
+                result: None,
+                timings: Timings::default(),
+            },
+        );
+        task
+    };
+
+    // Stored by a task of its own, so the result is kept for `/operation`
+    // once this request stops waiting
+    let stored = tokio::spawn(async move {
+        let (result, mut timings) = (&mut task)
+            .await
+            .unwrap_or_else(|e| (Err(e), Timings::default()));
+        timings.add_waited(task.waited());
+
+        let mut operations = operations().lock().unwrap();
+        if let Some(op) = operations.get_mut(&session_id)
+            && op.id == id
+        {
+            op.result = Some(result.map_err(|e| format!("{e:#}")));
+            op.timings = timings;
+        }
+    });
+
+    if tokio::time::timeout(wait, stored).await.is_err() {
+        return Ok(polling_html(id, name));
+    }
+
+    take_for(session_id, id)
+}
+
+/// The result of operation `id`, if it has finished, or the polling
+/// fragment if not. A result can only be taken once.
+pub fn take(session: &Session<SessionNullPool>, id: Uuid) -> Result<String, util::AppError> {
+    Ok(take_for(session.get_session_id().uuid(), id)?)
+}
+
+fn take_for(session_id: Uuid, id: Uuid) -> anyhow::Result<String> {
+    let mut operations = operations().lock().unwrap();
+
+    match operations.get(&session_id) {
+        Some(op) if op.id == id => match op.result {
+            None => Ok(polling_html(id, &op.name)),
+            Some(_) => {
+                let op = operations.remove(&session_id).unwrap();
+                timing::add(&op.timings);
+                op.result.unwrap().map_err(|e| anyhow!(e))
+            }
+        },
+        _ => Err(anyhow!("This computation has finished or been replaced")),
+    }
+}
+
+/// Asks the running operation to stop, returning its id. Searches stop as if
+/// they had run out of time, so they can be continued later.
+pub fn cancel(session: &Session<SessionNullPool>) -> anyhow::Result<Uuid> {
+    cancel_for(session.get_session_id().uuid())
+}
+
+fn cancel_for(session_id: Uuid) -> anyhow::Result<Uuid> {
+    let operations = operations().lock().unwrap();
+    match operations.get(&session_id) {
+        Some(op) if op.result.is_none() => {
+            op.cancel.cancel();
+            Ok(op.id)
+        }
+        _ => Err(anyhow!("Nothing is running")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use demystify::problem::util::test_utils::sudoku_4x4_planner;
+
+    use super::*;
+
+    fn planner() -> Arc<Mutex<PuzzlePlanner>> {
+        Arc::new(Mutex::new(sudoku_4x4_planner()))
+    }
+
+    #[tokio::test]
+    async fn test_operation_polling() {
+        let session_id = Uuid::new_v4();
+        let solver = planner();
+
+        // Finishes within the wait, so the result comes back directly
+        let result = run_for(
+            session_id,
+            solver.clone(),
+            "a step",
+            Duration::from_secs(10),
+            |p, _| Ok(p.steps_taken().to_string()),
+        )
+        .await;
+        assert_eq!(result.unwrap(), "0");
+        assert_eq!(current_for(session_id), None);
+
+        // Runs until cancelled, so the request returns a polling fragment
+        let (started, wait_started) = mpsc::channel();
+        let html = run_for(
+            session_id,
+            solver.clone(),
+            "a step",
+            Duration::ZERO,
+            move |_, cancel| {
+                started.send(()).unwrap();
+                while !cancel.is_cancelled() {
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+                Ok("cancelled".to_owned())
+            },
+        )
+        .await
+        .unwrap();
+        wait_started.recv().unwrap();
+
+        let id = current_for(session_id).unwrap();
+        assert!(html.contains(&format!("/operation?id={id}")), "{html}");
+        assert!(solver.try_lock().is_err());
+
+        let busy = run_for(
+            session_id,
+            solver.clone(),
+            "another",
+            Duration::ZERO,
+            |_, _| Ok(String::new()),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(busy.downcast_ref::<Busy>().unwrap().operation, Some(id));
+
+        assert_eq!(cancel_for(session_id).unwrap(), id);
+        let mut result = take_for(session_id, id).unwrap();
+        while result.contains("Still working") {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            result = take_for(session_id, id).unwrap();
+        }
+        assert_eq!(result, "cancelled");
+        assert!(take_for(session_id, id).is_err());
+        assert!(cancel_for(session_id).is_err());
+    }
+}
@@ -0,0 +1,245 @@
+//! Race mode, where several sessions solve the same puzzle from the same
+//! starting position, and their progress can be compared.
+//!
+//! A room keeps a snapshot of the planner it was created from. Each player
+//! who joins gets their own fork of that snapshot, so all players share one
+//! `PuzzleParse` but have separate solvers.
+//!
+//! Rooms are dropped once nobody has used them for [`ROOM_IDLE_TIMEOUT`],
+//! and each session can only have [`MAX_ROOMS_PER_SESSION`] open at once, so
+//! rooms cannot fill the server's memory.
+
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, bail};
+use axum_session::{Session, SessionNullPool};
+use demystify::problem::planner::PuzzlePlanner;
+use serde::Serialize;
+use tracing::info;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::saved::SessionFiles;
+
+struct Player {
+    name: String,
+    joined: Instant,
+    /// How long each step took, measured from the previous step (or joining)
+    step_times: Vec<Duration>,
+    last_step: Instant,
+}
+
+/// How long a room is kept after it was last joined, stepped in or looked
+/// at.
+pub const ROOM_IDLE_TIMEOUT: Duration = Duration::from_secs(2 * 60 * 60);
+
+/// The most rooms one session can have open.
+pub const MAX_ROOMS_PER_SESSION: usize = 3;
+
+struct Room {
+    start: PuzzlePlanner,
+    /// The files the creator's puzzle was loaded from, so players' sessions
+    /// can be saved
+    files: Option<SessionFiles>,
+    players: HashMap<Uuid, Player>,
+    /// The session which created the room
+    creator: Uuid,
+    last_used: Instant,
+}
+
+/// How far one player has got.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct Standing {
+    pub name: String,
+    /// The number of steps this player has applied
+    pub steps: usize,
+    /// Seconds between joining the room and the latest step
+    pub total_secs: f64,
+    /// Mean seconds per step, if any steps have been taken
+    pub mean_step_secs: Option<f64>,
+}
+
+fn rooms() -> &'static Mutex<HashMap<Uuid, Room>> {
+    static ROOMS: OnceLock<Mutex<HashMap<Uuid, Room>>> = OnceLock::new();
+    ROOMS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn player_id(session: &Session<SessionNullPool>) -> Uuid {
+    session.get_session_id().uuid()
+}
+
+/// Creates a room starting from the position of `planner`, returning its id.
+/// Fails if this session already has [`MAX_ROOMS_PER_SESSION`] rooms.
+pub fn create_room(
+    session: &Session<SessionNullPool>,
+    planner: &PuzzlePlanner,
+) -> anyhow::Result<Uuid> {
+    create_room_for(player_id(session), planner, session.get("files"))
+}
+
+fn create_room_for(
+    creator: Uuid,
+    planner: &PuzzlePlanner,
+    files: Option<SessionFiles>,
+) -> anyhow::Result<Uuid> {
+    let mut rooms = rooms().lock().unwrap();
+    let open = rooms.values().filter(|r| r.creator == creator).count();
+    if open >= MAX_ROOMS_PER_SESSION {
+        bail!(
+            "You already have {open} race rooms open, which is the most allowed. They close after {} hours without use.",
+            ROOM_IDLE_TIMEOUT.as_secs() / 3600
+        );
+    }
+
+    let id = Uuid::new_v4();
+    rooms.insert(
+        id,
+        Room {
+            start: planner.fork(),
+            files,
+            players: HashMap::new(),
+            creator,
+            last_used: Instant::now(),
+        },
+    );
+    Ok(id)
+}
+
+/// Drops the rooms which have not been used for `timeout`, returning how
+/// many there were.
+pub fn evict_idle(timeout: Duration) -> usize {
+    let mut rooms = rooms().lock().unwrap();
+    let before = rooms.len();
+    rooms.retain(|_, r| r.last_used.elapsed() < timeout);
+    before - rooms.len()
+}
+
+/// Drops idle rooms in the background, as described by [`evict_idle`].
+pub fn spawn_eviction() {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(ROOM_IDLE_TIMEOUT / 8);
+        loop {
+            interval.tick().await;
+            let evicted = evict_idle(ROOM_IDLE_TIMEOUT);
+            if evicted > 0 {
+                info!("Closed {evicted} idle race rooms");
+            }
+        }
+    });
+}
+
+/// Adds this session to a room, returning a fresh planner at the room's
+/// starting position, and giving the session the files the creator's puzzle
+/// was loaded from. Joining again restarts the player's progress.
+pub fn join_room(
+    session: &Session<SessionNullPool>,
+    room: Uuid,
+    name: Option<String>,
+) -> anyhow::Result<PuzzlePlanner> {
+    let mut rooms = rooms().lock().unwrap();
+    let r = rooms.get_mut(&room).context("No such race room")?;
+    r.last_used = Instant::now();
+
+    let name = name.unwrap_or_else(|| format!("Player {}", r.players.len() + 1));
+    let now = Instant::now();
+    r.players.insert(
+        player_id(session),
+        Player {
+            name,
+            joined: now,
+            step_times: vec![],
+            last_step: now,
+        },
+    );
+
+    session.set("race_room", room);
+    // Any files are from an earlier puzzle, so are replaced by the creator's
+    match &r.files {
+        Some(files) => session.set("files", files),
+        None => session.remove("files"),
+    }
+    Ok(r.start.fork())
+}
+
+/// Records that this session has applied a step, however it was found, if
+/// it is in a room.
+pub fn record_step(session: &Session<SessionNullPool>) {
+    let Some(room) = session.get::<Uuid>("race_room") else {
+        return;
+    };
+    record_step_for(room, player_id(session));
+}
+
+fn record_step_for(room: Uuid, player: Uuid) {
+    let mut rooms = rooms().lock().unwrap();
+    if let Some(r) = rooms.get_mut(&room)
+        && let Some(player) = r.players.get_mut(&player)
+    {
+        let now = Instant::now();
+        player.step_times.push(now - player.last_step);
+        player.last_step = now;
+        r.last_used = now;
+    }
+}
+
+/// The players in a room, most steps first, with ties broken by time taken.
+pub fn standings(room: Uuid) -> anyhow::Result<Vec<Standing>> {
+    let mut rooms = rooms().lock().unwrap();
+    let r = rooms.get_mut(&room).context("No such race room")?;
+    r.last_used = Instant::now();
+
+    let mut standings: Vec<_> = r
+        .players
+        .values()
+        .map(|p| {
+            let total_secs = (p.last_step - p.joined).as_secs_f64();
+            Standing {
+                name: p.name.clone(),
+                steps: p.step_times.len(),
+                total_secs,
+                mean_step_secs: (!p.step_times.is_empty())
+                    .then(|| total_secs / p.step_times.len() as f64),
+            }
+        })
+        .collect();
+
+    standings.sort_by(|a, b| {
+        b.steps
+            .cmp(&a.steps)
+            .then(a.total_secs.total_cmp(&b.total_secs))
+    });
+
+    Ok(standings)
+}
+
+#[cfg(test)]
+mod tests {
+    use demystify::problem::util::test_utils::sudoku_4x4_planner;
+
+    use super::*;
+
+    #[test]
+    fn test_room_limits() {
+        let planner = sudoku_4x4_planner();
+
+        let creator = Uuid::new_v4();
+        let first = create_room_for(creator, &planner, None).unwrap();
+        for _ in 1..MAX_ROOMS_PER_SESSION {
+            create_room_for(creator, &planner, None).unwrap();
+        }
+        let err = create_room_for(creator, &planner, None).unwrap_err();
+        assert!(err.to_string().contains("the most allowed"), "{err}");
+        // Other sessions have rooms of their own
+        create_room_for(Uuid::new_v4(), &planner, None).unwrap();
+
+        assert_eq!(evict_idle(ROOM_IDLE_TIMEOUT), 0);
+        assert!(standings(first).is_ok());
+        assert_eq!(evict_idle(Duration::ZERO), MAX_ROOMS_PER_SESSION + 1);
+        assert!(standings(first).is_err());
+        create_room_for(creator, &planner, None).unwrap();
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Context, anyhow, bail};
+use demystify::problem::{PuzLit, notes::Notes, planner::PuzzlePlanner, util::format::Format};
+use miniz_oxide::inflate::TINFLStatus;
+use serde::{Deserialize, Serialize};
+
+use crate::campaign::CampaignState;
+
+/// Bump this whenever the layout of [`SavedSession`] changes.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// How [`SavedSession`]s are read back. Add an upgrade from the previous
+/// version whenever [`FORMAT_VERSION`] is bumped.
+pub const SESSION_FORMAT: Format = Format {
+    name: "Saved session",
+    version: FORMAT_VERSION,
+    oldest: 1,
+    upgrades: &[],
+};
+
+/// The model and parameter files a session was started from.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SessionFiles {
+    /// File name of the model, used to tell `.eprime` and `.essence` apart
+    pub model_name: String,
+    pub model: String,
+    /// File name of the parameters, used to tell `.param` and `.json` apart
+    pub param_name: String,
+    pub param: String,
+}
+
+/// Everything needed to recreate a solving position, possibly on another
+/// instance of the server.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedSession {
+    pub format_version: u32,
+    pub files: SessionFiles,
+    /// The literals deduced so far, in the order they were deduced
+    pub known: Vec<PuzLit>,
+    /// The step which deduced each of `known`, with 0 for those known from
+    /// the start, so the history of the solve can be shown again. Empty in
+    /// sessions saved before it was kept.
+    #[serde(default)]
+    pub history: Vec<usize>,
+    /// Optional, so sessions saved before notes existed still load
+    #[serde(default)]
+    pub notes: Notes,
+    #[serde(default)]
+    pub steps_taken: usize,
+    /// The progress through the server's campaign, if the session has played
+    /// any of it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub campaign: Option<CampaignState>,
+}
+
+impl SavedSession {
+    pub fn new(files: SessionFiles, planner: &PuzzlePlanner) -> Self {
+        let puzzle = planner.puzzle();
+        let (known, history) = planner
+            .get_all_known_lits()
+            .iter()
+            .filter(|l| puzzle.lit_is_var(l))
+            .flat_map(|l| {
+                let step = planner.deduced_in(l).unwrap_or(0);
+                puzzle.lit_to_vars(l).iter().map(move |p| (p.clone(), step))
+            })
+            .unzip();
+
+        Self {
+            format_version: FORMAT_VERSION,
+            files,
+            known,
+            history,
+            notes: planner.notes().clone(),
+            steps_taken: planner.steps_taken(),
+            campaign: None,
+        }
+    }
+
+    /// Replays the saved deductions, and restores the notes, into a planner
+    /// freshly built from `files`. Saved files can come from anyone, so this
+    /// fails, leaving `planner` as it was, if any deduction is not about this
+    /// puzzle or does not follow from it.
+    pub fn restore_known(&self, planner: &mut PuzzlePlanner) -> anyhow::Result<()> {
+        if !self.history.is_empty() && self.history.len() != self.known.len() {
+            bail!("The saved session's history does not match its deductions");
+        }
+        let mut lits = BTreeMap::new();
+        for (i, p) in self.known.iter().enumerate() {
+            let lit = planner.puzzle().litmap.get(p).with_context(|| {
+                format!("The saved session mentions {p}, which is not in this puzzle")
+            })?;
+            lits.insert(*lit, self.history.get(i).copied().unwrap_or(0));
+        }
+        planner
+            .restore_deductions(&lits)
+            .context("The saved session does not match its puzzle")?;
+        *planner.notes_mut() = self.notes.clone();
+        planner.set_steps_taken(self.steps_taken);
+        Ok(())
+    }
+
+    /// Serialise to compressed JSON.
+    pub fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        let json = serde_json::to_vec(self)?;
+        Ok(miniz_oxide::deflate::compress_to_vec(&json, 6))
+    }
+
+    /// Read the output of [`SavedSession::to_bytes`], from this or an older
+    /// version of the server. Fails if it decompresses to more than
+    /// `max_size` bytes.
+    pub fn from_bytes(bytes: &[u8], max_size: usize) -> anyhow::Result<Self> {
+        let json = miniz_oxide::inflate::decompress_to_vec_with_limit(bytes, max_size).map_err(
+            |e| match e.status {
+                TINFLStatus::HasMoreOutput => {
+                    anyhow!("The saved session is larger than {max_size} bytes once decompressed")
+                }
+                _ => anyhow!("Failed to decompress saved session: {e}"),
+            },
+        )?;
+
+        // The version is checked before the fields, so files from a newer
+        // server give a useful error rather than a missing field.
+        SESSION_FORMAT.from_reader(json.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use demystify::problem::{PuzVar, VarValPair, util::test_utils::sudoku_4x4_planner as planner};
+
+    use super::*;
+
+    fn files() -> SessionFiles {
+        SessionFiles {
+            model_name: "upload.eprime".to_owned(),
+            model: String::new(),
+            param_name: "upload.param".to_owned(),
+            param: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_restore_known() {
+        // The 4x4 sudoku is solved in two steps
+        let mut played = planner();
+        for _ in 0..2 {
+            let step = played.next_step();
+            played.apply_step(&step);
+        }
+        let saved = SavedSession::new(files(), &played);
+        assert_eq!(saved.history.len(), saved.known.len());
+        assert!(saved.history.contains(&2));
+
+        let mut restored = planner();
+        saved.restore_known(&mut restored).unwrap();
+        assert_eq!(restored.steps_taken(), 2);
+        for lit in &played.get_all_known_lits() {
+            if played.puzzle().lit_is_var(lit) {
+                assert!(restored.get_all_known_lits().contains(lit));
+                assert_eq!(
+                    restored.deduced_in(lit).unwrap_or(0),
+                    played.deduced_in(lit).unwrap_or(0)
+                );
+            }
+        }
+
+        // Files can be edited, so literals must be about this puzzle
+        let mut unknown = saved.clone();
+        unknown.known.push(PuzLit::new_eq(VarValPair::new(
+            &PuzVar::new("nosuch", vec![1, 1]),
+            1,
+        )));
+        unknown.history.push(1);
+        let mut fresh = planner();
+        let err = unknown.restore_known(&mut fresh).unwrap_err();
+        assert!(err.to_string().contains("not in this puzzle"), "{err}");
+
+        // including the cells and values of the variables it does have
+        for (index, value) in [(vec![9, 9], 1), (vec![1, 1], 9)] {
+            let mut out_of_range = saved.clone();
+            out_of_range.known.push(PuzLit::new_eq(VarValPair::new(
+                &PuzVar::new("grid", index),
+                value,
+            )));
+            out_of_range.history.push(1);
+            let mut fresh = planner();
+            let err = out_of_range.restore_known(&mut fresh).unwrap_err();
+            assert!(err.to_string().contains("not in this puzzle"), "{err}");
+            assert_eq!(fresh.steps_taken(), 0);
+        }
+
+        // and must follow from it
+        let wrong = saved
+            .known
+            .iter()
+            .find(|p| p.sign() && p.var().name() == "grid")
+            .unwrap()
+            .neg();
+        let mut contradictory = saved.clone();
+        contradictory.known.push(wrong);
+        contradictory.history.push(2);
+        let mut fresh = planner();
+        let before = fresh.get_all_known_lits().len();
+        let err = contradictory.restore_known(&mut fresh).unwrap_err();
+        assert!(err.to_string().contains("does not match"), "{err}");
+        assert_eq!(fresh.get_all_known_lits().len(), before);
+    }
+
+    #[test]
+    fn test_saved_session_versions() {
+        let saved = SavedSession {
+            format_version: FORMAT_VERSION,
+            files: SessionFiles {
+                model_name: "upload.eprime".to_owned(),
+                model: String::new(),
+                param_name: "upload.param".to_owned(),
+                param: String::new(),
+            },
+            known: vec![],
+            history: vec![],
+            notes: Notes::default(),
+            steps_taken: 3,
+            campaign: None,
+        };
+        let back = SavedSession::from_bytes(&saved.to_bytes().unwrap(), usize::MAX).unwrap();
+        assert_eq!(back.steps_taken, 3);
+        assert_eq!(back.campaign, None);
+        let err = SavedSession::from_bytes(&saved.to_bytes().unwrap(), 10).unwrap_err();
+        assert!(err.to_string().contains("larger than 10 bytes"), "{err}");
+
+        let mut campaign = CampaignState {
+            level: Some(1),
+            ..CampaignState::default()
+        };
+        campaign.progress.finish(0, 2);
+        let with_campaign = SavedSession {
+            campaign: Some(campaign.clone()),
+            ..saved.clone()
+        };
+        let back =
+            SavedSession::from_bytes(&with_campaign.to_bytes().unwrap(), usize::MAX).unwrap();
+        assert_eq!(back.campaign, Some(campaign));
+
+        let newer = SavedSession {
+            format_version: FORMAT_VERSION + 1,
+            ..saved
+        };
+        let err = SavedSession::from_bytes(&newer.to_bytes().unwrap(), usize::MAX).unwrap_err();
+        assert!(err.to_string().contains("newer version"), "{err}");
+    }
+}
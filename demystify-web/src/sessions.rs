@@ -0,0 +1,387 @@
+//! The puzzles of active sessions, and the admin routes to inspect and evict
+//! them.
+//!
+//! Each session's planner is kept in memory until it is evicted, either by an
+//! operator through `/admin/sessions` or, with
+//! [`WebConfig::session_idle_timeout`](crate::config::WebConfig::session_idle_timeout),
+//! once it has not been used for that long. If
+//! [`WebConfig::session_dir`](crate::config::WebConfig::session_dir) is set,
+//! sessions can be saved there as a [`SavedSession`] when they are evicted,
+//! and are restored when their user comes back.
+//!
+//! The admin routes are only served when
+//! [`WebConfig::admin_token`](crate::config::WebConfig::admin_token) is set,
+//! and need it in an `Authorization: Bearer <token>` header.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock, RwLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, anyhow, bail};
+use axum::{
+    Json,
+    extract::{Path as UrlPath, Request},
+    http::{StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use demystify::problem::{history::HistoryConfig, planner::PuzzlePlanner};
+use serde::Serialize;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    limits::PuzzleSize,
+    saved::{SavedSession, SessionFiles},
+    util,
+};
+
+struct Entry {
+    planner: Arc<Mutex<PuzzlePlanner>>,
+    /// The files the puzzle was loaded from, which are needed to save it
+    files: Option<SessionFiles>,
+    created: Instant,
+    last_used: Instant,
+}
+
+fn sessions() -> &'static Mutex<HashMap<Uuid, Entry>> {
+    static SESSIONS: OnceLock<Mutex<HashMap<Uuid, Entry>>> = OnceLock::new();
+    SESSIONS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+static SESSION_DIR: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+fn history() -> &'static RwLock<HistoryConfig> {
+    static HISTORY: OnceLock<RwLock<HistoryConfig>> = OnceLock::new();
+    HISTORY.get_or_init(|| RwLock::new(HistoryConfig::default()))
+}
+
+/// Sets how the steps of each session are kept, from
+/// [`WebConfig::history_config`](crate::config::WebConfig::history_config).
+/// This applies to sessions stored from now on.
+pub fn set_history(config: HistoryConfig) {
+    *history().write().unwrap() = config;
+}
+
+/// Sets where sessions are saved, from
+/// [`WebConfig::session_dir`](crate::config::WebConfig::session_dir).
+pub fn set_session_dir(dir: Option<PathBuf>) {
+    *SESSION_DIR.write().unwrap() = dir;
+}
+
+fn session_dir() -> Option<PathBuf> {
+    SESSION_DIR.read().unwrap().clone()
+}
+
+fn saved_path(dir: &Path, id: Uuid) -> PathBuf {
+    dir.join(format!("{id}.dmy"))
+}
+
+/// The planner of session `id`, if it is in memory, counting this as a use.
+pub fn get(id: Uuid) -> Option<Arc<Mutex<PuzzlePlanner>>> {
+    let mut sessions = sessions().lock().unwrap();
+    let entry = sessions.get_mut(&id)?;
+    entry.last_used = Instant::now();
+    Some(entry.planner.clone())
+}
+
+/// Stores the planner of session `id`, which was loaded from `files`.
+pub fn set(id: Uuid, mut planner: PuzzlePlanner, files: Option<SessionFiles>) {
+    planner.config_mut().history = history().read().unwrap().clone();
+    let now = Instant::now();
+    sessions().lock().unwrap().insert(
+        id,
+        Entry {
+            planner: Arc::new(Mutex::new(planner)),
+            files,
+            created: now,
+            last_used: now,
+        },
+    );
+}
+
+/// Drops session `id` from memory, returning whether it was there.
+pub fn evict(id: Uuid) -> bool {
+    sessions().lock().unwrap().remove(&id).is_some()
+}
+
+/// Saves session `id` to the session directory, then drops it from memory.
+/// Fails, keeping the session, if there is no directory, the puzzle was not
+/// loaded from files (such as the demo puzzle), or it is in use.
+pub fn persist_and_evict(id: Uuid) -> anyhow::Result<PathBuf> {
+    let dir = session_dir().context("No session_dir is configured to save sessions in")?;
+
+    // Only serialised under the lock, so other sessions are not held up
+    // while the file is written
+    let (planner, last_used, bytes) = {
+        let sessions = sessions().lock().unwrap();
+        let entry = sessions.get(&id).context("No such session")?;
+        let files = entry
+            .files
+            .clone()
+            .context("This session's puzzle was not loaded from files, so cannot be saved")?;
+        let bytes = {
+            let planner = entry
+                .planner
+                .try_lock()
+                .map_err(|_| anyhow!("This session is in use"))?;
+            SavedSession::new(files, &planner).to_bytes()?
+        };
+        (entry.planner.clone(), entry.last_used, bytes)
+    };
+
+    std::fs::create_dir_all(&dir).with_context(|| format!("Cannot create {}", dir.display()))?;
+    let path = saved_path(&dir, id);
+    std::fs::write(&path, bytes).with_context(|| format!("Cannot write {}", path.display()))?;
+
+    let mut sessions = sessions().lock().unwrap();
+    match sessions.get(&id) {
+        Some(entry) if Arc::ptr_eq(&entry.planner, &planner) && entry.last_used == last_used => {
+            sessions.remove(&id);
+            Ok(path)
+        }
+        _ => {
+            // Used or replaced while it was being written, so the file is out
+            // of date
+            drop(sessions);
+            let _ = std::fs::remove_file(&path);
+            bail!("This session was used while it was being saved")
+        }
+    }
+}
+
+/// The saved copy of session `id`, if there is one, which is removed so it
+/// is only restored once.
+pub fn take_saved(id: Uuid) -> anyhow::Result<Option<Vec<u8>>> {
+    let Some(dir) = session_dir() else {
+        return Ok(None);
+    };
+    let path = saved_path(&dir, id);
+    let bytes = match std::fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Cannot read {}", path.display())),
+    };
+    std::fs::remove_file(&path).with_context(|| format!("Cannot remove {}", path.display()))?;
+    Ok(Some(bytes))
+}
+
+/// What `/admin/sessions` shows about one session.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SessionInfo {
+    pub id: Uuid,
+    pub age_secs: u64,
+    pub idle_secs: u64,
+    /// Whether the puzzle was loaded from files, so can be saved
+    pub saveable: bool,
+    /// Set if a request is using the puzzle, in which case its size is not
+    /// shown
+    pub busy: bool,
+    pub variables: Option<usize>,
+    pub clauses: Option<usize>,
+    /// The number of literals known so far
+    pub known: Option<usize>,
+    pub steps_taken: Option<usize>,
+    /// A rough estimate of the memory used, from the literals of the clauses
+    /// and of the history. The solver keeps its own copies of the clauses,
+    /// so the real footprint is a few times this.
+    pub approx_bytes: Option<usize>,
+}
+
+impl SessionInfo {
+    fn new(id: Uuid, entry: &Entry, now: Instant) -> Self {
+        let mut info = Self {
+            id,
+            age_secs: now.duration_since(entry.created).as_secs(),
+            idle_secs: now.duration_since(entry.last_used).as_secs(),
+            saveable: entry.files.is_some(),
+            busy: false,
+            variables: None,
+            clauses: None,
+            known: None,
+            steps_taken: None,
+            approx_bytes: None,
+        };
+        let Ok(planner) = entry.planner.try_lock() else {
+            info.busy = true;
+            return info;
+        };
+
+        let puzzle = planner.puzzle();
+        let size = PuzzleSize::new(puzzle);
+        let literals = puzzle
+            .cnf
+            .as_ref()
+            .map_or(0, |cnf| cnf.iter().map(|c| c.len()).sum::<usize>());
+        let known = planner.get_all_known_lits().len();
+        let file_bytes = entry
+            .files
+            .as_ref()
+            .map_or(0, |f| f.model.len() + f.param.len());
+
+        info.variables = Some(size.variables);
+        info.clauses = Some(size.clauses);
+        info.known = Some(known);
+        info.steps_taken = Some(planner.steps_taken());
+        info.approx_bytes =
+            Some((literals + known) * std::mem::size_of::<rustsat::types::Lit>() + file_bytes);
+        info
+    }
+}
+
+/// Every session in memory, most recently used first.
+#[must_use]
+pub fn list() -> Vec<SessionInfo> {
+    let now = Instant::now();
+    let mut infos: Vec<_> = sessions()
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(&id, entry)| SessionInfo::new(id, entry, now))
+        .collect();
+    infos.sort_by_key(|i| i.idle_secs);
+    infos
+}
+
+/// Evicts every session unused for at least `timeout`, saving those which
+/// can be saved if there is a session directory. Returns how many were
+/// evicted, and how many of those were saved.
+pub fn evict_idle(timeout: Duration) -> (usize, usize) {
+    let now = Instant::now();
+    let idle: Vec<Uuid> = sessions()
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, e)| now.duration_since(e.last_used) >= timeout)
+        .map(|(&id, _)| id)
+        .collect();
+
+    let (mut evicted, mut saved) = (0, 0);
+    for id in idle {
+        // It may have been used since it was found
+        let Some(saveable) = sessions()
+            .lock()
+            .unwrap()
+            .get(&id)
+            .filter(|e| Instant::now().duration_since(e.last_used) >= timeout)
+            .map(|e| e.files.is_some())
+        else {
+            continue;
+        };
+        let saveable = saveable && session_dir().is_some();
+        if saveable {
+            match persist_and_evict(id) {
+                Ok(_) => {
+                    evicted += 1;
+                    saved += 1;
+                }
+                // Most likely in use again, so keep it for now
+                Err(e) => warn!("Could not save idle session {id}: {e:#}"),
+            }
+        } else if evict(id) {
+            evicted += 1;
+        }
+    }
+    (evicted, saved)
+}
+
+/// Evicts idle sessions in the background, as described by [`evict_idle`].
+pub fn spawn_eviction(timeout: Duration) {
+    let period = (timeout / 4).clamp(Duration::from_secs(1), Duration::from_secs(60));
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            match tokio::task::spawn_blocking(move || evict_idle(timeout)).await {
+                Ok((0, _)) => {}
+                Ok((evicted, saved)) => {
+                    info!("Evicted {evicted} idle sessions, saving {saved}");
+                }
+                Err(e) => warn!("Evicting idle sessions failed: {e}"),
+            }
+        }
+    });
+}
+
+/// Rejects requests without `token`, as described in the [module docs](self).
+pub async fn require_token(token: Arc<str>, request: Request, next: Next) -> Response {
+    let given = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    match given {
+        Some(given) if constant_time_eq(given.as_bytes(), token.as_bytes()) => {
+            next.run(request).await
+        }
+        _ => (StatusCode::UNAUTHORIZED, "An admin token is needed").into_response(),
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn parse_id(id: &str) -> anyhow::Result<Uuid> {
+    Uuid::parse_str(id).with_context(|| format!("'{id}' is not a session id"))
+}
+
+/// Serves [`list`] as JSON.
+pub async fn list_endpoint() -> Json<Vec<SessionInfo>> {
+    Json(list())
+}
+
+/// Evicts one session without saving it.
+pub async fn evict_endpoint(UrlPath(id): UrlPath<String>) -> Result<String, util::AppError> {
+    let id = parse_id(&id)?;
+    if !evict(id) {
+        return Err(anyhow!("No such session").into());
+    }
+    Ok(format!("Evicted {id}"))
+}
+
+/// Saves one session, then evicts it.
+pub async fn persist_endpoint(UrlPath(id): UrlPath<String>) -> Result<String, util::AppError> {
+    let id = parse_id(&id)?;
+    let path = tokio::task::spawn_blocking(move || persist_and_evict(id))
+        .await
+        .context("Saving the session failed")??;
+    Ok(format!("Saved {id} to {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secrets"));
+    }
+
+    #[test]
+    fn test_persist_and_evict() -> anyhow::Result<()> {
+        let planner = demystify::problem::util::test_utils::sudoku_4x4_planner();
+        let files = SessionFiles {
+            model_name: "upload.eprime".to_owned(),
+            model: String::new(),
+            param_name: "upload.param".to_owned(),
+            param: String::new(),
+        };
+        let dir = tempfile::tempdir()?;
+        set_session_dir(Some(dir.path().to_owned()));
+
+        let id = Uuid::new_v4();
+        set(id, planner, Some(files));
+        let path = persist_and_evict(id)?;
+        assert!(path.exists());
+        assert!(get(id).is_none());
+        assert!(take_saved(id)?.is_some());
+        assert!(!path.exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,325 @@
+//! Where the time of each request goes, so that a slow demo can be put down
+//! to contention (waiting for a thread, or for another request on the same
+//! puzzle) or to the cost of solving.
+//!
+//! Every request is timed by [`middleware`] and logged. Code which solves or
+//! renders wraps that work in [`time`], and work done on another thread is
+//! gathered with [`collect`] and added back with [`add`]. With
+//! [`WebConfig::debug_timing`](crate::config::WebConfig::debug_timing), the
+//! breakdown is also returned in a `Server-Timing` header, and `/admin/timings`
+//! summarises recent requests for those with the
+//! [admin token](crate::config::WebConfig::admin_token).
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    Json,
+    extract::{MatchedPath, Request},
+    http::HeaderValue,
+    middleware::Next,
+    response::Response,
+};
+use demystify::service::Waited;
+use serde::Serialize;
+use tracing::info;
+
+/// How many requests to each endpoint are kept for [`summary`].
+const KEPT_PER_ENDPOINT: usize = 1000;
+
+/// A part of the work of a request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// Waiting for a thread to run a blocking operation on
+    Queue,
+    /// Waiting for the session's puzzle
+    Lock,
+    /// Searching for deductions
+    Solver,
+    /// Making the HTML for the result
+    Render,
+}
+
+/// The time a request spent in each [`Phase`]. The rest of `total` went on
+/// everything else, such as reading the request and the session.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Timings {
+    pub queue: Duration,
+    pub lock: Duration,
+    pub solver: Duration,
+    pub render: Duration,
+    pub total: Duration,
+}
+
+impl Timings {
+    fn phase_mut(&mut self, phase: Phase) -> &mut Duration {
+        match phase {
+            Phase::Queue => &mut self.queue,
+            Phase::Lock => &mut self.lock,
+            Phase::Solver => &mut self.solver,
+            Phase::Render => &mut self.render,
+        }
+    }
+
+    fn phases_total(&self) -> Duration {
+        self.queue + self.lock + self.solver + self.render
+    }
+
+    /// Adds the time a [`Task`](demystify::service::Task) was queued, as
+    /// [`Phase::Queue`] and [`Phase::Lock`].
+    pub fn add_waited(&mut self, waited: Option<Waited>) {
+        let waited = waited.unwrap_or_default();
+        self.queue += waited.thread;
+        self.lock += waited.planner;
+    }
+
+    fn add(&mut self, other: &Timings) {
+        self.queue += other.queue;
+        self.lock += other.lock;
+        self.solver += other.solver;
+        self.render += other.render;
+    }
+
+    /// The value of a `Server-Timing` header, in milliseconds.
+    #[must_use]
+    pub fn header_value(&self) -> String {
+        [
+            ("queue", self.queue),
+            ("lock", self.lock),
+            ("solver", self.solver),
+            ("render", self.render),
+            ("total", self.total),
+        ]
+        .iter()
+        .map(|(name, d)| format!("{name};dur={:.1}", d.as_secs_f64() * 1000.0))
+        .collect::<Vec<_>>()
+        .join(", ")
+    }
+}
+
+tokio::task_local! {
+    static REQUEST: RefCell<Timings>;
+}
+
+thread_local! {
+    static COLLECTING: RefCell<Option<Timings>> = const { RefCell::new(None) };
+}
+
+/// Applies `f` to the timings being gathered, either by [`collect`] on this
+/// thread or for the current request, if there are any.
+fn with_current<T>(f: impl FnOnce(&mut Timings) -> T) -> Option<T> {
+    let mut f = Some(f);
+    let collected = COLLECTING.with(|c| c.borrow_mut().as_mut().map(|t| (f.take().unwrap())(t)));
+    if collected.is_some() {
+        return collected;
+    }
+    REQUEST
+        .try_with(|t| (f.take().unwrap())(&mut t.borrow_mut()))
+        .ok()
+}
+
+/// Adds `d` to `phase` of the current request. Does nothing outside one.
+pub fn record(phase: Phase, d: Duration) {
+    with_current(|t| *t.phase_mut(phase) += d);
+}
+
+/// Runs `f`, counting the time it takes as `phase`, apart from any time which
+/// `f` itself records as another phase.
+pub fn time<T>(phase: Phase, f: impl FnOnce() -> T) -> T {
+    let before = with_current(|t| t.phases_total()).unwrap_or_default();
+    let start = Instant::now();
+    let result = f();
+    let elapsed = start.elapsed();
+    let nested = with_current(|t| t.phases_total()).unwrap_or_default() - before;
+    record(phase, elapsed.saturating_sub(nested));
+    result
+}
+
+/// Runs `f`, returning what it records instead of adding it to the current
+/// request. This is for work on other threads, whose timings can be passed
+/// back to the request with [`add`].
+pub fn collect<T>(f: impl FnOnce() -> T) -> (T, Timings) {
+    let outer = COLLECTING.with(|c| c.replace(Some(Timings::default())));
+    let result = f();
+    let timings = COLLECTING.with(|c| c.replace(outer)).unwrap_or_default();
+    (result, timings)
+}
+
+/// Adds `timings` (apart from their total) to the current request.
+pub fn add(timings: &Timings) {
+    with_current(|t| t.add(timings));
+}
+
+fn recent() -> &'static Mutex<HashMap<String, VecDeque<Timings>>> {
+    static RECENT: OnceLock<Mutex<HashMap<String, VecDeque<Timings>>>> = OnceLock::new();
+    RECENT.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Times each request, logs the result and keeps it for [`summary`]. If
+/// `header` is set, the timings are also returned in a `Server-Timing`
+/// header.
+pub async fn middleware(header: bool, request: Request, next: Next) -> Response {
+    let endpoint = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("(unmatched)", MatchedPath::as_str)
+        .to_owned();
+    let start = Instant::now();
+
+    let (mut response, mut timings) = REQUEST
+        .scope(RefCell::new(Timings::default()), async {
+            let response = next.run(request).await;
+            (response, REQUEST.with(|t| *t.borrow()))
+        })
+        .await;
+    timings.total = start.elapsed();
+
+    info!(
+        target: "timing",
+        endpoint,
+        queue_ms = timings.queue.as_millis(),
+        lock_ms = timings.lock.as_millis(),
+        solver_ms = timings.solver.as_millis(),
+        render_ms = timings.render.as_millis(),
+        total_ms = timings.total.as_millis(),
+        "Request timings"
+    );
+
+    if header && let Ok(value) = HeaderValue::from_str(&timings.header_value()) {
+        response.headers_mut().insert("server-timing", value);
+    }
+
+    let mut recent = recent().lock().unwrap();
+    let kept = recent.entry(endpoint).or_default();
+    if kept.len() == KEPT_PER_ENDPOINT {
+        kept.pop_front();
+    }
+    kept.push_back(timings);
+
+    response
+}
+
+/// Percentiles of the time spent in one phase, in milliseconds.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Percentiles {
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+impl Percentiles {
+    #[must_use]
+    pub fn new(mut durations: Vec<Duration>) -> Self {
+        durations.sort();
+        let at = |p: f64| {
+            // Nearest rank
+            let rank = ((p * durations.len() as f64).ceil() as usize).max(1);
+            durations
+                .get(rank - 1)
+                .map_or(0.0, |d| d.as_secs_f64() * 1000.0)
+        };
+        Self {
+            p50: at(0.5),
+            p90: at(0.9),
+            p99: at(0.99),
+            max: at(1.0),
+        }
+    }
+}
+
+/// The recent requests to one endpoint.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct EndpointSummary {
+    pub requests: usize,
+    pub queue: Percentiles,
+    pub lock: Percentiles,
+    pub solver: Percentiles,
+    pub render: Percentiles,
+    pub total: Percentiles,
+}
+
+impl EndpointSummary {
+    #[must_use]
+    pub fn new<'a>(timings: impl IntoIterator<Item = &'a Timings> + Clone) -> Self {
+        let phase = |f: fn(&Timings) -> Duration| {
+            Percentiles::new(timings.clone().into_iter().map(f).collect())
+        };
+        Self {
+            requests: timings.clone().into_iter().count(),
+            queue: phase(|t| t.queue),
+            lock: phase(|t| t.lock),
+            solver: phase(|t| t.solver),
+            render: phase(|t| t.render),
+            total: phase(|t| t.total),
+        }
+    }
+}
+
+/// A summary of the last requests to each endpoint, by route.
+#[must_use]
+pub fn summary() -> BTreeMap<String, EndpointSummary> {
+    recent()
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(endpoint, timings)| (endpoint.clone(), EndpointSummary::new(timings)))
+        .collect()
+}
+
+/// Serves [`summary`] as JSON.
+pub async fn timings_endpoint() -> Json<BTreeMap<String, EndpointSummary>> {
+    Json(summary())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_nested_and_collected() {
+        let ms = Duration::from_millis;
+        let ((), timings) = collect(|| {
+            time(Phase::Solver, || {
+                std::thread::sleep(ms(20));
+                time(Phase::Render, || std::thread::sleep(ms(10)));
+            });
+            record(Phase::Queue, ms(5));
+        });
+        assert_eq!(timings.queue, ms(5));
+        assert!(timings.render >= ms(10));
+        // The render time is not counted again as solver time
+        assert!(timings.solver >= ms(20) && timings.solver < ms(30));
+
+        // Outside a request or collection, nothing is kept
+        record(Phase::Lock, ms(1));
+        assert_eq!(collect(|| ()).1, Timings::default());
+
+        let header = Timings {
+            solver: ms(3),
+            ..timings
+        }
+        .header_value();
+        assert!(header.starts_with("queue;dur=5.0, lock;dur=0.0, solver;dur=3.0"));
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let durations = (1..=100).map(Duration::from_millis).collect();
+        let p = Percentiles::new(durations);
+        assert_eq!((p.p50, p.p90, p.p99, p.max), (50.0, 90.0, 99.0, 100.0));
+        assert_eq!(Percentiles::new(vec![]), Percentiles::default());
+
+        let timings = [Timings {
+            total: Duration::from_millis(7),
+            ..Timings::default()
+        }];
+        let summary = EndpointSummary::new(&timings);
+        assert_eq!(summary.requests, 1);
+        assert_eq!(summary.total.p99, 7.0);
+    }
+}
@@ -1,17 +1,23 @@
-use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex, OnceLock},
-};
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
 
-use anyhow::bail;
+use anyhow::{Context, anyhow, bail};
 use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
 };
 
 use axum_session::{Session, SessionNullPool};
-use demystify::problem::planner::PuzzlePlanner;
-use uuid::Uuid;
+use demystify::{
+    problem::{planner::PuzzlePlanner, solver::SearchBudget},
+    service::{PlannerBusy, PlannerService},
+};
+
+use crate::{
+    budget,
+    operation::{self, Busy},
+    saved::SessionFiles,
+    sessions, timing,
+};
 
 // Make our own error that wraps `anyhow::Error`.
 pub struct AppError(anyhow::Error);
@@ -19,6 +25,9 @@ pub struct AppError(anyhow::Error);
 // Tell axum how to convert `AppError` into a response.
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        if let Some(busy) = self.0.downcast_ref::<Busy>() {
+            return (StatusCode::CONFLICT, busy.html()).into_response();
+        }
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             format!("Something went wrong: {}", self.0),
@@ -38,36 +47,146 @@ where
     }
 }
 
-fn solver_global(
-    uuid: Uuid,
-    set_solver: Option<Arc<Mutex<PuzzlePlanner>>>,
-) -> Option<Arc<Mutex<PuzzlePlanner>>> {
-    type GlobalPuzzleStorage = Mutex<HashMap<Uuid, Arc<Mutex<PuzzlePlanner>>>>;
-    static SOLVER: OnceLock<GlobalPuzzleStorage> = OnceLock::new();
-    let m = SOLVER.get_or_init(|| Mutex::new(HashMap::new()));
-
-    if let Some(solver) = set_solver {
-        m.lock().unwrap().insert(uuid, solver);
-        None
-    } else {
-        m.lock().unwrap().get(&uuid).cloned()
-    }
-}
-
-/// Get global solver from uuid
-pub fn get_solver_global(
+/// Get global solver from uuid, restoring it if it was saved when it was
+/// evicted (see [`sessions`]). Restoring parses the puzzle again, so is done
+/// on a blocking thread.
+pub async fn get_solver_global(
     session: &Session<SessionNullPool>,
 ) -> anyhow::Result<Arc<Mutex<PuzzlePlanner>>> {
     let uuid = session.get_session_id().uuid();
-    let solver = solver_global(uuid, None);
-    if let Some(solver) = solver {
-        Ok(solver)
-    } else {
-        bail!("No solver -- have you uploaded files?");
+    if let Some(solver) = sessions::get(uuid) {
+        return Ok(solver);
+    }
+    let restoring = session.clone();
+    let restored = tokio::task::spawn_blocking(move || {
+        let Some(saved) = sessions::take_saved(uuid)? else {
+            return anyhow::Ok(false);
+        };
+        crate::wrap::restore_session(&restoring, &saved)?;
+        Ok(true)
+    })
+    .await
+    .context("Restoring the saved session failed")??;
+    if restored && let Some(solver) = sessions::get(uuid) {
+        return Ok(solver);
     }
+    bail!("No solver -- have you uploaded files?");
 }
 
+/// Sets the session's solver, which was loaded from the session's `files`,
+/// if it has any.
 pub fn set_solver_global(session: &Session<SessionNullPool>, set_solver: PuzzlePlanner) {
     let uuid = session.get_session_id().uuid();
-    solver_global(uuid, Some(Arc::new(Mutex::new(set_solver))));
+    sessions::set(uuid, set_solver, session.get::<SessionFiles>("files"));
+}
+
+/// Locks the session's solver, without waiting for another request which is
+/// using it. In that case this fails with [`Busy`].
+pub fn lock_solver<'a>(
+    session: &Session<SessionNullPool>,
+    solver: &'a Mutex<PuzzlePlanner>,
+) -> anyhow::Result<MutexGuard<'a, PuzzlePlanner>> {
+    match solver.try_lock() {
+        Ok(guard) => Ok(guard),
+        Err(TryLockError::WouldBlock) => Err(Busy {
+            operation: operation::current(session),
+        }
+        .into()),
+        Err(TryLockError::Poisoned(_)) => Err(anyhow!(
+            "An earlier request on this puzzle failed, please load it again"
+        )),
+    }
+}
+
+/// Runs `f` on the session's puzzle on a blocking thread, so that slow work
+/// which the request waits for does not hold up the runtime. Like
+/// [`lock_solver`], this fails with [`Busy`] rather than waiting for another
+/// request. `f` is cancelled after [`budget::solve_limit`], if it passes the
+/// [`SearchBudget`] it is given on to its searches.
+pub async fn with_solver<T, F>(session: &Session<SessionNullPool>, f: F) -> anyhow::Result<T>
+where
+    F: FnOnce(&mut PuzzlePlanner, &SearchBudget) -> anyhow::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    let solver = get_solver_global(session).await?;
+
+    let mut task = PlannerService::from_shared(solver).try_spawn(move |planner, cancel| {
+        let budget = SearchBudget {
+            cancel: Some(cancel.clone()),
+            ..SearchBudget::default()
+        };
+        let (result, timings) = timing::collect(|| f(planner, &budget));
+        Ok((result?, timings))
+    });
+    let (result, mut timings) = match task.finish_within(budget::solve_limit()).await {
+        Err(err) if err.is::<PlannerBusy>() => {
+            return Err(Busy {
+                operation: operation::current(session),
+            }
+            .into());
+        }
+        finished => finished?,
+    };
+    timings.add_waited(task.waited());
+    timing::add(&timings);
+    Ok(result)
+}
+
+/// Shortens every absolute path in `text` to its file name, so error messages
+/// sent to the browser do not show where the server keeps its files.
+#[must_use]
+pub fn strip_paths(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('/') {
+        let (before, from) = rest.split_at(start);
+        out += before;
+        let starts_path = before
+            .chars()
+            .last()
+            .is_none_or(|c| c.is_whitespace() || "\"'(=`".contains(c));
+        let end = from
+            .find(|c: char| c.is_whitespace() || "\"'()<>`,:".contains(c))
+            .unwrap_or(from.len());
+        let (word, after) = from.split_at(end);
+        if starts_path && word[1..].contains('/') {
+            out += word.rsplit('/').next().unwrap_or_default();
+        } else {
+            out += word;
+        }
+        rest = after;
+    }
+    out + rest
+}
+
+/// Escapes `text` for use in HTML.
+#[must_use]
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#x27;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::strip_paths;
+
+    #[test]
+    fn test_strip_paths() {
+        assert_eq!(
+            strip_paths("Failed to open \"/tmp/.tmpAb12/model.eprime\": not found"),
+            "Failed to open \"model.eprime\": not found"
+        );
+        assert_eq!(
+            strip_paths("/srv/demystify/x.param line 3\nsee /usr/lib/jvm"),
+            "x.param line 3\nsee jvm"
+        );
+        // Relative paths, URLs and single names are left alone
+        assert_eq!(
+            strip_paths("a/b.eprime, https://example.com/a/b and /toolLogs"),
+            "a/b.eprime, https://example.com/a/b and /toolLogs"
+        );
+    }
 }
@@ -1,17 +1,46 @@
-use anyhow::Context;
-use axum::{Json, extract::Multipart};
+use anyhow::{Context, bail};
+use axum::{Json, extract::Multipart, http::header, response::IntoResponse};
 use axum_session::{Session, SessionNullPool};
 use once_cell::sync::Lazy;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use utoipa::ToSchema;
 
-use std::{fs::File, io::Write, path::PathBuf, sync::Arc};
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock, RwLock},
+    time::Duration,
+};
 
 use anyhow::anyhow;
 
-use crate::util::{self, get_solver_global, set_solver_global};
+use crate::{
+    budget, campaign,
+    limits::{self, PuzzleTooLarge},
+    operation,
+    race::{self, Standing},
+    saved::{SavedSession, SessionFiles},
+    timing::{self, Phase},
+    util::{self, get_solver_global, set_solver_global},
+};
 
-use demystify::problem::{self, planner::PuzzlePlanner, solver::PuzzleSolver};
+use demystify::problem::{
+    self,
+    notes::{Note, NoteTarget},
+    parse::PuzzleParse,
+    planner::{BudgetedStep, LiteralStatus, PuzzlePlanner},
+    solver::{PuzzleSolver, SearchBudget},
+    util::{
+        compress::{decompress, strip_compressed_extension},
+        exec::ToolFailure,
+        fetch::{FetchLimits, fetch},
+    },
+};
+use demystify::service::PlannerService;
+use demystify::web::{planner::PlannerHtml, puzsvg::CandidateLayout};
 
 macro_rules! include_model_file {
     ($path:expr) => {
@@ -44,62 +73,384 @@ static EXAMPLES: Lazy<[(&str, &str, &str); 4]> = Lazy::new(|| {
     ]
 });
 
+/// Examples read from [`WebConfig::examples_dir`](crate::config::WebConfig::examples_dir),
+/// as (name, model, param).
+static EXTRA_EXAMPLES: OnceLock<Vec<(String, String, String)>> = OnceLock::new();
+
+/// The built-in examples, followed by any read by [`load_examples_dir`].
+fn examples() -> impl Iterator<Item = (&'static str, &'static str, &'static str)> {
+    EXAMPLES.iter().copied().chain(
+        EXTRA_EXAMPLES
+            .get()
+            .into_iter()
+            .flatten()
+            .map(|(name, model, param)| (name.as_str(), model.as_str(), param.as_str())),
+    )
+}
+
+/// Reads extra examples from `dir`, which is laid out like `examples/eprime`,
+/// and returns how many were found. Can only be called once.
+pub fn load_examples_dir(dir: &Path) -> anyhow::Result<usize> {
+    let mut found = vec![];
+
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read examples directory {dir:?}"))?
+        .filter_map(Result::ok)
+        .map(|e| e.path())
+        .collect();
+    entries.sort();
+
+    for model in entries {
+        if !model
+            .extension()
+            .is_some_and(|ext| ext == "eprime" || ext == "essence")
+        {
+            continue;
+        }
+        let Some(name) = model.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+
+        let mut params: Vec<PathBuf> = match std::fs::read_dir(dir.join(name)) {
+            Ok(params) => params
+                .filter_map(Result::ok)
+                .map(|e| e.path())
+                .filter(|p| p.extension().is_some_and(|ext| ext == "param"))
+                .collect(),
+            Err(_) => continue,
+        };
+        params.sort();
+        let Some(param) = params.first() else {
+            continue;
+        };
+
+        found.push((
+            name.to_owned(),
+            std::fs::read_to_string(&model).with_context(|| format!("Failed to read {model:?}"))?,
+            std::fs::read_to_string(param).with_context(|| format!("Failed to read {param:?}"))?,
+        ));
+    }
+
+    let count = found.len();
+    EXTRA_EXAMPLES
+        .set(found)
+        .map_err(|_| anyhow!("Extra examples have already been loaded"))?;
+    Ok(count)
+}
+
+/// A tiny sudoku shown when the page first loads, so there is something to
+/// click on straight away. It is built from a stored DIMACS file, so does not
+/// need conjure or savilerow, and is only parsed the first time it is used.
+static DEFAULT_PUZZLE: Lazy<Result<Arc<PuzzleParse>, String>> = Lazy::new(|| {
+    load_default_puzzle()
+        .map(Arc::new)
+        .map_err(|e| format!("{e:#}"))
+});
+
+fn load_default_puzzle() -> anyhow::Result<PuzzleParse> {
+    let temp_dir = tempfile::tempdir().context("Failed to create temporary directory")?;
+
+    let model = temp_dir.path().join("default.eprime");
+    let param = temp_dir.path().join("default.json");
+    let dimacs = temp_dir.path().join("default.dimacs");
+
+    std::fs::write(
+        &model,
+        include_model_file!("examples/default/sudoku-4x4.eprime"),
+    )?;
+    std::fs::write(
+        &param,
+        include_model_file!("examples/default/sudoku-4x4.json"),
+    )?;
+    std::fs::write(
+        &dimacs,
+        include_model_file!("examples/default/sudoku-4x4.dimacs"),
+    )?;
+
+    problem::parse::parse_eprime_with_dimacs(&model, &param, &dimacs)
+}
+
+/// Self-hosters can turn off the default puzzle by setting
+/// `DEMYSTIFY_DEFAULT_PUZZLE` to `0` or `false`.
+fn default_puzzle_enabled() -> bool {
+    std::env::var("DEMYSTIFY_DEFAULT_PUZZLE").map_or(true, |v| v != "0" && v != "false")
+}
+
+#[utoipa::path(
+    post,
+    path = "/defaultPuzzle",
+    summary = "Show the current puzzle, starting a small demo puzzle if none is loaded",
+    responses(
+        (status = 200, description = "HTML fragment showing the puzzle", body = String, content_type = "text/html")
+    )
+)]
+pub async fn default_puzzle(session: Session<SessionNullPool>) -> Result<String, util::AppError> {
+    if get_solver_global(&session).await.is_ok() {
+        return refresh(session).await;
+    }
+
+    if !default_puzzle_enabled() {
+        return Ok("Please upload a puzzle or select an example to begin.".to_string());
+    }
+
+    let puzzle = DEFAULT_PUZZLE
+        .as_ref()
+        .map_err(|e| anyhow!("Failed to load the default puzzle: {e}"))?
+        .clone();
+
+    let mut plan = PuzzlePlanner::new(PuzzleSolver::new(puzzle)?);
+    plan.config_mut().prefer_assignments = session_prefer_assignments(&session);
+    plan.config_mut().html_options.show_expressions = session_show_expressions(&session);
+    plan.config_mut().html_options.candidates = session_candidate_layout(&session);
+    plan.config_mut().html_options.margin_stats = session_margin_stats(&session);
+    // Any files are from an earlier puzzle, and must not be saved with this one
+    session.remove("files");
+    campaign::leave(&session);
+    set_solver_global(&session, plan);
+
+    let html = refresh(session).await?;
+
+    Ok(format!(
+        r###"
+        <div class="alert alert-info">
+            <h5>Welcome to Demystify!</h5>
+            <p>Here is a small sudoku to get started. Try clicking a cell to see how its
+            value can be deduced, or press <b>Best Next Step</b> to see the easiest
+            deduction. When you are ready, upload your own puzzle or pick an example below.</p>
+        </div>
+        {html}
+        "###
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/quickFullSolve",
+    summary = "Solve the current puzzle to completion, returning every step as JSON",
+    responses(
+        (status = 200, description = "The full list of solving steps", body = serde_json::Value)
+    )
+)]
 pub async fn dump_full_solve(
     session: Session<SessionNullPool>,
 ) -> Result<Json<Value>, util::AppError> {
-    let solver = get_solver_global(&session)?;
-
-    let mut solver = solver.lock().unwrap();
-
-    let solve = solver.quick_solve();
+    let solve = util::with_solver(&session, |solver, budget| {
+        timing::time(Phase::Solver, || solver.quick_solve_within(budget))
+    })
+    .await?;
 
     Ok(Json(serde_json::value::to_value(solve).unwrap()))
 }
 
-pub async fn best_next_step(session: Session<SessionNullPool>) -> Result<String, util::AppError> {
-    let solver = get_solver_global(&session)?;
+#[derive(Deserialize, ToSchema)]
+pub struct NextStepParams {
+    /// The token from a truncated `/bestNextStep`, to carry on that search
+    #[serde(default)]
+    resume: Option<String>,
+}
 
-    let mut solver = solver.lock().unwrap();
+#[utoipa::path(
+    post,
+    path = "/bestNextStep",
+    summary = "Find, display and apply the easiest next deduction",
+    description = "If the search takes longer than the per-request budget, nothing is applied and the response contains a button to continue the search.",
+    params(
+        ("resume" = Option<String>, Query, description = "The token from a truncated response, to carry on its search")
+    ),
+    responses(
+        (status = 200, description = "HTML fragment showing the deduction", body = String, content_type = "text/html")
+    )
+)]
+pub async fn best_next_step(
+    session: Session<SessionNullPool>,
+    axum::extract::Query(params): axum::extract::Query<NextStepParams>,
+) -> Result<String, util::AppError> {
+    let resume = match &params.resume {
+        Some(token) => {
+            let token = uuid::Uuid::parse_str(token)
+                .with_context(|| format!("'{token}' is not a continuation token"))?;
+            Some(budget::take(&session, token)?)
+        }
+        None => None,
+    };
 
-    let (solve, lits) = solver.quick_solve_html_step();
+    let op_session = session.clone();
+    operation::run(&session, "the next step", move |solver, cancel| {
+        let session = op_session;
+        let budget = SearchBudget {
+            cancel: Some(cancel.clone()),
+            ..budget::request_budget()
+        };
 
-    solver.mark_lits_as_deduced(&lits);
+        let step = match solver.next_step_budgeted(&budget, resume) {
+            BudgetedStep::Done(step) => step,
+            BudgetedStep::Truncated { problem, search } => {
+                let html = solver.draw(&problem);
+                let token = budget::store(&session, search);
+                let button = format!(
+                    r##"<button class="btn btn-warning" hx-post="/bestNextStep?resume={token}" hx-target="#mainSpace" hx-indicator="#indicator">Computation truncated &mdash; click to continue</button>"##
+                );
+                return Ok(button + &html);
+            }
+        };
 
-    if solve.is_empty() {
-        Ok("Please upload a puzzle or select an example to begin.".to_string())
-    } else {
-        Ok(solve)
-    }
+        let solve = timing::time(Phase::Render, || solver.render_and_apply_step(&step));
+
+        let mut finished = false;
+        if !step.muses.is_empty() {
+            race::record_step(&session);
+            finished = campaign::record_step(&session, solver);
+        }
+
+        if solve.is_empty() {
+            Ok("Please upload a puzzle or select an example to begin.".to_string())
+        } else if step.muses.is_empty() {
+            // Nothing more can be deduced, so say how the solve went
+            Ok(solve + &solver.solve_summary().html())
+        } else if finished {
+            Ok(campaign::finished_html() + &solve)
+        } else {
+            Ok(solve)
+        }
+    })
+    .await
 }
 
-pub async fn get_difficulties(session: Session<SessionNullPool>) -> Result<String, util::AppError> {
-    let solver = get_solver_global(&session)?;
+#[derive(Deserialize, ToSchema)]
+pub struct OperationParams {
+    /// The id of the operation, from the fragment which polls for it
+    id: String,
+}
 
-    let mut solver = solver.lock().unwrap();
+#[utoipa::path(
+    get,
+    path = "/operation",
+    summary = "Get the result of a long computation, such as `/bestNextStep`, which was still running when its request returned",
+    params(
+        ("id" = String, Query, description = "The id of the operation")
+    ),
+    responses(
+        (status = 200, description = "The result of the operation, or a fragment which polls again if it is still running", body = String, content_type = "text/html")
+    )
+)]
+pub async fn get_operation(
+    session: Session<SessionNullPool>,
+    axum::extract::Query(params): axum::extract::Query<OperationParams>,
+) -> Result<String, util::AppError> {
+    let id = uuid::Uuid::parse_str(&params.id)
+        .with_context(|| format!("'{}' is not an operation id", params.id))?;
+    operation::take(&session, id)
+}
 
-    let solve = solver.quick_generate_html_difficulties();
+#[utoipa::path(
+    post,
+    path = "/operation/cancel",
+    summary = "Stop the long computation running for this session",
+    description = "Searches stop as if they had run out of time, and can be continued. Other computations finish first.",
+    responses(
+        (status = 200, description = "A fragment which polls for the result of the operation", body = String, content_type = "text/html")
+    )
+)]
+pub async fn cancel_operation(session: Session<SessionNullPool>) -> Result<String, util::AppError> {
+    let id = operation::cancel(&session)?;
+    operation::take(&session, id)
+}
 
-    Ok(solve)
+/// How long to search for the MUSes of one literal when showing
+/// difficulties, when no time is asked for, in milliseconds.
+const DEFAULT_DIFFICULTY_LITERAL_MS: u64 = 2000;
+
+#[derive(Deserialize, ToSchema)]
+pub struct DifficultyParams {
+    #[serde(default)]
+    candidates: bool,
+    /// Show a literal's difficulty as unknown after searching this long for
+    /// it, in milliseconds, or 0 to search until it is found
+    literal_time_ms: Option<u64>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/getDifficulties",
+    summary = "Show how hard it is to deduce each remaining literal",
+    params(
+        ("candidates" = Option<bool>, Query, description = "Also show how many values each cell has left over a full solve (slower)"),
+        ("literal_time_ms" = Option<u64>, Query, description = "Show a literal's difficulty as unknown after searching this many milliseconds for it (2000 by default), or 0 for no limit")
+    ),
+    responses(
+        (status = 200, description = "HTML fragment showing the puzzle, with difficulties", body = String, content_type = "text/html")
+    )
+)]
+pub async fn get_difficulties(
+    session: Session<SessionNullPool>,
+    axum::extract::Query(params): axum::extract::Query<DifficultyParams>,
+) -> Result<String, util::AppError> {
+    operation::run(&session, "the difficulties", move |solver, cancel| {
+        let literal_time = params
+            .literal_time_ms
+            .unwrap_or(DEFAULT_DIFFICULTY_LITERAL_MS);
+        let config = solver.config_mut();
+        config.difficulty_candidate_counts = params.candidates;
+        config.difficulty_literal_time =
+            (literal_time > 0).then(|| Duration::from_millis(literal_time));
+        let budget = SearchBudget {
+            cancel: Some(cancel.clone()),
+            ..budget::request_budget()
+        };
+        Ok(solver.incremental_html_difficulties_within(&budget))
+    })
+    .await
 }
 
+#[utoipa::path(
+    post,
+    path = "/refresh",
+    summary = "Redraw the current state of the puzzle",
+    responses(
+        (status = 200, description = "HTML fragment showing the puzzle", body = String, content_type = "text/html")
+    )
+)]
 pub async fn refresh(session: Session<SessionNullPool>) -> Result<String, util::AppError> {
-    let solver = get_solver_global(&session)?;
+    let solve = util::with_solver(&session, |solver, _| {
+        Ok(timing::time(Phase::Render, || solver.render_state()))
+    })
+    .await?;
 
-    let mut solver = solver.lock().unwrap();
+    Ok(solve)
+}
 
-    let (solve, _) = solver.quick_display_html_step(None);
+#[utoipa::path(
+    post,
+    path = "/showRules",
+    summary = "Shade the cells covered by each kind of constraint, to learn the rules of the puzzle",
+    responses(
+        (status = 200, description = "HTML fragment showing the puzzle, with a list of its rules which can each be shown on it", body = String, content_type = "text/html")
+    )
+)]
+pub async fn show_rules(session: Session<SessionNullPool>) -> Result<String, util::AppError> {
+    let html = util::with_solver(&session, |solver, _| {
+        Ok(timing::time(Phase::Render, || solver.rules_html()))
+    })
+    .await?;
 
-    Ok(solve)
+    Ok(html)
 }
 
+#[utoipa::path(
+    post,
+    path = "/clickLiteral",
+    summary = "Explain how to deduce the value of a single cell",
+    params(
+        ("hx-trigger" = String, Header, description = "Id of the clicked cell, in the form `cell_<row>_<col>`")
+    ),
+    responses(
+        (status = 200, description = "HTML fragment showing the deduction", body = String, content_type = "text/html")
+    )
+)]
 pub async fn click_literal(
     headers: axum::http::header::HeaderMap,
     session: Session<SessionNullPool>,
 ) -> Result<String, util::AppError> {
-    let solver = get_solver_global(&session)?;
-
-    let mut solver = solver.lock().unwrap();
-
     let cell = headers
         .get("hx-trigger")
         .context("Missing header: 'hx-trigger'")?;
@@ -109,22 +460,402 @@ pub async fn click_literal(
 
     session.set("click_cell", &cell);
 
-    let (html, lits) = solver.quick_solve_html_step_for_literal(cell);
+    // Values which are already known need no search
+    {
+        let solver = get_solver_global(&session).await?;
+        let solver = util::lock_solver(&session, &solver)?;
+        let status = solver.literal_status(&cell);
+        if status != LiteralStatus::Unknown {
+            return Ok(literal_message(&status.to_string()));
+        }
+    }
+
+    let op_session = session.clone();
+    operation::run(&session, "this cell", move |solver, _| {
+        let Some(step) = solver.next_step_for_literal(cell) else {
+            return Ok(literal_message(
+                "This value cannot be deduced yet, as it is pinned or the puzzle does not decide it",
+            ));
+        };
+
+        let html = timing::time(Phase::Render, || solver.render_and_apply_step(&step));
+
+        let lidx_lits: Vec<_> = step.lits().iter().map(|x| x.lidx()).collect();
+        op_session.set("lidx_lits", &lidx_lits);
 
-    let lidx_lits: Vec<_> = lits.iter().map(|x| x.lidx()).collect();
-    session.set("lidx_lits", &lidx_lits);
+        race::record_step(&op_session);
+        if campaign::record_step(&op_session, solver) {
+            return Ok(campaign::finished_html() + &html);
+        }
+        Ok(html)
+    })
+    .await
+}
+
+/// An alert explaining why a clicked value was not explained, with a link
+/// back to the puzzle.
+fn literal_message(message: &str) -> String {
+    format!(
+        r##"<div class="alert alert-info">{}. <a href="#" hx-post="/refresh" hx-target="#mainSpace">Back to the puzzle</a></div>"##,
+        util::escape_html(message)
+    )
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct RegionParams {
+    /// The selected cells, each as `<row>_<col>`, separated by spaces or `;`
+    cells: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/deduceInRegion",
+    summary = "Find, display and apply the easiest deduction using only constraints which touch the selected cells",
+    request_body(content = RegionParams, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "HTML fragment showing the deduction", body = String, content_type = "text/html")
+    )
+)]
+pub async fn deduce_in_region(
+    session: Session<SessionNullPool>,
+    form: axum::extract::Form<RegionParams>,
+) -> Result<String, util::AppError> {
+    let cells = form
+        .cells
+        .split([' ', ';'])
+        .filter(|c| !c.is_empty())
+        .map(|c| {
+            c.split(['_', ','])
+                .map(|i| i.trim().parse())
+                .collect::<Result<Vec<i64>, _>>()
+                .with_context(|| format!("'{c}' is not a list of cell indices"))
+        })
+        .collect::<anyhow::Result<BTreeSet<_>>>()?;
+    if cells.is_empty() {
+        return Err(anyhow!("No cells were selected").into());
+    }
+
+    let op_session = session.clone();
+    operation::run(&session, "the selected cells", move |solver, _| {
+        let step = solver.next_step_within(&cells);
+        if step.muses.is_empty() {
+            return Ok(format!(
+                r#"<div class="alert alert-info">Nothing new can be deduced using only the constraints which touch the {} selected cells.</div>"#,
+                cells.len()
+            ));
+        }
+
+        let html = timing::time(Phase::Render, || solver.render_and_apply_step(&step));
+
+        race::record_step(&op_session);
+        if campaign::record_step(&op_session, solver) {
+            return Ok(campaign::finished_html() + &html);
+        }
+        Ok(html)
+    })
+    .await
+}
+
+#[utoipa::path(
+    post,
+    path = "/pinLiteral",
+    summary = "Pin or unpin a value of a cell, so it is not deduced until unpinned",
+    params(
+        ("hx-trigger" = String, Header, description = "Id of the value, in the form `D_<row>_<col>_<value>`")
+    ),
+    responses(
+        (status = 200, description = "HTML fragment showing the puzzle", body = String, content_type = "text/html")
+    )
+)]
+pub async fn pin_literal(
+    headers: axum::http::header::HeaderMap,
+    session: Session<SessionNullPool>,
+) -> Result<String, util::AppError> {
+    let solver = get_solver_global(&session).await?;
+
+    let mut solver = util::lock_solver(&session, &solver)?;
+
+    let lit = headers
+        .get("hx-trigger")
+        .context("Missing header: 'hx-trigger'")?;
+    let lit = lit.to_str()?;
+    let lit: Result<Vec<i64>, _> = lit.split('_').skip(1).map(str::parse).collect();
+    let lit = lit?;
+
+    solver.toggle_pin_literal(&lit);
+
+    Ok(timing::time(Phase::Render, || solver.render_state()))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct StepNoteParams {
+    /// The step to annotate, counting from 1. If blank, the step applied most recently.
+    #[serde(default)]
+    step: Option<String>,
+    /// The note, or blank to remove it
+    text: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CellNoteParams {
+    /// The indices of the cell, separated by `_` or `,`, such as `1_2`
+    cell: String,
+    /// The note, or blank to remove it
+    text: String,
+}
+
+fn note_saved(text: &str) -> String {
+    if text.trim().is_empty() {
+        "Note removed.".to_string()
+    } else {
+        "Note saved.".to_string()
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/notes/step",
+    summary = "Attach a note to a step, shown whenever that step is shown",
+    request_body(content = StepNoteParams, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "Short confirmation", body = String, content_type = "text/html")
+    )
+)]
+pub async fn note_step(
+    session: Session<SessionNullPool>,
+    form: axum::extract::Form<StepNoteParams>,
+) -> Result<String, util::AppError> {
+    let solver = get_solver_global(&session).await?;
+    let mut solver = util::lock_solver(&session, &solver)?;
+
+    let step = match form
+        .step
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+    {
+        Some(step) => step
+            .parse()
+            .with_context(|| format!("'{step}' is not a step number"))?,
+        None if solver.steps_taken() > 0 => solver.steps_taken(),
+        None => return Err(anyhow!("No step has been taken yet").into()),
+    };
+    if step == 0 {
+        return Err(anyhow!("Steps are counted from 1").into());
+    }
+
+    solver.notes_mut().set(NoteTarget::Step(step), &form.text);
+
+    Ok(note_saved(&form.text))
+}
+
+#[utoipa::path(
+    post,
+    path = "/notes/cell",
+    summary = "Attach a note to a cell, shown with the puzzle and with steps which deduce its value",
+    request_body(content = CellNoteParams, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "Short confirmation", body = String, content_type = "text/html")
+    )
+)]
+pub async fn note_cell(
+    session: Session<SessionNullPool>,
+    form: axum::extract::Form<CellNoteParams>,
+) -> Result<String, util::AppError> {
+    let solver = get_solver_global(&session).await?;
+    let mut solver = util::lock_solver(&session, &solver)?;
+
+    let cell: Vec<i64> = form
+        .cell
+        .split(['_', ','])
+        .map(|i| i.trim().parse())
+        .collect::<Result<_, _>>()
+        .with_context(|| format!("'{}' is not a list of cell indices", form.cell))?;
+
+    solver.notes_mut().set(NoteTarget::Cell(cell), &form.text);
+
+    Ok(note_saved(&form.text))
+}
+
+#[utoipa::path(
+    get,
+    path = "/notes",
+    summary = "List the notes on steps and cells",
+    responses(
+        (status = 200, description = "Each note, with the step or cell it is attached to", body = serde_json::Value)
+    )
+)]
+pub async fn get_notes(session: Session<SessionNullPool>) -> Result<Json<Value>, util::AppError> {
+    let solver = get_solver_global(&session).await?;
+    let solver = util::lock_solver(&session, &solver)?;
+
+    let notes: Vec<Note> = solver.notes().clone().into();
+    Ok(Json(serde_json::to_value(notes)?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/gridStats",
+    summary = "How far each row, column and region of the puzzle is solved",
+    responses(
+        (status = 200, description = "The cells solved, candidates left and constraints satisfied in each row, column and region", body = serde_json::Value)
+    )
+)]
+pub async fn grid_stats(session: Session<SessionNullPool>) -> Result<Json<Value>, util::AppError> {
+    let solver = get_solver_global(&session).await?;
+    let solver = util::lock_solver(&session, &solver)?;
+
+    Ok(Json(serde_json::to_value(solver.grid_stats()?)?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/exportWalkthrough",
+    summary = "Download the rest of the solve, with notes, as a single HTML page",
+    responses(
+        (status = 200, description = "A self-contained HTML page", body = String, content_type = "text/html")
+    )
+)]
+pub async fn export_walkthrough(
+    session: Session<SessionNullPool>,
+) -> Result<impl IntoResponse, util::AppError> {
+    let solver = get_solver_global(&session).await?;
+    // Solve a copy, so the session keeps its position
+    let walkthrough = util::lock_solver(&session, &solver)?.fork();
+
+    let (steps, timings) = PlannerService::new(walkthrough)
+        .run_with_timeout(budget::solve_limit(), |walkthrough, cancel| {
+            let budget = SearchBudget {
+                cancel: Some(cancel.clone()),
+                ..SearchBudget::default()
+            };
+            let (steps, timings) = timing::collect(|| {
+                timing::time(Phase::Solver, || {
+                    walkthrough.quick_solve_html_within(&budget)
+                })
+            });
+            Ok((steps?, timings))
+        })
+        .await?;
+    timing::add(&timings);
+    let html = format!(
+        "<html> <head> <style> {} </style> <script> {} </script> </head>\n<body> {steps}\n<script> doJavascript(); </script>\n</body> </html>",
+        demystify::web::base_css(),
+        demystify::web::base_javascript()
+    );
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/html"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"demystify-walkthrough.html\"",
+            ),
+        ],
+        html,
+    ))
+}
+
+/// The size of a thumbnail when none is asked for, in pixels.
+const DEFAULT_THUMBNAIL_SIZE: u32 = 150;
+
+/// The largest thumbnail drawn, in pixels. Anything bigger should use
+/// `/refresh`.
+const MAX_THUMBNAIL_SIZE: u32 = 500;
+
+#[derive(Deserialize, ToSchema)]
+pub struct ThumbnailParams {
+    /// Show the puzzle as it was after this many steps, rather than now
+    step: Option<usize>,
+    /// The width and height of the drawing, in pixels
+    size: Option<u32>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/thumbnail",
+    summary = "Draw a small preview of the puzzle, now or after an earlier step, with only its given and solved cells",
+    description = "Previews of the same state are cached, so a history timeline can ask for one per step cheaply.",
+    params(
+        ("step" = Option<usize>, Query, description = "Show the puzzle after this many steps (0 being the start), rather than now"),
+        ("size" = Option<u32>, Query, description = "The width and height of the drawing, in pixels, from 16 to 500 (150 by default)")
+    ),
+    responses(
+        (status = 200, description = "An SVG image", body = String, content_type = "image/svg+xml")
+    )
+)]
+pub async fn thumbnail(
+    session: Session<SessionNullPool>,
+    axum::extract::Query(params): axum::extract::Query<ThumbnailParams>,
+) -> Result<impl IntoResponse, util::AppError> {
+    let size = params.size.unwrap_or(DEFAULT_THUMBNAIL_SIZE);
+    if !(16..=MAX_THUMBNAIL_SIZE).contains(&size) {
+        return Err(anyhow!(
+            "Thumbnails are from 16 to {MAX_THUMBNAIL_SIZE} pixels across, not {size}"
+        )
+        .into());
+    }
+    let solver = get_solver_global(&session).await?;
+    let solver = util::lock_solver(&session, &solver)?;
+
+    let step = params.step.unwrap_or(solver.steps_taken());
+    let problem = solver.problem_after_step(step)?;
+    drop(solver);
+    let svg = timing::time(Phase::Render, || {
+        demystify::web::thumbnail_svg(&problem, size)
+    });
+
+    Ok(([(header::CONTENT_TYPE, "image/svg+xml")], svg.to_string()))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PastStepParams {
+    /// Which step to show, counting from 1
+    step: usize,
+}
+
+#[utoipa::path(
+    get,
+    path = "/pastStep",
+    summary = "Show an earlier step of this session again, as it was shown then",
+    description = "Only the latest steps are kept as they were shown, older ones are put back together from what changed, which takes a little longer.",
+    params(
+        ("step" = usize, Query, description = "The step to show, counting from 1 for the first step shown in this session")
+    ),
+    responses(
+        (status = 200, description = "HTML fragment showing the step", body = String, content_type = "text/html")
+    )
+)]
+pub async fn past_step(
+    session: Session<SessionNullPool>,
+    axum::extract::Query(params): axum::extract::Query<PastStepParams>,
+) -> Result<String, util::AppError> {
+    let index = params
+        .step
+        .checked_sub(1)
+        .context("Steps are counted from 1")?;
+    let html = util::with_solver(&session, move |solver, _| {
+        timing::time(Phase::Render, || solver.render_history_step(index))
+    })
+    .await?;
 
     Ok(html)
 }
 
+#[utoipa::path(
+    post,
+    path = "/uploadPuzzle",
+    summary = "Upload a model and parameter file, and start solving it",
+    request_body(content = UploadForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "HTML fragment showing the puzzle, or an error", body = String, content_type = "text/html")
+    )
+)]
 pub async fn upload_files(
     session: Session<SessionNullPool>,
     mut multipart: Multipart,
 ) -> Result<String, util::AppError> {
-    let temp_dir = tempfile::tempdir().context("Failed to create temporary directory")?;
-
-    let mut model: Option<PathBuf> = None;
-    let mut param: Option<PathBuf> = None;
+    let mut uploads = Uploads::new()?;
 
     while let Some(field) = multipart
         .next_field()
@@ -139,47 +870,81 @@ pub async fn upload_files(
             .into());
         }
 
-        // Grab the name
-        let form_file_name = field.file_name().context("No filename")?;
+        let form_file_name = field.file_name().context("No filename")?.to_owned();
 
         eprintln!("Got file '{form_file_name}'!");
 
+        // Unwrap the incoming bytes
+        let data = field.bytes().await.context("Failed to read file bytes")?;
+        uploads.add(&form_file_name, &data)?;
+    }
+
+    uploads.load(session).await
+}
+
+/// The model and parameters sent to `/uploadPuzzle` or fetched by
+/// `/importUrl`, saved in a temporary directory.
+struct Uploads {
+    dir: tempfile::TempDir,
+    model: Option<PathBuf>,
+    param: Option<PathBuf>,
+    // The names given by the user, for the command to run them locally
+    model_name: String,
+    param_name: String,
+}
+
+impl Uploads {
+    fn new() -> anyhow::Result<Self> {
+        Ok(Self {
+            dir: tempfile::tempdir().context("Failed to create temporary directory")?,
+            model: None,
+            param: None,
+            model_name: String::new(),
+            param_name: String::new(),
+        })
+    }
+
+    /// Saves the file called `form_file_name`, which is a model or parameters
+    /// depending on its extension.
+    fn add(&mut self, form_file_name: &str, data: &[u8]) -> anyhow::Result<()> {
+        // Grab the name, ignoring any '.gz' or '.zst'. Whether the file is
+        // really compressed is checked from its contents below.
+        let form_file_name = strip_compressed_extension(Path::new(form_file_name))
+            .to_string_lossy()
+            .into_owned();
+
         let file_name = if form_file_name.ends_with(".param") || form_file_name.ends_with(".json") {
-            if param.is_some() {
-                return Err(anyhow!("Cannot upload two param files (.param or .json)").into());
+            if self.param.is_some() {
+                bail!("Cannot upload two param files (.param or .json)");
             }
 
+            self.param_name.clone_from(&form_file_name);
             if form_file_name.ends_with(".param") {
-                param = Some("upload.param".into());
+                self.param = Some("upload.param".into());
                 "upload.param"
             } else {
-                param = Some("upload.json".into());
+                self.param = Some("upload.json".into());
                 "upload.json"
             }
-        } else if form_file_name.ends_with(".eprime") || form_file_name.ends_with(".essence") {
-            if model.is_some() {
-                return Err(anyhow!("Can only upload one .eprime or .essence file").into());
-            }
-            if form_file_name.ends_with(".eprime") {
-                model = Some("upload.eprime".into());
-                "upload.eprime"
-            } else {
-                model = Some("upload.essence".into());
-                "upload.essence"
+        } else if let Some(upload_name) = model_upload_name(&form_file_name) {
+            if self.model.is_some() {
+                bail!("Can only upload one .eprime or .essence file");
             }
+            self.model_name.clone_from(&form_file_name);
+            self.model = Some(upload_name.into());
+            upload_name
         } else {
-            return Err(anyhow!(
+            bail!(
                 "Only expecting .param, .json, .eprime or .essence uploads, not '{}'",
                 form_file_name
-            )
-            .into());
+            );
         };
 
         // Create a path for the soon-to-be file
-        let file_path = temp_dir.path().join(file_name);
+        let file_path = self.dir.path().join(file_name);
 
-        // Unwrap the incoming bytes
-        let data = field.bytes().await.context("Failed to read file bytes")?;
+        let data = decompress(data, limits::upload_limit())
+            .with_context(|| format!("Failed to decompress '{form_file_name}'"))?;
 
         // Open a handle to the file
         let mut file_handle = File::create(file_path).context("Failed to open file for writing")?;
@@ -188,71 +953,213 @@ pub async fn upload_files(
         file_handle
             .write_all(&data)
             .context("Failed to write data!")?;
+        Ok(())
     }
 
-    if model.is_none() {
-        return Ok(r###"
+    /// Parses the puzzle and starts solving it, returning the page to show.
+    async fn load(self, session: Session<SessionNullPool>) -> Result<String, util::AppError> {
+        let Uploads {
+            dir,
+            model,
+            param,
+            model_name,
+            param_name,
+        } = self;
+
+        if model.is_none() {
+            return Ok(r###"
             <div class="alert alert-danger">
                 <h4>Upload Error</h4>
                 <p>Please upload a model file (.eprime or .essence)</p>
             </div>
         "###
-        .to_string());
-    }
+            .to_string());
+        }
 
-    if param.is_none() {
-        return Ok(r###"
+        if param.is_none() {
+            return Ok(r###"
             <div class="alert alert-danger">
                 <h4>Upload Error</h4>
                 <p>Please upload a parameter file (.param or .json)</p>
             </div>
         "###
-        .to_string());
-    }
+            .to_string());
+        }
 
-    match load_model(&session, temp_dir, model, param) {
-        Ok(_) => refresh(session).await,
-        Err(e) => Ok(format!(
-            r###"
+        match load_model(&session, dir, model, param) {
+            Ok(plan) => {
+                campaign::leave(&session);
+                set_solver_global(&session, plan);
+                refresh(session).await
+            }
+            Err(e) if e.is::<PuzzleTooLarge>() => Ok(format!(
+                r###"
+            <div class="alert alert-warning">
+                <h4>Puzzle too large</h4>
+                <p>{}.</p>
+                <p>You can explain it on your own computer with the command line version of demystify instead:</p>
+                <pre>{}</pre>
+            </div>
+            "###,
+                util::escape_html(&e.to_string()),
+                util::escape_html(&limits::local_command(&model_name, &param_name))
+            )),
+            Err(e) => {
+                let details = error_details(&session, &e);
+                Ok(format!(
+                    r###"
             <div class="alert alert-danger">
                 <h4>Failed to upload puzzle</h4>
-                <pre class="text-danger">{e:#}</pre>
+                {details}
                 <p>Please check your files and try again.</p>
             </div>
             "###
-        )),
+                ))
+            }
+        }
+    }
+}
+
+/// What `/importUrl` may fetch, or `None` if it is turned off, from
+/// [`WebConfig::url_import_hosts`](crate::config::WebConfig::url_import_hosts).
+static URL_IMPORT: RwLock<Option<FetchLimits>> = RwLock::new(None);
+
+/// Sets what `/importUrl` may fetch, as described by [`URL_IMPORT`].
+pub fn set_url_import(limits: Option<FetchLimits>) {
+    *URL_IMPORT.write().unwrap() = limits;
+}
+
+/// The form sent to `/importUrl`.
+#[derive(Deserialize, ToSchema)]
+pub struct ImportUrlForm {
+    /// An https URL of an `.eprime` or `.essence` model, optionally ending in
+    /// `#sha256=<hex>` to check its checksum
+    pub model_url: String,
+    /// An https URL of a `.param` or `.json` parameter file, which can be
+    /// pinned in the same way
+    pub param_url: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/importUrl",
+    summary = "Download a model and parameter file, and start solving it",
+    request_body(content = ImportUrlForm, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "HTML fragment showing the puzzle, or an error", body = String, content_type = "text/html")
+    )
+)]
+pub async fn import_url(
+    session: Session<SessionNullPool>,
+    form: axum::extract::Form<ImportUrlForm>,
+) -> Result<String, util::AppError> {
+    let limits = URL_IMPORT
+        .read()
+        .unwrap()
+        .clone()
+        .context("Importing puzzles from URLs is turned off on this server")?;
+
+    let form = form.0;
+    let fetched = tokio::task::spawn_blocking(move || {
+        anyhow::Ok([
+            fetch(&form.model_url, &limits)?,
+            fetch(&form.param_url, &limits)?,
+        ])
+    })
+    .await
+    .context("Downloading failed")?;
+    let fetched = match fetched {
+        Ok(fetched) => fetched,
+        Err(e) => {
+            return Ok(format!(
+                r###"
+            <div class="alert alert-danger">
+                <h4>Failed to download puzzle</h4>
+                <p>{}</p>
+            </div>
+            "###,
+                util::escape_html(&format!("{e:#}"))
+            ));
+        }
+    };
+
+    // The downloads are checked like any other upload
+    let mut uploads = Uploads::new()?;
+    for file in &fetched {
+        uploads.add(&file.file_name, &file.contents)?;
+    }
+    uploads.load(session).await
+}
+
+/// The name an uploaded model called `form_file_name` is saved as, or `None`
+/// if it is not an `.eprime` or `.essence` file.
+fn model_upload_name(form_file_name: &str) -> Option<&'static str> {
+    if form_file_name.ends_with(".eprime") {
+        Some("upload.eprime")
+    } else if form_file_name.ends_with(".essence") {
+        Some("upload.essence")
+    } else {
+        None
     }
 }
 
-#[derive(Deserialize)]
+/// Files sent to `/uploadPuzzle`. Only used to document the API, as the
+/// upload is read field by field.
+#[derive(ToSchema)]
+pub struct UploadForm {
+    /// An `.eprime` or `.essence` model
+    #[schema(value_type = String, format = Binary)]
+    pub model: Vec<u8>,
+    /// A `.param` or `.json` parameter file
+    #[schema(value_type = String, format = Binary)]
+    pub parameter: Vec<u8>,
+}
+
+#[derive(Deserialize, ToSchema)]
 pub struct ExampleParams {
     example_name: String,
 }
 
-#[derive(Deserialize)]
-pub struct SubmitExampleParams {
-    param_content: String,
-    example_name: String,
+/// The form sent to `/submitExample`. Only used to document the API, as the
+/// form is read field by field.
+#[derive(ToSchema)]
+pub struct SubmitExampleForm {
+    pub example_name: String,
+    pub param_content: String,
+    /// An `.eprime` or `.essence` model to use instead of the example's own.
+    /// An empty file is ignored, as browsers send one when none is chosen.
+    #[schema(value_type = Option<String>, format = Binary)]
+    pub model: Option<Vec<u8>>,
 }
 
+#[utoipa::path(
+    post,
+    path = "/loadExample",
+    summary = "Show the parameters of a built-in example, ready for editing",
+    request_body(content = ExampleParams, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "HTML form containing the example parameters", body = String, content_type = "text/html")
+    )
+)]
 pub async fn load_example(
     _session: Session<SessionNullPool>,
     form: axum::extract::Form<ExampleParams>,
 ) -> Result<String, util::AppError> {
     let example_name = form.example_name.clone();
 
-    let param_content = EXAMPLES
-        .iter()
+    let param_content = examples()
         .find(|(name, _, _)| *name == example_name)
-        .map(|(_, _, content)| *content)
+        .map(|(_, _, content)| content)
         .context(format!("Example '{example_name}' not found"))?;
 
     Ok(format!(
         r###"
         <h5>Edit Parameters for {example_name}</h5>
-        <form id="paramForm" hx-post="/submitExample" hx-target="#mainSpace">
+        <form id="paramForm" hx-post="/submitExample" hx-encoding="multipart/form-data" hx-target="#mainSpace">
             <input type="hidden" name="example_name" value="{example_name}">
             <textarea name="param_content" class="form-control" rows="15" style="font-family: monospace;">{param_content}</textarea>
+            <label for="exampleModel" class="form-label mt-2">Replacement model (optional, .eprime/.essence, optionally .gz/.zst)</label>
+            <input type="file" class="form-control" id="exampleModel" name="model" accept=".eprime,.essence,.gz,.zst">
             <button type="submit" class="btn btn-primary mt-2" hx-indicator="#indicator">
                 Submit Parameters
             </button>
@@ -261,9 +1168,16 @@ pub async fn load_example(
     ))
 }
 
+#[utoipa::path(
+    get,
+    path = "/getExampleNames",
+    summary = "List the built-in examples",
+    responses(
+        (status = 200, description = "HTML form for choosing an example", body = String, content_type = "text/html")
+    )
+)]
 pub async fn get_example_names() -> String {
-    let options = EXAMPLES
-        .iter()
+    let options = examples()
         .map(|(name, _, _)| format!("<option value=\"{name}\">{name}</option>"))
         .collect::<Vec<_>>()
         .join("");
@@ -282,22 +1196,74 @@ pub async fn get_example_names() -> String {
     "###
 }
 
+#[utoipa::path(
+    post,
+    path = "/submitExample",
+    summary = "Start solving a built-in example, with (possibly edited) parameters and optionally a replacement model",
+    request_body(content = SubmitExampleForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "HTML fragment showing the puzzle, or an error", body = String, content_type = "text/html")
+    )
+)]
 pub async fn submit_example(
     session: Session<SessionNullPool>,
-    form: axum::extract::Form<SubmitExampleParams>,
+    mut multipart: Multipart,
 ) -> Result<String, util::AppError> {
-    let example_name = form.example_name.clone();
-    let param_content = form.param_content.clone();
+    let mut example_name = None;
+    let mut param_content = None;
+    // The replacement model, with the name it is saved as
+    let mut replacement: Option<(&str, Vec<u8>)> = None;
 
-    let model_content = EXAMPLES
-        .iter()
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .context("Failed to parse multipart upload")?
+    {
+        match field.name().unwrap_or_default() {
+            "example_name" => example_name = Some(field.text().await.context("Bad example name")?),
+            "param_content" => {
+                param_content = Some(field.text().await.context("Bad parameters")?);
+            }
+            "model" => {
+                let form_file_name = field.file_name().unwrap_or_default().to_owned();
+                let data = field.bytes().await.context("Failed to read file bytes")?;
+                if data.is_empty() {
+                    continue;
+                }
+                let form_file_name = strip_compressed_extension(Path::new(&form_file_name))
+                    .to_string_lossy()
+                    .into_owned();
+                let upload_name = model_upload_name(&form_file_name).with_context(|| {
+                    format!("Only expecting a .eprime or .essence model, not '{form_file_name}'")
+                })?;
+                let data = decompress(&data, limits::upload_limit())
+                    .with_context(|| format!("Failed to decompress '{form_file_name}'"))?;
+                replacement = Some((upload_name, data));
+            }
+            other => {
+                return Err(anyhow!(
+                    "Form malformed -- should contain 'example_name', 'param_content' and optionally 'model', but it contains '{other}'"
+                )
+                .into());
+            }
+        }
+    }
+
+    let example_name = example_name.context("No example chosen")?;
+    let param_content = param_content.context("No parameters given")?;
+
+    let model_content = examples()
         .find(|(name, _, _)| *name == example_name)
-        .map(|(_, content, _)| *content)
+        .map(|(_, content, _)| content)
         .context(format!("Example '{example_name}' not found"))?;
+    let (model_name, model_content) = match replacement {
+        Some((name, data)) => (name, data),
+        None => ("upload.eprime", model_content.as_bytes().to_vec()),
+    };
 
     let temp_dir = tempfile::tempdir().context("Failed to create temporary directory")?;
 
-    let model_dest = temp_dir.path().join("upload.eprime");
+    let model_dest = temp_dir.path().join(model_name);
     std::fs::write(&model_dest, model_content).context("Failed to write model file")?;
 
     let param_dest = temp_dir.path().join("upload.param");
@@ -306,20 +1272,119 @@ pub async fn submit_example(
     match load_model(
         &session,
         temp_dir,
-        Some("upload.eprime".into()),
+        Some(model_name.into()),
         Some("upload.param".into()),
     ) {
-        Ok(_) => refresh(session).await,
-        Err(e) => Ok(format!(
-            r###"
+        Ok(plan) => {
+            campaign::leave(&session);
+            set_solver_global(&session, plan);
+            refresh(session).await
+        }
+        Err(e) => {
+            let details = error_details(&session, &e);
+            Ok(format!(
+                r###"
             <div class="alert alert-danger">
                 <h4>Failed to load puzzle</h4>
-                <pre class="text-danger">{e:#}</pre>
-                <p>Please check your parameter file and try again.</p>
+                {details}
+                <p>Please check your parameters (and model, if you uploaded one) and try again.</p>
             </div>
             "###
-        )),
+            ))
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct SettingsParams {
+    /// One of `default`, `assignments` or `eliminations`
+    prefer: String,
+    /// Present (with any value) to show the Essence' expression of each constraint
+    #[serde(default)]
+    expressions: Option<String>,
+    /// Which candidates to draw in each cell: `all` (the default), `highlighted`
+    /// (only those used by the step), or `compact` (at most 3 across, showing
+    /// only those used by the step in cells with more)
+    #[serde(default)]
+    candidates: Option<String>,
+    /// Present (with any value) to write how far each row and column is
+    /// solved in the margins of the puzzle
+    #[serde(default)]
+    margin_stats: Option<String>,
+}
+
+/// The order preference stored in the session, applied to every puzzle loaded.
+fn session_prefer_assignments(session: &Session<SessionNullPool>) -> Option<bool> {
+    session.get("prefer_assignments").unwrap_or(None)
+}
+
+/// Whether to show constraint expressions, stored in the session.
+fn session_show_expressions(session: &Session<SessionNullPool>) -> bool {
+    session.get("show_expressions").unwrap_or(false)
+}
+
+/// How candidates are drawn, stored in the session.
+fn session_candidate_layout(session: &Session<SessionNullPool>) -> CandidateLayout {
+    session.get("candidate_layout").unwrap_or_default()
+}
+
+/// Whether to draw row and column stats in the margins, stored in the session.
+fn session_margin_stats(session: &Session<SessionNullPool>) -> bool {
+    session.get("margin_stats").unwrap_or(false)
+}
+
+#[utoipa::path(
+    post,
+    path = "/setSettings",
+    summary = "Choose which deductions are explained first, whether constraint expressions are shown, which candidates are drawn, and whether row and column stats are drawn in the margins",
+    request_body(content = SettingsParams, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "Short confirmation of the new setting", body = String, content_type = "text/html")
+    )
+)]
+pub async fn set_settings(
+    session: Session<SessionNullPool>,
+    form: axum::extract::Form<SettingsParams>,
+) -> Result<String, util::AppError> {
+    let prefer = match form.prefer.as_str() {
+        "default" => None,
+        "assignments" => Some(true),
+        "eliminations" => Some(false),
+        other => return Err(anyhow!("Unknown ordering '{other}'").into()),
+    };
+
+    let show_expressions = form.expressions.is_some();
+    let margin_stats = form.margin_stats.is_some();
+
+    let candidates = match form.candidates.as_deref() {
+        None | Some("all") => CandidateLayout::default(),
+        Some("highlighted") => CandidateLayout {
+            only_highlighted: true,
+            ..CandidateLayout::default()
+        },
+        Some("compact") => CandidateLayout {
+            max_grid: Some(3),
+            ..CandidateLayout::default()
+        },
+        Some(other) => return Err(anyhow!("Unknown candidate layout '{other}'").into()),
+    };
+
+    session.set("prefer_assignments", prefer);
+    session.set("show_expressions", show_expressions);
+    session.set("candidate_layout", candidates);
+    session.set("margin_stats", margin_stats);
+
+    // There may not be a puzzle loaded yet, in which case the setting is
+    // applied when one is.
+    if let Ok(solver) = get_solver_global(&session).await {
+        let mut solver = util::lock_solver(&session, &solver)?;
+        solver.config_mut().prefer_assignments = prefer;
+        solver.config_mut().html_options.show_expressions = show_expressions;
+        solver.config_mut().html_options.candidates = candidates;
+        solver.config_mut().html_options.margin_stats = margin_stats;
     }
+
+    Ok("Settings saved.".to_string())
 }
 
 fn load_model(
@@ -327,14 +1392,345 @@ fn load_model(
     temp_dir: tempfile::TempDir,
     model: Option<PathBuf>,
     param: Option<PathBuf>,
-) -> anyhow::Result<()> {
-    let puzzle = problem::parse::parse_essence(
-        &temp_dir.path().join(model.unwrap()),
-        &temp_dir.path().join(param.unwrap()),
-    )?;
+) -> anyhow::Result<PuzzlePlanner> {
+    let model = model.unwrap();
+    let param = param.unwrap();
+    let model_path = temp_dir.path().join(&model);
+    let param_path = temp_dir.path().join(&param);
+
+    let files = SessionFiles {
+        model_name: model.to_string_lossy().into_owned(),
+        model: std::fs::read_to_string(&model_path).context("Failed to read model file")?,
+        param_name: param.to_string_lossy().into_owned(),
+        param: std::fs::read_to_string(&param_path).context("Failed to read param file")?,
+    };
+
+    let puzzle = problem::parse::parse_essence(&model_path, &param_path)?;
+    limits::check(&puzzle)?;
     let puzzle = Arc::new(puzzle);
     let puz = PuzzleSolver::new(puzzle)?;
-    let plan = PuzzlePlanner::new(puz);
+    let mut plan = PuzzlePlanner::new(puz);
+    plan.config_mut().prefer_assignments = session_prefer_assignments(session);
+    plan.config_mut().html_options.show_expressions = session_show_expressions(session);
+    plan.config_mut().html_options.candidates = session_candidate_layout(session);
+    plan.config_mut().html_options.margin_stats = session_margin_stats(session);
+    session.set("files", &files);
+    Ok(plan)
+}
+
+#[utoipa::path(
+    get,
+    path = "/exportSession",
+    summary = "Download the current puzzle and solving position as a single file",
+    responses(
+        (status = 200, description = "Compressed JSON describing the session", body = Vec<u8>, content_type = "application/octet-stream")
+    )
+)]
+pub async fn export_session(
+    session: Session<SessionNullPool>,
+) -> Result<impl IntoResponse, util::AppError> {
+    let files: SessionFiles = session
+        .get("files")
+        .context("No puzzle loaded -- have you uploaded files?")?;
+
+    let solver = get_solver_global(&session).await?;
+    let solver = util::lock_solver(&session, &solver)?;
+
+    let campaign = campaign::state(&session);
+    let saved = SavedSession {
+        campaign: (campaign != campaign::CampaignState::default()).then_some(campaign),
+        ..SavedSession::new(files, &solver)
+    };
+    let bytes = saved.to_bytes()?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/octet-stream"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"demystify-session.dmy\"",
+            ),
+        ],
+        bytes,
+    ))
+}
+
+/// A saved session sent to `/importSession`. Only used to document the API.
+#[derive(ToSchema)]
+pub struct ImportForm {
+    /// A file produced by `/exportSession`
+    #[schema(value_type = String, format = Binary)]
+    pub session: Vec<u8>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/importSession",
+    summary = "Restore a puzzle and solving position from an exported file",
+    request_body(content = ImportForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "HTML fragment showing the puzzle, or an error", body = String, content_type = "text/html")
+    )
+)]
+pub async fn import_session(
+    session: Session<SessionNullPool>,
+    mut multipart: Multipart,
+) -> Result<String, util::AppError> {
+    let field = multipart
+        .next_field()
+        .await
+        .context("Failed to parse multipart upload")?
+        .context("No saved session uploaded")?;
+    let data = field.bytes().await.context("Failed to read file bytes")?;
+
+    // Restored by the session, if it was saved during a campaign
+    campaign::leave(&session);
+    match restore_session(&session, &data) {
+        Ok(()) => refresh(session).await,
+        Err(e) => {
+            let details = error_details(&session, &e);
+            Ok(format!(
+                r###"
+            <div class="alert alert-danger">
+                <h4>Failed to import session</h4>
+                {details}
+            </div>
+            "###
+            ))
+        }
+    }
+}
+
+/// Formats an error for an error box. When an external tool failed, only the
+/// end of its output is shown, and the full failure is kept in the session so
+/// it can be fetched from `/toolLogs`.
+fn error_details(session: &Session<SessionNullPool>, e: &anyhow::Error) -> String {
+    let Some(failure) = e.downcast_ref::<ToolFailure>() else {
+        return format!(
+            r#"<pre class="text-danger">{}</pre>"#,
+            util::strip_paths(&format!("{e:#}"))
+        );
+    };
+
+    // The failure is shown to the user, so should not say where the
+    // server keeps its files
+    let mut failure = failure.clone();
+    failure.forget_artifacts();
+    for text in failure
+        .args
+        .iter_mut()
+        .chain([&mut failure.stdout, &mut failure.stderr])
+    {
+        *text = util::strip_paths(text);
+    }
+    session.set("tool_failure", &failure);
+
+    let context = e
+        .chain()
+        .filter(|c| !c.is::<ToolFailure>())
+        .map(|c| util::strip_paths(&c.to_string()))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!(
+        r#"<pre class="text-danger">{context}
+{}</pre>
+        <p><a href="/toolLogs" target="_blank">View the full output</a></p>"#,
+        failure.trimmed(20)
+    )
+}
+
+#[utoipa::path(
+    get,
+    path = "/toolLogs",
+    summary = "Get the full output of the last external tool which failed in this session",
+    responses(
+        (status = 200, description = "The tool, its arguments, exit code, output and running time", body = serde_json::Value)
+    )
+)]
+pub async fn tool_logs(session: Session<SessionNullPool>) -> Result<Json<Value>, util::AppError> {
+    let failure: ToolFailure = session
+        .get("tool_failure")
+        .context("No external tool has failed in this session")?;
+
+    Ok(Json(serde_json::to_value(failure)?))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateRaceParams {
+    /// The name to show in the standings
+    name: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct JoinRaceParams {
+    /// The id returned by `/race/create`
+    room: String,
+    /// The name to show in the standings
+    name: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct StandingsParams {
+    room: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RaceRoom {
+    /// Share this with other players, so they can join
+    room: String,
+}
+
+fn parse_room(room: &str) -> anyhow::Result<uuid::Uuid> {
+    uuid::Uuid::parse_str(room).with_context(|| format!("'{room}' is not a race room id"))
+}
+
+#[utoipa::path(
+    post,
+    path = "/race/create",
+    summary = "Start a race from the current puzzle and position, and join it",
+    request_body = CreateRaceParams,
+    responses(
+        (status = 200, description = "The id of the new room", body = RaceRoom)
+    )
+)]
+pub async fn create_race(
+    session: Session<SessionNullPool>,
+    Json(params): Json<CreateRaceParams>,
+) -> Result<Json<RaceRoom>, util::AppError> {
+    let solver = get_solver_global(&session).await?;
+    let room = race::create_room(&session, &*util::lock_solver(&session, &solver)?)?;
+
+    // The creator restarts from the room's snapshot too, so everyone is timed
+    // from the same position.
+    let plan = race::join_room(&session, room, params.name)?;
+    set_solver_global(&session, plan);
+
+    Ok(Json(RaceRoom {
+        room: room.to_string(),
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/race/join",
+    summary = "Join a race, replacing the current puzzle with the race's starting position",
+    request_body = JoinRaceParams,
+    responses(
+        (status = 200, description = "The id of the joined room", body = RaceRoom)
+    )
+)]
+pub async fn join_race(
+    session: Session<SessionNullPool>,
+    Json(params): Json<JoinRaceParams>,
+) -> Result<Json<RaceRoom>, util::AppError> {
+    let room = parse_room(&params.room)?;
+    let plan = race::join_room(&session, room, params.name)?;
+    campaign::leave(&session);
+    set_solver_global(&session, plan);
+
+    Ok(Json(RaceRoom {
+        room: room.to_string(),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/race/standings",
+    summary = "Compare the progress of everyone in a race",
+    params(
+        ("room" = String, Query, description = "The id returned by `/race/create`")
+    ),
+    responses(
+        (status = 200, description = "Players, most steps first, with ties broken by time taken", body = Vec<Standing>)
+    )
+)]
+pub async fn race_standings(
+    axum::extract::Query(params): axum::extract::Query<StandingsParams>,
+) -> Result<Json<Vec<Standing>>, util::AppError> {
+    let room = parse_room(&params.room)?;
+    Ok(Json(race::standings(room)?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/campaign",
+    summary = "List the levels of the server's campaign, showing which this session has unlocked and finished",
+    responses(
+        (status = 200, description = "HTML list of levels, where unlocked levels can be started", body = String, content_type = "text/html")
+    )
+)]
+pub async fn campaign_levels(session: Session<SessionNullPool>) -> String {
+    campaign::levels_html(&session)
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CampaignLevelParams {
+    /// The index of the level, counting from 0
+    level: usize,
+}
+
+#[utoipa::path(
+    post,
+    path = "/campaign/start",
+    summary = "Start a level of the campaign, if this session has unlocked it",
+    request_body(content = CampaignLevelParams, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "HTML fragment showing the puzzle", body = String, content_type = "text/html")
+    )
+)]
+pub async fn start_campaign_level(
+    session: Session<SessionNullPool>,
+    form: axum::extract::Form<CampaignLevelParams>,
+) -> Result<String, util::AppError> {
+    let (puzzle, files) = campaign::start_level(&session, form.level)?;
+
+    let mut plan = PuzzlePlanner::new(PuzzleSolver::new(Arc::new(puzzle))?);
+    plan.config_mut().prefer_assignments = session_prefer_assignments(&session);
+    plan.config_mut().html_options.show_expressions = session_show_expressions(&session);
+    plan.config_mut().html_options.candidates = session_candidate_layout(&session);
+    plan.config_mut().html_options.margin_stats = session_margin_stats(&session);
+    match files {
+        Some(files) => session.set("files", &files),
+        None => session.remove("files"),
+    }
+    set_solver_global(&session, plan);
+
+    refresh(session).await
+}
+
+/// Loads a session saved with [`SavedSession::to_bytes`] into `session`.
+pub(crate) fn restore_session(
+    session: &Session<SessionNullPool>,
+    data: &[u8],
+) -> anyhow::Result<()> {
+    let saved = SavedSession::from_bytes(data, limits::upload_limit())?;
+
+    let temp_dir = tempfile::tempdir().context("Failed to create temporary directory")?;
+    // Only keep the extension, so the saved names can't escape the directory
+    let model_name = PathBuf::from(&saved.files.model_name);
+    let model_name = PathBuf::from("upload").with_extension(
+        model_name
+            .extension()
+            .context("Saved model has no extension")?,
+    );
+    let param_name = PathBuf::from(&saved.files.param_name);
+    let param_name = PathBuf::from("upload").with_extension(
+        param_name
+            .extension()
+            .context("Saved parameters have no extension")?,
+    );
+
+    std::fs::write(temp_dir.path().join(&model_name), &saved.files.model)
+        .context("Failed to write model file")?;
+    std::fs::write(temp_dir.path().join(&param_name), &saved.files.param)
+        .context("Failed to write param file")?;
+
+    let mut plan = load_model(session, temp_dir, Some(model_name), Some(param_name))?;
+    saved.restore_known(&mut plan)?;
     set_solver_global(session, plan);
+    if let Some(state) = &saved.campaign {
+        campaign::set_state(session, state);
+    }
     Ok(())
 }
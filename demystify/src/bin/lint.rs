@@ -0,0 +1,120 @@
+use clap::Parser;
+use demystify::problem::{
+    self,
+    analysis::{ConstraintUsage, NontrivialUsage, RevealProblem, localise_reveal_failure},
+    planner::{PlannerConfig, PuzzlePlanner},
+    solver::{MusConfig, PuzzleSolver},
+    util::exec::{RunMethod, set_run_method},
+};
+use itertools::Itertools;
+use std::{path::PathBuf, sync::Arc};
+
+#[derive(clap::Parser, Debug)]
+struct Opt {
+    #[arg(long)]
+    model: String,
+
+    #[arg(long, num_args = 1.., required = true, help = "One or more param files to solve")]
+    param: Vec<String>,
+
+    #[arg(long)]
+    searches: Option<i64>,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Specify the method to run the solver (Native, Docker, Podman)"
+    )]
+    conjure: Option<RunMethod>,
+}
+
+fn main() -> anyhow::Result<()> {
+    let opt = Opt::parse();
+
+    // Choose how we run conjure, native, docker or podman
+    if let Some(method) = opt.conjure {
+        set_run_method(method);
+    }
+
+    let mus_config: MusConfig = if let Some(searches) = opt.searches {
+        MusConfig::new_with_repeats(searches)
+    } else {
+        MusConfig::default()
+    };
+
+    let mut usage = ConstraintUsage::new();
+
+    for param in &opt.param {
+        eprintln!("Solving {param}");
+        let puzzle =
+            problem::parse::parse_essence(&PathBuf::from(&opt.model), &PathBuf::from(param))?;
+
+        let puzzle = Arc::new(puzzle);
+
+        let solver = PuzzleSolver::new(puzzle.clone())?;
+
+        let planner_config = PlannerConfig {
+            mus_config,
+            ..PlannerConfig::default()
+        };
+
+        let mut planner = PuzzlePlanner::new_with_config(solver, planner_config);
+
+        // Multi-step puzzles can go wrong part way through, so check the
+        // reveals before trying to explain them
+        if puzzle.has_facts()
+            && let Some(failure) = localise_reveal_failure(&mut planner.fork())
+        {
+            println!(
+                "{param}: problem after {} rounds of deductions, which revealed {}",
+                failure.round,
+                failure.reveals.iter().map(ToString::to_string).join(", ")
+            );
+            match failure.problem {
+                RevealProblem::Unsatisfiable { constraints, known } => {
+                    println!("  No solution, because of:");
+                    for c in constraints {
+                        println!("    {c}");
+                    }
+                    for k in known {
+                        println!("    {k}");
+                    }
+                }
+                RevealProblem::NotUnique { undecided } => {
+                    println!(
+                        "  No unique solution, undecided: {}",
+                        undecided.iter().map(ToString::to_string).join(", ")
+                    );
+                }
+            }
+            continue;
+        }
+
+        let steps = planner.quick_solve_muses();
+
+        let mut nontrivial = NontrivialUsage::new();
+        for step in &steps {
+            nontrivial.add_step(&puzzle, step);
+        }
+        if let Some(dominant) = nontrivial.dominant() {
+            println!("{param}: warning, {dominant}");
+        }
+
+        usage.add_instance(&puzzle, &steps);
+    }
+
+    println!("Constraint usage over {} instances:", usage.instances());
+    for (name, u) in usage.usage() {
+        println!(
+            "  {name}: used in {}/{} instances, {} MUSes",
+            u.instances,
+            usage.instances(),
+            u.muses
+        );
+    }
+
+    println!("Never used: {:?}", usage.never_used());
+    println!("Always used: {:?}", usage.always_used());
+
+    Ok(())
+}
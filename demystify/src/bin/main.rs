@@ -1,24 +1,105 @@
+use anyhow::{Context, bail};
 use clap::Parser;
 use demystify::{
+    json::Problem,
     problem::{
         self,
-        planner::{PlannerConfig, PuzzlePlanner},
-        solver::{MusConfig, PuzzleSolver, SolverConfig},
-        util::exec::{RunMethod, set_run_method},
+        analysis::ClueScope,
+        anonymize::Anonymized,
+        assertions::AssertSpec,
+        campaign::Campaign,
+        history::HistoryConfig,
+        parse::read_params,
+        planner::{CellWeight, ConstraintWeighting, PlannerConfig, PuzzlePlanner, StopCondition},
+        repro::ReproReport,
+        selftest,
+        solver::{
+            AdaptiveRepeats, MusConfig, PuzzleSolver, SolverConfig, Strictness, get_repeat_stats,
+            get_search_error_stats, probe::ProbeOrder,
+        },
+        telemetry::{RunStats, StatsStore, StatsSummary},
+        trace::SolveTrace,
+        util::{
+            exec::{
+                ContainerConfig, Executor, RunMethod, SystemExecutor, set_container_config,
+                set_keep_failed_runs, set_run_method,
+            },
+            fetch::{FetchLimits, fetch, sha256_hex},
+            logging::LogConfig,
+            memory::{MemoryUsage, format_kb},
+            param::{MergedParams, write_essence_param},
+        },
+    },
+    satcore::{
+        ShrinkOrder, get_shrink_stats, get_solver_calls, set_must_succeed_conflict_ceiling,
+        set_query_log_size, take_query_log,
     },
-    web::{base_css, base_javascript},
+    web::{
+        HtmlOptions, base_css, base_javascript,
+        markdown::{MarkdownGrid, trace_to_markdown},
+        pdf::trace_to_pdf,
+        planner::PlannerHtml,
+    },
+};
+use std::{
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
 };
-use std::{fs::File, path::PathBuf, sync::Arc};
-use tracing::Level;
-use tracing_subscriber::fmt::format::FmtSpan;
 
 #[derive(clap::Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Opt {
-    #[arg(long)]
-    model: String,
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    #[arg(long)]
-    param: String,
+    #[arg(
+        long,
+        required_unless_present = "model_url",
+        conflicts_with = "model_url"
+    )]
+    model: Option<String>,
+
+    #[arg(
+        long,
+        required_unless_present = "param_url",
+        conflicts_with = "param_url",
+        help = "The parameters. Give this more than once to merge several files, where later files override earlier ones"
+    )]
+    param: Vec<String>,
+
+    #[arg(
+        long = "let",
+        value_name = "NAME=VALUE",
+        help = "Set a parameter, overriding those from --param, such as --let n=6 or --let 'fixed=[[1, 0], [0, 1]]'"
+    )]
+    lets: Vec<String>,
+
+    #[arg(
+        long,
+        help = "Download the model from this https URL instead of --model. End the URL with #sha256=<hex> to check the file has that checksum"
+    )]
+    model_url: Option<String>,
+
+    #[arg(
+        long,
+        help = "Download the parameters from this https URL instead of --param. End the URL with #sha256=<hex> to check the file has that checksum"
+    )]
+    param_url: Option<String>,
+
+    #[arg(
+        long,
+        help = "Read the SAT encoding from this DIMACS file instead of running conjure and savilerow. --param must then be JSON"
+    )]
+    dimacs: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Write an anonymised copy of the puzzle (puzzle.eprime, puzzle.json and puzzle.dimacs) to this directory, to attach to bug reports, instead of solving. Read it back with --dimacs"
+    )]
+    anonymize: Option<PathBuf>,
 
     #[arg(
         long,
@@ -37,47 +118,602 @@ struct Opt {
     #[arg(long)]
     trace: bool,
 
+    #[arg(
+        long,
+        env = "DEMYSTIFY_TRACE_FILE",
+        help = "Write the trace to this file instead of demystify.trace (implies --trace)"
+    )]
+    trace_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        env = "DEMYSTIFY_TRACE_MAX_SIZE",
+        help = "Start a new trace file after this many bytes, keeping the last 3 as <file>.1, <file>.2 and <file>.3"
+    )]
+    trace_max_size: Option<u64>,
+
     #[arg(long)]
     html: bool,
 
+    #[arg(long, help = "Print the solve as plain text, one step at a time")]
+    text: bool,
+
+    #[arg(
+        long,
+        help = "Print an HTML page showing the cells covered by each $#CON in its own colour, to learn the rules of the puzzle, instead of solving it"
+    )]
+    show_rules: bool,
+
+    #[arg(
+        long,
+        help = "Write the grid and statements of every step to this file as JSON, for export-pdf"
+    )]
+    solve_trace: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Write the grid and statements of every step to this file as GitHub-flavoured Markdown, for pasting into issues and wikis"
+    )]
+    markdown: Option<PathBuf>,
+
+    #[arg(
+        long,
+        requires = "markdown",
+        help = "With --markdown, draw each grid as an embedded SVG image rather than a table"
+    )]
+    markdown_svg: bool,
+
     #[arg(long)]
     only_assign: bool,
 
+    #[arg(
+        long,
+        help = "Among equally small MUSes, explain assignments first (true) or eliminations first (false)"
+    )]
+    prefer_assignments: Option<bool>,
+
+    #[arg(
+        long,
+        help = "Shrink the MUS shown at each step, spending at most this many milliseconds on each"
+    )]
+    minimize_mus: Option<u64>,
+
+    #[arg(
+        long,
+        help = "Report which starting clues are in the scope of the constraints used in the solve, instead of printing it. Clues outside every constraint are redundant, but those inside may be too."
+    )]
+    clue_scope: bool,
+
+    #[arg(
+        long,
+        help = "Record the MUS chosen at each step to this file, so the solve can be reproduced"
+    )]
+    repro_report: Option<PathBuf>,
+
+    #[arg(
+        long,
+        conflicts_with = "repro_report",
+        help = "Replay a solve recorded with --repro-report, instead of searching"
+    )]
+    replay: Option<PathBuf>,
+
     #[arg(long)]
     searches: Option<i64>,
 
+    #[arg(
+        long,
+        default_value_t = ShrinkOrder::InOrder,
+        help = "The order literals are removed from each core while shrinking it (in-order, random, activity)"
+    )]
+    shrink_order: ShrinkOrder,
+
+    #[arg(
+        long,
+        default_value_t = ProbeOrder::InOrder,
+        help = "The order literals are probed in when looking for those which can be deduced (in-order, phase, recent)"
+    )]
+    probe_order: ProbeOrder,
+
+    #[arg(
+        long,
+        help = "Report how much work went into shrinking cores into MUSes"
+    )]
+    shrink_stats: bool,
+
+    #[arg(
+        long,
+        help = "Search up to this many times for a MUS of each literal whose MUSes vary in size, rather than a fixed --searches"
+    )]
+    adaptive_searches: Option<i64>,
+
+    #[arg(
+        long,
+        default_value_t = 0.0,
+        requires = "adaptive_searches",
+        help = "Only search again for literals whose MUS sizes have a variance above this"
+    )]
+    search_variance: f64,
+
+    #[arg(long, help = "Report how many MUS searches were made")]
+    search_stats: bool,
+
+    #[arg(
+        long,
+        help = "Report the memory used (resident set size) once each step was chosen, and at its peak, where it can be measured (on Linux)"
+    )]
+    memory_stats: bool,
+
+    #[arg(
+        long,
+        value_name = "QUERIES",
+        help = "Keep the last this many queries each thread makes of the SAT solver (the fixed literals, the assumptions, the result and any core), and print them at the end in terms of the puzzle, to see why an explanation came out as it did"
+    )]
+    query_log: Option<usize>,
+
+    #[arg(
+        long,
+        value_name = "MODE",
+        help = "Report searches which were skipped as the SAT solver hit its conflict limit, by phase (report), or also exit with an error if there were any (fail), for checking collections of puzzles"
+    )]
+    strict: Option<Strictness>,
+
+    #[arg(
+        long,
+        value_name = "CONFLICTS",
+        help = "Searches which must finish, such as checking the puzzle has a solution, are retried with higher conflict limits up to this many conflicts, instead of without a ceiling"
+    )]
+    conflict_ceiling: Option<i64>,
+
+    #[arg(
+        long,
+        default_value_t = 0.0,
+        help = "Among equally small MUSes, prefer those whose cells are close together. Values above 1 outweigh --prefer-assignments"
+    )]
+    locality_weight: f64,
+
+    #[arg(
+        long,
+        help = "Choose randomly, from this seed, between the MUSes ranked equally best, for a different walkthrough each time"
+    )]
+    variety_seed: Option<u64>,
+
+    #[arg(
+        long,
+        help = "Stop solving early: once a cell has a value (cell:1,2), after some steps (steps:10), or before the first step needing more constraints than a limit (difficulty:3)"
+    )]
+    stop_at: Option<StopCondition>,
+
+    #[arg(
+        long,
+        value_name = "CELL=WEIGHT",
+        help = "Explain the deductions about a cell sooner (weights above 1) or later (weights below 1), such as 3,4=0.5. A weight of 0 never explains the cell, so as not to spoil it, and only deduces it once nothing else can be. Can be given more than once"
+    )]
+    cell_weight: Vec<CellWeight>,
+
+    #[arg(
+        long,
+        help = "Find deductions which need only one constraint with a cheaper search first, and report the solver calls used"
+    )]
+    chained_singles: bool,
+
+    #[arg(
+        long,
+        help = "Find simple deductions (such as a cell with one value left) by unit propagation before using the SAT solver, and report how many steps needed no solver calls"
+    )]
+    simple_facts: bool,
+
+    #[arg(
+        long,
+        help = "Once nothing more can be deduced, list pairs of values of which one must be taken, such as a number which must go in one of two cells"
+    )]
+    either_or: bool,
+
+    #[arg(
+        long,
+        help = "Start with a setup step, listing what follows from the puzzle before any constraint is used"
+    )]
+    setup_step: bool,
+
+    #[arg(
+        long,
+        value_name = "CONSTRAINTS",
+        help = "Merge consecutive steps about the same cells, such as x != 3 then x = 5, when together they use at most this many constraints"
+    )]
+    merge_same_cell: Option<usize>,
+
+    #[arg(
+        long,
+        help = "End the text and HTML solves with a summary: whether the puzzle was solved, the deductions using each constraint, the hardest step, the time and solver calls taken, and any cells left open"
+    )]
+    summary: bool,
+
+    #[arg(
+        long,
+        value_name = "NAMES",
+        value_delimiter = ',',
+        help = "Only make steps using the constraints with these $#CON names, such as row,col, to teach a subset of the rules. Once these deduce nothing more, the solve stops and shows the easiest step which needs another rule"
+    )]
+    allow_constraints: Vec<String>,
+
+    #[arg(
+        long,
+        help = "Choose the MUSes whose constraints have the lowest total $#CONWEIGHT, rather than the smallest MUSes and then the lightest of those"
+    )]
+    weighted_size: bool,
+
+    #[arg(
+        long,
+        help = "Show the Essence' expression behind each constraint, as well as its description"
+    )]
+    show_expressions: bool,
+
+    #[arg(
+        long,
+        help = "Draw cells as filled (1) or empty (0) circles (true), or as numbers (false). By default, circles are used when every variable is 0/1"
+    )]
+    binary_cells: Option<bool>,
+
+    #[arg(
+        long,
+        help = "With --html, write how many cells of each row and column are solved in the margins, with more in a tooltip"
+    )]
+    margin_stats: bool,
+
     #[arg(
         long,
         value_enum,
         help = "Specify the method to run the solver (Native, Docker, Podman)"
     )]
     conjure: Option<RunMethod>,
+
+    #[arg(
+        long,
+        help = "The image Docker or Podman run conjure from, where {arch} is replaced by the architecture, such as arm64 [env: DEMYSTIFY_CONTAINER_IMAGE]"
+    )]
+    container_image: Option<String>,
+
+    #[arg(
+        long,
+        help = "The platform Docker or Podman run conjure on, such as linux/amd64, instead of the host's [env: DEMYSTIFY_CONTAINER_PLATFORM]"
+    )]
+    container_platform: Option<String>,
+
+    #[arg(
+        long,
+        env = "DEMYSTIFY_RECORD_STATS",
+        help = "Record statistics about this run (the kind and size of the puzzle, MUS sizes, solver calls and timings, but not the puzzle itself) on this machine, to see with 'demystify stats show'"
+    )]
+    record_stats: bool,
+
+    #[arg(
+        long,
+        env = "DEMYSTIFY_STATS_FILE",
+        help = "Record statistics in this file, instead of demystify/stats.jsonl in the user's data directory"
+    )]
+    stats_file: Option<PathBuf>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Statistics recorded with --record-stats
+    Stats {
+        #[command(subcommand)]
+        command: StatsCommand,
+    },
+    /// Render a trace written with --solve-trace as a PDF, one step per page
+    ExportPdf {
+        /// The trace to render
+        trace: PathBuf,
+        /// Where to write the PDF
+        out: PathBuf,
+    },
+    /// Summarise what happened between two steps of a trace written with
+    /// --solve-trace
+    TraceDiff {
+        /// The trace to read
+        trace: PathBuf,
+        /// The first step, counting from 1 (the setup step, if there is one,
+        /// is the first)
+        first: usize,
+        /// The last step, which is included
+        last: usize,
+        #[arg(long, help = "Print the summary as JSON")]
+        json: bool,
+    },
+    /// Check what can and cannot be deduced in a puzzle, as listed in a YAML
+    /// or JSON spec. Fails if any check fails, so it can be run in CI.
+    Assert {
+        #[arg(
+            long,
+            help = "The checks to make, and the model and parameters to make them on"
+        )]
+        spec: PathBuf,
+    },
+    /// Solve every level of a campaign, checking each can be solved without
+    /// guessing and can be unlocked. Fails if any check fails, so it can be
+    /// run in CI.
+    Campaign {
+        #[arg(long, help = "The campaign manifest, listing its levels in order")]
+        manifest: PathBuf,
+    },
+    /// Check this installation works, by solving the built-in test puzzles
+    /// and comparing them with their golden traces, then running conjure and
+    /// savilerow. Worth running before reporting a bug.
+    SelfTest {
+        #[arg(long, help = "Do not run conjure and savilerow")]
+        no_tools: bool,
+        #[arg(
+            long,
+            value_enum,
+            help = "Specify the method to run the solver (Native, Docker, Podman)"
+        )]
+        conjure: Option<RunMethod>,
+    },
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum StatsCommand {
+    /// Summarise the recorded runs
+    Show {
+        #[arg(
+            long,
+            env = "DEMYSTIFY_STATS_FILE",
+            help = "Read statistics from this file, instead of demystify/stats.jsonl in the user's data directory"
+        )]
+        stats_file: Option<PathBuf>,
+    },
+}
+
+fn stats_store(file: Option<&PathBuf>) -> anyhow::Result<StatsStore> {
+    match file {
+        Some(file) => Ok(StatsStore::new(file.clone())),
+        None => StatsStore::in_data_dir()
+            .context("Cannot find a data directory for statistics, set --stats-file"),
+    }
+}
+
+fn read_trace(path: &PathBuf) -> anyhow::Result<SolveTrace> {
+    let file = File::open(path).with_context(|| format!("Cannot open {}", path.display()))?;
+    SolveTrace::from_reader(BufReader::new(file))
+}
+
+/// The file at `path`, or else the file downloaded from `url` into
+/// `downloads`. Clap makes sure exactly one of these is given.
+fn local_or_fetched(
+    path: Option<&String>,
+    url: Option<&String>,
+    downloads: Option<&tempfile::TempDir>,
+) -> anyhow::Result<PathBuf> {
+    match (path, url, downloads) {
+        (Some(path), _, _) => Ok(PathBuf::from(path)),
+        (None, Some(url), Some(dir)) => {
+            let fetched = fetch(url, &FetchLimits::default())?;
+            eprintln!(
+                "Downloaded {} ({} bytes, SHA-256 {})",
+                fetched.url,
+                fetched.contents.len(),
+                sha256_hex(&fetched.contents)
+            );
+            fetched.save_in(dir.path())
+        }
+        _ => unreachable!("--model and --param, or their URLs, are required without a subcommand"),
+    }
+}
+
+/// Merges `params` and then `lets` into one file in `dir`, JSON if `json`
+/// and otherwise Essence, warning about any parameter given different
+/// values.
+fn merge_params(
+    params: &[PathBuf],
+    lets: &[String],
+    json: bool,
+    dir: &Path,
+) -> anyhow::Result<PathBuf> {
+    let mut merged = MergedParams::default();
+    for path in params {
+        merged.add(&path.display().to_string(), read_params(path)?);
+    }
+    for assignment in lets {
+        merged.set(assignment)?;
+    }
+    for conflict in &merged.conflicts {
+        eprintln!("Warning: {conflict}");
+    }
+
+    let (name, text) = if json {
+        ("params.json", serde_json::to_string_pretty(&merged.values)?)
+    } else {
+        ("params.param", write_essence_param(&merged.values)?)
+    };
+    let path = dir.join(name);
+    std::fs::write(&path, text).with_context(|| format!("Cannot write {}", path.display()))?;
+    Ok(path)
 }
 
 fn main() -> anyhow::Result<()> {
     let opt = Opt::parse();
 
-    let (non_block, _guard) = tracing_appender::non_blocking(File::create("demystify.trace")?);
+    // Leave the files of a failed conjure or savilerow run for inspection
+    set_keep_failed_runs(true);
+
+    if opt.container_image.is_some() || opt.container_platform.is_some() {
+        let env = ContainerConfig::from_env();
+        set_container_config(ContainerConfig {
+            image: opt.container_image.clone().unwrap_or(env.image),
+            platform: opt.container_platform.clone().or(env.platform),
+        });
+    }
+
+    if let Some(Command::Stats {
+        command: StatsCommand::Show { stats_file },
+    }) = &opt.command
+    {
+        let store = stats_store(stats_file.as_ref())?;
+        println!("Statistics from {}", store.path().display());
+        print!("{}", StatsSummary::new(&store.load()?));
+        return Ok(());
+    }
+
+    if let Some(Command::ExportPdf { trace, out }) = &opt.command {
+        let trace = read_trace(trace)?;
+        std::fs::write(out, trace_to_pdf(&trace))
+            .with_context(|| format!("Cannot write {}", out.display()))?;
+        println!("Wrote {} steps to {}", trace.steps.len(), out.display());
+        return Ok(());
+    }
+
+    if let Some(Command::TraceDiff {
+        trace,
+        first,
+        last,
+        json,
+    }) = &opt.command
+    {
+        if *first == 0 || *last == 0 {
+            bail!("Steps are counted from 1");
+        }
+        let diff = read_trace(trace)?.diff(first - 1, last - 1)?;
+        if *json {
+            println!("{}", serde_json::to_string_pretty(&diff)?);
+        } else {
+            print!("{}", diff.text());
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::Assert { spec }) = &opt.command {
+        let spec = AssertSpec::from_file(spec)?;
+        let outcomes = spec.run(Arc::new(spec.puzzle()?))?;
+        for outcome in &outcomes {
+            println!("{outcome}");
+        }
+        let failed = outcomes.iter().filter(|o| !o.passed()).count();
+        if failed > 0 {
+            bail!("{failed} of {} checks failed", outcomes.len());
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::Campaign { manifest }) = &opt.command {
+        if let Some(method) = opt.conjure {
+            set_run_method(method);
+        }
+        let campaign = Campaign::from_file(manifest)?;
+        let report = campaign.validate();
+        for outcome in &report.outcomes {
+            println!("{outcome}");
+        }
+        for (level, difficulty) in campaign.levels.iter().zip(&report.difficulties) {
+            if let Some(difficulty) = difficulty {
+                println!("{}: difficulty {difficulty}", level.name);
+            }
+        }
+        let failed = report.outcomes.iter().filter(|o| !o.passed()).count();
+        if failed > 0 {
+            bail!("{failed} of {} checks failed", report.outcomes.len());
+        }
+        println!(
+            "All {} levels of {} can be played",
+            campaign.levels.len(),
+            campaign.name
+        );
+        return Ok(());
+    }
+
+    if let Some(Command::SelfTest { no_tools, conjure }) = &opt.command {
+        if let Some(method) = conjure {
+            set_run_method(*method);
+        }
+        let tools = (!no_tools).then_some(&SystemExecutor as &dyn Executor);
+        let outcomes = selftest::run(tools)?;
+        for outcome in &outcomes {
+            println!("{outcome}");
+        }
+        let failed = outcomes.iter().filter(|o| !o.passed()).count();
+        if failed > 0 {
+            bail!("{failed} of {} checks failed", outcomes.len());
+        }
+        println!("All {} checks passed", outcomes.len());
+        return Ok(());
+    }
 
     // Choose how we run conjure, native, docker or podman
     if let Some(method) = opt.conjure {
         set_run_method(method);
     }
 
-    if opt.trace {
-        tracing_subscriber::fmt()
-            .with_span_events(FmtSpan::ACTIVE)
-            .with_max_level(Level::TRACE)
-            //.with_env_filter("trace,tracer=off")
-            .with_ansi(false)
-            .without_time()
-            //.pretty()
-            .with_writer(non_block)
-            .init();
+    // Only touch the file system if we are asked to trace
+    let _guard = if opt.trace || opt.trace_file.is_some() {
+        let file = opt
+            .trace_file
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("demystify.trace"));
+        Some(LogConfig::trace_file(file, opt.trace_max_size).init()?)
+    } else {
+        None
+    };
+
+    // Only make a directory for downloads if there are any
+    let downloads = if opt.model_url.is_some() || opt.param_url.is_some() {
+        Some(tempfile::tempdir().context("Failed to create a directory for downloads")?)
+    } else {
+        None
+    };
+    let model = local_or_fetched(
+        opt.model.as_ref(),
+        opt.model_url.as_ref(),
+        downloads.as_ref(),
+    )?;
+    let param = local_or_fetched(
+        opt.param.first(),
+        opt.param_url.as_ref(),
+        downloads.as_ref(),
+    )?;
+    if model == param {
+        bail!(
+            "The model and parameters cannot both be called {}",
+            model.display()
+        );
     }
 
-    let puzzle =
-        problem::parse::parse_essence(&PathBuf::from(opt.model), &PathBuf::from(opt.param))?;
+    // Only make a directory for merged parameters if there is anything to merge
+    let merged_dir = if opt.param.len() > 1 || !opt.lets.is_empty() {
+        Some(tempfile::tempdir().context("Failed to create a directory for parameters")?)
+    } else {
+        None
+    };
+    let param = match &merged_dir {
+        Some(dir) => {
+            let params: Vec<PathBuf> = std::iter::once(param)
+                .chain(opt.param.iter().skip(1).map(PathBuf::from))
+                .collect();
+            merge_params(&params, &opt.lets, opt.dimacs.is_some(), dir.path())?
+        }
+        None => param,
+    };
+
+    let start = Instant::now();
+    let puzzle = match &opt.dimacs {
+        Some(dimacs) => problem::parse::parse_eprime_with_dimacs(&model, &param, dimacs)?,
+        None => problem::parse::parse_essence(&model, &param)?,
+    };
+    let parse_time = start.elapsed();
+
+    if let Some(dir) = &opt.anonymize {
+        let paths = Anonymized::new(&puzzle).write(dir, "puzzle")?;
+        println!(
+            "Wrote {}",
+            paths.map(|p| p.display().to_string()).join(", ")
+        );
+        return Ok(());
+    }
+
+    // Problems drawing the puzzle, such as an unknown $#KIND
+    if let Ok(problem) = Problem::new_from_puzzle(&puzzle) {
+        for warning in &problem.warnings {
+            eprintln!("Warning: {warning}");
+        }
+    }
 
     let puzzle = Arc::new(puzzle);
 
@@ -85,26 +721,96 @@ fn main() -> anyhow::Result<()> {
         puzzle,
         SolverConfig {
             only_assignments: opt.only_assign,
+            probe_order: opt.probe_order,
         },
     )?;
 
-    let mus_config: MusConfig = if let Some(searches) = opt.searches {
+    let mut mus_config: MusConfig = if let Some(searches) = opt.searches {
         MusConfig::new_with_repeats(searches)
     } else {
         MusConfig::default()
     };
+    mus_config.shrink_order = opt.shrink_order;
+    if let Some(ceiling) = opt.conflict_ceiling {
+        set_must_succeed_conflict_ceiling(ceiling);
+    }
+    if let Some(size) = opt.query_log {
+        set_query_log_size(size);
+    }
+    mus_config.adaptive = opt.adaptive_searches.map(|max_repeats| AdaptiveRepeats {
+        max_repeats,
+        variance_threshold: opt.search_variance,
+    });
 
     let planner_config = PlannerConfig {
         mus_config,
         merge_small_threshold: opt.merge,
         skip_small_threshold: opt.skip,
         expand_to_all_deductions: true,
+        prefer_assignments: opt.prefer_assignments,
+        minimize_presented_mus: opt.minimize_mus.map(Duration::from_millis),
+        html_options: HtmlOptions {
+            show_expressions: opt.show_expressions,
+            binary_cells: opt.binary_cells,
+            margin_stats: opt.margin_stats,
+            ..HtmlOptions::default()
+        },
+        difficulty_candidate_counts: false,
+        difficulty_literal_time: None,
+        chained_singles: opt.chained_singles,
+        simple_facts: opt.simple_facts,
+        locality_weight: opt.locality_weight,
+        stop_condition: opt.stop_at.clone(),
+        either_or: opt.either_or,
+        setup_step: opt.setup_step,
+        constraint_weighting: if opt.weighted_size {
+            ConstraintWeighting::WeightedSize
+        } else {
+            ConstraintWeighting::TieBreak
+        },
+        merge_same_cell: opt.merge_same_cell,
+        summary: opt.summary,
+        cell_weights: opt
+            .cell_weight
+            .iter()
+            .map(|w| (w.cell.clone(), w.weight))
+            .collect(),
+        variety_seed: opt.variety_seed,
+        history: HistoryConfig::default(),
     };
 
+    let start = Instant::now();
+    let calls = get_solver_calls();
     let mut planner = PuzzlePlanner::new_with_config(solver, planner_config);
+    if !opt.allow_constraints.is_empty() {
+        planner.allow_only(&opt.allow_constraints.iter().cloned().collect())?;
+    }
 
-    if opt.html {
-        let html = planner.quick_solve_html();
+    if opt.clue_scope {
+        // The planner marks the clues as known when it is created
+        let clues = planner.get_all_known_lits().iter().copied().collect();
+        let steps = planner.quick_solve_muses();
+        let scope = ClueScope::new(planner.puzzle(), &clues, &steps);
+        println!(
+            "Clues in scope of the constraints used ({}):",
+            scope.in_scope.len()
+        );
+        for c in &scope.in_scope {
+            println!("  {c}");
+        }
+        println!(
+            "Clues outside every constraint used, so redundant ({}):",
+            scope.out_of_scope.len()
+        );
+        for c in &scope.out_of_scope {
+            println!("  {c}");
+        }
+    } else if opt.html || opt.show_rules {
+        let html = if opt.show_rules {
+            planner.rules_html()
+        } else {
+            planner.quick_solve_html()
+        };
         println!(
             "<html> <head> <style> {} </style> <script> {} </script> </head>",
             base_css(),
@@ -113,11 +819,150 @@ fn main() -> anyhow::Result<()> {
         println!("<body> {html}");
         println!("<script> doJavascript(); </script>");
         println!("</body> </html>");
+    } else if opt.text {
+        print!("{}", planner.quick_solve_text());
+    } else if let Some(path) = &opt.solve_trace {
+        let trace = planner.quick_solve_trace();
+        serde_json::to_writer(File::create(path)?, &trace)?;
+        println!("Wrote {} steps to {}", trace.steps.len(), path.display());
+    } else if let Some(path) = &opt.markdown {
+        let trace = planner.quick_solve_trace();
+        let grid = if opt.markdown_svg {
+            MarkdownGrid::Svg
+        } else {
+            MarkdownGrid::Table
+        };
+        std::fs::write(path, trace_to_markdown(&trace, grid))
+            .with_context(|| format!("Cannot write {}", path.display()))?;
+        println!("Wrote {} steps to {}", trace.steps.len(), path.display());
+    } else if let Some(replay) = &opt.replay {
+        let report = ReproReport::from_reader(BufReader::new(File::open(replay)?))?;
+        for step in planner.replay(&report)? {
+            let step: Vec<_> = step.iter().map(|m| planner.mus_to_user_mus(m)).collect();
+            println!("{step:?}");
+        }
     } else {
+        if opt.repro_report.is_some() {
+            planner.record_repro();
+        }
+
         for p in planner.quick_solve_with_progress() {
             println!("{p:?}");
+            if opt.show_expressions {
+                for con in p.iter().flat_map(|(_, cons)| cons) {
+                    if let Some(expr) = planner.puzzle().constraint_expression(con) {
+                        println!("  {con}: {expr}");
+                    }
+                }
+            }
+        }
+
+        if let Some(path) = &opt.repro_report {
+            let report = planner.take_repro_report().unwrap();
+            let hazards = report.hazards();
+            eprintln!(
+                "{} of {} steps chose between several equally small MUSes, and may differ between runs: {:?}",
+                hazards.len(),
+                report.steps.len(),
+                hazards
+            );
+            serde_json::to_writer_pretty(File::create(path)?, &report)?;
         }
     }
 
+    // The text and HTML solves end with this already
+    if !opt.text && !opt.html && !opt.show_rules {
+        if let Some(stuck) = planner.stuck() {
+            eprint!("{}", stuck.text());
+        } else if let Some(guess) = planner.solve_summary().guess_text() {
+            eprint!("{guess}");
+        }
+    }
+
+    if let Some(stop) = &opt.stop_at {
+        let left = planner.get_provable_varlits().len();
+        if left > 0 {
+            eprintln!("Stopped at {stop}, with {left} literals left to deduce");
+        }
+    }
+
+    if opt.chained_singles {
+        let stats = planner.chained_singles_stats();
+        eprintln!(
+            "Chained singles: {} deductions in {} steps, using {} of {} solver calls",
+            stats.deductions,
+            stats.steps,
+            stats.solver_calls,
+            get_solver_calls()
+        );
+    }
+
+    if opt.simple_facts {
+        let stats = planner.simple_facts_stats();
+        eprintln!(
+            "Simple facts: {} deductions in {} steps without the SAT solver, {} solver calls in total",
+            stats.deductions,
+            stats.steps,
+            get_solver_calls()
+        );
+    }
+
+    if opt.shrink_stats {
+        let stats = get_shrink_stats();
+        eprintln!(
+            "Shrinking ({}): {}, removing {:.0}% of each core",
+            opt.shrink_order,
+            stats,
+            stats.mean_reduction() * 100.0
+        );
+    }
+
+    if opt.search_stats {
+        eprintln!("Searches: {}", get_repeat_stats());
+    }
+
+    if opt.memory_stats {
+        match MemoryUsage::sample() {
+            Some(usage) => {
+                let steps = planner
+                    .step_stats()
+                    .memory
+                    .iter()
+                    .map(|step| format_kb(step.current_kb))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                eprintln!("Memory: {usage}, after each step: {steps}");
+            }
+            None => eprintln!("Memory: cannot be measured on this system"),
+        }
+    }
+
+    if opt.query_log.is_some() {
+        let puzzle = planner.puzzle();
+        for query in take_query_log() {
+            eprint!("{}", query.describe(|lit| puzzle.describe_lit(lit)));
+        }
+    }
+
+    let skipped = get_search_error_stats();
+    if opt.strict.is_some() {
+        eprintln!("Skipped searches: {skipped}");
+    }
+
+    if opt.record_stats {
+        let run = RunStats {
+            solver_calls: get_solver_calls() - calls,
+            parse_millis: parse_time.as_millis() as u64,
+            solve_millis: start.elapsed().as_millis() as u64,
+            peak_memory_kb: MemoryUsage::sample().map(|usage| usage.peak_kb),
+            ..RunStats::new(planner.puzzle(), planner.step_stats())
+        };
+        stats_store(opt.stats_file.as_ref())?.append(&run)?;
+    }
+
+    if opt.strict == Some(Strictness::Fail) {
+        skipped.check()?;
+    }
+
     Ok(())
 }
@@ -1,10 +1,10 @@
 use clap::Parser;
 use demystify::json::Problem;
+use demystify::problem::util::logging::LogConfig;
 use demystify::web::puzsvg::PuzzleDraw;
 use std::fs::File;
 use std::io::Write;
-use tracing::Level;
-use tracing_subscriber::fmt::format::FmtSpan;
+use std::path::PathBuf;
 
 #[derive(clap::Parser, Debug)]
 struct Opt {
@@ -16,24 +16,35 @@ struct Opt {
 
     #[arg(long)]
     trace: bool,
+
+    #[arg(
+        long,
+        env = "DEMYSTIFY_TRACE_FILE",
+        help = "Write the trace to this file instead of demystify.trace (implies --trace)"
+    )]
+    trace_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        env = "DEMYSTIFY_TRACE_MAX_SIZE",
+        help = "Start a new trace file after this many bytes, keeping the last 3 as <file>.1, <file>.2 and <file>.3"
+    )]
+    trace_max_size: Option<u64>,
 }
 
 fn main() -> anyhow::Result<()> {
     let opt = Opt::parse();
 
-    let (non_block, _guard) = tracing_appender::non_blocking(File::create("demystify.trace")?);
-
-    if opt.trace {
-        tracing_subscriber::fmt()
-            .with_span_events(FmtSpan::ACTIVE)
-            .with_max_level(Level::TRACE)
-            //.with_env_filter("trace,tracer=off")
-            .with_ansi(false)
-            .without_time()
-            //.pretty()
-            .with_writer(non_block)
-            .init();
-    }
+    // Only touch the file system if we are asked to trace
+    let _guard = if opt.trace || opt.trace_file.is_some() {
+        let file = opt
+            .trace_file
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("demystify.trace"));
+        Some(LogConfig::trace_file(file, opt.trace_max_size).init()?)
+    } else {
+        None
+    };
 
     let file = File::open(&opt.puzzle)?;
     let problem: Problem = serde_json::from_reader(file)?;
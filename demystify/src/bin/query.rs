@@ -0,0 +1,39 @@
+use clap::Parser;
+use demystify::problem::{
+    query::{Query, QueryDump},
+    repro::ReproReport,
+};
+use std::{fs::File, io::BufReader, path::PathBuf};
+
+#[derive(clap::Parser, Debug)]
+struct Opt {
+    #[arg(help = "A solve recorded with demystify --repro-report")]
+    trace: PathBuf,
+
+    #[arg(
+        help = "Which steps to print, such as \"steps where mus_size > 4 and constraint ~ 'knight'\". The fields are step, mus_size, candidates, constraint and cell"
+    )]
+    query: Query,
+
+    #[arg(long, help = "Print the matching steps as JSON")]
+    json: bool,
+}
+
+fn main() -> anyhow::Result<()> {
+    let opt = Opt::parse();
+
+    let report = ReproReport::from_reader(BufReader::new(File::open(&opt.trace)?))?;
+
+    let steps = opt.query.matching_steps(&report);
+
+    if opt.json {
+        println!("{}", serde_json::to_string_pretty(&QueryDump::new(steps))?);
+    } else {
+        for step in &steps {
+            println!("{step}");
+        }
+        eprintln!("{} of {} steps matched", steps.len(), report.steps.len());
+    }
+
+    Ok(())
+}
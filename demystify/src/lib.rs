@@ -1,6 +1,15 @@
+//! A constraint solving tool for explaining puzzles. The solver is in
+//! demystify-core and the drawing in demystify-render, which are re-exported
+//! here under the paths they have always had, so most programs need only
+//! this crate.
+
 #![allow(dead_code)]
 
-pub mod json;
-pub mod problem;
-pub mod satcore;
-pub mod web;
+#[doc(hidden)]
+pub use demystify_core::satcore;
+pub use demystify_core::{draw, json, problem};
+pub use demystify_render as web;
+
+pub mod prelude;
+#[cfg(feature = "service")]
+pub mod service;
@@ -0,0 +1,53 @@
+//! The types most programs embedding demystify need, which are kept stable
+//! between minor versions:
+//!
+//! ```no_run
+//! # use std::{path::Path, sync::Arc};
+//! use demystify::prelude::*;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let puzzle = parse_essence(Path::new("sudoku.eprime"), Path::new("puzzle.param"))?;
+//! let mut planner = PuzzlePlanner::new(PuzzleSolver::new(Arc::new(puzzle))?);
+//! let trace: SolveTrace = planner.quick_solve_trace();
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Modules marked `#[doc(hidden)]`, such as `satcore`, are public
+//! only so the demystify binaries and demystify-web can use them, and may
+//! change in any release. `cargo semver-checks` is run on every pull
+//! request, so changes which would break users of the rest are caught
+//! before release.
+
+pub use crate::json::Problem;
+pub use crate::problem::{
+    PuzLit, PuzVar, VarValPair,
+    musdict::{MusContext, MusDict},
+    parse::{PuzzleParse, parse_eprime_with_dimacs, parse_essence},
+    planner::{PlannerConfig, PuzzlePlanner, Step, StepKind, StopCondition},
+    solver::{CancelFlag, MusConfig, PuzzleSolver, SearchBudget, SolverConfig},
+    trace::{SolveSummary, SolveTrace, TraceStep},
+};
+pub use crate::web::{HtmlOptions, create_html, create_html_with_options, planner::PlannerHtml};
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use demystify_core::problem::util::test_utils::TST;
+
+    use super::*;
+
+    #[test]
+    fn test_prelude_solve() -> anyhow::Result<()> {
+        let puzzle = parse_eprime_with_dimacs(
+            &format!("{TST}/sudoku-4x4.eprime").into(),
+            &format!("{TST}/sudoku-4x4.json").into(),
+            &format!("{TST}/sudoku-4x4.dimacs").into(),
+        )?;
+        let mut planner = PuzzlePlanner::new(PuzzleSolver::new(Arc::new(puzzle))?);
+        let trace: SolveTrace = planner.quick_solve_trace();
+        assert!(trace.summary.is_some_and(|s: SolveSummary| s.solved));
+        Ok(())
+    }
+}
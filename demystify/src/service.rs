@@ -0,0 +1,396 @@
+//! Running a [`PuzzlePlanner`] from async code.
+//!
+//! Every planner method blocks until its search is done, which can be many
+//! seconds. A [`PlannerService`] runs each call on tokio's blocking thread
+//! pool instead, and returns a [`Task`] which can be awaited, asked how it is
+//! getting on, or cancelled:
+//!
+//! ```no_run
+//! # use std::time::Duration;
+//! # use demystify::{prelude::*, service::PlannerService};
+//! # async fn example(planner: PuzzlePlanner) -> anyhow::Result<()> {
+//! let service = PlannerService::new(planner);
+//! let step = service
+//!     .run_with_timeout(Duration::from_secs(10), |planner, cancel| {
+//!         let budget = SearchBudget {
+//!             cancel: Some(cancel.clone()),
+//!             ..SearchBudget::default()
+//!         };
+//!         Ok(planner.next_step_budgeted(&budget, None))
+//!     })
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Calls on the same planner run one at a time, in the order their threads
+//! get the planner's lock, or fail with [`PlannerBusy`] if started with
+//! [`PlannerService::try_spawn`] while another call has it. Searches only
+//! stop early when cancelled if they are given the task's [`CancelFlag`],
+//! usually in a [`SearchBudget`].
+//!
+//! This needs the `service` feature.
+//!
+//! [`SearchBudget`]: crate::problem::solver::SearchBudget
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        Arc, Mutex, OnceLock, TryLockError,
+        atomic::{AtomicU8, Ordering::Relaxed},
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context as _, anyhow};
+use tokio::task::JoinHandle;
+
+use crate::problem::{planner::PuzzlePlanner, solver::CancelFlag};
+
+/// How far a [`Task`] has got.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TaskStatus {
+    /// Waiting for a thread, or for an earlier call on the same planner
+    Queued,
+    /// Running on the planner
+    Running,
+    /// Done, so awaiting the task returns straight away
+    Finished,
+}
+
+impl TaskStatus {
+    fn from_u8(status: u8) -> TaskStatus {
+        match status {
+            0 => TaskStatus::Queued,
+            1 => TaskStatus::Running,
+            _ => TaskStatus::Finished,
+        }
+    }
+}
+
+/// The error from a [`Task`] started with [`PlannerService::try_spawn`]
+/// when another call was using the planner.
+#[derive(Debug, thiserror::Error)]
+#[error("The planner is in use by another call")]
+pub struct PlannerBusy;
+
+/// How long a [`Task`] was queued before it started running.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Waited {
+    /// Waiting for a thread from the blocking pool
+    pub thread: Duration,
+    /// Waiting for earlier calls on the same planner to finish
+    pub planner: Duration,
+}
+
+/// What a [`Task`] shares with the thread running it.
+#[derive(Debug, Default)]
+struct Progress {
+    status: AtomicU8,
+    waited: OnceLock<Waited>,
+}
+
+/// Marks a [`Task`] as finished when dropped, even if its call panics.
+struct Finished(Arc<Progress>);
+
+impl Drop for Finished {
+    fn drop(&mut self) {
+        self.0.status.store(TaskStatus::Finished as u8, Relaxed);
+    }
+}
+
+/// A call running on a [`PlannerService`]. Awaiting it gives the call's
+/// result.
+///
+/// Like tokio's `JoinHandle`, dropping a task does not stop it. Use
+/// [`Task::cancel`] first, or [`PlannerService::run_with_timeout`].
+#[derive(Debug)]
+pub struct Task<T> {
+    handle: JoinHandle<anyhow::Result<T>>,
+    cancel: CancelFlag,
+    progress: Arc<Progress>,
+}
+
+impl<T> Task<T> {
+    #[must_use]
+    pub fn status(&self) -> TaskStatus {
+        TaskStatus::from_u8(self.progress.status.load(Relaxed))
+    }
+
+    /// How long the task was queued, once it has started running.
+    #[must_use]
+    pub fn waited(&self) -> Option<Waited> {
+        self.progress.waited.get().copied()
+    }
+
+    /// Asks the call to stop. Searches which were given the task's
+    /// [`CancelFlag`] stop as if they had run out of time.
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    /// The flag passed to the call, which [`Task::cancel`] sets.
+    #[must_use]
+    pub fn cancel_flag(&self) -> &CancelFlag {
+        &self.cancel
+    }
+
+    /// Waits for the task, cancelling it if it has not finished within
+    /// `limit`, then waiting for it to stop.
+    pub async fn finish_within(&mut self, limit: Duration) -> anyhow::Result<T> {
+        match tokio::time::timeout(limit, &mut *self).await {
+            Ok(result) => result,
+            Err(_) => {
+                self.cancel();
+                self.await
+            }
+        }
+    }
+}
+
+impl<T> Future for Task<T> {
+    type Output = anyhow::Result<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.handle)
+            .poll(cx)
+            .map(|joined| match joined {
+                Ok(result) => result,
+                Err(err) if err.is_panic() => Err(anyhow!(
+                    "Internal error in the planner, so it can no longer be used"
+                )),
+                Err(err) => Err(err).context("The planner's thread was stopped"),
+            })
+    }
+}
+
+/// A [`PuzzlePlanner`] which is used from async code. Clones share the same
+/// planner.
+#[derive(Clone)]
+pub struct PlannerService {
+    planner: Arc<Mutex<PuzzlePlanner>>,
+}
+
+impl From<PuzzlePlanner> for PlannerService {
+    fn from(planner: PuzzlePlanner) -> Self {
+        PlannerService::new(planner)
+    }
+}
+
+impl PlannerService {
+    #[must_use]
+    pub fn new(planner: PuzzlePlanner) -> PlannerService {
+        PlannerService::from_shared(Arc::new(Mutex::new(planner)))
+    }
+
+    /// Uses a planner which is also locked directly elsewhere. Calls on the
+    /// service wait for those locks like any other.
+    #[must_use]
+    pub fn from_shared(planner: Arc<Mutex<PuzzlePlanner>>) -> PlannerService {
+        PlannerService { planner }
+    }
+
+    /// The planner, for code which locks it directly. This blocks, so should
+    /// only be done briefly, or away from the async runtime.
+    #[must_use]
+    pub fn shared(&self) -> &Arc<Mutex<PuzzlePlanner>> {
+        &self.planner
+    }
+
+    /// Starts running `f` on the planner, on tokio's blocking thread pool.
+    /// This must be called from within a tokio runtime.
+    ///
+    /// If `f` panics, the task fails, and so does every later call, as the
+    /// planner may have been left half changed. The planner must then be
+    /// built again.
+    pub fn spawn<T, F>(&self, f: F) -> Task<T>
+    where
+        F: FnOnce(&mut PuzzlePlanner, &CancelFlag) -> anyhow::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.spawn_locking(true, f)
+    }
+
+    /// Starts running `f` like [`PlannerService::spawn`], but if another call
+    /// has the planner when the task's thread starts, the task fails with
+    /// [`PlannerBusy`] rather than waiting for it.
+    pub fn try_spawn<T, F>(&self, f: F) -> Task<T>
+    where
+        F: FnOnce(&mut PuzzlePlanner, &CancelFlag) -> anyhow::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.spawn_locking(false, f)
+    }
+
+    /// Spawns `f`, either waiting for the planner's lock or failing with
+    /// [`PlannerBusy`] if it is held.
+    fn spawn_locking<T, F>(&self, wait: bool, f: F) -> Task<T>
+    where
+        F: FnOnce(&mut PuzzlePlanner, &CancelFlag) -> anyhow::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let cancel = CancelFlag::default();
+        let progress = Arc::new(Progress::default());
+        let planner = self.planner.clone();
+        let queued = Instant::now();
+        let handle = {
+            let cancel = cancel.clone();
+            let progress = progress.clone();
+            tokio::task::spawn_blocking(move || {
+                let _finished = Finished(progress.clone());
+                let thread = queued.elapsed();
+                let locked = if wait {
+                    planner.lock().map_err(|_| LockError::Poisoned)
+                } else {
+                    planner.try_lock().map_err(|err| match err {
+                        TryLockError::WouldBlock => LockError::Busy,
+                        TryLockError::Poisoned(_) => LockError::Poisoned,
+                    })
+                };
+                let _ = progress.waited.set(Waited {
+                    thread,
+                    planner: queued.elapsed() - thread,
+                });
+                match locked {
+                    // A panic poisons the lock, so the planner is not used
+                    // again
+                    Ok(mut planner) => {
+                        progress.status.store(TaskStatus::Running as u8, Relaxed);
+                        f(&mut planner, &cancel)
+                    }
+                    Err(LockError::Busy) => Err(PlannerBusy.into()),
+                    Err(LockError::Poisoned) => Err(anyhow!(
+                        "An earlier call on this planner failed, so it can no longer be used"
+                    )),
+                }
+            })
+        };
+        Task {
+            handle,
+            cancel,
+            progress,
+        }
+    }
+
+    /// Runs `f` on the planner and waits for its result, without blocking
+    /// the async runtime.
+    pub async fn run<T, F>(&self, f: F) -> anyhow::Result<T>
+    where
+        F: FnOnce(&mut PuzzlePlanner, &CancelFlag) -> anyhow::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.spawn(f).await
+    }
+
+    /// Runs `f` like [`PlannerService::run`], but cancels it if it has not
+    /// finished within `limit`, then waits for it to stop. This is only
+    /// prompt if `f` passes its [`CancelFlag`] on to its searches.
+    pub async fn run_with_timeout<T, F>(&self, limit: Duration, f: F) -> anyhow::Result<T>
+    where
+        F: FnOnce(&mut PuzzlePlanner, &CancelFlag) -> anyhow::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.spawn(f).finish_within(limit).await
+    }
+}
+
+/// Why a task's thread could not lock the planner.
+enum LockError {
+    Busy,
+    Poisoned,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use crate::problem::util::test_utils::sudoku_4x4_planner;
+
+    use super::*;
+
+    fn service() -> PlannerService {
+        PlannerService::new(sudoku_4x4_planner())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_planner_service() -> anyhow::Result<()> {
+        let service = service();
+
+        let steps = service.run(|p, _| Ok(p.quick_solve().len())).await?;
+        assert!(steps > 0);
+        let solved = service.run(|p, _| Ok(p.solve_summary().solved)).await?;
+        assert!(solved);
+
+        // The planner is held until the running call finishes, so a second
+        // call queues behind it
+        let (started, wait_started) = mpsc::channel();
+        let first = service.spawn(move |_, cancel| {
+            started.send(()).unwrap();
+            while !cancel.is_cancelled() {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            Ok("cancelled")
+        });
+        wait_started.recv()?;
+        assert_eq!(first.status(), TaskStatus::Running);
+        let mut second = service.spawn(|_, _| Ok("second"));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(second.status(), TaskStatus::Queued);
+        assert_eq!(second.waited(), None);
+        assert!(service.shared().try_lock().is_err());
+
+        first.cancel();
+        assert_eq!(first.await?, "cancelled");
+        assert_eq!((&mut second).await?, "second");
+        assert_eq!(second.status(), TaskStatus::Finished);
+        assert!(second.waited().unwrap().planner >= Duration::from_millis(20));
+
+        // Timing out cancels the call, and gives whatever it stopped with
+        let timed_out = service
+            .run_with_timeout(Duration::ZERO, |_, cancel| {
+                while !cancel.is_cancelled() {
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+                Ok("timed out")
+            })
+            .await?;
+        assert_eq!(timed_out, "timed out");
+
+        // While a call has the planner, try_spawn fails rather than queueing
+        let (started, wait_started) = mpsc::channel();
+        let holding = service.spawn(move |_, cancel| {
+            started.send(()).unwrap();
+            while !cancel.is_cancelled() {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            Ok(())
+        });
+        wait_started.recv()?;
+        let busy = service.try_spawn(|_, _| Ok(())).await.unwrap_err();
+        assert!(busy.is::<PlannerBusy>());
+        holding.cancel();
+        holding.await?;
+        assert!(service.try_spawn(|_, _| Ok(())).await.is_ok());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_planner_service_panic() -> anyhow::Result<()> {
+        let service = service();
+
+        // A panic may leave the planner half changed, so it is not used again
+        let mut task = service.spawn(|_, _| -> anyhow::Result<()> { panic!() });
+        let err = (&mut task).await.unwrap_err();
+        assert!(err.to_string().starts_with("Internal error in the planner"));
+        assert_eq!(task.status(), TaskStatus::Finished);
+        assert!(service.shared().is_poisoned());
+        let err = service.run(|_, _| Ok(())).await.unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("An earlier call on this planner failed")
+        );
+        Ok(())
+    }
+}
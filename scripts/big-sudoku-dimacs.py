@@ -0,0 +1,103 @@
+#!/usr/bin/env python3
+"""Writes an empty n x n sudoku (n a square number) as <out>.eprime,
+<out>.json and <out>.dimacs, in the same form as savilerow gives for
+demystify-core/tst/sudoku-4x4.eprime, without needing conjure or savilerow.
+
+This is for stress-testing the parser on large DIMACS files, for example
+
+    scripts/big-sudoku-dimacs.py 25 /tmp/sudoku25
+    demystify --model /tmp/sudoku25.eprime --param /tmp/sudoku25.json --dimacs /tmp/sudoku25.dimacs
+"""
+
+import itertools
+import json
+import os
+import sys
+
+n, out = int(sys.argv[1]), sys.argv[2]
+b = int(n**0.5)
+assert b * b == n, "n must be a square number"
+D = range(1, n + 1)
+C = range(b)
+
+here = os.path.dirname(os.path.abspath(__file__))
+with open(os.path.join(here, "../demystify-core/tst/sudoku-4x4.eprime")) as f:
+    eprime = f.read()
+eprime = (
+    eprime.replace("int(1..4)", f"int(1..{n})")
+    .replace("int(0..1)", f"int(0..{b - 1})")
+    .replace("of int(0..4)", f"of int(0..{n})")
+    .replace("2*", f"{b}*")
+    .replace("*2", f"*{b}")
+)
+with open(out + ".eprime", "w") as f:
+    f.write(eprime)
+
+with open(out + ".json", "w") as f:
+    json.dump({"fixed": {str(i): {str(j): 0 for j in D} for i in D}}, f)
+
+# The comments come before the clauses, so the clauses are written to a
+# second file and appended once the header can be written
+nvars = 0
+nclauses = 0
+comments = open(out + ".dimacs.tmp", "w")
+clauses = open(out + ".clauses.tmp", "w")
+
+
+def name(var, *idx):
+    return var + "".join(f"_{i:05}" for i in idx)
+
+
+def new_var():
+    global nvars
+    nvars += 1
+    return nvars
+
+
+def clause(*lits):
+    global nclauses
+    nclauses += 1
+    clauses.write(" ".join(map(str, lits)) + " 0\n")
+
+
+grid = {}
+for i, j, d in itertools.product(D, D, D):
+    grid[i, j, d] = new_var()
+    comments.write(f"c Var '{name('grid', i, j)}' direct represents '{d}' with '{grid[i, j, d]}'\n")
+
+
+def con(var, *idx):
+    v = new_var()
+    comments.write(f"c Var '{name(var, *idx)}' direct represents '0' with '{-v}'\n")
+    comments.write(f"c Var '{name(var, *idx)}' direct represents '1' with '{v}'\n")
+    return v
+
+
+for i, j1, j2, d in itertools.product(D, D, D, D):
+    if j1 < j2:
+        clause(-con("row_alldiff", i, j1, j2, d), -grid[i, j1, d], -grid[i, j2, d])
+for j, i1, i2, d in itertools.product(D, D, D, D):
+    if i1 < i2:
+        clause(-con("con_alldiff", j, i1, i2, d), -grid[i1, j, d], -grid[i2, j, d])
+for x, y, i1, j1, i2, j2 in itertools.product(C, C, C, C, C, C):
+    if i1 * b + j1 < i2 * b + j2:
+        for d in D:
+            clause(
+                -con("box_alldiff", x, y, i1, j1, i2, j2, d),
+                -grid[1 + b * x + i1, 1 + b * y + j1, d],
+                -grid[1 + b * x + i2, 1 + b * y + j2, d],
+            )
+for i, j in itertools.product(D, D):
+    clause(*(grid[i, j, d] for d in D))
+    for d1, d2 in itertools.combinations(D, 2):
+        clause(-grid[i, j, d1], -grid[i, j, d2])
+
+comments.close()
+clauses.close()
+with open(out + ".dimacs", "w") as f:
+    f.write(f"p cnf {nvars} {nclauses}\n")
+    for part in (".dimacs.tmp", ".clauses.tmp"):
+        with open(out + part) as p:
+            for line in p:
+                f.write(line)
+        os.remove(out + part)